@@ -0,0 +1,31 @@
+-- Run with `cargo run --features lua -- --script assets/scripts/triangle.lua`
+-- and edit while the application is running, saved changes are picked up
+-- straight away.
+
+local diamond = mesh.load_obj("assets/meshes/diamond.obj")
+material.set_albedo_color(diamond, 0.9, 0.6, 0.2)
+
+local t = 0.0
+local paused = false
+
+-- called every frame with the seconds since the last frame
+function on_update(dt)
+    if not paused then
+        t = t + dt
+    end
+
+    -- the triangle circles the center of the screen
+    triangle.set_position(0.25 * math.cos(t * 2.0), 0.25 * math.sin(t * 2.0))
+
+    -- the camera sways from side to side, moving the diamond
+    local x = 0.2 * math.sin(t)
+    camera.set_position(x, 0.0, 0.0)
+    camera.look_at(x, 0.0, -1.0)
+end
+
+-- space pauses and resumes the motion
+function on_key(key, pressed)
+    if key == "Space" and pressed then
+        paused = not paused
+    end
+end
@@ -0,0 +1,15 @@
+// A plasma which follows the mouse, written exactly as it would be on
+// Shadertoy. Run it with `cargo run -- shadertoy assets/shadertoy/plasma.frag`.
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = (2.0 * fragCoord - iResolution.xy) / iResolution.y;
+    vec2 mouse = iMouse.z > 0.0
+        ? (2.0 * iMouse.xy - iResolution.xy) / iResolution.y
+        : vec2(0.0);
+
+    float t = iTime * 0.5;
+    float v = sin(uv.x * 3.0 + t)
+        + sin(uv.y * 4.0 - t * 1.3)
+        + sin(length(uv - mouse) * 6.0 - t * 2.0);
+    vec3 color = 0.5 + 0.5 * cos(v + vec3(0.0, 2.1, 4.2));
+    fragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,42 @@
+// Gray-Scott reaction-diffusion with chemical A in red and B in green.
+//
+// Alpha is 2 once the simulation has started. Before that the buffer holds
+// the seed image, in linear light, or black, and B is seeded wherever the
+// seed is dark.
+const float FEED = 0.037;
+const float KILL = 0.06;
+const float STARTED = 2.0;
+
+vec2 state(vec2 fragCoord, vec2 offset) {
+    return texture(iChannel0, (fragCoord + offset) / iResolution.xy).xy;
+}
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec4 previous = texture(iChannel0, fragCoord / iResolution.xy);
+    float space = texelFetch(iChannel1, ivec2(32, 1), 0).x;
+    if (previous.a != STARTED || space > 0.5) {
+        float luminance = dot(previous.rgb, vec3(0.2126, 0.7152, 0.0722));
+        float b = previous.a == 0.0
+            ? step(length(fragCoord - iResolution.xy * 0.5), 20.0)
+            : step(luminance, 0.2);
+        fragColor = vec4(1.0, b, 0.0, STARTED);
+        return;
+    }
+
+    // a 3x3 laplacian, weighted 0.2 to the sides and 0.05 to the corners
+    vec2 laplacian = -previous.xy
+        + 0.2 * (state(fragCoord, vec2(1.0, 0.0))
+            + state(fragCoord, vec2(-1.0, 0.0))
+            + state(fragCoord, vec2(0.0, 1.0))
+            + state(fragCoord, vec2(0.0, -1.0)))
+        + 0.05 * (state(fragCoord, vec2(1.0, 1.0))
+            + state(fragCoord, vec2(-1.0, 1.0))
+            + state(fragCoord, vec2(1.0, -1.0))
+            + state(fragCoord, vec2(-1.0, -1.0)));
+    float a = previous.x;
+    float b = previous.y;
+    float reaction = a * b * b;
+    a += laplacian.x - reaction + FEED * (1.0 - a);
+    b += 0.5 * laplacian.y + reaction - (KILL + FEED) * b;
+    fragColor = vec4(clamp(vec2(a, b), 0.0, 1.0), 0.0, STARTED);
+}
@@ -0,0 +1,6 @@
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 ab = texture(iChannel0, fragCoord / iResolution.xy).xy;
+    float shade = clamp(ab.x - ab.y, 0.0, 1.0);
+    vec3 color = mix(vec3(0.05, 0.1, 0.2), vec3(0.95, 0.9, 0.8), shade);
+    fragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,17 @@
+// Fade what was drawn last frame and add a dot under the mouse, or at a
+// point circling the middle when the mouse isn't held.
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+    vec4 previous = texture(iChannel0, uv);
+    float space = texelFetch(iChannel1, ivec2(32, 1), 0).x;
+    if (iFrame < 2 || space > 0.5) {
+        previous = vec4(0.0);
+    }
+
+    vec2 center = iMouse.z > 0.0
+        ? iMouse.xy
+        : iResolution.xy * (0.5 + 0.3 * vec2(cos(iTime), sin(iTime * 1.3)));
+    float spot = smoothstep(12.0, 8.0, length(fragCoord - center));
+    vec3 color = 0.5 + 0.5 * cos(iTime + vec3(0.0, 2.1, 4.2));
+    fragColor = vec4(previous.rgb * 0.98 + spot * color, 1.0);
+}
@@ -0,0 +1,4 @@
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec3 trails = texture(iChannel0, fragCoord / iResolution.xy).rgb;
+    fragColor = vec4(trails / (1.0 + trails), 1.0);
+}
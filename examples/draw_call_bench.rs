@@ -0,0 +1,220 @@
+//! Times 10,000 draw calls a frame, to compare allocators with.
+//!
+//! cargo run --release --example draw_call_bench [--features mimalloc]
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::framebuffer::Subpass;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+/// Draw calls recorded every frame.
+const DRAW_CALLS: u32 = 10_000;
+
+/// Frames drawn before timing starts, so pools and caches have warmed up.
+const WARMUP_FRAMES: usize = 20;
+
+/// Frames timed.
+const FRAMES: usize = 200;
+
+/// mimalloc in place of the system allocator, as the application uses it.
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static ALLOC: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+struct Vertex {
+    pos: [f32; 2],
+}
+
+vulkano::impl_vertex!(Vertex, pos);
+
+/// Draws the same small triangle `DRAW_CALLS` times a frame, each call
+/// moved by a push constant, and keeps how long recording took.
+struct DrawCalls {
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    triangle: Arc<CpuAccessibleBuffer<[Vertex]>>,
+    recording: Rc<RefCell<Vec<Duration>>>,
+}
+
+impl DrawCalls {
+    fn new(
+        display: &Display,
+        recording: Rc<RefCell<Vec<Duration>>>,
+    ) -> Result<Self> {
+        let device = &display.device;
+        let vert = vertex_shader::Shader::load(device.clone())
+            .context("unable to load the vertex shader")?;
+        let frag = fragment_shader::Shader::load(device.clone())
+            .context("unable to load the fragment shader")?;
+        let pipeline = GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vert.main_entry_point(), ())
+            .fragment_shader(frag.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .blend_pass_through()
+            .render_pass(
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("could not create the pipeline subpass")?,
+            )
+            .build(device.clone())
+            .context("unable to build the draw call pipeline")?;
+        let triangle = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            [[0.0, -0.01], [0.01, 0.01], [-0.01, 0.01]]
+                .iter()
+                .map(|&pos| Vertex { pos }),
+        )
+        .context("unable to create the triangle's vertex buffer")?;
+        Ok(Self {
+            pipeline: Arc::new(pipeline),
+            triangle,
+            recording,
+        })
+    }
+}
+
+impl Sketch for DrawCalls {
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let start = Instant::now();
+        let [width, height] = display.scene_extent();
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [width as f32, height as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            ..DynamicState::none()
+        };
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the draw calls")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        let side = (DRAW_CALLS as f32).sqrt().ceil() as u32;
+        for call in 0..DRAW_CALLS {
+            let offset = [
+                (call % side) as f32 / side as f32 * 2.0 - 1.0,
+                (call / side) as f32 / side as f32 * 2.0 - 1.0,
+            ];
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &dynamic_state,
+                    vec![self.triangle.clone()],
+                    (),
+                    vertex_shader::ty::Offset { offset },
+                )
+                .context("unable to issue draw command")?;
+        }
+        let commands = builder
+            .build()
+            .context("unable to build the command buffer")?;
+        self.recording.borrow_mut().push(start.elapsed());
+        Ok(vec![commands])
+    }
+}
+
+/// Draw a scene of `DRAW_CALLS` draw calls for `FRAMES` frames and log how
+/// much cpu time each frame took, and how much of it went to recording.
+///
+/// Build it once as is and once with `--features mimalloc` to compare the
+/// system allocator with mimalloc. vulkano allocates for every draw call it
+/// records, which is where the allocator shows. Each frame is waited on
+/// before the next starts, so the frame time includes the gpu drawing the
+/// tiny triangles too, which is small next to the recording. This opens a
+/// hidden window, so it needs a display, and like any benchmark it should
+/// be run with `--release`.
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let recording = Rc::new(RefCell::new(vec![]));
+    let sketch = DrawCalls::new(app.display(), recording.clone())?;
+    app.set_sketch(sketch);
+    app.render_frames(WARMUP_FRAMES)?;
+
+    let mut frames = Vec::with_capacity(FRAMES);
+    for _ in 0..FRAMES {
+        let start = Instant::now();
+        app.render_frames(1)?;
+        frames.push(start.elapsed());
+    }
+    log::info!(
+        "{} allocator, {} draw calls a frame",
+        if cfg!(feature = "mimalloc") {
+            "mimalloc"
+        } else {
+            "system"
+        },
+        DRAW_CALLS
+    );
+    log_times("frame", &frames);
+    log_times("recording", &recording.borrow()[WARMUP_FRAMES..]);
+    Ok(())
+}
+
+fn log_times(name: &str, times: &[Duration]) {
+    let total: Duration = times.iter().sum();
+    log::info!(
+        "{} times over {} frames: mean {:.3}ms, min {:.3}ms, max {:.3}ms",
+        name,
+        times.len(),
+        total.as_secs_f64() * 1000.0 / times.len() as f64,
+        times.iter().min().unwrap_or(&total).as_secs_f64() * 1000.0,
+        times.iter().max().unwrap_or(&total).as_secs_f64() * 1000.0
+    );
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+
+            layout(location = 0) in vec2 pos;
+
+            layout(push_constant) uniform Offset {
+                vec2 offset;
+            };
+
+            void main() {
+                gl_Position = vec4(pos + offset, 0.0, 1.0);
+            }
+        "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+
+            layout(location = 0) out vec4 outColor;
+
+            void main() {
+                outColor = vec4(1.0);
+            }
+        "#
+    }
+}
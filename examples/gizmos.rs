@@ -0,0 +1,118 @@
+//! A curve with draggable control points. Shift keeps a drag on one axis, N
+//! snaps to a grid, Ctrl+Z undoes the last drag, and Ctrl+Shift+Z redoes it.
+//!
+//! cargo run --example gizmos
+
+use anyhow::Result;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::gizmos::Gizmos;
+use vulkan_starter::application::meshes::{
+    DrawList, Material, Mesh, MeshRenderer,
+};
+use vulkan_starter::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::VirtualKeyCode;
+
+const NAMES: [&str; 3] = ["start", "control", "end"];
+
+struct Curve {
+    renderer: MeshRenderer,
+    dot: Mesh,
+    transforms: TransformHierarchy,
+    dots: Vec<TransformId>,
+    points: [[f32; 2]; 3],
+    grid: bool,
+}
+
+impl Curve {
+    fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let dots = (0..32)
+            .map(|_| transforms.add(Transform::default(), None))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            renderer: MeshRenderer::new(display)?,
+            dot: Mesh::disc(&display.device, 8)?,
+            transforms,
+            dots,
+            points: [[-0.6, -0.3], [0.0, 0.5], [0.6, -0.3]],
+            grid: false,
+        })
+    }
+}
+
+impl Sketch for Curve {
+    fn gizmos(&mut self, gizmos: &mut Gizmos) -> Result<()> {
+        gizmos.set_grid(if self.grid { Some(0.1) } else { None });
+        // the application records each drag, and moves the point back when
+        // it's undone
+        for (name, point) in NAMES.iter().zip(self.points.iter_mut()) {
+            gizmos.point(name, point);
+        }
+        Ok(())
+    }
+
+    fn update(&mut self, _time: f32, _dt: f32) -> Result<()> {
+        // a quadratic bezier through the start and end points
+        let [a, b, c] = self.points;
+        let count = self.dots.len();
+        for (i, &dot) in self.dots.iter().enumerate() {
+            let t = i as f32 / (count - 1) as f32;
+            let lerp = |axis: usize| {
+                (1.0 - t) * (1.0 - t) * a[axis]
+                    + 2.0 * (1.0 - t) * t * b[axis]
+                    + t * t * c[axis]
+            };
+            let place = Transform {
+                translation: [lerp(0), lerp(1), 0.0],
+                scale: [0.015, 0.015, 0.015],
+                ..Transform::default()
+            };
+            self.transforms.set_local(dot, place)?;
+        }
+        self.transforms.resolve()
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mut draw_list = DrawList::default();
+        let teal = Material {
+            color: [0.2, 0.8, 0.8, 1.0],
+        };
+        for &dot in &self.dots {
+            draw_list.draw_mesh(&self.dot, teal, dot);
+        }
+        let commands = self.renderer.draw(
+            display,
+            camera,
+            &draw_list,
+            &self.transforms,
+        )?;
+        Ok(vec![commands])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) -> Result<()> {
+        if key == VirtualKeyCode::N {
+            self.grid = !self.grid;
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let curve = Curve::new(app.display())?;
+    app.set_sketch(curve);
+    app.main_loop()
+}
@@ -0,0 +1,69 @@
+//! A ring of particles which breathes in and out, space adds more.
+//!
+//! cargo run --example particles
+
+use anyhow::Result;
+use std::f32::consts::PI;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::particles::{Particles, Vertex};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::VirtualKeyCode;
+
+struct Ring {
+    particles: Particles,
+    count: usize,
+}
+
+impl Ring {
+    fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            particles: Particles::new(display)?,
+            count: 12,
+        })
+    }
+}
+
+impl Sketch for Ring {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        let radius = 0.5 + 0.2 * (time * 2.0).sin();
+        let vertices = (0..self.count)
+            .map(|i| {
+                let turn = i as f32 / self.count as f32;
+                let angle = turn * 2.0 * PI + time * 0.5;
+                let position = [radius * angle.cos(), radius * angle.sin()];
+                Vertex::new(position, [turn, 0.5, 1.0 - turn, 1.0])
+            })
+            .collect();
+        self.particles.set_vertices(vertices);
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        Ok(vec![self.particles.draw(display, camera)?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.particles.rebuild_swapchain_resources(display)
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) -> Result<()> {
+        if key == VirtualKeyCode::Space {
+            self.count = (self.count * 2).min(768);
+            log::info!("{} particles", self.count);
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let ring = Ring::new(app.display())?;
+    app.set_sketch(ring);
+    app.main_loop()
+}
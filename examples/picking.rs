@@ -0,0 +1,112 @@
+//! A row of shapes which light up under the cursor, clicking one selects it.
+//!
+//! cargo run --example picking
+
+use anyhow::Result;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::meshes::{
+    DrawList, Material, Mesh, MeshRenderer,
+};
+use vulkan_starter::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::MouseButton;
+
+struct Shapes {
+    renderer: MeshRenderer,
+    shapes: Vec<(Mesh, TransformId)>,
+    transforms: TransformHierarchy,
+    draw_list: DrawList,
+    hovered: Option<u32>,
+    selected: Option<u32>,
+}
+
+impl Shapes {
+    fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let mut shapes = vec![];
+        for (i, &segments) in [3, 4, 5, 6, 32].iter().enumerate() {
+            let place = Transform {
+                translation: [-0.6 + 0.3 * i as f32, 0.0, 0.0],
+                scale: [0.12, 0.12, 0.12],
+                ..Transform::default()
+            };
+            shapes.push((
+                Mesh::disc(&display.device, segments)?,
+                transforms.add(place, None)?,
+            ));
+        }
+        transforms.resolve()?;
+        Ok(Self {
+            renderer: MeshRenderer::new(display)?,
+            shapes,
+            transforms,
+            draw_list: DrawList::default(),
+            hovered: None,
+            selected: None,
+        })
+    }
+}
+
+impl Sketch for Shapes {
+    fn update(&mut self, _time: f32, _dt: f32) -> Result<()> {
+        // draw_mesh numbers the shapes from 1 in the order they're drawn
+        self.draw_list = DrawList::default();
+        for (i, (mesh, transform)) in self.shapes.iter().enumerate() {
+            let id = Some(i as u32 + 1);
+            let color = match (id == self.selected, id == self.hovered) {
+                (true, _) => [1.0, 0.5, 0.1, 1.0],
+                (false, true) => [0.9, 0.9, 0.9, 1.0],
+                (false, false) => [0.4, 0.4, 0.5, 1.0],
+            };
+            self.draw_list
+                .draw_mesh(mesh, Material { color }, *transform);
+        }
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let commands = self.renderer.draw(
+            display,
+            camera,
+            &self.draw_list,
+            &self.transforms,
+        )?;
+        Ok(vec![commands])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+
+    fn handle_click(&mut self, button: MouseButton) -> Result<()> {
+        if button == MouseButton::Left {
+            self.selected = self.hovered;
+            log::info!("selected {:?}", self.selected);
+        }
+        Ok(())
+    }
+
+    fn pickable(&self) -> Option<(&DrawList, &TransformHierarchy)> {
+        Some((&self.draw_list, &self.transforms))
+    }
+
+    fn picked(&mut self, picked_id: Option<u32>) -> Result<()> {
+        self.hovered = picked_id;
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let shapes = Shapes::new(app.display())?;
+    app.set_sketch(shapes);
+    app.main_loop()
+}
@@ -0,0 +1,65 @@
+//! Particles drawn through the lens effects and color grading.
+//!
+//! The application's keys still work, so L toggles the lens, G the grading,
+//! M motion blur, F depth of field, and C the clouds.
+//!
+//! cargo run --example post_processing
+
+use anyhow::Result;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::particles::{Particles, Vertex};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+struct Orbits {
+    particles: Particles,
+}
+
+impl Orbits {
+    fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            particles: Particles::new(display)?,
+        })
+    }
+}
+
+impl Sketch for Orbits {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        // particles near the edges show off the vignette and aberration
+        let vertices = (1..=6)
+            .map(|i| {
+                let radius = 0.15 * i as f32;
+                let angle = time / radius;
+                let brightness = 1.0 - 0.12 * i as f32;
+                Vertex::new(
+                    [radius * angle.cos(), radius * angle.sin()],
+                    [1.0, brightness, 0.2, 1.0],
+                )
+            })
+            .collect();
+        self.particles.set_vertices(vertices);
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        Ok(vec![self.particles.draw(display, camera)?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.particles.rebuild_swapchain_resources(display)
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let orbits = Orbits::new(app.display())?;
+    app.set_sketch(orbits);
+    app.toggle_lens_effects()?;
+    app.toggle_color_grading()?;
+    app.main_loop()
+}
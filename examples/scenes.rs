@@ -0,0 +1,165 @@
+//! Several sketches bundled as scenes, shift and 1 to 3 switch between them.
+//!
+//! cargo run --example scenes -- --scene clock --crossfade 0.5
+
+use anyhow::{Context, Result};
+use std::f32::consts::PI;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::meshes::{
+    DrawList, Material, Mesh, MeshRenderer,
+};
+use vulkan_starter::application::particles::{Particles, Vertex};
+use vulkan_starter::application::scenes::SceneManager;
+use vulkan_starter::application::text::{Label, TextRenderer};
+use vulkan_starter::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+struct Triangle {
+    renderer: MeshRenderer,
+    mesh: Mesh,
+    transforms: TransformHierarchy,
+    transform: TransformId,
+}
+
+impl Triangle {
+    fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let transform = transforms.add(Transform::default(), None)?;
+        Ok(Self {
+            renderer: MeshRenderer::new(display)?,
+            mesh: Mesh::disc(&display.device, 3)?,
+            transforms,
+            transform,
+        })
+    }
+}
+
+impl Sketch for Triangle {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        let spin = Transform {
+            scale: [0.5, 0.5, 0.5],
+            ..Transform::from_rotation_z(time)
+        };
+        self.transforms.set_local(self.transform, spin)?;
+        self.transforms.resolve()
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mut draw_list = DrawList::default();
+        let orange = Material {
+            color: [1.0, 0.5, 0.1, 1.0],
+        };
+        draw_list.draw_mesh(&self.mesh, orange, self.transform);
+        let commands = self.renderer.draw(
+            display,
+            camera,
+            &draw_list,
+            &self.transforms,
+        )?;
+        Ok(vec![commands])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+}
+
+struct Ring {
+    particles: Particles,
+}
+
+impl Sketch for Ring {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        let radius = 0.5 + 0.2 * (time * 2.0).sin();
+        let vertices = (0..24)
+            .map(|i| {
+                let turn = i as f32 / 24.0;
+                let angle = turn * 2.0 * PI + time * 0.5;
+                let position = [radius * angle.cos(), radius * angle.sin()];
+                Vertex::new(position, [turn, 0.5, 1.0 - turn, 1.0])
+            })
+            .collect();
+        self.particles.set_vertices(vertices);
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        Ok(vec![self.particles.draw(display, camera)?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.particles.rebuild_swapchain_resources(display)
+    }
+}
+
+struct Clock {
+    text: TextRenderer,
+    time: f32,
+}
+
+impl Sketch for Clock {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        self.time = time;
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let clock = format!("{:.1} seconds", self.time);
+        let labels = [Label {
+            text: &clock,
+            position: [-0.9, -0.1],
+            height: 0.2,
+        }];
+        Ok(vec![self.text.draw(display, &labels)?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.text.rebuild_swapchain_resources(display)
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut scenes = SceneManager::new();
+    scenes.register("triangle", Triangle::new);
+    scenes.register("ring", |display: &Display| {
+        Ok(Ring {
+            particles: Particles::new(display)?,
+        })
+    });
+    scenes.register("clock", |display: &Display| {
+        Ok(Clock {
+            text: TextRenderer::new(display)?,
+            time: 0.0,
+        })
+    });
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--scene") {
+        scenes.switch_to(&pair[1])?;
+    }
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--crossfade") {
+        let seconds = pair[1].parse::<f32>().with_context(|| {
+            format!("invalid crossfade duration {:?}", pair[1])
+        })?;
+        scenes.set_crossfade(seconds);
+    }
+
+    let mut app = Application::new()?;
+    app.set_scenes(scenes);
+    app.main_loop()
+}
@@ -0,0 +1,63 @@
+//! Text drawn with the built in bitmap font, with a clock which counts up.
+//!
+//! cargo run --example text
+
+use anyhow::Result;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::text::{Label, TextRenderer};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+struct Clock {
+    text: TextRenderer,
+    time: f32,
+}
+
+impl Clock {
+    fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            text: TextRenderer::new(display)?,
+            time: 0.0,
+        })
+    }
+}
+
+impl Sketch for Clock {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        self.time = time;
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let clock = format!("{:.1} seconds", self.time);
+        let labels = [
+            Label {
+                text: "Hello, Vulkan!",
+                position: [-0.9, -0.8],
+                height: 0.2,
+            },
+            Label {
+                text: &clock,
+                position: [-0.9, -0.5],
+                height: 0.1,
+            },
+        ];
+        Ok(vec![self.text.draw(display, &labels)?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.text.rebuild_swapchain_resources(display)
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let clock = Clock::new(app.display())?;
+    app.set_sketch(clock);
+    app.main_loop()
+}
@@ -0,0 +1,68 @@
+//! A textured quad which slowly pulses, drawn as a sprite.
+//!
+//! cargo run --example textured_quad
+
+use anyhow::Result;
+use std::path::Path;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::sprites::{Sprite, SpriteRenderer};
+use vulkan_starter::texture::{TextureArray, TextureBuilder};
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+const IMAGE: &str = "assets/textures/bokeh_star.png";
+
+struct TexturedQuad {
+    sprites: SpriteRenderer,
+    size: f32,
+}
+
+impl TexturedQuad {
+    fn new(display: &Display) -> Result<Self> {
+        // a sprite sheet with a single layer
+        let image = TextureBuilder::from_png(Path::new(IMAGE))?;
+        let sheet = TextureArray::build(
+            vec![image],
+            &display.device,
+            &display.graphics_queue,
+        )?;
+        Ok(Self {
+            sprites: SpriteRenderer::new(display, sheet)?,
+            size: 1.0,
+        })
+    }
+}
+
+impl Sketch for TexturedQuad {
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        self.size = 1.0 + 0.2 * time.sin();
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        // sprites are placed in normalized device coordinates, the camera
+        // converts so the quad stays square
+        let quad = Sprite {
+            center: [0.0, 0.0],
+            size: camera.to_ndc([self.size, self.size]),
+            layer: 0,
+        };
+        Ok(vec![self.sprites.draw(display, &[quad])?])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.sprites.rebuild_swapchain_resources(display)
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::new()?;
+    let quad = TexturedQuad::new(app.display())?;
+    app.set_sketch(quad);
+    app.main_loop()
+}
@@ -0,0 +1,85 @@
+//! A single spinning triangle, the smallest sketch there is.
+//!
+//! cargo run --example triangle
+
+use anyhow::Result;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::meshes::{
+    DrawList, Material, Mesh, MeshRenderer,
+};
+use vulkan_starter::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use vulkan_starter::application::SketchInfo;
+use vulkan_starter::{logging, Application, Display, Sketch};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+struct Triangle {
+    renderer: MeshRenderer,
+    mesh: Mesh,
+    transforms: TransformHierarchy,
+    transform: TransformId,
+}
+
+impl Triangle {
+    fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let transform = transforms.add(Transform::default(), None)?;
+        Ok(Self {
+            renderer: MeshRenderer::new(display)?,
+            mesh: Mesh::disc(&display.device, 3)?,
+            transforms,
+            transform,
+        })
+    }
+}
+
+impl Sketch for Triangle {
+    fn info() -> SketchInfo {
+        SketchInfo {
+            name: "triangle".to_owned(),
+            window_size: Some([768, 768]),
+            ..SketchInfo::default()
+        }
+    }
+
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        let spin = Transform {
+            scale: [0.5, 0.5, 0.5],
+            ..Transform::from_rotation_z(time)
+        };
+        self.transforms.set_local(self.transform, spin)?;
+        self.transforms.resolve()
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mut draw_list = DrawList::default();
+        let orange = Material {
+            color: [1.0, 0.5, 0.1, 1.0],
+        };
+        draw_list.draw_mesh(&self.mesh, orange, self.transform);
+        let commands = self.renderer.draw(
+            display,
+            camera,
+            &draw_list,
+            &self.transforms,
+        )?;
+        Ok(vec![commands])
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+}
+
+fn main() -> Result<()> {
+    logging::start()?;
+    let mut app = Application::for_sketch::<Triangle>()?;
+    let triangle = Triangle::new(app.display())?;
+    app.set_sketch(triangle);
+    app.main_loop()
+}
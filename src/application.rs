@@ -1,95 +1,2956 @@
-mod particles;
+#[cfg(feature = "hot-reload")]
+pub mod asset_watcher;
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "wgpu-compat")]
+pub mod buffer;
+mod builtin_textures;
+pub mod camera;
+pub mod capture_format;
+pub mod clock;
+pub mod compute_pipeline;
+mod crossfade;
+pub mod cull;
+pub mod debug;
+pub mod debug_view;
+mod dynamic_resolution;
+pub mod ecs;
+pub mod events;
+mod frame_stats;
+mod frame_step;
+mod fullscreen;
+pub mod gizmos;
+pub mod golden;
+pub mod latency;
+pub mod live_resources;
+pub mod meshes;
+#[cfg(feature = "network")]
+pub mod network;
+pub mod noise;
+mod offscreen;
+pub mod params;
+pub mod particles;
+pub mod post_process;
+mod profiler;
+pub mod raw_frame;
+#[cfg(feature = "rebuild-fade")]
+mod rebuild_fade;
+pub mod render_graph;
+#[cfg(feature = "replay")]
+pub mod replay;
+mod resource_overlay;
+pub mod scenes;
+#[cfg(any(feature = "scripting", feature = "lua"))]
+pub mod scripting;
+pub mod shadertoy;
+mod sketch;
+#[cfg(feature = "snapshot")]
+pub mod snapshot;
+pub mod sprites;
+mod stereo;
+pub mod text;
+mod text_box;
+pub mod text_input;
+mod title;
+pub mod transform;
 
-use crate::display::Display;
-use anyhow::{Context, Result};
+use crate::display::{
+    self, AllocatorKind, CapturePolicy, Display, LatencyMode, Stage, Submitter,
+    SwapchainState, WindowConfig,
+};
+use crate::texture::{TextureArray, TextureBuilder, TextureKind};
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "hot-reload")]
+use asset_watcher::AssetWatcher;
+#[cfg(feature = "audio")]
+use audio::{AudioSystem, SoundId};
+use builtin_textures::BuiltinTextures;
+use camera::{Camera, CoordinateSpace};
+use capture_format::CaptureEncoding;
+use clock::{AnimationClock, FrameCounter, FrameTiming, TimeSource};
+use compute_pipeline::Simulation;
+use crossfade::{Crossfade, INCOMING, OUTGOING};
+use cull::CullStats;
+use debug_view::DebugView;
+use dynamic_resolution::DynamicResolution;
+#[cfg(feature = "bevy-ecs")]
+use ecs::bevy_backend::{FrameTime, RenderData, View};
+#[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+use ecs::specs_backend::SpecsWorld;
+#[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+use ecs::World;
+use events::{AppEvent, PendingEvents};
+use frame_stats::{FrameReport, FrameStats};
+use frame_step::FrameStep;
+use gizmos::{GizmoEvent, GizmoOverlay, Gizmos};
+use golden::{Capture, GoldenImage};
+use latency::{LateLatch, LATCH_MARGIN};
+use meshes::picking::{self, PickingPass};
+use meshes::MeshRenderer;
+#[cfg(feature = "network")]
+use network::{NetworkClient, RemoteObjects, ServerState};
+use offscreen::OffscreenTarget;
+use params::Params;
 use particles::Particles;
+use post_process::{
+    BokehShape, ColorGrading, DepthOfField, LensEffects, MotionBlur, ToneMap,
+    VolumetricClouds,
+};
+use profiler::ProfilerOverlay;
+use raw_frame::RawFrame;
+#[cfg(feature = "rebuild-fade")]
+use rebuild_fade::RebuildFade;
+use render_graph::{AliasPlan, RenderGraph};
+#[cfg(feature = "replay")]
+use replay::{InputEvent, Replay, ReplayPlayer, ReplayRecorder};
+use resource_overlay::ResourceOverlay;
+use scenes::SceneManager;
+use scopeguard::ScopeGuard;
+#[cfg(any(feature = "scripting", feature = "lua"))]
+use scripting::{ScriptEngine, ScriptedMeshes};
+pub use sketch::{Sketch, SketchInfo};
+#[cfg(feature = "snapshot")]
+use snapshot::{CameraSettings, SimulationSettings, Snapshot, Snapshottable};
+use sprites::{Sprite, SpriteRenderer};
+use std::borrow::Cow;
 use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Instant;
-use winit::event::{Event, WindowEvent};
-use winit::event_loop::ControlFlow;
+use stereo::StereoPreview;
+use text_box::TextBox;
+use text_input::TextEdit;
+use title::{TitleTemplate, TitleValues};
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+#[cfg(any(feature = "scripting", feature = "lua"))]
+use winit::event::DeviceEvent;
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, MouseButton,
+    VirtualKeyCode, WindowEvent,
+};
+use winit::event_loop::{ControlFlow, EventLoopProxy};
+
+/// The directory of images shown by the slideshow, one layer per image.
+const SLIDESHOW_DIRECTORY: &str = "assets/textures/slides";
+
+/// Where F12 saves screenshots.
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+
+/// Where Shift+F3 writes the frames in the frame time graph.
+const FRAME_STATS_CSV: &str = "frame_stats.csv";
+
+/// The image used for the custom bokeh shape.
+const BOKEH_STAR: &str = "assets/textures/bokeh_star.png";
+
+/// The directory watched for changed assets.
+#[cfg(feature = "hot-reload")]
+const ASSET_DIRECTORY: &str = "assets";
+
+/// A window title template which replaces the window config's while assets
+/// are watched, one line like `WindowConfig::title_template`.
+#[cfg(feature = "hot-reload")]
+const TITLE_TEMPLATE: &str = "assets/title_template.txt";
+
+/// The parameter holding the color grading strength.
+const GRADING_STRENGTH: &str = "grading.strength";
+
+/// The parameter holding the motion blur strength.
+const BLUR_STRENGTH: &str = "motion_blur.strength";
+
+/// The sound played when space is pressed.
+#[cfg(feature = "audio")]
+const CLICK_SOUND: &str = "assets/sounds/click.wav";
+
+/// How long each slide is shown, in seconds.
+const SLIDE_SECONDS: f32 = 2.0;
+
+/// How many times a lost device is recreated before giving up.
+const MAX_DEVICE_RECOVERIES: u32 = 3;
+
+/// How many times the window gets a new surface before giving up.
+const MAX_SURFACE_RECOVERIES: u32 = 3;
+
+/// Where `start_puffin_server` serves profiling scopes, puffin's usual port.
+#[cfg(feature = "profiling")]
+const PUFFIN_ADDRESS: &str = "0.0.0.0:8585";
 
 pub struct Application {
-    display: Display,
+    builtins: BuiltinTextures,
     particles: Particles,
-    start: Instant,
+    simulation: Option<Box<dyn Simulation>>,
+    stereo: Option<StereoPreview>,
+    clouds: Option<VolumetricClouds>,
+    tone_map: Option<ToneMap>,
+    motion_blur: Option<MotionBlur>,
+    depth_of_field: Option<DepthOfField>,
+    lens_effects: Option<LensEffects>,
+
+    // which of the effects' offscreen targets share an image
+    transient_aliasing: AliasPlan,
+    dynamic_resolution: Option<DynamicResolution>,
+    slideshow: Option<SpriteRenderer>,
+    #[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+    world: Option<(World, MeshRenderer)>,
+
+    // the same world, updated by a specs dispatcher
+    #[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+    world: Option<(SpecsWorld, MeshRenderer)>,
+
+    // the same world, updated by a bevy schedule
+    #[cfg(feature = "bevy-ecs")]
+    world: Option<(bevy_app::App, MeshRenderer)>,
+    profiler: Option<ProfilerOverlay>,
+    resource_overlay: Option<ResourceOverlay>,
+    text_box: Option<TextBox>,
+
+    // the last frame before the swapchain was rebuilt, while it fades out
+    #[cfg(feature = "rebuild-fade")]
+    rebuild_fade: Option<RebuildFade>,
+
+    // created the first time F4 cycles away from the final image, and kept
+    // so its pipelines are ready whenever a view comes around again
+    debug_view: Option<DebugView>,
+
+    // drawn in place of the triangle
+    sketch: Option<Box<dyn Sketch>>,
+
+    // switches the sketch between registered scenes, fading between them
+    // with the crossfade
+    scenes: Option<SceneManager>,
+    crossfade: Option<Crossfade>,
+
+    // created once the sketch has something to pick
+    picking: Option<PickingPass>,
+
+    // where the cursor is in the window, in physical pixels
+    cursor: Option<[f32; 2]>,
+    left_pressed: bool,
+
+    // when the newest input which hasn't been drawn yet arrived
+    input_time: Option<Instant>,
+
+    // typed characters go to the text box or the sketch's focused field,
+    // and shortcuts are off, while set
+    text_input_enabled: bool,
+
+    // where the IME's window was last placed, in the window's pixels
+    ime_position: Option<[f32; 2]>,
+
+    // sent by other threads since the last frame
+    events: PendingEvents,
+
+    // predicts when to sample input while late latching
+    late_latch: Option<LateLatch>,
+
+    // renders one frame per step instead of continuously when active
+    frame_step: FrameStep,
+
+    // the next frame is drawn offscreen and copied to the cpu when set
+    capture_requested: bool,
+    capture: Option<Capture>,
+
+    // the captured frame is saved as a screenshot when set
+    screenshot_requested: bool,
+    capture_encoding: CaptureEncoding,
+
+    // the sketch's handles, drawn once it registers any
+    gizmos: Gizmos,
+    gizmo_overlay: Option<GizmoOverlay>,
+
+    // the values the user can undo and redo changing
+    params: Params,
+    modifiers: ModifiersState,
+    clock: AnimationClock,
+
+    // numbers the frames, recordings and replays are paced by the index
+    frames: FrameCounter,
+    timing: FrameTiming,
+
+    // the time the last update saw, in seconds
+    previous_time: f32,
+    frame_stats: FrameStats,
+
+    // what culling tested and rejected while drawing the last frame
+    culled: CullStats,
+
+    // how the camera stretches x and y over the window
+    coordinate_space: CoordinateSpace,
+    app_name: String,
+
+    // names the screenshots and the sketch's parameters
+    sketch_info: SketchInfo,
+
+    // the window a lost device opens the application with again
+    window_config: WindowConfig,
+    title_template: TitleTemplate,
+
+    // a user supplied grading lut used in place of the identity lut
+    lut: Option<PathBuf>,
+
+    // there may be no audio device even when audio is enabled
+    #[cfg(feature = "audio")]
+    audio: Option<(AudioSystem, SoundId)>,
+
+    #[cfg(feature = "network")]
+    network: Option<(NetworkClient, RemoteObjects)>,
+
+    #[cfg(feature = "replay")]
+    replay: Option<Replay>,
+
+    #[cfg(any(feature = "scripting", feature = "lua"))]
+    scripting: Option<(ScriptEngine, ScriptedMeshes)>,
+
+    // kept alive while its changes arrive as events
+    #[cfg(feature = "hot-reload")]
+    _asset_watcher: Option<AssetWatcher>,
+
+    // serves the profiler's frames until the application closes
+    #[cfg(feature = "profiling")]
+    _puffin_server: Option<puffin_http::Server>,
+
+    // connects to the tracy profiler, which marks each frame's end
+    #[cfg(feature = "tracy")]
+    tracy: tracy_client::Client,
+
+    // the name the simulation was created with, so a snapshot or a lost
+    // device can create it again
+    simulation_name: Option<String>,
+
+    // the slot F5 and F9 save to and restore from
+    #[cfg(feature = "snapshot")]
+    snapshot_slot: u32,
+
+    // a restored snapshot waiting for the start of the next frame
+    #[cfg(feature = "snapshot")]
+    pending_snapshot: Option<Snapshot>,
+
+    // where a restored snapshot put the camera
+    #[cfg(feature = "snapshot")]
+    camera: Option<CameraSettings>,
+
+    // fields are dropped in order, so the display is last to outlive
+    // everything built on its device
+    display: Display,
 }
 
-impl Application {
-    pub fn initialize() -> Result<Self> {
-        let display =
-            Display::create().context("unable to create the display")?;
-        let particles = Particles::new(&display)?;
+impl Application {
+    /// Create the application.
+    ///
+    /// @param simulation the name of a compute simulation to run in place of
+    /// the default particles
+    /// @param lut a `.cube` or strip png LUT to grade with instead of the
+    /// identity LUT
+    /// @param render_scale a fixed fraction of the window's resolution to
+    /// draw the scene at, which starts with dynamic resolution enabled
+    /// @param seed the seed for randomly initialized simulations, in place of
+    /// one taken from the clock
+    /// @param latency_mode whether frames are paced for throughput or for
+    /// the time from input to the screen
+    /// @param capture_policy whether the window is hidden from screen capture
+    /// or kept friendly to streaming software
+    pub fn initialize(
+        simulation: Option<&str>,
+        lut: Option<PathBuf>,
+        render_scale: Option<f32>,
+        seed: Option<u64>,
+        latency_mode: LatencyMode,
+        capture_policy: CapturePolicy,
+    ) -> Result<Self> {
+        let window_config = WindowConfig {
+            latency_mode,
+            capture_policy,
+            ..WindowConfig::default()
+        };
+        let display = Display::create_windowed(window_config.clone())
+            .context("unable to create the display")?;
+        Self::with_display(
+            display,
+            &window_config,
+            SketchInfo::default(),
+            simulation,
+            lut,
+            render_scale,
+            seed,
+        )
+    }
+
+    /// Create the application on a display which already exists, with the
+    /// same arguments as `initialize`.
+    fn with_display(
+        mut display: Display,
+        window_config: &WindowConfig,
+        sketch_info: SketchInfo,
+        simulation: Option<&str>,
+        lut: Option<PathBuf>,
+        render_scale: Option<f32>,
+        seed: Option<u64>,
+    ) -> Result<Self> {
+        log::info!(
+            "sketch {} {}.{}.{}{}",
+            sketch_info.name,
+            sketch_info.version.major,
+            sketch_info.version.minor,
+            sketch_info.version.patch,
+            sketch_info
+                .author
+                .as_ref()
+                .map(|author| format!(" by {}", author))
+                .unwrap_or_default()
+        );
+        let app_name = window_config.title.clone();
+        let title_template =
+            TitleTemplate::parse(&window_config.title_template);
+        let dynamic_resolution = match render_scale {
+            Some(scale) => {
+                display.set_render_scale(scale);
+                Some(DynamicResolution::new(&display, Some(scale))?)
+            }
+            None => None,
+        };
+        let builtins =
+            BuiltinTextures::load(&display.device, &display.graphics_queue)?;
+        let particles = Particles::new(&display)?;
+        let mut clock = AnimationClock::new(TimeSource::WallClock);
+        match display.refresh_rate() {
+            Some(refresh_rate) => clock.set_refresh_rate(refresh_rate),
+            None => log::warn!(
+                "unable to find the display's refresh rate, assuming {}hz",
+                clock.refresh_rate()
+            ),
+        }
+        let simulation_name = simulation.map(str::to_owned);
+        let simulation = simulation
+            .map(|name| {
+                compute_pipeline::create_simulation(
+                    name, &display, &builtins, seed,
+                )
+            })
+            .transpose()
+            .context("unable to create the simulation")?;
+        let late_latch = match display.latency_mode() {
+            LatencyMode::Reduced { late_latch: true } => {
+                Some(LateLatch::new(LATCH_MARGIN))
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            display,
+            builtins,
+            particles,
+            simulation,
+            stereo: None,
+            clouds: None,
+            tone_map: None,
+            motion_blur: None,
+            depth_of_field: None,
+            lens_effects: None,
+            transient_aliasing: AliasPlan::default(),
+            dynamic_resolution,
+            slideshow: None,
+            world: None,
+            profiler: None,
+            resource_overlay: None,
+            text_box: None,
+            #[cfg(feature = "rebuild-fade")]
+            rebuild_fade: None,
+            debug_view: None,
+            sketch: None,
+            scenes: None,
+            crossfade: None,
+            picking: None,
+            cursor: None,
+            left_pressed: false,
+            input_time: None,
+            text_input_enabled: false,
+            ime_position: None,
+            events: PendingEvents::default(),
+            late_latch,
+            frame_step: FrameStep::default(),
+            capture_requested: false,
+            capture: None,
+            screenshot_requested: false,
+            capture_encoding: CaptureEncoding::default(),
+            gizmos: Gizmos::new(),
+            gizmo_overlay: None,
+            params: Params::new(),
+            modifiers: ModifiersState::empty(),
+            clock,
+            frames: FrameCounter::new(Instant::now()),
+            timing: FrameTiming::default(),
+            previous_time: 0.0,
+            frame_stats: FrameStats::new(),
+            culled: CullStats::default(),
+            coordinate_space: CoordinateSpace::default(),
+            app_name,
+            sketch_info,
+            window_config: window_config.clone(),
+            title_template,
+            lut,
+            #[cfg(feature = "audio")]
+            audio: load_audio(),
+            #[cfg(feature = "network")]
+            network: None,
+            #[cfg(feature = "replay")]
+            replay: None,
+            #[cfg(any(feature = "scripting", feature = "lua"))]
+            scripting: None,
+            #[cfg(feature = "hot-reload")]
+            _asset_watcher: None,
+            #[cfg(feature = "profiling")]
+            _puffin_server: None,
+            #[cfg(feature = "tracy")]
+            tracy: tracy_client::Client::start(),
+            simulation_name,
+            #[cfg(feature = "snapshot")]
+            snapshot_slot: 1,
+            #[cfg(feature = "snapshot")]
+            pending_snapshot: None,
+            #[cfg(feature = "snapshot")]
+            camera: None,
+        })
+    }
+
+    /// Open a window with nothing but the default triangle, ready for a
+    /// sketch.
+    pub fn new() -> Result<Self> {
+        Self::with_latency_mode(LatencyMode::Throughput)
+    }
+
+    /// Like `new`, with frames paced for throughput or latency. Sketches
+    /// drawn with a pen or mouse feel more direct with reduced latency.
+    pub fn with_latency_mode(latency_mode: LatencyMode) -> Result<Self> {
+        Self::initialize(
+            None,
+            None,
+            None,
+            None,
+            latency_mode,
+            CapturePolicy::Default,
+        )
+    }
+
+    /// Open a window for a sketch of type `S`, named and sized as its
+    /// `Sketch::info` asks.
+    pub fn for_sketch<S: Sketch>() -> Result<Self> {
+        Self::with_sketch_info(S::info())
+    }
+
+    /// Open a window for a sketch described by `sketch_info`.
+    pub fn with_sketch_info(sketch_info: SketchInfo) -> Result<Self> {
+        Self::with_window_config(sketch_info.window_config(), sketch_info)
+    }
+
+    /// Open a window for a sketch described by `sketch_info`, with a window
+    /// config which overrides whatever the sketch asked for. Start from
+    /// `SketchInfo::window_config` to only change part of it.
+    pub fn with_window_config(
+        window_config: WindowConfig,
+        sketch_info: SketchInfo,
+    ) -> Result<Self> {
+        let display = Display::create_windowed(window_config.clone())
+            .context("unable to create the display")?;
+        Self::with_display(
+            display,
+            &window_config,
+            sketch_info,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// The sketch's name and version, as given when the application was
+    /// created.
+    pub fn sketch_info(&self) -> &SketchInfo {
+        &self.sketch_info
+    }
+
+    /// Where the current frame is in time.
+    pub fn frame_timing(&self) -> FrameTiming {
+        self.timing
+    }
+
+    /// The display sketches build their renderers with.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Draw the sketch in place of the triangle, or of the simulation when
+    /// there is one.
+    pub fn set_sketch(&mut self, sketch: impl Sketch + 'static) {
+        self.sketch = Some(Box::new(sketch));
+    }
+
+    /// Draw the scenes' sketches in place of the triangle, starting with the
+    /// first registered or whichever the manager was told to switch to.
+    /// Shift and 1 to 9 switch between the first nine scenes.
+    pub fn set_scenes(&mut self, scenes: SceneManager) {
+        self.scenes = Some(scenes);
+    }
+
+    /// Run a Rhai or Lua script, picked by the file's extension, which
+    /// controls the camera, the triangle, and any meshes it loads. The
+    /// script is run again whenever it is saved.
+    #[cfg(any(feature = "scripting", feature = "lua"))]
+    pub fn load_script(&mut self, path: &Path) -> Result<()> {
+        let mut engine = ScriptEngine::new();
+        engine.load_file(path)?;
+        let meshes = ScriptedMeshes::new(&self.display)?;
+        self.scripting = Some((engine, meshes));
+        Ok(())
+    }
+
+    /// Reload assets when they change on disk: the grading LUT, the bokeh
+    /// star, the slides, the title template, and meshes loaded by scripts.
+    /// Only the changed asset is loaded again, the rest of the scene is left
+    /// alone.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_assets(&mut self) -> Result<()> {
+        let mut watcher =
+            AssetWatcher::with_events(self.display.event_proxy.clone())?;
+        watcher.watch(Path::new(ASSET_DIRECTORY))?;
+        // a user supplied lut can live anywhere
+        if let Some(directory) = self.lut.as_deref().and_then(Path::parent) {
+            if !directory.as_os_str().is_empty()
+                && !asset_watcher::is_same_asset(
+                    directory,
+                    Path::new(ASSET_DIRECTORY),
+                )
+            {
+                watcher.watch(directory)?;
+            }
+        }
+        self._asset_watcher = Some(watcher);
+        if Path::new(TITLE_TEMPLATE).exists() {
+            self.load_title_template(Path::new(TITLE_TEMPLATE))?;
+        }
+        Ok(())
+    }
+
+    /// Show the window title as `template` describes from the next refresh
+    /// on, see `WindowConfig::title_template`.
+    pub fn set_title_template(&mut self, template: &str) {
+        self.title_template = TitleTemplate::parse(template);
+    }
+
+    /// Send events to the main loop from other threads, waking it. They're
+    /// handled at the start of the next frame.
+    pub fn event_proxy(&self) -> EventLoopProxy<AppEvent> {
+        self.display.event_proxy.clone()
+    }
+
+    /// Draw the entities in the demo world on top of the scene, updating
+    /// them every frame.
+    pub fn create_world(&mut self) -> Result<()> {
+        #[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+        let world = World::demo(&self.display)?;
+        #[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+        let world = SpecsWorld::demo(&self.display)?;
+        #[cfg(feature = "bevy-ecs")]
+        let world = ecs::bevy_backend::demo_app(&self.display)?;
+        let mut renderer = MeshRenderer::new(&self.display)?;
+        if let Some(debug_view) = &self.debug_view {
+            renderer.set_debug_view(&self.display, debug_view.mode())?;
+        }
+        self.world = Some((world, renderer));
+        Ok(())
+    }
+
+    /// Record every key press, to be saved to `path` when the window is
+    /// closed.
+    ///
+    /// @param simulation the name the simulation was created with, so the
+    /// replay can create it again
+    #[cfg(feature = "replay")]
+    pub fn record(&mut self, path: PathBuf, simulation: Option<&str>) {
+        let seed = self
+            .simulation
+            .as_ref()
+            .and_then(|simulation| simulation.seed());
+        let recorder = ReplayRecorder::new(simulation, seed);
+        self.replay = Some(Replay::Recording(recorder, path));
+    }
+
+    /// Press the recorded keys on the frames they were recorded on. The
+    /// application should have been created with the player's simulation
+    /// and seed.
+    #[cfg(feature = "replay")]
+    pub fn play(&mut self, player: ReplayPlayer) {
+        self.replay = Some(Replay::Playing(player));
+    }
+
+    /// Send the camera to a server every frame and draw the objects it sends
+    /// back.
+    #[cfg(feature = "network")]
+    pub fn connect(&mut self, addr: std::net::SocketAddr) -> Result<()> {
+        let client = NetworkClient::connect(
+            addr,
+            Some(self.display.event_proxy.clone()),
+        )?;
+        let objects = RemoteObjects::new(&self.display)?;
+        self.network = Some((client, objects));
+        Ok(())
+    }
+
+    /// Move the remote objects to where the server says they are.
+    #[cfg(feature = "network")]
+    pub fn apply_server_state(&mut self, state: ServerState) -> Result<()> {
+        if let Some((_, objects)) = &mut self.network {
+            objects.apply(&state)?;
+        }
+        Ok(())
+    }
+
+    /// Serve profiling scopes to `puffin_viewer --url 127.0.0.1:8585`.
+    ///
+    /// Scopes are only recorded once the server starts, so a profiling build
+    /// costs next to nothing without it.
+    #[cfg(feature = "profiling")]
+    pub fn start_puffin_server(&mut self) -> Result<()> {
+        let server =
+            puffin_http::Server::new(PUFFIN_ADDRESS).with_context(|| {
+                format!("unable to serve profiling on {}", PUFFIN_ADDRESS)
+            })?;
+        puffin::set_scopes_on(true);
+        log::info!("serving profiling on {}", PUFFIN_ADDRESS);
+        self._puffin_server = Some(server);
+        Ok(())
+    }
+
+    /// Update and render the next frame, under a span for the frame.
+    fn frame(&mut self) -> Result<()> {
+        let _span =
+            tracing::info_span!("frame", id = self.timing.index).entered();
+        self.update()?;
+        self.render()
+    }
+
+    /// Update the application
+    fn update(&mut self) -> Result<()> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_scope!("update");
+        let _span = tracing::info_span!("update").entered();
+        #[cfg(feature = "snapshot")]
+        if let Some(snapshot) = self.pending_snapshot.take() {
+            self.restore_snapshot(snapshot)?;
+        }
+        let t = self.time();
+        self.timing = self.frames.begin(t, Instant::now());
+        // a restored snapshot can move the clock backwards
+        let dt = (t - self.previous_time).max(0.0);
+        self.previous_time = t;
+        #[cfg(feature = "replay")]
+        self.advance_replay()?;
+        self.update_scenes(dt)?;
+        self.dispatch_events();
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, meshes)) = &mut self.scripting {
+            engine.update(t);
+            meshes.sync(&self.display, &engine.state())?;
+        }
+        let step = 2.0 * PI / 3.0;
+        let a1 = step + t;
+        let a2 = step * 2.0 + t;
+        let a3 = step * 3.0 + t;
+
+        let [x, y] = self.triangle_position();
+        self.particles.set_vertices(vec![
+            particles::Vertex::new(
+                [x + a1.cos(), y + a1.sin()],
+                [1.0, 0.0, 0.0, 1.0],
+            ),
+            particles::Vertex::new(
+                [x + a2.cos(), y + a2.sin()],
+                [0.0, 1.0, 0.0, 1.0],
+            ),
+            particles::Vertex::new(
+                [x + a3.cos(), y + a3.sin()],
+                [0.0, 0.0, 1.0, 1.0],
+            ),
+        ]);
+
+        if let Some(simulation) = &mut self.simulation {
+            simulation.update(t);
+        }
+        let camera =
+            self.place_camera(Camera::for_extent(self.display.scene_extent()));
+        self.gizmos.begin_frame(
+            &camera,
+            self.display.swapchain.dimensions(),
+            self.cursor,
+        );
+        if let Some(sketch) = &mut self.sketch {
+            if let Some(picking) = &mut self.picking {
+                sketch.picked(picking.picked())?;
+            }
+            let render_scale = self.display.render_scale();
+            let cursor = self
+                .cursor
+                .map(|[x, y]| [x * render_scale, y * render_scale]);
+            sketch.timing(self.timing)?;
+            sketch.cursor(cursor, self.left_pressed)?;
+            sketch.gizmos(&mut self.gizmos)?;
+            for handle in self.gizmos.handles() {
+                if let GizmoEvent::DragEnded { from, to } = handle.event {
+                    let namespace = self.sketch_info.slug();
+                    let x = gizmo_param(&namespace, &handle.name, "x");
+                    let y = gizmo_param(&namespace, &handle.name, "y");
+                    self.params.set_auto(&x, from[0]);
+                    self.params.set_auto(&y, from[1]);
+                    self.params.set_user_all(vec![(x, to[0]), (y, to[1])]);
+                }
+            }
+            sketch.update(t, dt)?;
+        }
+        self.follow_sketch_text_field();
+        if let Some(transition) =
+            self.scenes.as_mut().and_then(SceneManager::transition_mut)
+        {
+            transition.outgoing.timing(self.timing)?;
+            transition.outgoing.update(t, dt)?;
+        }
+        if let Some(clouds) = &mut self.clouds {
+            clouds.set_time(t);
+        }
+        #[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+        if let Some((world, _)) = &mut self.world {
+            ecs::spin_system(world, dt);
+            ecs::transform_system(world)?;
+            ecs::light_system(world)?;
+        }
+        #[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+        if let Some((world, _)) = &mut self.world {
+            world.update(dt);
+        }
+        #[cfg(feature = "bevy-ecs")]
+        if self.world.is_some() {
+            let view = self
+                .place_camera(Camera::for_extent(self.display.scene_extent()));
+            if let Some((app, _)) = &mut self.world {
+                app.insert_resource(FrameTime(dt));
+                app.insert_resource(View(view));
+                app.update();
+            }
+        }
+        #[cfg(feature = "audio")]
+        if let Some((audio, _)) = &mut self.audio {
+            audio.update();
+        }
+        #[cfg(feature = "network")]
+        if let Some((client, _)) = &mut self.network {
+            // the camera is fixed at the origin looking down -z
+            client.send_frame([0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0])?;
+            if let Some(state) = client.latest_state() {
+                self.apply_server_state(state)?;
+            }
+        }
+        if let Some(report) = self.frame_stats.frame(self.timing.index) {
+            self.update_render_scale(report)?;
+            self.update_title(report, t);
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.update(
+                &self.frame_stats,
+                self.display.frame_wait_stats(),
+                self.culled,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Move any crossfade along by `dt` seconds, tearing the outgoing scene
+    /// down once it's done, then set up the scene a switch was requested to.
+    /// Both wait for the frames in flight first. This happens before
+    /// anything is drawn, so a frame never mixes scenes other than through
+    /// the crossfade.
+    fn update_scenes(&mut self, dt: f32) -> Result<()> {
+        let scenes = match &mut self.scenes {
+            Some(scenes) => scenes,
+            None => return Ok(()),
+        };
+        // nothing still in flight may draw a scene which is torn down
+        if let Some(finished) = scenes.advance(dt) {
+            self.display.wait_for_all_frames()?;
+            finished.tear_down();
+        }
+        let index = match scenes.take_pending() {
+            Some(index) => index,
+            None => return Ok(()),
+        };
+        self.display.wait_for_all_frames()?;
+        scenes.retire(self.sketch.take());
+        self.sketch = Some(scenes.setup(index, &self.display)?);
+        self.frame_stats.marker(format!("scene {}", index + 1));
+        if scenes.transition().is_some() && self.crossfade.is_none() {
+            self.crossfade = Some(Crossfade::new(&self.display)?);
+        }
+        Ok(())
+    }
+
+    /// Switch to the scene for a number key, if there are that many.
+    fn switch_scene(&mut self, key: VirtualKeyCode) {
+        if let Some(scenes) = &mut self.scenes {
+            let index = key as usize - VirtualKeyCode::Key1 as usize;
+            if let Err(error) = scenes.switch_to_index(index) {
+                log::warn!("unable to switch scenes: {}", error);
+            }
+        }
+    }
+
+    /// The crossfade and how far it's faded in, while scenes are fading.
+    /// The stereo eyes are drawn from their own cameras, so they cut
+    /// between scenes instead.
+    fn fading(&self) -> Option<(&Crossfade, f32)> {
+        if self.stereo.is_some() {
+            return None;
+        }
+        let transition = self.scenes.as_ref()?.transition()?;
+        Some((self.crossfade.as_ref()?, transition.mix()))
+    }
+
+    /// Render the outgoing and incoming scenes into the crossfade's targets.
+    fn fade_scenes(
+        &self,
+        submitter: &mut Submitter,
+        camera: &Camera,
+    ) -> Result<()> {
+        let (crossfade, _) = match self.fading() {
+            Some(fading) => fading,
+            None => return Ok(()),
+        };
+        let scenes = [
+            (
+                OUTGOING,
+                self.scenes
+                    .as_ref()
+                    .and_then(SceneManager::transition)
+                    .map(|transition| &transition.outgoing),
+            ),
+            (INCOMING, self.sketch.as_ref()),
+        ];
+        for (target, sketch) in scenes.iter() {
+            let commands = match sketch {
+                Some(sketch) => sketch.draw(&self.display, camera)?,
+                None => vec![],
+            };
+            submitter.add(
+                Stage::Scene,
+                "crossfade scene",
+                crossfade.render_scene(&self.display, *target, commands)?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Where the center of the triangle is, a script can move it.
+    fn triangle_position(&self) -> [f32; 2] {
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, _)) = &self.scripting {
+            return engine.state().triangle_position;
+        }
+        [0.0, 0.0]
+    }
+
+    /// The camera in the application's coordinate space, moved to wherever
+    /// the world's camera, a snapshot, or the script placed it.
+    fn place_camera(&self, camera: Camera) -> Camera {
+        let camera = camera.with_space(self.coordinate_space);
+        #[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+        let pose = self.world.as_ref().and_then(|(world, _)| world.camera());
+        #[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+        let pose = self
+            .world
+            .as_ref()
+            .and_then(|(world, _)| world.render_data().camera);
+        #[cfg(feature = "bevy-ecs")]
+        let pose = self
+            .world
+            .as_ref()
+            .and_then(|(app, _)| app.world().resource::<RenderData>().camera);
+        let camera = match pose {
+            Some(pose) => camera.looking_from(pose.position, pose.target),
+            None => camera,
+        };
+        #[cfg(feature = "snapshot")]
+        let camera = match &self.camera {
+            Some(pose) => camera.looking_from(pose.position, pose.target),
+            None => camera,
+        };
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, _)) = &self.scripting {
+            let state = engine.state();
+            return camera.looking_from(
+                state.camera_position.unwrap_or(camera.position),
+                state.camera_target.unwrap_or(camera.target),
+            );
+        }
+        camera
+    }
+
+    /// Seconds since the application started. Recordings and replays advance
+    /// a fixed step each frame so the simulations see the same times when
+    /// the recording is played back.
+    fn time(&self) -> f32 {
+        #[cfg(feature = "replay")]
+        if self.replay.is_some() {
+            return self.frames.next_index() as f32 * replay::FRAME_TIME;
+        }
+        self.clock.time(Instant::now())
+    }
+
+    /// Press any keys recorded on this frame, then move on to the next one.
+    #[cfg(feature = "replay")]
+    fn advance_replay(&mut self) -> Result<()> {
+        let events = match &mut self.replay {
+            Some(Replay::Playing(player)) if !player.is_finished() => {
+                let events = player.events_for(self.timing.index);
+                if player.is_finished() {
+                    log::info!(
+                        "replay finished on frame {}",
+                        self.timing.index
+                    );
+                }
+                events
+            }
+            _ => vec![],
+        };
+        for event in events {
+            match event {
+                InputEvent::KeyPressed(key) => self.handle_key(key)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Save the recording, if there is one.
+    #[cfg(feature = "replay")]
+    fn save_replay(&self) -> Result<()> {
+        if let Some(Replay::Recording(recorder, path)) = &self.replay {
+            recorder.save(path)?;
+            log::info!(
+                "saved {} events over {} frames to {:?}",
+                recorder.events.len(),
+                self.frames.next_index(),
+                path
+            );
+        }
+        Ok(())
+    }
+
+    /// Everything about the application which can be restored later.
+    #[cfg(feature = "snapshot")]
+    fn snapshot(&self) -> Snapshot {
+        let camera =
+            self.place_camera(Camera::for_extent(self.display.scene_extent()));
+        let simulation = self
+            .simulation
+            .as_ref()
+            .zip(self.simulation_name.as_ref())
+            .map(|(simulation, name)| SimulationSettings {
+                name: name.clone(),
+                seed: simulation.seed(),
+                params: simulation.snapshot(),
+            });
+        Snapshot {
+            version: snapshot::SNAPSHOT_VERSION,
+            time: self.time(),
+            simulation,
+            camera: CameraSettings {
+                position: camera.position,
+                target: camera.target,
+            },
+            lut: self.lut.clone(),
+            color_grading: self.tone_map.as_ref().map(Snapshottable::snapshot),
+            clouds: self.clouds.as_ref().map(Snapshottable::snapshot),
+            motion_blur: self.motion_blur.as_ref().map(Snapshottable::snapshot),
+            depth_of_field: self
+                .depth_of_field
+                .as_ref()
+                .map(Snapshottable::snapshot),
+            lens_effects: self
+                .lens_effects
+                .as_ref()
+                .map(Snapshottable::snapshot),
+            stereo: self.stereo.is_some(),
+            slideshow: self.slideshow.is_some(),
+            dynamic_resolution: self.dynamic_resolution.is_some(),
+        }
+    }
+
+    /// Save a snapshot to the current slot. Failing to save is logged rather
+    /// than closing the window.
+    #[cfg(feature = "snapshot")]
+    fn save_snapshot(&self) {
+        let path = snapshot::slot_path(
+            Path::new(snapshot::SNAPSHOT_DIRECTORY),
+            self.snapshot_slot,
+        );
+        match self.snapshot().save(&path) {
+            Ok(()) => log::info!("saved a snapshot to {:?}", path),
+            Err(error) => log::error!("unable to save a snapshot {:?}", error),
+        }
+    }
+
+    /// Load the snapshot in the current slot, to be restored at the start of
+    /// the next frame.
+    #[cfg(feature = "snapshot")]
+    fn load_snapshot(&mut self) {
+        let path = snapshot::slot_path(
+            Path::new(snapshot::SNAPSHOT_DIRECTORY),
+            self.snapshot_slot,
+        );
+        match Snapshot::load(&path) {
+            Ok(snapshot) => self.pending_snapshot = Some(snapshot),
+            Err(error) => log::error!("unable to load a snapshot {:?}", error),
+        }
+    }
+
+    /// Put everything back the way the snapshot saw it.
+    ///
+    /// The simulation is always created again, its state on the gpu comes
+    /// from the snapshot's seed rather than from the moment it was taken.
+    /// Subsystems which were off are created and the rest keep their
+    /// resources, only rebuilding what the restored settings change.
+    #[cfg(feature = "snapshot")]
+    fn restore_snapshot(&mut self, snapshot: Snapshot) -> Result<()> {
+        for (key, value) in self.current_params() {
+            self.params.set_auto(&key, value);
+        }
+        self.clock.set_time(snapshot.time, Instant::now());
+
+        self.simulation = match &snapshot.simulation {
+            Some(settings) => {
+                let mut simulation = compute_pipeline::create_simulation(
+                    &settings.name,
+                    &self.display,
+                    &self.builtins,
+                    settings.seed,
+                )?;
+                simulation.restore(&self.display, &settings.params)?;
+                Some(simulation)
+            }
+            None => None,
+        };
+        self.simulation_name = snapshot
+            .simulation
+            .as_ref()
+            .map(|settings| settings.name.clone());
+        self.camera = Some(snapshot.camera);
+
+        if self.lut != snapshot.lut {
+            self.lut = snapshot.lut.clone();
+            // the new lut is loaded below
+            self.tone_map = None;
+        }
+        let (display, builtins, lut) =
+            (&self.display, &self.builtins, self.lut.as_deref());
+        snapshot::restore_enabled(
+            &mut self.tone_map,
+            snapshot.color_grading.as_ref(),
+            display,
+            |display| create_tone_map(display, builtins, lut),
+        )?;
+        snapshot::restore_enabled(
+            &mut self.clouds,
+            snapshot.clouds.as_ref(),
+            display,
+            VolumetricClouds::new,
+        )?;
+        snapshot::restore_enabled(
+            &mut self.motion_blur,
+            snapshot.motion_blur.as_ref(),
+            display,
+            MotionBlur::new,
+        )?;
+        snapshot::restore_enabled(
+            &mut self.depth_of_field,
+            snapshot.depth_of_field.as_ref(),
+            display,
+            DepthOfField::new,
+        )?;
+        snapshot::restore_enabled(
+            &mut self.lens_effects,
+            snapshot.lens_effects.as_ref(),
+            display,
+            LensEffects::new,
+        )?;
+        self.alias_post_process_targets()?;
+
+        if snapshot.stereo != self.stereo.is_some() {
+            self.toggle_stereo()?;
+        }
+        if snapshot.slideshow != self.slideshow.is_some() {
+            self.toggle_slideshow()?;
+        }
+        if snapshot.dynamic_resolution != self.dynamic_resolution.is_some() {
+            self.toggle_dynamic_resolution()?;
+        }
+        // the whole restore is undone in one step
+        self.params.set_user_all(self.current_params());
+        log::info!("restored snapshot slot {}", self.snapshot_slot);
+        Ok(())
+    }
+
+    /// Let the dynamic resolution controller pick a new scale from the
+    /// latest frame stats.
+    fn update_render_scale(&mut self, report: FrameReport) -> Result<()> {
+        let scale = match &mut self.dynamic_resolution {
+            Some(dynamic_resolution) => {
+                dynamic_resolution.controller.update(report)
+            }
+            None => None,
+        };
+        if let Some(scale) = scale {
+            self.display.set_render_scale(scale);
+            self.rebuild_render_resources()?;
+            let [width, height] = self.display.scene_extent();
+            log::info!(
+                "render scale {:.2}, drawing the scene at {}x{}",
+                scale,
+                width,
+                height
+            );
+        }
+        Ok(())
+    }
+
+    /// Refresh the window title with the latest frame stats.
+    fn update_title(&mut self, report: FrameReport, time: f32) {
+        let gpu = self.display.device.physical_device().name().to_owned();
+        let title = self.title_template.render(&TitleValues {
+            app: &self.app_name,
+            fps: report.fps,
+            frame_time: report.frame_time,
+            latency: report.latency,
+            gpu: &gpu,
+            extent: self.display.swapchain.dimensions(),
+            render_scale: self.display.render_scale(),
+            scene_extent: self.display.scene_extent(),
+            time,
+            seed: self
+                .simulation
+                .as_ref()
+                .and_then(|simulation| simulation.seed()),
+        });
+        self.display.surface.window().set_title(&title);
+    }
+
+    /// Draw the screen.
+    fn render(&mut self) -> Result<()> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_scope!("render");
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("render");
+        let mut submitter = Submitter::default();
+        if let Some(simulation) = &mut self.simulation {
+            let commands = {
+                #[cfg(feature = "profiling")]
+                puffin::profile_scope!("simulation step");
+                simulation.step(&self.display)?
+            };
+            submitter.add(simulation.stage(), "simulation", commands);
+        }
+        if let Some(sketch) = &mut self.sketch {
+            for commands in sketch.render_offscreen(&self.display)? {
+                submitter.add(Stage::Scene, "sketch offscreen", commands);
+            }
+            let mut raw_frame = RawFrame::new(&self.display);
+            sketch.render_raw(&mut raw_frame)?;
+            raw_frame.submit(&mut submitter);
+        }
+        // the world is culled once while it's updated, not once per eye
+        let mut culled = CullStats::default();
+        #[cfg(feature = "bevy-ecs")]
+        if let Some((app, _)) = &self.world {
+            culled += app.world().resource::<RenderData>().culled;
+        }
+        // the stereo eyes don't match the cursor, so nothing is picked
+        if self.stereo.is_none() {
+            self.pick(&mut submitter)?;
+        }
+        let scene_commands = match &self.stereo {
+            Some(stereo) => {
+                for (eye, camera) in
+                    stereo.eye_cameras(&self.display).iter().enumerate()
+                {
+                    let eye_commands = self
+                        .draw_scene(&self.place_camera(*camera), &mut culled)?;
+                    submitter.add(
+                        Stage::Scene,
+                        "stereo eye",
+                        stereo.render_eye(&self.display, eye, eye_commands)?,
+                    );
+                }
+                vec![stereo.composite(&self.display)?]
+            }
+            None => {
+                let camera = self.place_camera(Camera::for_extent(
+                    self.display.scene_extent(),
+                ));
+                self.fade_scenes(&mut submitter, &camera)?;
+                let scene_commands = self.draw_scene(&camera, &mut culled)?;
+                match &self.dynamic_resolution {
+                    Some(dynamic_resolution) => {
+                        submitter.add(
+                            Stage::Scene,
+                            "scaled scene",
+                            dynamic_resolution
+                                .render_scene(&self.display, scene_commands)?,
+                        );
+                        vec![dynamic_resolution.draw(&self.display)?]
+                    }
+                    None => scene_commands,
+                }
+            }
+        };
+        self.culled = culled;
+        let scene_commands = match &mut self.depth_of_field {
+            Some(depth_of_field) => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "depth of field scene",
+                    depth_of_field
+                        .render_scene(&self.display, scene_commands)?,
+                );
+
+                // like the velocities, only the particles have a distance
+                let camera =
+                    Camera::for_extent(self.display.swapchain.dimensions())
+                        .with_space(self.coordinate_space);
+                let distances = match (&self.sketch, &self.simulation) {
+                    (None, None) => self.particles.distance_vertices(&camera),
+                    _ => vec![],
+                };
+                if let Some(first) = distances.first() {
+                    depth_of_field.focus_on(&camera, first.pos);
+                }
+                submitter.add(
+                    Stage::PostProcess,
+                    "depth of field distance",
+                    depth_of_field.render_distance(
+                        &self.display,
+                        distances,
+                        camera.screen_space(),
+                    )?,
+                );
+                vec![depth_of_field.draw(&self.display)?]
+            }
+            None => scene_commands,
+        };
+        let scene_commands = match &self.motion_blur {
+            Some(motion_blur) => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "motion blur scene",
+                    motion_blur.render_scene(&self.display, scene_commands)?,
+                );
+
+                // the particles are already in clip space, while sketches and
+                // simulations draw their own geometry, which is treated as
+                // static
+                let camera =
+                    Camera::for_extent(self.display.swapchain.dimensions())
+                        .with_space(self.coordinate_space);
+                let velocities = match (&self.sketch, &self.simulation) {
+                    (None, None) => self.particles.velocity_vertices(&camera),
+                    _ => vec![],
+                };
+                submitter.add(
+                    Stage::PostProcess,
+                    "motion blur velocity",
+                    motion_blur.render_velocity(
+                        &self.display,
+                        velocities,
+                        post_process::IDENTITY,
+                        post_process::IDENTITY,
+                    )?,
+                );
+                vec![motion_blur.draw(&self.display)?]
+            }
+            None => scene_commands,
+        };
+        let scene_commands = match &self.lens_effects {
+            Some(lens_effects) => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "lens effects scene",
+                    lens_effects.render_scene(&self.display, scene_commands)?,
+                );
+                vec![lens_effects.draw(&self.display)?]
+            }
+            None => scene_commands,
+        };
+        let mut draw_commands = match &self.tone_map {
+            Some(tone_map) => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "tone map scene",
+                    tone_map.render_scene(&self.display, scene_commands)?,
+                );
+                vec![tone_map.draw(&self.display)?]
+            }
+            None => scene_commands,
+        };
+        if let Some(debug_view) = &self.debug_view {
+            // like the depth of field, only the particles have a depth
+            let camera =
+                Camera::for_extent(self.display.swapchain.dimensions())
+                    .with_space(self.coordinate_space);
+            let distances = match (&self.sketch, &self.simulation) {
+                (None, None) => self.particles.distance_vertices(&camera),
+                _ => vec![],
+            };
+            draw_commands = debug_view.draw(
+                &self.display,
+                &mut submitter,
+                draw_commands,
+                distances,
+                &camera,
+            )?;
+        }
+        if self.gizmo_overlay.is_none() && !self.gizmos.handles().is_empty() {
+            self.gizmo_overlay = Some(GizmoOverlay::new(&self.display)?);
+        }
+        if let Some(gizmo_overlay) = &self.gizmo_overlay {
+            draw_commands
+                .extend(gizmo_overlay.draw(&self.display, &self.gizmos)?);
+        }
+        if let Some(profiler) = &self.profiler {
+            draw_commands.extend(profiler.draw(&self.display)?);
+        }
+        if let Some(resource_overlay) = &self.resource_overlay {
+            draw_commands.push(
+                resource_overlay
+                    .draw(&self.display, &self.transient_aliasing)?,
+            );
+        }
+        if let Some(text_box) = &self.text_box {
+            draw_commands.push(text_box.draw(&self.display)?);
+        }
+        if self.capture_requested {
+            self.capture_requested = false;
+            let (capture, render, copy) =
+                Capture::record(&self.display, draw_commands)?;
+            submitter.add(Stage::PostProcess, "capture", render);
+            submitter.add(Stage::PostProcess, "capture readback", copy);
+            self.capture = Some(capture);
+            draw_commands = vec![];
+        }
+        #[cfg(feature = "rebuild-fade")]
+        if let Some(rebuild_fade) = &mut self.rebuild_fade {
+            draw_commands.push(rebuild_fade.draw_next(&self.display)?);
+            if rebuild_fade.finished() {
+                self.rebuild_fade = None;
+            }
+        }
+        if let Some(input_time) = self.input_time.take() {
+            submitter.set_input_time(input_time);
+        }
+        let state = match submitter.submit(&mut self.display, draw_commands) {
+            Ok(state) => {
+                self.finish_failure_capture(None);
+                state
+            }
+            Err(error) => return self.submission_failed(error),
+        };
+        self.clock.frame_presented(Instant::now());
+        if let Some(latency) = self.display.take_frame_latency() {
+            self.frame_stats.record_latency(latency.total());
+        }
+        if self.screenshot_requested {
+            self.screenshot_requested = false;
+            self.save_screenshot();
+        }
+
+        // a suboptimal swapchain, or a present which is being retried on
+        // another queue family
+        if let SwapchainState::NeedsRebuild = state {
+            self.rebuild_swapchain_resources()?;
+        }
+        Ok(())
+    }
+
+    /// With reduced latency, wait for the previous frame to finish before
+    /// the next samples its input. While late latching, this returns when
+    /// to sample it instead if that's still to come.
+    fn latch_input(&mut self) -> Result<Option<Instant>> {
+        if self.display.latency_mode() == LatencyMode::Throughput {
+            return Ok(None);
+        }
+        self.display.wait_for_frame_slot()?;
+        let late_latch = match &mut self.late_latch {
+            Some(late_latch) => late_latch,
+            None => return Ok(None),
+        };
+        if let Some(presented) = self.display.last_presented() {
+            late_latch.frame_presented(presented);
+        }
+        let now = Instant::now();
+        match late_latch.latch_point() {
+            Some(latch) if latch > now => Ok(Some(latch)),
+            _ => {
+                late_latch.input_sampled(now);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Add the pass which finds the sketch's draw under the cursor.
+    fn pick(&mut self, submitter: &mut Submitter) -> Result<()> {
+        let (draw_list, transforms) =
+            match self.sketch.as_ref().and_then(|sketch| sketch.pickable()) {
+                Some(pickable) => pickable,
+                None => return Ok(()),
+            };
+        if self.picking.is_none() {
+            self.picking = Some(PickingPass::new(&self.display)?);
+        }
+        let camera =
+            self.place_camera(Camera::for_extent(self.display.scene_extent()));
+        let window_extent = self.display.swapchain.dimensions();
+        if let Some(picking) = &mut self.picking {
+            let pixel = self.cursor.and_then(|cursor| {
+                picking::pick_pixel(cursor, window_extent, picking.extent())
+            });
+            if let Some(commands) = picking.render(
+                &self.display,
+                &camera,
+                draw_list,
+                transforms,
+                pixel,
+            )? {
+                submitter.add(Stage::Scene, "picking", commands);
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the secondary command buffers which draw the scene as seen by
+    /// the camera, adding what was culled along the way to `culled`.
+    fn draw_scene(
+        &self,
+        camera: &Camera,
+        culled: &mut CullStats,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        #[cfg(feature = "profiling")]
+        puffin::profile_scope!("draw scene");
+        #[cfg(feature = "tracy")]
+        let _span = tracy_client::span!("draw scene");
+        let _draw = tracing::info_span!("draw").entered();
+        let mut commands = vec![];
+        if let Some(clouds) = &self.clouds {
+            commands.push(clouds.draw(&self.display, camera)?);
+        }
+        match (&self.sketch, &self.simulation) {
+            (Some(sketch), _) => match self.fading() {
+                Some((crossfade, mix)) => {
+                    commands.push(crossfade.draw(&self.display, mix)?)
+                }
+                None => commands.extend(sketch.draw(&self.display, camera)?),
+            },
+            (None, Some(simulation)) => {
+                commands.push(simulation.draw(&self.display, camera)?);
+                *culled += simulation.culled();
+            }
+            (None, None) => {
+                commands.push(self.particles.draw(&self.display, camera)?);
+                *culled += self.particles.culled();
+            }
+        }
+        #[cfg(not(any(feature = "bevy-ecs", feature = "specs")))]
+        if let Some((world, renderer)) = &self.world {
+            commands.push(renderer.draw_lit(
+                &self.display,
+                camera,
+                &ecs::render_system(world)?,
+                world.transforms(),
+                world.lights(),
+            )?);
+        }
+        #[cfg(all(feature = "specs", not(feature = "bevy-ecs")))]
+        if let Some((world, renderer)) = &self.world {
+            let render = world.render_data();
+            commands.push(renderer.draw_lit(
+                &self.display,
+                camera,
+                &render.draw_list,
+                &world.transforms().0,
+                &render.lights,
+            )?);
+        }
+        #[cfg(feature = "bevy-ecs")]
+        if let Some((app, renderer)) = &self.world {
+            let render = app.world().resource::<RenderData>();
+            commands.push(renderer.draw_lit(
+                &self.display,
+                camera,
+                &render.draw_list,
+                &render.transforms,
+                &render.lights,
+            )?);
+        }
+        #[cfg(feature = "network")]
+        if let Some((_, objects)) = &self.network {
+            commands.push(objects.draw(&self.display, camera)?);
+        }
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, meshes)) = &self.scripting {
+            commands.push(meshes.draw(
+                &self.display,
+                camera,
+                &engine.state(),
+            )?);
+        }
+        if let Some(slideshow) = &self.slideshow {
+            commands.push(self.draw_slideshow(slideshow, camera)?);
+        }
+        Ok(commands)
+    }
+
+    /// Draw the current slide, with a thumbnail of every slide below it.
+    fn draw_slideshow(
+        &self,
+        slideshow: &SpriteRenderer,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let layers = slideshow.sheet.layers;
+        let t = self.time();
+        let current = (t / SLIDE_SECONDS) as u32 % layers;
+
+        let mut sprites = vec![Sprite {
+            center: camera.to_ndc([0.0, -0.15]),
+            size: camera.to_ndc([1.2, 1.2]),
+            layer: current,
+        }];
+        let thumbnail = 0.25;
+        let spacing = thumbnail * 1.2;
+        let first = -spacing * (layers - 1) as f32 / 2.0;
+        sprites.extend((0..layers).map(|layer| Sprite {
+            center: camera.to_ndc([first + spacing * layer as f32, 0.75]),
+            size: camera.to_ndc([thumbnail, thumbnail]),
+            layer,
+        }));
+        slideshow.draw(&self.display, &sprites)
+    }
+
+    /// Respond to a key press:
+    ///
+    /// - Tab toggles the stereo preview
+    /// - C toggles the clouds
+    /// - G toggles color grading, `[` and `]` adjust its strength, and D
+    ///   toggles its dithering
+    /// - M toggles motion blur, and `,` and `.` adjust its strength
+    /// - F toggles depth of field, and B cycles its bokeh shape
+    /// - L toggles the lens effects
+    /// - R toggles dynamic resolution
+    /// - S toggles the slideshow
+    /// - F2 opens the text box
+    /// - F3 toggles the frame time graph, and Shift+F3 exports its frames to
+    ///   `frame_stats.csv`
+    /// - F4 cycles the debug views
+    /// - F7 toggles the table of live gpu resources
+    /// - F11 toggles fullscreen
+    /// - F12 saves a screenshot to `screenshots`
+    /// - P cycles how the particles blend
+    /// - T locks the clock to the display's refresh rate
+    /// - Shift and 1 to 9 switch scenes
+    /// - 1 to 9 pick the snapshot slot F5 saves to and F9 restores from
+    /// - Ctrl+Z undoes the last edit, and Ctrl+Shift+Z redoes it
+    ///
+    /// Every other key is passed to the simulation and the sketch.
+    ///
+    /// Keys pressed by the user come through `handle_key_press`.
+    fn handle_key(&mut self, key: VirtualKeyCode) -> Result<()> {
+        match key {
+            VirtualKeyCode::Z if self.modifiers.ctrl() => {
+                self.undo_edit(self.modifiers.shift())
+            }
+            VirtualKeyCode::Tab => self.toggle_stereo()?,
+            VirtualKeyCode::C => self.toggle_clouds()?,
+            VirtualKeyCode::G => self.toggle_color_grading()?,
+            VirtualKeyCode::LBracket => self.adjust_grading_strength(-0.1),
+            VirtualKeyCode::RBracket => self.adjust_grading_strength(0.1),
+            VirtualKeyCode::D => self.toggle_dither(),
+            VirtualKeyCode::M => self.toggle_motion_blur()?,
+            VirtualKeyCode::F => self.toggle_depth_of_field()?,
+            VirtualKeyCode::B => self.cycle_bokeh_shape()?,
+            VirtualKeyCode::L => self.toggle_lens_effects()?,
+            VirtualKeyCode::R => self.toggle_dynamic_resolution()?,
+            VirtualKeyCode::S => self.toggle_slideshow()?,
+            VirtualKeyCode::F3 if self.modifiers.shift() => {
+                self.export_frame_stats()
+            }
+            VirtualKeyCode::F3 => self.toggle_profiler()?,
+            VirtualKeyCode::F4 => self.cycle_debug_view()?,
+            VirtualKeyCode::F2 => self.toggle_text_box()?,
+            VirtualKeyCode::F7 => self.toggle_resource_overlay()?,
+            VirtualKeyCode::F11 => self.display.toggle_fullscreen(),
+            VirtualKeyCode::F12 => self.request_screenshot(),
+            VirtualKeyCode::P => self.cycle_particle_blend_mode()?,
+            VirtualKeyCode::T => self.toggle_display_locked_time(),
+            VirtualKeyCode::Comma => self.adjust_blur_strength(-0.25),
+            VirtualKeyCode::Period => self.adjust_blur_strength(0.25),
+            VirtualKeyCode::Key1
+            | VirtualKeyCode::Key2
+            | VirtualKeyCode::Key3
+            | VirtualKeyCode::Key4
+            | VirtualKeyCode::Key5
+            | VirtualKeyCode::Key6
+            | VirtualKeyCode::Key7
+            | VirtualKeyCode::Key8
+            | VirtualKeyCode::Key9
+                if self.modifiers.shift() && self.scenes.is_some() =>
+            {
+                self.switch_scene(key)
+            }
+            #[cfg(feature = "snapshot")]
+            VirtualKeyCode::Key1
+            | VirtualKeyCode::Key2
+            | VirtualKeyCode::Key3
+            | VirtualKeyCode::Key4
+            | VirtualKeyCode::Key5
+            | VirtualKeyCode::Key6
+            | VirtualKeyCode::Key7
+            | VirtualKeyCode::Key8
+            | VirtualKeyCode::Key9 => {
+                self.snapshot_slot =
+                    key as u32 - VirtualKeyCode::Key1 as u32 + 1;
+                log::info!("snapshot slot {}", self.snapshot_slot);
+            }
+            #[cfg(feature = "snapshot")]
+            VirtualKeyCode::F5 => self.save_snapshot(),
+            #[cfg(feature = "snapshot")]
+            VirtualKeyCode::F9 => self.load_snapshot(),
+            _ => {
+                #[cfg(feature = "audio")]
+                if key == VirtualKeyCode::Space {
+                    if let Some((audio, click)) = &mut self.audio {
+                        audio.play(*click, 0.5, false)?;
+                    }
+                }
+                if let Some(simulation) = &mut self.simulation {
+                    simulation.handle_key(key);
+                }
+                if let Some(sketch) = &mut self.sketch {
+                    sketch.handle_key(key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Respond to a key pressed by the user. Key presses are recorded while
+    /// recording and ignored while a replay is still playing. Pause toggles
+    /// the frame step and F10 steps, neither is recorded.
+    ///
+    /// While text input is enabled no shortcut works, keys only edit the
+    /// text, see `handle_text_key`.
+    fn handle_key_press(&mut self, key: VirtualKeyCode) -> Result<()> {
+        if self.text_input_enabled {
+            return self.handle_text_key(key);
+        }
+        // stepping is for whoever is at the keyboard, it isn't recorded or
+        // seen by scripts
+        match key {
+            VirtualKeyCode::Pause => {
+                self.toggle_frame_step();
+                return Ok(());
+            }
+            VirtualKeyCode::F10 if self.frame_step.active() => {
+                self.frame_step.request();
+                return Ok(());
+            }
+            _ => (),
+        }
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, _)) = &mut self.scripting {
+            engine.key(key, true);
+        }
+        #[cfg(feature = "replay")]
+        match &mut self.replay {
+            Some(Replay::Recording(recorder, _)) => recorder
+                .record(self.frames.next_index(), InputEvent::KeyPressed(key)),
+            Some(Replay::Playing(player)) if !player.is_finished() => {
+                return Ok(());
+            }
+            _ => (),
+        }
+        self.handle_key(key)
+    }
+
+    /// Switch between the regular view and the side-by-side stereo preview.
+    pub fn toggle_stereo(&mut self) -> Result<()> {
+        self.stereo = match self.stereo.take() {
+            Some(_) => None,
+            None => Some(StereoPreview::new(&self.display)?),
+        };
+        log::info!("stereo preview enabled: {}", self.stereo.is_some());
+        Ok(())
+    }
+
+    /// Show or hide the volumetric clouds behind the scene.
+    pub fn toggle_clouds(&mut self) -> Result<()> {
+        self.clouds = match self.clouds.take() {
+            Some(_) => None,
+            None => Some(VolumetricClouds::new(&self.display)?),
+        };
+        log::info!("clouds enabled: {}", self.clouds.is_some());
+        Ok(())
+    }
+
+    /// Grade the final image with the user's LUT or the identity LUT, or
+    /// stop grading.
+    pub fn toggle_color_grading(&mut self) -> Result<()> {
+        self.tone_map = match self.tone_map.take() {
+            Some(_) => None,
+            None => Some(create_tone_map(
+                &self.display,
+                &self.builtins,
+                self.lut.as_deref(),
+            )?),
+        };
+        self.alias_post_process_targets()?;
+        log::info!("color grading enabled: {}", self.tone_map.is_some());
+        Ok(())
+    }
+
+    fn adjust_grading_strength(&mut self, delta: f32) {
+        if let Some(tone_map) = &mut self.tone_map {
+            let strength = &mut tone_map.grading.strength;
+            self.params.set_auto(GRADING_STRENGTH, *strength);
+            *strength = (*strength + delta).clamp(0.0, 1.0);
+            self.params.set_user(GRADING_STRENGTH, *strength);
+            log::info!("color grading strength {:.1}", strength);
+        }
+    }
+
+    fn toggle_dither(&mut self) {
+        if let Some(tone_map) = &mut self.tone_map {
+            tone_map.dither = !tone_map.dither;
+            log::info!("color grading dither enabled: {}", tone_map.dither);
+        }
+    }
+
+    /// Blur moving particles along their motion, or stop blurring.
+    pub fn toggle_motion_blur(&mut self) -> Result<()> {
+        self.motion_blur = match self.motion_blur.take() {
+            Some(_) => None,
+            None => Some(MotionBlur::new(&self.display)?),
+        };
+        self.alias_post_process_targets()?;
+        log::info!("motion blur enabled: {}", self.motion_blur.is_some());
+        Ok(())
+    }
+
+    /// Blur the particles away from the focused particle, or stop blurring.
+    pub fn toggle_depth_of_field(&mut self) -> Result<()> {
+        self.depth_of_field = match self.depth_of_field.take() {
+            Some(_) => None,
+            None => Some(DepthOfField::new(&self.display)?),
+        };
+        self.alias_post_process_targets()?;
+        log::info!("depth of field enabled: {}", self.depth_of_field.is_some());
+        Ok(())
+    }
+
+    /// Cycle the bokeh shape through a circle, a hexagon, and a star loaded
+    /// from an image.
+    fn cycle_bokeh_shape(&mut self) -> Result<()> {
+        if let Some(depth_of_field) = &mut self.depth_of_field {
+            let (shape, name) = match depth_of_field.bokeh_shape() {
+                BokehShape::Circle => (BokehShape::Hexagon, "hexagon"),
+                BokehShape::Hexagon => {
+                    (load_bokeh_star(&self.display)?, "star")
+                }
+                BokehShape::Custom(_) => (BokehShape::Circle, "circle"),
+            };
+            depth_of_field.set_bokeh_shape(&self.display, shape)?;
+            log::info!("bokeh shape {}", name);
+        }
+        Ok(())
+    }
+
+    /// Look through a cinematic lens, or stop.
+    pub fn toggle_lens_effects(&mut self) -> Result<()> {
+        self.lens_effects = match self.lens_effects.take() {
+            Some(_) => None,
+            None => Some(LensEffects::default_cinematic(&self.display)?),
+        };
+        self.alias_post_process_targets()?;
+        log::info!("lens effects enabled: {}", self.lens_effects.is_some());
+        Ok(())
+    }
+
+    /// Give the enabled post processing effects offscreen targets which are
+    /// shared wherever the render graph finds their lifetimes don't overlap.
+    ///
+    /// Each effect renders what came before it into its target, then draws
+    /// that target into the next effect's, so two effects apart never hold
+    /// an image at the same time. The graph is built the way `render`
+    /// chains them, and the memory saved is shown by the resource overlay.
+    fn alias_post_process_targets(&mut self) -> Result<()> {
+        let effects: Vec<&str> = [
+            ("depth of field", self.depth_of_field.is_some()),
+            ("motion blur", self.motion_blur.is_some()),
+            ("lens effects", self.lens_effects.is_some()),
+            ("tone map", self.tone_map.is_some()),
+        ]
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(effect, _)| *effect)
+        .collect();
+        let targets: Vec<String> = effects
+            .iter()
+            .map(|effect| format!("{} scene", effect))
+            .collect();
+
+        let mut graph = RenderGraph::new();
+        let desc = OffscreenTarget::desc(&self.display)?;
+        for target in &targets {
+            graph.add_transient(target, desc);
+        }
+        let mut reads = vec![];
+        for (i, pass) in std::iter::once(&"scene").chain(&effects).enumerate() {
+            let writes = targets.get(i).map_or("swapchain", String::as_str);
+            graph.add_node(pass, &reads, &[writes]);
+            reads = vec![writes];
+        }
+        let plan = graph
+            .alias_transients()
+            .context("unable to alias the post processing targets")?;
+
+        let images = plan
+            .slots()
+            .iter()
+            .map(|_| OffscreenTarget::new(&self.display).map(Arc::new))
+            .collect::<Result<Vec<_>>>()?;
+        let image = |effect: &str| {
+            let slot = plan
+                .slot_of(&format!("{} scene", effect))
+                .expect("every enabled effect's target was planned");
+            images[slot].clone()
+        };
+        if let Some(depth_of_field) = &mut self.depth_of_field {
+            depth_of_field
+                .share_scene(&self.display, image("depth of field"))?;
+        }
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.share_scene(&self.display, image("motion blur"))?;
+        }
+        if let Some(lens_effects) = &mut self.lens_effects {
+            lens_effects.share_scene(&self.display, image("lens effects"))?;
+        }
+        if let Some(tone_map) = &mut self.tone_map {
+            tone_map.share_scene(&self.display, image("tone map"))?;
+        }
+        if plan.saved_bytes() > 0 {
+            log::debug!(
+                "{} post processing targets share {} images",
+                targets.len(),
+                images.len()
+            );
+        }
+        self.transient_aliasing = plan;
+        Ok(())
+    }
+
+    /// Let the scene's resolution follow the frame rate, or go back to full
+    /// resolution.
+    pub fn toggle_dynamic_resolution(&mut self) -> Result<()> {
+        self.dynamic_resolution = match self.dynamic_resolution.take() {
+            Some(_) => None,
+            None => Some(DynamicResolution::new(&self.display, None)?),
+        };
+        self.display.set_render_scale(1.0);
+        self.rebuild_render_resources()?;
+        log::info!(
+            "dynamic resolution enabled: {}",
+            self.dynamic_resolution.is_some()
+        );
+        Ok(())
+    }
+
+    /// Show or hide a slideshow of the images in the slides directory.
+    pub fn toggle_slideshow(&mut self) -> Result<()> {
+        self.slideshow = match self.slideshow.take() {
+            Some(_) => None,
+            None => {
+                let slides = load_slides(&self.display)?;
+                Some(SpriteRenderer::new(&self.display, slides)?)
+            }
+        };
+        log::info!("slideshow enabled: {}", self.slideshow.is_some());
+        Ok(())
+    }
+
+    /// Hand the events which arrived since the last frame to whatever
+    /// they're for. A broken asset is logged and the old one kept, it's
+    /// probably being saved.
+    fn dispatch_events(&mut self) {
+        let events = self.events.take();
+        for path in &events.assets {
+            #[cfg(feature = "hot-reload")]
+            match self.reload_asset(path) {
+                Ok(()) => self.frame_stats.marker("asset reloaded"),
+                Err(error) => {
+                    log::error!("unable to reload {:?}: {:?}", path, error)
+                }
+            }
+            #[cfg(not(feature = "hot-reload"))]
+            log::debug!("hot reloading is disabled, ignoring {:?}", path);
+        }
+        for path in &events.shaders {
+            self.frame_stats.marker("shader changed");
+            log::info!(
+                "{:?} changed, shaders are compiled into the binary so \
+                restart to see the change",
+                path
+            );
+        }
+        for (name, value) in &events.params {
+            self.params.set_user(name, *value);
+            self.apply_param(name);
+        }
+        if events.redraw {
+            self.display.surface.window().request_redraw();
+        }
+    }
+
+    /// Load one changed asset again and swap it into whatever uses it.
+    /// Pipelines are kept, only images, buffers, and the descriptor sets
+    /// which bind them are replaced.
+    #[cfg(feature = "hot-reload")]
+    fn reload_asset(&mut self, path: &Path) -> Result<()> {
+        use asset_watcher::is_same_asset;
+
+        if is_same_asset(path, Path::new(TITLE_TEMPLATE)) {
+            self.load_title_template(path)?;
+            log::info!("reloaded the title template {:?}", path);
+            return Ok(());
+        }
+
+        let is_lut = self
+            .lut
+            .as_deref()
+            .is_some_and(|lut| is_same_asset(path, lut));
+        if is_lut {
+            if let Some(tone_map) = &mut self.tone_map {
+                let grading = ColorGrading::from_file(
+                    &self.display.device,
+                    &self.display.graphics_queue,
+                    path,
+                )?;
+                tone_map.set_grading(&self.display, grading)?;
+                log::info!("reloaded the color grading lut {:?}", path);
+            }
+            return Ok(());
+        }
+
+        if is_same_asset(path, Path::new(BOKEH_STAR)) {
+            if let Some(depth_of_field) = &mut self.depth_of_field {
+                if let BokehShape::Custom(_) = depth_of_field.bokeh_shape() {
+                    let star = load_bokeh_star(&self.display)?;
+                    depth_of_field.set_bokeh_shape(&self.display, star)?;
+                    log::info!("reloaded the bokeh star");
+                }
+            }
+            return Ok(());
+        }
+
+        let is_slide = path.parent().is_some_and(|directory| {
+            is_same_asset(directory, Path::new(SLIDESHOW_DIRECTORY))
+        });
+        if is_slide {
+            // the slides share one texture array, so they're loaded together
+            if let Some(slideshow) = &mut self.slideshow {
+                let slides = load_slides(&self.display)?;
+                slideshow.set_sheet(&self.display, slides)?;
+                log::info!("reloaded the slides after {:?} changed", path);
+            }
+            return Ok(());
+        }
+
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((engine, meshes)) = &mut self.scripting {
+            if path.extension().is_some_and(|extension| extension == "obj") {
+                let ids = engine.reload_mesh(path)?;
+                meshes.replace(&self.display, &engine.state(), &ids)?;
+                if !ids.is_empty() {
+                    log::info!("reloaded the mesh {:?}", path);
+                }
+                return Ok(());
+            }
+        }
+
+        log::debug!("nothing which can be reloaded uses {:?}", path);
+        Ok(())
+    }
+
+    /// Use the first line of a file as the window title template.
+    #[cfg(feature = "hot-reload")]
+    fn load_title_template(&mut self, path: &Path) -> Result<()> {
+        let template = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        self.set_title_template(template.lines().next().unwrap_or_default());
+        Ok(())
+    }
+
+    /// Write a json report of the device's properties, features, limits,
+    /// memory, and queue families.
+    #[cfg(feature = "device-report")]
+    pub fn save_device_report(&self, path: &Path) -> Result<()> {
+        let physical_device = self.display.device.physical_device();
+        let report = crate::display::generate_device_report(&physical_device);
+        let json = serde_json::to_string_pretty(&report)
+            .context("unable to serialize the device report")?;
+        std::fs::write(path, json).with_context(|| {
+            format!("unable to write the device report to {:?}", path)
+        })?;
+        log::info!("saved the device report to {:?}", path);
+        Ok(())
+    }
+
+    /// Queue at most `depth` frames on the gpu, between 1 and
+    /// `MAX_FRAMES_IN_FLIGHT`.
+    pub fn set_frames_in_flight(&mut self, depth: usize) {
+        self.display.set_frames_in_flight(depth);
+        log::info!("{} frames in flight", self.display.frames_in_flight());
+    }
+
+    /// Bind resources created by hand to memory from another allocator.
+    pub fn set_allocator(&mut self, kind: AllocatorKind) {
+        self.display.set_allocator(kind);
+        log::info!("allocator: {}", self.display.allocator.name());
+    }
+
+    /// Draw with raw normalized device coordinates, which stretch with the
+    /// window, instead of the default where a unit is the same size across
+    /// and up.
+    pub fn set_coordinate_space(&mut self, space: CoordinateSpace) {
+        self.coordinate_space = space;
+        log::info!("drawing in {:?} coordinates", space);
+    }
+
+    /// Choose where animation time comes from. Locked to the display, each
+    /// frame advances one refresh interval, or with `catch_up` one for every
+    /// vsync the frame spanned.
+    pub fn set_time_source(&mut self, source: TimeSource, catch_up: bool) {
+        self.clock.set_source(source, Instant::now());
+        self.clock.set_catch_up(catch_up);
+        log::info!(
+            "animation time source {:?} at {}hz",
+            source,
+            self.clock.refresh_rate()
+        );
+    }
+
+    /// Start or stop rendering one frame per press of F10.
+    fn toggle_frame_step(&mut self) {
+        let active = self.frame_step.toggle(&mut self.clock);
+        self.frame_stats.resume();
+        self.frame_stats.marker(if active {
+            "frame step started"
+        } else {
+            "frame step ended"
+        });
+        log::info!(
+            "frame step {}",
+            if active {
+                "on, F10 renders the next frame"
+            } else {
+                "off"
+            }
+        );
+    }
+
+    fn toggle_display_locked_time(&mut self) {
+        let source = match self.clock.source() {
+            TimeSource::WallClock => TimeSource::DisplayLocked,
+            TimeSource::DisplayLocked => TimeSource::WallClock,
+        };
+        self.clock.set_source(source, Instant::now());
+        log::info!("animation time source {:?}", source);
+    }
+
+    /// Step the clock by the refresh interval of the monitor the window is
+    /// on now, which changes when it's dragged to another monitor.
+    fn update_refresh_rate(&mut self) {
+        if let Some(refresh_rate) = self.display.refresh_rate() {
+            if refresh_rate != self.clock.refresh_rate() {
+                self.clock.set_refresh_rate(refresh_rate);
+                log::info!("display refresh rate {}hz", refresh_rate);
+            }
+        }
+    }
+
+    /// Log the window's logical, physical, and surface sizes, which only
+    /// agree at a scale factor of 1.
+    fn log_window_sizes(&self) {
+        match self.display.window_sizes() {
+            Ok(sizes) => {
+                log::info!("window sizes {}", sizes.describe().join(", "))
+            }
+            Err(error) => {
+                log::warn!("unable to read the window sizes {:?}", error)
+            }
+        }
+    }
 
-        Ok(Self {
-            display,
-            particles,
-            start: Instant::now(),
-        })
+    /// Cycle the particles between opaque, alpha, and additive blending.
+    fn cycle_particle_blend_mode(&mut self) -> Result<()> {
+        let blend_mode = self.particles.blend_mode().next();
+        self.particles.set_blend_mode(&self.display, blend_mode)?;
+        log::info!("particle blend mode {:?}", blend_mode);
+        Ok(())
     }
 
-    /// Update the application
-    fn update(&mut self) -> Result<()> {
-        let t = (Instant::now() - self.start).as_secs_f32();
-        let step = 2.0 * PI / 3.0;
-        let a1 = step + t;
-        let a2 = step * 2.0 + t;
-        let a3 = step * 3.0 + t;
+    /// Show or hide the graph of recent frame times.
+    pub fn toggle_profiler(&mut self) -> Result<()> {
+        self.profiler = match self.profiler.take() {
+            Some(_) => None,
+            None => {
+                let mut profiler = ProfilerOverlay::new(&self.display)?;
+                profiler.update(
+                    &self.frame_stats,
+                    self.display.frame_wait_stats(),
+                    self.culled,
+                );
+                Some(profiler)
+            }
+        };
+        log::info!("profiler enabled: {}", self.profiler.is_some());
+        Ok(())
+    }
 
-        self.particles.vertices = vec![
-            particles::Vertex::new([a1.cos(), a1.sin()], [1.0, 0.0, 0.0, 1.0]),
-            particles::Vertex::new([a2.cos(), a2.sin()], [0.0, 1.0, 0.0, 1.0]),
-            particles::Vertex::new([a3.cos(), a3.sin()], [0.0, 0.0, 1.0, 1.0]),
-        ];
+    /// Show or hide the table of live pipelines, textures, and buffers.
+    pub fn toggle_resource_overlay(&mut self) -> Result<()> {
+        self.resource_overlay = match self.resource_overlay.take() {
+            Some(_) => None,
+            None => Some(ResourceOverlay::new(&self.display)?),
+        };
+        log::info!(
+            "resource overlay enabled: {}",
+            self.resource_overlay.is_some()
+        );
+        Ok(())
+    }
 
-        // no-op
+    /// Open or close the text box, which takes every typed character while
+    /// it's open. What's typed into it is logged when Enter is pressed.
+    pub fn toggle_text_box(&mut self) -> Result<()> {
+        self.text_box = match self.text_box.take() {
+            Some(_) => None,
+            None => Some(TextBox::new(&self.display)?),
+        };
+        self.set_text_input_enabled(self.text_box.is_some());
+        self.place_text_box_ime();
+        log::info!("text box open: {}", self.text_box.is_some());
         Ok(())
     }
 
-    /// Draw the screen.
-    fn render(&mut self) -> Result<()> {
-        let particle_draw_commands = self.particles.draw(&self.display)?;
-        self.display.render(vec![particle_draw_commands])?;
+    /// Whether typed characters go to the text box or the sketch's focused
+    /// text field, with the keyboard shortcuts off.
+    pub fn text_input_enabled(&self) -> bool {
+        self.text_input_enabled
+    }
+
+    /// Turn text input on while the text box is open or the sketch has a
+    /// field focused, and off otherwise.
+    ///
+    /// winit 0.24 has no way to tell the OS to show or hide its IME, so an
+    /// IME stays available whether or not text input is on, and only its
+    /// position follows the caret. It has no preedit events either. The IME
+    /// draws the text being composed itself, and its committed text comes
+    /// through `WindowEvent::ReceivedCharacter` a character at a time.
+    fn set_text_input_enabled(&mut self, enabled: bool) {
+        if enabled != self.text_input_enabled {
+            log::debug!("text input enabled: {}", enabled);
+            self.text_input_enabled = enabled;
+            self.ime_position = None;
+        }
+    }
+
+    /// Move the IME's window to `position`, in the window's pixels from the
+    /// top left.
+    fn place_ime(&mut self, position: [f32; 2]) {
+        if self.ime_position == Some(position) {
+            return;
+        }
+        self.ime_position = Some(position);
+        let [x, y] = position;
+        self.display
+            .surface
+            .window()
+            .set_ime_position(PhysicalPosition::new(x as f64, y as f64));
+    }
+
+    fn place_text_box_ime(&mut self) {
+        let size = self.display.surface.window().inner_size();
+        let position = match &self.text_box {
+            Some(text_box) => text_box
+                .caret_position(&self.display, [size.width, size.height]),
+            None => return,
+        };
+        self.place_ime(position);
+    }
+
+    /// Enable text input while the sketch has a text field focused, with
+    /// the IME at its caret. The text box takes text input over while it's
+    /// open.
+    fn follow_sketch_text_field(&mut self) {
+        if self.text_box.is_some() {
+            return;
+        }
+        let caret = self.sketch.as_ref().and_then(|sketch| sketch.text_caret());
+        self.set_text_input_enabled(caret.is_some());
+        if let Some([x, y]) = caret {
+            // the sketch's pixels are the scene's, which is scaled
+            let render_scale = self.display.render_scale();
+            self.place_ime([x / render_scale, y / render_scale]);
+        }
+    }
+
+    /// Respond to a character typed while text input is enabled. The text
+    /// box gets it while it's open and the sketch otherwise.
+    fn handle_char(&mut self, c: char) -> Result<()> {
+        if !self.text_input_enabled {
+            return Ok(());
+        }
+        if let Some(text_box) = &mut self.text_box {
+            if let TextEdit::Submitted(text) = text_box.handle_char(c) {
+                log::info!(
+                    "text box submitted {:?}, {} characters in {} bytes",
+                    text,
+                    text.chars().count(),
+                    text.len()
+                );
+            }
+            self.place_text_box_ime();
+        } else if let Some(sketch) = &mut self.sketch {
+            sketch.handle_char(c)?;
+        }
+        Ok(())
+    }
+
+    /// Respond to a key pressed while text input is enabled. The text box
+    /// moves its caret or closes on Escape, otherwise the sketch hears the
+    /// key to edit its field with.
+    fn handle_text_key(&mut self, key: VirtualKeyCode) -> Result<()> {
+        if let Some(text_box) = &mut self.text_box {
+            match text_box.handle_key(key) {
+                TextEdit::Cancelled => self.toggle_text_box()?,
+                TextEdit::Changed => self.place_text_box_ime(),
+                _ => (),
+            }
+        } else if let Some(sketch) = &mut self.sketch {
+            sketch.handle_key(key)?;
+        }
+        Ok(())
+    }
+
+    /// Cycle between the final image and the depth, overdraw, wireframe,
+    /// and normals debug views.
+    pub fn cycle_debug_view(&mut self) -> Result<()> {
+        if self.debug_view.is_none() {
+            self.debug_view = Some(DebugView::new(&self.display)?);
+        }
+        if let Some(debug_view) = &mut self.debug_view {
+            let mode = debug_view.mode().next();
+            debug_view.set_mode(mode);
+            if let Some((_, renderer)) = &mut self.world {
+                renderer.set_debug_view(&self.display, mode)?;
+            }
+            log::info!("debug view {:?}", mode);
+        }
         Ok(())
     }
 
+    /// Mark something which happened this frame, like a sketch reloading
+    /// its shaders. Markers are drawn on the frame time graph and written
+    /// to the frame stats csv alongside their frame.
+    pub fn marker(&mut self, name: impl Into<Cow<'static, str>>) {
+        self.frame_stats.marker(name);
+    }
+
+    /// Write the frames in the frame time graph to `FRAME_STATS_CSV`.
+    /// Capture the next frame and save it once it's drawn. The window
+    /// shows the frame before it instead, so only the file has it.
+    fn request_screenshot(&mut self) {
+        self.capture_requested = true;
+        self.screenshot_requested = true;
+    }
+
+    /// Wait for the captured frame and write it to `SCREENSHOT_DIRECTORY`
+    /// in the capture encoding.
+    fn save_screenshot(&mut self) {
+        let path = Path::new(SCREENSHOT_DIRECTORY).join(format!(
+            "{}-frame-{}.png",
+            self.sketch_info.slug(),
+            self.timing.index
+        ));
+        let encoding = self.capture_encoding;
+        let display = &mut self.display;
+        let saved = self
+            .capture
+            .take()
+            .context("the frame wasn't captured")
+            .and_then(|capture| {
+                display.wait_for_all_frames()?;
+                capture.save(&path, encoding)
+            });
+        match saved {
+            Ok(()) => log::info!("saved a screenshot to {:?}", path),
+            Err(error) => {
+                log::error!("unable to save a screenshot {:?}", error)
+            }
+        }
+    }
+
+    /// Choose how screenshots are written, 16 bit pngs keep the precision
+    /// of 10 bit and HDR swapchains.
+    pub fn set_capture_encoding(&mut self, encoding: CaptureEncoding) {
+        self.capture_encoding = encoding;
+    }
+
+    fn export_frame_stats(&self) {
+        let result = File::create(FRAME_STATS_CSV)
+            .and_then(|file| self.frame_stats.write_csv(BufWriter::new(file)));
+        match result {
+            Ok(()) => {
+                log::info!("saved the frame stats to {}", FRAME_STATS_CSV)
+            }
+            Err(error) => {
+                log::error!("unable to save the frame stats {:?}", error)
+            }
+        }
+    }
+
+    fn adjust_blur_strength(&mut self, delta: f32) {
+        if let Some(motion_blur) = &mut self.motion_blur {
+            let strength = &mut motion_blur.strength;
+            self.params.set_auto(BLUR_STRENGTH, *strength);
+            *strength = (*strength + delta).clamp(0.0, 4.0);
+            self.params.set_user(BLUR_STRENGTH, *strength);
+            log::info!("motion blur strength {:.2}", strength);
+        }
+    }
+
+    /// Undo the last edit to a parameter or gizmo, or redo the last undone
+    /// one.
+    fn undo_edit(&mut self, redo: bool) {
+        let (keys, action) = if redo {
+            (self.params.redo(), "redid")
+        } else {
+            (self.params.undo(), "undid")
+        };
+        if keys.is_empty() {
+            log::info!("nothing to {}", if redo { "redo" } else { "undo" });
+            return;
+        }
+        for key in &keys {
+            self.apply_param(key);
+        }
+        log::info!("{} the edit to {}", action, keys.join(", "));
+    }
+
+    /// Copy a parameter's value from the params store to whatever uses it.
+    fn apply_param(&mut self, key: &str) {
+        let value = match self.params.get(key) {
+            Some(value) => value,
+            None => return,
+        };
+        match key {
+            GRADING_STRENGTH => {
+                if let Some(tone_map) = &mut self.tone_map {
+                    tone_map.grading.strength = value;
+                }
+            }
+            BLUR_STRENGTH => {
+                if let Some(motion_blur) = &mut self.motion_blur {
+                    motion_blur.strength = value;
+                }
+            }
+            _ => {
+                let namespace = self.sketch_info.slug();
+                let handle = key
+                    .strip_prefix(namespace.as_str())
+                    .and_then(|key| key.strip_prefix(".gizmo."))
+                    .and_then(|handle| handle.rsplit_once('.'))
+                    .map(|(name, _)| name);
+                if let Some(name) = handle {
+                    let x =
+                        self.params.get(&gizmo_param(&namespace, name, "x"));
+                    let y =
+                        self.params.get(&gizmo_param(&namespace, name, "y"));
+                    if let (Some(x), Some(y)) = (x, y) {
+                        self.gizmos.restore(name, [x, y]);
+                    }
+                }
+                #[cfg(feature = "snapshot")]
+                if let (Some(name), Some(simulation)) =
+                    (key.strip_prefix("simulation."), &mut self.simulation)
+                {
+                    simulation.set_param(name, value);
+                }
+            }
+        }
+    }
+
+    /// The current value of every parameter which isn't a gizmo's, read
+    /// from the subsystems using them.
+    #[cfg(feature = "snapshot")]
+    fn current_params(&self) -> Vec<(String, f32)> {
+        let mut params = vec![];
+        if let Some(tone_map) = &self.tone_map {
+            params
+                .push((GRADING_STRENGTH.to_owned(), tone_map.grading.strength));
+        }
+        if let Some(motion_blur) = &self.motion_blur {
+            params.push((BLUR_STRENGTH.to_owned(), motion_blur.strength));
+        }
+        if let Some(simulation) = &self.simulation {
+            for (name, value) in simulation.params() {
+                params.push((format!("simulation.{}", name), value));
+            }
+        }
+        params
+    }
+
     /// Rebuild the swapchain and command buffers
     fn rebuild_swapchain_resources(&mut self) -> Result<()> {
+        #[cfg(feature = "rebuild-fade")]
+        let previous = self.display.presented_image();
         self.display.rebuild_swapchain()?;
+        self.frame_stats.marker("swapchain rebuilt");
+
+        // cover the window with the old frame while everything else is
+        // rebuilt
+        #[cfg(feature = "rebuild-fade")]
+        {
+            let rebuild_fade = RebuildFade::new(&self.display, previous)?;
+            rebuild_fade.present(&mut self.display);
+            self.rebuild_fade = Some(rebuild_fade);
+        }
+        self.rebuild_render_resources()
+    }
+
+    /// Rebuild everything which depends on the swapchain's extent or the
+    /// scene extent.
+    fn rebuild_render_resources(&mut self) -> Result<()> {
         self.particles.rebuild_swapchain_resources(&self.display)?;
+        if let Some(simulation) = &mut self.simulation {
+            simulation.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(sketch) = &mut self.sketch {
+            sketch.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(transition) =
+            self.scenes.as_mut().and_then(SceneManager::transition_mut)
+        {
+            transition
+                .outgoing
+                .rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(crossfade) = &mut self.crossfade {
+            crossfade.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(stereo) = &mut self.stereo {
+            stereo.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(clouds) = &mut self.clouds {
+            clouds.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(tone_map) = &mut self.tone_map {
+            tone_map.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(depth_of_field) = &mut self.depth_of_field {
+            depth_of_field.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(lens_effects) = &mut self.lens_effects {
+            lens_effects.rebuild_swapchain_resources(&self.display)?;
+        }
+        self.alias_post_process_targets()?;
+        if let Some(dynamic_resolution) = &mut self.dynamic_resolution {
+            dynamic_resolution.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some((_, renderer)) = &mut self.world {
+            renderer.rebuild_swapchain_resources(&self.display)?;
+        }
+        #[cfg(feature = "network")]
+        if let Some((_, objects)) = &mut self.network {
+            objects.rebuild_swapchain_resources(&self.display)?;
+        }
+        #[cfg(any(feature = "scripting", feature = "lua"))]
+        if let Some((_, meshes)) = &mut self.scripting {
+            meshes.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(slideshow) = &mut self.slideshow {
+            slideshow.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(resource_overlay) = &mut self.resource_overlay {
+            resource_overlay.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(text_box) = &mut self.text_box {
+            text_box.rebuild_swapchain_resources(&self.display)?;
+        }
+        // the caret moved with the window's size
+        self.place_text_box_ime();
+        if let Some(debug_view) = &mut self.debug_view {
+            debug_view.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(picking) = &mut self.picking {
+            picking.rebuild_swapchain_resources(&self.display)?;
+        }
+        if let Some(gizmo_overlay) = &mut self.gizmo_overlay {
+            gizmo_overlay.rebuild_swapchain_resources(&self.display)?;
+        }
         Ok(())
     }
 
+    /// Capture the frame after a failed submission in detail and carry on,
+    /// or fail with the error when that frame fails too, the device was
+    /// lost, or a report was written less than a minute ago.
+    fn submission_failed(&mut self, error: anyhow::Error) -> Result<()> {
+        if self.display.failure_capture().capturing() {
+            self.finish_failure_capture(Some(&error));
+            return Err(error);
+        }
+        if display::is_device_lost(&error)
+            || !self
+                .display
+                .failure_capture()
+                .arm(&error, self.timing.index)
+        {
+            return Err(error);
+        }
+        log::error!(
+            "unable to submit frame {}, capturing the next one {:?}",
+            self.timing.index,
+            error
+        );
+        self.frame_stats.marker("submission failed");
+
+        // the failed frame's swapchain image is never presented
+        self.rebuild_swapchain_resources()
+    }
+
+    fn finish_failure_capture(&mut self, error: Option<&anyhow::Error>) {
+        match self.display.failure_capture().finish(error) {
+            Ok(Some(path)) => log::warn!("wrote the failure report {:?}", path),
+            Ok(None) => (),
+            Err(error) => {
+                log::warn!("unable to write the failure report {:?}", error)
+            }
+        }
+    }
+
+    /// Update and draw a number of frames without running the event loop
+    /// or showing the window, then wait for the gpu to finish them. Checks
+    /// use this to drive the whole renderer.
+    pub fn render_frames(&mut self, frames: usize) -> Result<()> {
+        for frame in 0..frames {
+            self.update()
+                .and_then(|_| self.render())
+                .with_context(|| format!("unable to render frame {}", frame))?;
+        }
+        self.display.wait_for_all_frames()
+    }
+
+    /// Update and draw one frame like `render_frames`, drawing it offscreen
+    /// instead of to the window, and read back what it looked like.
+    pub fn capture_frame(&mut self) -> Result<GoldenImage> {
+        self.capture_requested = true;
+        self.render_frames(1)?;
+        self.capture
+            .take()
+            .context("the frame wasn't captured")?
+            .read()
+    }
+
+    /// Resize the window to `size` physical pixels and rebuild everything
+    /// drawn at its size.
+    pub fn set_window_size(&mut self, size: [u32; 2]) -> Result<()> {
+        self.display
+            .surface
+            .window()
+            .set_inner_size(PhysicalSize::new(size[0], size[1]));
+        self.rebuild_swapchain_resources()
+    }
+
+    /// Move the animation clock to `time` seconds and hold it there, each
+    /// frame stepping it by one refresh interval.
+    pub fn set_time(&mut self, time: f32) {
+        self.clock
+            .set_source(TimeSource::DisplayLocked, Instant::now());
+        self.clock.set_time(time, Instant::now());
+        self.previous_time = time;
+    }
+
+    /// Save whatever has to outlive the window before exiting.
+    fn close(&mut self) {
+        #[cfg(feature = "replay")]
+        if let Err(error) = self.save_replay() {
+            log::error!("unable to save the replay {:?}", error);
+        }
+        #[cfg(feature = "pipeline-cache")]
+        if let Err(error) = self.particles.save_pipeline_cache() {
+            log::error!("unable to save the pipeline cache {:?}", error);
+        }
+    }
+
+    /// Wait for the gpu to finish, then free everything in order: the
+    /// command buffers, pipelines, and images the application built on the
+    /// device first, then the display's swapchain, device, surface, and
+    /// instance.
+    ///
+    /// `into_display` drops every other field before handing the display
+    /// back, and the display is the application's last field so a plain
+    /// drop frees things in the same order. This is called when `main_loop`
+    /// exits, and through a guard in `main` when `main_loop` is never
+    /// reached or panics.
+    pub fn destroy(self) {
+        if let Err(error) = self.destroy_checked() {
+            log::warn!("{:?}", error);
+        }
+    }
+
+    /// Destroy the application like `destroy`, failing when any of the
+    /// display's resources was still referenced after everything else was
+    /// dropped, like a render pass or swapchain image cloned into something
+    /// which leaked.
+    pub fn destroy_checked(mut self) -> Result<()> {
+        if let Err(error) = self.display.wait_for_all_frames() {
+            log::warn!("unable to wait for the frames in flight {:?}", error);
+        }
+        self.into_display().destroy_checked()
+    }
+
+    /// The strong count of each of the display's vulkan resources, see
+    /// `Display::debug_resource_counts`.
+    pub fn debug_resource_counts(&self) -> Vec<(String, usize)> {
+        self.display.debug_resource_counts()
+    }
+
+    /// Drop everything but the display, which is returned, so nothing built
+    /// on the device outlives it.
+    fn into_display(self) -> Display {
+        self.display
+    }
+
+    /// Replace everything built on a lost device, keeping the window.
+    ///
+    /// The display brings up a new device for the same window, then the
+    /// application is rebuilt on it, see `rebuild`. Losing the device while
+    /// a sketch or scene is shown can't be recovered from.
+    ///
+    /// @param attempt how many times the device has been lost, giving up
+    /// after `MAX_DEVICE_RECOVERIES`
+    fn recover_from_device_loss(
+        self,
+        error: &anyhow::Error,
+        attempt: u32,
+    ) -> Result<Self> {
+        let physical_device = self.display.device.physical_device();
+        log::error!(
+            "lost the device {:?} ({:?}, vulkan {:?}, driver {:#x}), \
+            recovery {} of {}: {:?}",
+            physical_device.name(),
+            physical_device.ty(),
+            physical_device.api_version(),
+            physical_device.driver_version(),
+            attempt,
+            MAX_DEVICE_RECOVERIES,
+            error
+        );
+        if attempt > MAX_DEVICE_RECOVERIES {
+            self.destroy();
+            bail!("the device was lost {} times", attempt);
+        }
+        let app = self.rebuild(Display::recreate_device)?;
+        log::info!("recovered from the lost device");
+        Ok(app)
+    }
+
+    /// Replace everything built on the window's surface once it's lost,
+    /// which Windows drivers do when they're updated or reset while the
+    /// application runs.
+    ///
+    /// The display gives the same window a new surface and brings up a
+    /// device which can present to it, then the application is rebuilt on
+    /// it like it is for a lost device. When the surface is lost too often
+    /// the error is written to a failure report, if one wasn't written in
+    /// the last minute, before giving up.
+    ///
+    /// @param attempt how many times the surface has been lost, giving up
+    /// after `MAX_SURFACE_RECOVERIES`
+    fn recover_from_surface_loss(
+        mut self,
+        error: &anyhow::Error,
+        attempt: u32,
+    ) -> Result<Self> {
+        log::error!(
+            "lost the window's surface, recovery {} of {}: {:?}",
+            attempt,
+            MAX_SURFACE_RECOVERIES,
+            error
+        );
+        if attempt > MAX_SURFACE_RECOVERIES {
+            // there's no next frame to capture, so the report only has
+            // the error
+            if self.display.failure_capture().arm(error, self.timing.index) {
+                self.finish_failure_capture(Some(error));
+            }
+            self.destroy();
+            bail!("the surface was lost {} times", attempt);
+        }
+        let app = self.rebuild(Display::recreate_surface)?;
+        log::info!("recovered from the lost surface");
+        Ok(app)
+    }
+
+    /// Create the application again on the display `recreate` makes from
+    /// this one's, with the simulation, LUT, seed, and render scale it had,
+    /// the demo world if it was shown, and the effects and overlays which
+    /// were toggled on, see `EnabledEffects`.
+    ///
+    /// Only the application's own pipeline is recovered. Sketches and
+    /// scenes hold meshes, textures, and pipelines which only their creator
+    /// can build again, so losing the device while one is shown exits.
+    fn rebuild(
+        self,
+        recreate: impl FnOnce(Display) -> Result<Display>,
+    ) -> Result<Self> {
+        if self.sketch.is_some() || self.scenes.is_some() {
+            self.destroy();
+            bail!("sketches and scenes can't be rebuilt on a new device");
+        }
+
+        let simulation = self.simulation_name.clone();
+        let seed = self
+            .simulation
+            .as_ref()
+            .and_then(|simulation| simulation.seed());
+        let render_scale = self
+            .dynamic_resolution
+            .as_ref()
+            .map(|_| self.display.render_scale());
+        let lut = self.lut.clone();
+        let world = self.world.is_some();
+        let window_config = WindowConfig {
+            latency_mode: self.display.latency_mode(),
+            capture_policy: self.display.capture_policy(),
+            ..self.window_config.clone()
+        };
+        let sketch_info = self.sketch_info.clone();
+        let effects = EnabledEffects::of(&self);
+
+        // everything but the display is dropped before the display releases
+        // the lost device
+        let display = recreate(self.into_display())?;
+        let mut app = Self::with_display(
+            display,
+            &window_config,
+            sketch_info,
+            simulation.as_deref(),
+            lut,
+            render_scale,
+            seed,
+        )
+        .context("unable to recreate the application")?;
+        if world {
+            app.create_world()?;
+        }
+        effects.enable(&mut app)?;
+        Ok(app)
+    }
+
     /**
      * Main application loop for this window. Blocks the thread until the
      * window is closed.
      */
-    pub fn main_loop(mut self) -> Result<()> {
-        let event_loop = self
+    pub fn main_loop(self) -> Result<()> {
+        // destroyed when the event loop exits and drops the closure, or on an
+        // early return or panic
+        let mut app = scopeguard::guard(self, Application::destroy);
+        let event_loop = app
             .display
             .event_loop
             .take()
             .context("unable to take ownership of the event loop")?;
 
         // render once before showing the window so it's not garbage
-        self.render()
+        app.render()
             .context("unable to render the first application frame")?;
-        self.display.surface.window().set_visible(true);
+        app.display.surface.window().set_visible(true);
 
+        // empty only while the application is replaced after a lost device
+        let mut current = Some(app);
+        let mut device_losses = 0;
+        let mut surface_losses = 0;
         event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Poll;
+            let app = match &mut current {
+                Some(app) => app,
+                None => {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+            };
+            // between steps the loop sleeps until there's input
+            *control_flow = if app.frame_step.waiting() {
+                ControlFlow::Wait
+            } else {
+                ControlFlow::Poll
+            };
+            let mut device_lost = None;
+            let mut surface_lost = None;
+
+            // a profiled frame runs from one update to the next
+            #[cfg(any(feature = "profiling", feature = "tracy"))]
+            if let Event::MainEventsCleared = event {
+                #[cfg(feature = "profiling")]
+                puffin::GlobalProfiler::lock().new_frame();
+                #[cfg(feature = "tracy")]
+                app.tracy.frame_mark();
+            }
 
             match event {
+                #[cfg(feature = "telemetry")]
+                Event::LoopDestroyed => crate::logging::flush(),
+
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     ..
                 } => {
+                    app.close();
+                    *control_flow = ControlFlow::Exit;
+                }
+
+                Event::UserEvent(event) => app.events.push(event),
+
+                Event::MainEventsCleared if app.events.shutdown => {
+                    app.close();
                     *control_flow = ControlFlow::Exit;
                 }
 
                 Event::WindowEvent {
                     event: WindowEvent::Resized(_),
                     ..
-                } => match self.rebuild_swapchain_resources() {
+                } => match app.rebuild_swapchain_resources() {
+                    Err(error) if display::is_surface_lost(&error) => {
+                        surface_lost = Some(error);
+                    }
                     Err(error) => {
                         log::error!(
                             "unable to rebuild the swapchain {}",
@@ -100,20 +2961,381 @@ impl Application {
                     Ok(_) => {}
                 },
 
-                Event::MainEventsCleared => {
-                    match self.update().and_then(|_| self.render()) {
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Pressed,
+                                    virtual_keycode: Some(key),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    app.input_time = Some(Instant::now());
+                    if let Err(error) = app.handle_key_press(key) {
+                        log::error!("unable to handle the key press {}", error);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+
+                // dead keys arrive composed with the key after them, and an
+                // IME's text one character after another
+                Event::WindowEvent {
+                    event: WindowEvent::ReceivedCharacter(c),
+                    ..
+                } => {
+                    app.input_time = Some(Instant::now());
+                    if let Err(error) = app.handle_char(c) {
+                        log::error!("unable to handle the character {}", error);
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+
+                Event::WindowEvent {
+                    event: WindowEvent::CursorMoved { position, .. },
+                    ..
+                } => {
+                    app.cursor = Some([position.x as f32, position.y as f32]);
+                    app.input_time = Some(Instant::now());
+                }
+
+                Event::WindowEvent {
+                    event: WindowEvent::CursorLeft { .. },
+                    ..
+                } => {
+                    app.cursor = None;
+                }
+
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::MouseInput {
+                            state: ElementState::Pressed,
+                            button,
+                            ..
+                        },
+                    ..
+                } => {
+                    app.input_time = Some(Instant::now());
+                    if button == MouseButton::Left {
+                        app.left_pressed = true;
+                        app.gizmos.press();
+                    }
+                    if let Some(sketch) = &mut app.sketch {
+                        if let Err(error) = sketch.handle_click(button) {
+                            log::error!("unable to handle the click {}", error);
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                }
+
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::MouseInput {
+                            state: ElementState::Released,
+                            button: MouseButton::Left,
+                            ..
+                        },
+                    ..
+                } => {
+                    app.input_time = Some(Instant::now());
+                    app.left_pressed = false;
+                    app.gizmos.release();
+                }
+
+                Event::WindowEvent {
+                    event: WindowEvent::Moved(_),
+                    ..
+                } => app.update_refresh_rate(),
+
+                // the window is resized to the new physical size after
+                // this, and the swapchain is rebuilt for it then
+                Event::WindowEvent {
+                    event: WindowEvent::ScaleFactorChanged { .. },
+                    ..
+                } => {
+                    app.update_refresh_rate();
+                    app.log_window_sizes();
+                }
+
+                Event::WindowEvent {
+                    event: WindowEvent::ModifiersChanged(modifiers),
+                    ..
+                } => {
+                    app.gizmos.set_axis_lock(modifiers.shift());
+                    app.modifiers = modifiers;
+                }
+
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            input:
+                                KeyboardInput {
+                                    state: ElementState::Released,
+                                    virtual_keycode: Some(key),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    #[cfg(any(feature = "scripting", feature = "lua"))]
+                    if let Some((engine, _)) = &mut app.scripting {
+                        engine.key(key, false);
+                    }
+                    if let Some(sketch) = &mut app.sketch {
+                        if let Err(error) = sketch.handle_key_release(key) {
+                            log::error!(
+                                "unable to handle the key release {}",
+                                error
+                            );
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                }
+
+                #[cfg(any(feature = "scripting", feature = "lua"))]
+                Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta: (dx, dy) },
+                    ..
+                } => {
+                    if let Some((engine, _)) = &mut app.scripting {
+                        engine.mouse(dx as f32, dy as f32);
+                    }
+                }
+
+                Event::MainEventsCleared if app.frame_step.active() => {
+                    if !app.frame_step.take() {
+                        return;
+                    }
+                    // the step is timed on its own, not from the last step
+                    app.frame_stats.resume();
+                    match app.frame() {
+                        Err(error) if display::is_device_lost(&error) => {
+                            device_lost = Some(error);
+                        }
+                        Err(error) if display::is_surface_lost(&error) => {
+                            surface_lost = Some(error);
+                        }
+                        Err(error) => {
+                            log::error!("unable to render the step {}", error);
+                            *control_flow = ControlFlow::Exit;
+                        }
+                        Ok(_) => {
+                            log::info!("stepped frame {}", app.timing.index);
+                            *control_flow = ControlFlow::Wait;
+                        }
+                    }
+                }
+
+                Event::MainEventsCleared => match app.latch_input() {
+                    Ok(Some(latch)) => {
+                        // keep taking input until the frame has to start
+                        *control_flow = ControlFlow::WaitUntil(latch);
+                    }
+                    Ok(None) => match app.frame() {
+                        Err(error) if display::is_device_lost(&error) => {
+                            device_lost = Some(error);
+                        }
+                        Err(error) if display::is_surface_lost(&error) => {
+                            surface_lost = Some(error);
+                        }
                         Err(error) => {
                             log::error!("unable to render the frame {}", error);
                             *control_flow = ControlFlow::Exit;
                         }
                         Ok(_) => {
-                            self.display.surface.window().request_redraw();
+                            app.display.surface.window().request_redraw();
                         }
+                    },
+                    Err(error) => {
+                        log::error!(
+                            "unable to wait for the previous frame {}",
+                            error
+                        );
+                        *control_flow = ControlFlow::Exit;
                     }
-                }
+                },
 
                 _ => (),
             }
+
+            if let Some(error) = device_lost {
+                device_losses += 1;
+                let lost = match current.take() {
+                    Some(lost) => ScopeGuard::into_inner(lost),
+                    None => return,
+                };
+                match lost.recover_from_device_loss(&error, device_losses) {
+                    Ok(recovered) => {
+                        current = Some(scopeguard::guard(
+                            recovered,
+                            Application::destroy,
+                        ))
+                    }
+                    Err(error) => {
+                        log::error!(
+                            "unable to recover from the lost device {:?}",
+                            error
+                        );
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+            }
+
+            if let Some(error) = surface_lost {
+                surface_losses += 1;
+                let lost = match current.take() {
+                    Some(lost) => ScopeGuard::into_inner(lost),
+                    None => return,
+                };
+                match lost.recover_from_surface_loss(&error, surface_losses) {
+                    Ok(recovered) => {
+                        current = Some(scopeguard::guard(
+                            recovered,
+                            Application::destroy,
+                        ))
+                    }
+                    Err(error) => {
+                        log::error!(
+                            "unable to recover from the lost surface {:?}",
+                            error
+                        );
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+            }
         });
     }
 }
+
+/// The effects and overlays which were toggled on, kept while the
+/// application is rebuilt on a new device so they can be toggled on again.
+/// The color grading and motion blur keep their strengths, the depth of
+/// field goes back to a round bokeh.
+struct EnabledEffects {
+    stereo: bool,
+    clouds: bool,
+    depth_of_field: bool,
+    lens_effects: bool,
+    slideshow: bool,
+    profiler: bool,
+    resource_overlay: bool,
+
+    // the grading strength and whether it dithers
+    color_grading: Option<(f32, bool)>,
+
+    // the blur strength
+    motion_blur: Option<f32>,
+}
+
+impl EnabledEffects {
+    fn of(app: &Application) -> Self {
+        Self {
+            stereo: app.stereo.is_some(),
+            clouds: app.clouds.is_some(),
+            depth_of_field: app.depth_of_field.is_some(),
+            lens_effects: app.lens_effects.is_some(),
+            slideshow: app.slideshow.is_some(),
+            profiler: app.profiler.is_some(),
+            resource_overlay: app.resource_overlay.is_some(),
+            color_grading: app
+                .tone_map
+                .as_ref()
+                .map(|tone_map| (tone_map.grading.strength, tone_map.dither)),
+            motion_blur: app
+                .motion_blur
+                .as_ref()
+                .map(|motion_blur| motion_blur.strength),
+        }
+    }
+
+    /// Toggle each effect on again in a newly created application, where
+    /// they all start off.
+    fn enable(self, app: &mut Application) -> Result<()> {
+        let toggles: [(bool, fn(&mut Application) -> Result<()>); 7] = [
+            (self.stereo, Application::toggle_stereo),
+            (self.clouds, Application::toggle_clouds),
+            (self.depth_of_field, Application::toggle_depth_of_field),
+            (self.lens_effects, Application::toggle_lens_effects),
+            (self.slideshow, Application::toggle_slideshow),
+            (self.profiler, Application::toggle_profiler),
+            (self.resource_overlay, Application::toggle_resource_overlay),
+        ];
+        for &(enabled, toggle) in toggles.iter() {
+            if enabled {
+                toggle(app)?;
+            }
+        }
+        if let Some((strength, dither)) = self.color_grading {
+            app.toggle_color_grading()?;
+            if let Some(tone_map) = &mut app.tone_map {
+                tone_map.grading.strength = strength;
+                tone_map.dither = dither;
+            }
+        }
+        if let Some(strength) = self.motion_blur {
+            app.toggle_motion_blur()?;
+            if let Some(motion_blur) = &mut app.motion_blur {
+                motion_blur.strength = strength;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parameter holding one axis of a gizmo handle's position, under the
+/// sketch's namespace so sketches don't restore each other's handles.
+fn gizmo_param(namespace: &str, name: &str, axis: &str) -> String {
+    format!("{}.gizmo.{}.{}", namespace, name, axis)
+}
+
+/// Tone map with the LUT at `lut`, or the identity LUT.
+fn create_tone_map(
+    display: &Display,
+    builtins: &BuiltinTextures,
+    lut: Option<&Path>,
+) -> Result<ToneMap> {
+    let grading = match lut {
+        Some(path) => ColorGrading::from_file(
+            &display.device,
+            &display.graphics_queue,
+            path,
+        )?,
+        None => builtins.identity_lut.clone(),
+    };
+    ToneMap::new(display, grading, &builtins.blue_noise)
+}
+
+/// The star shaped bokeh, loaded from an image.
+fn load_bokeh_star(display: &Display) -> Result<BokehShape> {
+    let star = TextureBuilder::from_png(Path::new(BOKEH_STAR))?
+        .kind(TextureKind::Data)
+        .build(&display.device, &display.graphics_queue)?;
+    Ok(BokehShape::Custom(star.image))
+}
+
+/// Every image in the slides directory, one layer each.
+fn load_slides(display: &Display) -> Result<TextureArray> {
+    TextureArray::from_png_directory(
+        Path::new(SLIDESHOW_DIRECTORY),
+        &display.device,
+        &display.graphics_queue,
+    )
+}
+
+/// Open the default audio output and load the sound effects, or carry on
+/// without sound when that fails.
+#[cfg(feature = "audio")]
+fn load_audio() -> Option<(AudioSystem, SoundId)> {
+    let load = || -> Result<(AudioSystem, SoundId)> {
+        let mut audio = AudioSystem::new()?;
+        let click = audio.load_sound(Path::new(CLICK_SOUND))?;
+        Ok((audio, click))
+    };
+    load()
+        .map_err(|error| log::warn!("audio disabled: {:?}", error))
+        .ok()
+}
@@ -0,0 +1,218 @@
+use super::events::AppEvent;
+use anyhow::{anyhow, Result};
+use notify::{
+    watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::Duration;
+use winit::event_loop::EventLoopProxy;
+
+/// How long an asset has to be left alone before it's reported, editors and
+/// exporters often write a file in more than one step.
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// The extensions of files which can be reloaded while running.
+const ASSET_EXTENSIONS: [&str; 6] =
+    ["cube", "gltf", "obj", "png", "toml", "txt"];
+
+/// The extensions of shader sources, which are reported as shader changes
+/// by watchers which send events.
+const SHADER_EXTENSIONS: [&str; 5] = ["comp", "frag", "geom", "glsl", "vert"];
+
+/// Notices when asset files change on disk.
+///
+/// Changes are collected into a set of dirty paths rather than handled as
+/// they arrive, so a file which is written several times between two frames,
+/// or renamed over after being written, is only reloaded once.
+pub struct AssetWatcher {
+    watcher: RecommendedWatcher,
+
+    // none when the changes are sent to the event loop instead
+    changes: Option<Receiver<DebouncedEvent>>,
+    dirty_assets: HashSet<PathBuf>,
+}
+
+impl AssetWatcher {
+    /// A watcher whose changes are collected with `take_dirty`.
+    pub fn new() -> Result<Self> {
+        let (sender, changes) = channel();
+        Ok(Self {
+            watcher: create_watcher(sender)?,
+            changes: Some(changes),
+            dirty_assets: HashSet::new(),
+        })
+    }
+
+    /// A watcher which sends each change to the event loop, waking it,
+    /// as an `AssetLoaded` or `ShaderChanged` event. The event loop
+    /// coalesces repeated changes, `take_dirty` never returns anything.
+    pub fn with_events(events: EventLoopProxy<AppEvent>) -> Result<Self> {
+        let (sender, changes) = channel();
+        let watcher = create_watcher(sender)?;
+        // the thread ends once the watcher is dropped and the channel closes
+        std::thread::spawn(move || {
+            for change in changes {
+                let event = match changed_path(change) {
+                    Some(path) if is_asset(&path) => {
+                        AppEvent::AssetLoaded(path)
+                    }
+                    Some(path) if is_shader(&path) => {
+                        AppEvent::ShaderChanged(path)
+                    }
+                    _ => continue,
+                };
+                if events.send_event(event).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            watcher,
+            changes: None,
+            dirty_assets: HashSet::new(),
+        })
+    }
+
+    /// Watch every asset in a directory and the directories below it.
+    pub fn watch(&mut self, directory: &Path) -> Result<()> {
+        self.watcher
+            .watch(directory, RecursiveMode::Recursive)
+            .map_err(|error| {
+                anyhow!("unable to watch {:?}: {:?}", directory, error)
+            })
+    }
+
+    /// Every asset which changed since the last call, each only once.
+    pub fn take_dirty(&mut self) -> HashSet<PathBuf> {
+        if let Some(changes) = &self.changes {
+            for change in changes.try_iter() {
+                match changed_path(change) {
+                    Some(path) if is_asset(&path) => {
+                        self.dirty_assets.insert(path);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        std::mem::take(&mut self.dirty_assets)
+    }
+}
+
+fn create_watcher(
+    sender: Sender<DebouncedEvent>,
+) -> Result<RecommendedWatcher> {
+    // notify's errors only describe themselves through Debug
+    watcher(sender, RELOAD_DELAY).map_err(|error| {
+        anyhow!("unable to create the asset watcher: {:?}", error)
+    })
+}
+
+/// The file a change wrote, logging errors.
+fn changed_path(change: DebouncedEvent) -> Option<PathBuf> {
+    match change {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Rename(_, path) => Some(path),
+        DebouncedEvent::Error(error, path) => {
+            log::warn!("unable to watch {:?}: {:?}", path, error);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// True for files with one of the `ASSET_EXTENSIONS`.
+pub fn is_asset(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ASSET_EXTENSIONS.contains(&extension))
+}
+
+/// True for files with one of the `SHADER_EXTENSIONS`.
+pub fn is_shader(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| SHADER_EXTENSIONS.contains(&extension))
+}
+
+/// True when both paths lead to the same file. The watcher reports absolute
+/// paths while assets are usually loaded with relative ones.
+pub fn is_same_asset(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use std::time::Instant;
+
+    /// How long to wait for saved assets to be noticed.
+    const CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Save assets into a watched scratch directory, some of them several
+    /// times in a row. Each changed asset must be reported exactly once
+    /// while files which aren't assets are ignored.
+    #[test]
+    fn saves_are_coalesced() -> Result<()> {
+        let directory = std::env::temp_dir().join("vulkan_starter_hot_reload");
+        std::fs::create_dir_all(&directory)
+            .context("unable to create the asset directory")?;
+        let mut watcher = AssetWatcher::new()?;
+        watcher.watch(&directory)?;
+
+        let write = |name: &str, contents: &str| {
+            std::fs::write(directory.join(name), contents)
+                .with_context(|| format!("unable to write {}", name))
+        };
+        for size in 2..5 {
+            write("grade.cube", &format!("LUT_3D_SIZE {}\n", size))?;
+        }
+        write("diamond.obj", "v 0 0 0\n")?;
+        write("diamond.obj", "v 1 1 1\n")?;
+        write("notes.txt", "not an asset\n")?;
+        // exporters often write a temporary file and rename it into place
+        write("scene.toml.partial", "bloom_intensity = 0.5\n")?;
+        std::fs::rename(
+            directory.join("scene.toml.partial"),
+            directory.join("scene.toml"),
+        )
+        .context("unable to rename the config")?;
+
+        let expected: HashSet<&str> =
+            ["diamond.obj", "grade.cube", "scene.toml"]
+                .iter()
+                .copied()
+                .collect();
+        let mut changed: Vec<PathBuf> = vec![];
+        let start = Instant::now();
+        while changed.len() < expected.len() {
+            assert!(
+                start.elapsed() < CHANGE_TIMEOUT,
+                "only {:?} were noticed",
+                changed
+            );
+            std::thread::sleep(Duration::from_millis(50));
+            changed.extend(watcher.take_dirty());
+        }
+        // anything still on its way would be reported after the debounce
+        std::thread::sleep(Duration::from_millis(500));
+        changed.extend(watcher.take_dirty());
+
+        let names: Vec<&str> = changed
+            .iter()
+            .filter_map(|path| path.file_name()?.to_str())
+            .collect();
+        let unique: HashSet<&str> = names.iter().copied().collect();
+        assert_eq!(names.len(), unique.len(), "reported twice: {:?}", names);
+        assert_eq!(unique, expected);
+
+        std::fs::remove_dir_all(&directory)
+            .context("unable to remove the asset directory")
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{bail, Context, Result};
+use rodio::buffer::SamplesBuffer;
+use rodio::source::{Buffered, Source};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Identifies a sound loaded by an `AudioSystem`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SoundId(usize);
+
+/// Plays sound effects on the default output device.
+///
+/// Sounds are fully decoded when they are loaded, so playing one never
+/// touches the disk. Every call to `play` gets its own sink, which is
+/// dropped by `update` once the sound has finished.
+pub struct AudioSystem {
+    // the stream stops playing when it is dropped
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    sounds: Vec<Buffered<SamplesBuffer<i16>>>,
+    playing: Vec<Sink>,
+}
+
+impl AudioSystem {
+    pub fn new() -> Result<Self> {
+        let (stream, handle) = OutputStream::try_default()
+            .context("unable to open the default audio output")?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+            sounds: vec![],
+            playing: vec![],
+        })
+    }
+
+    /// Decode a WAV or OGG file into memory.
+    pub fn load_sound(&mut self, path: &Path) -> Result<SoundId> {
+        let file = File::open(path)
+            .with_context(|| format!("unable to open {:?}", path))?;
+        let decoder = Decoder::new(BufReader::new(file))
+            .with_context(|| format!("unable to decode {:?}", path))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<i16> = decoder.collect();
+        log::info!(
+            "loaded {:?}, {} channels at {}hz, {:.2}s",
+            path,
+            channels,
+            sample_rate,
+            samples.len() as f32 / (channels as u32 * sample_rate) as f32
+        );
+        self.sounds.push(
+            SamplesBuffer::new(channels, sample_rate, samples).buffered(),
+        );
+        Ok(SoundId(self.sounds.len() - 1))
+    }
+
+    /// Start playing a sound. Looping sounds play until the audio system is
+    /// dropped.
+    pub fn play(
+        &mut self,
+        id: SoundId,
+        volume: f32,
+        looping: bool,
+    ) -> Result<()> {
+        let sound = match self.sounds.get(id.0) {
+            Some(sound) => sound.clone(),
+            None => bail!("sound {} has not been loaded", id.0),
+        };
+        let sink = Sink::try_new(&self.handle)
+            .context("unable to create a sink for the sound")?;
+        sink.set_volume(volume.max(0.0));
+        if looping {
+            sink.append(sound.repeat_infinite());
+        } else {
+            sink.append(sound);
+        }
+        self.playing.push(sink);
+        Ok(())
+    }
+
+    /// Drop the sinks of sounds which have finished playing.
+    pub fn update(&mut self) {
+        self.playing.retain(|sink| !sink.empty());
+    }
+}
@@ -0,0 +1,106 @@
+use super::meshes::MeshVertex;
+use super::particles::Vertex;
+use std::mem::{offset_of, size_of};
+use wgpu::{vertex_attr_array, VertexAttribute};
+
+/// The layout of a vertex type as a wgpu vertex buffer would describe it, so
+/// the same vertices can be drawn by a wgpu backend without declaring a
+/// second copy of each type.
+pub trait WgpuCompatLayout {
+    /// Bytes from the start of one vertex to the start of the next.
+    fn wgpu_stride() -> u64;
+
+    /// Each member's format, offset, and shader location.
+    fn wgpu_attributes() -> Vec<VertexAttribute>;
+}
+
+const PARTICLE_ATTRIBUTES: [VertexAttribute; 2] =
+    vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+const MESH_ATTRIBUTES: [VertexAttribute; 1] =
+    vertex_attr_array![0 => Float32x3];
+
+// vulkano steps through vertex buffers by the size of the vertex type, so a
+// member added to one side but not the other fails to build here rather than
+// drawing garbage later
+const _: () = assert!(
+    packed_stride(&PARTICLE_ATTRIBUTES) == size_of::<Vertex>() as u64,
+    "the particle vertex's wgpu stride doesn't match vulkano's"
+);
+const _: () = assert!(
+    PARTICLE_ATTRIBUTES[1].offset == offset_of!(Vertex, color) as u64,
+    "the particle vertex's color is at a different offset for wgpu"
+);
+const _: () = assert!(
+    packed_stride(&MESH_ATTRIBUTES) == size_of::<MeshVertex>() as u64,
+    "the mesh vertex's wgpu stride doesn't match vulkano's"
+);
+
+impl WgpuCompatLayout for Vertex {
+    fn wgpu_stride() -> u64 {
+        packed_stride(&PARTICLE_ATTRIBUTES)
+    }
+
+    fn wgpu_attributes() -> Vec<VertexAttribute> {
+        PARTICLE_ATTRIBUTES.to_vec()
+    }
+}
+
+impl WgpuCompatLayout for MeshVertex {
+    fn wgpu_stride() -> u64 {
+        packed_stride(&MESH_ATTRIBUTES)
+    }
+
+    fn wgpu_attributes() -> Vec<VertexAttribute> {
+        MESH_ATTRIBUTES.to_vec()
+    }
+}
+
+/// The stride of a vertex with no padding after its last attribute.
+const fn packed_stride(attributes: &[VertexAttribute]) -> u64 {
+    let mut stride = 0;
+    let mut i = 0;
+    while i < attributes.len() {
+        let end = attributes[i].offset + attributes[i].format.size();
+        if end > stride {
+            stride = end;
+        }
+        i += 1;
+    }
+    stride
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::pipeline::vertex::Vertex as VulkanoVertex;
+
+    /// Fail unless every member is at the same offset for wgpu as vulkano
+    /// reads from `impl_vertex!`, bound to locations in member order.
+    fn assert_layouts_match<T>(members: &[&str])
+    where
+        T: WgpuCompatLayout + VulkanoVertex,
+    {
+        let attributes = T::wgpu_attributes();
+        assert_eq!(attributes.len(), members.len());
+        for (location, (member, attribute)) in
+            members.iter().zip(&attributes).enumerate()
+        {
+            let info = T::member(member)
+                .unwrap_or_else(|| panic!("there's no member {}", member));
+            assert_eq!(attribute.offset, info.offset as u64, "{}", member);
+            assert_eq!(attribute.shader_location, location as u32);
+        }
+        assert_eq!(T::wgpu_stride(), size_of::<T>() as u64);
+    }
+
+    #[test]
+    fn particle_vertex_layouts_match() {
+        assert_layouts_match::<Vertex>(&["pos", "color"]);
+    }
+
+    #[test]
+    fn mesh_vertex_layouts_match() {
+        assert_layouts_match::<MeshVertex>(&["pos"]);
+    }
+}
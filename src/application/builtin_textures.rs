@@ -0,0 +1,43 @@
+use super::post_process::ColorGrading;
+use crate::texture::{Texture, TextureBuilder, TextureKind};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::device::{Device, Queue};
+
+/// A tileable 64x64 blue noise texture, every value from 0 to 255 appears
+/// equally often.
+const BLUE_NOISE: &str = "assets/textures/blue_noise_64.png";
+
+/// A 32³ LUT which maps every color to itself.
+pub const IDENTITY_LUT: &str = "assets/luts/identity_32.cube";
+
+/// Small utility textures which ship with the application and are loaded
+/// once at startup.
+pub struct BuiltinTextures {
+    /// Blue noise for dithering, sample it with `texelFetch` and wrap the
+    /// pixel coordinates so it tiles across the screen.
+    pub blue_noise: Texture,
+
+    /// A color grading LUT which leaves colors unchanged.
+    pub identity_lut: ColorGrading,
+}
+
+impl BuiltinTextures {
+    pub fn load(device: &Arc<Device>, queue: &Arc<Queue>) -> Result<Self> {
+        let blue_noise = TextureBuilder::from_png(Path::new(BLUE_NOISE))?
+            .kind(TextureKind::Data)
+            .build(device, queue)
+            .context("unable to load the blue noise texture")?;
+        let identity_lut = ColorGrading::from_cube_file(
+            device,
+            queue,
+            Path::new(IDENTITY_LUT),
+        )
+        .context("unable to load the identity lut")?;
+        Ok(Self {
+            blue_noise,
+            identity_lut,
+        })
+    }
+}
@@ -0,0 +1,242 @@
+use super::cull::Mat4;
+use super::transform::multiply;
+
+/// The distance between the camera's near and far planes in world units.
+pub const DEPTH_RANGE: f32 = 4.0;
+
+/// How x and y are stretched to fill the window.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CoordinateSpace {
+    /// A unit is the same number of pixels across and up, and the window's
+    /// shorter side spans -1 to 1. Wider or taller windows show more past
+    /// the sides.
+    Unit,
+
+    /// Raw normalized device coordinates, where -1 to 1 spans both sides
+    /// of the window whatever its shape, so everything stretches with it.
+    Ndc,
+}
+
+impl Default for CoordinateSpace {
+    fn default() -> Self {
+        CoordinateSpace::Unit
+    }
+}
+
+/// What to multiply a position in `space` by to get normalized device
+/// coordinates in a target of `extent` pixels.
+pub fn aspect_fix(extent: [u32; 2], space: CoordinateSpace) -> [f32; 2] {
+    let [width, height] = [extent[0].max(1) as f32, extent[1].max(1) as f32];
+    match space {
+        CoordinateSpace::Unit if width >= height => [height / width, 1.0],
+        CoordinateSpace::Unit => [1.0, width / height],
+        CoordinateSpace::Ndc => [1.0, 1.0],
+    }
+}
+
+/// An orthographic camera, by default at the origin looking down the -z axis.
+///
+/// The camera keeps +y up, corrects for the aspect ratio of the target so
+/// the shorter side spans -1 to 1 unless its space is `Ndc`, and maps z
+/// from [-2, 2] in front of and behind the camera into Vulkan's [0, 1]
+/// depth range.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct Camera {
+    /// The target's width divided by its height.
+    pub aspect_ratio: f32,
+
+    /// The target's size in pixels.
+    pub resolution: [f32; 2],
+
+    /// How x and y are stretched to fill the target.
+    pub space: CoordinateSpace,
+
+    /// Horizontal offset of the eye for stereo rendering. Negative values
+    /// are the left eye, positive values are the right eye, and 0 is a
+    /// regular mono view.
+    pub eye_offset: f32,
+
+    /// Where the camera is in world space.
+    pub position: [f32; 3],
+
+    /// The point the camera looks at.
+    pub target: [f32; 3],
+}
+
+impl Camera {
+    /// A mono camera for a target with the given dimensions.
+    pub fn for_extent(extent: [u32; 2]) -> Self {
+        Self {
+            aspect_ratio: extent[0] as f32 / extent[1] as f32,
+            resolution: [extent[0] as f32, extent[1] as f32],
+            space: CoordinateSpace::default(),
+            eye_offset: 0.0,
+            position: [0.0, 0.0, 0.0],
+            target: [0.0, 0.0, -1.0],
+        }
+    }
+
+    /// The same camera moved to `position` and looking at `target`.
+    pub fn looking_from(self, position: [f32; 3], target: [f32; 3]) -> Self {
+        Self {
+            position,
+            target,
+            ..self
+        }
+    }
+
+    /// The same camera in another coordinate space, `Ndc` for raw
+    /// normalized device coordinates.
+    pub fn with_space(self, space: CoordinateSpace) -> Self {
+        Self { space, ..self }
+    }
+
+    /// What positions are multiplied by to land in normalized device
+    /// coordinates, the `aspect_fix` the shaders see.
+    pub fn aspect_fix(&self) -> [f32; 2] {
+        let [width, height] = self.resolution;
+        aspect_fix([width as u32, height as u32], self.space)
+    }
+
+    /// Where a position in the camera's space lands in normalized device
+    /// coordinates, for sizing geometry like sprites which is drawn there
+    /// directly.
+    pub fn to_ndc(&self, position: [f32; 2]) -> [f32; 2] {
+        let [x, y] = self.aspect_fix();
+        [position[0] * x, position[1] * y]
+    }
+
+    /// The same camera viewed from an eye offset horizontally.
+    pub fn with_eye_offset(self, eye_offset: f32) -> Self {
+        Self { eye_offset, ..self }
+    }
+
+    /// The combined view-projection matrix.
+    ///
+    /// Eye offsets shear x by depth so points at z = 0 line up in both eyes
+    /// while nearer and further points separate.
+    pub fn view_projection(&self) -> Mat4 {
+        let [x, y] = self.aspect_fix();
+        let projection = [
+            [x, 0.0, 0.0, 0.0],
+            [0.0, -y, 0.0, 0.0],
+            [self.eye_offset * x, 0.0, 0.25, 0.0],
+            [0.0, 0.0, 0.5, 1.0],
+        ];
+        multiply(&projection, &self.view())
+    }
+
+    /// The matrix which moves the world so the camera is at the origin
+    /// looking down -z. Looking straight up or down uses -z as the up
+    /// direction in place of +y.
+    fn view(&self) -> Mat4 {
+        let forward = normalize(sub(self.target, self.position));
+        let up = if forward[1].abs() > 0.999 {
+            [0.0, 0.0, -1.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        let side = normalize(cross(forward, up));
+        let up = cross(side, forward);
+        let eye = self.position;
+        [
+            [side[0], up[0], -forward[0], 0.0],
+            [side[1], up[1], -forward[1], 0.0],
+            [side[2], up[2], -forward[2], 0.0],
+            [-dot(side, eye), -dot(up, eye), dot(forward, eye), 1.0],
+        ]
+    }
+
+    /// The same depth mapping as `view_projection` without moving x and y,
+    /// for geometry like the particles which is positioned directly in
+    /// normalized device coordinates.
+    pub fn screen_space(&self) -> Mat4 {
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.25, 0.0],
+            [0.0, 0.0, 0.5, 1.0],
+        ]
+    }
+
+    /// Where a point in world space lands in normalized device coordinates.
+    pub fn project(&self, world_pos: [f32; 3]) -> [f32; 2] {
+        let matrix = self.view_projection();
+        let [x, y, z] = world_pos;
+        let row = |r: usize| {
+            matrix[0][r] * x
+                + matrix[1][r] * y
+                + matrix[2][r] * z
+                + matrix[3][r]
+        };
+        [row(0) / row(3), row(1) / row(3)]
+    }
+
+    /// The point on the z = 0 plane which lands at a position in normalized
+    /// device coordinates, the inverse of `project` for that plane. There is
+    /// no such point when the plane is seen edge on.
+    pub fn unproject(&self, ndc: [f32; 2]) -> Option<[f32; 2]> {
+        let m = self.view_projection();
+
+        // ndc = clip / w is linear in x and y once multiplied through by w
+        let coefficients = |r: usize| {
+            [
+                m[0][r] - ndc[r] * m[0][3],
+                m[1][r] - ndc[r] * m[1][3],
+                ndc[r] * m[3][3] - m[3][r],
+            ]
+        };
+        let [a, b, e] = coefficients(0);
+        let [c, d, f] = coefficients(1);
+        let determinant = a * d - b * c;
+        if determinant.abs() < 1e-6 {
+            return None;
+        }
+        Some([(e * d - b * f) / determinant, (a * f - e * c) / determinant])
+    }
+
+    /// The distances from the near plane to the near and far planes, the
+    /// distances depths of 0 and 1 map back to.
+    pub fn near_far(&self) -> [f32; 2] {
+        [0.0, DEPTH_RANGE]
+    }
+
+    /// The distance from the near plane to a point along the view direction.
+    pub fn distance(&self, world_pos: [f32; 3]) -> f32 {
+        let matrix = self.view_projection();
+        let [x, y, z] = world_pos;
+        let row = |r: usize| {
+            matrix[0][r] * x
+                + matrix[1][r] * y
+                + matrix[2][r] * z
+                + matrix[3][r]
+        };
+        row(2) / row(3) * DEPTH_RANGE
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Scale a vector to unit length, a zero vector looks down -z.
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length < f32::EPSILON {
+        return [0.0, 0.0, -1.0];
+    }
+    [v[0] / length, v[1] / length, v[2] / length]
+}
@@ -0,0 +1,400 @@
+use crate::display::SurfaceFormatInfo;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use vulkano::format::Format;
+use vulkano::swapchain::ColorSpace;
+
+/// The luminance of sRGB white in the HDR color spaces, in nits. scRGB puts
+/// 1.0 at 80 nits, and HDR10 is scaled to match.
+pub const SDR_WHITE_NITS: f32 = 80.0;
+
+/// Converts linear BT.2020 colors to linear BT.709, which shares its
+/// primaries with sRGB.
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.660_491, -0.587_641, -0.072_850],
+    [-0.124_550, 1.132_900, -0.008_349],
+    [-0.018_151, -0.100_579, 1.118_730],
+];
+
+/// How captured frames are written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CaptureEncoding {
+    /// An 8 bit sRGB png, which is exact for 8 bit swapchains.
+    #[default]
+    Png8,
+
+    /// A 16 bit sRGB png, which keeps the precision of 10 bit and half
+    /// float swapchains. Anything brighter than SDR white is clipped.
+    Png16,
+}
+
+impl CaptureEncoding {
+    /// Parse the name used on the command line: `png8` or `png16`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "png8" => Some(CaptureEncoding::Png8),
+            "png16" => Some(CaptureEncoding::Png16),
+            _ => None,
+        }
+    }
+}
+
+/// How the values in a swapchain image relate to the light shown.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Transfer {
+    /// Already sRGB encoded, the values are kept as they are.
+    Srgb,
+
+    /// Linear with sRGB primaries, 1.0 is SDR white.
+    Linear,
+
+    /// SMPTE ST 2084 encoded with BT.2020 primaries, 1.0 is 10,000 nits.
+    Pq,
+}
+
+impl Transfer {
+    fn of(color_space: ColorSpace) -> Result<Self> {
+        match color_space {
+            ColorSpace::SrgbNonLinear => Ok(Transfer::Srgb),
+            ColorSpace::ExtendedSrgbLinear => Ok(Transfer::Linear),
+            ColorSpace::Hdr10St2084 => Ok(Transfer::Pq),
+            color_space => bail!(
+                "unable to convert a capture in the {:?} color space, only \
+                sRGB, extended sRGB linear, and HDR10 are supported",
+                color_space
+            ),
+        }
+    }
+
+    /// Encode the rgb channels of a color as sRGB, clipped to SDR white.
+    fn to_srgb(self, rgb: [f32; 3]) -> [f32; 3] {
+        match self {
+            Transfer::Srgb => rgb.map(saturate),
+            Transfer::Linear => rgb.map(|c| encode_srgb(saturate(c))),
+            Transfer::Pq => {
+                let nits = rgb.map(|c| decode_pq(c) * 10_000.0);
+                let relative = nits.map(|c| c / SDR_WHITE_NITS);
+                let bt709 = BT2020_TO_BT709.map(|row| {
+                    row[0] * relative[0]
+                        + row[1] * relative[1]
+                        + row[2] * relative[2]
+                });
+                bt709.map(|c| encode_srgb(saturate(c)))
+            }
+        }
+    }
+}
+
+/// The size in bytes of a texel in a format which captures can be converted
+/// from.
+pub fn texel_size(format: Format) -> Result<usize> {
+    match format {
+        Format::B8G8R8A8Srgb
+        | Format::B8G8R8A8Unorm
+        | Format::R8G8B8A8Srgb
+        | Format::R8G8B8A8Unorm
+        | Format::A2B10G10R10UnormPack32
+        | Format::A2R10G10B10UnormPack32 => Ok(4),
+        Format::R16G16B16A16Sfloat => Ok(8),
+        format => bail!(
+            "unable to convert a capture in {:?}, only 8 bit, 10 bit, and \
+            half float rgba formats are supported",
+            format
+        ),
+    }
+}
+
+/// Convert the texels read back from a swapchain image to sRGB encoded
+/// rgba, each channel from 0 to 1, using the transfer function of the
+/// swapchain's color space.
+///
+/// Fails for formats and color spaces which can't be converted yet, rather
+/// than producing the wrong colors.
+pub fn to_srgb(
+    surface_format: SurfaceFormatInfo,
+    texels: &[u8],
+) -> Result<Vec<[f32; 4]>> {
+    let size = texel_size(surface_format.format)?;
+    let transfer = Transfer::of(surface_format.color_space)?;
+    Ok(texels
+        .chunks_exact(size)
+        .map(|texel| {
+            let [r, g, b, a] = decode_texel(surface_format.format, texel);
+            let [r, g, b] = transfer.to_srgb([r, g, b]);
+            [r, g, b, saturate(a)]
+        })
+        .collect())
+}
+
+/// Quantize sRGB encoded pixels to 8 bits per channel.
+pub fn to_rgba8(pixels: &[[f32; 4]]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.map(|c| (saturate(c) * 255.0).round() as u8))
+        .collect()
+}
+
+/// Quantize sRGB encoded pixels to 16 bits per channel.
+pub fn to_rgba16(pixels: &[[f32; 4]]) -> Vec<u16> {
+    pixels
+        .iter()
+        .flat_map(|pixel| pixel.map(|c| (saturate(c) * 65535.0).round() as u16))
+        .collect()
+}
+
+/// Write sRGB encoded pixels, top row first, to a png.
+pub fn save_png(
+    path: &Path,
+    extent: [u32; 2],
+    pixels: &[[f32; 4]],
+    encoding: CaptureEncoding,
+) -> Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("unable to create {:?}", directory))?;
+    }
+    let file = File::create(path)
+        .with_context(|| format!("unable to create {:?}", path))?;
+    let mut encoder =
+        png::Encoder::new(BufWriter::new(file), extent[0], extent[1]);
+    encoder.set_color(png::ColorType::RGBA);
+    let data = match encoding {
+        CaptureEncoding::Png8 => {
+            encoder.set_depth(png::BitDepth::Eight);
+            to_rgba8(pixels)
+        }
+        CaptureEncoding::Png16 => {
+            // 16 bit samples are big endian
+            encoder.set_depth(png::BitDepth::Sixteen);
+            to_rgba16(pixels)
+                .into_iter()
+                .flat_map(u16::to_be_bytes)
+                .collect()
+        }
+    };
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .with_context(|| format!("unable to write {:?}", path))
+}
+
+/// The channels of one texel as rgba, normalized or float, still in the
+/// swapchain's encoding.
+fn decode_texel(format: Format, texel: &[u8]) -> [f32; 4] {
+    let unorm8 = |i: usize| texel[i] as f32 / 255.0;
+    let packed =
+        || u32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]);
+    let unorm10 =
+        |bits: u32, shift: u32| ((bits >> shift) & 0x3ff) as f32 / 1023.0;
+    let half = |i: usize| {
+        decode_half(u16::from_ne_bytes([texel[i * 2], texel[i * 2 + 1]]))
+    };
+    match format {
+        Format::B8G8R8A8Srgb | Format::B8G8R8A8Unorm => {
+            [unorm8(2), unorm8(1), unorm8(0), unorm8(3)]
+        }
+        Format::A2B10G10R10UnormPack32 => {
+            let bits = packed();
+            [
+                unorm10(bits, 0),
+                unorm10(bits, 10),
+                unorm10(bits, 20),
+                (bits >> 30) as f32 / 3.0,
+            ]
+        }
+        Format::A2R10G10B10UnormPack32 => {
+            let bits = packed();
+            [
+                unorm10(bits, 20),
+                unorm10(bits, 10),
+                unorm10(bits, 0),
+                (bits >> 30) as f32 / 3.0,
+            ]
+        }
+        Format::R16G16B16A16Sfloat => [half(0), half(1), half(2), half(3)],
+        _ => [unorm8(0), unorm8(1), unorm8(2), unorm8(3)],
+    }
+}
+
+/// Convert an IEEE 754 half precision float to single precision.
+pub fn decode_half(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        // subnormal
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// The sRGB transfer function, from linear light to encoded.
+pub fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The SMPTE ST 2084 (PQ) EOTF, from encoded to linear light where 1.0 is
+/// 10,000 nits.
+pub fn decode_pq(encoded: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let p = saturate(encoded).powf(1.0 / M2);
+    ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+/// Clamp to 0 to 1, mapping NaN to 0.
+fn saturate(value: f32) -> f32 {
+    value.max(0.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PQ code, out of 1023, for SDR white at 80 nits.
+    const PQ_SDR_WHITE: u32 = 497;
+
+    /// A PQ code, out of 1023, for a quarter of SDR white at 20 nits, which
+    /// is 137 once sRGB encoded.
+    const PQ_QUARTER_WHITE: u32 = 365;
+
+    fn srgb(format: Format) -> SurfaceFormatInfo {
+        SurfaceFormatInfo {
+            format,
+            color_space: ColorSpace::SrgbNonLinear,
+        }
+    }
+
+    /// Four 10 bit channels packed the way the A2 formats hold them.
+    fn packed(r: u32, g: u32, b: u32, a: u32) -> [u8; 4] {
+        (r | g << 10 | b << 20 | a << 30).to_ne_bytes()
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{} should be {}",
+            actual,
+            expected
+        );
+    }
+
+    fn assert_rgba8(
+        surface_format: SurfaceFormatInfo,
+        texels: &[u8],
+        expected: &[u8],
+    ) -> Result<()> {
+        let pixels = to_rgba8(&to_srgb(surface_format, texels)?);
+        assert_eq!(pixels, expected, "{:?}", surface_format);
+        Ok(())
+    }
+
+    #[test]
+    fn transfer_functions_match_their_reference_values() {
+        assert_close(decode_half(0x3c00), 1.0);
+        assert_close(decode_half(0xc000), -2.0);
+        assert_close(decode_half(0x3800), 0.5);
+        assert_eq!(decode_half(0x0001), 2f32.powi(-24));
+        assert_eq!(decode_half(0x7c00), f32::INFINITY);
+        assert_close(encode_srgb(0.0), 0.0);
+        assert_close(encode_srgb(1.0), 1.0);
+        assert_close(encode_srgb(0.5), 0.735_357);
+        assert_close(decode_pq(0.0), 0.0);
+        assert_close(decode_pq(1.0), 1.0);
+    }
+
+    /// The golden images depend on 8 bit sRGB coming through unchanged.
+    #[test]
+    fn eight_bit_captures_come_through_unchanged() -> Result<()> {
+        let all_bytes: Vec<u8> = (0..=255).flat_map(|v| [v, v, v, v]).collect();
+        for &format in &[Format::B8G8R8A8Srgb, Format::R8G8B8A8Unorm] {
+            assert_rgba8(srgb(format), &all_bytes, &all_bytes)?;
+        }
+        assert_rgba8(
+            srgb(Format::B8G8R8A8Unorm),
+            &[10, 20, 30, 40],
+            &[30, 20, 10, 40],
+        )
+    }
+
+    #[test]
+    fn ten_bit_captures_keep_their_precision() -> Result<()> {
+        let texel = packed(1023, 512, 0, 3);
+        let abgr = srgb(Format::A2B10G10R10UnormPack32);
+        assert_eq!(
+            to_rgba16(&to_srgb(abgr, &texel)?),
+            [65535, 32800, 0, 65535]
+        );
+        let argb = srgb(Format::A2R10G10B10UnormPack32);
+        assert_eq!(
+            to_rgba16(&to_srgb(argb, &texel)?),
+            [0, 32800, 65535, 65535]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn scrgb_is_encoded_and_clipped_to_sdr_white() -> Result<()> {
+        let scrgb = SurfaceFormatInfo {
+            format: Format::R16G16B16A16Sfloat,
+            color_space: ColorSpace::ExtendedSrgbLinear,
+        };
+        let halves: Vec<u8> = [
+            0x3c00u16, 0x3800, 0x0000, 0x3c00, 0x4000, 0xbc00, 0x3c00, 0x3c00,
+        ]
+        .iter()
+        .flat_map(|half| half.to_ne_bytes())
+        .collect();
+        assert_rgba8(scrgb, &halves, &[255, 188, 0, 255, 255, 0, 255, 255])
+    }
+
+    #[test]
+    fn hdr10_is_scaled_to_sdr_white_and_clipped() -> Result<()> {
+        let hdr10 = SurfaceFormatInfo {
+            format: Format::A2B10G10R10UnormPack32,
+            color_space: ColorSpace::Hdr10St2084,
+        };
+        let white = PQ_SDR_WHITE;
+        let quarter = PQ_QUARTER_WHITE;
+        let texels: Vec<u8> = [
+            packed(white, white, white, 3),
+            packed(quarter, quarter, quarter, 3),
+            packed(1023, 0, 0, 3),
+            packed(0, 0, 0, 3),
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+        assert_rgba8(
+            hdr10,
+            &texels,
+            &[
+                255, 255, 255, 255, 137, 137, 137, 255, 255, 0, 0, 255, 0, 0,
+                0, 255,
+            ],
+        )
+    }
+
+    #[test]
+    fn unconvertible_captures_are_refused() {
+        assert!(texel_size(Format::R32G32B32A32Sfloat).is_err());
+        let display_p3 = SurfaceFormatInfo {
+            format: Format::B8G8R8A8Unorm,
+            color_space: ColorSpace::DisplayP3NonLinear,
+        };
+        assert!(to_srgb(display_p3, &[0, 0, 0, 0]).is_err());
+    }
+}
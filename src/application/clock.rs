@@ -0,0 +1,289 @@
+use std::time::{Duration, Instant};
+
+/// The refresh rate assumed when the monitor's rate can't be looked up.
+pub const DEFAULT_REFRESH_RATE: f32 = 60.0;
+
+/// The most refresh intervals one frame can advance when catching up on
+/// missed vsyncs, so a long stall like a window drag doesn't jump ahead.
+pub const MAX_CATCH_UP: u32 = 4;
+
+/// Where the animation clock gets its time from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimeSource {
+    /// The time measured since the start, which jitters with every frame.
+    WallClock,
+
+    /// Exactly one refresh interval per presented frame, so every frame of a
+    /// capture is the same step apart.
+    DisplayLocked,
+}
+
+/// The time animations and simulations see.
+///
+/// Locked to the display, each presented frame advances the clock by the
+/// monitor's refresh interval. A frame which took longer than an interval
+/// missed at least one vsync. With catch up on, the clock advances by every
+/// interval the frame spanned, up to `MAX_CATCH_UP`, so animations keep pace
+/// with the wall clock. With it off every frame is one step, which keeps a
+/// captured sequence perfectly even at the cost of running slow.
+pub struct AnimationClock {
+    source: TimeSource,
+    refresh_rate: f32,
+    catch_up: bool,
+
+    // the wall clock's time is measured from here
+    start: Instant,
+
+    // the display locked time, in seconds
+    locked_time: f64,
+    last_present: Option<Instant>,
+}
+
+impl AnimationClock {
+    pub fn new(source: TimeSource) -> Self {
+        Self {
+            source,
+            refresh_rate: DEFAULT_REFRESH_RATE,
+            catch_up: false,
+            start: Instant::now(),
+            locked_time: 0.0,
+            last_present: None,
+        }
+    }
+
+    pub fn source(&self) -> TimeSource {
+        self.source
+    }
+
+    /// Switch time sources, carrying on from the current time.
+    pub fn set_source(&mut self, source: TimeSource, now: Instant) {
+        let time = self.time(now);
+        self.source = source;
+        self.set_time(time, now);
+    }
+
+    pub fn refresh_rate(&self) -> f32 {
+        self.refresh_rate
+    }
+
+    /// Step by a different refresh interval from the next frame on, like
+    /// when the window moves to another monitor.
+    pub fn set_refresh_rate(&mut self, refresh_rate: f32) {
+        if refresh_rate > 0.0 {
+            self.refresh_rate = refresh_rate;
+        }
+    }
+
+    pub fn catch_up(&self) -> bool {
+        self.catch_up
+    }
+
+    /// Advance by every missed vsync, rather than one step per frame.
+    pub fn set_catch_up(&mut self, catch_up: bool) {
+        self.catch_up = catch_up;
+    }
+
+    /// Seconds between refreshes of the display.
+    pub fn step(&self) -> f64 {
+        1.0 / self.refresh_rate as f64
+    }
+
+    /// The seconds since the clock started, as of `now`.
+    pub fn time(&self, now: Instant) -> f32 {
+        match self.source {
+            TimeSource::WallClock => (now - self.start).as_secs_f32(),
+            TimeSource::DisplayLocked => self.locked_time as f32,
+        }
+    }
+
+    /// Move the clock to `time` seconds, like when a snapshot is restored.
+    pub fn set_time(&mut self, time: f32, now: Instant) {
+        let elapsed = Duration::from_secs_f32(time.max(0.0));
+        self.start = now.checked_sub(elapsed).unwrap_or(now);
+        self.locked_time = time.max(0.0) as f64;
+        self.last_present = None;
+    }
+
+    /// Count a frame presented at `now`, returning the number of refresh
+    /// intervals the display locked clock advanced by.
+    pub fn frame_presented(&mut self, now: Instant) -> u32 {
+        let steps = match self.last_present {
+            Some(last) if self.catch_up => {
+                missed_steps((now - last).as_secs_f64(), self.step())
+            }
+            _ => 1,
+        };
+        self.last_present = Some(now);
+        if self.source == TimeSource::DisplayLocked {
+            self.locked_time += steps as f64 * self.step();
+        }
+        steps
+    }
+}
+
+/// Where a frame is in time, the one place features read frame numbers and
+/// times from.
+///
+/// Shaders see the index as a 32 bit `int` which wraps back to 0 after
+/// `i32::MAX` frames, and the elapsed time as 32 bit float seconds, see
+/// `shader_frame` and `shader_time`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct FrameTiming {
+    /// Frames started since the application started, counting from 0. The
+    /// index goes up by one every frame whatever happens to the clock.
+    pub index: u64,
+
+    /// The animation clock's time, which replays, display locking, and
+    /// restored snapshots step or move.
+    pub elapsed: Duration,
+
+    /// The wall time since the application started, which nothing moves.
+    pub real_elapsed: Duration,
+}
+
+impl FrameTiming {
+    /// The index as shaders see it, wrapping to 0 after `i32::MAX`.
+    pub fn shader_frame(&self) -> i32 {
+        (self.index & i32::MAX as u64) as i32
+    }
+
+    /// The elapsed time in seconds as shaders see it.
+    pub fn shader_time(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+}
+
+/// Numbers the frames and measures the wall time since the start.
+pub struct FrameCounter {
+    next: u64,
+    start: Instant,
+}
+
+impl FrameCounter {
+    pub fn new(start: Instant) -> Self {
+        Self { next: 0, start }
+    }
+
+    /// The index the next frame will get, which is also how many frames
+    /// have started.
+    pub fn next_index(&self) -> u64 {
+        self.next
+    }
+
+    /// Start the next frame at `time` seconds on the animation clock.
+    pub fn begin(&mut self, time: f32, now: Instant) -> FrameTiming {
+        let timing = FrameTiming {
+            index: self.next,
+            elapsed: Duration::from_secs_f32(time.max(0.0)),
+            real_elapsed: now.saturating_duration_since(self.start),
+        };
+        self.next += 1;
+        timing
+    }
+}
+
+/// How many refresh intervals a frame which took `elapsed` seconds spanned,
+/// at least one and at most `MAX_CATCH_UP`. Frame times jitter around the
+/// interval, so they're rounded rather than truncated.
+fn missed_steps(elapsed: f64, step: f64) -> u32 {
+    let steps = (elapsed / step).round() as u32;
+    steps.clamp(1, MAX_CATCH_UP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fail unless the clock reads the given number of seconds.
+    fn assert_time(clock: &AnimationClock, now: Instant, seconds: f64) {
+        let time = clock.time(now) as f64;
+        assert!(
+            (time - seconds).abs() < 1e-5,
+            "the clock reads {}s rather than {}s",
+            time,
+            seconds
+        );
+    }
+
+    /// A clock locked to a 144Hz display, and a way to make instants a
+    /// number of milliseconds after it started.
+    fn locked_clock() -> (AnimationClock, impl Fn(f64) -> Instant) {
+        let start = Instant::now();
+        let mut clock = AnimationClock::new(TimeSource::DisplayLocked);
+        clock.set_refresh_rate(144.0);
+        (clock, move |ms: f64| {
+            start + Duration::from_secs_f64(ms / 1000.0)
+        })
+    }
+
+    #[test]
+    fn jittery_frames_advance_exactly_one_interval() {
+        let (mut clock, at) = locked_clock();
+
+        // about 6.94ms apart, give or take a millisecond
+        let presents = [6.0, 14.5, 20.1, 28.3, 34.5];
+        for &ms in &presents {
+            clock.frame_presented(at(ms));
+        }
+        assert_time(&clock, at(40.0), presents.len() as f64 / 144.0);
+    }
+
+    #[test]
+    fn missed_vsyncs_are_only_caught_up_on_when_asked() {
+        let (mut clock, at) = locked_clock();
+        clock.frame_presented(at(6.9));
+
+        // a 3 frame stall only counts once without catch up
+        assert_eq!(clock.frame_presented(at(27.8)), 1);
+        clock.set_catch_up(true);
+        assert_eq!(clock.frame_presented(at(48.6)), 3);
+        assert_eq!(clock.frame_presented(at(1000.0)), MAX_CATCH_UP);
+        assert_time(&clock, at(1000.0), (2 + 3 + MAX_CATCH_UP) as f64 / 144.0);
+    }
+
+    #[test]
+    fn a_new_refresh_rate_changes_the_step() {
+        // dragged onto a 60hz monitor
+        let (mut clock, at) = locked_clock();
+        clock.frame_presented(at(6.9));
+        let before = clock.time(at(6.9)) as f64;
+        clock.set_refresh_rate(60.0);
+        clock.frame_presented(at(23.6));
+        assert_time(&clock, at(23.6), before + 1.0 / 60.0);
+    }
+
+    #[test]
+    fn switching_sources_keeps_the_time() {
+        let (mut clock, at) = locked_clock();
+        clock.frame_presented(at(6.9));
+        clock.frame_presented(at(13.8));
+        let now = at(20.0);
+        let locked = clock.time(now);
+        clock.set_source(TimeSource::WallClock, now);
+        let wall = clock.time(now);
+        assert!(
+            (wall - locked).abs() < 1e-3,
+            "switching to the wall clock jumped from {} to {}",
+            locked,
+            wall
+        );
+    }
+
+    #[test]
+    fn frames_are_numbered_whatever_the_clock_does() {
+        // moving the clock back, like restoring a snapshot, keeps counting
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut frames = FrameCounter::new(start);
+        frames.begin(2.0, at(10));
+        let timing = frames.begin(0.5, at(20));
+        assert_eq!(timing.index, 1);
+        assert_eq!(timing.real_elapsed, Duration::from_millis(20));
+
+        let wrapped = FrameTiming {
+            index: i32::MAX as u64 + 3,
+            ..timing
+        };
+        assert_eq!(wrapped.shader_frame(), 2);
+    }
+}
@@ -0,0 +1,194 @@
+use super::builtin_textures::BuiltinTextures;
+use super::camera::Camera;
+use super::cull::{Bounds, CullStats};
+use crate::display::{Display, Stage};
+use anyhow::{bail, Context, Result};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::VirtualKeyCode;
+
+mod cloth;
+mod game_of_life;
+mod gradient;
+mod heat_diffusion;
+mod nbody;
+mod solar_system;
+mod sph;
+
+pub use cloth::ClothSimulation;
+pub use game_of_life::{GameOfLife, Pattern};
+pub use gradient::DarkGradient;
+pub use heat_diffusion::HeatDiffusion;
+pub use nbody::{Distribution, NBodySimulation};
+pub use solar_system::SolarSystem;
+pub use sph::SphFluid;
+
+/// The number of invocations in a single compute workgroup. Every compute
+/// shader in this module which works on a buffer declares
+/// `local_size_x = 64`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// The width and height of a single compute workgroup. Every compute shader
+/// in this module which works on an image declares `local_size_x = 8` and
+/// `local_size_y = 8`.
+const TILE_SIZE: u32 = 8;
+
+/// A simulation which keeps its state on the gpu and advances it with
+/// compute shaders.
+pub trait Simulation {
+    /// Update any cpu-side parameters based on the elapsed time in seconds.
+    fn update(&mut self, _time: f32) {}
+
+    /// Respond to a key press.
+    fn handle_key(&mut self, _key: VirtualKeyCode) {}
+
+    /// Build a primary command buffer which advances the simulation by one
+    /// frame.
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer>;
+
+    /// When the step runs. Simulations which step on the async compute stage
+    /// must build their step for the display's compute queue, share their
+    /// buffers with `Display::compute_families`, and draw the state from the
+    /// previous step.
+    fn stage(&self) -> Stage {
+        Stage::Simulation
+    }
+
+    /// Build a secondary command buffer which draws the simulation's current
+    /// state as seen by the camera.
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer>;
+
+    /// What culling tested and rejected the last time the simulation was
+    /// drawn, for simulations which cull.
+    fn culled(&self) -> CullStats {
+        CullStats::default()
+    }
+
+    /// Rebuild any resources which depend on the swapchain.
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()>;
+
+    /// The seed used to randomly initialize the simulation, if it was.
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+
+    /// The parameters which can be changed while running, by name.
+    #[cfg(feature = "snapshot")]
+    fn params(&self) -> Vec<(&'static str, f32)> {
+        vec![]
+    }
+
+    /// Change one of the parameters returned by `params`.
+    #[cfg(feature = "snapshot")]
+    fn set_param(&mut self, _name: &str, _value: f32) {}
+}
+
+/// Create a simulation by name.
+///
+/// `life:<path>` starts the Game of Life from a png image and `nbody:<n>`
+/// runs the n-body simulation with `n` bodies. Randomly initialized
+/// simulations use `seed` when it is given, or a seed from the clock.
+pub fn create_simulation(
+    name: &str,
+    display: &Display,
+    builtins: &BuiltinTextures,
+    seed: Option<u64>,
+) -> Result<Box<dyn Simulation>> {
+    let seed = || {
+        let seed = seed.unwrap_or_else(clock_seed);
+        log::info!("simulation seed {}", seed);
+        seed
+    };
+    let simulation: Box<dyn Simulation> = match name {
+        "gradient" => Box::new(DarkGradient::new(display, builtins)?),
+        "cloth" => Box::new(ClothSimulation::new(display, 32, 48, 0.03)?),
+        "heat" => {
+            let mut heat = HeatDiffusion::new(display, 256, 256)?;
+            heat.set_dt(0.2);
+            heat.add_heat_source(64.0, 128.0, 12.0, 100.0);
+            heat.add_heat_source(192.0, 96.0, 8.0, -50.0);
+            heat.add_heat_source(160.0, 200.0, 6.0, 60.0);
+            Box::new(heat)
+        }
+        "life" => Box::new(GameOfLife::new(
+            display,
+            256,
+            256,
+            Pattern::Random(seed()),
+        )?),
+        "glider-gun" => Box::new(GameOfLife::new(
+            display,
+            256,
+            256,
+            Pattern::GosperGliderGun,
+        )?),
+        "nbody" => Box::new(NBodySimulation::new(
+            display,
+            4096,
+            Distribution::Disk(seed()),
+        )?),
+        "nbody-collision" => Box::new(NBodySimulation::new(
+            display,
+            4096,
+            Distribution::Collision(seed()),
+        )?),
+        "solar-system" => Box::new(SolarSystem::new(display)?),
+        "sph" => Box::new(SphFluid::new(display, 8192, fluid_domain())?),
+        "sph-periodic" => {
+            let mut fluid = SphFluid::new(display, 8192, fluid_domain())?;
+            fluid.set_gravity(0.0);
+            fluid.set_surface_tension(0.0);
+            fluid.set_periodic(true);
+            Box::new(fluid)
+        }
+        _ if name.starts_with("nbody:") => {
+            let count = name["nbody:".len()..]
+                .parse()
+                .with_context(|| format!("invalid body count in {:?}", name))?;
+            Box::new(NBodySimulation::new(
+                display,
+                count,
+                Distribution::Sphere(seed()),
+            )?)
+        }
+        _ if name.starts_with("life:") => Box::new(GameOfLife::new(
+            display,
+            256,
+            256,
+            Pattern::CustomPng(PathBuf::from(&name["life:".len()..])),
+        )?),
+        _ => bail!("unknown simulation {:?}", name),
+    };
+    Ok(simulation)
+}
+
+/// The box which holds the fluid simulations.
+fn fluid_domain() -> Bounds {
+    Bounds::Aabb {
+        min: [-0.8, -0.6, -0.3],
+        max: [0.8, 0.6, 0.3],
+    }
+}
+
+/// A seed for randomly initialized simulations, taken from the clock.
+fn clock_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// The number of workgroups required to cover `count` invocations.
+fn workgroups(count: u32) -> [u32; 3] {
+    [count.div_ceil(WORKGROUP_SIZE), 1, 1]
+}
+
+/// The number of workgroups required to cover a `width * height` image.
+fn workgroups_2d(width: u32, height: u32) -> [u32; 3] {
+    [width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1]
+}
@@ -0,0 +1,852 @@
+use super::{workgroups, Simulation};
+use crate::application::camera::Camera;
+use crate::application::cull::{Bounds, Cull, CullStats};
+use crate::application::debug::BarrierValidator;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::cell::Cell;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::{
+    viewport::Viewport, ComputePipeline, ComputePipelineAbstract,
+    GraphicsPipeline, GraphicsPipelineAbstract,
+};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+
+/// Each particle has one slot for each of the 8 surrounding grid cells.
+const NEIGHBORS: usize = 8;
+
+/// Index primitive restart value for u32 indices.
+const RESTART_INDEX: u32 = u32::MAX;
+
+const GRAVITY: [f32; 4] = [0.0, -9.8, 0.0, 0.0];
+const TIME_STEP: f32 = 1.0 / 60.0;
+const DAMPING: f32 = 0.99;
+
+/// Jacobi passes over the constraints each step, unless changed with
+/// `set_iterations`.
+const ITERATIONS: u32 = 8;
+
+/// A single cloth particle as laid out in the simulation storage buffers.
+///
+/// The `w` component of position is the particle's inverse mass, pinned
+/// particles have an inverse mass of 0.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+struct Particle {
+    position: [f32; 4],
+    previous: [f32; 4],
+    velocity: [f32; 4],
+}
+
+/// A cloth vertex written by the normals compute pass.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct ClothVertex {
+    pub position: [f32; 4],
+    pub normal: [f32; 4],
+}
+
+impl_vertex!(ClothVertex, position, normal);
+
+/// A grid of particles held together by distance constraints.
+///
+/// Each frame the particles are integrated under gravity and wind, the
+/// constraints are relaxed with `iterations` Jacobi passes, and the vertex
+/// normals are recomputed for rendering.
+pub struct ClothSimulation {
+    rows: u32,
+    cols: u32,
+    iterations: u32,
+
+    // the wind set with `set_wind`, or none to blow in gusts
+    wind: Option<[f32; 4]>,
+    gust: [f32; 4],
+
+    // index of the particle buffer which holds the current state
+    current: usize,
+
+    integrate_pipeline: Arc<DynComputePipeline>,
+    solve_pipeline: Arc<DynComputePipeline>,
+    finalize_pipeline: Arc<DynComputePipeline>,
+    normals_pipeline: Arc<DynComputePipeline>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+
+    // descriptor sets are indexed by the particle buffer they read from
+    integrate_sets: [Arc<DynDescriptorSet>; 2],
+    solve_sets: [Arc<DynDescriptorSet>; 2],
+    finalize_sets: [Arc<DynDescriptorSet>; 2],
+    normals_sets: [Arc<DynDescriptorSet>; 2],
+
+    vertices: Arc<DeviceLocalBuffer<[ClothVertex]>>,
+    indices: Arc<CpuAccessibleBuffer<[u32]>>,
+
+    // conservative bounds for every position the cloth can reach
+    bounds: Bounds,
+    culled: Cell<CullStats>,
+}
+
+impl ClothSimulation {
+    /// Create a cloth with `rows * cols` particles, each separated by
+    /// `spacing` units. The two top corners are pinned in place.
+    pub fn new(
+        display: &Display,
+        rows: u32,
+        cols: u32,
+        spacing: f32,
+    ) -> Result<Self> {
+        let device = &display.device;
+        let particles = initial_particles(rows, cols, spacing);
+        let rest_lengths = rest_lengths(&particles, rows, cols);
+
+        let storage_usage = BufferUsage {
+            storage_buffer: true,
+            ..BufferUsage::none()
+        };
+        let particle_buffers = [
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                storage_usage,
+                false,
+                particles.iter().cloned(),
+            )
+            .context("unable to create the cloth particle buffer")?,
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                storage_usage,
+                false,
+                particles.iter().cloned(),
+            )
+            .context("unable to create the cloth particle buffer")?,
+        ];
+        let rest_length_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            storage_usage,
+            false,
+            rest_lengths.into_iter(),
+        )
+        .context("unable to create the cloth rest length buffer")?;
+        let vertices = DeviceLocalBuffer::array(
+            device.clone(),
+            particles.len(),
+            BufferUsage {
+                storage_buffer: true,
+                vertex_buffer: true,
+                ..BufferUsage::none()
+            },
+            vec![display.graphics_queue.family()],
+        )
+        .context("unable to create the cloth vertex buffer")?;
+        let indices = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::index_buffer(),
+            false,
+            strip_indices(rows, cols).into_iter(),
+        )
+        .context("unable to create the cloth index buffer")?;
+
+        let integrate_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &integrate_shader::Shader::load(device.clone())
+                    .context("unable to load the cloth integrate shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the cloth integrate pipeline")?,
+        );
+        let solve_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &solve_shader::Shader::load(device.clone())
+                    .context("unable to load the cloth solve shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the cloth solve pipeline")?,
+        );
+        let finalize_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &finalize_shader::Shader::load(device.clone())
+                    .context("unable to load the cloth finalize shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the cloth finalize pipeline")?,
+        );
+        let normals_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &normals_shader::Shader::load(device.clone())
+                    .context("unable to load the cloth normals shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the cloth normals pipeline")?,
+        );
+
+        let ping_pong_sets = |pipeline: &Arc<DynComputePipeline>| -> Result<_> {
+            let layout = pipeline
+                .descriptor_set_layout(0)
+                .context("cloth pipeline has no descriptor set layout")?;
+            let mut sets: Vec<Arc<DynDescriptorSet>> = vec![];
+            for src in 0..2 {
+                sets.push(Arc::new(
+                    PersistentDescriptorSet::start(layout.clone())
+                        .add_buffer(particle_buffers[src].clone())?
+                        .add_buffer(particle_buffers[1 - src].clone())?
+                        .build()?,
+                ));
+            }
+            Ok([sets[0].clone(), sets[1].clone()])
+        };
+        let integrate_sets = ping_pong_sets(&integrate_pipeline)
+            .context("unable to create the cloth integrate descriptors")?;
+        let finalize_sets = ping_pong_sets(&finalize_pipeline)
+            .context("unable to create the cloth finalize descriptors")?;
+
+        let solve_layout = solve_pipeline
+            .descriptor_set_layout(0)
+            .context("cloth solve pipeline has no descriptor set layout")?;
+        let mut solve_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        let normals_layout = normals_pipeline
+            .descriptor_set_layout(0)
+            .context("cloth normals pipeline has no descriptor set layout")?;
+        let mut normals_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        for src in 0..2 {
+            solve_sets.push(Arc::new(
+                PersistentDescriptorSet::start(solve_layout.clone())
+                    .add_buffer(particle_buffers[src].clone())?
+                    .add_buffer(particle_buffers[1 - src].clone())?
+                    .add_buffer(rest_length_buffer.clone())?
+                    .build()
+                    .context("unable to create the cloth solve descriptors")?,
+            ));
+            normals_sets.push(Arc::new(
+                PersistentDescriptorSet::start(normals_layout.clone())
+                    .add_buffer(particle_buffers[src].clone())?
+                    .add_buffer(vertices.clone())?
+                    .build()
+                    .context("unable to create the cloth normal descriptors")?,
+            ));
+        }
+
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+
+        Ok(Self {
+            rows,
+            cols,
+            iterations: ITERATIONS,
+            wind: None,
+            gust: [0.0, 0.0, 1.0, 0.0],
+            current: 0,
+            integrate_pipeline,
+            solve_pipeline,
+            finalize_pipeline,
+            normals_pipeline,
+            graphics_pipeline,
+            integrate_sets,
+            solve_sets: [solve_sets[0].clone(), solve_sets[1].clone()],
+            finalize_sets,
+            normals_sets: [normals_sets[0].clone(), normals_sets[1].clone()],
+            vertices,
+            indices,
+            bounds: reachable_bounds(rows, cols, spacing),
+            culled: Cell::default(),
+        })
+    }
+
+    /// Set the wind which blows on every unpinned particle. Until it's set
+    /// the wind blows in gusts which change with time.
+    ///
+    /// The direction does not need to be normalized, strength is an
+    /// acceleration in units per second squared.
+    pub fn set_wind(&mut self, direction: [f32; 3], strength: f32) {
+        self.wind = Some(wind(direction, strength));
+    }
+
+    /// Set how many Jacobi passes relax the constraints each step. More
+    /// passes make the cloth stiffer and cost more time.
+    pub fn set_iterations(&mut self, iterations: u32) {
+        self.iterations = iterations;
+    }
+
+    fn particle_count(&self) -> u32 {
+        self.rows * self.cols
+    }
+}
+
+impl Simulation for ClothSimulation {
+    fn update(&mut self, time: f32) {
+        let strength =
+            2.0 + 1.5 * (time * 0.7).sin() + 0.5 * (time * 2.3).sin();
+        self.gust = wind([(time * 0.3).sin() * 0.5, 0.0, 1.0], strength);
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let count = self.particle_count();
+        let groups = workgroups(count);
+
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the cloth command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        builder
+            .dispatch(
+                groups,
+                self.integrate_pipeline.clone(),
+                self.integrate_sets[self.current].clone(),
+                integrate_shader::ty::PushConstantData {
+                    gravity: GRAVITY,
+                    wind: self.wind.unwrap_or(self.gust),
+                    dt: TIME_STEP,
+                    count,
+                },
+            )
+            .context("unable to dispatch the cloth integrate pass")?;
+        self.current = 1 - self.current;
+
+        for _ in 0..self.iterations {
+            builder
+                .dispatch(
+                    groups,
+                    self.solve_pipeline.clone(),
+                    self.solve_sets[self.current].clone(),
+                    solve_shader::ty::PushConstantData {
+                        rows: self.rows,
+                        cols: self.cols,
+                        stiffness: 1.0,
+                    },
+                )
+                .context("unable to dispatch the cloth solve pass")?;
+            self.current = 1 - self.current;
+        }
+
+        builder
+            .dispatch(
+                groups,
+                self.finalize_pipeline.clone(),
+                self.finalize_sets[self.current].clone(),
+                finalize_shader::ty::PushConstantData {
+                    dt: TIME_STEP,
+                    damping: DAMPING,
+                    count,
+                },
+            )
+            .context("unable to dispatch the cloth finalize pass")?;
+        self.current = 1 - self.current;
+
+        builder
+            .dispatch(
+                groups,
+                self.normals_pipeline.clone(),
+                self.normals_sets[self.current].clone(),
+                normals_shader::ty::PushConstantData {
+                    rows: self.rows,
+                    cols: self.cols,
+                },
+            )
+            .context("unable to dispatch the cloth normals pass")?;
+
+        builder
+            .build()
+            .context("unable to build the cloth command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let projection = camera.view_projection();
+
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the cloth")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        let mut cull = Cull::frustum(&projection);
+        let visible = cull.is_visible(&self.bounds);
+        self.culled.set(cull.stats());
+        if !visible {
+            return builder
+                .build()
+                .context("unable to build the command buffer");
+        }
+
+        builder
+            .draw_indexed(
+                self.graphics_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.indices.clone(),
+                (),
+                vertex_shader::ty::PushConstantData { projection },
+            )
+            .context("unable to draw the cloth")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn culled(&self) -> CullStats {
+        self.culled.get()
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.graphics_pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+}
+
+/// The wind as the integrate pass reads it, a unit direction and a strength.
+fn wind(direction: [f32; 3], strength: f32) -> [f32; 4] {
+    let [x, y, z] = direction;
+    let length = (x * x + y * y + z * z).sqrt();
+    if length > 0.0 {
+        [x / length, y / length, z / length, strength]
+    } else {
+        [0.0, 0.0, 0.0, 0.0]
+    }
+}
+
+/// Particles can never be further from the pinned corners than the length of
+/// the cloth's diagonal, so a box around the pins with that half-size holds
+/// every reachable position.
+fn reachable_bounds(rows: u32, cols: u32, spacing: f32) -> Bounds {
+    let width = (cols - 1) as f32 * spacing;
+    let height = (rows - 1) as f32 * spacing;
+    let reach = (width * width + height * height).sqrt();
+    let pin_y = height / 2.0;
+    Bounds::Aabb {
+        min: [-width / 2.0 - reach, pin_y - reach, -reach],
+        max: [width / 2.0 + reach, pin_y + reach, reach],
+    }
+}
+
+/// Lay the particles out on a grid in the xy plane, centered on the origin.
+fn initial_particles(rows: u32, cols: u32, spacing: f32) -> Vec<Particle> {
+    let half_width = (cols - 1) as f32 * spacing / 2.0;
+    let half_height = (rows - 1) as f32 * spacing / 2.0;
+    let mut particles = Vec::with_capacity((rows * cols) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let pinned = row == 0 && (col == 0 || col == cols - 1);
+            let position = [
+                col as f32 * spacing - half_width,
+                half_height - row as f32 * spacing,
+                0.0,
+                if pinned { 0.0 } else { 1.0 },
+            ];
+            particles.push(Particle {
+                position,
+                previous: position,
+                velocity: [0.0; 4],
+            });
+        }
+    }
+    particles
+}
+
+/// The grid offset for each neighbor slot, the order must match the solve
+/// shader.
+fn neighbor_offset(slot: usize) -> (i64, i64) {
+    let cell = if slot < 4 { slot } else { slot + 1 } as i64;
+    (cell % 3 - 1, cell / 3 - 1)
+}
+
+/// Compute the rest length between each particle and its neighbors. Slots
+/// without a neighbor hold a negative length.
+fn rest_lengths(particles: &[Particle], rows: u32, cols: u32) -> Vec<f32> {
+    let mut lengths = Vec::with_capacity(particles.len() * NEIGHBORS);
+    for row in 0..rows as i64 {
+        for col in 0..cols as i64 {
+            let particle = &particles[(row * cols as i64 + col) as usize];
+            for slot in 0..NEIGHBORS {
+                let (dx, dy) = neighbor_offset(slot);
+                let (x, y) = (col + dx, row + dy);
+                if x < 0 || y < 0 || x >= cols as i64 || y >= rows as i64 {
+                    lengths.push(-1.0);
+                    continue;
+                }
+                let other = &particles[(y * cols as i64 + x) as usize];
+                let d: f32 = (0..3)
+                    .map(|i| other.position[i] - particle.position[i])
+                    .map(|d| d * d)
+                    .sum();
+                lengths.push(d.sqrt());
+            }
+        }
+    }
+    lengths
+}
+
+/// Build one triangle strip per pair of rows, separated by the primitive
+/// restart index.
+fn strip_indices(rows: u32, cols: u32) -> Vec<u32> {
+    let mut indices = vec![];
+    for row in 0..rows - 1 {
+        for col in 0..cols {
+            indices.push(row * cols + col);
+            indices.push((row + 1) * cols + col);
+        }
+        indices.push(RESTART_INDEX);
+    }
+    indices
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the cloth vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the cloth fragment shader")?;
+
+    let dimensions = [swapchain_extent[0] as f32, swapchain_extent[1] as f32];
+    let viewport = Viewport {
+        dimensions,
+        origin: [0.0, 0.0],
+        depth_range: 0.0..1.0,
+    };
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<ClothVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![viewport])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .cull_mode_disabled()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_alpha_blending()
+        .triangle_strip()
+        .primitive_restart(true)
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the cloth pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the cloth graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod integrate_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 previous;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Source {
+                Particle particles[];
+            } src;
+
+            layout(set = 0, binding = 1) writeonly buffer Destination {
+                Particle particles[];
+            } dst;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 gravity;
+                vec4 wind;
+                float dt;
+                uint count;
+            } pc;
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.count) {
+                    return;
+                }
+
+                Particle p = src.particles[index];
+                p.previous = p.position;
+                if (p.position.w > 0.0) {
+                    // flutter the wind across the surface of the cloth
+                    float phase = p.position.x * 7.0 + p.position.y * 5.0;
+                    float flutter = 0.75 + 0.25 * sin(phase);
+                    vec3 acceleration =
+                        pc.gravity.xyz + pc.wind.xyz * pc.wind.w * flutter;
+                    p.velocity.xyz += acceleration * pc.dt;
+                    p.position.xyz += p.velocity.xyz * pc.dt;
+                }
+                dst.particles[index] = p;
+            }
+            "#
+    }
+}
+
+mod solve_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 previous;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Source {
+                Particle particles[];
+            } src;
+
+            layout(set = 0, binding = 1) writeonly buffer Destination {
+                Particle particles[];
+            } dst;
+
+            layout(set = 0, binding = 2) readonly buffer RestLengths {
+                float lengths[];
+            } rest;
+
+            layout(push_constant) uniform PushConstantData {
+                uint rows;
+                uint cols;
+                float stiffness;
+            } pc;
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.rows * pc.cols) {
+                    return;
+                }
+
+                Particle p = src.particles[index];
+                float w = p.position.w;
+                int row = int(index / pc.cols);
+                int col = int(index % pc.cols);
+
+                vec3 correction = vec3(0.0);
+                uint constraints = 0;
+                for (int slot = 0; slot < 8; slot++) {
+                    float rest_length = rest.lengths[index * 8 + uint(slot)];
+                    if (w == 0.0 || rest_length < 0.0) {
+                        continue;
+                    }
+
+                    // skip the center cell of the 3x3 neighborhood
+                    int cell = slot < 4 ? slot : slot + 1;
+                    int x = col + cell % 3 - 1;
+                    int y = row + cell / 3 - 1;
+                    Particle other = src.particles[uint(y) * pc.cols + uint(x)];
+
+                    vec3 delta = other.position.xyz - p.position.xyz;
+                    float dist = length(delta);
+                    float total_w = w + other.position.w;
+                    if (dist > 0.0) {
+                        float error = dist - rest_length;
+                        correction += (w / total_w) * error * (delta / dist);
+                        constraints += 1;
+                    }
+                }
+
+                if (constraints > 0) {
+                    p.position.xyz +=
+                        pc.stiffness * correction / float(constraints);
+                }
+                dst.particles[index] = p;
+            }
+            "#
+    }
+}
+
+mod finalize_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 previous;
+                vec4 velocity;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Source {
+                Particle particles[];
+            } src;
+
+            layout(set = 0, binding = 1) writeonly buffer Destination {
+                Particle particles[];
+            } dst;
+
+            layout(push_constant) uniform PushConstantData {
+                float dt;
+                float damping;
+                uint count;
+            } pc;
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.count) {
+                    return;
+                }
+
+                Particle p = src.particles[index];
+                p.velocity.xyz =
+                    (p.position.xyz - p.previous.xyz) / pc.dt * pc.damping;
+                dst.particles[index] = p;
+            }
+            "#
+    }
+}
+
+mod normals_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            struct Particle {
+                vec4 position;
+                vec4 previous;
+                vec4 velocity;
+            };
+
+            struct Vertex {
+                vec4 position;
+                vec4 normal;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Particles {
+                Particle particles[];
+            } src;
+
+            layout(set = 0, binding = 1) writeonly buffer Vertices {
+                Vertex vertices[];
+            } dst;
+
+            layout(push_constant) uniform PushConstantData {
+                uint rows;
+                uint cols;
+            } pc;
+
+            vec3 position_at(uint row, uint col) {
+                return src.particles[row * pc.cols + col].position.xyz;
+            }
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.rows * pc.cols) {
+                    return;
+                }
+
+                uint row = index / pc.cols;
+                uint col = index % pc.cols;
+                uint left = col > 0 ? col - 1 : col;
+                uint right = min(col + 1, pc.cols - 1);
+                uint up = row > 0 ? row - 1 : row;
+                uint down = min(row + 1, pc.rows - 1);
+
+                vec3 dx = position_at(row, right) - position_at(row, left);
+                vec3 dy = position_at(down, col) - position_at(up, col);
+                vec3 normal = cross(dx, dy);
+                if (length(normal) > 0.0) {
+                    normal = normalize(normal);
+                } else {
+                    normal = vec3(0.0, 0.0, 1.0);
+                }
+
+                dst.vertices[index].position = vec4(position_at(row, col), 1.0);
+                dst.vertices[index].normal = vec4(normal, 0.0);
+            }
+            "#
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec4 position;
+            layout(location = 1) in vec4 normal;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 projection;
+            } pc;
+
+            layout(location = 0) out vec3 vertNormal;
+
+            void main() {
+                vertNormal = normal.xyz;
+                gl_Position = pc.projection * vec4(position.xyz, 1.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec3 fragNormal;
+            layout(location = 0) out vec4 outColor;
+
+            const vec3 LIGHT = vec3(0.3, 0.6, 0.74);
+
+            void main() {
+                // the cloth is lit from both sides
+                float diffuse = abs(dot(normalize(fragNormal), LIGHT));
+                vec3 color = vec3(0.8, 0.25, 0.2);
+                outColor = vec4(color * (0.2 + 0.8 * diffuse), 1.0);
+            }
+            "#
+    }
+}
@@ -0,0 +1,571 @@
+use super::{workgroups_2d, Simulation};
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::application::fullscreen::{self, FullscreenVertex};
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::{Dimensions, StorageImage};
+use vulkano::pipeline::{
+    ComputePipeline, ComputePipelineAbstract, GraphicsPipeline,
+    GraphicsPipelineAbstract,
+};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use winit::event::VirtualKeyCode;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+
+/// Live cells in Bill Gosper's glider gun, as (x, y) offsets from the gun's
+/// top left corner.
+const GOSPER_GLIDER_GUN: &[(u32, u32)] = &[
+    (24, 0),
+    (22, 1),
+    (24, 1),
+    (12, 2),
+    (13, 2),
+    (20, 2),
+    (21, 2),
+    (34, 2),
+    (35, 2),
+    (11, 3),
+    (15, 3),
+    (20, 3),
+    (21, 3),
+    (34, 3),
+    (35, 3),
+    (0, 4),
+    (1, 4),
+    (10, 4),
+    (16, 4),
+    (20, 4),
+    (21, 4),
+    (0, 5),
+    (1, 5),
+    (10, 5),
+    (14, 5),
+    (16, 5),
+    (17, 5),
+    (22, 5),
+    (24, 5),
+    (10, 6),
+    (16, 6),
+    (24, 6),
+    (11, 7),
+    (15, 7),
+    (12, 8),
+    (13, 8),
+];
+
+/// The starting state of the grid.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Each cell is alive with a 1 in 4 chance.
+    Random(u64),
+
+    /// A single Gosper glider gun near the top left corner.
+    GosperGliderGun,
+
+    /// Cells are alive wherever the image is bright, the image is centered
+    /// on the grid.
+    CustomPng(PathBuf),
+}
+
+/// Conway's Game of Life on a wrapping grid.
+///
+/// Cells live in a pair of `R8Uint` images which are ping-ponged by a
+/// compute shader, one generation per frame. Space pauses and resumes the
+/// simulation, N advances a single generation.
+pub struct GameOfLife {
+    width: u32,
+    height: u32,
+    paused: bool,
+    pending_steps: u32,
+    seed: Option<u64>,
+
+    // index of the image which holds the current generation
+    current: usize,
+
+    // the initial pattern, uploaded by the first step
+    initial_cells: Option<Arc<CpuAccessibleBuffer<[u8]>>>,
+
+    images: [Arc<StorageImage<Format>>; 2],
+
+    life_pipeline: Arc<DynComputePipeline>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+
+    // descriptor sets are indexed by the image they read from
+    life_sets: [Arc<DynDescriptorSet>; 2],
+    draw_sets: [Arc<DynDescriptorSet>; 2],
+
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl GameOfLife {
+    pub fn new(
+        display: &Display,
+        width: u32,
+        height: u32,
+        initial_pattern: Pattern,
+    ) -> Result<Self> {
+        let device = &display.device;
+        let seed = match initial_pattern {
+            Pattern::Random(seed) => Some(seed),
+            _ => None,
+        };
+        let cells = match initial_pattern {
+            Pattern::Random(seed) => random_cells(width, height, seed),
+            Pattern::GosperGliderGun => glider_gun_cells(width, height),
+            Pattern::CustomPng(path) => png_cells(width, height, &path)
+                .with_context(|| {
+                    format!("unable to load the life pattern {:?}", path)
+                })?,
+        };
+        let initial_cells = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            cells.into_iter(),
+        )
+        .context("unable to create the initial life pattern buffer")?;
+
+        let create_image = || {
+            StorageImage::new(
+                device.clone(),
+                Dimensions::Dim2d { width, height },
+                Format::R8Uint,
+                Some(display.graphics_queue.family()),
+            )
+            .context("unable to create the life image")
+        };
+        let images = [create_image()?, create_image()?];
+
+        let life_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &life_shader::Shader::load(device.clone())
+                    .context("unable to load the life shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the life pipeline")?,
+        );
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+
+        // integer images can only be sampled without filtering
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::Repeat,
+            SamplerAddressMode::Repeat,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .context("unable to create the life sampler")?;
+
+        let life_layout = life_pipeline
+            .descriptor_set_layout(0)
+            .context("life pipeline has no descriptor set layout")?;
+        let draw_layout = graphics_pipeline
+            .descriptor_set_layout(0)
+            .context("life graphics pipeline has no descriptor set layout")?;
+        let mut life_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        let mut draw_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        for src in 0..2 {
+            life_sets.push(Arc::new(
+                PersistentDescriptorSet::start(life_layout.clone())
+                    .add_image(images[src].clone())?
+                    .add_image(images[1 - src].clone())?
+                    .build()
+                    .context("unable to create the life descriptors")?,
+            ));
+            draw_sets.push(Arc::new(
+                PersistentDescriptorSet::start(draw_layout.clone())
+                    .add_sampled_image(images[src].clone(), sampler.clone())?
+                    .build()
+                    .context("unable to create the life draw descriptors")?,
+            ));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            paused: false,
+            pending_steps: 0,
+            seed,
+            current: 0,
+            initial_cells: Some(initial_cells),
+            images,
+            life_pipeline,
+            graphics_pipeline,
+            life_sets: [life_sets[0].clone(), life_sets[1].clone()],
+            draw_sets: [draw_sets[0].clone(), draw_sets[1].clone()],
+            vertices: fullscreen::create_vertex_buffer(device)?,
+        })
+    }
+
+    /// Stop advancing generations.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Advance one generation per frame.
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Advance a single generation on the next frame, even while paused.
+    pub fn step_one(&mut self) {
+        self.pending_steps += 1;
+    }
+}
+
+impl Simulation for GameOfLife {
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Space if self.paused => self.resume(),
+            VirtualKeyCode::Space => self.pause(),
+            VirtualKeyCode::N => self.step_one(),
+            _ => (),
+        }
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the life command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        if let Some(cells) = self.initial_cells.take() {
+            builder
+                .copy_buffer_to_image(cells, self.images[self.current].clone())
+                .context("unable to upload the initial life pattern")?;
+        }
+
+        let generations = if self.paused { 0 } else { 1 } + self.pending_steps;
+        self.pending_steps = 0;
+        for _ in 0..generations {
+            builder
+                .dispatch(
+                    workgroups_2d(self.width, self.height),
+                    self.life_pipeline.clone(),
+                    self.life_sets[self.current].clone(),
+                    (),
+                )
+                .context("unable to dispatch the life generation")?;
+            self.current = 1 - self.current;
+        }
+
+        builder
+            .build()
+            .context("unable to build the life command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for life")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.graphics_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.draw_sets[self.current].clone(),
+                (),
+            )
+            .context("unable to draw the life grid")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.graphics_pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+
+    fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+}
+
+/// A small deterministic generator so the same seed always produces the same
+/// pattern. (splitmix64)
+fn random_cells(width: u32, height: u32, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..width * height)
+        .map(|_| {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^= z >> 31;
+            z.is_multiple_of(4) as u8
+        })
+        .collect()
+}
+
+fn glider_gun_cells(width: u32, height: u32) -> Vec<u8> {
+    let mut cells = vec![0; (width * height) as usize];
+    for &(x, y) in GOSPER_GLIDER_GUN {
+        let (x, y) = ((x + 2) % width, (y + 2) % height);
+        cells[(y * width + x) as usize] = 1;
+    }
+    cells
+}
+
+fn png_cells(width: u32, height: u32, path: &Path) -> Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16,
+    );
+    let (info, mut reader) = decoder.read_info()?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels)?;
+    let bytes_per_pixel = info.line_size / info.width as usize;
+
+    let offset_x = (width as i64 - info.width as i64) / 2;
+    let offset_y = (height as i64 - info.height as i64) / 2;
+    let mut cells = vec![0; (width * height) as usize];
+    for y in 0..info.height as i64 {
+        for x in 0..info.width as i64 {
+            let (cell_x, cell_y) = (x + offset_x, y + offset_y);
+            if cell_x < 0
+                || cell_y < 0
+                || cell_x >= width as i64
+                || cell_y >= height as i64
+            {
+                continue;
+            }
+            let pixel =
+                y as usize * info.line_size + x as usize * bytes_per_pixel;
+            let cell = (cell_y * width as i64 + cell_x) as usize;
+            cells[cell] = (pixels[pixel] > 127) as u8;
+        }
+    }
+    Ok(cells)
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the life fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the life pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the life graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod life_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, r8ui) uniform readonly uimage2D src;
+            layout(set = 0, binding = 1, r8ui) uniform writeonly uimage2D dst;
+
+            void main() {
+                ivec2 size = imageSize(src);
+                ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+                if (coord.x >= size.x || coord.y >= size.y) {
+                    return;
+                }
+
+                uint neighbors = 0;
+                for (int dy = -1; dy <= 1; dy++) {
+                    for (int dx = -1; dx <= 1; dx++) {
+                        if (dx == 0 && dy == 0) {
+                            continue;
+                        }
+                        // the grid wraps at the edges
+                        ivec2 neighbor = (coord + ivec2(dx, dy) + size) % size;
+                        neighbors += imageLoad(src, neighbor).r;
+                    }
+                }
+
+                uint alive = imageLoad(src, coord).r;
+                uint next = 0;
+                if (neighbors == 3 || (alive == 1 && neighbors == 2)) {
+                    next = 1;
+                }
+                imageStore(dst, coord, uvec4(next, 0, 0, 0));
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform usampler2D cells;
+
+            void main() {
+                ivec2 size = textureSize(cells, 0);
+                ivec2 coord = min(ivec2(fragUv * vec2(size)), size - 1);
+                uint alive = texelFetch(cells, coord, 0).r;
+                vec3 dead = vec3(0.05, 0.05, 0.08);
+                vec3 living = vec3(0.9, 0.95, 0.8);
+                outColor = vec4(alive == 1 ? living : dead, 1.0);
+            }
+            "#
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 64;
+
+    /// The glider the gun has fired after 30 generations, as offsets from
+    /// the gun's top left corner.
+    const GLIDER: &[(u32, u32)] =
+        &[(23, 9), (24, 10), (25, 10), (23, 11), (24, 11)];
+
+    /// One generation by the compute shader's rules, on a wrapping grid.
+    fn step(cells: &[u8]) -> Vec<u8> {
+        let cell =
+            |x: u32, y: u32| cells[((y % SIZE) * SIZE + x % SIZE) as usize];
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let (x, y) = (i % SIZE + SIZE, i / SIZE + SIZE);
+                let neighbors: u8 = [
+                    (x - 1, y - 1),
+                    (x, y - 1),
+                    (x + 1, y - 1),
+                    (x - 1, y),
+                    (x + 1, y),
+                    (x - 1, y + 1),
+                    (x, y + 1),
+                    (x + 1, y + 1),
+                ]
+                .iter()
+                .map(|&(x, y)| cell(x, y))
+                .sum();
+                (neighbors == 3 || (cell(x, y) == 1 && neighbors == 2)) as u8
+            })
+            .collect()
+    }
+
+    /// The live cells as offsets from the gun's top left corner, in the
+    /// gun's order of rows then columns.
+    fn live(cells: &[u8]) -> Vec<(u32, u32)> {
+        (0..SIZE * SIZE)
+            .filter(|&i| cells[i as usize] == 1)
+            .map(|i| (i % SIZE - 2, i / SIZE - 2))
+            .collect()
+    }
+
+    /// The live cells where the gun is, leaving out anything it fired.
+    fn gun(cells: &[u8]) -> Vec<(u32, u32)> {
+        live(cells)
+            .into_iter()
+            .filter(|&(x, y)| x <= 35 && y <= 8)
+            .collect()
+    }
+
+    fn sorted(cells: &[(u32, u32)]) -> Vec<(u32, u32)> {
+        let mut cells = cells.to_vec();
+        cells.sort_by_key(|&(x, y)| (y, x));
+        cells
+    }
+
+    #[test]
+    fn the_glider_gun_has_a_period_of_30() {
+        let mut cells = glider_gun_cells(SIZE, SIZE);
+        assert_eq!(gun(&cells), sorted(GOSPER_GLIDER_GUN));
+        for generation in 1..30 {
+            cells = step(&cells);
+            assert_ne!(
+                gun(&cells),
+                sorted(GOSPER_GLIDER_GUN),
+                "the gun repeated after {} generations",
+                generation
+            );
+        }
+        cells = step(&cells);
+        assert_eq!(gun(&cells), sorted(GOSPER_GLIDER_GUN));
+    }
+
+    #[test]
+    fn the_glider_gun_fires_one_glider_every_period() {
+        let mut cells = glider_gun_cells(SIZE, SIZE);
+        for _ in 0..30 {
+            cells = step(&cells);
+        }
+        let mut expected = GOSPER_GLIDER_GUN.to_vec();
+        expected.extend_from_slice(GLIDER);
+        assert_eq!(live(&cells), sorted(&expected));
+    }
+}
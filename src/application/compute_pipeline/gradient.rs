@@ -0,0 +1,201 @@
+use super::Simulation;
+use crate::application::builtin_textures::BuiltinTextures;
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::application::fullscreen::{self, FullscreenVertex};
+use crate::application::post_process;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+use winit::event::VirtualKeyCode;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// A sketch for checking dithering, a dark gradient which bands badly when
+/// quantized to 8 bits.
+///
+/// The left half of the screen is drawn as-is and the right half is dithered
+/// with the builtin blue noise. Space toggles the dithering on the right half
+/// so the two can be compared in place. There is no gpu state to advance.
+pub struct DarkGradient {
+    dither: bool,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl DarkGradient {
+    pub fn new(display: &Display, builtins: &BuiltinTextures) -> Result<Self> {
+        let pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        let layout = pipeline
+            .descriptor_set_layout(0)
+            .context("gradient pipeline has no descriptor set layout")?;
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_sampled_image(
+                    builtins.blue_noise.image.clone(),
+                    Sampler::simple_repeat_linear_no_mipmap(
+                        display.device.clone(),
+                    ),
+                )?
+                .build()
+                .context("unable to create the gradient descriptors")?,
+        );
+        Ok(Self {
+            dither: true,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+}
+
+impl Simulation for DarkGradient {
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        if key == VirtualKeyCode::Space {
+            self.dither = !self.dither;
+            log::info!("gradient dithering enabled: {}", self.dither);
+        }
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the gradient command buffer builder")?,
+            &display.graphics_queue,
+        )
+        .build()
+        .context("unable to build the gradient command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the gradient")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        let srgb_target = post_process::is_srgb(display.swapchain.format());
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                fragment_shader::ty::PushConstantData {
+                    srgb_target: srgb_target as u32,
+                    dither: self.dither as u32,
+                },
+            )
+            .context("unable to draw the gradient")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the gradient fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the gradient pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the gradient graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        include: ["src/application"],
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+            #extension GL_GOOGLE_include_directive : require
+
+            #include <dither.glsl>
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D blue_noise;
+
+            layout(push_constant) uniform PushConstantData {
+                uint srgb_target;
+                uint dither;
+            } pc;
+
+            void main() {
+                // a slow ramp across the darkest few percent of the range,
+                // where 8 bit sRGB has the fewest steps to spare
+                vec3 dark = vec3(0.0);
+                vec3 light = vec3(0.04, 0.045, 0.06);
+                vec3 color = mix(dark, light, fragUv.y);
+
+                if (pc.dither != 0 && fragUv.x > 0.5) {
+                    ivec2 texel = dither_texel(
+                        gl_FragCoord.xy,
+                        textureSize(blue_noise, 0)
+                    );
+                    float noise = texelFetch(blue_noise, texel, 0).r;
+                    color = dither(color, noise, pc.srgb_target != 0);
+                }
+                outColor = vec4(color, 1.0);
+            }
+            "#
+    }
+}
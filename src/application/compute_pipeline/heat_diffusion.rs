@@ -0,0 +1,453 @@
+use super::{workgroups_2d, Simulation};
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::application::fullscreen::{self, FullscreenVertex};
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::image::{Dimensions, StorageImage};
+use vulkano::pipeline::{
+    ComputePipeline, ComputePipelineAbstract, GraphicsPipeline,
+    GraphicsPipelineAbstract,
+};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+
+/// The explicit scheme is only stable while `alpha * dt <= 1/4` on a grid
+/// with unit spacing.
+const MAX_ALPHA_DT: f32 = 0.25;
+
+/// Diffusion steps taken for every rendered frame.
+const STEPS_PER_FRAME: u32 = 8;
+
+/// A region of the grid which is held at a fixed temperature.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+struct HeatSource {
+    position: [f32; 2],
+    radius: f32,
+    temperature: f32,
+}
+
+/// Heat flowing across a 2D grid.
+///
+/// The temperature lives in a pair of `R32Float` images which are
+/// ping-ponged by a compute shader solving `u' = u + alpha*dt*(lap u)` with
+/// finite differences. The latest temperature is drawn with a cool-to-hot
+/// palette.
+///
+/// The compute writes are ordered before the fragment shader's reads by the
+/// semaphore which `Display::render` signals between compute and graphics
+/// submissions.
+pub struct HeatDiffusion {
+    width: u32,
+    height: u32,
+    alpha: f32,
+    dt: f32,
+    sources: Vec<HeatSource>,
+
+    // index of the image which holds the current temperature
+    current: usize,
+    cleared: bool,
+
+    images: [Arc<StorageImage<Format>>; 2],
+    source_pool: CpuBufferPool<HeatSource>,
+
+    diffuse_pipeline: Arc<DynComputePipeline>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+
+    // descriptor sets are indexed by the image they read from
+    diffuse_sets: [Arc<DynDescriptorSet>; 2],
+    draw_sets: [Arc<DynDescriptorSet>; 2],
+
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl HeatDiffusion {
+    /// Create a `width * height` grid where every cell starts at 0 degrees.
+    pub fn new(display: &Display, width: u32, height: u32) -> Result<Self> {
+        let device = &display.device;
+        let create_image = || {
+            StorageImage::new(
+                device.clone(),
+                Dimensions::Dim2d { width, height },
+                Format::R32Sfloat,
+                Some(display.graphics_queue.family()),
+            )
+            .context("unable to create the heat diffusion image")
+        };
+        let images = [create_image()?, create_image()?];
+
+        let diffuse_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &diffuse_shader::Shader::load(device.clone())
+                    .context("unable to load the heat diffusion shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the heat diffusion pipeline")?,
+        );
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .context("unable to create the heat diffusion sampler")?;
+
+        let diffuse_layout = diffuse_pipeline
+            .descriptor_set_layout(0)
+            .context("heat diffusion pipeline has no descriptor set layout")?;
+        let draw_layout = graphics_pipeline
+            .descriptor_set_layout(0)
+            .context("heat graphics pipeline has no descriptor set layout")?;
+        let mut diffuse_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        let mut draw_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        for src in 0..2 {
+            diffuse_sets.push(Arc::new(
+                PersistentDescriptorSet::start(diffuse_layout.clone())
+                    .add_image(images[src].clone())?
+                    .add_image(images[1 - src].clone())?
+                    .build()
+                    .context("unable to create the diffusion descriptors")?,
+            ));
+            draw_sets.push(Arc::new(
+                PersistentDescriptorSet::start(draw_layout.clone())
+                    .add_sampled_image(images[src].clone(), sampler.clone())?
+                    .build()
+                    .context("unable to create the heat draw descriptors")?,
+            ));
+        }
+
+        Ok(Self {
+            width,
+            height,
+            alpha: 1.0,
+            dt: 0.2,
+            sources: vec![],
+            current: 0,
+            cleared: false,
+            images,
+            source_pool: CpuBufferPool::new(
+                device.clone(),
+                BufferUsage {
+                    storage_buffer: true,
+                    ..BufferUsage::none()
+                },
+            ),
+            diffuse_pipeline,
+            graphics_pipeline,
+            diffuse_sets: [diffuse_sets[0].clone(), diffuse_sets[1].clone()],
+            draw_sets: [draw_sets[0].clone(), draw_sets[1].clone()],
+            vertices: fullscreen::create_vertex_buffer(device)?,
+        })
+    }
+
+    /// Hold every cell within `radius` of the texel `(x, y)` at
+    /// `temperature`.
+    pub fn add_heat_source(
+        &mut self,
+        x: f32,
+        y: f32,
+        radius: f32,
+        temperature: f32,
+    ) {
+        self.sources.push(HeatSource {
+            position: [x, y],
+            radius,
+            temperature,
+        });
+    }
+
+    /// Set the time step for each diffusion step.
+    ///
+    /// Time steps which would make the explicit scheme unstable are clamped
+    /// to the largest stable value.
+    pub fn set_dt(&mut self, dt: f32) {
+        let max_dt = MAX_ALPHA_DT / self.alpha;
+        if dt > max_dt {
+            log::warn!(
+                "heat diffusion dt {} is unstable, clamping to {}",
+                dt,
+                max_dt
+            );
+        }
+        self.dt = dt.min(max_dt);
+    }
+
+    /// The range of temperatures which are mapped onto the palette.
+    fn temperature_range(&self) -> (f32, f32) {
+        self.sources.iter().fold((0.0, 1.0), |(min, max), source| {
+            (min.min(source.temperature), max.max(source.temperature))
+        })
+    }
+}
+
+impl Simulation for HeatDiffusion {
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the heat command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        if !self.cleared {
+            for image in &self.images {
+                builder
+                    .clear_color_image(
+                        image.clone(),
+                        ClearValue::Float([0.0, 0.0, 0.0, 0.0]),
+                    )
+                    .context("unable to clear the heat diffusion image")?;
+            }
+            self.cleared = true;
+        }
+
+        // the storage buffer can't be empty, so an unused source is always
+        // appended
+        let mut sources = self.sources.clone();
+        sources.push(HeatSource::default());
+        let sources = Arc::new(self.source_pool.chunk(sources)?);
+        let source_set: Arc<DynDescriptorSet> = Arc::new(
+            PersistentDescriptorSet::start(
+                self.diffuse_pipeline
+                    .descriptor_set_layout(1)
+                    .context("heat diffusion pipeline has no source layout")?
+                    .clone(),
+            )
+            .add_buffer(sources)?
+            .build()
+            .context("unable to create the heat source descriptors")?,
+        );
+
+        for _ in 0..STEPS_PER_FRAME {
+            builder
+                .dispatch(
+                    workgroups_2d(self.width, self.height),
+                    self.diffuse_pipeline.clone(),
+                    (
+                        self.diffuse_sets[self.current].clone(),
+                        source_set.clone(),
+                    ),
+                    diffuse_shader::ty::PushConstantData {
+                        alpha: self.alpha,
+                        dt: self.dt,
+                        source_count: self.sources.len() as u32,
+                    },
+                )
+                .context("unable to dispatch the heat diffusion step")?;
+            self.current = 1 - self.current;
+        }
+
+        builder
+            .build()
+            .context("unable to build the heat command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let (min_temperature, max_temperature) = self.temperature_range();
+
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for heat diffusion")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.graphics_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.draw_sets[self.current].clone(),
+                fragment_shader::ty::PushConstantData {
+                    min_temperature,
+                    max_temperature,
+                },
+            )
+            .context("unable to draw the heat diffusion image")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.graphics_pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the heat fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the heat pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the heat graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod diffuse_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0, r32f) uniform readonly image2D src;
+            layout(set = 0, binding = 1, r32f) uniform writeonly image2D dst;
+
+            struct HeatSource {
+                vec2 position;
+                float radius;
+                float temperature;
+            };
+
+            layout(set = 1, binding = 0) readonly buffer Sources {
+                HeatSource sources[];
+            } heat;
+
+            layout(push_constant) uniform PushConstantData {
+                float alpha;
+                float dt;
+                uint source_count;
+            } pc;
+
+            float temperature_at(ivec2 coord, ivec2 size) {
+                // clamping gives insulated (zero-flux) boundaries
+                return imageLoad(src, clamp(coord, ivec2(0), size - 1)).r;
+            }
+
+            void main() {
+                ivec2 size = imageSize(src);
+                ivec2 coord = ivec2(gl_GlobalInvocationID.xy);
+                if (coord.x >= size.x || coord.y >= size.y) {
+                    return;
+                }
+
+                float u = temperature_at(coord, size);
+                float laplacian =
+                    temperature_at(coord + ivec2(-1, 0), size)
+                    + temperature_at(coord + ivec2(1, 0), size)
+                    + temperature_at(coord + ivec2(0, -1), size)
+                    + temperature_at(coord + ivec2(0, 1), size)
+                    - 4.0 * u;
+                float next = u + pc.alpha * pc.dt * laplacian;
+
+                for (uint i = 0; i < pc.source_count; i++) {
+                    HeatSource source = heat.sources[i];
+                    float dist = distance(vec2(coord), source.position);
+                    if (dist <= source.radius) {
+                        next = source.temperature;
+                    }
+                }
+
+                imageStore(dst, coord, vec4(next, 0.0, 0.0, 0.0));
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D temperature;
+
+            layout(push_constant) uniform PushConstantData {
+                float min_temperature;
+                float max_temperature;
+            } pc;
+
+            float band(float s, float i) {
+                return clamp(s - i, 0.0, 1.0);
+            }
+
+            // blue -> cyan -> green -> yellow -> red
+            vec3 palette(float t) {
+                float s = t * 4.0;
+                vec3 color = vec3(0.0, 0.0, 1.0);
+                color = mix(color, vec3(0.0, 1.0, 1.0), band(s, 0.0));
+                color = mix(color, vec3(0.0, 1.0, 0.0), band(s, 1.0));
+                color = mix(color, vec3(1.0, 1.0, 0.0), band(s, 2.0));
+                color = mix(color, vec3(1.0, 0.0, 0.0), band(s, 3.0));
+                return color;
+            }
+
+            void main() {
+                float u = texture(temperature, fragUv).r;
+                float range = pc.max_temperature - pc.min_temperature;
+                float t = (u - pc.min_temperature) / max(range, 0.0001);
+                outColor = vec4(palette(t), 1.0);
+            }
+            "#
+    }
+}
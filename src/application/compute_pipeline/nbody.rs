@@ -0,0 +1,654 @@
+use super::{workgroups, Simulation};
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::display::{Display, Stage};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, CommandBuffer, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::{
+    viewport::Viewport, ComputePipeline, ComputePipelineAbstract,
+    GraphicsPipeline, GraphicsPipelineAbstract,
+};
+use vulkano::sync::GpuFuture;
+use winit::event::VirtualKeyCode;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+
+const DEFAULT_G: f32 = 1.0;
+const DEFAULT_SOFTENING: f32 = 0.05;
+const TIME_STEP: f32 = 0.01;
+
+/// How often, in seconds, the average frame time is logged.
+const BENCHMARK_INTERVAL: f32 = 2.0;
+
+/// A body's position as laid out in the simulation storage buffers. The `w`
+/// component is the body's mass.
+///
+/// Positions and velocities are both `R32G32B32A32Float` vectors, the
+/// velocity buffers hold plain `[f32; 4]`s with an unused `w`.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct Body {
+    pub position: [f32; 4],
+}
+
+impl_vertex!(Body, position);
+
+/// The starting positions and velocities of the bodies. Each distribution is
+/// seeded so the same seed always produces the same bodies.
+#[derive(Debug, Copy, Clone)]
+pub enum Distribution {
+    /// Bodies scattered uniformly through a sphere, initially at rest.
+    Sphere(u64),
+
+    /// A rotating disk of bodies orbiting a heavy central body.
+    Disk(u64),
+
+    /// Two rotating disks on a collision course.
+    Collision(u64),
+}
+
+/// Gravitational attraction between every pair of bodies.
+///
+/// Each frame a compute shader sums the softened force on every body from
+/// every other body, staging the positions through shared memory one
+/// workgroup-sized tile at a time. The bodies are drawn as point sprites
+/// whose brightness follows the log of their mass. Up and down scale the
+/// gravitational constant, left and right scale the softening length.
+///
+/// The step runs on the async compute stage. Each step reads one pair of
+/// buffers and writes the other while the frame draws the pair being read,
+/// so the bodies drawn are always one step behind the simulation.
+pub struct NBodySimulation {
+    count: u32,
+    g: f32,
+    softening: f32,
+    seed: u64,
+
+    // index of the buffers which hold the current state
+    current: usize,
+
+    // index of the buffers read by the last step, which are drawn
+    drawn: usize,
+
+    positions: [Arc<DeviceLocalBuffer<[Body]>>; 2],
+
+    gravity_pipeline: Arc<DynComputePipeline>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+
+    // descriptor sets are indexed by the buffers they read from
+    gravity_sets: [Arc<DynDescriptorSet>; 2],
+
+    // frame timing for benchmarks
+    frames: u32,
+    last_report: f32,
+}
+
+impl NBodySimulation {
+    /// Create a simulation with `n` bodies.
+    pub fn new(
+        display: &Display,
+        n: usize,
+        initial_distribution: Distribution,
+    ) -> Result<Self> {
+        let device = &display.device;
+        let (seed, (bodies, velocities)) = match initial_distribution {
+            Distribution::Sphere(seed) => (seed, sphere(n, seed)),
+            Distribution::Disk(seed) => (seed, disk(n, seed)),
+            Distribution::Collision(seed) => (seed, collision(n, seed)),
+        };
+
+        let upload_usage = BufferUsage::transfer_source();
+        let initial_positions = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            upload_usage,
+            false,
+            bodies.into_iter(),
+        )
+        .context("unable to create the n-body upload buffer")?;
+        let initial_velocities = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            upload_usage,
+            false,
+            velocities.into_iter(),
+        )
+        .context("unable to create the n-body upload buffer")?;
+
+        let position_usage = BufferUsage {
+            storage_buffer: true,
+            vertex_buffer: true,
+            transfer_destination: true,
+            ..BufferUsage::none()
+        };
+        let velocity_usage = BufferUsage {
+            storage_buffer: true,
+            transfer_destination: true,
+            ..BufferUsage::none()
+        };
+        let mut positions = vec![];
+        let mut velocities = vec![];
+        for _ in 0..2 {
+            positions.push(
+                DeviceLocalBuffer::array(
+                    device.clone(),
+                    n,
+                    position_usage,
+                    display.compute_families(),
+                )
+                .context("unable to create the n-body position buffer")?,
+            );
+            velocities.push(
+                DeviceLocalBuffer::array(
+                    device.clone(),
+                    n,
+                    velocity_usage,
+                    display.compute_families(),
+                )
+                .context("unable to create the n-body velocity buffer")?,
+            );
+        }
+
+        // upload before the first step, which may run on another queue
+        // while the first frame is drawn
+        let mut upload = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context(
+                "unable to create the n-body upload command buffer builder",
+            )?,
+            &display.graphics_queue,
+        );
+        upload
+            .copy_buffer(initial_positions, positions[0].clone())
+            .context("unable to upload the initial n-body positions")?;
+        upload
+            .copy_buffer(initial_velocities, velocities[0].clone())
+            .context("unable to upload the initial n-body velocities")?;
+        upload
+            .build()
+            .context("unable to build the n-body upload command buffer")?
+            .execute(display.graphics_queue.clone())
+            .context("unable to execute the n-body upload")?
+            .then_signal_fence_and_flush()
+            .context("unable to flush the n-body upload")?
+            .wait(None)
+            .context("unable to complete the n-body upload")?;
+
+        let gravity_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &gravity_shader::Shader::load(device.clone())
+                    .context("unable to load the n-body gravity shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the n-body gravity pipeline")?,
+        );
+
+        let layout = gravity_pipeline
+            .descriptor_set_layout(0)
+            .context("n-body pipeline has no descriptor set layout")?;
+        let mut gravity_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        for src in 0..2 {
+            gravity_sets.push(Arc::new(
+                PersistentDescriptorSet::start(layout.clone())
+                    .add_buffer(positions[src].clone())?
+                    .add_buffer(velocities[src].clone())?
+                    .add_buffer(positions[1 - src].clone())?
+                    .add_buffer(velocities[1 - src].clone())?
+                    .build()
+                    .context("unable to create the n-body descriptors")?,
+            ));
+        }
+
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+
+        Ok(Self {
+            count: n as u32,
+            g: DEFAULT_G,
+            softening: DEFAULT_SOFTENING,
+            seed,
+            current: 0,
+            drawn: 0,
+            positions: [positions[0].clone(), positions[1].clone()],
+            gravity_pipeline,
+            graphics_pipeline,
+            gravity_sets: [gravity_sets[0].clone(), gravity_sets[1].clone()],
+            frames: 0,
+            last_report: 0.0,
+        })
+    }
+
+    /// Set the gravitational constant.
+    pub fn set_g(&mut self, g: f32) {
+        self.g = g;
+    }
+
+    /// Set the softening length which keeps close encounters from producing
+    /// unbounded forces. Negative values are treated as 0.
+    pub fn set_softening(&mut self, softening: f32) {
+        self.softening = softening.max(0.0);
+    }
+}
+
+impl Simulation for NBodySimulation {
+    fn update(&mut self, time: f32) {
+        self.frames += 1;
+        let elapsed = time - self.last_report;
+        if elapsed >= BENCHMARK_INTERVAL {
+            log::info!(
+                "n-body with {} bodies: {:.2} ms per frame",
+                self.count,
+                elapsed * 1000.0 / self.frames as f32
+            );
+            self.frames = 0;
+            self.last_report = time;
+        }
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Up => self.set_g(self.g * 1.25),
+            VirtualKeyCode::Down => self.set_g(self.g / 1.25),
+            VirtualKeyCode::Right => self.set_softening(self.softening * 1.25),
+            VirtualKeyCode::Left => self.set_softening(self.softening / 1.25),
+            _ => return,
+        }
+        log::info!("n-body g {}, softening {}", self.g, self.softening);
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.compute_queue.family(),
+            )
+            .context("unable to create the n-body command buffer builder")?,
+            &display.compute_queue,
+        );
+
+        builder
+            .dispatch(
+                workgroups(self.count),
+                self.gravity_pipeline.clone(),
+                self.gravity_sets[self.current].clone(),
+                gravity_shader::ty::PushConstantData {
+                    g: self.g,
+                    softening: self.softening,
+                    dt: TIME_STEP,
+                    count: self.count,
+                },
+            )
+            .context("unable to dispatch the n-body gravity pass")?;
+        self.drawn = self.current;
+        self.current = 1 - self.current;
+
+        builder
+            .build()
+            .context("unable to build the n-body command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the n-body")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.graphics_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.positions[self.drawn].clone()],
+                (),
+                vertex_shader::ty::PushConstantData {
+                    projection: camera.view_projection(),
+                    count: self.count,
+                },
+            )
+            .context("unable to draw the n-body")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.graphics_pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::AsyncCompute
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn params(&self) -> Vec<(&'static str, f32)> {
+        vec![("g", self.g), ("softening", self.softening)]
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "g" => self.set_g(value),
+            "softening" => self.set_softening(value),
+            _ => log::warn!("the n-body simulation has no {:?}", name),
+        }
+    }
+}
+
+/// A small deterministic generator so the same seed always produces the same
+/// bodies. (splitmix64)
+struct Random(u64);
+
+impl Random {
+    /// A uniform value in [0, 1).
+    fn next(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// A uniform value in [-1, 1).
+    fn signed(&mut self) -> f32 {
+        self.next() * 2.0 - 1.0
+    }
+}
+
+type State = (Vec<Body>, Vec<[f32; 4]>);
+
+fn sphere(n: usize, seed: u64) -> State {
+    let mut random = Random(seed);
+    let mut bodies = Vec::with_capacity(n);
+    while bodies.len() < n {
+        let p = [random.signed(), random.signed(), random.signed()];
+        if p[0] * p[0] + p[1] * p[1] + p[2] * p[2] > 1.0 {
+            continue;
+        }
+        let mass = (0.5 + random.next()) / n as f32;
+        bodies.push(Body {
+            position: [p[0] * 0.8, p[1] * 0.8, p[2] * 0.8, mass],
+        });
+    }
+    (bodies, vec![[0.0; 4]; n])
+}
+
+fn disk(n: usize, seed: u64) -> State {
+    let mut random = Random(seed);
+    rotating_disk(&mut random, n, [0.0, 0.0], [0.0, 0.0], 0.8, 1.0)
+}
+
+fn collision(n: usize, seed: u64) -> State {
+    let mut random = Random(seed);
+    let (mut bodies, mut velocities) =
+        rotating_disk(&mut random, n / 2, [-0.6, 0.25], [0.2, 0.0], 0.4, 0.5);
+    let (other_bodies, other_velocities) = rotating_disk(
+        &mut random,
+        n - n / 2,
+        [0.6, -0.25],
+        [-0.2, 0.0],
+        0.4,
+        0.5,
+    );
+    bodies.extend(other_bodies);
+    velocities.extend(other_velocities);
+    (bodies, velocities)
+}
+
+/// A disk of bodies in the xy plane on roughly circular orbits around a
+/// central body which holds half of the disk's mass.
+fn rotating_disk(
+    random: &mut Random,
+    n: usize,
+    center: [f32; 2],
+    velocity: [f32; 2],
+    radius: f32,
+    total_mass: f32,
+) -> State {
+    let mut bodies = Vec::with_capacity(n);
+    let mut velocities = Vec::with_capacity(n);
+    if n == 0 {
+        return (bodies, velocities);
+    }
+
+    let central_mass = total_mass / 2.0;
+    bodies.push(Body {
+        position: [center[0], center[1], 0.0, central_mass],
+    });
+    velocities.push([velocity[0], velocity[1], 0.0, 0.0]);
+
+    let orbiting_mass = total_mass - central_mass;
+    for _ in 1..n {
+        let r = radius * (0.1 + 0.9 * random.next().sqrt());
+        let angle = random.next() * std::f32::consts::PI * 2.0;
+        let (sin, cos) = angle.sin_cos();
+        let mass = orbiting_mass * (0.5 + random.next()) / (n - 1) as f32;
+
+        // the orbiting bodies are spread evenly by area, so the mass inside
+        // the orbit grows with the square of the radius
+        let inside = central_mass + orbiting_mass * (r / radius).powi(2);
+        let speed = (DEFAULT_G * inside / r).sqrt();
+
+        bodies.push(Body {
+            position: [
+                center[0] + r * cos,
+                center[1] + r * sin,
+                random.signed() * 0.02,
+                mass,
+            ],
+        });
+        velocities.push([
+            velocity[0] - speed * sin,
+            velocity[1] + speed * cos,
+            0.0,
+            0.0,
+        ]);
+    }
+    (bodies, velocities)
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the n-body vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the n-body fragment shader")?;
+
+    let dimensions = [swapchain_extent[0] as f32, swapchain_extent[1] as f32];
+    let viewport = Viewport {
+        dimensions,
+        origin: [0.0, 0.0],
+        depth_range: 0.0..1.0,
+    };
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<Body>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![viewport])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_alpha_blending()
+        .point_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the n-body pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the n-body graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod gravity_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            layout(set = 0, binding = 0) readonly buffer SourcePositions {
+                vec4 positions[];
+            } src_position;
+
+            layout(set = 0, binding = 1) readonly buffer SourceVelocities {
+                vec4 velocities[];
+            } src_velocity;
+
+            layout(set = 0, binding = 2) writeonly buffer DestPositions {
+                vec4 positions[];
+            } dst_position;
+
+            layout(set = 0, binding = 3) writeonly buffer DestVelocities {
+                vec4 velocities[];
+            } dst_velocity;
+
+            layout(push_constant) uniform PushConstantData {
+                float g;
+                float softening;
+                float dt;
+                uint count;
+            } pc;
+
+            shared vec4 tile[64];
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                uint local = gl_LocalInvocationID.x;
+                bool active = index < pc.count;
+
+                vec4 body = vec4(0.0);
+                if (active) {
+                    body = src_position.positions[index];
+                }
+
+                // every invocation must reach the barriers, even the ones
+                // past the end of the buffer
+                float soft2 = pc.softening * pc.softening;
+                vec3 acceleration = vec3(0.0);
+                for (uint base = 0; base < pc.count; base += 64) {
+                    uint other = base + local;
+                    if (other < pc.count) {
+                        tile[local] = src_position.positions[other];
+                    } else {
+                        // massless padding contributes no force
+                        tile[local] = vec4(0.0);
+                    }
+                    barrier();
+
+                    for (uint i = 0; i < 64; i++) {
+                        vec3 delta = tile[i].xyz - body.xyz;
+                        float dist2 = dot(delta, delta) + soft2;
+                        if (dist2 > 0.0) {
+                            float inv_dist = inversesqrt(dist2);
+                            float inv_dist3 = inv_dist * inv_dist * inv_dist;
+                            acceleration += tile[i].w * inv_dist3 * delta;
+                        }
+                    }
+                    barrier();
+                }
+
+                if (!active) {
+                    return;
+                }
+
+                vec4 velocity = src_velocity.velocities[index];
+                velocity.xyz += pc.g * acceleration * pc.dt;
+                body.xyz += velocity.xyz * pc.dt;
+                dst_velocity.velocities[index] = velocity;
+                dst_position.positions[index] = body;
+            }
+            "#
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec4 position;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 projection;
+                uint count;
+            } pc;
+
+            layout(location = 0) out float vertBrightness;
+
+            void main() {
+                // an average body has a mass of 1 / count
+                float relative_mass = position.w * float(pc.count);
+                float brightness = 0.3 + 0.15 * log(relative_mass);
+                vertBrightness = clamp(brightness, 0.05, 1.0);
+                gl_PointSize = 2.0 + 4.0 * vertBrightness;
+                gl_Position = pc.projection * vec4(position.xyz, 1.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in float fragBrightness;
+            layout(location = 0) out vec4 outColor;
+
+            void main() {
+                // fade out towards the edge of the sprite
+                float falloff = 1.0 - length(gl_PointCoord * 2.0 - 1.0);
+                if (falloff <= 0.0) {
+                    discard;
+                }
+                vec3 color = vec3(1.0, 0.9, 0.75) * fragBrightness;
+                outColor = vec4(color, falloff);
+            }
+            "#
+    }
+}
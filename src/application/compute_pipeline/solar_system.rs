@@ -0,0 +1,121 @@
+use super::Simulation;
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::application::meshes::{DrawList, Material, Mesh, MeshRenderer};
+use crate::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use crate::display::Display;
+use anyhow::{Context, Result};
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
+
+/// How far the planet orbits from the sun.
+const PLANET_ORBIT: f32 = 0.65;
+
+/// How far the moon orbits from the planet.
+const MOON_ORBIT: f32 = 0.16;
+
+/// A sun, a planet, and a moon, each orbiting its parent.
+///
+/// Every body is placed by a transform relative to its parent, so the moon
+/// follows the planet around the sun without knowing where the sun is. The
+/// bodies' sizes are on their own child transforms so scaling a body does
+/// not scale its children's orbits. There is no gpu state to advance, each
+/// step only resolves the hierarchy.
+pub struct SolarSystem {
+    transforms: TransformHierarchy,
+    sun_spin: TransformId,
+    planet_orbit: TransformId,
+    moon_orbit: TransformId,
+    time: f32,
+    draw_list: DrawList,
+    renderer: MeshRenderer,
+}
+
+impl SolarSystem {
+    pub fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let sun_spin = transforms.add(Transform::default(), None)?;
+        let sun = transforms.add(Transform::from_scale(0.2), Some(sun_spin))?;
+
+        let planet_orbit = transforms.add(Transform::default(), None)?;
+        let planet_center = transforms.add(
+            Transform::from_translation([PLANET_ORBIT, 0.0, 0.0]),
+            Some(planet_orbit),
+        )?;
+        let planet =
+            transforms.add(Transform::from_scale(0.07), Some(planet_center))?;
+
+        let moon_orbit =
+            transforms.add(Transform::default(), Some(planet_center))?;
+        let moon_center = transforms.add(
+            Transform::from_translation([MOON_ORBIT, 0.0, 0.0]),
+            Some(moon_orbit),
+        )?;
+        let moon =
+            transforms.add(Transform::from_scale(0.03), Some(moon_center))?;
+
+        let disc = Mesh::disc(&display.device, 48)?;
+        let mut draw_list = DrawList::default();
+        let color = |r, g, b| Material {
+            color: [r, g, b, 1.0],
+        };
+        draw_list.draw_mesh(&disc, color(1.0, 0.8, 0.2), sun);
+        draw_list.draw_mesh(&disc, color(0.2, 0.5, 1.0), planet);
+        draw_list.draw_mesh(&disc, color(0.7, 0.7, 0.7), moon);
+
+        Ok(Self {
+            transforms,
+            sun_spin,
+            planet_orbit,
+            moon_orbit,
+            time: 0.0,
+            draw_list,
+            renderer: MeshRenderer::new(display)?,
+        })
+    }
+}
+
+impl Simulation for SolarSystem {
+    fn update(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let rotations = [
+            (self.sun_spin, 0.2),
+            (self.planet_orbit, 0.5),
+            (self.moon_orbit, 2.5),
+        ];
+        for &(id, speed) in &rotations {
+            self.transforms
+                .set_local(id, Transform::from_rotation_z(self.time * speed))?;
+        }
+        self.transforms.resolve()?;
+        BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context(
+                "unable to create the solar system command buffer builder",
+            )?,
+            &display.graphics_queue,
+        )
+        .build()
+        .context("unable to build the solar system command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        self.renderer
+            .draw(display, camera, &self.draw_list, &self.transforms)
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+}
@@ -0,0 +1,1100 @@
+use super::{workgroups, Simulation};
+use crate::application::camera::Camera;
+use crate::application::cull::{Bounds, Cull, CullStats};
+use crate::application::debug::BarrierValidator;
+use crate::display::Display;
+use anyhow::{bail, Context, Result};
+use std::cell::Cell;
+use std::sync::Arc;
+use vulkano::buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
+use vulkano::pipeline::{
+    viewport::Viewport, ComputePipeline, ComputePipelineAbstract,
+    GraphicsPipeline, GraphicsPipelineAbstract,
+};
+use winit::event::VirtualKeyCode;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+type DynBuffer = dyn BufferAccess + Send + Sync;
+
+/// The number of particle indices each hash bucket can hold. Must match
+/// `BUCKET_CAPACITY` in the compute shaders, particles which land in a full
+/// bucket are left out of the neighbor search for that step.
+const BUCKET_CAPACITY: u32 = 32;
+
+/// The kernel support radius.
+const SMOOTHING_RADIUS: f32 = 0.05;
+
+const TIME_STEP: f32 = 0.002;
+const STEPS_PER_FRAME: u32 = 4;
+const STIFFNESS: f32 = 100.0;
+const GRAVITY: f32 = -9.8;
+
+/// How often, in seconds, the total momentum is logged for periodic fluids.
+const MOMENTUM_INTERVAL: f32 = 2.0;
+
+/// A fluid particle's position as laid out in the simulation storage
+/// buffers, the `w` component is unused.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct FluidParticle {
+    pub position: [f32; 4],
+}
+
+impl_vertex!(FluidParticle, position);
+
+/// One corner of the billboard quad drawn for every particle.
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct BillboardCorner {
+    pub corner: [f32; 2],
+}
+
+impl_vertex!(BillboardCorner, corner);
+
+/// Smoothed-particle hydrodynamics using Müller's kernels.
+///
+/// Each step hashes the particles into a spatial hash grid, sums the density
+/// and pressure at every particle, then integrates the pressure, viscosity,
+/// surface tension, and gravity forces. The fluid is confined by the walls
+/// of its domain, or wraps around them when periodic. Up and down scale the
+/// viscosity, left and right scale the surface tension, and page up and page
+/// down scale the rest density.
+pub struct SphFluid {
+    count: u32,
+    domain: Bounds,
+    culled: Cell<CullStats>,
+    grid: [u32; 3],
+    bucket_count: u32,
+    mass: f32,
+    rest_density: f32,
+    viscosity: f32,
+    surface_tension: f32,
+    gravity: f32,
+    periodic: bool,
+
+    // index of the buffers which hold the current state
+    current: usize,
+
+    positions: [Arc<CpuAccessibleBuffer<[FluidParticle]>>; 2],
+    velocities: [Arc<CpuAccessibleBuffer<[[f32; 4]]>>; 2],
+    bucket_counts: Arc<CpuAccessibleBuffer<[u32]>>,
+    corners: Arc<CpuAccessibleBuffer<[BillboardCorner]>>,
+
+    hash_pipeline: Arc<DynComputePipeline>,
+    density_pipeline: Arc<DynComputePipeline>,
+    force_pipeline: Arc<DynComputePipeline>,
+    graphics_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+
+    // descriptor sets are indexed by the buffers they read from
+    hash_sets: [Arc<DynDescriptorSet>; 2],
+    density_sets: [Arc<DynDescriptorSet>; 2],
+    force_sets: [Arc<DynDescriptorSet>; 2],
+
+    last_report: f32,
+}
+
+impl SphFluid {
+    /// Create a fluid with `particle_count` particles which starts as a
+    /// block of water in the lower corner of the domain.
+    pub fn new(
+        display: &Display,
+        particle_count: usize,
+        domain: Bounds,
+    ) -> Result<Self> {
+        let device = &display.device;
+        let extent = domain.extent();
+        let grid = [
+            (extent[0] / SMOOTHING_RADIUS) as u32,
+            (extent[1] / SMOOTHING_RADIUS) as u32,
+            (extent[2] / SMOOTHING_RADIUS) as u32,
+        ];
+        if grid.iter().any(|&cells| cells < 3) {
+            bail!(
+                "the fluid domain {:?} must be at least 3 smoothing radii \
+                across on every axis",
+                domain
+            );
+        }
+        let bucket_count = (particle_count as u32).next_power_of_two();
+
+        // particles start half a smoothing radius apart, which puts the
+        // initial block at roughly the rest density
+        let spacing = SMOOTHING_RADIUS / 2.0;
+        let rest_density = 1000.0;
+        let mass = rest_density * spacing * spacing * spacing;
+        let particles = dam_break(particle_count, &domain, spacing);
+
+        let storage_usage = BufferUsage {
+            storage_buffer: true,
+            vertex_buffer: true,
+            ..BufferUsage::none()
+        };
+        let mut positions = vec![];
+        let mut velocities = vec![];
+        for _ in 0..2 {
+            positions.push(
+                CpuAccessibleBuffer::from_iter(
+                    device.clone(),
+                    storage_usage,
+                    false,
+                    particles.iter().cloned(),
+                )
+                .context("unable to create the fluid position buffer")?,
+            );
+            velocities.push(
+                CpuAccessibleBuffer::from_iter(
+                    device.clone(),
+                    storage_usage,
+                    false,
+                    (0..particle_count).map(|_| [0.0; 4]),
+                )
+                .context("unable to create the fluid velocity buffer")?,
+            );
+        }
+        let scalar_buffer = |len: u32| {
+            CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage {
+                    storage_buffer: true,
+                    transfer_destination: true,
+                    ..BufferUsage::none()
+                },
+                false,
+                (0..len).map(|_| 0u32),
+            )
+        };
+        let bucket_counts = scalar_buffer(bucket_count)
+            .context("unable to create the fluid hash buckets")?;
+        let bucket_entries = scalar_buffer(bucket_count * BUCKET_CAPACITY)
+            .context("unable to create the fluid hash buckets")?;
+        let densities = scalar_buffer(particle_count as u32)
+            .context("unable to create the fluid density buffer")?;
+        let pressures = scalar_buffer(particle_count as u32)
+            .context("unable to create the fluid pressure buffer")?;
+        let corners = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            [
+                [-1.0, -1.0],
+                [1.0, -1.0],
+                [1.0, 1.0],
+                [-1.0, -1.0],
+                [1.0, 1.0],
+                [-1.0, 1.0],
+            ]
+            .iter()
+            .map(|&corner| BillboardCorner { corner }),
+        )
+        .context("unable to create the fluid billboard buffer")?;
+
+        let hash_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &hash_shader::Shader::load(device.clone())
+                    .context("unable to load the fluid hash shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the fluid hash pipeline")?,
+        );
+        let density_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &density_shader::Shader::load(device.clone())
+                    .context("unable to load the fluid density shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the fluid density pipeline")?,
+        );
+        let force_pipeline: Arc<DynComputePipeline> = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &force_shader::Shader::load(device.clone())
+                    .context("unable to load the fluid force shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the fluid force pipeline")?,
+        );
+
+        let hash_layout = hash_pipeline
+            .descriptor_set_layout(0)
+            .context("fluid hash pipeline has no descriptor set layout")?;
+        let density_layout = density_pipeline
+            .descriptor_set_layout(0)
+            .context("fluid density pipeline has no descriptor set layout")?;
+        let force_layout = force_pipeline
+            .descriptor_set_layout(0)
+            .context("fluid force pipeline has no descriptor set layout")?;
+        let mut hash_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        let mut density_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        let mut force_sets: Vec<Arc<DynDescriptorSet>> = vec![];
+        for src in 0..2 {
+            hash_sets.push(Arc::new(
+                PersistentDescriptorSet::start(hash_layout.clone())
+                    .add_buffer(positions[src].clone())?
+                    .add_buffer(bucket_counts.clone())?
+                    .add_buffer(bucket_entries.clone())?
+                    .build()
+                    .context("unable to create the fluid hash descriptors")?,
+            ));
+            density_sets.push(Arc::new(
+                PersistentDescriptorSet::start(density_layout.clone())
+                    .add_buffer(positions[src].clone())?
+                    .add_buffer(bucket_counts.clone())?
+                    .add_buffer(bucket_entries.clone())?
+                    .add_buffer(densities.clone())?
+                    .add_buffer(pressures.clone())?
+                    .build()
+                    .context(
+                        "unable to create the fluid density descriptors",
+                    )?,
+            ));
+            force_sets.push(Arc::new(
+                PersistentDescriptorSet::start(force_layout.clone())
+                    .add_buffer(positions[src].clone())?
+                    .add_buffer(velocities[src].clone())?
+                    .add_buffer(bucket_counts.clone())?
+                    .add_buffer(bucket_entries.clone())?
+                    .add_buffer(densities.clone())?
+                    .add_buffer(pressures.clone())?
+                    .add_buffer(positions[1 - src].clone())?
+                    .add_buffer(velocities[1 - src].clone())?
+                    .build()
+                    .context("unable to create the fluid force descriptors")?,
+            ));
+        }
+
+        let graphics_pipeline = create_graphics_pipeline(
+            device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+
+        Ok(Self {
+            count: particle_count as u32,
+            domain,
+            culled: Cell::default(),
+            grid,
+            bucket_count,
+            mass,
+            rest_density,
+            viscosity: 3.5,
+            surface_tension: 0.0728,
+            gravity: GRAVITY,
+            periodic: false,
+            current: 0,
+            positions: [positions[0].clone(), positions[1].clone()],
+            velocities: [velocities[0].clone(), velocities[1].clone()],
+            bucket_counts,
+            corners,
+            hash_pipeline,
+            density_pipeline,
+            force_pipeline,
+            graphics_pipeline,
+            hash_sets: [hash_sets[0].clone(), hash_sets[1].clone()],
+            density_sets: [density_sets[0].clone(), density_sets[1].clone()],
+            force_sets: [force_sets[0].clone(), force_sets[1].clone()],
+            last_report: 0.0,
+        })
+    }
+
+    /// Set the dynamic viscosity. Negative values are treated as 0.
+    pub fn set_viscosity(&mut self, viscosity: f32) {
+        self.viscosity = viscosity.max(0.0);
+    }
+
+    /// Set the surface tension coefficient. Negative values are treated
+    /// as 0.
+    pub fn set_surface_tension(&mut self, surface_tension: f32) {
+        self.surface_tension = surface_tension.max(0.0);
+    }
+
+    /// Set the density the fluid relaxes towards, pressure is proportional
+    /// to how far a particle's density is above it.
+    pub fn set_rest_density(&mut self, rest_density: f32) {
+        self.rest_density = rest_density;
+    }
+
+    /// Set the vertical acceleration due to gravity.
+    pub fn set_gravity(&mut self, gravity: f32) {
+        self.gravity = gravity;
+    }
+
+    /// When periodic, particles which leave one side of the domain enter
+    /// from the opposite side and interact with their neighbors across the
+    /// boundary. Otherwise they bounce off of the domain's walls.
+    pub fn set_periodic(&mut self, periodic: bool) {
+        self.periodic = periodic;
+    }
+
+    /// The mass of every particle together.
+    pub fn total_mass(&self) -> f32 {
+        self.mass * self.count as f32
+    }
+
+    /// The fluid's total momentum.
+    ///
+    /// Pressure and viscosity forces are pairwise symmetric, so without
+    /// gravity, walls, or surface tension the total stays constant.
+    pub fn momentum(&self) -> Result<[f32; 3]> {
+        let velocities = self.velocities[self.current]
+            .read()
+            .context("unable to read the fluid velocities")?;
+        let mut momentum = [0.0; 3];
+        for velocity in velocities.iter() {
+            for axis in 0..3 {
+                momentum[axis] += self.mass * velocity[axis];
+            }
+        }
+        Ok(momentum)
+    }
+
+    fn cell_size(&self) -> [f32; 4] {
+        let extent = self.domain.extent();
+        [
+            extent[0] / self.grid[0] as f32,
+            extent[1] / self.grid[1] as f32,
+            extent[2] / self.grid[2] as f32,
+            0.0,
+        ]
+    }
+}
+
+impl Simulation for SphFluid {
+    fn update(&mut self, time: f32) {
+        if !self.periodic || time - self.last_report < MOMENTUM_INTERVAL {
+            return;
+        }
+        self.last_report = time;
+        match self.momentum() {
+            Ok(momentum) => log::info!("fluid momentum {:?}", momentum),
+            Err(error) => log::warn!("{:?}", error),
+        }
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        match key {
+            VirtualKeyCode::Up => self.set_viscosity(self.viscosity * 1.25),
+            VirtualKeyCode::Down => self.set_viscosity(self.viscosity / 1.25),
+            VirtualKeyCode::Right => {
+                self.set_surface_tension(self.surface_tension * 1.25)
+            }
+            VirtualKeyCode::Left => {
+                self.set_surface_tension(self.surface_tension / 1.25)
+            }
+            VirtualKeyCode::PageUp => {
+                self.set_rest_density(self.rest_density * 1.05)
+            }
+            VirtualKeyCode::PageDown => {
+                self.set_rest_density(self.rest_density / 1.05)
+            }
+            _ => return,
+        }
+        log::info!(
+            "fluid viscosity {}, surface tension {}, rest density {}",
+            self.viscosity,
+            self.surface_tension,
+            self.rest_density
+        );
+    }
+
+    fn step(&mut self, display: &Display) -> Result<AutoCommandBuffer> {
+        let groups = workgroups(self.count);
+        let ([min_x, min_y, min_z], [max_x, max_y, max_z]) =
+            self.domain.corners();
+        let domain_min = [min_x, min_y, min_z, 0.0];
+        let domain_max = [max_x, max_y, max_z, 0.0];
+        let cell_size = self.cell_size();
+        let grid = [
+            self.grid[0],
+            self.grid[1],
+            self.grid[2],
+            self.periodic as u32,
+        ];
+
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the fluid command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        for _ in 0..STEPS_PER_FRAME {
+            builder
+                .fill_buffer(self.bucket_counts.clone(), 0)
+                .context("unable to clear the fluid hash buckets")?;
+            builder
+                .dispatch(
+                    groups,
+                    self.hash_pipeline.clone(),
+                    self.hash_sets[self.current].clone(),
+                    hash_shader::ty::PushConstantData {
+                        domain_min,
+                        cell_size,
+                        grid,
+                        count: self.count,
+                        bucket_count: self.bucket_count,
+                    },
+                )
+                .context("unable to dispatch the fluid hash pass")?;
+            builder
+                .dispatch(
+                    groups,
+                    self.density_pipeline.clone(),
+                    self.density_sets[self.current].clone(),
+                    density_shader::ty::PushConstantData {
+                        domain_min,
+                        domain_max,
+                        cell_size,
+                        grid,
+                        h: SMOOTHING_RADIUS,
+                        mass: self.mass,
+                        rest_density: self.rest_density,
+                        stiffness: STIFFNESS,
+                        count: self.count,
+                        bucket_count: self.bucket_count,
+                    },
+                )
+                .context("unable to dispatch the fluid density pass")?;
+            builder
+                .dispatch(
+                    groups,
+                    self.force_pipeline.clone(),
+                    self.force_sets[self.current].clone(),
+                    force_shader::ty::PushConstantData {
+                        domain_min,
+                        domain_max,
+                        cell_size,
+                        grid,
+                        h: SMOOTHING_RADIUS,
+                        mass: self.mass,
+                        viscosity: self.viscosity,
+                        surface_tension: self.surface_tension,
+                        gravity: self.gravity,
+                        dt: TIME_STEP,
+                        count: self.count,
+                        bucket_count: self.bucket_count,
+                    },
+                )
+                .context("unable to dispatch the fluid force pass")?;
+            self.current = 1 - self.current;
+        }
+
+        builder
+            .build()
+            .context("unable to build the fluid command buffer")
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let projection = camera.view_projection();
+
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the fluid")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        let mut cull = Cull::frustum(&projection);
+        let visible = cull.is_visible(&self.domain);
+        self.culled.set(cull.stats());
+        if !visible {
+            return builder
+                .build()
+                .context("unable to build the command buffer");
+        }
+
+        let buffers: Vec<Arc<DynBuffer>> =
+            vec![self.corners.clone(), self.positions[self.current].clone()];
+        builder
+            .draw(
+                self.graphics_pipeline.clone(),
+                &DynamicState::none(),
+                buffers,
+                (),
+                vertex_shader::ty::PushConstantData {
+                    projection,
+                    radius: SMOOTHING_RADIUS / 4.0,
+                },
+            )
+            .context("unable to draw the fluid")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn culled(&self) -> CullStats {
+        self.culled.get()
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.graphics_pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn params(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("viscosity", self.viscosity),
+            ("surface_tension", self.surface_tension),
+            ("rest_density", self.rest_density),
+            ("gravity", self.gravity),
+        ]
+    }
+
+    #[cfg(feature = "snapshot")]
+    fn set_param(&mut self, name: &str, value: f32) {
+        match name {
+            "viscosity" => self.set_viscosity(value),
+            "surface_tension" => self.set_surface_tension(value),
+            "rest_density" => self.set_rest_density(value),
+            "gravity" => self.set_gravity(value),
+            _ => log::warn!("the fluid simulation has no {:?}", name),
+        }
+    }
+}
+
+/// Stack the particles into a block in the lower corner of the domain. The
+/// block grows upwards once it fills the floor.
+fn dam_break(
+    count: usize,
+    domain: &Bounds,
+    spacing: f32,
+) -> Vec<FluidParticle> {
+    let (min, _) = domain.corners();
+    let extent = domain.extent();
+    let side = (count as f32).cbrt().ceil() as usize;
+    let columns = side.min((extent[0] / spacing) as usize).max(1);
+    let depth = side.min((extent[2] / spacing) as usize).max(1);
+
+    (0..count)
+        .map(|i| {
+            let x = i % columns;
+            let z = (i / columns) % depth;
+            let y = i / (columns * depth);
+            FluidParticle {
+                position: [
+                    min[0] + (x as f32 + 0.5) * spacing,
+                    min[1] + (y as f32 + 0.5) * spacing,
+                    min[2] + (z as f32 + 0.5) * spacing,
+                    0.0,
+                ],
+            }
+        })
+        .collect()
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fluid vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the fluid fragment shader")?;
+
+    let dimensions = [swapchain_extent[0] as f32, swapchain_extent[1] as f32];
+    let viewport = Viewport {
+        dimensions,
+        origin: [0.0, 0.0],
+        depth_range: 0.0..1.0,
+    };
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input(OneVertexOneInstanceDefinition::<
+            BillboardCorner,
+            FluidParticle,
+        >::new())
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![viewport])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the fluid pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the fluid graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod hash_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            const uint BUCKET_CAPACITY = 32;
+
+            layout(set = 0, binding = 0) readonly buffer Positions {
+                vec4 positions[];
+            } src;
+
+            layout(set = 0, binding = 1) buffer BucketCounts {
+                uint counts[];
+            } buckets;
+
+            layout(set = 0, binding = 2) writeonly buffer BucketEntries {
+                uint entries[];
+            } bucket_entries;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 domain_min;
+                vec4 cell_size;
+                uvec4 grid;
+                uint count;
+                uint bucket_count;
+            } pc;
+
+            ivec3 cell_of(vec3 p) {
+                vec3 local = (p - pc.domain_min.xyz) / pc.cell_size.xyz;
+                ivec3 cell = ivec3(floor(local));
+                return clamp(cell, ivec3(0), ivec3(pc.grid.xyz) - 1);
+            }
+
+            uint hash(ivec3 cell) {
+                uvec3 c = uvec3(cell);
+                uint h = c.x * 73856093u;
+                h ^= c.y * 19349663u;
+                h ^= c.z * 83492791u;
+                return h % pc.bucket_count;
+            }
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.count) {
+                    return;
+                }
+
+                uint bucket = hash(cell_of(src.positions[index].xyz));
+                uint slot = atomicAdd(buckets.counts[bucket], 1);
+                if (slot < BUCKET_CAPACITY) {
+                    uint entry = bucket * BUCKET_CAPACITY + slot;
+                    bucket_entries.entries[entry] = index;
+                }
+            }
+            "#
+    }
+}
+
+mod density_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            const uint BUCKET_CAPACITY = 32;
+            const float PI = 3.14159265;
+
+            layout(set = 0, binding = 0) readonly buffer Positions {
+                vec4 positions[];
+            } src;
+
+            layout(set = 0, binding = 1) readonly buffer BucketCounts {
+                uint counts[];
+            } buckets;
+
+            layout(set = 0, binding = 2) readonly buffer BucketEntries {
+                uint entries[];
+            } bucket_entries;
+
+            layout(set = 0, binding = 3) writeonly buffer Densities {
+                float densities[];
+            } dst_density;
+
+            layout(set = 0, binding = 4) writeonly buffer Pressures {
+                float pressures[];
+            } dst_pressure;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 domain_min;
+                vec4 domain_max;
+                vec4 cell_size;
+                uvec4 grid;
+                float h;
+                float mass;
+                float rest_density;
+                float stiffness;
+                uint count;
+                uint bucket_count;
+            } pc;
+
+            ivec3 cell_of(vec3 p) {
+                vec3 local = (p - pc.domain_min.xyz) / pc.cell_size.xyz;
+                ivec3 cell = ivec3(floor(local));
+                return clamp(cell, ivec3(0), ivec3(pc.grid.xyz) - 1);
+            }
+
+            uint hash(ivec3 cell) {
+                uvec3 c = uvec3(cell);
+                uint h = c.x * 73856093u;
+                h ^= c.y * 19349663u;
+                h ^= c.z * 83492791u;
+                return h % pc.bucket_count;
+            }
+
+            // the shortest vector between two points, wrapping around the
+            // domain when the boundary is periodic
+            vec3 separation(vec3 from, vec3 to) {
+                vec3 delta = to - from;
+                if (pc.grid.w != 0) {
+                    vec3 extent = pc.domain_max.xyz - pc.domain_min.xyz;
+                    delta -= extent * round(delta / extent);
+                }
+                return delta;
+            }
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.count) {
+                    return;
+                }
+
+                vec3 p = src.positions[index].xyz;
+                ivec3 home = cell_of(p);
+                ivec3 grid = ivec3(pc.grid.xyz);
+                float h2 = pc.h * pc.h;
+                float poly6 = 315.0 / (64.0 * PI * pow(pc.h, 9.0));
+
+                float density = 0.0;
+                for (int dz = -1; dz <= 1; dz++) {
+                for (int dy = -1; dy <= 1; dy++) {
+                for (int dx = -1; dx <= 1; dx++) {
+                    ivec3 cell = home + ivec3(dx, dy, dz);
+                    if (pc.grid.w != 0) {
+                        cell = (cell + grid) % grid;
+                    } else if (any(lessThan(cell, ivec3(0)))
+                            || any(greaterThanEqual(cell, grid))) {
+                        continue;
+                    }
+
+                    uint bucket = hash(cell);
+                    uint entries = min(buckets.counts[bucket], BUCKET_CAPACITY);
+                    for (uint i = 0; i < entries; i++) {
+                        uint entry = bucket * BUCKET_CAPACITY + i;
+                        uint other = bucket_entries.entries[entry];
+                        vec3 q = src.positions[other].xyz;
+
+                        // buckets are shared by every cell with the same hash
+                        if (cell_of(q) != cell) {
+                            continue;
+                        }
+
+                        vec3 delta = separation(p, q);
+                        float r2 = dot(delta, delta);
+                        if (r2 < h2) {
+                            float w = h2 - r2;
+                            density += pc.mass * poly6 * w * w * w;
+                        }
+                    }
+                }
+                }
+                }
+
+                dst_density.densities[index] = density;
+                dst_pressure.pressures[index] =
+                    max(pc.stiffness * (density - pc.rest_density), 0.0);
+            }
+            "#
+    }
+}
+
+mod force_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 64) in;
+
+            const uint BUCKET_CAPACITY = 32;
+            const float PI = 3.14159265;
+
+            // the color field gradient must be at least this long before
+            // surface tension applies
+            const float SURFACE_THRESHOLD = 7.065;
+
+            // the fraction of a particle's speed kept when it bounces off of
+            // a wall
+            const float RESTITUTION = 0.5;
+
+            layout(set = 0, binding = 0) readonly buffer Positions {
+                vec4 positions[];
+            } src;
+
+            layout(set = 0, binding = 1) readonly buffer Velocities {
+                vec4 velocities[];
+            } src_velocity;
+
+            layout(set = 0, binding = 2) readonly buffer BucketCounts {
+                uint counts[];
+            } buckets;
+
+            layout(set = 0, binding = 3) readonly buffer BucketEntries {
+                uint entries[];
+            } bucket_entries;
+
+            layout(set = 0, binding = 4) readonly buffer Densities {
+                float densities[];
+            } density;
+
+            layout(set = 0, binding = 5) readonly buffer Pressures {
+                float pressures[];
+            } pressure;
+
+            layout(set = 0, binding = 6) writeonly buffer DestPositions {
+                vec4 positions[];
+            } dst;
+
+            layout(set = 0, binding = 7) writeonly buffer DestVelocities {
+                vec4 velocities[];
+            } dst_velocity;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 domain_min;
+                vec4 domain_max;
+                vec4 cell_size;
+                uvec4 grid;
+                float h;
+                float mass;
+                float viscosity;
+                float surface_tension;
+                float gravity;
+                float dt;
+                uint count;
+                uint bucket_count;
+            } pc;
+
+            ivec3 cell_of(vec3 p) {
+                vec3 local = (p - pc.domain_min.xyz) / pc.cell_size.xyz;
+                ivec3 cell = ivec3(floor(local));
+                return clamp(cell, ivec3(0), ivec3(pc.grid.xyz) - 1);
+            }
+
+            uint hash(ivec3 cell) {
+                uvec3 c = uvec3(cell);
+                uint h = c.x * 73856093u;
+                h ^= c.y * 19349663u;
+                h ^= c.z * 83492791u;
+                return h % pc.bucket_count;
+            }
+
+            vec3 separation(vec3 from, vec3 to) {
+                vec3 delta = to - from;
+                if (pc.grid.w != 0) {
+                    vec3 extent = pc.domain_max.xyz - pc.domain_min.xyz;
+                    delta -= extent * round(delta / extent);
+                }
+                return delta;
+            }
+
+            void main() {
+                uint index = gl_GlobalInvocationID.x;
+                if (index >= pc.count) {
+                    return;
+                }
+
+                vec3 p = src.positions[index].xyz;
+                vec3 v = src_velocity.velocities[index].xyz;
+                float rho = density.densities[index];
+                float pressure_i = pressure.pressures[index];
+
+                ivec3 home = cell_of(p);
+                ivec3 grid = ivec3(pc.grid.xyz);
+                float h = pc.h;
+                float h2 = h * h;
+                float spiky = 45.0 / (PI * pow(h, 6.0));
+                float poly6 = 945.0 / (32.0 * PI * pow(h, 9.0));
+
+                vec3 pressure_acc = vec3(0.0);
+                vec3 viscosity_acc = vec3(0.0);
+                vec3 color_normal = vec3(0.0);
+                float color_laplacian = 0.0;
+
+                for (int dz = -1; dz <= 1; dz++) {
+                for (int dy = -1; dy <= 1; dy++) {
+                for (int dx = -1; dx <= 1; dx++) {
+                    ivec3 cell = home + ivec3(dx, dy, dz);
+                    if (pc.grid.w != 0) {
+                        cell = (cell + grid) % grid;
+                    } else if (any(lessThan(cell, ivec3(0)))
+                            || any(greaterThanEqual(cell, grid))) {
+                        continue;
+                    }
+
+                    uint bucket = hash(cell);
+                    uint entries = min(buckets.counts[bucket], BUCKET_CAPACITY);
+                    for (uint i = 0; i < entries; i++) {
+                        uint entry = bucket * BUCKET_CAPACITY + i;
+                        uint other = bucket_entries.entries[entry];
+                        vec3 q = src.positions[other].xyz;
+                        if (cell_of(q) != cell) {
+                            continue;
+                        }
+
+                        // points from the neighbor towards this particle
+                        vec3 d = -separation(p, q);
+                        float r2 = dot(d, d);
+                        if (r2 >= h2) {
+                            continue;
+                        }
+
+                        float rho_j = density.densities[other];
+                        float volume = pc.mass / rho_j;
+                        float w = h2 - r2;
+                        color_laplacian +=
+                            volume * -poly6 * w * (3.0 * h2 - 7.0 * r2);
+                        if (other == index || r2 == 0.0) {
+                            continue;
+                        }
+
+                        float r = sqrt(r2);
+                        float shared_pressure =
+                            (pressure_i + pressure.pressures[other]) / 2.0;
+                        pressure_acc += pc.mass * shared_pressure
+                            / (rho * rho_j) * spiky * (h - r) * (h - r)
+                            * (d / r);
+
+                        vec3 v_j = src_velocity.velocities[other].xyz;
+                        viscosity_acc += pc.viscosity * pc.mass * (v_j - v)
+                            / (rho * rho_j) * spiky * (h - r);
+
+                        color_normal += volume * -poly6 * w * w * d;
+                    }
+                }
+                }
+                }
+
+                vec3 surface_acc = vec3(0.0);
+                float normal_length = length(color_normal);
+                if (normal_length > SURFACE_THRESHOLD) {
+                    surface_acc = -pc.surface_tension * color_laplacian
+                        * (color_normal / normal_length) / rho;
+                }
+
+                vec3 acceleration = pressure_acc + viscosity_acc + surface_acc
+                    + vec3(0.0, pc.gravity, 0.0);
+                v += acceleration * pc.dt;
+                p += v * pc.dt;
+
+                vec3 lo = pc.domain_min.xyz;
+                vec3 hi = pc.domain_max.xyz;
+                if (pc.grid.w != 0) {
+                    p = lo + mod(p - lo, hi - lo);
+                } else {
+                    for (int axis = 0; axis < 3; axis++) {
+                        if (p[axis] < lo[axis]) {
+                            p[axis] = lo[axis];
+                            v[axis] = abs(v[axis]) * RESTITUTION;
+                        } else if (p[axis] > hi[axis]) {
+                            p[axis] = hi[axis];
+                            v[axis] = -abs(v[axis]) * RESTITUTION;
+                        }
+                    }
+                }
+
+                dst.positions[index] = vec4(p, 0.0);
+                dst_velocity.velocities[index] = vec4(v, 0.0);
+            }
+            "#
+    }
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 corner;
+            layout(location = 1) in vec4 position;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 projection;
+                float radius;
+            } pc;
+
+            layout(location = 0) out vec2 vertCorner;
+
+            void main() {
+                vertCorner = corner;
+
+                // billboards always face the camera, so the corner offset is
+                // scaled in clip space
+                vec2 scale = vec2(pc.projection[0][0], pc.projection[1][1]);
+                vec4 center = pc.projection * vec4(position.xyz, 1.0);
+                vec2 offset = corner * scale * pc.radius * center.w;
+                gl_Position = center + vec4(offset, 0.0, 0.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragCorner;
+            layout(location = 0) out vec4 outColor;
+
+            const vec3 LIGHT = vec3(0.3, 0.6, 0.74);
+
+            void main() {
+                float r2 = dot(fragCorner, fragCorner);
+                if (r2 > 1.0) {
+                    discard;
+                }
+
+                // shade the billboard as the front half of a sphere
+                vec3 normal = vec3(fragCorner, sqrt(1.0 - r2));
+                float diffuse = max(dot(normal, LIGHT), 0.0);
+                vec3 color = vec3(0.15, 0.45, 0.85);
+                outColor = vec4(color * (0.25 + 0.75 * diffuse), 1.0);
+            }
+            "#
+    }
+}
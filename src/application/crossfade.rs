@@ -0,0 +1,199 @@
+use super::fullscreen::{self, FullscreenVertex};
+use super::offscreen::OffscreenTarget;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// The target the outgoing scene is rendered into.
+pub const OUTGOING: usize = 0;
+
+/// The target the incoming scene is rendered into.
+pub const INCOMING: usize = 1;
+
+/// Blends from one scene to another while switching scenes.
+///
+/// Both scenes render into their own image at the scene extent, then a
+/// fullscreen pass mixes the two in place of either scene. Both targets use
+/// the display's render pass, so the scenes draw into them without changes.
+pub struct Crossfade {
+    targets: [OffscreenTarget; 2],
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl Crossfade {
+    pub fn new(display: &Display) -> Result<Self> {
+        let targets = create_targets(display)?;
+        let pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        let descriptor_set =
+            create_descriptor_set(display, &pipeline, &targets)?;
+        Ok(Self {
+            targets,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// Build a primary command buffer which renders one scene's secondary
+    /// command buffers into its target, `OUTGOING` or `INCOMING`.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        target: usize,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.targets[target].render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a secondary command buffer which draws the two scenes mixed,
+    /// showing only the outgoing scene at 0 and only the incoming one at 1.
+    pub fn draw(
+        &self,
+        display: &Display,
+        mix: f32,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the crossfade")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                fragment_shader::ty::PushConstantData { amount: mix },
+            )
+            .context("unable to draw the crossfade")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let targets = create_targets(display)?;
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set =
+            create_descriptor_set(display, &self.pipeline, &targets)?;
+        self.targets = targets;
+        Ok(())
+    }
+}
+
+fn create_targets(display: &Display) -> Result<[OffscreenTarget; 2]> {
+    Ok([
+        OffscreenTarget::with_extent(display, display.scene_extent())?,
+        OffscreenTarget::with_extent(display, display.scene_extent())?,
+    ])
+}
+
+fn create_descriptor_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    targets: &[OffscreenTarget; 2],
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let sampler =
+        Sampler::simple_repeat_linear_no_mipmap(display.device.clone());
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("crossfade pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(
+                targets[OUTGOING].image.clone(),
+                sampler.clone(),
+            )?
+            .add_sampled_image(targets[INCOMING].image.clone(), sampler)?
+            .build()
+            .context("unable to create the crossfade descriptors")?,
+    ))
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    scene_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the crossfade fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(scene_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the crossfade pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the crossfade graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D outgoing;
+            layout(set = 0, binding = 1) uniform sampler2D incoming;
+
+            layout(push_constant) uniform PushConstantData {
+                float amount;
+            } pc;
+
+            void main() {
+                outColor = mix(
+                    texture(outgoing, fragUv),
+                    texture(incoming, fragUv),
+                    pc.amount
+                );
+            }
+            "#
+    }
+}
@@ -0,0 +1,385 @@
+use std::ops::AddAssign;
+
+/// A column-major 4x4 matrix, the same layout as a glsl `mat4`.
+pub type Mat4 = [[f32; 4]; 4];
+
+/// Bounding volumes which can be tested against a culling volume.
+///
+/// When culling against 2D screen bounds only the x and y components are
+/// considered, spheres become circles.
+#[derive(Debug, Copy, Clone)]
+pub enum Bounds {
+    Aabb { min: [f32; 3], max: [f32; 3] },
+    Sphere { center: [f32; 3], radius: f32 },
+}
+
+impl Bounds {
+    /// The smallest and largest corners of the axis-aligned box around the
+    /// bounds.
+    pub fn corners(&self) -> ([f32; 3], [f32; 3]) {
+        match *self {
+            Bounds::Aabb { min, max } => (min, max),
+            Bounds::Sphere { center, radius } => (
+                [center[0] - radius, center[1] - radius, center[2] - radius],
+                [center[0] + radius, center[1] + radius, center[2] + radius],
+            ),
+        }
+    }
+
+    /// The length of the box around the bounds along each axis.
+    pub fn extent(&self) -> [f32; 3] {
+        let (min, max) = self.corners();
+        [max[0] - min[0], max[1] - min[1], max[2] - min[2]]
+    }
+}
+
+/// How many bounds were tested and rejected by a cull, or by every cull
+/// while drawing a frame once they're added together.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct CullStats {
+    pub tested: usize,
+    pub rejected: usize,
+}
+
+impl AddAssign for CullStats {
+    fn add_assign(&mut self, other: Self) {
+        self.tested += other.tested;
+        self.rejected += other.rejected;
+    }
+}
+
+/// An axis-aligned rectangle in 2D space.
+#[derive(Debug, Copy, Clone)]
+pub struct Rect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Rect {
+    /// The full screen in normalized device coordinates.
+    pub fn ndc() -> Self {
+        Self {
+            min: [-1.0, -1.0],
+            max: [1.0, 1.0],
+        }
+    }
+
+    /// True when the bounds overlap the rectangle. Bounds which exactly
+    /// touch an edge are considered visible.
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        match *bounds {
+            Bounds::Aabb { min, max } => {
+                min[0] <= self.max[0]
+                    && max[0] >= self.min[0]
+                    && min[1] <= self.max[1]
+                    && max[1] >= self.min[1]
+            }
+            Bounds::Sphere { center, radius } => {
+                let closest_x = center[0].max(self.min[0]).min(self.max[0]);
+                let closest_y = center[1].max(self.min[1]).min(self.max[1]);
+                let dx = center[0] - closest_x;
+                let dy = center[1] - closest_y;
+                dx * dx + dy * dy <= radius * radius
+            }
+        }
+    }
+}
+
+/// A plane where `normal . p + distance == 0`. The normal points into the
+/// visible half-space.
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: [f32; 3],
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: [f32; 4]) -> Self {
+        let [a, b, c, d] = row;
+        let length = (a * a + b * b + c * c).sqrt();
+        if length > 0.0 {
+            Self {
+                normal: [a / length, b / length, c / length],
+                distance: d / length,
+            }
+        } else {
+            Self {
+                normal: [a, b, c],
+                distance: d,
+            }
+        }
+    }
+
+    /// The signed distance from the plane to a point.
+    pub fn signed_distance(&self, point: [f32; 3]) -> f32 {
+        self.normal[0] * point[0]
+            + self.normal[1] * point[1]
+            + self.normal[2] * point[2]
+            + self.distance
+    }
+}
+
+/// The six planes which bound a view volume.
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum planes from a combined view-projection matrix.
+    ///
+    /// Vulkan clip space keeps `-w <= x <= w`, `-w <= y <= w`, and
+    /// `0 <= z <= w`, so the near plane is the z row alone rather than
+    /// `w + z` as it would be with OpenGL conventions.
+    pub fn from_view_projection(matrix: &Mat4) -> Self {
+        let row =
+            |i: usize| [matrix[0][i], matrix[1][i], matrix[2][i], matrix[3][i]];
+        let add = |a: [f32; 4], b: [f32; 4]| {
+            [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]
+        };
+        let sub = |a: [f32; 4], b: [f32; 4]| {
+            [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]]
+        };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_row(add(w, x)), // left
+                Plane::from_row(sub(w, x)), // right
+                Plane::from_row(add(w, y)), // top
+                Plane::from_row(sub(w, y)), // bottom
+                Plane::from_row(z),         // near
+                Plane::from_row(sub(w, z)), // far
+            ],
+        }
+    }
+
+    /// True when any part of the bounds is inside the frustum. Bounds which
+    /// exactly touch a plane are considered visible.
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        match *bounds {
+            Bounds::Aabb { min, max } => self.planes.iter().all(|plane| {
+                // the corner furthest along the plane's normal
+                let corner = |axis: usize| {
+                    if plane.normal[axis] >= 0.0 {
+                        max[axis]
+                    } else {
+                        min[axis]
+                    }
+                };
+                plane.signed_distance([corner(0), corner(1), corner(2)]) >= 0.0
+            }),
+            Bounds::Sphere { center, radius } => self
+                .planes
+                .iter()
+                .all(|plane| plane.signed_distance(center) >= -radius),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Volume {
+    Screen(Rect),
+    Frustum(Frustum),
+}
+
+/// Rejects draws which cannot be visible and counts how many were skipped.
+#[derive(Debug, Copy, Clone)]
+pub struct Cull {
+    volume: Volume,
+    stats: CullStats,
+}
+
+impl Cull {
+    /// Cull against 2D camera bounds.
+    pub fn screen(bounds: Rect) -> Self {
+        Self::new(Volume::Screen(bounds))
+    }
+
+    /// Cull against the frustum of a view-projection matrix.
+    pub fn frustum(view_projection: &Mat4) -> Self {
+        Self::new(Volume::Frustum(Frustum::from_view_projection(
+            view_projection,
+        )))
+    }
+
+    fn new(volume: Volume) -> Self {
+        Self {
+            volume,
+            stats: CullStats::default(),
+        }
+    }
+
+    /// Test the bounds and record the result.
+    pub fn is_visible(&mut self, bounds: &Bounds) -> bool {
+        let visible = match &self.volume {
+            Volume::Screen(rect) => rect.intersects(bounds),
+            Volume::Frustum(frustum) => frustum.intersects(bounds),
+        };
+        self.stats.tested += 1;
+        if !visible {
+            self.stats.rejected += 1;
+        }
+        visible
+    }
+
+    /// Keep only the items whose bounds are visible.
+    pub fn retain<T, F>(&mut self, items: &[T], bounds: F) -> Vec<T>
+    where
+        T: Clone,
+        F: Fn(&T) -> Bounds,
+    {
+        items
+            .iter()
+            .filter(|item| self.is_visible(&bounds(item)))
+            .cloned()
+            .collect()
+    }
+
+    /// The number of bounds tested and rejected since the cull was created.
+    pub fn stats(&self) -> CullStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: Mat4 = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    /// A right handed perspective projection with a 90 degree field of
+    /// view, looking down -z, which maps depth to Vulkan's 0..1.
+    fn perspective(near: f32, far: f32) -> Mat4 {
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, far / (near - far), -1.0],
+            [0.0, 0.0, near * far / (near - far), 0.0],
+        ]
+    }
+
+    fn assert_plane(plane: &Plane, normal: [f32; 3], distance: f32) {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5 * b.abs().max(1.0);
+        assert!(
+            normal.iter().zip(&plane.normal).all(|(&a, &b)| close(a, b))
+                && close(plane.distance, distance),
+            "{:?} should have the normal {:?} and distance {}",
+            plane,
+            normal,
+            distance
+        );
+    }
+
+    fn aabb(min: [f32; 3], max: [f32; 3]) -> Bounds {
+        Bounds::Aabb { min, max }
+    }
+
+    #[test]
+    fn clip_space_planes_keep_depth_between_0_and_1() {
+        let [left, right, top, bottom, near, far] =
+            Frustum::from_view_projection(&IDENTITY).planes;
+        assert_plane(&left, [1.0, 0.0, 0.0], 1.0);
+        assert_plane(&right, [-1.0, 0.0, 0.0], 1.0);
+        assert_plane(&top, [0.0, 1.0, 0.0], 1.0);
+        assert_plane(&bottom, [0.0, -1.0, 0.0], 1.0);
+        assert_plane(&near, [0.0, 0.0, 1.0], 0.0);
+        assert_plane(&far, [0.0, 0.0, -1.0], 1.0);
+    }
+
+    #[test]
+    fn perspective_planes_are_at_the_near_and_far_distances() {
+        let frustum = Frustum::from_view_projection(&perspective(0.1, 100.0));
+        let [left, right, .., near, far] = frustum.planes;
+        let diagonal = 0.5_f32.sqrt();
+        assert_plane(&left, [diagonal, 0.0, -diagonal], 0.0);
+        assert_plane(&right, [-diagonal, 0.0, -diagonal], 0.0);
+        assert_plane(&near, [0.0, 0.0, -1.0], -0.1);
+        assert_plane(&far, [0.0, 0.0, 1.0], 100.0);
+
+        // with OpenGL's w + z the near plane would be behind the camera
+        let point = |z: f32| Bounds::Sphere {
+            center: [0.0, 0.0, z],
+            radius: 0.0,
+        };
+        assert!(frustum.intersects(&point(-0.1)));
+        assert!(!frustum.intersects(&point(-0.05)));
+        assert!(frustum.intersects(&point(-100.0)));
+        assert!(!frustum.intersects(&point(-101.0)));
+    }
+
+    #[test]
+    fn boxes_exactly_on_a_frustum_plane_are_visible() {
+        let frustum = Frustum::from_view_projection(&IDENTITY);
+        let touching = [
+            aabb([1.0, 0.0, 0.5], [2.0, 0.5, 0.6]),
+            aabb([-2.0, 0.0, 0.5], [-1.0, 0.5, 0.6]),
+            aabb([0.0, 1.0, 0.5], [0.5, 2.0, 0.6]),
+            aabb([0.0, 0.0, -1.0], [0.5, 0.5, 0.0]),
+            aabb([0.0, 0.0, 1.0], [0.5, 0.5, 2.0]),
+        ];
+        for bounds in touching.iter() {
+            assert!(frustum.intersects(bounds), "{:?}", bounds);
+        }
+        let outside = [
+            aabb([1.001, 0.0, 0.5], [2.0, 0.5, 0.6]),
+            aabb([0.0, 0.0, -1.0], [0.5, 0.5, -0.001]),
+            aabb([0.0, 0.0, 1.001], [0.5, 0.5, 2.0]),
+        ];
+        for bounds in outside.iter() {
+            assert!(!frustum.intersects(bounds), "{:?}", bounds);
+        }
+
+        // a box around the whole frustum has no corner inside it
+        assert!(frustum.intersects(&aabb([-5.0; 3], [5.0; 3])));
+    }
+
+    #[test]
+    fn bounds_on_the_screen_edge_are_visible() {
+        let screen = Rect::ndc();
+        assert!(screen.intersects(&aabb([1.0, 0.0, 0.0], [2.0, 1.0, 0.0])));
+        assert!(!screen.intersects(&aabb([1.001, 0.0, 0.0], [2.0, 1.0, 0.0])));
+
+        // the closest point to the circles is the top right corner
+        let circle = |radius: f32| Bounds::Sphere {
+            center: [1.75, 2.0, 0.0],
+            radius,
+        };
+        assert!(screen.intersects(&circle(1.25)));
+        assert!(!screen.intersects(&circle(1.24)));
+    }
+
+    #[test]
+    fn spheres_have_a_box_around_them() {
+        let sphere = Bounds::Sphere {
+            center: [1.0, 2.0, 3.0],
+            radius: 0.5,
+        };
+        assert_eq!(sphere.corners(), ([0.5, 1.5, 2.5], [1.5, 2.5, 3.5]));
+        assert_eq!(sphere.extent(), [1.0; 3]);
+    }
+
+    #[test]
+    fn stats_add_up_across_culls() {
+        let mut cull = Cull::screen(Rect::ndc());
+        let visible = cull.retain(&[0.0, 0.5, 3.0, -4.0], |&x| {
+            aabb([x, 0.0, 0.0], [x, 0.0, 0.0])
+        });
+        assert_eq!(visible, [0.0, 0.5]);
+        let mut frame = CullStats::default();
+        frame += cull.stats();
+        frame += cull.stats();
+        assert_eq!(
+            frame,
+            CullStats {
+                tested: 8,
+                rejected: 4
+            }
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use vulkano::buffer::TypedBufferAccess;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder,
+    AutoCommandBufferBuilderContextError, BeginRenderPassError, BlitImageError,
+    BuildError, ClearColorImageError, CommandBuffer, CopyBufferImageError,
+    ExecuteCommandsError, SubpassContents,
+};
+use vulkano::device::Queue;
+use vulkano::format::{AcceptsPixels, ClearValue, Format};
+use vulkano::framebuffer::{FramebufferAbstract, RenderPassDescClearValues};
+use vulkano::image::ImageAccess;
+use vulkano::sampler::Filter;
+
+#[cfg(feature = "shader-reflect")]
+use spirv_reflect::ShaderModule as ReflectShaderModule;
+#[cfg(feature = "shader-reflect")]
+use vulkano::descriptor::pipeline_layout::PipelineLayoutDesc;
+
+#[cfg(debug_assertions)]
+use vulkano::image::ImageLayout;
+#[cfg(debug_assertions)]
+use vulkano::sync::{AccessCheckError, AccessError, PipelineStages};
+
+/// Records a primary command buffer while checking that every image it
+/// writes is separated by a pipeline barrier from every later read.
+///
+/// Commands recorded on the primary buffer itself are synchronized by
+/// vulkano, which inserts a barrier with the right stages and layout
+/// transition before any access that conflicts with an earlier one. What
+/// vulkano doesn't see is inside secondary command buffers: executing them
+/// is unsafe because their accesses are never checked against the primary.
+/// So the validator tracks each image the primary writes, in render pass
+/// attachments, clears, copies, and blits, and asks every executed secondary
+/// whether it touches one. A secondary which samples an attachment of the
+/// render pass it runs in, or an image written earlier in the same buffer,
+/// is a hazard no barrier protects against, and the validator panics with
+/// the stages and layouts the missing barrier would need.
+///
+/// The builder's other commands are reached through `Deref`. Secondary
+/// command buffers don't need wrapping since their accesses are checked
+/// where they're executed. Release builds record without checking.
+pub struct BarrierValidator {
+    builder: AutoCommandBufferBuilder,
+
+    #[cfg(debug_assertions)]
+    queue: Arc<Queue>,
+
+    // images written by earlier commands, and the attachments of the
+    // render pass being recorded
+    #[cfg(debug_assertions)]
+    writes: Vec<Write>,
+    #[cfg(debug_assertions)]
+    attachments: Vec<Write>,
+}
+
+/// An image written by the primary command buffer.
+#[cfg(debug_assertions)]
+struct Write {
+    target: Target,
+    command: &'static str,
+    stage: &'static str,
+    layout: ImageLayout,
+}
+
+#[cfg(debug_assertions)]
+enum Target {
+    Image(Arc<dyn ImageAccess + Send + Sync>),
+    Attachment(Arc<dyn FramebufferAbstract + Send + Sync>, usize),
+}
+
+impl BarrierValidator {
+    /// Wrap a primary command buffer builder. The queue is the one the
+    /// buffer will be submitted to.
+    pub fn wrap(builder: AutoCommandBufferBuilder, queue: &Arc<Queue>) -> Self {
+        #[cfg(not(debug_assertions))]
+        let _ = queue;
+        Self {
+            builder,
+            #[cfg(debug_assertions)]
+            queue: queue.clone(),
+            #[cfg(debug_assertions)]
+            writes: vec![],
+            #[cfg(debug_assertions)]
+            attachments: vec![],
+        }
+    }
+
+    pub fn begin_render_pass<F, C>(
+        &mut self,
+        framebuffer: F,
+        contents: SubpassContents,
+        clear_values: C,
+    ) -> Result<&mut Self, BeginRenderPassError>
+    where
+        F: FramebufferAbstract
+            + RenderPassDescClearValues<C>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        #[cfg(debug_assertions)]
+        {
+            let attachments = framebuffer.num_attachments();
+            let framebuffer: Arc<dyn FramebufferAbstract + Send + Sync> =
+                Arc::new(framebuffer.clone());
+            self.attachments = (0..attachments)
+                .map(|index| Write {
+                    layout: framebuffer
+                        .attachment_desc(index)
+                        .map(|desc| desc.final_layout)
+                        .unwrap_or(ImageLayout::ColorAttachmentOptimal),
+                    target: Target::Attachment(framebuffer.clone(), index),
+                    command: "a render pass",
+                    stage: "color attachment output",
+                })
+                .collect();
+        }
+        self.builder
+            .begin_render_pass(framebuffer, contents, clear_values)?;
+        Ok(self)
+    }
+
+    pub fn end_render_pass(
+        &mut self,
+    ) -> Result<&mut Self, AutoCommandBufferBuilderContextError> {
+        self.builder.end_render_pass()?;
+        #[cfg(debug_assertions)]
+        self.writes.append(&mut self.attachments);
+        Ok(self)
+    }
+
+    /// Execute secondary command buffers, checking each against the images
+    /// this buffer has written so far.
+    ///
+    /// # Safety
+    ///
+    /// Like `AutoCommandBufferBuilder::execute_commands_from_vec`, vulkano
+    /// doesn't synchronize the secondary buffers with the primary. In debug
+    /// builds reads of images written by the primary are caught.
+    pub unsafe fn execute_commands_from_vec<C>(
+        &mut self,
+        command_buffers: Vec<C>,
+    ) -> Result<&mut Self, ExecuteCommandsError>
+    where
+        C: CommandBuffer + Send + Sync + 'static,
+    {
+        #[cfg(debug_assertions)]
+        for (index, command_buffer) in command_buffers.iter().enumerate() {
+            self.check_secondary(index, command_buffer);
+        }
+        self.builder.execute_commands_from_vec(command_buffers)?;
+        Ok(self)
+    }
+
+    pub fn clear_color_image<I>(
+        &mut self,
+        image: I,
+        color: ClearValue,
+    ) -> Result<&mut Self, ClearColorImageError>
+    where
+        I: ImageAccess + Clone + Send + Sync + 'static,
+    {
+        #[cfg(debug_assertions)]
+        self.record_transfer(image.clone(), "a clear");
+        self.builder.clear_color_image(image, color)?;
+        Ok(self)
+    }
+
+    pub fn copy_buffer_to_image<S, D, Px>(
+        &mut self,
+        source: S,
+        destination: D,
+    ) -> Result<&mut Self, CopyBufferImageError>
+    where
+        S: TypedBufferAccess<Content = [Px]> + Send + Sync + 'static,
+        D: ImageAccess + Clone + Send + Sync + 'static,
+        Format: AcceptsPixels<Px>,
+    {
+        #[cfg(debug_assertions)]
+        self.record_transfer(destination.clone(), "a buffer to image copy");
+        self.builder.copy_buffer_to_image(source, destination)?;
+        Ok(self)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn copy_buffer_to_image_dimensions<S, D, Px>(
+        &mut self,
+        source: S,
+        destination: D,
+        offset: [u32; 3],
+        size: [u32; 3],
+        first_layer: u32,
+        num_layers: u32,
+        mipmap: u32,
+    ) -> Result<&mut Self, CopyBufferImageError>
+    where
+        S: TypedBufferAccess<Content = [Px]> + Send + Sync + 'static,
+        D: ImageAccess + Clone + Send + Sync + 'static,
+        Format: AcceptsPixels<Px>,
+    {
+        #[cfg(debug_assertions)]
+        self.record_transfer(destination.clone(), "a buffer to image copy");
+        self.builder.copy_buffer_to_image_dimensions(
+            source,
+            destination,
+            offset,
+            size,
+            first_layer,
+            num_layers,
+            mipmap,
+        )?;
+        Ok(self)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn blit_image<S, D>(
+        &mut self,
+        source: S,
+        source_top_left: [i32; 3],
+        source_bottom_right: [i32; 3],
+        source_base_array_layer: u32,
+        source_mip_level: u32,
+        destination: D,
+        destination_top_left: [i32; 3],
+        destination_bottom_right: [i32; 3],
+        destination_base_array_layer: u32,
+        destination_mip_level: u32,
+        layer_count: u32,
+        filter: Filter,
+    ) -> Result<&mut Self, BlitImageError>
+    where
+        S: ImageAccess + Send + Sync + 'static,
+        D: ImageAccess + Clone + Send + Sync + 'static,
+    {
+        #[cfg(debug_assertions)]
+        self.record_transfer(destination.clone(), "a blit");
+        self.builder.blit_image(
+            source,
+            source_top_left,
+            source_bottom_right,
+            source_base_array_layer,
+            source_mip_level,
+            destination,
+            destination_top_left,
+            destination_bottom_right,
+            destination_base_array_layer,
+            destination_mip_level,
+            layer_count,
+            filter,
+        )?;
+        Ok(self)
+    }
+
+    pub fn build(self) -> Result<AutoCommandBuffer, BuildError> {
+        self.builder.build()
+    }
+
+    #[cfg(debug_assertions)]
+    fn record_transfer<I>(&mut self, image: I, command: &'static str)
+    where
+        I: ImageAccess + Send + Sync + 'static,
+    {
+        // vulkano moves images without a fixed layout into the transfer
+        // layout for the copy
+        let layout = match image.initial_layout_requirement() {
+            ImageLayout::General => ImageLayout::General,
+            _ => ImageLayout::TransferDstOptimal,
+        };
+        self.writes.push(Write {
+            target: Target::Image(Arc::new(image)),
+            command,
+            stage: "transfer",
+            layout,
+        });
+    }
+
+    /// Panic if a secondary command buffer touches an image this buffer
+    /// wrote.
+    #[cfg(debug_assertions)]
+    fn check_secondary(&self, index: usize, secondary: &impl CommandBuffer) {
+        let writes = self
+            .attachments
+            .iter()
+            .map(|write| (write, true))
+            .chain(self.writes.iter().map(|write| (write, false)));
+        for (write, in_render_pass) in writes {
+            let image = match write.target.image() {
+                Some(image) => image,
+                None => continue,
+            };
+            // an undefined layout only asks whether the image is used
+            let stages = match secondary.check_image_access(
+                image,
+                ImageLayout::Undefined,
+                false,
+                &self.queue,
+            ) {
+                Ok(Some((stages, _))) => stage_names(stages),
+                Ok(None) => "an unknown stage".to_owned(),
+                Err(AccessCheckError::Unknown) => continue,
+                Err(AccessCheckError::Denied(error)) => format!("{}", error),
+            };
+            let expected = match secondary.check_image_access(
+                image,
+                write.layout,
+                false,
+                &self.queue,
+            ) {
+                Err(AccessCheckError::Denied(
+                    AccessError::UnexpectedImageLayout { allowed, .. },
+                )) => allowed,
+                _ => write.layout,
+            };
+            if in_render_pass {
+                panic!(
+                    "hazard: secondary command buffer {} reads attachment {} \
+                    of the render pass it runs in, at {}. No barrier can \
+                    separate the read from the attachment write, draw the \
+                    image in an earlier render pass",
+                    index,
+                    write.target.describe(),
+                    stages
+                );
+            }
+            panic!(
+                "hazard: secondary command buffer {} reads {} written by {} \
+                without a barrier. vulkano doesn't synchronize secondary \
+                command buffers, a barrier from the {} stage to {} with a \
+                layout transition from {:?} to {:?} is needed. Record the \
+                write in an earlier command buffer",
+                index,
+                write.target.describe(),
+                write.command,
+                write.stage,
+                stages,
+                write.layout,
+                expected
+            );
+        }
+    }
+}
+
+impl Deref for BarrierValidator {
+    type Target = AutoCommandBufferBuilder;
+
+    fn deref(&self) -> &Self::Target {
+        &self.builder
+    }
+}
+
+impl DerefMut for BarrierValidator {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.builder
+    }
+}
+
+#[cfg(debug_assertions)]
+impl Target {
+    fn image(&self) -> Option<&dyn ImageAccess> {
+        match self {
+            Target::Image(image) => Some(image.as_ref()),
+            Target::Attachment(framebuffer, index) => framebuffer
+                .attached_image_view(*index)
+                .map(|view| view.parent()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Target::Image(image) => {
+                let dimensions = image.dimensions();
+                format!(
+                    "a {}x{} {:?} image",
+                    dimensions.width(),
+                    dimensions.height(),
+                    image.format()
+                )
+            }
+            Target::Attachment(_, index) => format!("attachment {}", index),
+        }
+    }
+}
+
+/// The stages which are set, for hazard messages.
+#[cfg(debug_assertions)]
+fn stage_names(stages: PipelineStages) -> String {
+    let names = [
+        (stages.vertex_shader, "vertex shader"),
+        (stages.fragment_shader, "fragment shader"),
+        (stages.compute_shader, "compute shader"),
+        (stages.color_attachment_output, "color attachment output"),
+        (stages.transfer, "transfer"),
+        (stages.all_graphics, "all graphics"),
+        (stages.all_commands, "all commands"),
+    ];
+    let set: Vec<&str> = names
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|&(_, name)| name)
+        .collect();
+    if set.is_empty() {
+        format!("{:?}", stages)
+    } else {
+        set.join(" and ")
+    }
+}
+
+/// Warn about every binding `pipeline_layout` declares which the reflected
+/// shader never uses.
+///
+/// A forgotten uniform still takes a descriptor slot and has to be written
+/// and bound every time the pipeline is used. Only bindings the shader's
+/// `main` entry point actually reaches count as used, so a binding which
+/// is declared in the shader but never read is reported too.
+#[cfg(feature = "shader-reflect")]
+pub fn check_unused_bindings<L>(
+    reflected: &ReflectShaderModule,
+    pipeline_layout: &Arc<L>,
+) where
+    L: PipelineLayoutDesc + ?Sized,
+{
+    let used = match reflected.enumerate_descriptor_bindings(Some("main")) {
+        Ok(used) => used,
+        Err(error) => {
+            log::warn!("unable to reflect the shader's bindings: {}", error);
+            return;
+        }
+    };
+    for set in 0..pipeline_layout.num_sets() {
+        let bindings = pipeline_layout.num_bindings_in_set(set).unwrap_or(0);
+        for binding in 0..bindings {
+            let descriptor = match pipeline_layout.descriptor(set, binding) {
+                Some(descriptor) => descriptor,
+                None => continue,
+            };
+            let is_used = used.iter().any(|reflected| {
+                reflected.set as usize == set
+                    && reflected.binding as usize == binding
+            });
+            if !is_used {
+                log::warn!(
+                    "set {} binding {} ({:?}) is never used by the shader",
+                    set,
+                    binding,
+                    descriptor.ty
+                );
+            }
+        }
+    }
+}
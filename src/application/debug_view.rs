@@ -0,0 +1,640 @@
+use super::camera::Camera;
+use super::cull::Mat4;
+use super::debug::BarrierValidator;
+use super::fullscreen::{self, FullscreenVertex};
+use super::post_process::{DistanceTarget, DistanceVertex};
+use super::text::{Label, TextRenderer};
+use crate::display::{Display, Stage, Submitter};
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState, SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{
+    Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass,
+};
+use vulkano::image::{Dimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynFramebuffer = dyn FramebufferAbstract + Send + Sync;
+type DynPipeline = dyn GraphicsPipelineAbstract + Send + Sync;
+
+/// The format of the overdraw buffer, which counts the fragments drawn into
+/// each pixel.
+const OVERDRAW_FORMAT: Format = Format::R16Sfloat;
+
+/// The top left corner of the badge naming the view, in normalized device
+/// coordinates.
+const BADGE_POSITION: [f32; 2] = [-0.95, -0.95];
+
+/// The height of the badge's text in normalized device coordinates.
+const BADGE_HEIGHT: f32 = 0.05;
+
+/// What the frame shows in place of, or on top of, the final image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum DebugViewMode {
+    /// The frame as it's normally drawn.
+    #[default]
+    Final,
+
+    /// The depth buffer linearized to the distance from the near plane,
+    /// white at the near plane and black at the far plane.
+    Depth,
+
+    /// How many fragments were drawn into each pixel, from blue through
+    /// green and yellow to red.
+    Overdraw,
+
+    /// The edges of the meshes over the frame.
+    Wireframe,
+
+    /// The meshes colored by their normals.
+    Normals,
+}
+
+impl DebugViewMode {
+    /// The next mode, wrapping around, for cycling with a key.
+    pub fn next(self) -> Self {
+        match self {
+            DebugViewMode::Final => DebugViewMode::Depth,
+            DebugViewMode::Depth => DebugViewMode::Overdraw,
+            DebugViewMode::Overdraw => DebugViewMode::Wireframe,
+            DebugViewMode::Wireframe => DebugViewMode::Normals,
+            DebugViewMode::Normals => DebugViewMode::Final,
+        }
+    }
+
+    /// The name shown on the badge.
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugViewMode::Final => "FINAL",
+            DebugViewMode::Depth => "DEPTH",
+            DebugViewMode::Overdraw => "OVERDRAW",
+            DebugViewMode::Wireframe => "WIREFRAME",
+            DebugViewMode::Normals => "NORMALS",
+        }
+    }
+}
+
+/// A full screen pipeline and the images it reads.
+struct ViewPipeline {
+    pipeline: Arc<DynPipeline>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+/// Replaces the final image with the depth buffer or an overdraw heatmap,
+/// and names the active view in a badge.
+///
+/// Like the depth of field's distances, only the particles are drawn into
+/// the depth and overdraw buffers. The wireframe and normals views are drawn
+/// by the mesh renderer, so they only change the 3D meshes. Every full
+/// screen pipeline is built with the view, so switching views never waits
+/// on a pipeline.
+pub struct DebugView {
+    mode: DebugViewMode,
+    distance: DistanceTarget,
+    overdraw: OverdrawTarget,
+    pipelines: HashMap<DebugViewMode, ViewPipeline>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+    text: TextRenderer,
+}
+
+impl DebugView {
+    pub fn new(display: &Display) -> Result<Self> {
+        let extent = display.swapchain.dimensions();
+        let distance = DistanceTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            extent,
+            true,
+        )?;
+        let overdraw = OverdrawTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            extent,
+        )?;
+        Ok(Self {
+            mode: DebugViewMode::Final,
+            pipelines: create_view_pipelines(display, &distance, &overdraw)?,
+            distance,
+            overdraw,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+            text: TextRenderer::new(display)?,
+        })
+    }
+
+    pub fn mode(&self) -> DebugViewMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: DebugViewMode) {
+        self.mode = mode;
+    }
+
+    /// Add the passes the active view reads, then build the secondary
+    /// command buffers which draw the frame: `draw_commands` for the views
+    /// drawn by the scene itself, the view over the whole screen for the
+    /// others, and the badge.
+    pub fn draw(
+        &self,
+        display: &Display,
+        submitter: &mut Submitter,
+        draw_commands: Vec<AutoCommandBuffer>,
+        vertices: Vec<DistanceVertex>,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mvp = camera.screen_space();
+        let mut commands = match self.mode {
+            DebugViewMode::Depth => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "debug view depth",
+                    self.distance.render(
+                        &display.device,
+                        &display.graphics_queue,
+                        vertices,
+                        mvp,
+                    )?,
+                );
+                let [near, far] = camera.near_far();
+                vec![self.draw_view(
+                    display,
+                    depth_shader::ty::PushConstantData { near, far },
+                )?]
+            }
+            DebugViewMode::Overdraw => {
+                submitter.add(
+                    Stage::PostProcess,
+                    "debug view overdraw",
+                    self.overdraw.render(
+                        &display.device,
+                        &display.graphics_queue,
+                        vertices,
+                        mvp,
+                    )?,
+                );
+                vec![self.draw_view(display, ())?]
+            }
+            _ => draw_commands,
+        };
+        if self.mode != DebugViewMode::Final {
+            commands.push(self.text.draw(
+                display,
+                &[Label {
+                    text: self.mode.name(),
+                    position: BADGE_POSITION,
+                    height: BADGE_HEIGHT,
+                }],
+            )?);
+        }
+        Ok(commands)
+    }
+
+    /// Build a secondary command buffer which draws the active view's full
+    /// screen pipeline.
+    fn draw_view<Pc>(
+        &self,
+        display: &Display,
+        push_constants: Pc,
+    ) -> Result<AutoCommandBuffer> {
+        let view = self
+            .pipelines
+            .get(&self.mode)
+            .with_context(|| format!("no pipeline for {:?}", self.mode))?;
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the debug view")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                view.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                view.descriptor_set.clone(),
+                push_constants,
+            )
+            .context("unable to draw the debug view")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let extent = display.swapchain.dimensions();
+        self.distance = DistanceTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            extent,
+            true,
+        )?;
+        self.overdraw = OverdrawTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            extent,
+        )?;
+        self.pipelines =
+            create_view_pipelines(display, &self.distance, &self.overdraw)?;
+        self.text.rebuild_swapchain_resources(display)
+    }
+}
+
+/// A buffer which counts the fragments drawn into each pixel by adding one
+/// for every fragment.
+struct OverdrawTarget {
+    image: Arc<StorageImage<Format>>,
+    framebuffer: Arc<DynFramebuffer>,
+    pipeline: Arc<DynPipeline>,
+}
+
+impl OverdrawTarget {
+    fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        extent: [u32; 2],
+    ) -> Result<Self> {
+        let features = OVERDRAW_FORMAT
+            .properties(device.physical_device())
+            .optimal_tiling_features;
+        if !features.color_attachment_blend || !features.sampled_image {
+            bail!("the device cannot blend and sample an overdraw buffer");
+        }
+
+        let [width, height] = extent;
+        let image = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d { width, height },
+            OVERDRAW_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            Some(queue.family()),
+        )
+        .context("unable to create the overdraw image")?;
+        let render_pass: Arc<DynRenderPass> = Arc::new(
+            vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    overdraw: {
+                        load: Clear,
+                        store: Store,
+                        format: OVERDRAW_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [overdraw],
+                    depth_stencil: {}
+                }
+            )
+            .context("unable to create the overdraw render pass")?,
+        );
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())?
+                .build()
+                .context("unable to create the overdraw framebuffer")?,
+        );
+        let pipeline = create_overdraw_pipeline(device, extent, &render_pass)?;
+
+        Ok(Self {
+            image,
+            framebuffer,
+            pipeline,
+        })
+    }
+
+    /// Build a primary command buffer which clears the count and adds every
+    /// point's fragments to it.
+    fn render(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        vertices: Vec<DistanceVertex>,
+        mvp: Mat4,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                queue.family(),
+            )
+            .context("unable to create the overdraw command buffer builder")?,
+            &queue,
+        );
+        builder
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                SubpassContents::Inline,
+                vec![ClearValue::Float([0.0, 0.0, 0.0, 0.0])],
+            )
+            .context("unable to begin the overdraw render pass")?;
+        if !vertices.is_empty() {
+            let vertex_buffer = CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                vertices.into_iter(),
+            )
+            .context("unable to create the overdraw vertex buffer")?;
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![vertex_buffer],
+                    (),
+                    overdraw_vertex_shader::ty::PushConstantData { mvp },
+                )
+                .context("unable to draw the overdraw")?;
+        }
+        builder
+            .end_render_pass()
+            .context("unable to end the overdraw render pass")?;
+        builder
+            .build()
+            .context("unable to build the overdraw command buffer")
+    }
+}
+
+/// Add every fragment to what's already in the target.
+fn counting() -> AttachmentBlend {
+    AttachmentBlend {
+        enabled: true,
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::One,
+        color_destination: BlendFactor::One,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::One,
+        mask_red: true,
+        mask_green: true,
+        mask_blue: true,
+        mask_alpha: true,
+    }
+}
+
+fn create_overdraw_pipeline(
+    device: &Arc<Device>,
+    extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<DynPipeline>> {
+    let vert = overdraw_vertex_shader::Shader::load(device.clone())
+        .context("unable to load the overdraw vertex shader")?;
+    let frag = overdraw_fragment_shader::Shader::load(device.clone())
+        .context("unable to load the overdraw fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<DistanceVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        }])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_collective(counting())
+        .point_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the overdraw pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the overdraw graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+/// The full screen pipelines of the depth and overdraw views, with the
+/// buffers they show.
+fn create_view_pipelines(
+    display: &Display,
+    distance: &DistanceTarget,
+    overdraw: &OverdrawTarget,
+) -> Result<HashMap<DebugViewMode, ViewPipeline>> {
+    let device = &display.device;
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let start = || -> Result<_> {
+        Ok(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<FullscreenVertex>()
+            .vertex_shader(vert.main_entry_point(), ())
+            .viewports(vec![fullscreen::viewport(
+                display.swapchain.dimensions(),
+            )])
+            .depth_clamp(false)
+            .polygon_mode_fill()
+            .line_width(1.0)
+            .depth_write(false)
+            .sample_shading_disabled()
+            .blend_pass_through()
+            .triangle_list()
+            .render_pass(
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("could not create the debug view subpass")?,
+            ))
+    };
+    let nearest = Sampler::new(
+        device.clone(),
+        Filter::Nearest,
+        Filter::Nearest,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the debug view sampler")?;
+
+    let depth_shader = depth_shader::Shader::load(device.clone())
+        .context("unable to load the depth view shader")?;
+    let depth: Arc<DynPipeline> = Arc::new(
+        start()?
+            .fragment_shader(depth_shader.main_entry_point(), ())
+            .build(device.clone())
+            .context("could not create the depth view pipeline")?,
+    );
+    let layout = depth
+        .descriptor_set_layout(0)
+        .context("depth view pipeline has no descriptor set layout")?;
+    let depth_set = Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(distance.depth.clone(), nearest.clone())?
+            .build()
+            .context("unable to create the depth view descriptors")?,
+    );
+
+    let overdraw_shader = heatmap_shader::Shader::load(device.clone())
+        .context("unable to load the overdraw view shader")?;
+    let heatmap: Arc<DynPipeline> = Arc::new(
+        start()?
+            .fragment_shader(overdraw_shader.main_entry_point(), ())
+            .build(device.clone())
+            .context("could not create the overdraw view pipeline")?,
+    );
+    let layout = heatmap
+        .descriptor_set_layout(0)
+        .context("overdraw view pipeline has no descriptor set layout")?;
+    let heatmap_set = Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(overdraw.image.clone(), nearest)?
+            .build()
+            .context("unable to create the overdraw view descriptors")?,
+    );
+
+    let mut pipelines = HashMap::new();
+    pipelines.insert(
+        DebugViewMode::Depth,
+        ViewPipeline {
+            pipeline: depth,
+            descriptor_set: depth_set,
+        },
+    );
+    pipelines.insert(
+        DebugViewMode::Overdraw,
+        ViewPipeline {
+            pipeline: heatmap,
+            descriptor_set: heatmap_set,
+        },
+    );
+    Ok(pipelines)
+}
+
+mod overdraw_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec3 pos;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 mvp;
+            } pc;
+
+            void main() {
+                gl_Position = pc.mvp * vec4(pos, 1.0);
+
+                // the same size as the particles
+                gl_PointSize = 64.0;
+            }
+            "#
+    }
+}
+
+mod overdraw_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) out float outCount;
+
+            void main() {
+                outCount = 1.0;
+            }
+            "#
+    }
+}
+
+mod depth_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D depth;
+
+            layout(push_constant) uniform PushConstantData {
+                float near;
+                float far;
+            } pc;
+
+            void main() {
+                float d = texture(depth, fragUv).r;
+
+                // nothing was drawn here
+                if (d >= 1.0) {
+                    outColor = vec4(0.05, 0.05, 0.15, 1.0);
+                    return;
+                }
+
+                // the camera is orthographic, so the distance is linear in
+                // depth
+                float distance = mix(pc.near, pc.far, d);
+                float shade = 1.0 - (distance - pc.near) / (pc.far - pc.near);
+
+                // a darker line every world unit
+                float line = step(0.97, fract(distance));
+                outColor = vec4(vec3(shade) * (1.0 - 0.5 * line), 1.0);
+            }
+            "#
+    }
+}
+
+mod heatmap_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D overdraw;
+
+            // the count which reaches red
+            const float MAX_OVERDRAW = 8.0;
+
+            // the colors from a single fragment up to MAX_OVERDRAW, pixels
+            // with no fragments are black
+            const vec3 RAMP[4] = vec3[](
+                vec3(0.0, 0.2, 1.0),
+                vec3(0.0, 1.0, 0.3),
+                vec3(1.0, 1.0, 0.0),
+                vec3(1.0, 0.1, 0.0)
+            );
+
+            void main() {
+                float count = texture(overdraw, fragUv).r;
+                float t = clamp(count / MAX_OVERDRAW, 0.0, 1.0) * 4.0;
+                vec3 color = vec3(0.0);
+                for (int i = 0; i < 4; i++) {
+                    color = mix(color, RAMP[i], clamp(t - float(i), 0.0, 1.0));
+                }
+                outColor = vec4(color, 1.0);
+            }
+            "#
+    }
+}
@@ -0,0 +1,41 @@
+// Blue noise dithering for shaders which write 8 bit color targets.
+//
+// Quantizing a smooth gradient to 8 bits leaves visible bands, especially in
+// dark sRGB colors. Offsetting each pixel by up to half a quantization step
+// of blue noise before the hardware rounds it trades the bands for fine,
+// even grain which the eye averages away.
+
+#ifndef DITHER_GLSL
+#define DITHER_GLSL
+
+// The blue noise texel for a pixel, the texture tiles across the screen.
+ivec2 dither_texel(vec2 frag_coord, ivec2 noise_size) {
+    return ivec2(frag_coord) % noise_size;
+}
+
+vec3 dither_linear_to_srgb(vec3 c) {
+    vec3 low = c * 12.92;
+    vec3 high = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.0031308))));
+}
+
+vec3 dither_srgb_to_linear(vec3 c) {
+    vec3 low = c / 12.92;
+    vec3 high = pow((c + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, vec3(lessThanEqual(c, vec3(0.04045))));
+}
+
+// Dither a color before it is written to an 8 bit target.
+//
+// sRGB targets quantize after the hardware encodes the color, so the offset
+// is applied to the encoded value and the result is decoded again.
+vec3 dither(vec3 color, float noise, bool srgb_target) {
+    float offset = (noise - 0.5) / 255.0;
+    if (srgb_target) {
+        vec3 encoded = dither_linear_to_srgb(clamp(color, 0.0, 1.0));
+        return dither_srgb_to_linear(clamp(encoded + offset, 0.0, 1.0));
+    }
+    return color + offset;
+}
+
+#endif
@@ -0,0 +1,290 @@
+use super::frame_stats::FrameReport;
+use super::fullscreen::{self, FullscreenVertex};
+use super::offscreen::OffscreenTarget;
+use crate::display::{Display, MIN_RENDER_SCALE};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// The frame time the controller tries to hold, in milliseconds.
+const TARGET_FRAME_TIME: f32 = 1000.0 / 60.0;
+
+/// The scale is lowered when frames take this much longer than the target.
+const SLOW_FRAME: f32 = 1.15;
+
+/// The scale is raised when frames finish this much sooner than the target.
+const FAST_FRAME: f32 = 0.85;
+
+/// How many reports in a row must be outside the target before the scale
+/// changes.
+const PATIENCE: u32 = 2;
+
+/// How much the scale is lowered or raised at a time.
+const SCALE_STEP: f32 = 0.1;
+
+/// Picks the render scale from the measured frame times.
+///
+/// There is a dead band between `FAST_FRAME` and `SLOW_FRAME` times the
+/// target where the scale is left alone, and the frame time has to stay
+/// outside of it for `PATIENCE` reports, so a scale which lands right on the
+/// edge doesn't flip back and forth every second.
+#[derive(Debug, Copy, Clone)]
+pub struct ResolutionController {
+    /// A fixed scale used in place of the measured one.
+    pub pinned: Option<f32>,
+
+    scale: f32,
+    slow_reports: u32,
+    fast_reports: u32,
+}
+
+impl ResolutionController {
+    pub fn new(pinned: Option<f32>) -> Self {
+        Self {
+            pinned,
+            scale: pinned
+                .map_or(1.0, |scale| scale.clamp(MIN_RENDER_SCALE, 1.0)),
+            slow_reports: 0,
+            fast_reports: 0,
+        }
+    }
+
+    /// Consider the latest frame report. Returns the new scale when it
+    /// should change.
+    pub fn update(&mut self, report: FrameReport) -> Option<f32> {
+        let scale = match self.pinned {
+            Some(pinned) => pinned.clamp(MIN_RENDER_SCALE, 1.0),
+            None => self.measured_scale(report.frame_time),
+        };
+        if (scale - self.scale).abs() < f32::EPSILON {
+            return None;
+        }
+        self.scale = scale;
+        Some(scale)
+    }
+
+    fn measured_scale(&mut self, frame_time: f32) -> f32 {
+        if frame_time > TARGET_FRAME_TIME * SLOW_FRAME {
+            self.slow_reports += 1;
+            self.fast_reports = 0;
+        } else if frame_time < TARGET_FRAME_TIME * FAST_FRAME {
+            self.fast_reports += 1;
+            self.slow_reports = 0;
+        } else {
+            self.slow_reports = 0;
+            self.fast_reports = 0;
+        }
+
+        if self.slow_reports >= PATIENCE {
+            self.slow_reports = 0;
+            (self.scale - SCALE_STEP).max(MIN_RENDER_SCALE)
+        } else if self.fast_reports >= PATIENCE {
+            self.fast_reports = 0;
+            (self.scale + SCALE_STEP).min(1.0)
+        } else {
+            self.scale
+        }
+    }
+}
+
+/// Draws the scene below the swapchain's resolution and upscales it.
+///
+/// The scene is rendered at the display's scene extent, then stretched over
+/// the swapchain with bilinear filtering and a light sharpening pass to win
+/// back some of the detail. Everything drawn after the upscale, like the
+/// post-processing, runs at full resolution.
+pub struct DynamicResolution {
+    pub controller: ResolutionController,
+
+    /// How strongly the upscaled image is sharpened, 0 is plain bilinear.
+    pub sharpness: f32,
+
+    scene: OffscreenTarget,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl DynamicResolution {
+    pub fn new(display: &Display, pinned: Option<f32>) -> Result<Self> {
+        let scene =
+            OffscreenTarget::with_extent(display, display.scene_extent())?;
+        let pipeline = create_upscale_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let descriptor_set = create_upscale_set(display, &pipeline, &scene)?;
+        Ok(Self {
+            controller: ResolutionController::new(pinned),
+            sharpness: 0.25,
+            scene,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// Build a primary command buffer which renders the scene's secondary
+    /// command buffers at the scene extent.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.scene.render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a secondary command buffer which draws the upscaled scene over
+    /// the whole screen.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the upscale")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                upscale_shader::ty::PushConstantData {
+                    sharpness: self.sharpness.clamp(0.0, 1.0),
+                },
+            )
+            .context("unable to draw the upscaled scene")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    /// Rebuild the scene image, after the swapchain or the render scale
+    /// changed.
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let scene =
+            OffscreenTarget::with_extent(display, display.scene_extent())?;
+        self.pipeline = create_upscale_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set =
+            create_upscale_set(display, &self.pipeline, &scene)?;
+        self.scene = scene;
+        Ok(())
+    }
+}
+
+fn create_upscale_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    scene: &OffscreenTarget,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let sampler = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the upscale sampler")?;
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("upscale pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(scene.image.clone(), sampler)?
+            .build()
+            .context("unable to create the upscale descriptors")?,
+    ))
+}
+
+fn create_upscale_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = upscale_shader::Shader::load(device.clone())
+        .context("unable to load the upscale fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the upscale pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the upscale graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod upscale_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+
+            layout(push_constant) uniform PushConstantData {
+                float sharpness;
+            } pc;
+
+            void main() {
+                // bilinear upscale, then push the center away from the
+                // average of its neighbors one source texel away
+                vec2 texel = 1.0 / vec2(textureSize(scene, 0));
+                vec4 center = texture(scene, fragUv);
+                vec4 neighbors =
+                    texture(scene, fragUv + vec2(texel.x, 0.0))
+                    + texture(scene, fragUv - vec2(texel.x, 0.0))
+                    + texture(scene, fragUv + vec2(0.0, texel.y))
+                    + texture(scene, fragUv - vec2(0.0, texel.y));
+                vec4 sharpened =
+                    center + (center - neighbors * 0.25) * pc.sharpness;
+                outColor = clamp(sharpened, 0.0, 1.0);
+            }
+            "#
+    }
+}
@@ -0,0 +1,327 @@
+use super::camera::Camera;
+use super::meshes::{DrawList, Light, Material, Mesh};
+use super::transform::{Transform, TransformHierarchy, TransformId};
+use crate::display::Display;
+use anyhow::{Context, Result};
+
+#[cfg(feature = "bevy-ecs")]
+pub mod bevy_backend;
+#[cfg(feature = "specs")]
+pub mod specs_backend;
+
+/// The demo's shapes as the number of segments in each disc, where it sits,
+/// and how fast it spins.
+const DEMO_SHAPES: [(u32, [f32; 3], f32); 3] = [
+    (3, [-0.6, 0.0, 0.0], 1.0),
+    (4, [0.0, 0.0, 0.0], -0.5),
+    (6, [0.6, 0.0, 0.0], 0.25),
+];
+
+/// The demo's warm and cool lights.
+const DEMO_LIGHTS: [([f32; 3], PointLight); 2] = [
+    (
+        [-0.6, 0.3, 0.0],
+        PointLight {
+            color: [1.0, 0.6, 0.3],
+            intensity: 2.0,
+            range: 0.8,
+        },
+    ),
+    (
+        [0.6, -0.3, 0.0],
+        PointLight {
+            color: [0.3, 0.5, 1.0],
+            intensity: 2.0,
+            range: 0.8,
+        },
+    ),
+];
+
+/// The material every demo shape is drawn with.
+const DEMO_MATERIAL: Material = Material {
+    color: [0.9, 0.9, 0.9, 1.0],
+};
+
+/// Identifies a mesh added to a `World`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MeshId(usize);
+
+/// Identifies a material added to a `World`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MaterialId(usize);
+
+/// Draws a mesh at the entity's transform.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct MeshRef(pub MeshId);
+
+/// Shades the entity's mesh, meshes without a material are drawn white.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct MaterialRef(pub MaterialId);
+
+/// Lights the meshes within `range` of the entity's transform.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct PointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+/// Turns the entity's transform around the z axis, in radians per second.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct Spin(pub f32);
+
+/// Entities, along with the meshes and materials they refer to.
+///
+/// Entities are placed by a `Transform` component. Each frame
+/// `transform_system` resolves every transform, then `light_system` and
+/// `render_system` collect the lights and draws for a `MeshRenderer`. The
+/// first entity with a `Camera` component is the camera the scene is seen
+/// through.
+pub struct World {
+    pub entities: hecs::World,
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+    transforms: TransformHierarchy,
+    lights: Vec<Light>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self {
+            entities: hecs::World::new(),
+            meshes: vec![],
+            materials: vec![],
+            transforms: TransformHierarchy::default(),
+            lights: vec![],
+        }
+    }
+
+    /// A row of spinning shapes lit by a warm and a cool light.
+    pub fn demo(display: &Display) -> Result<Self> {
+        let mut world = Self::new();
+        let material = world.add_material(DEMO_MATERIAL);
+        for &(segments, translation, speed) in DEMO_SHAPES.iter() {
+            let mesh = world.add_mesh(Mesh::disc(&display.device, segments)?);
+            world.entities.spawn((
+                demo_transform(translation),
+                MeshRef(mesh),
+                MaterialRef(material),
+                Spin(speed),
+            ));
+        }
+        for &(translation, light) in DEMO_LIGHTS.iter() {
+            world
+                .entities
+                .spawn((Transform::from_translation(translation), light));
+        }
+        Ok(world)
+    }
+
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    /// The transforms resolved by the last `transform_system`.
+    pub fn transforms(&self) -> &TransformHierarchy {
+        &self.transforms
+    }
+
+    /// The lights collected by the last `light_system`.
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// The first camera in the world, if there is one.
+    pub fn camera(&self) -> Option<Camera> {
+        self.entities
+            .query::<&Camera>()
+            .iter()
+            .next()
+            .map(|(_, camera)| *camera)
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turn every spinning entity by however far it spins in `dt` seconds.
+pub fn spin_system(world: &mut World, dt: f32) {
+    for (_, (transform, spin)) in
+        world.entities.query_mut::<(&mut Transform, &Spin)>()
+    {
+        rotate_z(transform, spin.0 * dt);
+    }
+}
+
+/// Turn a transform further around the z axis, after its existing rotation.
+fn rotate_z(transform: &mut Transform, angle: f32) {
+    let [x, y, z, w] = transform.rotation;
+    let [_, _, sz, sw] = Transform::from_rotation_z(angle).rotation;
+    transform.rotation = [
+        sw * x - sz * y,
+        sw * y + sz * x,
+        sw * z + sz * w,
+        sw * w - sz * z,
+    ];
+}
+
+/// A demo shape's transform, a fifth of the size of its mesh.
+fn demo_transform(translation: [f32; 3]) -> Transform {
+    Transform {
+        translation,
+        scale: [0.2, 0.2, 0.2],
+        ..Transform::default()
+    }
+}
+
+/// Resolve every entity's `Transform` into a world matrix and tag the entity
+/// with the `TransformId` of its matrix.
+pub fn transform_system(world: &mut World) -> Result<()> {
+    let mut transforms = TransformHierarchy::default();
+    let mut resolved = vec![];
+    for (entity, transform) in world.entities.query::<&Transform>().iter() {
+        resolved.push((entity, transforms.add(*transform, None)?));
+    }
+    transforms.resolve()?;
+    for (entity, id) in resolved {
+        world
+            .entities
+            .insert_one(entity, id)
+            .context("unable to tag an entity with its transform")?;
+    }
+    world.transforms = transforms;
+    Ok(())
+}
+
+/// Collect every `PointLight` at its entity's resolved position.
+pub fn light_system(world: &mut World) -> Result<()> {
+    let mut lights = vec![];
+    for (_, (light, id)) in world
+        .entities
+        .query::<(&PointLight, &TransformId)>()
+        .with::<&Transform>()
+        .iter()
+    {
+        let matrix = world.transforms.world(*id)?;
+        lights.push(Light {
+            position: [matrix[3][0], matrix[3][1], matrix[3][2]],
+            color: light.color,
+            intensity: light.intensity,
+            range: light.range,
+        });
+    }
+    world.lights = lights;
+    Ok(())
+}
+
+/// Collect a draw for every entity with a `MeshRef` and a `Transform`.
+pub fn render_system(world: &World) -> Result<DrawList> {
+    let mut draw_list = DrawList::default();
+    for (_, (mesh, material, id)) in world
+        .entities
+        .query::<(&MeshRef, Option<&MaterialRef>, &TransformId)>()
+        .with::<&Transform>()
+        .iter()
+    {
+        let (mesh, material) =
+            find_draw(&world.meshes, &world.materials, mesh, material)?;
+        draw_list.draw_mesh(mesh, material, *id);
+    }
+    Ok(draw_list)
+}
+
+/// The mesh and material an entity refers to, meshes without a material are
+/// drawn white.
+fn find_draw<'a>(
+    meshes: &'a [Mesh],
+    materials: &[Material],
+    mesh: &MeshRef,
+    material: Option<&MaterialRef>,
+) -> Result<(&'a Mesh, Material)> {
+    let mesh = meshes
+        .get(mesh.0 .0)
+        .with_context(|| format!("there is no {:?}", mesh.0))?;
+    let material = match material {
+        Some(MaterialRef(material)) => *materials
+            .get(material.0)
+            .with_context(|| format!("there is no {:?}", material))?,
+        None => Material {
+            color: [1.0, 1.0, 1.0, 1.0],
+        },
+    };
+    Ok((mesh, material))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// Fail unless a spinner has turned a quarter of the way around.
+    pub(super) fn assert_quarter_turn(spun: &Transform) {
+        let expected = Transform::from_rotation_z(PI / 2.0);
+        let error = spun
+            .rotation
+            .iter()
+            .zip(&expected.rotation)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max);
+        assert!(error < 1e-5, "the spinner is at {:?}", spun.rotation);
+    }
+
+    /// Only entities with a `Transform` are resolved and lit, and an entity
+    /// whose transform is removed drops out on the next frame.
+    #[test]
+    fn systems_spin_resolve_and_light() -> Result<()> {
+        let mut world = World::new();
+        let spinner = world.entities.spawn((Transform::default(), Spin(PI)));
+        let lamp = world.entities.spawn((
+            Transform::from_translation([0.5, -0.25, 1.0]),
+            PointLight {
+                color: [1.0, 0.5, 0.25],
+                intensity: 2.0,
+                range: 3.0,
+            },
+        ));
+        world.entities.spawn((PointLight {
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 1.0,
+        },));
+
+        // two quarter second steps turn the spinner a quarter of the way
+        for _ in 0..2 {
+            spin_system(&mut world, 0.25);
+        }
+        transform_system(&mut world)?;
+        light_system(&mut world)?;
+        assert_quarter_turn(&*world.entities.get::<&Transform>(spinner)?);
+        let lights = world.lights();
+        assert_eq!(lights.len(), 1, "{:?}", lights);
+        assert_eq!(lights[0].position, [0.5, -0.25, 1.0]);
+
+        world.entities.remove_one::<Transform>(lamp)?;
+        transform_system(&mut world)?;
+        light_system(&mut world)?;
+        assert!(
+            world.lights().is_empty(),
+            "a light without a transform was lit {:?}",
+            world.lights()
+        );
+        assert!(world.camera().is_none());
+        Ok(())
+    }
+}
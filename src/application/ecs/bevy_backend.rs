@@ -0,0 +1,333 @@
+use super::{
+    demo_transform, find_draw, rotate_z, MaterialId, MaterialRef, MeshId,
+    MeshRef, PointLight, Spin, DEMO_LIGHTS, DEMO_MATERIAL, DEMO_SHAPES,
+};
+use crate::application::camera::Camera;
+use crate::application::cull::{Bounds, Cull, CullStats};
+use crate::application::meshes::{DrawList, Light, Material, Mesh};
+use crate::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use crate::display::Display;
+use anyhow::Result;
+use bevy_app::{App, Plugin, PostUpdate, Update};
+use bevy_ecs::prelude::*;
+
+/// The seconds since the last frame, set before every update.
+#[derive(Resource, Debug, Default, Copy, Clone)]
+pub struct FrameTime(pub f32);
+
+/// The camera the next frame is seen through, set before every update.
+#[derive(Resource, Debug, Copy, Clone)]
+pub struct View(pub Camera);
+
+impl Default for View {
+    fn default() -> Self {
+        Self(Camera::for_extent([1, 1]))
+    }
+}
+
+/// The meshes and materials entities refer to.
+#[derive(Resource, Default)]
+pub struct MeshAssets {
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+}
+
+impl MeshAssets {
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+}
+
+/// Everything the `RenderSet` collects for the `MeshRenderer`.
+#[derive(Resource, Default)]
+pub struct RenderData {
+    pub transforms: TransformHierarchy,
+    pub lights: Vec<Light>,
+    pub draw_list: DrawList,
+
+    /// The first camera in the world, if there is one.
+    pub camera: Option<Camera>,
+
+    /// What culling tested and rejected while preparing the frame.
+    pub culled: CullStats,
+
+    // the meshes which survived culling
+    visible: Vec<Entity>,
+}
+
+/// The systems which prepare the world for drawing, in order.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RenderSet;
+
+/// Spins entities during `Update`, then runs the `RenderSet` in
+/// `PostUpdate`.
+///
+/// The `RenderSet` uses the same components as the hecs `World`, so both
+/// backends draw the same scene. Command buffers are still built by the
+/// `MeshRenderer` when the scene is drawn, once for each camera, from the
+/// `RenderData` left behind by the set.
+pub struct RenderPlugin;
+
+impl Plugin for RenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameTime>()
+            .init_resource::<View>()
+            .init_resource::<MeshAssets>()
+            .init_resource::<RenderData>()
+            .add_systems(Update, spin_system)
+            .add_systems(
+                PostUpdate,
+                (
+                    upload_uniforms,
+                    cull_meshes.pipe(log_error),
+                    record_commands.pipe(log_error),
+                )
+                    .chain()
+                    .in_set(RenderSet),
+            );
+    }
+}
+
+/// An app with the same spinning shapes and lights as `World::demo`.
+pub fn demo_app(display: &Display) -> Result<App> {
+    let mut app = App::new();
+    app.add_plugins(RenderPlugin);
+    let mut assets = MeshAssets::default();
+    let material = assets.add_material(DEMO_MATERIAL);
+    for &(segments, translation, speed) in DEMO_SHAPES.iter() {
+        let mesh = assets.add_mesh(Mesh::disc(&display.device, segments)?);
+        app.world_mut().spawn((
+            demo_transform(translation),
+            MeshRef(mesh),
+            MaterialRef(material),
+            Spin(speed),
+        ));
+    }
+    for &(translation, light) in DEMO_LIGHTS.iter() {
+        app.world_mut()
+            .spawn((Transform::from_translation(translation), light));
+    }
+    app.insert_resource(assets);
+    Ok(app)
+}
+
+/// Turn every spinning entity by however far it spins this frame.
+pub fn spin_system(
+    time: Res<FrameTime>,
+    mut spinners: Query<(&mut Transform, &Spin)>,
+) {
+    for (mut transform, spin) in &mut spinners {
+        rotate_z(&mut transform, spin.0 * time.0);
+    }
+}
+
+/// An entity with a transform, which may be a light and may have been tagged
+/// by the last frame.
+type Placed<'a> = (
+    Entity,
+    &'a Transform,
+    Option<&'a PointLight>,
+    Option<&'a mut TransformId>,
+);
+
+/// Resolve every entity's `Transform`, tag the entity with its
+/// `TransformId`, and collect the lights and camera which the renderer
+/// uploads as uniforms.
+pub fn upload_uniforms(
+    mut commands: Commands,
+    mut entities: Query<Placed>,
+    cameras: Query<&Camera>,
+    mut render: ResMut<RenderData>,
+) {
+    // every transform is a root, so adding and resolving can't fail
+    let mut transforms = TransformHierarchy::default();
+    let mut lights = vec![];
+    for (entity, transform, light, tag) in &mut entities {
+        if let Ok(id) = transforms.add(*transform, None) {
+            // only new entities are moved to a new archetype
+            match tag {
+                Some(mut tag) => *tag = id,
+                None => {
+                    commands.entity(entity).insert(id);
+                }
+            }
+            if let Some(light) = light {
+                lights.push((*light, id));
+            }
+        }
+    }
+    let _ = transforms.resolve();
+    render.lights = lights
+        .into_iter()
+        .filter_map(|(light, id)| {
+            let matrix = transforms.world(id).ok()?;
+            Some(Light {
+                position: [matrix[3][0], matrix[3][1], matrix[3][2]],
+                color: light.color,
+                intensity: light.intensity,
+                range: light.range,
+            })
+        })
+        .collect();
+    render.transforms = transforms;
+    render.camera = cameras.iter().next().copied();
+}
+
+/// Entities with a mesh which are still placed by a transform.
+type PlacedMesh = (With<MeshRef>, With<Transform>);
+
+/// Keep the meshes which can be seen from the `View`, assuming each mesh
+/// fits in a unit sphere like the demo's discs.
+pub fn cull_meshes(
+    view: Res<View>,
+    meshes: Query<(Entity, &TransformId), PlacedMesh>,
+    mut render: ResMut<RenderData>,
+) -> Result<()> {
+    let render = &mut *render;
+    let mut cull = Cull::frustum(&view.0.view_projection());
+    render.visible.clear();
+    for (entity, id) in &meshes {
+        let matrix = render.transforms.world(*id)?;
+        let radius = matrix[..3]
+            .iter()
+            .map(|axis| {
+                (axis[0] * axis[0] + axis[1] * axis[1] + axis[2] * axis[2])
+                    .sqrt()
+            })
+            .fold(0.0, f32::max);
+        let bounds = Bounds::Sphere {
+            center: [matrix[3][0], matrix[3][1], matrix[3][2]],
+            radius,
+        };
+        if cull.is_visible(&bounds) {
+            render.visible.push(entity);
+        }
+    }
+    render.culled = cull.stats();
+    Ok(())
+}
+
+/// Collect a draw for every mesh which survived culling.
+pub fn record_commands(
+    assets: Res<MeshAssets>,
+    meshes: Query<(&MeshRef, Option<&MaterialRef>, &TransformId)>,
+    mut render: ResMut<RenderData>,
+) -> Result<()> {
+    let mut draw_list = DrawList::default();
+    for &entity in &render.visible {
+        let (mesh, material, id) = meshes.get(entity)?;
+        let (mesh, material) =
+            find_draw(&assets.meshes, &assets.materials, mesh, material)?;
+        draw_list.draw_mesh(mesh, material, *id);
+    }
+    render.draw_list = draw_list;
+    Ok(())
+}
+
+/// Report a failed system without stopping the app.
+fn log_error(In(result): In<Result<()>>) {
+    if let Err(error) = result {
+        log::error!("an ecs system failed {:?}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::ecs::tests::assert_quarter_turn;
+    use crate::application::ecs::{self, World};
+    use std::f32::consts::PI;
+    use std::time::Instant;
+
+    #[test]
+    fn schedule_spins_and_lights() {
+        let mut app = App::new();
+        app.add_plugins(RenderPlugin);
+        app.insert_resource(FrameTime(0.25));
+        let spinner =
+            app.world_mut().spawn((Transform::default(), Spin(PI))).id();
+        app.world_mut().spawn((
+            Transform::from_translation([0.5, -0.25, 1.0]),
+            PointLight {
+                color: [1.0, 0.5, 0.25],
+                intensity: 2.0,
+                range: 3.0,
+            },
+        ));
+        for _ in 0..2 {
+            app.update();
+        }
+
+        assert_quarter_turn(app.world().get::<Transform>(spinner).unwrap());
+        let lights = &app.world().resource::<RenderData>().lights;
+        assert_eq!(lights.len(), 1, "{:?}", lights);
+        assert_eq!(lights[0].position, [0.5, -0.25, 1.0]);
+    }
+
+    /// Update a world of spinning entities and lights with each backend and
+    /// compare their frame times. Run it with
+    /// `cargo test --release --features bevy-ecs -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bevy_keeps_up_with_hecs() -> Result<()> {
+        const ENTITIES: usize = 10_000;
+        const FRAMES: u32 = 60;
+        let place = |i: usize| {
+            let x = (i % 100) as f32 / 50.0 - 1.0;
+            let y = (i / 100) as f32 / 50.0 - 1.0;
+            Transform::from_translation([x, y, 0.0])
+        };
+        let light = PointLight {
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 0.1,
+        };
+
+        let mut world = World::new();
+        let mut app = App::new();
+        app.add_plugins(RenderPlugin);
+        app.insert_resource(FrameTime(1.0 / 60.0));
+        for i in 0..ENTITIES {
+            if i % 100 == 0 {
+                world.entities.spawn((place(i), light));
+                app.world_mut().spawn((place(i), light));
+            } else {
+                world.entities.spawn((place(i), Spin(1.0)));
+                app.world_mut().spawn((place(i), Spin(1.0)));
+            }
+        }
+
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            ecs::spin_system(&mut world, 1.0 / 60.0);
+            ecs::transform_system(&mut world)?;
+            ecs::light_system(&mut world)?;
+        }
+        let hecs = start.elapsed().as_secs_f32() * 1000.0 / FRAMES as f32;
+        let start = Instant::now();
+        for _ in 0..FRAMES {
+            app.update();
+        }
+        let bevy = start.elapsed().as_secs_f32() * 1000.0 / FRAMES as f32;
+        println!(
+            "{} entities take {:.3}ms a frame with hecs and {:.3}ms with bevy",
+            ENTITIES, hecs, bevy
+        );
+
+        // generous, since the timings are noisy
+        assert!(bevy < hecs * 3.0, "bevy is much slower than hecs");
+        assert_eq!(
+            app.world().resource::<RenderData>().lights.len(),
+            world.lights().len()
+        );
+        Ok(())
+    }
+}
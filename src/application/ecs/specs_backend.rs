@@ -0,0 +1,467 @@
+use super::{
+    demo_transform, find_draw, rotate_z, MaterialId, MaterialRef, MeshId,
+    MeshRef, PointLight, Spin, DEMO_LIGHTS, DEMO_MATERIAL, DEMO_SHAPES,
+};
+use crate::application::camera::Camera;
+use crate::application::meshes::{DrawList, Light, Material, Mesh};
+use crate::application::transform::{
+    Transform, TransformHierarchy, TransformId,
+};
+use crate::display::Display;
+use anyhow::Result;
+use specs::prelude::*;
+use specs::Component;
+use std::f32::consts::PI;
+
+/// Store components shared with the other backends the way specs' derive
+/// would, the derive can't be used outside this module since it expects
+/// specs' prelude to be in scope.
+macro_rules! shared_components {
+    ($($component:ty),*) => {
+        $(
+            impl Component for $component {
+                type Storage = DenseVecStorage<Self>;
+            }
+        )*
+    };
+}
+
+shared_components!(
+    Camera,
+    MaterialRef,
+    MeshRef,
+    PointLight,
+    Spin,
+    Transform,
+    TransformId
+);
+
+/// The seconds since the last frame, set before every dispatch.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FrameTime(pub f32);
+
+/// How fast an entity moves, in units and radians around the z axis per
+/// second. The `AnimationSystem` sets it and the `PhysicsSystem` moves the
+/// entity's transform by it.
+#[derive(Component, Debug, Default, Copy, Clone)]
+pub struct Velocity {
+    pub linear: [f32; 3],
+    pub angular: f32,
+}
+
+/// Bobs the entity up and down by `amplitude`, `frequency` times a second.
+#[derive(Component, Debug, Copy, Clone)]
+pub struct Bob {
+    pub amplitude: f32,
+    pub frequency: f32,
+}
+
+/// The meshes and materials entities refer to.
+#[derive(Default)]
+pub struct MeshAssets {
+    meshes: Vec<Mesh>,
+    materials: Vec<Material>,
+}
+
+impl MeshAssets {
+    pub fn add_mesh(&mut self, mesh: Mesh) -> MeshId {
+        self.meshes.push(mesh);
+        MeshId(self.meshes.len() - 1)
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+}
+
+/// The world matrices resolved by the `TransformSystem`.
+#[derive(Default)]
+pub struct Transforms(pub TransformHierarchy);
+
+/// Everything the `RenderSystem` collects for the `MeshRenderer`.
+#[derive(Default)]
+pub struct RenderData {
+    pub lights: Vec<Light>,
+    pub draw_list: DrawList,
+
+    /// The first camera in the world, if there is one.
+    pub camera: Option<Camera>,
+}
+
+/// Resolve every entity's `Transform` into a world matrix and tag the entity
+/// with the `TransformId` of its matrix. Entities which lost their transform
+/// lose their tag.
+pub struct TransformSystem;
+
+impl<'a> System<'a> for TransformSystem {
+    type SystemData = (
+        Entities<'a>,
+        ReadStorage<'a, Transform>,
+        WriteStorage<'a, TransformId>,
+        Write<'a, Transforms>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, transforms, mut ids, mut resolved): Self::SystemData,
+    ) {
+        // every transform is a root, so adding and resolving can't fail
+        let mut hierarchy = TransformHierarchy::default();
+        for (entity, transform) in (&entities, &transforms).join() {
+            if let Ok(id) = hierarchy.add(*transform, None) {
+                let _ = ids.insert(entity, id);
+            }
+        }
+        let _ = hierarchy.resolve();
+        let untagged: Vec<Entity> = (&entities, !&transforms, &ids)
+            .join()
+            .map(|(entity, _, _)| entity)
+            .collect();
+        for entity in untagged {
+            ids.remove(entity);
+        }
+        resolved.0 = hierarchy;
+    }
+}
+
+/// Set the velocity of every spinning or bobbing entity, keeping its own
+/// clock to know where each bob is.
+#[derive(Default)]
+pub struct AnimationSystem {
+    time: f32,
+}
+
+impl<'a> System<'a> for AnimationSystem {
+    type SystemData = (
+        Entities<'a>,
+        Read<'a, FrameTime>,
+        ReadStorage<'a, Spin>,
+        ReadStorage<'a, Bob>,
+        WriteStorage<'a, Velocity>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, time, spins, bobs, mut velocities): Self::SystemData,
+    ) {
+        self.time += time.0;
+        for (entity, spin, bob) in
+            (&entities, spins.maybe(), bobs.maybe()).join()
+        {
+            if spin.is_none() && bob.is_none() {
+                continue;
+            }
+            if let Ok(entry) = velocities.entry(entity) {
+                let velocity = entry.or_insert_with(Velocity::default);
+                velocity.angular = spin.map_or(0.0, |spin| spin.0);
+                velocity.linear[1] = bob.map_or(0.0, |bob| {
+                    // the rate of change of amplitude * sin(2 pi f t)
+                    let angular_frequency = 2.0 * PI * bob.frequency;
+                    bob.amplitude
+                        * angular_frequency
+                        * (angular_frequency * self.time).cos()
+                });
+            }
+        }
+    }
+}
+
+/// Move every entity's transform by its velocity over the frame.
+pub struct PhysicsSystem;
+
+impl<'a> System<'a> for PhysicsSystem {
+    type SystemData = (
+        Read<'a, FrameTime>,
+        ReadStorage<'a, Velocity>,
+        WriteStorage<'a, Transform>,
+    );
+
+    fn run(&mut self, (time, velocities, mut transforms): Self::SystemData) {
+        let dt = time.0;
+        for (velocity, transform) in (&velocities, &mut transforms).join() {
+            for (position, speed) in
+                transform.translation.iter_mut().zip(&velocity.linear)
+            {
+                *position += speed * dt;
+            }
+            rotate_z(transform, velocity.angular * dt);
+        }
+    }
+}
+
+/// Collect the lights, draws, and camera from the transforms resolved this
+/// frame.
+pub struct RenderSystem;
+
+impl<'a> System<'a> for RenderSystem {
+    type SystemData = (
+        Read<'a, MeshAssets>,
+        Read<'a, Transforms>,
+        ReadStorage<'a, TransformId>,
+        ReadStorage<'a, MeshRef>,
+        ReadStorage<'a, MaterialRef>,
+        ReadStorage<'a, PointLight>,
+        ReadStorage<'a, Camera>,
+        Write<'a, RenderData>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            assets,
+            transforms,
+            ids,
+            meshes,
+            materials,
+            lights,
+            cameras,
+            mut render,
+        ): Self::SystemData,
+    ) {
+        render.lights = (&lights, &ids)
+            .join()
+            .filter_map(|(light, id)| {
+                let matrix = transforms.0.world(*id).ok()?;
+                Some(Light {
+                    position: [matrix[3][0], matrix[3][1], matrix[3][2]],
+                    color: light.color,
+                    intensity: light.intensity,
+                    range: light.range,
+                })
+            })
+            .collect();
+        let mut draw_list = DrawList::default();
+        for (mesh, material, id) in (&meshes, materials.maybe(), &ids).join() {
+            match find_draw(&assets.meshes, &assets.materials, mesh, material) {
+                Ok((mesh, material)) => {
+                    draw_list.draw_mesh(mesh, material, *id)
+                }
+                Err(error) => log::error!("unable to draw {:?}", error),
+            }
+        }
+        render.draw_list = draw_list;
+        render.camera = cameras.join().next().copied();
+    }
+}
+
+/// A specs world and the dispatcher which runs its systems every frame.
+///
+/// The `AnimationSystem` and `TransformSystem` touch different components,
+/// so a parallel dispatcher runs them at the same time. The
+/// `PhysicsSystem` waits for both: it needs the new velocities and must not
+/// move transforms while they're being resolved. The `RenderSystem` runs
+/// last. Transforms are resolved before physics moves them, so what's drawn
+/// is a frame behind the physics.
+pub struct SpecsWorld {
+    pub entities: World,
+    dispatcher: Dispatcher<'static, 'static>,
+}
+
+impl SpecsWorld {
+    /// An empty world, with every system on the calling thread when
+    /// `parallel` is false.
+    pub fn new(parallel: bool) -> Self {
+        let builder = DispatcherBuilder::new();
+        let builder = if parallel {
+            builder
+                .with(AnimationSystem::default(), "animation", &[])
+                .with(TransformSystem, "transform", &[])
+                .with(PhysicsSystem, "physics", &["animation", "transform"])
+                .with(RenderSystem, "render", &["physics"])
+        } else {
+            builder
+                .with_thread_local(AnimationSystem::default())
+                .with_thread_local(TransformSystem)
+                .with_thread_local(PhysicsSystem)
+                .with_thread_local(RenderSystem)
+        };
+        let mut dispatcher = builder.build();
+        let mut entities = World::new();
+        dispatcher.setup(&mut entities);
+        Self {
+            entities,
+            dispatcher,
+        }
+    }
+
+    /// The same spinning shapes and lights as `World::demo`, which also bob
+    /// up and down.
+    pub fn demo(display: &Display) -> Result<Self> {
+        let mut world = Self::new(true);
+        let mut assets = MeshAssets::default();
+        let material = assets.add_material(DEMO_MATERIAL);
+        for (i, &(segments, translation, speed)) in
+            DEMO_SHAPES.iter().enumerate()
+        {
+            let mesh = assets.add_mesh(Mesh::disc(&display.device, segments)?);
+            world
+                .entities
+                .create_entity()
+                .with(demo_transform(translation))
+                .with(MeshRef(mesh))
+                .with(MaterialRef(material))
+                .with(Spin(speed))
+                .with(Bob {
+                    amplitude: 0.1,
+                    frequency: 0.25 * (i + 1) as f32,
+                })
+                .build();
+        }
+        for &(translation, light) in DEMO_LIGHTS.iter() {
+            world
+                .entities
+                .create_entity()
+                .with(Transform::from_translation(translation))
+                .with(light)
+                .build();
+        }
+        world.entities.insert(assets);
+        Ok(world)
+    }
+
+    /// Run every system once, `dt` seconds after the last update.
+    pub fn update(&mut self, dt: f32) {
+        self.entities.insert(FrameTime(dt));
+        self.dispatcher.dispatch(&self.entities);
+        self.entities.maintain();
+    }
+
+    /// The transforms resolved by the last update.
+    pub fn transforms(&self) -> specs::shred::Fetch<'_, Transforms> {
+        self.entities.read_resource::<Transforms>()
+    }
+
+    /// The lights, draws, and camera collected by the last update.
+    pub fn render_data(&self) -> specs::shred::Fetch<'_, RenderData> {
+        self.entities.read_resource::<RenderData>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::ecs::tests::assert_quarter_turn;
+    use std::time::Instant;
+
+    #[test]
+    fn dispatcher_spins_bobs_and_lights() {
+        let mut world = SpecsWorld::new(true);
+        let spinner = world
+            .entities
+            .create_entity()
+            .with(Transform::default())
+            .with(Spin(PI))
+            .build();
+        let bobber = world
+            .entities
+            .create_entity()
+            .with(Transform::default())
+            .with(Bob {
+                amplitude: 1.0,
+                frequency: 0.25,
+            })
+            .build();
+        let lamp = world
+            .entities
+            .create_entity()
+            .with(Transform::from_translation([0.5, -0.25, 1.0]))
+            .with(PointLight {
+                color: [1.0, 0.5, 0.25],
+                intensity: 2.0,
+                range: 3.0,
+            })
+            .build();
+
+        // a quarter turn, and a bob a sixteenth of the way along its cycle
+        for _ in 0..2 {
+            world.update(0.25);
+        }
+        let transforms = world.entities.read_storage::<Transform>();
+        assert_quarter_turn(&transforms.get(spinner).copied().unwrap());
+
+        // each step moves at the speed from the end of the step, so the bob
+        // is only near sin(pi / 4)
+        let height = transforms.get(bobber).map_or(0.0, |t| t.translation[1]);
+        assert!(
+            (height - (PI / 4.0).sin()).abs() < 0.15,
+            "the bob is at {} instead of near 0.707",
+            height
+        );
+        drop(transforms);
+        let lights = world.render_data().lights.clone();
+        assert_eq!(lights.len(), 1, "{:?}", lights);
+        assert_eq!(lights[0].position, [0.5, -0.25, 1.0]);
+
+        world.entities.write_storage::<Transform>().remove(lamp);
+        world.update(0.25);
+        assert!(
+            world.render_data().lights.is_empty(),
+            "a light without a transform was lit"
+        );
+    }
+
+    /// Update a world of spinning, bobbing entities and lights with a
+    /// parallel and a sequential dispatcher and compare their frame times.
+    /// Run it with `cargo test --release --features specs -- --ignored`.
+    #[test]
+    #[ignore]
+    fn parallel_dispatch_keeps_up_with_sequential() {
+        const ENTITIES: usize = 10_000;
+        const FRAMES: u32 = 60;
+        let populate = |world: &mut SpecsWorld| {
+            for i in 0..ENTITIES {
+                let x = (i % 100) as f32 / 50.0 - 1.0;
+                let y = (i / 100) as f32 / 50.0 - 1.0;
+                let builder = world
+                    .entities
+                    .create_entity()
+                    .with(Transform::from_translation([x, y, 0.0]));
+                if i % 100 == 0 {
+                    builder
+                        .with(PointLight {
+                            color: [1.0, 1.0, 1.0],
+                            intensity: 1.0,
+                            range: 0.1,
+                        })
+                        .build();
+                } else {
+                    builder
+                        .with(Spin(1.0))
+                        .with(Bob {
+                            amplitude: 0.01,
+                            frequency: 1.0,
+                        })
+                        .build();
+                }
+            }
+        };
+        let time = |parallel: bool| {
+            let mut world = SpecsWorld::new(parallel);
+            populate(&mut world);
+            let start = Instant::now();
+            for _ in 0..FRAMES {
+                world.update(1.0 / 60.0);
+            }
+            let ms = start.elapsed().as_secs_f32() * 1000.0 / FRAMES as f32;
+            let lights = world.render_data().lights.len();
+            (ms, lights)
+        };
+
+        let (sequential, sequential_lights) = time(false);
+        let (parallel, parallel_lights) = time(true);
+        println!(
+            "{} entities take {:.3}ms a frame dispatched sequentially and \
+            {:.3}ms in parallel",
+            ENTITIES, sequential, parallel
+        );
+
+        // generous, since the timings are noisy and a single core machine
+        // can't run anything in parallel
+        assert!(
+            parallel < sequential * 2.0,
+            "the parallel dispatcher is much slower than the sequential one"
+        );
+        assert_eq!(parallel_lights, sequential_lights);
+    }
+}
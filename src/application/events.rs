@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+
+/// Something a background thread wants the main loop to know about.
+///
+/// Events are sent through an `EventLoopProxy`, which wakes the event loop
+/// even while it's waiting, and are handled together at the start of the
+/// next frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AppEvent {
+    /// An asset changed on disk and can be loaded again.
+    AssetLoaded(PathBuf),
+
+    /// A shader's source changed on disk.
+    ShaderChanged(PathBuf),
+
+    /// Set a parameter as though the user had changed it, so it can be
+    /// undone.
+    ParamChanged { name: String, value: f32 },
+
+    /// Draw a frame as soon as possible.
+    RequestRedraw,
+
+    /// Close the window as though the user had closed it.
+    Shutdown,
+}
+
+/// The events received since the last frame.
+///
+/// Background threads can send the same event many times between two
+/// frames, so repeats are coalesced: each path is kept once, in the order
+/// it first arrived, and each parameter keeps the last value it was sent.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PendingEvents {
+    pub assets: Vec<PathBuf>,
+    pub shaders: Vec<PathBuf>,
+    pub params: Vec<(String, f32)>,
+    pub redraw: bool,
+    pub shutdown: bool,
+}
+
+impl PendingEvents {
+    pub fn push(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::AssetLoaded(path) => {
+                if !self.assets.contains(&path) {
+                    self.assets.push(path);
+                }
+            }
+            AppEvent::ShaderChanged(path) => {
+                if !self.shaders.contains(&path) {
+                    self.shaders.push(path);
+                }
+            }
+            AppEvent::ParamChanged { name, value } => {
+                match self.params.iter_mut().find(|(key, _)| *key == name) {
+                    Some((_, previous)) => *previous = value,
+                    None => self.params.push((name, value)),
+                }
+            }
+            AppEvent::RequestRedraw => self.redraw = true,
+            AppEvent::Shutdown => self.shutdown = true,
+        }
+    }
+
+    /// Take every pending event, leaving nothing pending.
+    pub fn take(&mut self) -> Self {
+        std::mem::take(self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, value: f32) -> AppEvent {
+        AppEvent::ParamChanged {
+            name: name.to_owned(),
+            value,
+        }
+    }
+
+    /// A burst of events, like several background threads would send
+    /// between two frames, comes out coalesced.
+    #[test]
+    fn repeats_are_coalesced() {
+        let grade = PathBuf::from("assets/grade.cube");
+        let diamond = PathBuf::from("assets/diamond.obj");
+        let shader = PathBuf::from("assets/shaders/wave.frag");
+        let mut pending = PendingEvents::default();
+        assert!(pending.is_empty());
+        for event in vec![
+            AppEvent::AssetLoaded(grade.clone()),
+            param("grading.strength", 0.25),
+            AppEvent::AssetLoaded(diamond.clone()),
+            AppEvent::ShaderChanged(shader.clone()),
+            AppEvent::AssetLoaded(grade.clone()),
+            param("motion_blur.strength", 0.5),
+            param("grading.strength", 0.75),
+            AppEvent::ShaderChanged(shader.clone()),
+            AppEvent::RequestRedraw,
+        ] {
+            pending.push(event);
+        }
+
+        let events = pending.take();
+        assert_eq!(events.assets, vec![grade, diamond]);
+        assert_eq!(events.shaders, vec![shader]);
+        assert_eq!(
+            events.params,
+            vec![
+                ("grading.strength".to_owned(), 0.75),
+                ("motion_blur.strength".to_owned(), 0.5),
+            ]
+        );
+        assert!(events.redraw);
+        assert!(!events.shutdown);
+        assert!(pending.is_empty(), "{:?} was left pending", pending);
+    }
+
+    #[test]
+    fn a_shutdown_is_pending_until_its_taken() {
+        let mut pending = PendingEvents::default();
+        pending.push(AppEvent::Shutdown);
+        pending.push(AppEvent::Shutdown);
+        assert!(pending.shutdown);
+        let events = pending.take();
+        assert!(events.shutdown);
+        assert!(!events.redraw);
+        assert!(pending.is_empty());
+    }
+}
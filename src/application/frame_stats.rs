@@ -0,0 +1,251 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// How often the frame statistics are reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many of the most recent frame times are kept.
+pub const HISTORY: usize = 240;
+
+/// How many of the most recent markers are kept.
+pub const MAX_MARKERS: usize = 64;
+
+/// Something which happened during a frame, like a scene switch, kept to
+/// line up with spikes in the frame times.
+#[derive(Debug, Clone)]
+pub struct Marker {
+    /// The index of the frame the marker was made during, counting from 0.
+    pub frame: u64,
+
+    /// When the marker was made, since the stats were created.
+    pub time: Duration,
+
+    pub name: Cow<'static, str>,
+}
+
+/// The average frame rate over the last reporting interval.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameReport {
+    pub fps: f32,
+
+    /// The average frame time in milliseconds.
+    pub frame_time: f32,
+
+    /// The average estimated input to photon latency in milliseconds,
+    /// nothing when no frame was measured.
+    pub latency: Option<f32>,
+}
+
+/// Counts frames and reports the average frame rate once per interval.
+///
+/// The time each of the last `HISTORY` frames took is kept in a ring buffer
+/// for the profiler graph, along with their measured latencies, and the last
+/// `MAX_MARKERS` markers are kept alongside them.
+pub struct FrameStats {
+    frames: u32,
+    interval_start: Instant,
+    last_frame: Instant,
+
+    // when the stats were created, and the index of the frame after the
+    // last one recorded
+    start: Instant,
+    frame_count: u64,
+
+    // frame times in milliseconds, the oldest is at `next`
+    frame_times: [f32; HISTORY],
+    next: usize,
+
+    // latencies in milliseconds alongside the frame times, 0 when unmeasured
+    latencies: [f32; HISTORY],
+    latency_sum: f32,
+    latency_count: u32,
+
+    // when each frame ended in seconds since `start`, alongside the times
+    end_times: [f32; HISTORY],
+
+    // the oldest marker is first
+    markers: VecDeque<Marker>,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            interval_start: Instant::now(),
+            last_frame: Instant::now(),
+            start: Instant::now(),
+            frame_count: 0,
+            frame_times: [0.0; HISTORY],
+            next: 0,
+            latencies: [0.0; HISTORY],
+            latency_sum: 0.0,
+            latency_count: 0,
+            end_times: [0.0; HISTORY],
+            markers: VecDeque::with_capacity(MAX_MARKERS),
+        }
+    }
+
+    /// Mark something which happened during the current frame, the oldest
+    /// marker is dropped once there are `MAX_MARKERS`. Markers named with a
+    /// string literal don't allocate.
+    pub fn marker(&mut self, name: impl Into<Cow<'static, str>>) {
+        let name = name.into();
+        log::debug!("frame {} marker: {}", self.frame_count, name);
+        if self.markers.len() == MAX_MARKERS {
+            self.markers.pop_front();
+        }
+        self.markers.push_back(Marker {
+            frame: self.frame_count,
+            time: self.start.elapsed(),
+            name,
+        });
+    }
+
+    /// The markers made during the frames in `frame_times`, with the index
+    /// of their frame there.
+    pub fn history_markers(
+        &self,
+    ) -> impl Iterator<Item = (usize, &Marker)> + '_ {
+        let first = self.frame_count as i64 - HISTORY as i64;
+        self.markers.iter().filter_map(move |marker| {
+            let index = marker.frame as i64 - first;
+            if (0..HISTORY as i64).contains(&index) {
+                Some((index as usize, marker))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Write the frames in `frame_times` as csv, one row per frame with the
+    /// names of the frame's markers in the last column. Frames from before
+    /// the application started are left out.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "frame,end_s,frame_time_ms,latency_ms,markers")?;
+        let first = self.frame_count as i64 - HISTORY as i64;
+        let rows = self
+            .frame_times()
+            .zip(self.latencies())
+            .zip(self.end_times())
+            .enumerate()
+            .map(|(i, ((ms, latency), end))| {
+                (first + i as i64, ms, latency, end)
+            })
+            .filter(|&(frame, ..)| frame >= 0);
+        for (frame, ms, latency, end) in rows {
+            let names: Vec<&str> = self
+                .markers
+                .iter()
+                .filter(|marker| marker.frame == frame as u64)
+                .map(|marker| marker.name.as_ref())
+                .collect();
+            write!(writer, "{},{:.6},{:.3},", frame, end, ms)?;
+            if latency > 0.0 {
+                write!(writer, "{:.3}", latency)?;
+            }
+            writeln!(writer, ",{}", csv_field(&names.join("; ")))?;
+        }
+        writer.flush()
+    }
+
+    /// Forget the time since the last frame, so the first frame after an
+    /// idle stretch, like a single step, is timed on its own. The report
+    /// interval starts again too.
+    pub fn resume(&mut self) {
+        let now = Instant::now();
+        self.last_frame = now;
+        self.interval_start = now;
+        self.frames = 0;
+        self.latency_sum = 0.0;
+        self.latency_count = 0;
+    }
+
+    /// Record the finished frame `index`, numbered like `FrameTiming`.
+    /// Markers made from here until the next frame is recorded belong to
+    /// frame `index + 1`. Returns a report when a full interval has passed
+    /// since the last one.
+    pub fn frame(&mut self, index: u64) -> Option<FrameReport> {
+        let now = Instant::now();
+        self.frame_times[self.next] =
+            (now - self.last_frame).as_secs_f32() * 1000.0;
+        self.latencies[self.next] = 0.0;
+        self.end_times[self.next] = (now - self.start).as_secs_f32();
+        self.next = (self.next + 1) % HISTORY;
+        self.last_frame = now;
+        self.frame_count = index + 1;
+
+        self.frames += 1;
+        let elapsed = self.interval_start.elapsed();
+        if elapsed < REPORT_INTERVAL {
+            return None;
+        }
+
+        let seconds = elapsed.as_secs_f32();
+        let report = FrameReport {
+            fps: self.frames as f32 / seconds,
+            frame_time: seconds * 1000.0 / self.frames as f32,
+            latency: match self.latency_count {
+                0 => None,
+                count => Some(self.latency_sum / count as f32),
+            },
+        };
+        self.frames = 0;
+        self.latency_sum = 0.0;
+        self.latency_count = 0;
+        self.interval_start = Instant::now();
+        Some(report)
+    }
+
+    /// Record the latency of the frame which finished most recently, against
+    /// the latest frame.
+    pub fn record_latency(&mut self, latency: Duration) {
+        let ms = latency.as_secs_f32() * 1000.0;
+        self.latencies[(self.next + HISTORY - 1) % HISTORY] = ms;
+        self.latency_sum += ms;
+        self.latency_count += 1;
+    }
+
+    /// The latencies alongside `frame_times`, 0 for frames which weren't
+    /// measured.
+    pub fn latencies(&self) -> impl Iterator<Item = f32> + '_ {
+        self.latencies[self.next..]
+            .iter()
+            .chain(&self.latencies[..self.next])
+            .copied()
+    }
+
+    /// The last `HISTORY` frame times in milliseconds, oldest first. Frames
+    /// from before the application started count as 0.
+    pub fn frame_times(&self) -> impl Iterator<Item = f32> + '_ {
+        self.frame_times[self.next..]
+            .iter()
+            .chain(&self.frame_times[..self.next])
+            .copied()
+    }
+
+    /// When each frame in `frame_times` ended, in seconds since the stats
+    /// were created.
+    fn end_times(&self) -> impl Iterator<Item = f32> + '_ {
+        self.end_times[self.next..]
+            .iter()
+            .chain(&self.end_times[..self.next])
+            .copied()
+    }
+}
+
+/// Quote a csv field when it needs to be.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
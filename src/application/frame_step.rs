@@ -0,0 +1,64 @@
+use super::clock::{AnimationClock, TimeSource};
+use std::time::Instant;
+
+/// Halts the render loop so frames can be looked at one at a time, with the
+/// validation output, markers, and readbacks of each frame on their own.
+///
+/// Unlike pausing the animation, nothing is rendered at all while stepping.
+/// Each step updates, records, submits, and presents exactly one frame, and
+/// the event loop waits for events in between, still handling input.
+///
+/// The animation clock is locked to the display without catching up while
+/// stepping, so every step advances time by one refresh interval however
+/// long the wait for the key was. The clock's source is put back when
+/// stepping ends.
+#[derive(Default)]
+pub struct FrameStep {
+    // the clock's source and catch up from before stepping, while stepping
+    restore: Option<(TimeSource, bool)>,
+    pending: bool,
+}
+
+impl FrameStep {
+    pub fn active(&self) -> bool {
+        self.restore.is_some()
+    }
+
+    /// True while stepping with no step requested, when the event loop
+    /// should sleep until the next event.
+    pub fn waiting(&self) -> bool {
+        self.active() && !self.pending
+    }
+
+    /// Start or stop stepping, returning whether it's now active.
+    pub fn toggle(&mut self, clock: &mut AnimationClock) -> bool {
+        let now = Instant::now();
+        match self.restore.take() {
+            Some((source, catch_up)) => {
+                clock.set_source(source, now);
+                clock.set_catch_up(catch_up);
+            }
+            None => {
+                self.restore = Some((clock.source(), clock.catch_up()));
+                clock.set_source(TimeSource::DisplayLocked, now);
+                clock.set_catch_up(false);
+            }
+        }
+        self.pending = false;
+        self.active()
+    }
+
+    /// Render one more frame, if stepping.
+    pub fn request(&mut self) {
+        self.pending = self.active();
+    }
+
+    /// True when a frame should be rendered now, which is always unless
+    /// stepping. Consumes the requested step.
+    pub fn take(&mut self) -> bool {
+        if !self.active() {
+            return true;
+        }
+        std::mem::replace(&mut self.pending, false)
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::device::Device;
+use vulkano::impl_vertex;
+use vulkano::pipeline::viewport::Viewport;
+
+/// A vertex of the single triangle which covers the whole screen.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct FullscreenVertex {
+    pub pos: [f32; 2],
+}
+
+impl_vertex!(FullscreenVertex, pos);
+
+/// Create the vertex buffer for a triangle which covers the entire viewport.
+pub fn create_vertex_buffer(
+    device: &Arc<Device>,
+) -> Result<Arc<CpuAccessibleBuffer<[FullscreenVertex]>>> {
+    let vertices = [[-1.0, -1.0], [3.0, -1.0], [-1.0, 3.0]]
+        .iter()
+        .map(|&pos| FullscreenVertex { pos });
+    CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::vertex_buffer(),
+        false,
+        vertices,
+    )
+    .context("unable to create the fullscreen vertex buffer")
+}
+
+/// A viewport which covers the full swapchain extent.
+pub fn viewport(swapchain_extent: [u32; 2]) -> Viewport {
+    Viewport {
+        dimensions: [swapchain_extent[0] as f32, swapchain_extent[1] as f32],
+        origin: [0.0, 0.0],
+        depth_range: 0.0..1.0,
+    }
+}
+
+/// Passes texture coordinates in the range [0, 1] to the fragment shader.
+pub mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 pos;
+
+            layout(location = 0) out vec2 vertUv;
+
+            void main() {
+                vertUv = pos * 0.5 + 0.5;
+                gl_Position = vec4(pos, 0.0, 1.0);
+            }
+            "#
+    }
+}
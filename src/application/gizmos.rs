@@ -0,0 +1,530 @@
+use super::camera::Camera;
+use super::profiler::{self, OverlayVertex};
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use vulkano::buffer::CpuBufferPool;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::framebuffer::Subpass;
+use vulkano::pipeline::GraphicsPipelineAbstract;
+
+/// How close the cursor has to be to grab a handle, and how big handles are
+/// drawn, in physical pixels.
+pub const HANDLE_RADIUS: f32 = 8.0;
+
+/// The number of sides each handle's circle is drawn with.
+const SEGMENTS: usize = 24;
+
+/// What happened to a handle this frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GizmoEvent {
+    /// The cursor isn't over the handle.
+    Idle,
+
+    /// The cursor is over the handle, which would be grabbed by a click.
+    Hovered,
+
+    /// The handle was grabbed this frame while at `from`.
+    DragStarted { from: [f32; 2] },
+
+    /// The handle is being dragged and may have moved.
+    Dragged,
+
+    /// The handle was let go this frame. The application records the move
+    /// so it can be undone.
+    DragEnded { from: [f32; 2], to: [f32; 2] },
+
+    /// The handle was put back by undo, or moved again by redo.
+    Restored { from: [f32; 2], to: [f32; 2] },
+}
+
+/// A handle registered this frame, as the overlay draws it.
+#[derive(Debug, Clone)]
+pub struct DrawnHandle {
+    pub name: String,
+
+    /// Where the handle is in normalized device coordinates.
+    pub ndc: [f32; 2],
+    pub event: GizmoEvent,
+}
+
+/// The handle being dragged.
+#[derive(Debug, Clone)]
+struct Drag {
+    name: String,
+    from: [f32; 2],
+
+    // where the handle is relative to the cursor, so it doesn't jump to the
+    // cursor when grabbed off center
+    grab_offset: [f32; 2],
+
+    // false when the handle wasn't registered last frame
+    seen: bool,
+}
+
+/// Draggable handles for points on the z = 0 plane, like attractors or the
+/// control points of a curve.
+///
+/// Handles are registered every frame, immediate mode, by calling `point`
+/// with a name and the position it edits. The first handle under the cursor
+/// when the left button goes down is grabbed and follows the cursor,
+/// through the camera, until the button is released. Holding shift keeps it
+/// on whichever axis it has moved furthest along since it was grabbed, and
+/// with a grid the position snaps to the nearest grid point.
+pub struct Gizmos {
+    camera: Camera,
+    window_extent: [u32; 2],
+    cursor: Option<[f32; 2]>,
+    grid: Option<f32>,
+    axis_locked: bool,
+
+    // the left button's state, and whether it went down since the last frame
+    down: bool,
+    pressed: bool,
+    just_pressed: bool,
+
+    drag: Option<Drag>,
+    handles: Vec<DrawnHandle>,
+
+    // positions from undo or redo, applied when their handle is next
+    // registered
+    restores: HashMap<String, [f32; 2]>,
+}
+
+impl Gizmos {
+    pub fn new() -> Self {
+        Self {
+            camera: Camera::for_extent([1, 1]),
+            window_extent: [1, 1],
+            cursor: None,
+            grid: None,
+            axis_locked: false,
+            down: false,
+            pressed: false,
+            just_pressed: false,
+            drag: None,
+            handles: vec![],
+            restores: HashMap::new(),
+        }
+    }
+
+    /// Snap dragged handles to a grid with this spacing, or not at all.
+    pub fn set_grid(&mut self, grid: Option<f32>) {
+        self.grid = grid.filter(|&spacing| spacing > 0.0);
+    }
+
+    /// Keep dragged handles on one axis while `locked`, usually while shift
+    /// is held.
+    pub fn set_axis_lock(&mut self, locked: bool) {
+        self.axis_locked = locked;
+    }
+
+    /// Move a handle to `position` the next time it's registered, dropping
+    /// it if it's being dragged.
+    pub fn restore(&mut self, name: &str, position: [f32; 2]) {
+        if self.drag.as_ref().is_some_and(|drag| drag.name == name) {
+            self.drag = None;
+        }
+        self.restores.insert(name.to_owned(), position);
+    }
+
+    /// The left mouse button went down.
+    pub fn press(&mut self) {
+        self.down = true;
+        self.pressed = true;
+    }
+
+    /// The left mouse button came up.
+    pub fn release(&mut self) {
+        self.down = false;
+    }
+
+    /// Start a frame of handles, seen through `camera` in a window of
+    /// `window_extent` physical pixels with the cursor at `cursor`.
+    pub fn begin_frame(
+        &mut self,
+        camera: &Camera,
+        window_extent: [u32; 2],
+        cursor: Option<[f32; 2]>,
+    ) {
+        self.camera = *camera;
+        self.window_extent = window_extent;
+        self.cursor = cursor;
+        self.just_pressed = std::mem::take(&mut self.pressed);
+        self.handles.clear();
+
+        // a handle which stopped being registered can't stay grabbed
+        if let Some(drag) = &mut self.drag {
+            if !std::mem::take(&mut drag.seen) {
+                self.drag = None;
+            }
+        }
+    }
+
+    /// Register a handle for `position` this frame, moving it if it's being
+    /// dragged.
+    pub fn point(&mut self, name: &str, position: &mut [f32; 2]) -> GizmoEvent {
+        let event = match self.restores.remove(name) {
+            Some(restored) => {
+                let from = std::mem::replace(position, restored);
+                GizmoEvent::Restored { from, to: restored }
+            }
+            None => self.update_point(name, position),
+        };
+        self.handles.push(DrawnHandle {
+            name: name.to_owned(),
+            ndc: self.camera.project([position[0], position[1], 0.0]),
+            event,
+        });
+        event
+    }
+
+    /// The handles registered this frame, in order.
+    pub fn handles(&self) -> &[DrawnHandle] {
+        &self.handles
+    }
+
+    fn update_point(
+        &mut self,
+        name: &str,
+        position: &mut [f32; 2],
+    ) -> GizmoEvent {
+        let cursor_world = self.cursor_world();
+        if let Some(drag) = &mut self.drag {
+            if drag.name != name {
+                return GizmoEvent::Idle;
+            }
+            drag.seen = true;
+            if !self.down {
+                let from = drag.from;
+                self.drag = None;
+                return GizmoEvent::DragEnded {
+                    from,
+                    to: *position,
+                };
+            }
+            if let Some([x, y]) = cursor_world {
+                let target = [x + drag.grab_offset[0], y + drag.grab_offset[1]];
+                *position =
+                    constrain(target, drag.from, self.axis_locked, self.grid);
+            }
+            return GizmoEvent::Dragged;
+        }
+
+        if !self.is_under_cursor(*position) {
+            return GizmoEvent::Idle;
+        }
+        match cursor_world {
+            Some([x, y]) if self.just_pressed => {
+                // one press only grabs one handle
+                self.just_pressed = false;
+                self.drag = Some(Drag {
+                    name: name.to_owned(),
+                    from: *position,
+                    grab_offset: [position[0] - x, position[1] - y],
+                    seen: true,
+                });
+                GizmoEvent::DragStarted { from: *position }
+            }
+            _ => GizmoEvent::Hovered,
+        }
+    }
+
+    /// Where the cursor is on the z = 0 plane.
+    fn cursor_world(&self) -> Option<[f32; 2]> {
+        let [x, y] = self.cursor?;
+        let [width, height] = self.window_extent;
+        let ndc = [2.0 * x / width as f32 - 1.0, 2.0 * y / height as f32 - 1.0];
+        self.camera.unproject(ndc)
+    }
+
+    /// True when the cursor is within `HANDLE_RADIUS` pixels of where the
+    /// handle is drawn.
+    fn is_under_cursor(&self, position: [f32; 2]) -> bool {
+        let cursor = match self.cursor {
+            Some(cursor) => cursor,
+            None => return false,
+        };
+        let [x, y] = self.camera.project([position[0], position[1], 0.0]);
+        let [width, height] = self.window_extent;
+        let dx = (x + 1.0) * 0.5 * width as f32 - cursor[0];
+        let dy = (y + 1.0) * 0.5 * height as f32 - cursor[1];
+        dx * dx + dy * dy <= HANDLE_RADIUS * HANDLE_RADIUS
+    }
+}
+
+impl Default for Gizmos {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keep a dragged position on the axis it's moved furthest along since
+/// `from` when locked, then snap it to the grid.
+fn constrain(
+    target: [f32; 2],
+    from: [f32; 2],
+    axis_locked: bool,
+    grid: Option<f32>,
+) -> [f32; 2] {
+    let mut position = target;
+    if axis_locked {
+        let [dx, dy] = [target[0] - from[0], target[1] - from[1]];
+        if dx.abs() >= dy.abs() {
+            position[1] = from[1];
+        } else {
+            position[0] = from[0];
+        }
+    }
+    if let Some(spacing) = grid {
+        for value in position.iter_mut() {
+            *value = (*value / spacing).round() * spacing;
+        }
+    }
+    position
+}
+
+/// Draws every registered handle as a circle over the final image, after
+/// post processing, so handles are never blurred.
+pub struct GizmoOverlay {
+    line_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    fill_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    vertex_pool: CpuBufferPool<OverlayVertex>,
+}
+
+impl GizmoOverlay {
+    pub fn new(display: &Display) -> Result<Self> {
+        let (line_pipeline, fill_pipeline) =
+            profiler::create_graphics_pipelines(
+                &display.device,
+                display.swapchain.dimensions(),
+                &display.render_pass,
+            )?;
+        Ok(Self {
+            line_pipeline,
+            fill_pipeline,
+            vertex_pool: CpuBufferPool::vertex_buffer(display.device.clone()),
+        })
+    }
+
+    /// Build a secondary command buffer which draws the handles, nothing is
+    /// built when there are none.
+    pub fn draw(
+        &self,
+        display: &Display,
+        gizmos: &Gizmos,
+    ) -> Result<Option<AutoCommandBuffer>> {
+        if gizmos.handles().is_empty() {
+            return Ok(None);
+        }
+        let [width, height] = display.swapchain.dimensions();
+        let radius = [
+            2.0 * HANDLE_RADIUS / width as f32,
+            2.0 * HANDLE_RADIUS / height as f32,
+        ];
+        let mut fill = vec![];
+        let mut lines = vec![];
+        for handle in gizmos.handles() {
+            let color = handle_color(handle.event);
+            let [x, y] = handle.ndc;
+            let point = |i: usize| {
+                let angle = 2.0 * PI * i as f32 / SEGMENTS as f32;
+                [x + radius[0] * angle.cos(), y + radius[1] * angle.sin()]
+            };
+            let translucent = [color[0], color[1], color[2], 0.35];
+            for i in 0..SEGMENTS {
+                for &pos in &[[x, y], point(i), point(i + 1)] {
+                    fill.push(OverlayVertex {
+                        pos,
+                        color: translucent,
+                    });
+                }
+                for &pos in &[point(i), point(i + 1)] {
+                    lines.push(OverlayVertex { pos, color });
+                }
+            }
+        }
+
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the gizmos")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        for (pipeline, vertices) in [
+            (self.fill_pipeline.clone(), fill),
+            (self.line_pipeline.clone(), lines),
+        ] {
+            let vertices = Arc::new(
+                self.vertex_pool
+                    .chunk(vertices)
+                    .context("unable to upload the gizmo handles")?,
+            );
+            builder
+                .draw(pipeline, &DynamicState::none(), vec![vertices], (), ())
+                .context("unable to draw the gizmo handles")?;
+        }
+        let commands = builder
+            .build()
+            .context("unable to build the command buffer")?;
+        Ok(Some(commands))
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let (line_pipeline, fill_pipeline) =
+            profiler::create_graphics_pipelines(
+                &display.device,
+                display.swapchain.dimensions(),
+                &display.render_pass,
+            )?;
+        self.line_pipeline = line_pipeline;
+        self.fill_pipeline = fill_pipeline;
+        Ok(())
+    }
+}
+
+/// Grabbed handles are orange, hovered ones yellow, and the rest white.
+fn handle_color(event: GizmoEvent) -> [f32; 4] {
+    match event {
+        GizmoEvent::Idle => [0.9, 0.9, 0.9, 1.0],
+        GizmoEvent::Hovered => [1.0, 0.85, 0.2, 1.0],
+        _ => [1.0, 0.5, 0.1, 1.0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The window the tests drag handles around in.
+    const WINDOW: [u32; 2] = [800, 600];
+
+    #[test]
+    fn points_map_through_the_camera_and_back() {
+        let cameras = [
+            Camera::for_extent(WINDOW),
+            Camera::for_extent(WINDOW)
+                .looking_from([0.3, -0.2, 1.0], [0.3, -0.2, 0.0]),
+        ];
+        for camera in cameras.iter() {
+            for &point in &[[0.0, 0.0], [0.25, 0.1], [-0.9, 0.6]] {
+                let ndc = camera.project([point[0], point[1], 0.0]);
+                let back = camera.unproject(ndc).unwrap_or([f32::NAN; 2]);
+                assert!(
+                    distance(back, point) < 1e-4,
+                    "{:?} came back from {:?} as {:?}",
+                    point,
+                    ndc,
+                    back
+                );
+            }
+        }
+    }
+
+    /// Handles are grabbed off center so they must not jump to the cursor,
+    /// a locked drag must stay on one axis, and a drag with a grid must land
+    /// on it.
+    #[test]
+    fn handles_drag_lock_and_snap() {
+        let camera = Camera::for_extent(WINDOW);
+        let mut gizmos = Gizmos::new();
+        let mut attractor = [0.25, 0.1];
+        let mut other = [0.25, 0.1];
+        let grab = add(pixel(&camera, attractor), [3.0, -2.0]);
+
+        gizmos.press();
+        gizmos.begin_frame(&camera, WINDOW, Some(grab));
+        assert_eq!(
+            gizmos.point("attractor", &mut attractor),
+            GizmoEvent::DragStarted { from: [0.25, 0.1] }
+        );
+        assert_eq!(
+            gizmos.point("other", &mut other),
+            GizmoEvent::Idle,
+            "a second handle under the same press"
+        );
+
+        // 80 pixels right and 6 up while locked moves only along x
+        gizmos.set_axis_lock(true);
+        gizmos.begin_frame(&camera, WINDOW, Some(add(grab, [80.0, -6.0])));
+        gizmos.point("attractor", &mut attractor);
+        gizmos.point("other", &mut other);
+        let expected = [0.25 + 80.0 / 300.0, 0.1];
+        assert!(
+            distance(attractor, expected) < 1e-4,
+            "the locked drag moved to {:?}, not {:?}",
+            attractor,
+            expected
+        );
+
+        gizmos.set_axis_lock(false);
+        gizmos.set_grid(Some(0.25));
+        gizmos.begin_frame(&camera, WINDOW, Some(add(grab, [80.0, -6.0])));
+        gizmos.point("attractor", &mut attractor);
+        gizmos.point("other", &mut other);
+        assert!(
+            distance(attractor, [0.5, 0.0]) < 1e-4,
+            "the snapped drag moved to {:?}",
+            attractor
+        );
+
+        gizmos.release();
+        gizmos.begin_frame(&camera, WINDOW, Some(add(grab, [80.0, -6.0])));
+        let ended = gizmos.point("attractor", &mut attractor);
+        gizmos.point("other", &mut other);
+        assert_eq!(
+            ended,
+            GizmoEvent::DragEnded {
+                from: [0.25, 0.1],
+                to: [0.5, 0.0],
+            }
+        );
+        assert_eq!(other, [0.25, 0.1], "the other handle moved");
+    }
+
+    /// An undone drag moves the handle back the next time it's registered.
+    #[test]
+    fn restored_handles_move_once() {
+        let camera = Camera::for_extent(WINDOW);
+        let mut gizmos = Gizmos::new();
+        let mut point = [0.5, 0.5];
+        gizmos.restore("control", [0.1, 0.2]);
+        gizmos.begin_frame(&camera, WINDOW, None);
+        assert_eq!(
+            gizmos.point("control", &mut point),
+            GizmoEvent::Restored {
+                from: [0.5, 0.5],
+                to: [0.1, 0.2],
+            }
+        );
+        assert_eq!(point, [0.1, 0.2]);
+        gizmos.begin_frame(&camera, WINDOW, None);
+        assert_eq!(gizmos.point("control", &mut point), GizmoEvent::Idle);
+    }
+
+    /// Where a point on the z = 0 plane is in the window, in pixels.
+    fn pixel(camera: &Camera, point: [f32; 2]) -> [f32; 2] {
+        let [x, y] = camera.project([point[0], point[1], 0.0]);
+        [
+            (x + 1.0) * 0.5 * WINDOW[0] as f32,
+            (y + 1.0) * 0.5 * WINDOW[1] as f32,
+        ]
+    }
+
+    fn add(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+        [a[0] + b[0], a[1] + b[1]]
+    }
+
+    fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+        ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+    }
+}
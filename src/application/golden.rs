@@ -0,0 +1,378 @@
+use super::capture_format::{self, CaptureEncoding};
+use super::debug::BarrierValidator;
+use super::offscreen::OffscreenTarget;
+use super::Application;
+use crate::display::{Display, SurfaceFormatInfo};
+use crate::texture;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
+
+/// Where the golden images are checked in.
+pub const GOLDEN_DIRECTORY: &str = "assets/golden";
+
+/// Where the captured and diff images of failed comparisons are written.
+pub const ARTIFACT_DIRECTORY: &str = "target/golden";
+
+/// How far a captured frame may be from its golden image.
+///
+/// No two gpu vendors rasterize exactly alike. The rules leave room in
+/// which pixels along an edge are covered, msaa sample positions and
+/// resolves differ, and blending, srgb conversion, and transcendental
+/// functions in shaders round differently. A few levels of `channel`
+/// difference absorb the rounding, while `pixels` absorbs edges which land
+/// on the other side of a pixel. Tests of smooth gradients get by with the
+/// default, tests full of edges, like text, need a larger pixel count, and
+/// tests meant to catch exact results can use `EXACT`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Tolerance {
+    /// The largest difference in any channel, out of 255, for a pixel to
+    /// still count as the same.
+    pub channel: u8,
+
+    /// How many pixels may differ by more than `channel`.
+    pub pixels: usize,
+}
+
+impl Tolerance {
+    /// Every pixel must match exactly.
+    pub const EXACT: Tolerance = Tolerance {
+        channel: 0,
+        pixels: 0,
+    };
+}
+
+impl Default for Tolerance {
+    /// Enough for rounding differences between vendors and a short stretch
+    /// of edge pixels.
+    fn default() -> Self {
+        Self {
+            channel: 3,
+            pixels: 64,
+        }
+    }
+}
+
+/// Tightly packed rgba8 pixels, top row first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl GoldenImage {
+    pub fn load(path: &Path) -> Result<Self> {
+        let (width, height, pixels) = texture::decode_png(path)
+            .with_context(|| format!("unable to load {:?}", path))?;
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .with_context(|| format!("unable to create {:?}", directory))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("unable to create {:?}", path))?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .with_context(|| format!("unable to write {:?}", path))
+    }
+
+    /// The `size` pixels in the middle of the image, for comparing frames
+    /// of windows with different shapes.
+    pub fn crop_center(&self, size: [u32; 2]) -> Result<Self> {
+        let [width, height] = size;
+        if width > self.width || height > self.height {
+            bail!(
+                "can't crop {}x{} out of a {}x{} image",
+                width,
+                height,
+                self.width,
+                self.height
+            );
+        }
+        let left = ((self.width - width) / 2) as usize;
+        let top = ((self.height - height) / 2) as usize;
+        let pixels = self
+            .pixels
+            .chunks_exact(self.width as usize * 4)
+            .skip(top)
+            .take(height as usize)
+            .flat_map(|row| &row[left * 4..(left + width as usize) * 4])
+            .copied()
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// How a captured frame compared with its golden image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    /// The pixels which differ by more than the tolerance allows.
+    pub differing: usize,
+
+    /// The largest difference in any channel of any pixel.
+    pub max_delta: u8,
+}
+
+/// Compare two images of the same size pixel by pixel.
+pub fn compare(
+    expected: &GoldenImage,
+    actual: &GoldenImage,
+    tolerance: Tolerance,
+) -> Result<Comparison> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        bail!(
+            "the golden image is {}x{}, the frame is {}x{}",
+            expected.width,
+            expected.height,
+            actual.width,
+            actual.height
+        );
+    }
+    let mut comparison = Comparison {
+        differing: 0,
+        max_delta: 0,
+    };
+    for (expected, actual) in expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        let delta = pixel_delta(expected, actual);
+        comparison.max_delta = comparison.max_delta.max(delta);
+        if delta > tolerance.channel {
+            comparison.differing += 1;
+        }
+    }
+    Ok(comparison)
+}
+
+/// The frame in dim grey with every pixel which differs by more than the
+/// tolerance in red.
+pub fn diff_image(
+    expected: &GoldenImage,
+    actual: &GoldenImage,
+    tolerance: Tolerance,
+) -> GoldenImage {
+    let pixels = expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+        .flat_map(|(expected, actual)| {
+            if pixel_delta(expected, actual) > tolerance.channel {
+                [255, 0, 0, 255]
+            } else {
+                let luma =
+                    (actual[0] as u32 + actual[1] as u32 + actual[2] as u32)
+                        / 3;
+                let grey = (luma / 4) as u8;
+                [grey, grey, grey, 255]
+            }
+        })
+        .collect();
+    GoldenImage {
+        width: actual.width,
+        height: actual.height,
+        pixels,
+    }
+}
+
+fn pixel_delta(expected: &[u8], actual: &[u8]) -> u8 {
+    expected
+        .iter()
+        .zip(actual)
+        .map(|(&expected, &actual)| (expected as i16 - actual as i16).abs())
+        .max()
+        .unwrap_or(0) as u8
+}
+
+/// Compares frames of an application with the golden images in
+/// `GOLDEN_DIRECTORY`, or replaces the golden images when regenerating.
+///
+/// A golden test sets up the feature it covers, calls `check`, and puts
+/// things back for the next test:
+///
+/// ```ignore
+/// app.toggle_lens_effects()?;
+/// golden.check(&mut app, "lens_effects", Tolerance::default())?;
+/// app.toggle_lens_effects()?;
+/// ```
+pub struct Golden {
+    /// The window's size in physical pixels while frames are captured.
+    pub size: [u32; 2],
+
+    /// The animation time every frame is captured at, in seconds.
+    pub time: f32,
+
+    /// Write each captured frame as its golden image instead of comparing.
+    pub regenerate: bool,
+}
+
+impl Golden {
+    /// Resize the application's window to `size`, failing when the window
+    /// manager won't allow it.
+    pub fn prepare(&self, app: &mut Application) -> Result<()> {
+        app.set_window_size(self.size)?;
+        let extent = app.display().swapchain.dimensions();
+        if extent != self.size {
+            bail!(
+                "golden images are {}x{} but the window is {}x{}",
+                self.size[0],
+                self.size[1],
+                extent[0],
+                extent[1]
+            );
+        }
+        Ok(())
+    }
+
+    /// Capture a frame at `time` and compare it with the golden image
+    /// `name`. On failure the frame and a diff are written to
+    /// `ARTIFACT_DIRECTORY`.
+    pub fn check(
+        &self,
+        app: &mut Application,
+        name: &str,
+        tolerance: Tolerance,
+    ) -> Result<()> {
+        app.set_time(self.time);
+        let actual = app.capture_frame()?;
+        let golden_path =
+            Path::new(GOLDEN_DIRECTORY).join(name).with_extension("png");
+        if self.regenerate {
+            actual.save(&golden_path)?;
+            log::info!("{}: wrote {:?}", name, golden_path);
+            return Ok(());
+        }
+
+        let expected = GoldenImage::load(&golden_path).with_context(|| {
+            format!("no golden image for {}, regenerate it first", name)
+        })?;
+        let comparison = compare(&expected, &actual, tolerance)?;
+        if comparison.differing <= tolerance.pixels {
+            log::info!(
+                "{}: {} pixels over the tolerance, largest difference {}",
+                name,
+                comparison.differing,
+                comparison.max_delta
+            );
+            return Ok(());
+        }
+
+        let artifact = |suffix: &str| -> PathBuf {
+            Path::new(ARTIFACT_DIRECTORY)
+                .join(format!("{}.{}.png", name, suffix))
+        };
+        actual.save(&artifact("actual"))?;
+        diff_image(&expected, &actual, tolerance).save(&artifact("diff"))?;
+        bail!(
+            "{}: {} pixels differ by more than {}, {} are allowed, see {:?}",
+            name,
+            comparison.differing,
+            tolerance.channel,
+            tolerance.pixels,
+            artifact("diff")
+        );
+    }
+}
+
+/// A frame's final image, copied where the cpu can read it.
+///
+/// The copy is in the swapchain's format and color space, which it's
+/// converted from when read, so 10 bit and HDR swapchains are captured with
+/// the colors they show.
+pub struct Capture {
+    extent: [u32; 2],
+    surface_format: SurfaceFormatInfo,
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+impl Capture {
+    /// Build the passes which draw the swapchain's secondary command buffers
+    /// into an offscreen image the swapchain's size, then copy it into a
+    /// buffer.
+    pub fn record(
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<(Self, AutoCommandBuffer, AutoCommandBuffer)> {
+        // fail before drawing anything when the format can't be converted
+        let surface_format = display.surface_format();
+        let texel_size = capture_format::texel_size(surface_format.format)?;
+        let target = OffscreenTarget::new(display)?;
+        let render = target.render(display, graphics_queue_subbuffers)?;
+
+        let extent = display.swapchain.dimensions();
+        let buffer = CpuAccessibleBuffer::from_iter(
+            display.device.clone(),
+            BufferUsage::transfer_destination(),
+            true,
+            (0..extent[0] as usize * extent[1] as usize * texel_size)
+                .map(|_| 0u8),
+        )
+        .context("unable to create the capture buffer")?;
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the capture command buffer builder")?,
+            &display.graphics_queue,
+        );
+        builder
+            .copy_image_to_buffer(target.image.clone(), buffer.clone())
+            .context("unable to copy the captured frame")?;
+        let copy = builder
+            .build()
+            .context("unable to build the capture command buffer")?;
+
+        let capture = Self {
+            extent,
+            surface_format,
+            buffer,
+        };
+        Ok((capture, render, copy))
+    }
+
+    /// Read the captured pixels once the frame has finished, as 8 bit sRGB.
+    pub fn read(&self) -> Result<GoldenImage> {
+        Ok(GoldenImage {
+            width: self.extent[0],
+            height: self.extent[1],
+            pixels: capture_format::to_rgba8(&self.read_srgb()?),
+        })
+    }
+
+    /// Write the captured pixels to a png once the frame has finished.
+    pub fn save(&self, path: &Path, encoding: CaptureEncoding) -> Result<()> {
+        let pixels = self.read_srgb()?;
+        capture_format::save_png(path, self.extent, &pixels, encoding)
+    }
+
+    fn read_srgb(&self) -> Result<Vec<[f32; 4]>> {
+        let texels = self
+            .buffer
+            .read()
+            .context("unable to read the captured frame")?;
+        capture_format::to_srgb(self.surface_format, &texels)
+    }
+}
@@ -0,0 +1,176 @@
+use crate::display::Presented;
+use std::time::{Duration, Instant};
+
+/// How much earlier than predicted input is sampled, to absorb frames which
+/// take a little longer than usual.
+pub const LATCH_MARGIN: Duration = Duration::from_millis(2);
+
+/// How much each new measurement moves the running averages.
+const SMOOTHING: f64 = 0.1;
+
+/// Predicts the latest moment input can be sampled while the frame still
+/// makes the next present.
+///
+/// The present interval is the running average time between frames being
+/// seen to finish, and the frame cost is the average time from sampling
+/// input until the frame finished, less any time spent blocked waiting for a
+/// swapchain image. Sampling at the last present plus the interval, less the
+/// cost and a margin, means the frame finishes just as the next one is due.
+/// When presents aren't held to vsync the interval shrinks by the margin
+/// each frame until there's nothing left to wait for.
+pub struct LateLatch {
+    margin: Duration,
+    present_interval: Option<f64>,
+    frame_cost: Option<f64>,
+    last_present: Option<Instant>,
+
+    // when the frame waiting to be presented sampled its input
+    sampled: Option<Instant>,
+}
+
+impl LateLatch {
+    pub fn new(margin: Duration) -> Self {
+        Self {
+            margin,
+            present_interval: None,
+            frame_cost: None,
+            last_present: None,
+            sampled: None,
+        }
+    }
+
+    /// Note that input for the next frame is being sampled now.
+    pub fn input_sampled(&mut self, now: Instant) {
+        self.sampled = Some(now);
+    }
+
+    /// Note that the last frame was seen to finish. The same present can be
+    /// reported more than once, it's only counted once.
+    pub fn frame_presented(&mut self, presented: Presented) {
+        let Presented {
+            at: presented,
+            acquire_wait,
+        } = presented;
+        let last_present = match self.last_present {
+            Some(last) if last == presented => return,
+            last => last,
+        };
+        if let Some(last) = last_present {
+            let interval = presented.saturating_duration_since(last);
+            self.present_interval =
+                Some(smooth(self.present_interval, interval));
+        }
+        if let Some(sampled) = self.sampled.take() {
+            let cost = presented
+                .saturating_duration_since(sampled)
+                .saturating_sub(acquire_wait);
+            self.frame_cost = Some(smooth(self.frame_cost, cost));
+        }
+        self.last_present = Some(presented);
+    }
+
+    /// The average time between presents.
+    pub fn present_interval(&self) -> Option<Duration> {
+        self.present_interval.map(Duration::from_secs_f64)
+    }
+
+    /// The average time from sampling input until the frame is presented,
+    /// without waiting on the display.
+    pub fn frame_cost(&self) -> Option<Duration> {
+        self.frame_cost.map(Duration::from_secs_f64)
+    }
+
+    /// When to sample input for the next frame, nothing until a couple of
+    /// frames have been measured.
+    pub fn latch_point(&self) -> Option<Instant> {
+        let last_present = self.last_present?;
+        let lead = self.frame_cost()? + self.margin;
+        let next_present = last_present + self.present_interval()?;
+        Some(next_present.checked_sub(lead).unwrap_or(last_present))
+    }
+}
+
+/// Move a running average towards a new measurement, or start it there.
+fn smooth(average: Option<f64>, measured: Duration) -> f64 {
+    let measured = measured.as_secs_f64();
+    match average {
+        Some(average) => average + (measured - average) * SMOOTHING,
+        None => measured,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How long the simulated frames take from sampling input to presenting.
+    const FRAME_COST: Duration = Duration::from_millis(5);
+
+    /// The simulated display's refresh interval, 60hz.
+    const REFRESH: Duration = Duration::from_micros(16_667);
+
+    /// With fifo every present lands on a refresh, so the latch should
+    /// sample the frame cost and margin before one without missing any.
+    #[test]
+    fn vsynced_frames_sample_input_just_in_time() {
+        let start = Instant::now();
+        let mut latch = LateLatch::new(LATCH_MARGIN);
+        let mut now = start;
+        let mut input_age = Duration::default();
+        for frame in 0..240 {
+            if let Some(point) = latch.latch_point() {
+                now = now.max(point);
+            }
+            latch.input_sampled(now);
+            let refreshes = ((now + FRAME_COST - start).as_secs_f64()
+                / REFRESH.as_secs_f64())
+            .ceil() as u32;
+            let presented = start + REFRESH * refreshes;
+            if frame >= 120 {
+                assert_eq!(refreshes, frame + 1, "frame {} missed", frame);
+            }
+            input_age = presented - now;
+            // the frame is held until its refresh when acquiring the next
+            // image
+            latch.frame_presented(Presented {
+                at: presented,
+                acquire_wait: presented - (now + FRAME_COST),
+            });
+            now = presented;
+        }
+        let expected = FRAME_COST + LATCH_MARGIN;
+        assert!(
+            input_age <= expected + Duration::from_millis(1),
+            "input was sampled {:?} before the present, not {:?}",
+            input_age,
+            expected
+        );
+    }
+
+    /// With mailbox or immediate nothing holds the frames back, so waiting
+    /// only slows them down and the latch should back off.
+    #[test]
+    fn unpaced_frames_stop_waiting() {
+        let mut latch = LateLatch::new(LATCH_MARGIN);
+        let mut now = Instant::now();
+        let mut waited = Duration::default();
+        for _ in 0..240 {
+            waited = match latch.latch_point() {
+                Some(point) => point.saturating_duration_since(now),
+                None => Duration::default(),
+            };
+            now += waited;
+            latch.input_sampled(now);
+            now += FRAME_COST;
+            latch.frame_presented(Presented {
+                at: now,
+                acquire_wait: Duration::default(),
+            });
+        }
+        assert!(
+            waited <= Duration::from_micros(100),
+            "unpaced frames still wait {:?}",
+            waited
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Add;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static PIPELINES: AtomicUsize = AtomicUsize::new(0);
+static TEXTURES: AtomicUsize = AtomicUsize::new(0);
+static BUFFERS: AtomicUsize = AtomicUsize::new(0);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static REGISTRY: Mutex<BTreeMap<u64, ResourceInfo>> =
+    Mutex::new(BTreeMap::new());
+
+/// The kinds of gpu resources which are counted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResourceKind {
+    Pipeline,
+    Texture,
+    Buffer,
+}
+
+impl ResourceKind {
+    pub const ALL: [ResourceKind; 3] = [
+        ResourceKind::Pipeline,
+        ResourceKind::Texture,
+        ResourceKind::Buffer,
+    ];
+
+    fn counter(self) -> &'static AtomicUsize {
+        match self {
+            ResourceKind::Pipeline => &PIPELINES,
+            ResourceKind::Texture => &TEXTURES,
+            ResourceKind::Buffer => &BUFFERS,
+        }
+    }
+}
+
+/// What a live resource is, as registered by whatever created it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceInfo {
+    pub kind: ResourceKind,
+
+    /// What the resource is for, like a texture's file name.
+    pub label: String,
+
+    /// Anything else worth knowing, like a texture's dimensions and format
+    /// or where a buffer's memory lives.
+    pub detail: String,
+
+    /// The memory it takes, or 0 when it isn't known.
+    pub bytes: u64,
+}
+
+/// Counts one gpu resource as alive for as long as it's kept.
+///
+/// Renderers, meshes, and textures hold one next to the resource it stands
+/// for, so the counts go down when they're dropped. Anything which outlives
+/// its owner, like a renderer moved into a global, keeps the count up and
+/// shows as a leak. Each one is listed in the `ResourceRegistry` for as
+/// long as it's kept.
+#[derive(Debug)]
+pub struct LiveResource {
+    kind: ResourceKind,
+    id: u64,
+}
+
+impl LiveResource {
+    /// Count a resource with nothing known about it but its kind.
+    pub fn new(kind: ResourceKind) -> Self {
+        Self::described(kind, "unlabelled", String::new(), 0)
+    }
+
+    /// Count a resource and list it with a label, details, and its size in
+    /// bytes.
+    pub fn described(
+        kind: ResourceKind,
+        label: impl Into<String>,
+        detail: impl Into<String>,
+        bytes: u64,
+    ) -> Self {
+        kind.counter().fetch_add(1, Ordering::Relaxed);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let info = ResourceInfo {
+            kind,
+            label: label.into(),
+            detail: detail.into(),
+            bytes,
+        };
+        registry().insert(id, info);
+        Self { kind, id }
+    }
+}
+
+impl Drop for LiveResource {
+    fn drop(&mut self) {
+        self.kind.counter().fetch_sub(1, Ordering::Relaxed);
+        registry().remove(&self.id);
+    }
+}
+
+/// The registry, which stays usable after a panic while it was held since
+/// every change to it is a single insert or remove.
+fn registry() -> MutexGuard<'static, BTreeMap<u64, ResourceInfo>> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Every live resource at the moment it was taken.
+#[derive(Debug, Clone)]
+pub struct ResourceRegistry {
+    resources: Vec<ResourceInfo>,
+}
+
+impl ResourceRegistry {
+    /// The resources alive right now, largest first.
+    pub fn snapshot() -> Self {
+        let mut resources: Vec<ResourceInfo> =
+            registry().values().cloned().collect();
+        resources.sort_by(|a, b| {
+            b.bytes.cmp(&a.bytes).then_with(|| a.kind.cmp(&b.kind))
+        });
+        Self { resources }
+    }
+
+    /// Every resource, largest first.
+    pub fn resources(&self) -> &[ResourceInfo] {
+        &self.resources
+    }
+
+    /// How many resources of a kind there are and their total size.
+    pub fn total(&self, kind: ResourceKind) -> (usize, u64) {
+        self.resources
+            .iter()
+            .filter(|resource| resource.kind == kind)
+            .fold((0, 0), |(count, bytes), resource| {
+                (count + 1, bytes + resource.bytes)
+            })
+    }
+}
+
+/// The number of counted resources of each kind.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct LiveCounts {
+    pub pipelines: usize,
+    pub textures: usize,
+    pub buffers: usize,
+}
+
+impl LiveCounts {
+    /// The resources alive right now.
+    pub fn now() -> Self {
+        Self {
+            pipelines: PIPELINES.load(Ordering::Relaxed),
+            textures: TEXTURES.load(Ordering::Relaxed),
+            buffers: BUFFERS.load(Ordering::Relaxed),
+        }
+    }
+
+    /// How many more of each kind there are than in `other`, or none where
+    /// `other` has more.
+    pub fn beyond(self, other: LiveCounts) -> Self {
+        Self {
+            pipelines: self.pipelines.saturating_sub(other.pipelines),
+            textures: self.textures.saturating_sub(other.textures),
+            buffers: self.buffers.saturating_sub(other.buffers),
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self == Self::default()
+    }
+}
+
+impl Add for LiveCounts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            pipelines: self.pipelines + other.pipelines,
+            textures: self.textures + other.textures,
+            buffers: self.buffers + other.buffers,
+        }
+    }
+}
+
+impl fmt::Display for LiveCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} pipelines, {} textures, and {} buffers",
+            self.pipelines, self.textures, self.buffers
+        )
+    }
+}
+
+/// Run `f`, returning what it returned along with the resources it left
+/// alive.
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, LiveCounts) {
+    let before = LiveCounts::now();
+    let value = f();
+    (value, LiveCounts::now().beyond(before))
+}
@@ -0,0 +1,702 @@
+use super::camera::Camera;
+use super::debug_view::DebugViewMode;
+use super::live_resources::{LiveResource, ResourceKind};
+use super::transform::{TransformHierarchy, TransformId};
+use crate::display::Display;
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+#[cfg(any(feature = "scripting", feature = "lua"))]
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::BufferAccess;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::{
+    viewport::Viewport, GraphicsPipeline, GraphicsPipelineAbstract,
+};
+
+pub mod picking;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynPipeline = dyn GraphicsPipelineAbstract + Send + Sync;
+
+/// The per-draw data pushed for every mesh.
+type PushConstantData = vertex_shader::ty::PushConstantData;
+
+/// The most point lights a mesh is lit by, any more are ignored. The mesh
+/// fragment shader declares an array of this many lights.
+pub const MAX_LIGHTS: usize = 8;
+
+#[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
+pub struct MeshVertex {
+    pub pos: [f32; 3],
+}
+
+impl_vertex!(MeshVertex, pos);
+
+/// A triangle list in model space.
+#[derive(Clone)]
+pub struct Mesh {
+    vertices: Arc<CpuAccessibleBuffer<[MeshVertex]>>,
+
+    // clones share the buffer, so they share its count too
+    _live: Arc<LiveResource>,
+}
+
+impl Mesh {
+    pub fn from_vertices(
+        device: &Arc<Device>,
+        vertices: Vec<MeshVertex>,
+    ) -> Result<Self> {
+        if vertices.is_empty() || !vertices.len().is_multiple_of(3) {
+            bail!(
+                "a triangle list needs a multiple of 3 vertices, not {}",
+                vertices.len()
+            );
+        }
+        let count = vertices.len();
+        let vertices = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            vertices.into_iter(),
+        )
+        .context("unable to create the mesh vertex buffer")?;
+        Ok(Self {
+            vertices,
+            _live: Arc::new(LiveResource::described(
+                ResourceKind::Buffer,
+                "mesh vertices",
+                format!("{} vertices, host visible", count),
+                (count * std::mem::size_of::<MeshVertex>()) as u64,
+            )),
+        })
+    }
+
+    /// A disc with a radius of 1 around the origin in the xy plane.
+    pub fn disc(device: &Arc<Device>, segments: u32) -> Result<Self> {
+        let point = |i: u32| {
+            let angle = 2.0 * PI * i as f32 / segments as f32;
+            MeshVertex {
+                pos: [angle.cos(), angle.sin(), 0.0],
+            }
+        };
+        let vertices = (0..segments)
+            .flat_map(|i| {
+                vec![MeshVertex { pos: [0.0; 3] }, point(i), point(i + 1)]
+            })
+            .collect();
+        Self::from_vertices(device, vertices)
+    }
+}
+
+/// Read the triangles from a Wavefront OBJ file.
+///
+/// Only positions and faces are read, everything else is skipped. Faces with
+/// more than three corners are split into a fan of triangles, and negative
+/// indices count back from the most recent position as the format allows.
+#[cfg(any(feature = "scripting", feature = "lua"))]
+pub fn load_obj(path: &Path) -> Result<Vec<MeshVertex>> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read {:?}", path))?;
+    parse_obj(&source).with_context(|| format!("invalid obj file {:?}", path))
+}
+
+#[cfg(any(feature = "scripting", feature = "lua"))]
+fn parse_obj(source: &str) -> Result<Vec<MeshVertex>> {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut vertices = vec![];
+    for (number, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let mut pos = [0.0; 3];
+                for value in pos.iter_mut() {
+                    *value = words
+                        .next()
+                        .and_then(|word| word.parse().ok())
+                        .with_context(|| {
+                            format!("bad position on line {}", number + 1)
+                        })?;
+                }
+                positions.push(pos);
+            }
+            Some("f") => {
+                let corners = words
+                    .map(|word| {
+                        // v, v/vt, v//vn, or v/vt/vn
+                        let index = word
+                            .split('/')
+                            .next()
+                            .and_then(|index| index.parse::<i64>().ok());
+                        let index = match index {
+                            Some(index) if index < 0 => {
+                                positions.len() as i64 + index
+                            }
+                            Some(index) => index - 1,
+                            None => -1,
+                        };
+                        positions.get(index as usize).copied().with_context(
+                            || format!("bad face index on line {}", number + 1),
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if corners.len() < 3 {
+                    bail!("a face needs 3 corners on line {}", number + 1);
+                }
+                for i in 1..corners.len() - 1 {
+                    for &pos in &[corners[0], corners[i], corners[i + 1]] {
+                        vertices.push(MeshVertex { pos });
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    if vertices.is_empty() {
+        bail!("there are no faces");
+    }
+    Ok(vertices)
+}
+
+/// How a mesh is shaded.
+#[derive(Debug, Copy, Clone)]
+pub struct Material {
+    pub color: [f32; 4],
+}
+
+/// A point light in world space. Its light fades out quadratically, reaching
+/// nothing at `range`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Light {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+struct Draw {
+    mesh: Mesh,
+    material: Material,
+    transform_id: TransformId,
+    id: u32,
+}
+
+/// The meshes to draw this frame, in order.
+///
+/// Every draw has an id which picking reports when the draw is under the
+/// cursor. Id 0 is never reported, it stands for nothing being picked.
+#[derive(Default)]
+pub struct DrawList {
+    draws: Vec<Draw>,
+}
+
+impl DrawList {
+    /// Draw a mesh with a material, placed by a transform's world matrix.
+    ///
+    /// The draw's id is its position in the list plus one, so it only stays
+    /// the same between frames when the list is built in the same order.
+    pub fn draw_mesh(
+        &mut self,
+        mesh: &Mesh,
+        material: Material,
+        transform_id: TransformId,
+    ) {
+        let id = self.draws.len() as u32 + 1;
+        self.draw_mesh_with_id(mesh, material, transform_id, id);
+    }
+
+    /// Like `draw_mesh`, with an id chosen by the caller. A draw with id 0
+    /// hides whatever is behind it from picking without being picked itself.
+    pub fn draw_mesh_with_id(
+        &mut self,
+        mesh: &Mesh,
+        material: Material,
+        transform_id: TransformId,
+        id: u32,
+    ) {
+        self.draws.push(Draw {
+            mesh: mesh.clone(),
+            material,
+            transform_id,
+            id,
+        });
+    }
+
+    /// The ids of every draw, in the order they are drawn.
+    pub fn ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.draws.iter().map(|draw| draw.id)
+    }
+}
+
+/// Draws the meshes in a draw list.
+///
+/// The camera is shared by every draw through a uniform buffer while each
+/// draw pushes its own model matrix and color, so the push constants stay
+/// well within the 128 bytes every device supports.
+///
+/// The normals and wireframe debug views have pipelines of their own, built
+/// the first time their view is shown and kept by mode after that.
+pub struct MeshRenderer {
+    pipeline: Arc<DynPipeline>,
+    debug_view: DebugViewMode,
+    debug_pipelines: HashMap<DebugViewMode, Arc<DynPipeline>>,
+    camera_pool: CpuBufferPool<vertex_shader::ty::Camera>,
+    light_pool: CpuBufferPool<fragment_shader::ty::Lights>,
+    _live: LiveResource,
+}
+
+impl MeshRenderer {
+    pub fn new(display: &Display) -> Result<Self> {
+        let push_constants_size = std::mem::size_of::<PushConstantData>();
+        let limit = display
+            .device
+            .physical_device()
+            .limits()
+            .max_push_constants_size() as usize;
+        if push_constants_size > limit {
+            bail!(
+                "the mesh push constants need {} bytes but the device only \
+                supports {}",
+                push_constants_size,
+                limit
+            );
+        }
+        Ok(Self {
+            pipeline: create_graphics_pipeline(
+                &display.device,
+                display.scene_extent(),
+                &display.render_pass,
+            )?,
+            debug_view: DebugViewMode::Final,
+            debug_pipelines: HashMap::new(),
+            camera_pool: CpuBufferPool::uniform_buffer(display.device.clone()),
+            light_pool: CpuBufferPool::uniform_buffer(display.device.clone()),
+            _live: LiveResource::described(
+                ResourceKind::Pipeline,
+                "mesh renderer",
+                "lit meshes",
+                0,
+            ),
+        })
+    }
+
+    /// Build a secondary command buffer which draws every mesh in the list
+    /// with its transform's resolved world matrix, unlit.
+    pub fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+        draw_list: &DrawList,
+        transforms: &TransformHierarchy,
+    ) -> Result<AutoCommandBuffer> {
+        self.draw_lit(display, camera, draw_list, transforms, &[])
+    }
+
+    /// Draw the meshes for a debug view: their normals in place of their
+    /// color, or their edges over them. Other views draw them as usual.
+    ///
+    /// The wireframe needs the `fill_mode_non_solid` device feature, without
+    /// it the meshes are drawn as usual.
+    pub fn set_debug_view(
+        &mut self,
+        display: &Display,
+        mode: DebugViewMode,
+    ) -> Result<()> {
+        self.debug_view = mode;
+        let needs_pipeline = match mode {
+            DebugViewMode::Normals => true,
+            DebugViewMode::Wireframe => {
+                let supported =
+                    display.device.enabled_features().fill_mode_non_solid;
+                if !supported {
+                    log::warn!(
+                        "the device can't draw wireframes, the meshes are \
+                        drawn filled"
+                    );
+                }
+                supported
+            }
+            _ => false,
+        };
+        if needs_pipeline && !self.debug_pipelines.contains_key(&mode) {
+            let pipeline = create_debug_pipeline(
+                &display.device,
+                display.scene_extent(),
+                &display.render_pass,
+                mode,
+            )?;
+            self.debug_pipelines.insert(mode, pipeline);
+        }
+        Ok(())
+    }
+
+    /// Like `draw`, but every mesh is lit by up to `MAX_LIGHTS` point lights.
+    /// Meshes drawn with no lights keep their material's color.
+    pub fn draw_lit(
+        &self,
+        display: &Display,
+        camera: &Camera,
+        draw_list: &DrawList,
+        transforms: &TransformHierarchy,
+        lights: &[Light],
+    ) -> Result<AutoCommandBuffer> {
+        let camera_buffer = self
+            .camera_pool
+            .next(vertex_shader::ty::Camera {
+                view_projection: camera.view_projection(),
+                resolution: camera.resolution,
+                aspect_fix: camera.aspect_fix(),
+            })
+            .context("unable to upload the mesh camera")?;
+        let light_buffer = self
+            .light_pool
+            .next(light_data(lights))
+            .context("unable to upload the mesh lights")?;
+        let layout = self
+            .pipeline
+            .descriptor_set_layout(0)
+            .context("mesh pipeline has no descriptor set layout")?;
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_buffer(camera_buffer.clone())?
+                .add_buffer(light_buffer)?
+                .build()
+                .context("unable to create the mesh descriptors")?,
+        );
+        let normals = self.debug_pipelines.get(&DebugViewMode::Normals);
+        let (pipeline, descriptor_set) = match (self.debug_view, normals) {
+            (DebugViewMode::Normals, Some(normals)) => {
+                (normals.clone(), camera_set(normals, camera_buffer.clone())?)
+            }
+            _ => (self.pipeline.clone(), descriptor_set),
+        };
+
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the meshes")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        for draw in &draw_list.draws {
+            builder
+                .draw(
+                    pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![draw.mesh.vertices.clone()],
+                    descriptor_set.clone(),
+                    PushConstantData {
+                        model: transforms.world(draw.transform_id)?,
+                        color: draw.material.color,
+                    },
+                )
+                .context("unable to draw a mesh")?;
+        }
+        let wireframe = self.debug_pipelines.get(&DebugViewMode::Wireframe);
+        if let (DebugViewMode::Wireframe, Some(wireframe)) =
+            (self.debug_view, wireframe)
+        {
+            let descriptor_set = camera_set(wireframe, camera_buffer)?;
+            for draw in &draw_list.draws {
+                builder
+                    .draw(
+                        wireframe.clone(),
+                        &DynamicState::none(),
+                        vec![draw.mesh.vertices.clone()],
+                        descriptor_set.clone(),
+                        PushConstantData {
+                            model: transforms.world(draw.transform_id)?,
+                            color: draw.material.color,
+                        },
+                    )
+                    .context("unable to draw a mesh's wireframe")?;
+            }
+        }
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        for (&mode, pipeline) in self.debug_pipelines.iter_mut() {
+            *pipeline = create_debug_pipeline(
+                &display.device,
+                display.scene_extent(),
+                &display.render_pass,
+                mode,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The descriptors for a debug pipeline, which only reads the camera.
+fn camera_set(
+    pipeline: &Arc<DynPipeline>,
+    camera_buffer: impl BufferAccess + Send + Sync + 'static,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("mesh debug pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_buffer(camera_buffer)?
+            .build()
+            .context("unable to create the mesh debug descriptors")?,
+    ))
+}
+
+/// Pack the first `MAX_LIGHTS` lights for the fragment shader.
+fn light_data(lights: &[Light]) -> fragment_shader::ty::Lights {
+    let mut data = fragment_shader::ty::Lights {
+        lights: [fragment_shader::ty::PointLight {
+            position_range: [0.0; 4],
+            color_intensity: [0.0; 4],
+        }; MAX_LIGHTS],
+        count: lights.len().min(MAX_LIGHTS) as u32,
+    };
+    for (packed, light) in data.lights.iter_mut().zip(lights) {
+        let [x, y, z] = light.position;
+        let [r, g, b] = light.color;
+        packed.position_range = [x, y, z, light.range];
+        packed.color_intensity = [r, g, b, light.intensity];
+    }
+    data
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the mesh vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the mesh fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<MeshVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [
+                swapchain_extent[0] as f32,
+                swapchain_extent[1] as f32,
+            ],
+            depth_range: 0.0..1.0,
+        }])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_alpha_blending()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the mesh pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the mesh graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+/// The pipeline which draws the meshes for the normals or wireframe debug
+/// view.
+fn create_debug_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+    mode: DebugViewMode,
+) -> Result<Arc<DynPipeline>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the mesh vertex shader")?;
+    let start = || -> Result<_> {
+        Ok(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<MeshVertex>()
+            .vertex_shader(vert.main_entry_point(), ())
+            .viewports(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [
+                    swapchain_extent[0] as f32,
+                    swapchain_extent[1] as f32,
+                ],
+                depth_range: 0.0..1.0,
+            }])
+            .depth_clamp(false)
+            .line_width(1.0)
+            .depth_write(false)
+            .sample_shading_disabled()
+            .blend_alpha_blending()
+            .triangle_list()
+            .render_pass(
+                Subpass::from(render_pass.clone(), 0)
+                    .context("could not create the mesh debug subpass")?,
+            ))
+    };
+    let pipeline: Arc<DynPipeline> = match mode {
+        DebugViewMode::Normals => {
+            let frag = normals_shader::Shader::load(device.clone())
+                .context("unable to load the mesh normals shader")?;
+            Arc::new(
+                start()?
+                    .fragment_shader(frag.main_entry_point(), ())
+                    .polygon_mode_fill()
+                    .build(device.clone())
+                    .context("could not create the mesh normals pipeline")?,
+            )
+        }
+        DebugViewMode::Wireframe => {
+            let frag = wireframe_shader::Shader::load(device.clone())
+                .context("unable to load the mesh wireframe shader")?;
+            Arc::new(
+                start()?
+                    .fragment_shader(frag.main_entry_point(), ())
+                    .polygon_mode_line()
+                    .build(device.clone())
+                    .context("could not create the mesh wireframe pipeline")?,
+            )
+        }
+        _ => bail!("the meshes have no pipeline for {:?}", mode),
+    };
+    Ok(pipeline)
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec3 pos;
+
+            layout(location = 0) out vec4 vertColor;
+            layout(location = 1) out vec3 worldPos;
+
+            layout(set = 0, binding = 0) uniform Camera {
+                mat4 view_projection;
+                vec2 resolution;
+                vec2 aspect_fix;
+            } camera;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 model;
+                vec4 color;
+            } pc;
+
+            void main() {
+                vec4 world = pc.model * vec4(pos, 1.0);
+                vertColor = pc.color;
+                worldPos = world.xyz;
+                gl_Position = camera.view_projection * world;
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec4 fragColor;
+            layout(location = 1) in vec3 worldPos;
+            layout(location = 0) out vec4 outColor;
+
+            struct PointLight {
+                vec4 position_range;
+                vec4 color_intensity;
+            };
+
+            layout(set = 0, binding = 1) uniform Lights {
+                PointLight lights[8];
+                uint count;
+            } lighting;
+
+            // how much light reaches a mesh which no light is near, once
+            // there is any light at all
+            const float AMBIENT = 0.1;
+
+            void main() {
+                if (lighting.count == 0) {
+                    outColor = fragColor;
+                    return;
+                }
+                vec3 light = vec3(AMBIENT);
+                for (uint i = 0; i < min(lighting.count, 8u); i++) {
+                    vec4 position_range = lighting.lights[i].position_range;
+                    vec4 color_intensity = lighting.lights[i].color_intensity;
+                    float falloff = clamp(
+                        1.0 - distance(worldPos, position_range.xyz)
+                            / position_range.w,
+                        0.0,
+                        1.0
+                    );
+                    light += color_intensity.rgb * color_intensity.w
+                        * falloff * falloff;
+                }
+                outColor = vec4(fragColor.rgb * light, fragColor.a);
+            }
+            "#
+    }
+}
+
+/// Flat normals from the derivatives of the world position, the meshes
+/// don't have normals of their own.
+mod normals_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 1) in vec3 worldPos;
+            layout(location = 0) out vec4 outColor;
+
+            void main() {
+                vec3 normal = normalize(cross(dFdx(worldPos), dFdy(worldPos)));
+                outColor = vec4(normal * 0.5 + 0.5, 1.0);
+            }
+            "#
+    }
+}
+
+mod wireframe_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) out vec4 outColor;
+
+            void main() {
+                outColor = vec4(0.2, 1.0, 0.6, 1.0);
+            }
+            "#
+    }
+}
@@ -0,0 +1,397 @@
+use super::{DrawList, MeshVertex};
+use crate::application::camera::Camera;
+use crate::application::debug::BarrierValidator;
+use crate::application::transform::TransformHierarchy;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState, SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{
+    Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass,
+};
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::pipeline::{
+    viewport::Viewport, GraphicsPipeline, GraphicsPipelineAbstract,
+};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynFramebuffer = dyn FramebufferAbstract + Send + Sync;
+
+/// Every draw's id is written to a single unsigned integer channel.
+const ID_FORMAT: Format = Format::R32Uint;
+
+/// The id buffer's resolution as a fraction of the scene extent. Only one
+/// pixel is ever read, so it doesn't need to match the scene.
+pub const PICK_SCALE: f32 = 0.5;
+
+/// Which pixel of the id buffer is under the cursor.
+///
+/// The scene is always stretched over the whole window, even when it's drawn
+/// at a lower resolution, so the cursor maps across proportionally. There's
+/// no letterbox to offset by.
+///
+/// @param cursor the cursor's position in the window, in physical pixels
+/// @param window_extent the window's size in physical pixels
+/// @param pick_extent the id buffer's size
+pub fn pick_pixel(
+    cursor: [f32; 2],
+    window_extent: [u32; 2],
+    pick_extent: [u32; 2],
+) -> Option<[u32; 2]> {
+    let mut pixel = [0; 2];
+    for axis in 0..2 {
+        let fraction = cursor[axis] / window_extent[axis] as f32;
+        if !(0.0..1.0).contains(&fraction) {
+            return None;
+        }
+        pixel[axis] = ((fraction * pick_extent[axis] as f32) as u32)
+            .min(pick_extent[axis] - 1);
+    }
+    Some(pixel)
+}
+
+/// Finds which draw is under the cursor.
+///
+/// Each frame the draw list is drawn again into a single sampled id buffer,
+/// with every fragment writing its draw's id, and the pixel under the cursor
+/// is copied into a small host visible buffer. The copy is read at the start
+/// of the next frame, so the pick always lags a frame behind. Reading never
+/// waits for the gpu, if the copy somehow isn't finished the previous pick is
+/// kept.
+///
+/// The display's multisampling doesn't matter here: the id buffer has one
+/// sample per pixel, so every pixel holds exactly one draw's id rather than
+/// a blend of the ids along an edge.
+pub struct PickingPass {
+    render_pass: Arc<DynRenderPass>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    image: Arc<AttachmentImage<Format>>,
+    framebuffer: Arc<DynFramebuffer>,
+    extent: [u32; 2],
+    camera_pool: CpuBufferPool<vertex_shader::ty::Camera>,
+    readback: Arc<CpuAccessibleBuffer<[u32]>>,
+
+    // true when the last frame copied an id which hasn't been read yet
+    pending: bool,
+    picked: Option<u32>,
+}
+
+impl PickingPass {
+    pub fn new(display: &Display) -> Result<Self> {
+        let render_pass: Arc<DynRenderPass> = Arc::new(
+            vulkano::single_pass_renderpass!(
+                display.device.clone(),
+                attachments: {
+                    ids: {
+                        load: Clear,
+                        store: Store,
+                        format: ID_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [ids],
+                    depth_stencil: {}
+                }
+            )
+            .context("unable to create the picking render pass")?,
+        );
+        let extent = pick_extent(display);
+        let (image, framebuffer) =
+            create_id_buffer(&display.device, &render_pass, extent)?;
+        let pipeline =
+            create_graphics_pipeline(&display.device, extent, &render_pass)?;
+        let readback = CpuAccessibleBuffer::from_iter(
+            display.device.clone(),
+            BufferUsage::transfer_destination(),
+            true,
+            std::iter::once(0u32),
+        )
+        .context("unable to create the picking readback buffer")?;
+        Ok(Self {
+            render_pass,
+            pipeline,
+            image,
+            framebuffer,
+            extent,
+            camera_pool: CpuBufferPool::uniform_buffer(display.device.clone()),
+            readback,
+            pending: false,
+            picked: None,
+        })
+    }
+
+    /// The id buffer's size.
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// The id under the cursor as of the last frame, or nothing when the
+    /// cursor was outside the window or over no draw.
+    pub fn picked(&mut self) -> Option<u32> {
+        if self.pending {
+            if let Ok(ids) = self.readback.read() {
+                self.picked = Some(ids[0]).filter(|&id| id != 0);
+                self.pending = false;
+            }
+        }
+        self.picked
+    }
+
+    /// Build a primary command buffer which draws every id and copies the
+    /// one at `pixel` for the next frame to read.
+    ///
+    /// There's nothing to draw when the cursor is outside the window, so no
+    /// command buffer is built and the next pick is nothing.
+    pub fn render(
+        &mut self,
+        display: &Display,
+        camera: &Camera,
+        draw_list: &DrawList,
+        transforms: &TransformHierarchy,
+        pixel: Option<[u32; 2]>,
+    ) -> Result<Option<AutoCommandBuffer>> {
+        let [x, y] = match pixel {
+            Some(pixel) => pixel,
+            None => {
+                self.pending = false;
+                self.picked = None;
+                return Ok(None);
+            }
+        };
+        let camera_buffer = self
+            .camera_pool
+            .next(vertex_shader::ty::Camera {
+                view_projection: camera.view_projection(),
+                resolution: camera.resolution,
+                aspect_fix: camera.aspect_fix(),
+            })
+            .context("unable to upload the picking camera")?;
+        let layout = self
+            .pipeline
+            .descriptor_set_layout(0)
+            .context("picking pipeline has no descriptor set layout")?;
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_buffer(camera_buffer)?
+                .build()
+                .context("unable to create the picking descriptors")?,
+        );
+
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the picking command buffer builder")?,
+            &display.graphics_queue,
+        );
+        builder
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                SubpassContents::Inline,
+                vec![ClearValue::Uint([0, 0, 0, 0])],
+            )
+            .context("unable to begin the picking render pass")?;
+        for draw in &draw_list.draws {
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![draw.mesh.vertices.clone()],
+                    descriptor_set.clone(),
+                    vertex_shader::ty::PushConstantData {
+                        model: transforms.world(draw.transform_id)?,
+                        id: draw.id,
+                    },
+                )
+                .context("unable to draw a mesh's id")?;
+        }
+        builder
+            .end_render_pass()
+            .context("unable to end the picking render pass")?;
+        builder
+            .copy_image_to_buffer_dimensions(
+                self.image.clone(),
+                self.readback.clone(),
+                [x, y, 0],
+                [1, 1, 1],
+                0,
+                1,
+                0,
+            )
+            .context("unable to copy the picked id")?;
+        self.pending = true;
+        let commands = builder
+            .build()
+            .context("unable to build the picking command buffer")?;
+        Ok(Some(commands))
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.extent = pick_extent(display);
+        let (image, framebuffer) =
+            create_id_buffer(&display.device, &self.render_pass, self.extent)?;
+        self.image = image;
+        self.framebuffer = framebuffer;
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            self.extent,
+            &self.render_pass,
+        )?;
+        self.pending = false;
+        Ok(())
+    }
+}
+
+/// The scene extent scaled down by `PICK_SCALE`, at least a pixel across.
+fn pick_extent(display: &Display) -> [u32; 2] {
+    let [width, height] = display.scene_extent();
+    let scale = |size: u32| ((size as f32 * PICK_SCALE) as u32).max(1);
+    [scale(width), scale(height)]
+}
+
+fn create_id_buffer(
+    device: &Arc<Device>,
+    render_pass: &Arc<DynRenderPass>,
+    extent: [u32; 2],
+) -> Result<(Arc<AttachmentImage<Format>>, Arc<DynFramebuffer>)> {
+    let image = AttachmentImage::with_usage(
+        device.clone(),
+        extent,
+        ID_FORMAT,
+        ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            ..ImageUsage::none()
+        },
+    )
+    .context("unable to create the id buffer")?;
+    let framebuffer = Arc::new(
+        Framebuffer::start(render_pass.clone())
+            .add(image.clone())?
+            .build()
+            .context("unable to create the picking framebuffer")?,
+    );
+    Ok((image, framebuffer))
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the picking vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the picking fragment shader")?;
+
+    // integer attachments can't be blended, later draws simply overwrite
+    // earlier ones just as they cover them in the scene
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<MeshVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        }])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the picking subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the picking graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec3 pos;
+
+            layout(location = 0) flat out uint vertId;
+
+            layout(set = 0, binding = 0) uniform Camera {
+                mat4 view_projection;
+                vec2 resolution;
+                vec2 aspect_fix;
+            } camera;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 model;
+                uint id;
+            } pc;
+
+            void main() {
+                vertId = pc.id;
+                gl_Position = camera.view_projection * pc.model
+                    * vec4(pos, 1.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) flat in uint fragId;
+            layout(location = 0) out uint outId;
+
+            void main() {
+                outId = fragId;
+            }
+            "#
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The id buffer is smaller than the window, so the corners, the center,
+    /// and positions just outside the window check the scaling and clamping.
+    #[test]
+    fn cursor_positions_map_onto_the_id_buffer() {
+        let window = [800, 600];
+        let ids = [400, 300];
+        let cases = [
+            ("top left", [0.0, 0.0], Some([0, 0])),
+            ("center", [400.0, 300.0], Some([200, 150])),
+            ("bottom right", [799.9, 599.9], Some([399, 299])),
+            ("odd pixel", [3.0, 5.0], Some([1, 2])),
+            ("left of the window", [-1.0, 10.0], None),
+            ("below the window", [10.0, 600.0], None),
+        ];
+        for &(name, cursor, expected) in cases.iter() {
+            assert_eq!(pick_pixel(cursor, window, ids), expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use super::camera::Camera;
+use super::events::AppEvent;
+use super::meshes::{DrawList, Material, Mesh, MeshRenderer};
+use super::transform::{Transform, TransformHierarchy, TransformId};
+use crate::display::Display;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::runtime::Runtime;
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event_loop::EventLoopProxy;
+
+/// The largest datagram which is read from the server.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// How long to wait before receiving again after an error.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// The colors given to remote objects, picked by id.
+const OBJECT_COLORS: [[f32; 4]; 4] = [
+    [0.9, 0.3, 0.3, 1.0],
+    [0.3, 0.9, 0.4, 1.0],
+    [0.3, 0.5, 0.9, 1.0],
+    [0.9, 0.8, 0.3, 1.0],
+];
+
+/// What the client sends to the server every frame.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientState {
+    pub camera_pos: [f32; 3],
+
+    /// A unit quaternion stored as `[x, y, z, w]`.
+    pub camera_rot: [f32; 4],
+
+    pub frame: u64,
+}
+
+/// One object owned by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObjectState {
+    pub id: u32,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl ObjectState {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.translation,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+/// A snapshot of every object the server knows about.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerState {
+    pub objects: Vec<ObjectState>,
+}
+
+/// Exchanges bincode encoded states with a server over UDP.
+///
+/// The socket lives on a small tokio runtime owned by the client. One task
+/// sends the states queued by `send_frame` and another decodes whatever the
+/// server sends back and hands it to the main thread through a channel, so
+/// the render loop never blocks on the network. Dropping the client shuts
+/// the runtime and both tasks down.
+///
+/// Given an event loop proxy, each state which arrives also sends a
+/// `RequestRedraw` event, so a waiting event loop wakes up to draw it.
+pub struct NetworkClient {
+    _runtime: Runtime,
+    outgoing: tokio::sync::mpsc::UnboundedSender<ClientState>,
+    incoming: mpsc::Receiver<ServerState>,
+    frame: u64,
+}
+
+impl NetworkClient {
+    pub fn connect(
+        addr: SocketAddr,
+        events: Option<EventLoopProxy<AppEvent>>,
+    ) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_io()
+            .enable_time()
+            .build()
+            .context("unable to start the network runtime")?;
+        let socket = runtime.block_on(async {
+            let local: SocketAddr = match addr {
+                SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+                SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+            };
+            let socket = UdpSocket::bind(local)
+                .await
+                .context("unable to bind the client socket")?;
+            socket
+                .connect(addr)
+                .await
+                .with_context(|| format!("unable to connect to {}", addr))?;
+            Ok::<_, anyhow::Error>(Arc::new(socket))
+        })?;
+        log::info!(
+            "sending client state from {:?} to {}",
+            socket.local_addr().ok(),
+            addr
+        );
+
+        let (outgoing, mut to_send) = tokio::sync::mpsc::unbounded_channel();
+        let sender = socket.clone();
+        runtime.spawn(async move {
+            while let Some(state) = to_send.recv().await {
+                let result = bincode::serialize(&state)
+                    .context("unable to encode the client state");
+                let sent = match result {
+                    Ok(bytes) => sender
+                        .send(&bytes)
+                        .await
+                        .context("unable to send the client state"),
+                    Err(error) => Err(error),
+                };
+                if let Err(error) = sent {
+                    log::warn!("{:?}", error);
+                }
+            }
+        });
+
+        let (received, incoming) = mpsc::channel();
+        runtime.spawn(async move {
+            let mut buffer = vec![0u8; MAX_DATAGRAM];
+            loop {
+                let length = match socket.recv(&mut buffer).await {
+                    Ok(length) => length,
+                    Err(error) => {
+                        // usually nothing is listening yet, try again shortly
+                        log::trace!("unable to receive: {}", error);
+                        tokio::time::sleep(RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                match bincode::deserialize::<ServerState>(&buffer[..length]) {
+                    Ok(state) => {
+                        if received.send(state).is_err() {
+                            break;
+                        }
+                        if let Some(events) = &events {
+                            // the event loop has exited if this fails
+                            let _ = events.send_event(AppEvent::RequestRedraw);
+                        }
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "unable to decode the server state {}",
+                            error
+                        )
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            _runtime: runtime,
+            outgoing,
+            incoming,
+            frame: 0,
+        })
+    }
+
+    /// Queue this frame's state to be sent to the server.
+    pub fn send_frame(
+        &mut self,
+        camera_pos: [f32; 3],
+        camera_rot: [f32; 4],
+    ) -> Result<()> {
+        let state = ClientState {
+            camera_pos,
+            camera_rot,
+            frame: self.frame,
+        };
+        self.frame += 1;
+        self.outgoing
+            .send(state)
+            .context("the network send task has stopped")
+    }
+
+    /// The most recent state from the server, if any arrived since the last
+    /// call. Older states are skipped.
+    pub fn latest_state(&self) -> Option<ServerState> {
+        self.incoming.try_iter().last()
+    }
+}
+
+/// The objects described by the server, drawn as discs.
+///
+/// Objects are created the first time their id appears. Each state is a
+/// full snapshot, but objects missing from a state are kept where they were
+/// last seen since a lost datagram is more likely than a removal.
+pub struct RemoteObjects {
+    transforms: TransformHierarchy,
+    ids: HashMap<u32, TransformId>,
+    mesh: Mesh,
+    draw_list: DrawList,
+    renderer: MeshRenderer,
+}
+
+impl RemoteObjects {
+    pub fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            transforms: TransformHierarchy::default(),
+            ids: HashMap::new(),
+            mesh: Mesh::disc(&display.device, 24)?,
+            draw_list: DrawList::default(),
+            renderer: MeshRenderer::new(display)?,
+        })
+    }
+
+    /// Move every object in the state, adding any which are new.
+    pub fn apply(&mut self, state: &ServerState) -> Result<()> {
+        for object in &state.objects {
+            match self.ids.get(&object.id) {
+                Some(&id) => {
+                    self.transforms.set_local(id, object.transform())?
+                }
+                None => {
+                    let id = self.transforms.add(object.transform(), None)?;
+                    let color =
+                        OBJECT_COLORS[object.id as usize % OBJECT_COLORS.len()];
+                    self.draw_list.draw_mesh(
+                        &self.mesh,
+                        Material { color },
+                        id,
+                    );
+                    self.ids.insert(object.id, id);
+                }
+            }
+        }
+        self.transforms.resolve()
+    }
+
+    /// Build a secondary command buffer which draws every object.
+    pub fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        self.renderer
+            .draw(display, camera, &self.draw_list, &self.transforms)
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// How long to wait for either side to hear from the other.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// A stand-in server on the loopback interface waits for a client state,
+    /// checks that it decodes to what was sent, then replies with a server
+    /// state which must come out of `latest_state` unchanged.
+    #[test]
+    fn states_round_trip_through_a_server() -> Result<()> {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0")
+            .context("unable to bind the test server socket")?;
+        server
+            .set_read_timeout(Some(TIMEOUT))
+            .context("unable to set the test server timeout")?;
+        let addr = server.local_addr()?;
+
+        let mut client = NetworkClient::connect(addr, None)?;
+        client.send_frame([1.0, 2.0, 3.0], [0.0, 0.0, 0.0, 1.0])?;
+
+        let mut buffer = vec![0u8; 1024];
+        let (length, client_addr) = server
+            .recv_from(&mut buffer)
+            .context("the test server never heard from the client")?;
+        let received: ClientState = bincode::deserialize(&buffer[..length])
+            .context("unable to decode the client state")?;
+        assert_eq!(received.camera_pos, [1.0, 2.0, 3.0]);
+        assert_eq!(received.frame, 0);
+
+        let reply = ServerState {
+            objects: vec![ObjectState {
+                id: 7,
+                translation: [0.5, -0.5, 0.0],
+                rotation: [0.0, 0.0, 0.0, 1.0],
+                scale: [0.1, 0.1, 0.1],
+            }],
+        };
+        let bytes = bincode::serialize(&reply)
+            .context("unable to encode the server state")?;
+        server
+            .send_to(&bytes, client_addr)
+            .context("unable to reply to the client")?;
+
+        let start = Instant::now();
+        let state = loop {
+            if let Some(state) = client.latest_state() {
+                break state;
+            }
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "the client never received the server state"
+            );
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        match state.objects.as_slice() {
+            [object] => {
+                assert_eq!(object.id, 7);
+                assert_eq!(object.translation, [0.5, -0.5, 0.0]);
+            }
+            objects => panic!("the server sent one object, not {:?}", objects),
+        }
+        Ok(())
+    }
+}
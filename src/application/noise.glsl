@@ -0,0 +1,147 @@
+// Procedural noise functions for compute and fragment shaders.
+//
+// Every function takes a seed and is deterministic, the same point and seed
+// always produce the same value on every device. Value and Worley noise are
+// in [0, 1], Perlin and simplex noise are in about [-1, 1].
+
+#ifndef NOISE_GLSL
+#define NOISE_GLSL
+
+const int NOISE_VALUE = 0;
+const int NOISE_PERLIN = 1;
+const int NOISE_SIMPLEX = 2;
+const int NOISE_WORLEY = 3;
+
+// A well mixed 32 bit hash, from the PCG family.
+uint noise_hash(uint x) {
+    uint state = x * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+uint noise_hash_cell(ivec2 cell, uint seed) {
+    uint h = noise_hash(uint(cell.x) ^ noise_hash(seed));
+    return noise_hash(uint(cell.y) ^ h);
+}
+
+// A hash of the lattice cell mapped to [0, 1].
+float noise_random(ivec2 cell, uint seed) {
+    return float(noise_hash_cell(cell, seed)) / 4294967295.0;
+}
+
+// A unit length gradient for the lattice cell.
+vec2 noise_gradient(ivec2 cell, uint seed) {
+    float angle = noise_random(cell, seed) * 6.28318530718;
+    return vec2(cos(angle), sin(angle));
+}
+
+// The quintic fade curve, its first and second derivatives are 0 at the
+// cell edges.
+vec2 noise_fade(vec2 t) {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+// Smoothly interpolated random values at the lattice points.
+float value_noise(vec2 p, uint seed) {
+    ivec2 cell = ivec2(floor(p));
+    vec2 f = noise_fade(fract(p));
+    float a = noise_random(cell, seed);
+    float b = noise_random(cell + ivec2(1, 0), seed);
+    float c = noise_random(cell + ivec2(0, 1), seed);
+    float d = noise_random(cell + ivec2(1, 1), seed);
+    return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+// Classic Perlin gradient noise.
+float perlin_noise(vec2 p, uint seed) {
+    ivec2 cell = ivec2(floor(p));
+    vec2 t = fract(p);
+    vec2 f = noise_fade(t);
+    float a = dot(noise_gradient(cell, seed), t);
+    float b = dot(noise_gradient(cell + ivec2(1, 0), seed), t - vec2(1, 0));
+    float c = dot(noise_gradient(cell + ivec2(0, 1), seed), t - vec2(0, 1));
+    float d = dot(noise_gradient(cell + ivec2(1, 1), seed), t - vec2(1, 1));
+
+    // unit gradients give at most sqrt(1/2), rescale to about [-1, 1]
+    return mix(mix(a, b, f.x), mix(c, d, f.x), f.y) * 1.41421356;
+}
+
+// 2D simplex noise on a skewed triangular lattice.
+float simplex_noise(vec2 p, uint seed) {
+    const float F2 = 0.36602540378; // (sqrt(3) - 1) / 2
+    const float G2 = 0.21132486540; // (3 - sqrt(3)) / 6
+
+    vec2 skewed = floor(p + (p.x + p.y) * F2);
+    ivec2 cell = ivec2(skewed);
+    vec2 x0 = p - (skewed - (skewed.x + skewed.y) * G2);
+    ivec2 corner = x0.x > x0.y ? ivec2(1, 0) : ivec2(0, 1);
+    vec2 x1 = x0 - vec2(corner) + G2;
+    vec2 x2 = x0 - 1.0 + 2.0 * G2;
+
+    float total = 0.0;
+    float t0 = max(0.5 - dot(x0, x0), 0.0);
+    total += pow(t0, 4.0) * dot(noise_gradient(cell, seed), x0);
+    float t1 = max(0.5 - dot(x1, x1), 0.0);
+    total += pow(t1, 4.0) * dot(noise_gradient(cell + corner, seed), x1);
+    float t2 = max(0.5 - dot(x2, x2), 0.0);
+    total += pow(t2, 4.0) * dot(noise_gradient(cell + ivec2(1), seed), x2);
+
+    // the largest possible sum for unit gradients is about 1/70
+    return total * 70.0;
+}
+
+// The distance to the nearest of one random feature point per cell.
+float worley_noise(vec2 p, uint seed) {
+    ivec2 cell = ivec2(floor(p));
+    vec2 f = fract(p);
+    float nearest = 1.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            ivec2 neighbor = cell + ivec2(x, y);
+            vec2 feature = vec2(
+                noise_random(neighbor, seed),
+                noise_random(neighbor, seed ^ 0x68bc21ebu));
+            vec2 offset = vec2(x, y) + feature - f;
+            nearest = min(nearest, length(offset));
+        }
+    }
+    return nearest;
+}
+
+// A single octave of the selected noise type.
+float noise_of_type(int noise_type, vec2 p, uint seed) {
+    if (noise_type == NOISE_PERLIN) {
+        return perlin_noise(p, seed);
+    } else if (noise_type == NOISE_SIMPLEX) {
+        return simplex_noise(p, seed);
+    } else if (noise_type == NOISE_WORLEY) {
+        return worley_noise(p, seed);
+    }
+    return value_noise(p, seed);
+}
+
+// Fractal Brownian motion, a sum of octaves which each scale the frequency
+// by lacunarity and the amplitude by gain. The sum is normalized so it keeps
+// the range of a single octave.
+float fbm(
+    int noise_type,
+    vec2 p,
+    uint seed,
+    int octaves,
+    float lacunarity,
+    float gain
+) {
+    float total = 0.0;
+    float amplitude = 1.0;
+    float amplitude_sum = 0.0;
+    for (int octave = 0; octave < octaves; octave++) {
+        uint octave_seed = seed + uint(octave) * 0x9e3779b9u;
+        total += amplitude * noise_of_type(noise_type, p, octave_seed);
+        amplitude_sum += amplitude;
+        p *= lacunarity;
+        amplitude *= gain;
+    }
+    return amplitude_sum > 0.0 ? total / amplitude_sum : 0.0;
+}
+
+#endif
@@ -0,0 +1,259 @@
+use crate::application::debug::BarrierValidator;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::device::{Device, Queue};
+use vulkano::format::Format;
+use vulkano::image::{Dimensions, ImmutableImage, MipmapsCount};
+use vulkano::pipeline::{ComputePipeline, ComputePipelineAbstract};
+use vulkano::sync::GpuFuture;
+
+type DynComputePipeline = dyn ComputePipelineAbstract + Send + Sync;
+
+/// The width and height of a single workgroup in the noise shader.
+const TILE_SIZE: u32 = 8;
+
+/// The number of noise cells across the shorter side of the texture in the
+/// first octave.
+const BASE_FREQUENCY: f32 = 4.0;
+
+/// The noise functions in `noise.glsl`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoiseType {
+    /// Smoothly interpolated random values, in [0, 1].
+    Value,
+
+    /// Classic gradient noise, in about [-1, 1].
+    Perlin,
+
+    /// Gradient noise on a triangular lattice, in about [-1, 1].
+    Simplex,
+
+    /// The distance to the nearest random feature point, in [0, 1].
+    Worley,
+}
+
+impl NoiseType {
+    pub const ALL: [NoiseType; 4] = [
+        NoiseType::Value,
+        NoiseType::Perlin,
+        NoiseType::Simplex,
+        NoiseType::Worley,
+    ];
+
+    /// The matching `NOISE_*` constant in `noise.glsl`.
+    fn shader_id(self) -> i32 {
+        match self {
+            NoiseType::Value => 0,
+            NoiseType::Perlin => 1,
+            NoiseType::Simplex => 2,
+            NoiseType::Worley => 3,
+        }
+    }
+}
+
+/// Fills `R32Sfloat` textures with fractal noise on the gpu.
+///
+/// Every texture made by one generator uses the same seed, so generating the
+/// same noise twice gives identical textures.
+pub struct NoiseTextureGenerator {
+    seed: u32,
+    pipeline: Arc<DynComputePipeline>,
+}
+
+impl NoiseTextureGenerator {
+    pub fn new(device: &Arc<Device>, seed: u32) -> Result<Self> {
+        let pipeline = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &noise_shader::Shader::load(device.clone())
+                    .context("unable to load the noise shader")?
+                    .main_entry_point(),
+                &(),
+                None,
+            )
+            .context("unable to create the noise pipeline")?,
+        );
+        Ok(Self { seed, pipeline })
+    }
+
+    /// Generate a texture of fractal Brownian motion.
+    ///
+    /// Each of the `octaves` scales the frequency by `lacunarity` and the
+    /// amplitude by `gain`. The sum is normalized, so the texels stay in the
+    /// range of a single octave of the noise type.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        width: u32,
+        height: u32,
+        noise_type: NoiseType,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    ) -> Result<Arc<ImmutableImage<Format>>> {
+        let values = self.compute_values(
+            device, queue, width, height, noise_type, octaves, lacunarity, gain,
+        )?;
+        let (image, upload) = ImmutableImage::from_buffer(
+            values,
+            Dimensions::Dim2d { width, height },
+            MipmapsCount::One,
+            Format::R32Sfloat,
+            queue.clone(),
+        )
+        .context("unable to create the noise texture")?;
+        upload
+            .then_signal_fence_and_flush()
+            .context("unable to upload the noise texture")?
+            .wait(None)
+            .context("unable to upload the noise texture")?;
+        Ok(image)
+    }
+
+    /// Generate the same noise as `generate` and read it back in
+    /// row-major order.
+    #[allow(clippy::too_many_arguments)]
+    pub fn generate_values(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        width: u32,
+        height: u32,
+        noise_type: NoiseType,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    ) -> Result<Vec<f32>> {
+        let values = self.compute_values(
+            device, queue, width, height, noise_type, octaves, lacunarity, gain,
+        )?;
+        let values = values.read().context("unable to read the noise")?;
+        Ok(values.to_vec())
+    }
+
+    /// Run the noise shader and wait for it to finish.
+    #[allow(clippy::too_many_arguments)]
+    fn compute_values(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        width: u32,
+        height: u32,
+        noise_type: NoiseType,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    ) -> Result<Arc<CpuAccessibleBuffer<[f32]>>> {
+        let values = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage {
+                storage_buffer: true,
+                transfer_source: true,
+                ..BufferUsage::none()
+            },
+            false,
+            (0..width * height).map(|_| 0.0f32),
+        )
+        .context("unable to create the noise buffer")?;
+        let set = Arc::new(
+            PersistentDescriptorSet::start(
+                self.pipeline
+                    .descriptor_set_layout(0)
+                    .context("noise pipeline has no descriptor set")?
+                    .clone(),
+            )
+            .add_buffer(values.clone())?
+            .build()
+            .context("unable to create the noise descriptors")?,
+        );
+
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                queue.family(),
+            )
+            .context("unable to create the noise command buffer builder")?,
+            &queue,
+        );
+        builder
+            .dispatch(
+                [width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1],
+                self.pipeline.clone(),
+                set,
+                noise_shader::ty::PushConstantData {
+                    width,
+                    height,
+                    noise_type: noise_type.shader_id(),
+                    octaves: octaves as i32,
+                    frequency: BASE_FREQUENCY / width.min(height) as f32,
+                    lacunarity,
+                    gain,
+                    seed: self.seed,
+                },
+            )
+            .context("unable to dispatch the noise shader")?;
+        builder
+            .build()
+            .context("unable to build the noise command buffer")?
+            .execute(queue.clone())
+            .context("unable to execute the noise shader")?
+            .then_signal_fence_and_flush()
+            .context("unable to flush the noise shader")?
+            .wait(None)
+            .context("unable to wait for the noise shader")?;
+        Ok(values)
+    }
+}
+
+mod noise_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        include: ["src/application"],
+        src: r#"
+            #version 450
+            #extension GL_GOOGLE_include_directive : require
+
+            #include <noise.glsl>
+
+            layout(local_size_x = 8, local_size_y = 8) in;
+
+            layout(set = 0, binding = 0) buffer Values {
+                float values[];
+            };
+
+            layout(push_constant) uniform PushConstantData {
+                uint width;
+                uint height;
+                int noise_type;
+                int octaves;
+                float frequency;
+                float lacunarity;
+                float gain;
+                uint seed;
+            } push;
+
+            void main() {
+                uvec2 texel = gl_GlobalInvocationID.xy;
+                if (texel.x >= push.width || texel.y >= push.height) {
+                    return;
+                }
+
+                vec2 p = (vec2(texel) + 0.5) * push.frequency;
+                values[texel.y * push.width + texel.x] = fbm(
+                    push.noise_type,
+                    p,
+                    push.seed,
+                    push.octaves,
+                    push.lacunarity,
+                    push.gain
+                );
+            }
+        "#
+    }
+}
@@ -0,0 +1,136 @@
+use crate::application::debug::BarrierValidator;
+use crate::application::render_graph::TransientDesc;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, SubpassContents,
+};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{Framebuffer, FramebufferAbstract};
+use vulkano::image::{AttachmentImage, Dimensions, ImageUsage, StorageImage};
+
+type DynFramebuffer = dyn FramebufferAbstract + Send + Sync;
+
+/// An image which the scene can be rendered into and then sampled by a later
+/// pass, usually the size of the swapchain.
+///
+/// The target uses the display's render pass, so every pipeline built for the
+/// display can draw into it without changes.
+pub struct OffscreenTarget {
+    pub image: Arc<StorageImage<Format>>,
+    framebuffer: Arc<DynFramebuffer>,
+}
+
+impl OffscreenTarget {
+    /// A target the size of the swapchain.
+    pub fn new(display: &Display) -> Result<Self> {
+        Self::with_extent(display, display.swapchain.dimensions())
+    }
+
+    /// What a target the size of the swapchain is, so the render graph can
+    /// plan which targets share memory. Its bytes include the multisampled
+    /// image it renders through.
+    pub fn desc(display: &Display) -> Result<TransientDesc> {
+        let [width, height] = display.swapchain.dimensions();
+        let format = display.swapchain.format();
+        let samples = display
+            .render_pass
+            .num_samples(0)
+            .context("the render pass has no intermediary attachment")?;
+        let texel = format.size().unwrap_or(4) as u64;
+        Ok(TransientDesc {
+            extent: [width, height],
+            format,
+            samples,
+            bytes: width as u64 * height as u64 * texel * (1 + samples as u64),
+        })
+    }
+
+    /// A target with any extent, like the scene extent when the scene is
+    /// drawn below the swapchain's resolution.
+    pub fn with_extent(display: &Display, extent: [u32; 2]) -> Result<Self> {
+        let [width, height] = extent;
+        let format = display.swapchain.format();
+        let samples = display
+            .render_pass
+            .num_samples(0)
+            .context("the render pass has no intermediary attachment")?;
+
+        // storage images stay in the general layout, so they can be sampled
+        // as soon as the render pass completes, or copied when the frame is
+        // captured
+        let image = StorageImage::with_usage(
+            display.device.clone(),
+            Dimensions::Dim2d { width, height },
+            format,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            },
+            Some(display.graphics_queue.family()),
+        )
+        .context("unable to create the offscreen image")?;
+        let intermediary = AttachmentImage::transient_multisampled(
+            display.device.clone(),
+            [width, height],
+            samples,
+            format,
+        )
+        .context("unable to create the offscreen multisample image")?;
+        let framebuffer = Arc::new(
+            Framebuffer::start(display.render_pass.clone())
+                .add(intermediary)?
+                .add(image.clone())?
+                .build()
+                .context("unable to create the offscreen framebuffer")?,
+        );
+
+        Ok(Self { image, framebuffer })
+    }
+
+    /// Build a primary command buffer which renders secondary command
+    /// buffers into the target's image.
+    pub fn render(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the offscreen command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        builder
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                SubpassContents::SecondaryCommandBuffers,
+                vec![
+                    ClearValue::Float([0.0, 0.0, 0.0, 1.0]),
+                    ClearValue::Float([0.0, 0.0, 0.0, 1.0]),
+                ],
+            )
+            .context("unable to begin the offscreen render pass")?;
+
+        unsafe {
+            // unsafe because vulkano does not check synchronization between
+            // subbuffers and the main.
+            builder
+                .execute_commands_from_vec(graphics_queue_subbuffers)
+                .context("error while rendering the offscreen commands")?;
+        }
+
+        builder
+            .end_render_pass()
+            .context("unable to end the offscreen render pass")?;
+        builder
+            .build()
+            .context("unable to build the offscreen command buffer")
+    }
+}
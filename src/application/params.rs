@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The most edits which can be undone.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Edits to the same parameter closer together than this are undone
+/// together, so dragging a value undoes in one step rather than one per
+/// frame.
+pub const COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+/// One parameter's value before and after an edit, nothing before means the
+/// parameter didn't exist.
+#[derive(Debug, Clone)]
+struct Change {
+    key: String,
+    before: Option<f32>,
+    after: f32,
+}
+
+/// Everything one undo or redo puts back.
+#[derive(Debug, Clone)]
+struct Edit {
+    changes: Vec<Change>,
+
+    // when the edit last grew, coalescing is measured from here
+    at: Instant,
+
+    // false once the edit has been undone or redone, it can't grow after
+    open: bool,
+}
+
+impl Edit {
+    /// True when a change to `key` at `now` should join this edit.
+    fn coalesces(&self, key: &str, now: Instant, window: Duration) -> bool {
+        match self.changes.as_slice() {
+            [change] => {
+                self.open && change.key == key && now - self.at < window
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Named values which the user can change, and undo and redo changing.
+///
+/// Only edits made with `set_user` are recorded. Values written every frame
+/// by the application itself go through `set_auto`, otherwise the history
+/// would fill with them and undo would be taken back by the next frame.
+///
+/// A user edit made right after one to the same key joins it rather than
+/// starting a new entry, and a user edit after an undo drops everything
+/// which could have been redone. The oldest entries are forgotten once there
+/// are more than the history limit.
+pub struct Params {
+    values: BTreeMap<String, f32>,
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    history_limit: usize,
+    coalesce_window: Duration,
+}
+
+impl Params {
+    pub fn new() -> Self {
+        Self::with_history(HISTORY_LIMIT, COALESCE_WINDOW)
+    }
+
+    /// Keep at most `history_limit` edits, joining edits to the same key
+    /// within `coalesce_window` of each other.
+    pub fn with_history(
+        history_limit: usize,
+        coalesce_window: Duration,
+    ) -> Self {
+        Self {
+            values: BTreeMap::new(),
+            undo: VecDeque::new(),
+            redo: vec![],
+            history_limit,
+            coalesce_window,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.values.get(key).copied()
+    }
+
+    /// Every parameter by name.
+    pub fn values(&self) -> &BTreeMap<String, f32> {
+        &self.values
+    }
+
+    /// The number of edits which can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// The number of undone edits which can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Write a value without recording it, for changes the application makes
+    /// by itself.
+    pub fn set_auto(&mut self, key: &str, value: f32) {
+        self.values.insert(key.to_owned(), value);
+    }
+
+    /// Write a value the user chose, so it can be undone.
+    pub fn set_user(&mut self, key: &str, value: f32) {
+        let now = Instant::now();
+        let window = self.coalesce_window;
+        match self.undo.back_mut() {
+            Some(edit) if edit.coalesces(key, now, window) => {
+                edit.changes[0].after = value;
+                edit.at = now;
+                self.values.insert(key.to_owned(), value);
+                self.redo.clear();
+            }
+            _ => self.record(vec![(key.to_owned(), value)], now),
+        }
+    }
+
+    /// Write several values the user chose at once, like a restored
+    /// snapshot, which are undone together and never coalesced.
+    pub fn set_user_all(
+        &mut self,
+        values: impl IntoIterator<Item = (String, f32)>,
+    ) {
+        self.record(values.into_iter().collect(), Instant::now());
+    }
+
+    /// Put back the values from before the last edit, returning the keys
+    /// which changed.
+    pub fn undo(&mut self) -> Vec<String> {
+        let mut edit = match self.undo.pop_back() {
+            Some(edit) => edit,
+            None => return vec![],
+        };
+        // later changes to a key in one edit win, so undo them first
+        for change in edit.changes.iter().rev() {
+            match change.before {
+                Some(before) => {
+                    self.values.insert(change.key.clone(), before);
+                }
+                None => {
+                    self.values.remove(&change.key);
+                }
+            }
+        }
+        let keys = changed_keys(&edit);
+        edit.open = false;
+        self.redo.push(edit);
+        // the next edit starts a new entry, even to the same key
+        if let Some(previous) = self.undo.back_mut() {
+            previous.open = false;
+        }
+        keys
+    }
+
+    /// Apply the last undone edit again, returning the keys which changed.
+    pub fn redo(&mut self) -> Vec<String> {
+        let edit = match self.redo.pop() {
+            Some(edit) => edit,
+            None => return vec![],
+        };
+        for change in &edit.changes {
+            self.values.insert(change.key.clone(), change.after);
+        }
+        let keys = changed_keys(&edit);
+        self.undo.push_back(edit);
+        keys
+    }
+
+    fn record(&mut self, values: Vec<(String, f32)>, now: Instant) {
+        if values.is_empty() {
+            return;
+        }
+        let changes = values
+            .into_iter()
+            .map(|(key, after)| {
+                let before = self.values.insert(key.clone(), after);
+                Change { key, before, after }
+            })
+            .collect();
+        self.undo.push_back(Edit {
+            changes,
+            at: now,
+            open: true,
+        });
+        while self.undo.len() > self.history_limit {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The keys an edit changed, each once, in the order they were first
+/// changed.
+fn changed_keys(edit: &Edit) -> Vec<String> {
+    let mut keys: Vec<String> = vec![];
+    for change in &edit.changes {
+        if !keys.contains(&change.key) {
+            keys.push(change.key.clone());
+        }
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A drag's many small edits undo together, while edits to another
+    /// value or after a pause don't join them.
+    #[test]
+    fn drags_undo_in_one_step() {
+        let mut params = Params::with_history(10, Duration::from_millis(200));
+        params.set_auto("strength", 1.0);
+        for step in 1..=5 {
+            params.set_user("strength", 1.0 - 0.1 * step as f32);
+        }
+        // writes from the application itself are never recorded
+        params.set_auto("time", 3.0);
+        assert_eq!(params.undo_len(), 1);
+        params.set_user("aperture", 2.0);
+        std::thread::sleep(Duration::from_millis(300));
+        params.set_user("aperture", 4.0);
+        assert_eq!(params.undo_len(), 3);
+
+        params.undo();
+        params.undo();
+        assert_eq!(params.get("aperture"), None);
+        assert_eq!(params.undo(), ["strength"]);
+        assert_eq!(params.get("strength"), Some(1.0));
+        assert_eq!(params.get("time"), Some(3.0));
+    }
+
+    #[test]
+    fn only_the_newest_edits_are_kept() {
+        let mut params = Params::with_history(3, Duration::from_millis(0));
+        for (i, key) in ["a", "b", "c", "d", "e"].iter().enumerate() {
+            params.set_user(key, i as f32);
+        }
+        assert_eq!(params.undo_len(), 3);
+        let mut undone = vec![];
+        while params.undo_len() > 0 {
+            undone.extend(params.undo());
+        }
+        assert_eq!(undone, ["e", "d", "c"]);
+        assert_eq!(params.get("a"), Some(0.0));
+        assert_eq!(params.get("b"), Some(1.0));
+    }
+
+    /// Redo replays undone edits until the user makes a new one.
+    #[test]
+    fn new_edits_drop_what_could_be_redone() {
+        let mut params = Params::with_history(10, Duration::from_millis(0));
+        params.set_user("g", 1.0);
+        params.set_user("g", 2.0);
+        params.undo();
+        assert_eq!(params.redo(), ["g"]);
+        assert_eq!(params.get("g"), Some(2.0));
+        params.undo();
+        params.undo();
+        assert_eq!(params.redo_len(), 2);
+        params.set_user("softening", 0.5);
+        assert_eq!(params.redo_len(), 0);
+        assert!(params.redo().is_empty());
+
+        // a snapshot is one entry, however many values it restores
+        params.set_user_all(vec![
+            ("g".to_owned(), 5.0),
+            ("softening".to_owned(), 0.1),
+        ]);
+        params.undo();
+        assert_eq!(params.get("g"), None);
+        assert_eq!(params.get("softening"), Some(0.5));
+    }
+}
@@ -1,31 +1,66 @@
+use super::camera::Camera;
+use super::cull::{Bounds, Cull, CullStats, Rect};
+use super::live_resources::{LiveResource, ResourceKind};
+use super::post_process::{DistanceVertex, VelocityVertex};
 use crate::display::Display;
 use anyhow::{Context, Result};
+use std::cell::Cell;
+#[cfg(feature = "pipeline-cache")]
+use std::path::Path;
 use std::sync::Arc;
 use vulkano::command_buffer::{
     AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
 };
+use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::GraphicsPipelineAbstract;
 use vulkano::{buffer::cpu_pool::CpuBufferPool, framebuffer::Subpass};
 
 mod pipeline;
 
+pub use pipeline::{BlendMode, PipelineCache, PipelineStateDesc, RenderMode};
 pub type Vertex = pipeline::Vertex;
 
+/// Where the compiled particle pipelines are kept between runs.
+#[cfg(feature = "pipeline-cache")]
+pub const PIPELINE_CACHE_DIRECTORY: &str = "cache/pipelines";
+
+/// The size of a particle on screen, in pixels. Must match gl_PointSize in the
+/// vertex shader.
+const POINT_SIZE: f32 = 64.0;
+
 pub struct Particles {
     pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    pipelines: PipelineCache,
+    blend_mode: BlendMode,
 
     // vertex buffers
     vertex_buffer_pool: CpuBufferPool<Vertex>,
 
     // vertices
     pub vertices: Vec<Vertex>,
+    previous_vertices: Vec<Vertex>,
+
+    // what culling tested and rejected the last time the particles were
+    // drawn
+    culled: Cell<CullStats>,
+
+    _live: [LiveResource; 2],
 }
 
 impl Particles {
     pub fn new(display: &Display) -> Result<Self> {
-        let pipeline = pipeline::create_graphics_pipeline(
+        #[cfg(feature = "pipeline-cache")]
+        let mut pipelines = PipelineCache::load(
+            &display.device,
+            Path::new(PIPELINE_CACHE_DIRECTORY),
+        )?;
+        #[cfg(not(feature = "pipeline-cache"))]
+        let mut pipelines = PipelineCache::new(&display.device)?;
+        pipelines.warm(&display.device, &display.render_pass)?;
+        let blend_mode = BlendMode::Alpha;
+        let pipeline = pipelines.get_or_create_pipeline(
+            PipelineStateDesc::new(blend_mode, RenderMode::Points),
             &display.device,
-            display.swapchain.dimensions(),
             &display.render_pass,
         )?;
 
@@ -34,32 +69,138 @@ impl Particles {
 
         Ok(Self {
             pipeline,
+            pipelines,
+            blend_mode,
             vertex_buffer_pool,
             vertices: vec![
                 Vertex::new([0.0, -0.5], [1.0, 1.0, 1.0, 1.0]),
                 Vertex::new([0.5, 0.5], [0.0, 0.0, 1.0, 1.0]),
                 Vertex::new([-0.5, 0.5], [0.0, 1.0, 0.0, 1.0]),
             ],
+            previous_vertices: vec![],
+            culled: Cell::default(),
+            _live: [
+                LiveResource::described(
+                    ResourceKind::Pipeline,
+                    "particles",
+                    "",
+                    0,
+                ),
+                LiveResource::described(
+                    ResourceKind::Buffer,
+                    "particle vertices",
+                    "host visible",
+                    0,
+                ),
+            ],
         })
     }
 
-    pub fn rebuild_swapchain_resources(
+    /// Replace the vertices, keeping the old ones to compute velocities.
+    pub fn set_vertices(&mut self, vertices: Vec<Vertex>) {
+        self.previous_vertices =
+            std::mem::replace(&mut self.vertices, vertices);
+    }
+
+    /// Each particle's position now and when the vertices were last set, in
+    /// normalized device coordinates for the camera.
+    ///
+    /// When the number of particles changed there is no way to match them
+    /// up, so every particle is treated as static.
+    pub fn velocity_vertices(&self, camera: &Camera) -> Vec<VelocityVertex> {
+        let previous = if self.previous_vertices.len() == self.vertices.len() {
+            &self.previous_vertices
+        } else {
+            &self.vertices
+        };
+        self.vertices
+            .iter()
+            .zip(previous)
+            .map(|(vertex, previous)| VelocityVertex {
+                pos: camera.to_ndc(vertex.pos),
+                previous_pos: camera.to_ndc(previous.pos),
+            })
+            .collect()
+    }
+
+    /// Each particle's position with the particles spread evenly from the
+    /// near plane (z = -1) to the far plane (z = 1), so depth of field has
+    /// something to separate. The particles themselves are drawn flat.
+    pub fn distance_vertices(&self, camera: &Camera) -> Vec<DistanceVertex> {
+        let last = self.vertices.len().saturating_sub(1).max(1) as f32;
+        self.vertices
+            .iter()
+            .enumerate()
+            .map(|(i, vertex)| {
+                let [x, y] = camera.to_ndc(vertex.pos);
+                DistanceVertex {
+                    pos: [x, y, i as f32 / last * 2.0 - 1.0],
+                }
+            })
+            .collect()
+    }
+
+    /// What culling tested and rejected the last time the particles were
+    /// drawn.
+    pub fn culled(&self) -> CullStats {
+        self.culled.get()
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    /// Blend the particles differently, compiling the pipeline only the
+    /// first time a mode is used.
+    pub fn set_blend_mode(
         &mut self,
         display: &Display,
+        blend_mode: BlendMode,
     ) -> Result<()> {
-        self.pipeline = pipeline::create_graphics_pipeline(
+        self.pipeline = self.pipelines.get_or_create_pipeline(
+            PipelineStateDesc::new(blend_mode, RenderMode::Points),
             &display.device,
-            display.swapchain.dimensions(),
             &display.render_pass,
         )?;
+        self.blend_mode = blend_mode;
+        Ok(())
+    }
+
+    /// Save the compiled pipelines for the next run.
+    #[cfg(feature = "pipeline-cache")]
+    pub fn save_pipeline_cache(&self) -> Result<()> {
+        self.pipelines.save(Path::new(PIPELINE_CACHE_DIRECTORY))
+    }
+
+    /// The viewport is set while drawing and the render pass is created
+    /// again with the same formats, so the pipeline can be kept.
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        _display: &Display,
+    ) -> Result<()> {
         Ok(())
     }
 
-    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
-        let vertex_buffer = Arc::new(
-            self.vertex_buffer_pool
-                .chunk(self.vertices.iter().cloned())?,
-        );
+    /// Draw the particles with their positions in the camera's coordinate
+    /// space. Only its aspect fix is used, the particles don't move with it.
+    pub fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let [width, height] = display.scene_extent();
+        let radius = POINT_SIZE / width.min(height) as f32;
+        let mut cull = Cull::screen(Rect::ndc());
+        let vertices = tracing::info_span!("cull").in_scope(|| {
+            cull.retain(&self.vertices, |vertex| {
+                let [x, y] = camera.to_ndc(vertex.pos);
+                Bounds::Sphere {
+                    center: [x, y, 0.0],
+                    radius,
+                }
+            })
+        });
+        self.culled.set(cull.stats());
 
         let mut builder =
             AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
@@ -70,13 +211,31 @@ impl Particles {
                 )?,
             )
             .with_context(|| "unable to create the command buffer builder")?;
+        if vertices.is_empty() {
+            return builder
+                .build()
+                .with_context(|| "unable to build the command buffer");
+        }
+
+        let vertex_buffer = Arc::new(self.vertex_buffer_pool.chunk(vertices)?);
+        let dynamic_state = DynamicState {
+            viewports: Some(vec![Viewport {
+                origin: [0.0, 0.0],
+                dimensions: [width as f32, height as f32],
+                depth_range: 0.0..1.0,
+            }]),
+            ..DynamicState::none()
+        };
         builder
             .draw(
                 self.pipeline.clone(),
-                &DynamicState::none(),
+                &dynamic_state,
                 vec![vertex_buffer],
                 (),
-                (),
+                pipeline::Space {
+                    resolution: [width as f32, height as f32],
+                    aspect_fix: camera.aspect_fix(),
+                },
             )
             .with_context(|| "unable to issue draw command")?;
         builder
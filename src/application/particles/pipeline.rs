@@ -1,15 +1,43 @@
 use anyhow::{Context, Result};
+#[cfg(feature = "pipeline-cache")]
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+#[cfg(feature = "pipeline-cache")]
+use std::io::{BufReader, BufWriter};
+#[cfg(feature = "pipeline-cache")]
+use std::path::Path;
 use std::sync::Arc;
 use vulkano::device::Device;
 use vulkano::framebuffer::{RenderPassAbstract, Subpass};
 use vulkano::impl_vertex;
-use vulkano::pipeline::{
-    viewport::Viewport, GraphicsPipeline, GraphicsPipelineAbstract,
-};
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
+use vulkano::pipeline::cache::PipelineCache as VulkanPipelineCache;
+use vulkano::pipeline::vertex::Vertex as VertexDefinition;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
 
 type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynPipeline = dyn GraphicsPipelineAbstract + Send + Sync;
+
+/// The file the driver's compiled pipelines are saved to.
+#[cfg(feature = "pipeline-cache")]
+const CACHE_FILE: &str = "pipeline_cache.bin";
+
+/// The file the states of the cached pipelines are saved to.
+#[cfg(feature = "pipeline-cache")]
+const STATES_FILE: &str = "pipeline_states.json";
+
+/// The push constants, where the particles' positions are in the camera's
+/// coordinate space.
+pub(super) use vertex_shader::ty::Space;
+
+/// The shaders are written inline below, so this file changes whenever they
+/// do.
+const SHADER_SOURCE: &str = include_str!("pipeline.rs");
 
 #[derive(Default, Debug, Copy, Clone)]
+#[repr(C)]
 pub struct Vertex {
     pub pos: [f32; 2],
     pub color: [f32; 4],
@@ -23,45 +51,278 @@ impl Vertex {
     }
 }
 
-pub fn create_graphics_pipeline(
+/// How particles are blended over what's already drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "pipeline-cache", derive(Serialize, Deserialize))]
+pub enum BlendMode {
+    Opaque,
+    Alpha,
+    Additive,
+}
+
+impl BlendMode {
+    /// The next mode, wrapping around, for cycling with a key.
+    pub fn next(self) -> Self {
+        match self {
+            BlendMode::Opaque => BlendMode::Alpha,
+            BlendMode::Alpha => BlendMode::Additive,
+            BlendMode::Additive => BlendMode::Opaque,
+        }
+    }
+}
+
+/// The primitives the particles' vertices are drawn as.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "pipeline-cache", derive(Serialize, Deserialize))]
+pub enum RenderMode {
+    Points,
+    Triangles,
+}
+
+/// Everything which tells one particle pipeline from another.
+///
+/// The hashes cover what can't be compared directly: a saved state whose
+/// vertex format or shaders no longer match is stale, and is dropped when
+/// loaded. The viewport is set while drawing, so one pipeline serves every
+/// window size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "pipeline-cache", derive(Serialize, Deserialize))]
+pub struct PipelineStateDesc {
+    pub blend_mode: BlendMode,
+    pub render_mode: RenderMode,
+    pub vertex_format_hash: u64,
+    pub shader_hash: u64,
+}
+
+impl PipelineStateDesc {
+    /// The state for the current vertex format and shaders.
+    pub fn new(blend_mode: BlendMode, render_mode: RenderMode) -> Self {
+        Self {
+            blend_mode,
+            render_mode,
+            vertex_format_hash: vertex_format_hash(),
+            shader_hash: shader_hash(),
+        }
+    }
+
+    /// False for states saved by a build with other vertices or shaders.
+    pub fn is_current(&self) -> bool {
+        self.vertex_format_hash == vertex_format_hash()
+            && self.shader_hash == shader_hash()
+    }
+}
+
+/// The particle pipelines created so far, by their state.
+///
+/// Every pipeline is also built through the driver's own pipeline cache, so
+/// with the `pipeline-cache` feature a state seen in an earlier run is
+/// compiled from the saved binary rather than from scratch. Pipelines are
+/// built against the display's render pass, which is created again with the
+/// same formats when the swapchain is, so a cached pipeline stays
+/// compatible.
+pub struct PipelineCache {
+    pipelines: HashMap<PipelineStateDesc, Arc<DynPipeline>>,
+
+    // every state seen this run or saved by an earlier one
+    states: HashSet<PipelineStateDesc>,
+    vulkan_cache: Arc<VulkanPipelineCache>,
+}
+
+impl PipelineCache {
+    pub fn new(device: &Arc<Device>) -> Result<Self> {
+        let vulkan_cache = VulkanPipelineCache::empty(device.clone())
+            .context("unable to create the pipeline cache")?;
+        Ok(Self {
+            pipelines: HashMap::new(),
+            states: HashSet::new(),
+            vulkan_cache,
+        })
+    }
+
+    /// Load the cache saved to a directory, or start empty when there isn't
+    /// one.
+    #[cfg(feature = "pipeline-cache")]
+    pub fn load(device: &Arc<Device>, directory: &Path) -> Result<Self> {
+        let data = match std::fs::read(directory.join(CACHE_FILE)) {
+            Ok(data) => data,
+            Err(_) => return Self::new(device),
+        };
+        // the driver checks the header for its vendor, device, and version
+        // and ignores data it didn't write, so any bytes are safe to pass
+        let vulkan_cache =
+            unsafe { VulkanPipelineCache::with_data(device.clone(), &data) }
+                .context("unable to load the pipeline cache")?;
+
+        let path = directory.join(STATES_FILE);
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("unable to open {:?}", path))?;
+        let saved: Vec<PipelineStateDesc> =
+            serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("unable to read {:?}", path))?;
+        let states: HashSet<PipelineStateDesc> = saved
+            .into_iter()
+            .filter(PipelineStateDesc::is_current)
+            .collect();
+        log::info!(
+            "loaded {} bytes of cached pipelines for {} states",
+            data.len(),
+            states.len()
+        );
+        Ok(Self {
+            pipelines: HashMap::new(),
+            states,
+            vulkan_cache,
+        })
+    }
+
+    /// Save the driver's compiled pipelines and every known state to a
+    /// directory.
+    #[cfg(feature = "pipeline-cache")]
+    pub fn save(&self, directory: &Path) -> Result<()> {
+        std::fs::create_dir_all(directory)
+            .with_context(|| format!("unable to create {:?}", directory))?;
+        let data = self
+            .vulkan_cache
+            .get_data()
+            .context("unable to read the pipeline cache")?;
+        std::fs::write(directory.join(CACHE_FILE), &data)
+            .context("unable to write the pipeline cache")?;
+
+        let path = directory.join(STATES_FILE);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("unable to create {:?}", path))?;
+        let states: Vec<&PipelineStateDesc> = self.states.iter().collect();
+        serde_json::to_writer_pretty(BufWriter::new(file), &states)
+            .with_context(|| format!("unable to write {:?}", path))?;
+        log::info!(
+            "saved {} bytes of cached pipelines for {} states",
+            data.len(),
+            states.len()
+        );
+        Ok(())
+    }
+
+    /// The pipeline for a state, compiling it the first time it's asked
+    /// for.
+    pub fn get_or_create_pipeline(
+        &mut self,
+        desc: PipelineStateDesc,
+        device: &Arc<Device>,
+        render_pass: &Arc<DynRenderPass>,
+    ) -> Result<Arc<DynPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(&desc) {
+            return Ok(pipeline.clone());
+        }
+        let pipeline = create_graphics_pipeline(
+            device,
+            render_pass,
+            desc,
+            &self.vulkan_cache,
+        )?;
+        self.pipelines.insert(desc, pipeline.clone());
+        self.states.insert(desc);
+        Ok(pipeline)
+    }
+
+    /// Compile every known state which isn't yet, so switching to one later
+    /// doesn't stall a frame.
+    pub fn warm(
+        &mut self,
+        device: &Arc<Device>,
+        render_pass: &Arc<DynRenderPass>,
+    ) -> Result<()> {
+        let states: Vec<PipelineStateDesc> =
+            self.states.iter().copied().collect();
+        for desc in states {
+            self.get_or_create_pipeline(desc, device, render_pass)?;
+        }
+        Ok(())
+    }
+}
+
+/// A hash of the vertex's size and the layout of each of its members.
+///
+/// `DefaultHasher` may change between Rust releases, which only means a
+/// saved cache is rebuilt.
+fn vertex_format_hash() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    std::mem::size_of::<Vertex>().hash(&mut hasher);
+    for name in &["pos", "color"] {
+        if let Some(member) = <Vertex as VertexDefinition>::member(name) {
+            name.hash(&mut hasher);
+            member.offset.hash(&mut hasher);
+            member.array_size.hash(&mut hasher);
+            format!("{:?}", member.ty).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn shader_hash() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    SHADER_SOURCE.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn create_graphics_pipeline(
     device: &Arc<Device>,
-    swapchain_extent: [u32; 2],
     render_pass: &Arc<DynRenderPass>,
-) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    desc: PipelineStateDesc,
+    vulkan_cache: &Arc<VulkanPipelineCache>,
+) -> Result<Arc<DynPipeline>> {
     let vert = vertex_shader::Shader::load(device.clone())
         .context("unable to load the vertex shader")?;
     let frag = fragment_shader::Shader::load(device.clone())
         .context("unable to load the fragment shader")?;
 
-    let dimensions = [swapchain_extent[0] as f32, swapchain_extent[1] as f32];
-    let viewport = Viewport {
-        dimensions,
-        origin: [0.0, 0.0],
-        depth_range: 0.0..1.0,
-    };
-
-    let pipeline = GraphicsPipeline::start()
+    let builder = GraphicsPipeline::start()
         .vertex_input_single_buffer::<Vertex>()
         .vertex_shader(vert.main_entry_point(), ())
         .fragment_shader(frag.main_entry_point(), ())
-        .viewports(vec![viewport])
+        .viewports_dynamic_scissors_irrelevant(1)
         .depth_clamp(false)
         .polygon_mode_fill()
         .line_width(1.0)
         .depth_write(false)
         .sample_shading_disabled()
-        .blend_alpha_blending()
-        .point_list()
+        .build_with_cache(vulkan_cache.clone())
         .render_pass(
             Subpass::from(render_pass.clone(), 0)
                 .context("could not create the pipeline subpass")?,
-        )
+        );
+    let builder = match desc.render_mode {
+        RenderMode::Points => builder.point_list(),
+        RenderMode::Triangles => builder.triangle_list(),
+    };
+    let builder = match desc.blend_mode {
+        BlendMode::Opaque => builder.blend_pass_through(),
+        BlendMode::Alpha => builder.blend_alpha_blending(),
+        BlendMode::Additive => builder.blend_collective(additive()),
+    };
+    let pipeline = builder
         .build(device.clone())
         .context("could not create the graphics pipeline")?;
 
     Ok(Arc::new(pipeline))
 }
 
+/// Add the fragment's color, weighted by its alpha, to the target.
+fn additive() -> AttachmentBlend {
+    AttachmentBlend {
+        enabled: true,
+        color_op: BlendOp::Add,
+        color_source: BlendFactor::SrcAlpha,
+        color_destination: BlendFactor::One,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::One,
+        mask_red: true,
+        mask_green: true,
+        mask_blue: true,
+        mask_alpha: true,
+    }
+}
+
 mod vertex_shader {
     //
     vulkano_shaders::shader! {
@@ -75,10 +336,15 @@ mod vertex_shader {
 
             layout(location = 0) out vec4 vertColor;
 
+            layout(push_constant) uniform Space {
+                vec2 resolution;
+                vec2 aspect_fix;
+            } space;
+
             void main() {
                 vertColor = color;
                 gl_PointSize = 64.0;
-                gl_Position = vec4(pos, 0.0, 1.0);
+                gl_Position = vec4(pos * space.aspect_fix, 0.0, 1.0);
             }
             "#
     }
@@ -100,3 +366,40 @@ mod fragment_shader {
             "#
     }
 }
+
+#[cfg(all(test, feature = "pipeline-cache"))]
+mod tests {
+    use super::*;
+
+    /// Saved states must come back equal and hash the same so they find
+    /// their pipelines, the way the cache saves them.
+    #[test]
+    fn saved_states_round_trip() -> Result<()> {
+        let states = vec![
+            PipelineStateDesc::new(BlendMode::Alpha, RenderMode::Points),
+            PipelineStateDesc::new(BlendMode::Additive, RenderMode::Points),
+            PipelineStateDesc::new(BlendMode::Opaque, RenderMode::Triangles),
+        ];
+        let json = serde_json::to_string(&states)
+            .context("unable to write the pipeline states")?;
+        let loaded: Vec<PipelineStateDesc> = serde_json::from_str(&json)
+            .context("unable to read the pipeline states")?;
+        assert_eq!(loaded, states);
+        let unique: HashSet<PipelineStateDesc> =
+            states.iter().chain(&loaded).copied().collect();
+        assert_eq!(unique.len(), states.len(), "loaded states hash apart");
+        assert!(loaded.iter().all(PipelineStateDesc::is_current));
+        Ok(())
+    }
+
+    #[test]
+    fn states_from_other_shaders_are_stale() {
+        let current =
+            PipelineStateDesc::new(BlendMode::Alpha, RenderMode::Points);
+        let stale = PipelineStateDesc {
+            shader_hash: current.shader_hash.wrapping_add(1),
+            ..current
+        };
+        assert!(!stale.is_current());
+    }
+}
@@ -0,0 +1,2293 @@
+use super::camera::{Camera, DEPTH_RANGE};
+use super::cull::Mat4;
+use super::fullscreen::{self, FullscreenVertex};
+use super::offscreen::OffscreenTarget;
+use crate::application::debug::BarrierValidator;
+use crate::display::Display;
+use crate::texture::{self, Texture};
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState, SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::{Device, Queue};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{
+    Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass,
+};
+use vulkano::image::{
+    AttachmentImage, Dimensions, ImageUsage, ImmutableImage, MipmapsCount,
+    StorageImage,
+};
+use vulkano::impl_vertex;
+use vulkano::pipeline::viewport::Viewport;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use vulkano::sync::GpuFuture;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynFramebuffer = dyn FramebufferAbstract + Send + Sync;
+
+/// The width, height, and depth of the cloud noise texture.
+const NOISE_SIZE: u32 = 128;
+
+/// The number of Worley feature cells along each side of the noise texture.
+const NOISE_CELLS: u32 = 8;
+
+/// The direction towards the sun, it does not need to be normalized.
+const SUN_DIRECTION: [f32; 4] = [0.4, 0.5, 0.6, 0.0];
+
+/// The format of the view distance buffer used for depth of field.
+const DISTANCE_FORMAT: Format = Format::R32Sfloat;
+
+/// The format of the screen-space velocity buffer.
+const VELOCITY_FORMAT: Format = Format::R16G16Sfloat;
+
+/// The model-view-projection matrix for geometry which is already in clip
+/// space.
+pub const IDENTITY: Mat4 = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// A sky full of ray-marched volumetric clouds.
+///
+/// Every pixel marches a ray from the ground through the altitude band
+/// between `layer_min` and `layer_max`. Density comes from a tileable 3D
+/// Worley noise texture, light is absorbed following Beer's law, and each
+/// sample is lit by marching `light_steps` further samples towards the sun.
+pub struct VolumetricClouds {
+    /// The altitude of the bottom of the cloud layer.
+    pub layer_min: f32,
+
+    /// The altitude of the top of the cloud layer.
+    pub layer_max: f32,
+
+    /// The fraction of the sky covered by clouds, from 0 to 1.
+    pub coverage: f32,
+
+    /// How much light each unit of cloud absorbs.
+    pub density: f32,
+
+    /// The number of samples taken towards the sun to light each cloud
+    /// sample.
+    pub light_steps: u32,
+
+    time: f32,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl VolumetricClouds {
+    /// Create the clouds and upload their noise texture.
+    pub fn new(display: &Display) -> Result<Self> {
+        let (noise, upload) = ImmutableImage::from_iter(
+            worley_noise(NOISE_SIZE, NOISE_CELLS).into_iter(),
+            Dimensions::Dim3d {
+                width: NOISE_SIZE,
+                height: NOISE_SIZE,
+                depth: NOISE_SIZE,
+            },
+            MipmapsCount::One,
+            Format::R8Unorm,
+            display.graphics_queue.clone(),
+        )
+        .context("unable to create the cloud noise texture")?;
+        upload
+            .then_signal_fence_and_flush()
+            .context("unable to upload the cloud noise texture")?
+            .wait(None)
+            .context("unable to upload the cloud noise texture")?;
+
+        let pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        let layout = pipeline
+            .descriptor_set_layout(0)
+            .context("cloud pipeline has no descriptor set layout")?;
+        let descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(layout.clone())
+                .add_sampled_image(
+                    noise,
+                    Sampler::simple_repeat_linear_no_mipmap(
+                        display.device.clone(),
+                    ),
+                )?
+                .build()
+                .context("unable to create the cloud descriptors")?,
+        );
+
+        Ok(Self {
+            layer_min: 1.5,
+            layer_max: 3.5,
+            coverage: 0.5,
+            density: 1.2,
+            light_steps: 6,
+            time: 0.0,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// Set the time in seconds, the clouds slowly drift with the wind as it
+    /// advances.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Build a secondary command buffer which draws the clouds over the
+    /// whole screen.
+    pub fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the clouds")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                fragment_shader::ty::PushConstantData {
+                    sun_direction: SUN_DIRECTION,
+                    aspect_ratio: camera.aspect_ratio,
+                    eye_offset: camera.eye_offset,
+                    time: self.time,
+                    layer_min: self.layer_min,
+                    layer_max: self.layer_max,
+                    coverage: self.coverage,
+                    density: self.density,
+                    light_steps: self.light_steps,
+                },
+            )
+            .context("unable to draw the clouds")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        Ok(())
+    }
+}
+
+/// A color grading lookup table loaded from a `.cube` file.
+///
+/// The table is uploaded as an `R16G16B16A16Sfloat` 3D texture and sampled
+/// with trilinear filtering, so a 17³ or 33³ table grades as smoothly as a
+/// 64³ one.
+#[derive(Clone)]
+pub struct ColorGrading {
+    /// How much of the graded color is used, 0 bypasses the grading and 1
+    /// applies it fully.
+    pub strength: f32,
+
+    lut: Arc<ImmutableImage<Format>>,
+    lut_size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl ColorGrading {
+    /// Load a `.cube` file or a strip png, picked by the file's extension.
+    pub fn from_file(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        path: &Path,
+    ) -> Result<ColorGrading> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("png") => Self::from_strip_png(device, queue, path),
+            _ => Self::from_cube_file(device, queue, path),
+        }
+    }
+
+    pub fn from_cube_file(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        path: &Path,
+    ) -> Result<ColorGrading> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        let cube = CubeLut::parse(&source)
+            .with_context(|| format!("unable to parse {:?}", path))?;
+        Self::from_lut(device, queue, cube)
+    }
+
+    /// Load a LUT stored as a strip of square slices, one per blue value,
+    /// laid out left to right. Red increases to the right within each slice
+    /// and green increases downwards.
+    pub fn from_strip_png(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        path: &Path,
+    ) -> Result<ColorGrading> {
+        let (width, height, pixels) = texture::decode_png(path)
+            .with_context(|| format!("unable to decode {:?}", path))?;
+        let mut cube = CubeLut::from_strip(width, height, &pixels)
+            .with_context(|| format!("unable to parse {:?}", path))?;
+        cube.title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        Self::from_lut(device, queue, cube)
+    }
+
+    fn from_lut(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        cube: CubeLut,
+    ) -> Result<ColorGrading> {
+        let lut = texture::upload_lut(device, queue, cube.size, &cube.entries)?;
+        log::info!(
+            "loaded the {}³ color grading lut {:?}",
+            cube.size,
+            cube.title.as_deref().unwrap_or("untitled")
+        );
+        Ok(ColorGrading {
+            strength: 1.0,
+            lut,
+            lut_size: cube.size,
+            domain_min: cube.domain_min,
+            domain_max: cube.domain_max,
+        })
+    }
+}
+
+/// Renders the scene into an offscreen image, then draws it onto the
+/// swapchain through a color grading LUT.
+pub struct ToneMap {
+    pub grading: ColorGrading,
+
+    /// Dither the graded colors with blue noise to hide banding.
+    pub dither: bool,
+
+    blue_noise: Arc<ImmutableImage<Format>>,
+    scene: Arc<OffscreenTarget>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl ToneMap {
+    pub fn new(
+        display: &Display,
+        grading: ColorGrading,
+        blue_noise: &Texture,
+    ) -> Result<Self> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let pipeline = create_tone_map_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let descriptor_set = create_tone_map_set(
+            display,
+            &pipeline,
+            &scene,
+            &grading,
+            &blue_noise.image,
+        )?;
+        Ok(Self {
+            grading,
+            dither: true,
+            blue_noise: blue_noise.image.clone(),
+            scene,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// Build a primary command buffer which renders the scene's secondary
+    /// command buffers into the image which is graded.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.scene.render(display, graphics_queue_subbuffers)
+    }
+
+    /// Grade with a different LUT, keeping the current strength.
+    pub fn set_grading(
+        &mut self,
+        display: &Display,
+        grading: ColorGrading,
+    ) -> Result<()> {
+        self.descriptor_set = create_tone_map_set(
+            display,
+            &self.pipeline,
+            &self.scene,
+            &grading,
+            &self.blue_noise,
+        )?;
+        self.grading = ColorGrading {
+            strength: self.grading.strength,
+            ..grading
+        };
+        Ok(())
+    }
+
+    /// Build a secondary command buffer which draws the graded scene over
+    /// the whole screen.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let grading = &self.grading;
+        let [min_r, min_g, min_b] = grading.domain_min;
+        let [max_r, max_g, max_b] = grading.domain_max;
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the tone map")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                tone_map_shader::ty::PushConstantData {
+                    domain_min: [min_r, min_g, min_b, 0.0],
+                    domain_max: [max_r, max_g, max_b, 0.0],
+                    strength: grading.strength.clamp(0.0, 1.0),
+                    lut_size: grading.lut_size as f32,
+                    srgb_target: is_srgb(display.swapchain.format()) as u32,
+                    dither: self.dither as u32,
+                },
+            )
+            .context("unable to draw the tone map")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        self.pipeline = create_tone_map_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set = create_tone_map_set(
+            display,
+            &self.pipeline,
+            &scene,
+            &self.grading,
+            &self.blue_noise,
+        )?;
+        self.scene = scene;
+        Ok(())
+    }
+
+    /// Grade another target instead of this one's own, which may be shared
+    /// with the other effects, see `Application::alias_post_process_targets`.
+    pub fn share_scene(
+        &mut self,
+        display: &Display,
+        scene: Arc<OffscreenTarget>,
+    ) -> Result<()> {
+        self.descriptor_set = create_tone_map_set(
+            display,
+            &self.pipeline,
+            &scene,
+            &self.grading,
+            &self.blue_noise,
+        )?;
+        self.scene = scene;
+        Ok(())
+    }
+}
+
+/// A point's position in this frame and the last one, used to fill the
+/// velocity buffer.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct VelocityVertex {
+    pub pos: [f32; 2],
+    pub previous_pos: [f32; 2],
+}
+
+impl_vertex!(VelocityVertex, pos, previous_pos);
+
+/// A screen-space velocity buffer in pixels per frame.
+///
+/// The display's render pass only has a single color attachment, so the
+/// velocity of the geometry is rendered in its own pass rather than as a
+/// second output of the color pass. Anything which is not drawn into the
+/// velocity buffer is treated as static.
+pub struct VelocityTarget {
+    pub image: Arc<StorageImage<Format>>,
+    extent: [u32; 2],
+    framebuffer: Arc<DynFramebuffer>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+impl VelocityTarget {
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        extent: [u32; 2],
+    ) -> Result<Self> {
+        let features = VELOCITY_FORMAT
+            .properties(device.physical_device())
+            .optimal_tiling_features;
+        if !features.color_attachment || !features.sampled_image {
+            bail!("the device cannot render and sample a velocity buffer");
+        }
+
+        let [width, height] = extent;
+        let image = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d { width, height },
+            VELOCITY_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            Some(queue.family()),
+        )
+        .context("unable to create the velocity image")?;
+        let render_pass: Arc<DynRenderPass> = Arc::new(
+            vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    velocity: {
+                        load: Clear,
+                        store: Store,
+                        format: VELOCITY_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [velocity],
+                    depth_stencil: {}
+                }
+            )
+            .context("unable to create the velocity render pass")?,
+        );
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())?
+                .build()
+                .context("unable to create the velocity framebuffer")?,
+        );
+        let pipeline = create_velocity_pipeline(device, extent, &render_pass)?;
+
+        Ok(Self {
+            image,
+            extent,
+            framebuffer,
+            pipeline,
+        })
+    }
+
+    /// Build a primary command buffer which clears the velocity buffer and
+    /// draws each point's motion since the last frame.
+    pub fn render(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        vertices: Vec<VelocityVertex>,
+        mvp: Mat4,
+        previous_mvp: Mat4,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                queue.family(),
+            )
+            .context("unable to create the velocity command buffer builder")?,
+            &queue,
+        );
+        builder
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                SubpassContents::Inline,
+                vec![ClearValue::Float([0.0, 0.0, 0.0, 0.0])],
+            )
+            .context("unable to begin the velocity render pass")?;
+        if !vertices.is_empty() {
+            let vertex_buffer = CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                vertices.into_iter(),
+            )
+            .context("unable to create the velocity vertex buffer")?;
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![vertex_buffer],
+                    (),
+                    velocity_vertex_shader::ty::PushConstantData {
+                        mvp,
+                        previous_mvp,
+                        extent: [self.extent[0] as f32, self.extent[1] as f32],
+                    },
+                )
+                .context("unable to draw the velocities")?;
+        }
+        builder
+            .end_render_pass()
+            .context("unable to end the velocity render pass")?;
+        builder
+            .build()
+            .context("unable to build the velocity command buffer")
+    }
+}
+
+/// Blurs the scene along each pixel's screen-space velocity.
+///
+/// The scene is rendered into an offscreen image, then every pixel takes
+/// `samples` samples of it along its velocity. Velocities longer than
+/// `max_velocity_px` are clamped so fast motion does not smear across the
+/// whole screen, and pixels with no velocity are copied unchanged.
+pub struct MotionBlur {
+    /// The number of scene samples taken along each pixel's velocity.
+    pub samples: u32,
+
+    /// The longest blur in pixels.
+    pub max_velocity_px: f32,
+
+    /// Scales every velocity, 0 disables the blur and 1 blurs across the
+    /// full motion of the last frame.
+    pub strength: f32,
+
+    scene: Arc<OffscreenTarget>,
+    velocity: VelocityTarget,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl MotionBlur {
+    pub fn new(display: &Display) -> Result<Self> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let velocity = VelocityTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            display.swapchain.dimensions(),
+        )?;
+        let pipeline = create_motion_blur_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let descriptor_set =
+            create_motion_blur_set(display, &pipeline, &scene, &velocity)?;
+        Ok(Self {
+            samples: 8,
+            max_velocity_px: 32.0,
+            strength: 1.0,
+            scene,
+            velocity,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// Build a primary command buffer which renders the scene's secondary
+    /// command buffers into the image which is blurred.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.scene.render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a primary command buffer which fills the velocity buffer from
+    /// each point's current and previous position.
+    pub fn render_velocity(
+        &self,
+        display: &Display,
+        vertices: Vec<VelocityVertex>,
+        mvp: Mat4,
+        previous_mvp: Mat4,
+    ) -> Result<AutoCommandBuffer> {
+        self.velocity.render(
+            &display.device,
+            &display.graphics_queue,
+            vertices,
+            mvp,
+            previous_mvp,
+        )
+    }
+
+    /// Build a secondary command buffer which draws the blurred scene over
+    /// the whole screen.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the motion blur")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                motion_blur_shader::ty::PushConstantData {
+                    samples: self.samples.max(1),
+                    max_velocity_px: self.max_velocity_px.max(0.0),
+                    strength: self.strength.max(0.0),
+                },
+            )
+            .context("unable to draw the motion blur")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let velocity = VelocityTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            display.swapchain.dimensions(),
+        )?;
+        self.pipeline = create_motion_blur_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set =
+            create_motion_blur_set(display, &self.pipeline, &scene, &velocity)?;
+        self.scene = scene;
+        self.velocity = velocity;
+        Ok(())
+    }
+
+    /// Blur another target instead of this one's own, which may be shared
+    /// with the other effects, see `Application::alias_post_process_targets`.
+    pub fn share_scene(
+        &mut self,
+        display: &Display,
+        scene: Arc<OffscreenTarget>,
+    ) -> Result<()> {
+        self.descriptor_set = create_motion_blur_set(
+            display,
+            &self.pipeline,
+            &scene,
+            &self.velocity,
+        )?;
+        self.scene = scene;
+        Ok(())
+    }
+}
+
+/// A point's position, used to fill the view distance buffer.
+#[derive(Default, Debug, Copy, Clone)]
+pub struct DistanceVertex {
+    pub pos: [f32; 3],
+}
+
+impl_vertex!(DistanceVertex, pos);
+
+/// A buffer of each pixel's distance from the camera's near plane.
+///
+/// Like the velocity buffer this is drawn in its own pass, with a depth test
+/// so the nearest geometry wins. Pixels with no geometry are at the far
+/// plane.
+pub struct DistanceTarget {
+    pub image: Arc<StorageImage<Format>>,
+
+    /// The depth buffer of the pass, which later passes can only sample
+    /// when the target was created with `sampled_depth`.
+    pub depth: Arc<AttachmentImage>,
+    framebuffer: Arc<DynFramebuffer>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+}
+
+impl DistanceTarget {
+    /// Create the target. With `sampled_depth` its depth buffer can be read
+    /// by later passes, otherwise it's transient.
+    pub fn new(
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        extent: [u32; 2],
+        sampled_depth: bool,
+    ) -> Result<Self> {
+        let [width, height] = extent;
+        let image = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d { width, height },
+            DISTANCE_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            Some(queue.family()),
+        )
+        .context("unable to create the distance image")?;
+        let depth = AttachmentImage::with_usage(
+            device.clone(),
+            extent,
+            Format::D16Unorm,
+            ImageUsage {
+                depth_stencil_attachment: true,
+                transient_attachment: !sampled_depth,
+                sampled: sampled_depth,
+                ..ImageUsage::none()
+            },
+        )
+        .context("unable to create the distance depth buffer")?;
+        let render_pass: Arc<DynRenderPass> = Arc::new(
+            vulkano::single_pass_renderpass!(
+                device.clone(),
+                attachments: {
+                    distance: {
+                        load: Clear,
+                        store: Store,
+                        format: DISTANCE_FORMAT,
+                        samples: 1,
+                    },
+                    // stored in case the depth buffer is sampled
+                    depth: {
+                        load: Clear,
+                        store: Store,
+                        format: Format::D16Unorm,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [distance],
+                    depth_stencil: {depth}
+                }
+            )
+            .context("unable to create the distance render pass")?,
+        );
+        let framebuffer = Arc::new(
+            Framebuffer::start(render_pass.clone())
+                .add(image.clone())?
+                .add(depth.clone())?
+                .build()
+                .context("unable to create the distance framebuffer")?,
+        );
+        let pipeline = create_distance_pipeline(device, extent, &render_pass)?;
+
+        Ok(Self {
+            image,
+            depth,
+            framebuffer,
+            pipeline,
+        })
+    }
+
+    /// Build a primary command buffer which clears the buffer to the far
+    /// plane and draws each point's distance.
+    pub fn render(
+        &self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        vertices: Vec<DistanceVertex>,
+        mvp: Mat4,
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                queue.family(),
+            )
+            .context("unable to create the distance command buffer builder")?,
+            &queue,
+        );
+        builder
+            .begin_render_pass(
+                self.framebuffer.clone(),
+                SubpassContents::Inline,
+                vec![
+                    ClearValue::Float([DEPTH_RANGE, 0.0, 0.0, 0.0]),
+                    ClearValue::Depth(1.0),
+                ],
+            )
+            .context("unable to begin the distance render pass")?;
+        if !vertices.is_empty() {
+            let vertex_buffer = CpuAccessibleBuffer::from_iter(
+                device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                vertices.into_iter(),
+            )
+            .context("unable to create the distance vertex buffer")?;
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![vertex_buffer],
+                    (),
+                    distance_vertex_shader::ty::PushConstantData {
+                        mvp,
+                        depth_range: DEPTH_RANGE,
+                    },
+                )
+                .context("unable to draw the distances")?;
+        }
+        builder
+            .end_render_pass()
+            .context("unable to end the distance render pass")?;
+        builder
+            .build()
+            .context("unable to build the distance command buffer")
+    }
+}
+
+/// The shape of the out-of-focus highlights.
+#[derive(Clone)]
+pub enum BokehShape {
+    Circle,
+    Hexagon,
+
+    /// The red channel of the image weights each sample, the image covers
+    /// the full blur radius.
+    Custom(Arc<ImmutableImage<Format>>),
+}
+
+impl BokehShape {
+    /// The matching `SHAPE_*` constant in the depth of field shader.
+    fn shader_id(&self) -> u32 {
+        match self {
+            BokehShape::Circle => 0,
+            BokehShape::Hexagon => 1,
+            BokehShape::Custom(_) => 2,
+        }
+    }
+}
+
+/// Blurs the parts of the scene which are out of focus.
+///
+/// Each pixel's circle of confusion grows with its distance from the focus
+/// distance, scaled by the aperture and limited to `max_blur_radius` pixels.
+/// Out-of-focus samples are gathered into separate near and far layers: the
+/// far layer only takes samples behind the focus so sharp foreground edges
+/// stay crisp, while the near layer spreads over in-focus pixels the way a
+/// blurred foreground does through a real lens. Both are composited over the
+/// sharp scene.
+pub struct DepthOfField {
+    /// The distance from the near plane which is perfectly sharp.
+    pub focus_distance: f32,
+
+    /// How quickly the blur grows away from the focus distance, a larger
+    /// aperture gives a shallower depth of field.
+    pub aperture: f32,
+
+    /// The largest circle of confusion in pixels.
+    pub max_blur_radius: f32,
+
+    bokeh_shape: BokehShape,
+    blank_bokeh: Arc<ImmutableImage<Format>>,
+    scene: Arc<OffscreenTarget>,
+    distance: DistanceTarget,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl DepthOfField {
+    pub fn new(display: &Display) -> Result<Self> {
+        // the built in shapes are computed in the shader, but the descriptor
+        // set always needs an image
+        let (blank_bokeh, upload) = ImmutableImage::from_iter(
+            std::iter::once(255u8),
+            Dimensions::Dim2d {
+                width: 1,
+                height: 1,
+            },
+            MipmapsCount::One,
+            Format::R8Unorm,
+            display.graphics_queue.clone(),
+        )
+        .context("unable to create the blank bokeh image")?;
+        upload
+            .then_signal_fence_and_flush()
+            .context("unable to upload the blank bokeh image")?
+            .wait(None)
+            .context("unable to upload the blank bokeh image")?;
+
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let distance = DistanceTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            display.swapchain.dimensions(),
+            false,
+        )?;
+        let pipeline = create_depth_of_field_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let bokeh_shape = BokehShape::Hexagon;
+        let descriptor_set = create_depth_of_field_set(
+            display,
+            &pipeline,
+            &scene,
+            &distance,
+            &bokeh_image(&bokeh_shape, &blank_bokeh),
+        )?;
+        Ok(Self {
+            focus_distance: DEPTH_RANGE / 2.0,
+            aperture: 1.0,
+            max_blur_radius: 12.0,
+            bokeh_shape,
+            blank_bokeh,
+            scene,
+            distance,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    pub fn bokeh_shape(&self) -> &BokehShape {
+        &self.bokeh_shape
+    }
+
+    /// Change the bokeh shape, a custom shape's image is bound right away.
+    pub fn set_bokeh_shape(
+        &mut self,
+        display: &Display,
+        bokeh_shape: BokehShape,
+    ) -> Result<()> {
+        self.descriptor_set = create_depth_of_field_set(
+            display,
+            &self.pipeline,
+            &self.scene,
+            &self.distance,
+            &bokeh_image(&bokeh_shape, &self.blank_bokeh),
+        )?;
+        self.bokeh_shape = bokeh_shape;
+        Ok(())
+    }
+
+    /// Focus on a point in the world, as seen by the camera.
+    pub fn focus_on(&mut self, camera: &Camera, world_pos: [f32; 3]) {
+        self.focus_distance = camera.distance(world_pos);
+    }
+
+    /// Build a primary command buffer which renders the scene's secondary
+    /// command buffers into the image which is blurred.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.scene.render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a primary command buffer which fills the distance buffer.
+    pub fn render_distance(
+        &self,
+        display: &Display,
+        vertices: Vec<DistanceVertex>,
+        mvp: Mat4,
+    ) -> Result<AutoCommandBuffer> {
+        self.distance.render(
+            &display.device,
+            &display.graphics_queue,
+            vertices,
+            mvp,
+        )
+    }
+
+    /// Build a secondary command buffer which draws the scene with depth of
+    /// field over the whole screen.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0).context(
+                    "unable to select subpass for the depth of field",
+                )?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                depth_of_field_shader::ty::PushConstantData {
+                    focus_distance: self.focus_distance,
+                    aperture: self.aperture.max(0.0),
+                    max_blur_radius: self.max_blur_radius.max(0.0),
+                    shape: self.bokeh_shape.shader_id(),
+                },
+            )
+            .context("unable to draw the depth of field")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let distance = DistanceTarget::new(
+            &display.device,
+            &display.graphics_queue,
+            display.swapchain.dimensions(),
+            false,
+        )?;
+        self.pipeline = create_depth_of_field_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set = create_depth_of_field_set(
+            display,
+            &self.pipeline,
+            &scene,
+            &distance,
+            &bokeh_image(&self.bokeh_shape, &self.blank_bokeh),
+        )?;
+        self.scene = scene;
+        self.distance = distance;
+        Ok(())
+    }
+
+    /// Blur another target instead of this one's own, which may be shared
+    /// with the other effects, see `Application::alias_post_process_targets`.
+    pub fn share_scene(
+        &mut self,
+        display: &Display,
+        scene: Arc<OffscreenTarget>,
+    ) -> Result<()> {
+        self.descriptor_set = create_depth_of_field_set(
+            display,
+            &self.pipeline,
+            &scene,
+            &self.distance,
+            &bokeh_image(&self.bokeh_shape, &self.blank_bokeh),
+        )?;
+        self.scene = scene;
+        Ok(())
+    }
+}
+
+/// The image bound for a bokeh shape.
+fn bokeh_image(
+    shape: &BokehShape,
+    blank: &Arc<ImmutableImage<Format>>,
+) -> Arc<ImmutableImage<Format>> {
+    match shape {
+        BokehShape::Custom(image) => image.clone(),
+        _ => blank.clone(),
+    }
+}
+
+/// Imperfections of a real camera lens, applied in a single pass.
+///
+/// The screen coordinates are first remapped by the barrel distortion, then
+/// the red and blue channels are sampled slightly further out and further in
+/// than green for chromatic aberration, and finally the corners are darkened
+/// by the vignette. Every effect is off when its parameter is 0.
+pub struct LensEffects {
+    /// How dark the corners get, 0 leaves them untouched and 1 makes them
+    /// black.
+    pub vignette_strength: f32,
+
+    /// Where the vignette starts, as a fraction of the distance from the
+    /// center of the screen to a corner.
+    pub vignette_radius: f32,
+
+    /// How far apart the red and blue channels are sampled, as a fraction
+    /// of the distance from the center.
+    pub chromatic_aberration: f32,
+
+    /// Positive values bulge the image outward like a wide angle lens while
+    /// negative values pinch it into a pincushion.
+    pub barrel_distortion: f32,
+
+    scene: Arc<OffscreenTarget>,
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl LensEffects {
+    /// Lens effects which start out disabled.
+    pub fn new(display: &Display) -> Result<Self> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        let pipeline = create_lens_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let descriptor_set = create_lens_set(display, &pipeline, &scene)?;
+        Ok(Self {
+            vignette_strength: 0.0,
+            vignette_radius: 1.0,
+            chromatic_aberration: 0.0,
+            barrel_distortion: 0.0,
+            scene,
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// A soft vignette with a little distortion and color fringing.
+    pub fn default_cinematic(display: &Display) -> Result<Self> {
+        Ok(Self {
+            vignette_strength: 0.45,
+            vignette_radius: 0.55,
+            chromatic_aberration: 0.004,
+            barrel_distortion: 0.06,
+            ..Self::new(display)?
+        })
+    }
+
+    /// Build a primary command buffer which renders the scene's secondary
+    /// command buffers into the image seen through the lens.
+    pub fn render_scene(
+        &self,
+        display: &Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.scene.render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a secondary command buffer which draws the scene through the
+    /// lens over the whole screen.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let [width, height] = display.swapchain.dimensions();
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the lens effects")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                lens_shader::ty::PushConstantData {
+                    vignette_strength: self.vignette_strength.clamp(0.0, 1.0),
+                    vignette_radius: self.vignette_radius.clamp(0.0, 0.99),
+                    chromatic_aberration: self.chromatic_aberration,
+                    barrel_distortion: self.barrel_distortion,
+                    aspect_ratio: width as f32 / height as f32,
+                },
+            )
+            .context("unable to draw the lens effects")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let scene = Arc::new(OffscreenTarget::new(display)?);
+        self.pipeline = create_lens_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set = create_lens_set(display, &self.pipeline, &scene)?;
+        self.scene = scene;
+        Ok(())
+    }
+
+    /// Draw another target instead of this one's own, which may be shared
+    /// with the other effects, see `Application::alias_post_process_targets`.
+    pub fn share_scene(
+        &mut self,
+        display: &Display,
+        scene: Arc<OffscreenTarget>,
+    ) -> Result<()> {
+        self.descriptor_set = create_lens_set(display, &self.pipeline, &scene)?;
+        self.scene = scene;
+        Ok(())
+    }
+}
+
+/// The contents of an Adobe/Resolve `.cube` 3D LUT file.
+struct CubeLut {
+    title: Option<String>,
+    size: u32,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+
+    // output colors with red changing fastest, then green, then blue
+    entries: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    fn parse(source: &str) -> Result<Self> {
+        let mut lut = CubeLut {
+            title: None,
+            size: 0,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            entries: vec![],
+        };
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let context = || format!("invalid line {}: {:?}", index + 1, line);
+            match keyword {
+                "TITLE" => {
+                    let title = line["TITLE".len()..].trim().trim_matches('"');
+                    lut.title = Some(title.to_owned());
+                }
+                "LUT_3D_SIZE" => {
+                    lut.size = words
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .with_context(context)?;
+                }
+                "DOMAIN_MIN" => lut.domain_min = parse_triple(line)?,
+                "DOMAIN_MAX" => lut.domain_max = parse_triple(line)?,
+                "LUT_1D_SIZE" => bail!("1D luts are not supported"),
+                _ if keyword
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c == '_') =>
+                {
+                    log::warn!("ignoring cube keyword {:?}", keyword);
+                }
+                _ => {
+                    lut.entries.push(parse_triple(line).with_context(context)?)
+                }
+            }
+        }
+
+        if lut.size < 2 {
+            bail!("the lut must have a LUT_3D_SIZE of at least 2");
+        }
+        let expected = (lut.size * lut.size * lut.size) as usize;
+        if lut.entries.len() != expected {
+            bail!(
+                "a {}³ lut needs {} entries but {} were found",
+                lut.size,
+                expected,
+                lut.entries.len()
+            );
+        }
+        if (0..3).any(|i| lut.domain_max[i] <= lut.domain_min[i]) {
+            bail!("the lut's DOMAIN_MAX must be above its DOMAIN_MIN");
+        }
+        Ok(lut)
+    }
+
+    /// Read a strip of `size` slices which are each `size` texels square.
+    fn from_strip(width: u32, height: u32, rgba: &[u8]) -> Result<Self> {
+        let size = height;
+        if size < 2 || width != size * size {
+            bail!(
+                "a strip lut must be n² by n texels, but this one is {}x{}",
+                width,
+                height
+            );
+        }
+        let mut entries = Vec::with_capacity((size * size * size) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let index = ((g * width + b * size + r) * 4) as usize;
+                    let texel = &rgba[index..index + 3];
+                    entries.push([
+                        texel[0] as f32 / 255.0,
+                        texel[1] as f32 / 255.0,
+                        texel[2] as f32 / 255.0,
+                    ]);
+                }
+            }
+        }
+        Ok(CubeLut {
+            title: None,
+            size,
+            domain_min: [0.0; 3],
+            domain_max: [1.0; 3],
+            entries,
+        })
+    }
+}
+
+/// Parse the last three numbers on a line, skipping any keyword.
+fn parse_triple(line: &str) -> Result<[f32; 3]> {
+    let values: Vec<f32> = line
+        .split_whitespace()
+        .filter_map(|word| word.parse().ok())
+        .collect();
+    match values.as_slice() {
+        &[r, g, b] => Ok([r, g, b]),
+        _ => bail!("expected three numbers in {:?}", line),
+    }
+}
+
+/// True when writes to the format are encoded as sRGB by the hardware.
+pub fn is_srgb(format: Format) -> bool {
+    matches!(
+        format,
+        Format::B8G8R8A8Srgb
+            | Format::R8G8B8A8Srgb
+            | Format::A8B8G8R8SrgbPack32
+    )
+}
+
+/// Tileable 3D Worley noise, bright near feature points and dark between
+/// them.
+///
+/// The texture is split into `cells` cells along each side with one feature
+/// point in each. Neighbor lookups wrap around the edges so the texture
+/// tiles seamlessly.
+fn worley_noise(size: u32, cells: u32) -> Vec<u8> {
+    let cell_size = size as f32 / cells as f32;
+
+    // a small deterministic generator so the clouds are the same every run
+    // (splitmix64)
+    let mut state: u64 = 0x5eed;
+    let mut random = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    };
+    let points: Vec<[f32; 3]> = (0..cells * cells * cells)
+        .map(|_| [random(), random(), random()])
+        .collect();
+    let point = |x: i64, y: i64, z: i64| {
+        let wrap = |v: i64| v.rem_euclid(cells as i64) as u32;
+        let index = (wrap(z) * cells + wrap(y)) * cells + wrap(x);
+        let offset = points[index as usize];
+        [
+            (x as f32 + offset[0]) * cell_size,
+            (y as f32 + offset[1]) * cell_size,
+            (z as f32 + offset[2]) * cell_size,
+        ]
+    };
+
+    let mut texels = Vec::with_capacity((size * size * size) as usize);
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                let p = [x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5];
+                let cell = [
+                    (p[0] / cell_size) as i64,
+                    (p[1] / cell_size) as i64,
+                    (p[2] / cell_size) as i64,
+                ];
+                let mut nearest = f32::MAX;
+                for dz in -1..=1 {
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let q =
+                                point(cell[0] + dx, cell[1] + dy, cell[2] + dz);
+                            let d: f32 =
+                                (0..3).map(|i| (q[i] - p[i]).powi(2)).sum();
+                            nearest = nearest.min(d);
+                        }
+                    }
+                }
+                let value = 1.0 - (nearest.sqrt() / cell_size).min(1.0);
+                texels.push((value * 255.0) as u8);
+            }
+        }
+    }
+    texels
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the cloud fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the cloud pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the cloud graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_tone_map_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    scene: &OffscreenTarget,
+    grading: &ColorGrading,
+    blue_noise: &Arc<ImmutableImage<Format>>,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let scene_sampler =
+        Sampler::simple_repeat_linear_no_mipmap(display.device.clone());
+    let lut_sampler = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the lut sampler")?;
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("tone map pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(scene.image.clone(), scene_sampler)?
+            .add_sampled_image(grading.lut.clone(), lut_sampler)?
+            .add_sampled_image(
+                blue_noise.clone(),
+                Sampler::simple_repeat_linear_no_mipmap(display.device.clone()),
+            )?
+            .build()
+            .context("unable to create the tone map descriptors")?,
+    ))
+}
+
+fn create_tone_map_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = tone_map_shader::Shader::load(device.clone())
+        .context("unable to load the tone map fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the tone map pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the tone map graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_motion_blur_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    scene: &OffscreenTarget,
+    velocity: &VelocityTarget,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let scene_sampler = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the motion blur sampler")?;
+    let velocity_sampler =
+        Sampler::simple_repeat_linear_no_mipmap(display.device.clone());
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("motion blur pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(scene.image.clone(), scene_sampler)?
+            .add_sampled_image(velocity.image.clone(), velocity_sampler)?
+            .build()
+            .context("unable to create the motion blur descriptors")?,
+    ))
+}
+
+fn create_motion_blur_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = motion_blur_shader::Shader::load(device.clone())
+        .context("unable to load the motion blur fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the motion blur pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the motion blur graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_velocity_pipeline(
+    device: &Arc<Device>,
+    extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = velocity_vertex_shader::Shader::load(device.clone())
+        .context("unable to load the velocity vertex shader")?;
+    let frag = velocity_fragment_shader::Shader::load(device.clone())
+        .context("unable to load the velocity fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<VelocityVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        }])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .point_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the velocity pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the velocity graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_depth_of_field_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    scene: &OffscreenTarget,
+    distance: &DistanceTarget,
+    bokeh: &Arc<ImmutableImage<Format>>,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let clamped = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the depth of field sampler")?;
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("depth of field pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(scene.image.clone(), clamped.clone())?
+            .add_sampled_image(distance.image.clone(), clamped.clone())?
+            .add_sampled_image(bokeh.clone(), clamped)?
+            .build()
+            .context("unable to create the depth of field descriptors")?,
+    ))
+}
+
+fn create_depth_of_field_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = depth_of_field_shader::Shader::load(device.clone())
+        .context("unable to load the depth of field fragment shader")?;
+
+    let pipeline =
+        GraphicsPipeline::start()
+            .vertex_input_single_buffer::<FullscreenVertex>()
+            .vertex_shader(vert.main_entry_point(), ())
+            .fragment_shader(frag.main_entry_point(), ())
+            .viewports(vec![fullscreen::viewport(swapchain_extent)])
+            .depth_clamp(false)
+            .polygon_mode_fill()
+            .line_width(1.0)
+            .depth_write(false)
+            .sample_shading_disabled()
+            .blend_pass_through()
+            .triangle_list()
+            .render_pass(Subpass::from(render_pass.clone(), 0).context(
+                "could not create the depth of field pipeline subpass",
+            )?)
+            .build(device.clone())
+            .context("could not create the depth of field graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_distance_pipeline(
+    device: &Arc<Device>,
+    extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = distance_vertex_shader::Shader::load(device.clone())
+        .context("unable to load the distance vertex shader")?;
+    let frag = distance_fragment_shader::Shader::load(device.clone())
+        .context("unable to load the distance fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<DistanceVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [extent[0] as f32, extent[1] as f32],
+            depth_range: 0.0..1.0,
+        }])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_stencil_simple_depth()
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .point_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the distance pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the distance graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+fn create_lens_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    scene: &OffscreenTarget,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    // samples pushed past the edge by the distortion are masked out in the
+    // shader, clamping keeps the edge itself from wrapping
+    let sampler = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the lens sampler")?;
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("lens pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(scene.image.clone(), sampler)?
+            .build()
+            .context("unable to create the lens descriptors")?,
+    ))
+}
+
+fn create_lens_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = lens_shader::Shader::load(device.clone())
+        .context("unable to load the lens fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the lens pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the lens graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler3D noise_texture;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 sun_direction;
+                float aspect_ratio;
+                float eye_offset;
+                float time;
+                float layer_min;
+                float layer_max;
+                float coverage;
+                float density;
+                uint light_steps;
+            } pc;
+
+            const int VIEW_STEPS = 48;
+            const float NOISE_SCALE = 0.08;
+            const vec3 WIND = vec3(0.02, 0.0, 0.01);
+            const vec3 SUN_COLOR = vec3(1.0, 0.95, 0.85);
+            const float PI = 3.14159265;
+
+            float cloud_density(vec3 p) {
+                vec3 uvw = p * NOISE_SCALE + WIND * pc.time;
+                float noise = texture(noise_texture, uvw).r;
+
+                // thin the clouds out towards the top and bottom of the band
+                float height = (p.y - pc.layer_min)
+                    / (pc.layer_max - pc.layer_min);
+                float profile = clamp(4.0 * height * (1.0 - height), 0.0, 1.0);
+                float cloud = noise * profile - (1.0 - pc.coverage);
+                return max(cloud, 0.0) * pc.density;
+            }
+
+            // Henyey-Greenstein phase function
+            float phase(float cos_theta) {
+                float g = 0.6;
+                float denom = 1.0 + g * g - 2.0 * g * cos_theta;
+                return (1.0 - g * g) / (4.0 * PI * pow(denom, 1.5));
+            }
+
+            vec3 sky(vec3 dir) {
+                float t = clamp(dir.y, 0.0, 1.0);
+                return mix(vec3(0.7, 0.8, 0.95), vec3(0.25, 0.45, 0.8), t);
+            }
+
+            void main() {
+                vec3 sun = normalize(pc.sun_direction.xyz);
+                vec2 screen = vec2(
+                    (fragUv.x * 2.0 - 1.0) * pc.aspect_ratio,
+                    1.0 - fragUv.y * 2.0
+                );
+                vec3 dir = normalize(vec3(screen.x, screen.y + 0.6, 1.5));
+                vec3 origin = vec3(pc.eye_offset, 0.0, 0.0);
+
+                if (dir.y <= 0.0) {
+                    outColor = vec4(0.3, 0.32, 0.3, 1.0);
+                    return;
+                }
+
+                // march the part of the ray inside the cloud layer
+                float t_enter = pc.layer_min / dir.y;
+                float t_exit = pc.layer_max / dir.y;
+                float step_size = (t_exit - t_enter) / float(VIEW_STEPS);
+                float thickness = pc.layer_max - pc.layer_min;
+                float light_step = thickness / float(max(pc.light_steps, 1u));
+                float scatter_phase = phase(dot(dir, sun));
+
+                float transmittance = 1.0;
+                vec3 light = vec3(0.0);
+                for (int i = 0; i < VIEW_STEPS; i++) {
+                    float t = t_enter + (float(i) + 0.5) * step_size;
+                    vec3 p = origin + dir * t;
+                    float d = cloud_density(p);
+                    if (d <= 0.0) {
+                        continue;
+                    }
+
+                    // how much sunlight reaches this sample
+                    float optical_depth = 0.0;
+                    for (uint j = 0; j < pc.light_steps; j++) {
+                        vec3 q = p + sun * light_step * (float(j) + 0.5);
+                        optical_depth += cloud_density(q) * light_step;
+                    }
+                    float sun_transmittance = exp(-optical_depth);
+
+                    // Beer's law
+                    float sample_transmittance = exp(-d * step_size);
+                    vec3 scattered =
+                        SUN_COLOR * sun_transmittance * scatter_phase * 4.0
+                        + sky(dir) * 0.3;
+                    light += transmittance * (1.0 - sample_transmittance)
+                        * scattered;
+                    transmittance *= sample_transmittance;
+                    if (transmittance < 0.01) {
+                        break;
+                    }
+                }
+
+                outColor = vec4(sky(dir) * transmittance + light, 1.0);
+            }
+            "#
+    }
+}
+
+mod tone_map_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        include: ["src/application"],
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+            #extension GL_GOOGLE_include_directive : require
+
+            #include <dither.glsl>
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            layout(set = 0, binding = 1) uniform sampler3D lut;
+            layout(set = 0, binding = 2) uniform sampler2D blue_noise;
+
+            layout(push_constant) uniform PushConstantData {
+                vec4 domain_min;
+                vec4 domain_max;
+                float strength;
+                float lut_size;
+                uint srgb_target;
+                uint dither;
+            } pc;
+
+            void main() {
+                vec4 color = texture(scene, fragUv);
+
+                // luts are authored for display encoded colors, an sRGB
+                // target hands the shader linear colors so encode them first
+                vec3 encoded = color.rgb;
+                if (pc.srgb_target != 0) {
+                    encoded = dither_linear_to_srgb(clamp(color.rgb, 0.0, 1.0));
+                }
+
+                // sample the centers of the first and last texels at the
+                // edges of the domain so the whole table is interpolated
+                vec3 range = pc.domain_max.xyz - pc.domain_min.xyz;
+                vec3 t = clamp((encoded - pc.domain_min.xyz) / range, 0.0, 1.0);
+                vec3 uvw = (t * (pc.lut_size - 1.0) + 0.5) / pc.lut_size;
+                vec3 graded = texture(lut, uvw).rgb;
+                if (pc.srgb_target != 0) {
+                    graded = dither_srgb_to_linear(clamp(graded, 0.0, 1.0));
+                }
+
+                vec3 result = mix(color.rgb, graded, pc.strength);
+                if (pc.dither != 0) {
+                    ivec2 texel = dither_texel(
+                        gl_FragCoord.xy,
+                        textureSize(blue_noise, 0)
+                    );
+                    float noise = texelFetch(blue_noise, texel, 0).r;
+                    result = dither(result, noise, pc.srgb_target != 0);
+                }
+                outColor = vec4(result, color.a);
+            }
+            "#
+    }
+}
+
+mod velocity_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 pos;
+            layout(location = 1) in vec2 previous_pos;
+
+            layout(location = 0) flat out vec2 vertVelocity;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 mvp;
+                mat4 previous_mvp;
+                vec2 extent;
+            } pc;
+
+            void main() {
+                vec4 current = pc.mvp * vec4(pos, 0.0, 1.0);
+                vec4 previous = pc.previous_mvp * vec4(previous_pos, 0.0, 1.0);
+
+                // clip space to pixels, ndc spans two units across the screen
+                vec2 motion = current.xy / current.w - previous.xy / previous.w;
+                vertVelocity = motion * 0.5 * pc.extent;
+
+                // the same size as the particles
+                gl_PointSize = 64.0;
+                gl_Position = current;
+            }
+            "#
+    }
+}
+
+mod velocity_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) flat in vec2 fragVelocity;
+            layout(location = 0) out vec2 outVelocity;
+
+            void main() {
+                outVelocity = fragVelocity;
+            }
+            "#
+    }
+}
+
+mod motion_blur_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            layout(set = 0, binding = 1) uniform sampler2D velocity;
+
+            layout(push_constant) uniform PushConstantData {
+                uint samples;
+                float max_velocity_px;
+                float strength;
+            } pc;
+
+            void main() {
+                vec2 motion = texture(velocity, fragUv).xy * pc.strength;
+                float speed = length(motion);
+                if (speed < 0.5 || pc.samples < 2) {
+                    outColor = texture(scene, fragUv);
+                    return;
+                }
+                motion *= min(speed, pc.max_velocity_px) / speed;
+
+                // center the samples on the pixel so the blur trails both
+                // ways, like a shutter open across the frame
+                vec2 step_uv = motion / vec2(textureSize(scene, 0));
+                vec4 sum = vec4(0.0);
+                for (uint i = 0; i < pc.samples; i++) {
+                    float t = float(i) / float(pc.samples - 1) - 0.5;
+                    sum += texture(scene, fragUv + step_uv * t);
+                }
+                outColor = sum / float(pc.samples);
+            }
+            "#
+    }
+}
+
+mod distance_vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec3 pos;
+
+            layout(location = 0) out float vertDistance;
+
+            layout(push_constant) uniform PushConstantData {
+                mat4 mvp;
+                float depth_range;
+            } pc;
+
+            void main() {
+                gl_Position = pc.mvp * vec4(pos, 1.0);
+                vertDistance = gl_Position.z / gl_Position.w * pc.depth_range;
+
+                // the same size as the particles
+                gl_PointSize = 64.0;
+            }
+            "#
+    }
+}
+
+mod distance_fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in float fragDistance;
+            layout(location = 0) out float outDistance;
+
+            void main() {
+                outDistance = fragDistance;
+            }
+            "#
+    }
+}
+
+mod depth_of_field_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+            layout(set = 0, binding = 1) uniform sampler2D distance;
+            layout(set = 0, binding = 2) uniform sampler2D bokeh;
+
+            layout(push_constant) uniform PushConstantData {
+                float focus_distance;
+                float aperture;
+                float max_blur_radius;
+                uint shape;
+            } pc;
+
+            const uint SHAPE_CIRCLE = 0;
+            const uint SHAPE_HEXAGON = 1;
+            const uint SHAPE_CUSTOM = 2;
+
+            // the kernel is a grid of taps across the unit square, masked
+            // by the bokeh shape
+            const int KERNEL_RADIUS = 5;
+
+            // the signed circle of confusion in pixels, negative in front
+            // of the focus and positive behind it
+            float circle_of_confusion(vec2 uv) {
+                float d = texture(distance, uv).r;
+                float coc = (d - pc.focus_distance) * pc.aperture;
+                return clamp(coc, -1.0, 1.0) * pc.max_blur_radius;
+            }
+
+            // how much a tap at an offset in the unit square contributes
+            float bokeh_weight(vec2 offset) {
+                if (pc.shape == SHAPE_CUSTOM) {
+                    return texture(bokeh, offset * 0.5 + 0.5).r;
+                }
+                if (pc.shape == SHAPE_HEXAGON) {
+                    vec2 p = abs(offset);
+                    float hex = max(p.x * 0.866025 + p.y * 0.5, p.y);
+                    return hex <= 1.0 ? 1.0 : 0.0;
+                }
+                return dot(offset, offset) <= 1.0 ? 1.0 : 0.0;
+            }
+
+            void main() {
+                vec4 sharp = texture(scene, fragUv);
+                float center_coc = circle_of_confusion(fragUv);
+                vec2 texel = 1.0 / vec2(textureSize(scene, 0));
+
+                vec4 near_sum = vec4(0.0);
+                float near_weight = 0.0;
+                vec4 far_sum = vec4(0.0);
+                float far_weight = 0.0;
+                float taps = 0.0;
+                for (int y = -KERNEL_RADIUS; y <= KERNEL_RADIUS; y++) {
+                    for (int x = -KERNEL_RADIUS; x <= KERNEL_RADIUS; x++) {
+                        vec2 offset = vec2(x, y) / float(KERNEL_RADIUS);
+                        float weight = bokeh_weight(offset);
+                        if (weight <= 0.0) {
+                            continue;
+                        }
+                        taps += weight;
+
+                        vec2 offset_px = offset * pc.max_blur_radius;
+                        vec2 uv = fragUv + offset_px * texel;
+                        float coc = circle_of_confusion(uv);
+                        vec4 color = texture(scene, uv);
+
+                        // a sample only reaches this pixel when its own
+                        // circle of confusion covers the distance to it
+                        float reach = length(offset_px);
+                        if (coc < 0.0 && -coc >= reach) {
+                            near_sum += color * weight;
+                            near_weight += weight;
+                        }
+                        if (coc > 0.0 && coc >= reach
+                            && center_coc >= 0.0) {
+                            far_sum += color * weight;
+                            far_weight += weight;
+                        }
+                    }
+                }
+
+                // the far layer replaces the sharp scene as this pixel
+                // falls out of focus
+                vec4 color = sharp;
+                if (far_weight > 0.0) {
+                    float far_amount =
+                        clamp(center_coc / pc.max_blur_radius * 4.0, 0.0, 1.0);
+                    color = mix(sharp, far_sum / far_weight, far_amount);
+                }
+
+                // the near layer covers whatever is behind it in proportion
+                // to how much of the kernel it filled
+                if (near_weight > 0.0) {
+                    float coverage =
+                        clamp(near_weight / max(taps, 1.0) * 2.0, 0.0, 1.0);
+                    color = mix(color, near_sum / near_weight, coverage);
+                }
+                outColor = color;
+            }
+            "#
+    }
+}
+
+mod lens_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D scene;
+
+            layout(push_constant) uniform PushConstantData {
+                float vignette_strength;
+                float vignette_radius;
+                float chromatic_aberration;
+                float barrel_distortion;
+                float aspect_ratio;
+            } pc;
+
+            // the scene at a point relative to the center of the screen, or
+            // black when the point is off screen
+            vec4 sample_centered(vec2 centered) {
+                vec2 uv = centered * 0.5 + 0.5;
+                if (any(lessThan(uv, vec2(0.0)))
+                    || any(greaterThan(uv, vec2(1.0)))) {
+                    return vec4(0.0, 0.0, 0.0, 1.0);
+                }
+                return texture(scene, uv);
+            }
+
+            void main() {
+                // distances are measured in the aspect corrected space so
+                // the effects stay round on a wide screen, and scaled so a
+                // corner is 1 away from the center
+                vec2 centered = fragUv * 2.0 - 1.0;
+                vec2 aspect = vec2(pc.aspect_ratio, 1.0);
+                float corner = length(aspect);
+                float r = length(centered * aspect) / corner;
+
+                vec2 distorted =
+                    centered * (1.0 + pc.barrel_distortion * r * r);
+
+                float red = sample_centered(
+                    distorted * (1.0 + pc.chromatic_aberration)).r;
+                vec4 green = sample_centered(distorted);
+                float blue = sample_centered(
+                    distorted * (1.0 - pc.chromatic_aberration)).b;
+
+                float vignette = 1.0 - pc.vignette_strength
+                    * smoothstep(pc.vignette_radius, 1.0, r);
+                outColor = vec4(vec3(red, green.g, blue) * vignette, green.a);
+            }
+            "#
+    }
+}
@@ -0,0 +1,384 @@
+use super::cull::CullStats;
+use super::frame_stats::{FrameStats, HISTORY, MAX_MARKERS};
+use super::fullscreen;
+use super::text::{Label, TextRenderer};
+use crate::display::{Display, WaitStats};
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::impl_vertex;
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// The panel's corners in normalized device coordinates, top left first.
+const PANEL: [[f32; 2]; 2] = [[0.4, -0.95], [0.95, -0.6]];
+
+/// The height of a line of the window sizes under the panel, and the
+/// distance between lines, in normalized device coordinates.
+const SIZE_LINE_HEIGHT: f32 = 0.03;
+const SIZE_LINE_SPACING: f32 = 0.04;
+
+/// The frame time at the top of the graph, in milliseconds.
+const MAX_FRAME_TIME: f32 = 50.0;
+
+/// Guide lines are drawn at 60fps and 30fps, frames slower than a guide
+/// take on its color.
+const GUIDES: [(f32, [f32; 4]); 2] = [
+    (1000.0 / 60.0, [1.0, 0.8, 0.2, 1.0]),
+    (1000.0 / 30.0, [1.0, 0.25, 0.2, 1.0]),
+];
+
+/// The color of frames faster than every guide.
+const FAST: [f32; 4] = [0.3, 1.0, 0.4, 1.0];
+
+/// The color of the measured latency, drawn over the frame times.
+const LATENCY: [f32; 4] = [0.3, 0.7, 1.0, 1.0];
+
+/// The color of the vertical line drawn at each marker's frame.
+const MARKER: [f32; 4] = [0.9, 0.5, 1.0, 0.8];
+
+/// Each line segment of the two graphs, markers, guides, and border is two
+/// vertices.
+const MAX_VERTICES: usize =
+    2 * (2 * (HISTORY - 1) + MAX_MARKERS + GUIDES.len() + 4);
+
+#[derive(Default, Debug, Copy, Clone)]
+pub struct OverlayVertex {
+    pub pos: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl_vertex!(OverlayVertex, pos, color);
+
+/// A scrolling graph of the recent frame times in a corner of the window,
+/// with the measured latency of each frame over it in blue and a purple line
+/// at each marker.
+///
+/// The line vertices are rebuilt in place every update and uploaded through
+/// a buffer pool, so drawing the graph doesn't allocate once the pool has
+/// grown. Under the panel are how often the cpu had to wait for the gpu
+/// before beginning a frame, how many bounds culling rejected in the last
+/// frame, and the window's logical, physical, and surface sizes, read again
+/// whenever the swapchain is rebuilt.
+pub struct ProfilerOverlay {
+    line_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    panel_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    vertex_pool: CpuBufferPool<OverlayVertex>,
+    panel: Arc<CpuAccessibleBuffer<[OverlayVertex]>>,
+    lines: Vec<OverlayVertex>,
+    text: TextRenderer,
+    waits: String,
+    culled: String,
+    window_sizes: Vec<String>,
+}
+
+impl ProfilerOverlay {
+    pub fn new(display: &Display) -> Result<Self> {
+        let (line_pipeline, panel_pipeline) = create_graphics_pipelines(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        Ok(Self {
+            line_pipeline,
+            panel_pipeline,
+            vertex_pool: CpuBufferPool::vertex_buffer(display.device.clone()),
+            panel: create_panel_buffer(&display.device)?,
+            lines: Vec::with_capacity(MAX_VERTICES),
+            text: TextRenderer::new(display)?,
+            waits: String::new(),
+            culled: String::new(),
+            window_sizes: display.window_sizes()?.describe(),
+        })
+    }
+
+    /// Rebuild the graph from the latest frame times, latencies, and
+    /// markers, and the lines under it from the waits for the gpu and what
+    /// was culled.
+    pub fn update(
+        &mut self,
+        frame_stats: &FrameStats,
+        waits: WaitStats,
+        culled: CullStats,
+    ) {
+        self.waits.clear();
+        write!(
+            self.waits,
+            "cpu waited {:.0}% of frames, {:.1}ms avg",
+            waits.fraction_waited() * 100.0,
+            waits.average_wait().as_secs_f32() * 1000.0
+        )
+        .expect("unable to format the waits");
+        self.culled.clear();
+        write!(
+            self.culled,
+            "culled {} of {} bounds",
+            culled.rejected, culled.tested
+        )
+        .expect("unable to format the culled bounds");
+
+        let [[left, top], [right, bottom]] = PANEL;
+        let x =
+            |i: usize| left + (right - left) * i as f32 / (HISTORY - 1) as f32;
+        let y = |ms: f32| {
+            bottom - (bottom - top) * (ms / MAX_FRAME_TIME).clamp(0.0, 1.0)
+        };
+
+        self.lines.clear();
+        let border = [0.6, 0.6, 0.6, 1.0];
+        let corners =
+            [[left, top], [right, top], [right, bottom], [left, bottom]];
+        for (i, &corner) in corners.iter().enumerate() {
+            self.lines.push(OverlayVertex {
+                pos: corner,
+                color: border,
+            });
+            self.lines.push(OverlayVertex {
+                pos: corners[(i + 1) % corners.len()],
+                color: border,
+            });
+        }
+        for &(ms, color) in GUIDES.iter() {
+            let color = [color[0], color[1], color[2], 0.5];
+            self.lines.push(OverlayVertex {
+                pos: [left, y(ms)],
+                color,
+            });
+            self.lines.push(OverlayVertex {
+                pos: [right, y(ms)],
+                color,
+            });
+        }
+        for (i, _) in frame_stats.history_markers() {
+            self.lines.push(OverlayVertex {
+                pos: [x(i), top],
+                color: MARKER,
+            });
+            self.lines.push(OverlayVertex {
+                pos: [x(i), bottom],
+                color: MARKER,
+            });
+        }
+        let mut previous = None;
+        for (i, ms) in frame_stats.frame_times().enumerate() {
+            let vertex = OverlayVertex {
+                pos: [x(i), y(ms)],
+                color: threshold_color(ms),
+            };
+            if let Some(previous) = previous {
+                self.lines.push(previous);
+                self.lines.push(vertex);
+            }
+            previous = Some(vertex);
+        }
+
+        // frames without a measured latency leave a gap
+        let mut previous = None;
+        for (i, ms) in frame_stats.latencies().enumerate() {
+            if ms <= 0.0 {
+                previous = None;
+                continue;
+            }
+            let vertex = OverlayVertex {
+                pos: [x(i), y(ms)],
+                color: LATENCY,
+            };
+            if let Some(previous) = previous {
+                self.lines.push(previous);
+                self.lines.push(vertex);
+            }
+            previous = Some(vertex);
+        }
+    }
+
+    /// Build the secondary command buffers which draw the panel, graph, and
+    /// the lines under it over the final image.
+    pub fn draw(&self, display: &Display) -> Result<Vec<AutoCommandBuffer>> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the profiler")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.panel_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.panel.clone()],
+                (),
+                (),
+            )
+            .context("unable to draw the profiler panel")?;
+        let lines = Arc::new(
+            self.vertex_pool
+                .chunk(self.lines.iter().copied())
+                .context("unable to upload the profiler graph")?,
+        );
+        builder
+            .draw(
+                self.line_pipeline.clone(),
+                &DynamicState::none(),
+                vec![lines],
+                (),
+                (),
+            )
+            .context("unable to draw the profiler graph")?;
+        let graph = builder
+            .build()
+            .context("unable to build the command buffer")?;
+
+        let [[left, _], [_, bottom]] = PANEL;
+        let labels: Vec<Label> = [&self.waits, &self.culled]
+            .iter()
+            .copied()
+            .chain(&self.window_sizes)
+            .enumerate()
+            .map(|(i, line)| Label {
+                text: line,
+                position: [left, bottom + SIZE_LINE_SPACING * (i as f32 + 0.5)],
+                height: SIZE_LINE_HEIGHT,
+            })
+            .collect();
+        Ok(vec![graph, self.text.draw(display, &labels)?])
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let (line_pipeline, panel_pipeline) = create_graphics_pipelines(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.line_pipeline = line_pipeline;
+        self.panel_pipeline = panel_pipeline;
+        self.text.rebuild_swapchain_resources(display)?;
+        self.window_sizes = display.window_sizes()?.describe();
+        Ok(())
+    }
+}
+
+/// The color of the slowest guide the frame time is over.
+fn threshold_color(ms: f32) -> [f32; 4] {
+    GUIDES
+        .iter()
+        .rev()
+        .find(|(guide, _)| ms > *guide)
+        .map_or(FAST, |&(_, color)| color)
+}
+
+/// Two translucent triangles behind the graph.
+fn create_panel_buffer(
+    device: &Arc<Device>,
+) -> Result<Arc<CpuAccessibleBuffer<[OverlayVertex]>>> {
+    let [[left, top], [right, bottom]] = PANEL;
+    let corners = [
+        [left, top],
+        [right, top],
+        [right, bottom],
+        [left, top],
+        [right, bottom],
+        [left, bottom],
+    ];
+    let vertices = corners.iter().map(|&pos| OverlayVertex {
+        pos,
+        color: [0.0, 0.0, 0.0, 0.6],
+    });
+    CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::vertex_buffer(),
+        false,
+        vertices,
+    )
+    .context("unable to create the profiler panel buffer")
+}
+
+/// The pipelines for an overlay's lines and the panels behind them, which
+/// only differ in topology. The gizmos draw their handles with them too.
+pub fn create_graphics_pipelines(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<(
+    Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+)> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the overlay vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the overlay fragment shader")?;
+
+    let start = || -> Result<_> {
+        Ok(GraphicsPipeline::start()
+            .vertex_input_single_buffer::<OverlayVertex>()
+            .vertex_shader(vert.main_entry_point(), ())
+            .fragment_shader(frag.main_entry_point(), ())
+            .viewports(vec![fullscreen::viewport(swapchain_extent)])
+            .depth_clamp(false)
+            .polygon_mode_fill()
+            .line_width(1.0)
+            .depth_write(false)
+            .sample_shading_disabled()
+            .blend_alpha_blending()
+            .render_pass(
+                Subpass::from(render_pass.clone(), 0)
+                    .context("could not create the overlay subpass")?,
+            ))
+    };
+    let lines = start()?
+        .line_list()
+        .build(device.clone())
+        .context("could not create the overlay line pipeline")?;
+    let panel = start()?
+        .triangle_list()
+        .build(device.clone())
+        .context("could not create the overlay panel pipeline")?;
+    Ok((Arc::new(lines), Arc::new(panel)))
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 pos;
+            layout(location = 1) in vec4 color;
+
+            layout(location = 0) out vec4 vertColor;
+
+            void main() {
+                vertColor = color;
+                gl_Position = vec4(pos, 0.0, 1.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec4 fragColor;
+            layout(location = 0) out vec4 outColor;
+
+            void main() {
+                outColor = fragColor;
+            }
+            "#
+    }
+}
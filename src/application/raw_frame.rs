@@ -0,0 +1,87 @@
+use crate::display::{Display, Stage, Submitter};
+use std::sync::Arc;
+use vulkano::command_buffer::AutoCommandBuffer;
+use vulkano::device::{Device, DeviceOwned, Queue};
+use vulkano::framebuffer::{FramebufferAbstract, RenderPassAbstract};
+
+/// An escape hatch for sketches which need vulkano directly, like for a
+/// barrier pattern or an extension the renderers don't cover.
+///
+/// A sketch gets one from `Sketch::render_raw` every frame, builds primary
+/// command buffers on the device and queues it exposes, and hands them to
+/// `execute_raw`. They run in submission order after every pass the
+/// application draws offscreen, and before the swapchain's render pass
+/// draws the frame and its overlays, chained on the same futures.
+///
+/// The command buffers must be built for the graphics queue's family on
+/// this device, debug builds assert the device. They can't present, vulkano
+/// has no command for it. They must not keep the swapchain's framebuffers
+/// or images past the frame: which image the frame lands in is only known
+/// once it's acquired, after the command buffers are recorded, and the
+/// swapchain is rebuilt whenever the window changes size.
+pub struct RawFrame<'a> {
+    display: &'a Display,
+    command_buffers: Vec<Arc<AutoCommandBuffer>>,
+}
+
+impl<'a> RawFrame<'a> {
+    pub(crate) fn new(display: &'a Display) -> Self {
+        Self {
+            display,
+            command_buffers: vec![],
+        }
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.display.device
+    }
+
+    /// The queue the raw command buffers run on.
+    pub fn graphics_queue(&self) -> &Arc<Queue> {
+        &self.display.graphics_queue
+    }
+
+    /// The compute queue, or the graphics queue without a separate one.
+    /// Work submitted here isn't chained with the frame.
+    pub fn compute_queue(&self) -> &Arc<Queue> {
+        &self.display.compute_queue
+    }
+
+    /// The render pass the frame is drawn with, for pipelines which draw
+    /// into secondary command buffers of the sketch's own.
+    pub fn render_pass(&self) -> &Arc<dyn RenderPassAbstract + Send + Sync> {
+        &self.display.render_pass
+    }
+
+    /// A framebuffer for each swapchain image, in the order the images are
+    /// acquired by index.
+    pub fn framebuffers(
+        &self,
+    ) -> &[Arc<dyn FramebufferAbstract + Send + Sync>] {
+        &self.display.framebuffer_images
+    }
+
+    /// Run a primary command buffer with the frame, after the ones added
+    /// before it.
+    pub fn execute_raw(&mut self, command_buffer: Arc<AutoCommandBuffer>) {
+        debug_assert!(
+            Arc::ptr_eq(command_buffer.device(), &self.display.device),
+            "a raw command buffer was built on another device"
+        );
+        debug_assert!(
+            !self
+                .command_buffers
+                .iter()
+                .any(|queued| Arc::ptr_eq(queued, &command_buffer)),
+            "a raw command buffer was executed twice in one frame"
+        );
+        self.command_buffers.push(command_buffer);
+    }
+
+    /// Queue the command buffers on the frame's submitter.
+    pub(crate) fn submit(self, submitter: &mut Submitter) {
+        for command_buffer in self.command_buffers {
+            submitter.add_shared(Stage::Raw, "raw", command_buffer);
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use super::debug::BarrierValidator;
+use super::fullscreen::{self, FullscreenVertex};
+use crate::display::{Display, Submitter};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, CommandBuffer, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::Subpass;
+use vulkano::image::swapchain::SwapchainImage;
+use vulkano::image::{Dimensions, ImageUsage, StorageImage};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, Sampler};
+use vulkano::sync::GpuFuture;
+use winit::window::Window;
+
+/// How many frames the old frame takes to fade out.
+const FADE_FRAMES: u32 = 8;
+
+/// The color shown instead when no frame was presented before the rebuild.
+const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Covers the window with the last frame presented before the swapchain was
+/// rebuilt, then fades it out over the first few new frames.
+///
+/// Rebuilding everything which depends on the swapchain can take a while,
+/// long enough for the window to show garbage or stall. The last frame is
+/// blitted from its old swapchain image into a copy the new extent, which
+/// is presented as soon as the new swapchain exists. Without a frame to
+/// copy, the window is covered in the background color instead.
+pub struct RebuildFade {
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+
+    // how many frames have been drawn since the rebuild
+    frame: u32,
+}
+
+impl RebuildFade {
+    /// Copy `previous`, the last frame presented on the old swapchain, at
+    /// the new swapchain's extent.
+    pub fn new(
+        display: &Display,
+        previous: Option<Arc<SwapchainImage<Arc<Window>>>>,
+    ) -> Result<Self> {
+        let copy = copy_to_extent(display, previous)?;
+        let pipeline = create_graphics_pipeline(display)?;
+        let descriptor_set = create_descriptor_set(display, &pipeline, copy)?;
+        Ok(Self {
+            pipeline,
+            descriptor_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+            frame: 0,
+        })
+    }
+
+    /// Present a frame of nothing but the old frame, before the rest of
+    /// the rebuild. A failure only means the window isn't covered.
+    pub fn present(&self, display: &mut Display) {
+        let presented = self.draw(display).and_then(|commands| {
+            Submitter::default().submit(display, vec![commands])
+        });
+        if let Err(error) = presented {
+            log::warn!("unable to present the previous frame: {:?}", error);
+        }
+    }
+
+    /// True once the old frame has faded out completely.
+    pub fn finished(&self) -> bool {
+        self.frame >= FADE_FRAMES
+    }
+
+    /// Build a secondary command buffer which draws the old frame over the
+    /// new one, a little more transparent than last time.
+    pub fn draw_next(
+        &mut self,
+        display: &Display,
+    ) -> Result<AutoCommandBuffer> {
+        self.frame += 1;
+        self.draw(display)
+    }
+
+    fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let opacity = 1.0 - self.frame as f32 / FADE_FRAMES as f32;
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the rebuild fade")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.descriptor_set.clone(),
+                fragment_shader::ty::PushConstantData { opacity },
+            )
+            .context("unable to draw the rebuild fade")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+}
+
+/// Blit the old frame into an image the size of the new swapchain, scaling
+/// it to fit, and wait for the blit to finish. Without an old frame the
+/// image is a single texel of the background color.
+fn copy_to_extent(
+    display: &Display,
+    previous: Option<Arc<SwapchainImage<Arc<Window>>>>,
+) -> Result<Arc<StorageImage<Format>>> {
+    let [width, height] = match previous {
+        Some(_) => display.swapchain.dimensions(),
+        None => [1, 1],
+    };
+    let copy = StorageImage::with_usage(
+        display.device.clone(),
+        Dimensions::Dim2d { width, height },
+        display.swapchain.format(),
+        ImageUsage {
+            sampled: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        },
+        Some(display.graphics_queue.family()),
+    )
+    .context("unable to create the previous frame's copy")?;
+
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            display.device.clone(),
+            display.graphics_queue.family(),
+        )
+        .context("unable to create the rebuild fade command buffer builder")?,
+        &display.graphics_queue,
+    );
+    match previous {
+        Some(previous) => {
+            let [previous_width, previous_height] = previous.dimensions();
+            builder
+                .blit_image(
+                    previous,
+                    [0, 0, 0],
+                    [previous_width as i32, previous_height as i32, 1],
+                    0,
+                    0,
+                    copy.clone(),
+                    [0, 0, 0],
+                    [width as i32, height as i32, 1],
+                    0,
+                    0,
+                    1,
+                    Filter::Linear,
+                )
+                .context("unable to copy the previous frame")?;
+        }
+        None => {
+            builder
+                .clear_color_image(copy.clone(), ClearValue::Float(BACKGROUND))
+                .context("unable to clear the previous frame's copy")?;
+        }
+    }
+    let commands = builder
+        .build()
+        .context("unable to build the rebuild fade command buffer")?;
+    commands
+        .execute(display.graphics_queue.clone())
+        .context("unable to copy the previous frame")?
+        .then_signal_fence_and_flush()
+        .context("unable to copy the previous frame")?
+        .wait(None)
+        .context("unable to copy the previous frame")?;
+    Ok(copy)
+}
+
+fn create_descriptor_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    copy: Arc<StorageImage<Format>>,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let sampler =
+        Sampler::simple_repeat_linear_no_mipmap(display.device.clone());
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("rebuild fade pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(copy, sampler)?
+            .build()
+            .context("unable to create the rebuild fade descriptors")?,
+    ))
+}
+
+fn create_graphics_pipeline(
+    display: &Display,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let device = &display.device;
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the rebuild fade fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(display.swapchain.dimensions())])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_alpha_blending()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(display.render_pass.clone(), 0)
+                .context("could not create the rebuild fade subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the rebuild fade graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D previous;
+
+            layout(push_constant) uniform PushConstantData {
+                float opacity;
+            } pc;
+
+            void main() {
+                outColor = vec4(texture(previous, fragUv).rgb, pc.opacity);
+            }
+            "#
+    }
+}
@@ -0,0 +1,575 @@
+use std::fmt;
+use vulkano::format::Format;
+
+/// Why a render graph couldn't be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenderGraphError {
+    /// The named node reads a resource it writes, either itself or through
+    /// the nodes between them, so no order can run it after its own output.
+    Cycle(String),
+
+    /// `resource` could still be read when `overwritten_by` is written to
+    /// the memory they were planned to share.
+    AliasedRead {
+        resource: String,
+        overwritten_by: String,
+    },
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RenderGraphError::Cycle(node) => {
+                write!(f, "{:?} depends on its own output", node)
+            }
+            RenderGraphError::AliasedRead {
+                resource,
+                overwritten_by,
+            } => write!(
+                f,
+                "{:?} may be read after {:?} overwrites the memory they share",
+                resource, overwritten_by
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+/// A pass and the resources it reads and writes, by name.
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    reads: Vec<String>,
+    writes: Vec<String>,
+}
+
+/// What a transient image is. Images alike in every way can share memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientDesc {
+    pub extent: [u32; 2],
+    pub format: Format,
+    pub samples: u32,
+
+    /// The memory one image takes, for reporting what aliasing saves.
+    pub bytes: u64,
+}
+
+/// Transient images which share memory, in the order they hold it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasSlot {
+    pub desc: TransientDesc,
+    pub resources: Vec<String>,
+}
+
+/// Which transient images share memory, from `RenderGraph::alias_transients`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AliasPlan {
+    slots: Vec<AliasSlot>,
+}
+
+impl AliasPlan {
+    pub fn slots(&self) -> &[AliasSlot] {
+        &self.slots
+    }
+
+    /// The index of the slot a transient image was placed in.
+    pub fn slot_of(&self, resource: &str) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.resources.iter().any(|r| r == resource))
+    }
+
+    /// The memory the transient images would take with one image each.
+    pub fn unaliased_bytes(&self) -> u64 {
+        self.slots
+            .iter()
+            .map(|slot| slot.desc.bytes * slot.resources.len() as u64)
+            .sum()
+    }
+
+    /// The memory the transient images take with one image per slot.
+    pub fn aliased_bytes(&self) -> u64 {
+        self.slots.iter().map(|slot| slot.desc.bytes).sum()
+    }
+
+    pub fn saved_bytes(&self) -> u64 {
+        self.unaliased_bytes() - self.aliased_bytes()
+    }
+}
+
+/// The passes of a frame and the resources which connect them.
+///
+/// A node runs after every node which writes a resource it reads. Nodes
+/// which don't depend on each other keep the order they were added in, so
+/// a graph which is already in order compiles to the same order.
+///
+/// Resources declared transient only live for part of the frame, and
+/// `alias_transients` plans which of them can share memory.
+#[derive(Debug, Clone, Default)]
+pub struct RenderGraph {
+    nodes: Vec<Node>,
+    transients: Vec<(String, TransientDesc)>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pass which reads and writes the named resources.
+    pub fn add_node(&mut self, name: &str, reads: &[&str], writes: &[&str]) {
+        let owned = |names: &[&str]| -> Vec<String> {
+            names.iter().map(|&name| name.to_owned()).collect()
+        };
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            reads: owned(reads),
+            writes: owned(writes),
+        });
+    }
+
+    /// Declare a resource as a transient image which only has to live from
+    /// the first pass which uses it to the last.
+    pub fn add_transient(&mut self, name: &str, desc: TransientDesc) {
+        self.transients.push((name.to_owned(), desc));
+    }
+
+    /// The nodes in an order where each runs after the nodes it reads
+    /// from.
+    pub fn compile(&self) -> Result<Vec<&str>, RenderGraphError> {
+        let dependencies = self.dependencies();
+        if let Some(node) = (0..self.nodes.len())
+            .find(|&node| dependencies[node].contains(&node))
+        {
+            return Err(RenderGraphError::Cycle(self.nodes[node].name.clone()));
+        }
+
+        // Kahn's algorithm, always taking the earliest node which is ready
+        let mut remaining: Vec<usize> = dependencies
+            .iter()
+            .map(|dependencies| dependencies.len())
+            .collect();
+        let mut done = vec![false; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(node) = (0..self.nodes.len())
+            .find(|&node| !done[node] && remaining[node] == 0)
+        {
+            done[node] = true;
+            order.push(self.nodes[node].name.as_str());
+            for (dependent, dependencies) in dependencies.iter().enumerate() {
+                remaining[dependent] -= dependencies
+                    .iter()
+                    .filter(|&&dependency| dependency == node)
+                    .count();
+            }
+        }
+
+        if order.len() < self.nodes.len() {
+            // the nodes left over are on a cycle or depend on one
+            let node = (0..self.nodes.len())
+                .filter(|&node| !done[node])
+                .find(|&node| self.reaches(&dependencies, node, node))
+                .expect("one of the nodes left over is on a cycle");
+            return Err(RenderGraphError::Cycle(self.nodes[node].name.clone()));
+        }
+        Ok(order)
+    }
+
+    /// Place the transient images in slots which each get one image's
+    /// memory, so images whose lifetimes don't overlap share it.
+    ///
+    /// Images are placed in the order they're first used. An image joins a
+    /// slot of images with the same description when every pass using
+    /// those runs before every pass using it, by the graph's dependencies
+    /// rather than just the compiled order, so nothing can read an image
+    /// once another has been written over it. The plan is checked against
+    /// the dependencies again before it's returned.
+    pub fn alias_transients(&self) -> Result<AliasPlan, RenderGraphError> {
+        let order = self.compile()?;
+        let dependencies = self.dependencies();
+        let first_use = |resource: &str| {
+            order
+                .iter()
+                .position(|&name| {
+                    self.users(resource)
+                        .any(|node| self.nodes[node].name == name)
+                })
+                .unwrap_or(order.len())
+        };
+        let mut transients: Vec<&(String, TransientDesc)> =
+            self.transients.iter().collect();
+        transients.sort_by_key(|(name, _)| first_use(name));
+
+        let mut plan = AliasPlan::default();
+        for (name, desc) in transients {
+            let slot = plan.slots.iter_mut().find(|slot| {
+                slot.desc == *desc
+                    && slot.resources.iter().all(|earlier| {
+                        self.happens_before(&dependencies, earlier, name)
+                    })
+            });
+            match slot {
+                Some(slot) => slot.resources.push(name.clone()),
+                None => plan.slots.push(AliasSlot {
+                    desc: *desc,
+                    resources: vec![name.clone()],
+                }),
+            }
+        }
+        self.check_aliasing(&plan)?;
+        Ok(plan)
+    }
+
+    /// Fail when an image in a slot could be read after a later image in
+    /// the same slot is written.
+    pub fn check_aliasing(
+        &self,
+        plan: &AliasPlan,
+    ) -> Result<(), RenderGraphError> {
+        let dependencies = self.dependencies();
+        for slot in &plan.slots {
+            for (i, earlier) in slot.resources.iter().enumerate() {
+                for later in &slot.resources[i + 1..] {
+                    if !self.happens_before(&dependencies, earlier, later) {
+                        return Err(RenderGraphError::AliasedRead {
+                            resource: earlier.clone(),
+                            overwritten_by: later.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The graph in Graphviz's DOT language, with an edge from each writer
+    /// to each reader labeled with the resource between them. Render it
+    /// with `dot -Tsvg`.
+    pub fn to_dot_string(&self) -> String {
+        let mut dot = String::from("digraph render_graph {\n");
+        for node in &self.nodes {
+            dot.push_str(&format!("    {};\n", quote(&node.name)));
+        }
+        for reader in &self.nodes {
+            for resource in &reader.reads {
+                for writer in self
+                    .nodes
+                    .iter()
+                    .filter(|writer| writer.writes.contains(resource))
+                {
+                    dot.push_str(&format!(
+                        "    {} -> {} [label={}];\n",
+                        quote(&writer.name),
+                        quote(&reader.name),
+                        quote(resource)
+                    ));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// For each node, the nodes which write what it reads, once for every
+    /// resource they share.
+    fn dependencies(&self) -> Vec<Vec<usize>> {
+        self.nodes
+            .iter()
+            .map(|reader| {
+                reader
+                    .reads
+                    .iter()
+                    .flat_map(|resource| {
+                        self.nodes
+                            .iter()
+                            .enumerate()
+                            .filter(move |(_, writer)| {
+                                writer.writes.contains(resource)
+                            })
+                            .map(|(writer, _)| writer)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The nodes which read or write a resource.
+    fn users<'a>(
+        &'a self,
+        resource: &'a str,
+    ) -> impl Iterator<Item = usize> + 'a {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(move |(_, node)| {
+                node.reads.iter().any(|r| r == resource)
+                    || node.writes.iter().any(|w| w == resource)
+            })
+            .map(|(node, _)| node)
+    }
+
+    /// True when every node using `later` depends on every node using
+    /// `earlier`, so all of `earlier`'s uses are done before `later` is
+    /// touched.
+    fn happens_before(
+        &self,
+        dependencies: &[Vec<usize>],
+        earlier: &str,
+        later: &str,
+    ) -> bool {
+        self.users(earlier).all(|before| {
+            self.users(later).all(|after| {
+                after != before && self.reaches(dependencies, after, before)
+            })
+        })
+    }
+
+    /// True when `to` can be reached by following dependencies from `from`.
+    fn reaches(
+        &self,
+        dependencies: &[Vec<usize>],
+        from: usize,
+        to: usize,
+    ) -> bool {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut pending = dependencies[from].clone();
+        while let Some(node) = pending.pop() {
+            if node == to {
+                return true;
+            }
+            if !visited[node] {
+                visited[node] = true;
+                pending.extend(&dependencies[node]);
+            }
+        }
+        false
+    }
+}
+
+/// A DOT identifier for any name.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: TransientDesc = TransientDesc {
+        extent: [1920, 1080],
+        format: Format::B8G8R8A8Srgb,
+        samples: 1,
+        bytes: 1920 * 1080 * 4,
+    };
+
+    /// "scene" renders into the first target and each pass after it reads
+    /// one target and writes the next, the last writing "swapchain".
+    fn chain(passes: &[&str], desc: TransientDesc) -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        let targets: Vec<String> = (1..passes.len())
+            .map(|i| format!("target {}", i - 1))
+            .collect();
+        for target in &targets {
+            graph.add_transient(target, desc);
+        }
+        let mut reads = vec![];
+        for (i, pass) in passes.iter().enumerate() {
+            let writes = targets.get(i).map_or("swapchain", String::as_str);
+            graph.add_node(pass, &reads, &[writes]);
+            reads = vec![writes];
+        }
+        graph
+    }
+
+    fn resources(plan: &AliasPlan) -> Vec<Vec<&str>> {
+        plan.slots()
+            .iter()
+            .map(|slot| slot.resources.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    /// The passes `Application::render` submits with depth of field, motion
+    /// blur, lens effects, and tone mapping on, added backwards.
+    fn frame_graph() -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        graph.add_node("present", &["tone mapped"], &["swapchain"]);
+        graph.add_node("tone map", &["lens color"], &["tone mapped"]);
+        graph.add_node("lens effects", &["blurred color"], &["lens color"]);
+        graph.add_node(
+            "motion blur",
+            &["focused color", "velocity"],
+            &["blurred color"],
+        );
+        graph.add_node("motion blur velocity", &[], &["velocity"]);
+        graph.add_node(
+            "depth of field",
+            &["scene color", "distance"],
+            &["focused color"],
+        );
+        graph.add_node("depth of field distance", &[], &["distance"]);
+        graph.add_node("scene", &["offscreen"], &["scene color"]);
+        graph.add_node("sketch offscreen", &[], &["offscreen"]);
+        graph
+    }
+
+    #[test]
+    fn the_frame_compiles_after_its_inputs() {
+        assert_eq!(
+            frame_graph().compile().unwrap(),
+            vec![
+                "motion blur velocity",
+                "depth of field distance",
+                "sketch offscreen",
+                "scene",
+                "depth of field",
+                "motion blur",
+                "lens effects",
+                "tone map",
+                "present",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_dot_graph_has_every_pass_and_dependency() {
+        let dot = frame_graph().to_dot_string();
+        assert!(dot.starts_with("digraph render_graph {\n"));
+        assert_eq!(dot.matches(" -> ").count(), 8);
+        assert!(dot.contains("    \"tone map\";\n"));
+        assert!(dot.contains(
+            "    \"motion blur velocity\" -> \"motion blur\" \
+             [label=\"velocity\"];\n"
+        ));
+    }
+
+    #[test]
+    fn a_pass_reading_its_own_output_is_a_cycle() {
+        let mut graph = RenderGraph::new();
+        graph.add_node("scene", &[], &["scene color"]);
+        graph.add_node("trails", &["scene color", "trails"], &["trails"]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::Cycle("trails".to_owned()))
+        );
+    }
+
+    #[test]
+    fn a_loop_through_several_passes_is_a_cycle() {
+        let mut graph = RenderGraph::new();
+        graph.add_node("present", &["c"], &["swapchain"]);
+        graph.add_node("a", &["c"], &["a"]);
+        graph.add_node("b", &["a"], &["b"]);
+        graph.add_node("c", &["b"], &["c"]);
+        assert_eq!(
+            graph.compile(),
+            Err(RenderGraphError::Cycle("a".to_owned()))
+        );
+    }
+
+    #[test]
+    fn a_chain_alternates_between_two_targets() {
+        let graph = chain(&["scene", "a", "b", "c", "d"], FULL);
+        let plan = graph.alias_transients().unwrap();
+        assert_eq!(
+            resources(&plan),
+            vec![vec!["target 0", "target 2"], vec!["target 1", "target 3"]]
+        );
+        assert_eq!(plan.slot_of("target 2"), Some(0));
+        assert_eq!(plan.slot_of("swapchain"), None);
+        assert_eq!(plan.unaliased_bytes(), 4 * FULL.bytes);
+        assert_eq!(plan.aliased_bytes(), 2 * FULL.bytes);
+        assert_eq!(plan.saved_bytes(), 2 * FULL.bytes);
+    }
+
+    #[test]
+    fn overlapping_lifetimes_are_not_aliased() {
+        // "late" is written before "early" is last read
+        let mut graph = RenderGraph::new();
+        graph.add_transient("early", FULL);
+        graph.add_transient("late", FULL);
+        graph.add_node("first", &[], &["early"]);
+        graph.add_node("second", &["early"], &["late"]);
+        graph.add_node("third", &["early", "late"], &["swapchain"]);
+        let plan = graph.alias_transients().unwrap();
+        assert_eq!(resources(&plan), vec![vec!["early"], vec!["late"]]);
+        assert_eq!(plan.saved_bytes(), 0);
+    }
+
+    #[test]
+    fn different_descriptions_are_not_aliased() {
+        let half = TransientDesc {
+            extent: [960, 540],
+            bytes: 960 * 540 * 4,
+            ..FULL
+        };
+        let mut graph = chain(&["scene", "a", "b"], FULL);
+        graph.add_transient("half", half);
+        graph.add_node("c", &["swapchain"], &["half"]);
+        graph.add_node("d", &["half"], &["output"]);
+        let plan = graph.alias_transients().unwrap();
+        assert_eq!(
+            resources(&plan),
+            vec![vec!["target 0"], vec!["target 1"], vec!["half"]]
+        );
+
+        let multisampled = TransientDesc { samples: 4, ..FULL };
+        let mut graph = RenderGraph::new();
+        graph.add_transient("resolved", FULL);
+        graph.add_transient("multisampled", multisampled);
+        graph.add_node("first", &[], &["resolved"]);
+        graph.add_node("second", &["resolved"], &["out"]);
+        graph.add_node("third", &["out"], &["multisampled"]);
+        let plan = graph.alias_transients().unwrap();
+        assert_eq!(plan.slots().len(), 2);
+    }
+
+    #[test]
+    fn passes_which_are_only_ordered_are_not_aliased() {
+        // nothing makes "b" wait for "a" to finish reading "first", so they
+        // could overlap on the gpu even though they compile in order
+        let mut graph = RenderGraph::new();
+        graph.add_transient("first", FULL);
+        graph.add_transient("second", FULL);
+        graph.add_node("write first", &[], &["first"]);
+        graph.add_node("a", &["first"], &["left"]);
+        graph.add_node("write second", &[], &["second"]);
+        graph.add_node("b", &["second"], &["right"]);
+        let plan = graph.alias_transients().unwrap();
+        assert_eq!(resources(&plan), vec![vec!["first"], vec!["second"]]);
+    }
+
+    #[test]
+    fn unsafe_plans_are_rejected() {
+        let mut graph = RenderGraph::new();
+        graph.add_transient("early", FULL);
+        graph.add_transient("late", FULL);
+        graph.add_node("first", &[], &["early"]);
+        graph.add_node("second", &["early"], &["late"]);
+        graph.add_node("third", &["early", "late"], &["swapchain"]);
+        let plan = AliasPlan {
+            slots: vec![AliasSlot {
+                desc: FULL,
+                resources: vec!["early".to_owned(), "late".to_owned()],
+            }],
+        };
+        assert_eq!(
+            graph.check_aliasing(&plan),
+            Err(RenderGraphError::AliasedRead {
+                resource: "early".to_owned(),
+                overwritten_by: "late".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn cycles_stop_aliasing() {
+        let mut graph = RenderGraph::new();
+        graph.add_transient("history", FULL);
+        graph.add_node("trails", &["history"], &["history"]);
+        assert_eq!(
+            graph.alias_transients(),
+            Err(RenderGraphError::Cycle("trails".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use winit::event::VirtualKeyCode;
+
+/// How far time advances each frame while recording or replaying, in
+/// seconds.
+pub const FRAME_TIME: f32 = 1.0 / 60.0;
+
+/// Bumped whenever the replay file layout changes.
+const REPLAY_VERSION: u32 = 1;
+
+/// Input from the user which changes what the application does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputEvent {
+    KeyPressed(VirtualKeyCode),
+}
+
+/// Everything needed to replay a run.
+#[derive(Serialize, Deserialize)]
+struct ReplayFile {
+    version: u32,
+    simulation: Option<String>,
+    seed: Option<u64>,
+    events: Vec<(u64, InputEvent)>,
+}
+
+/// Records every input event along with the frame it arrived on.
+///
+/// The simulation and its seed are saved alongside the events since
+/// replaying the same keys over differently seeded content would diverge
+/// on the first frame.
+pub struct ReplayRecorder {
+    pub simulation: Option<String>,
+    pub seed: Option<u64>,
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+impl ReplayRecorder {
+    pub fn new(simulation: Option<&str>, seed: Option<u64>) -> Self {
+        Self {
+            simulation: simulation.map(str::to_owned),
+            seed,
+            events: vec![],
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, event: InputEvent) {
+        self.events.push((frame, event));
+    }
+
+    /// Write the recording to a binary file.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)
+            .with_context(|| format!("unable to create {:?}", path))?;
+        let replay = ReplayFile {
+            version: REPLAY_VERSION,
+            simulation: self.simulation.clone(),
+            seed: self.seed,
+            events: self.events.clone(),
+        };
+        bincode::serialize_into(BufWriter::new(file), &replay)
+            .with_context(|| format!("unable to write the replay {:?}", path))
+    }
+}
+
+/// Hands back recorded events on the frames they were recorded on.
+pub struct ReplayPlayer {
+    simulation: Option<String>,
+    seed: Option<u64>,
+    events: VecDeque<(u64, InputEvent)>,
+}
+
+impl ReplayPlayer {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("unable to open {:?}", path))?;
+        let replay: ReplayFile = bincode::deserialize_from(BufReader::new(
+            file,
+        ))
+        .with_context(|| format!("unable to read the replay {:?}", path))?;
+        if replay.version != REPLAY_VERSION {
+            bail!(
+                "{:?} is a version {} replay, only version {} is supported",
+                path,
+                replay.version,
+                REPLAY_VERSION
+            );
+        }
+        log::info!("loaded {} events from {:?}", replay.events.len(), path);
+        Ok(Self {
+            simulation: replay.simulation,
+            seed: replay.seed,
+            events: replay.events.into(),
+        })
+    }
+
+    /// The simulation which was running when the replay was recorded.
+    pub fn simulation(&self) -> Option<&str> {
+        self.simulation.as_deref()
+    }
+
+    /// The seed the recorded simulation was created with.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Take the events recorded on `frame`, in the order they arrived.
+    /// Events from earlier frames which were never taken come first.
+    pub fn events_for(&mut self, frame: u64) -> Vec<InputEvent> {
+        let mut events = vec![];
+        while let Some(&(recorded, event)) = self.events.front() {
+            if recorded > frame {
+                break;
+            }
+            events.push(event);
+            self.events.pop_front();
+        }
+        events
+    }
+
+    /// True once every event has been taken.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+/// What the application is doing with its input.
+pub enum Replay {
+    /// Recording live input, to be saved to the path on exit.
+    Recording(ReplayRecorder, PathBuf),
+
+    /// Replaying recorded input, live input is ignored until it finishes.
+    Playing(ReplayPlayer),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every event comes out on the frame it was recorded on. Two keys
+    /// pressed on the same frame keep their order, and nothing is handed
+    /// out early.
+    #[test]
+    fn events_replay_on_their_frames() -> Result<()> {
+        let path = std::env::temp_dir().join("vulkan_starter_replay_test.bin");
+        let recorded = [
+            (0, InputEvent::KeyPressed(VirtualKeyCode::Space)),
+            (3, InputEvent::KeyPressed(VirtualKeyCode::Up)),
+            (3, InputEvent::KeyPressed(VirtualKeyCode::N)),
+            (90, InputEvent::KeyPressed(VirtualKeyCode::Tab)),
+        ];
+        let mut recorder = ReplayRecorder::new(Some("nbody"), Some(1234));
+        for &(frame, event) in &recorded {
+            recorder.record(frame, event);
+        }
+        recorder.save(&path)?;
+
+        let mut player = ReplayPlayer::load(&path)?;
+        std::fs::remove_file(&path).context("unable to remove the replay")?;
+        assert_eq!(player.simulation(), Some("nbody"));
+        assert_eq!(player.seed(), Some(1234));
+        let mut replayed = vec![];
+        for frame in 0..100 {
+            replayed.extend(
+                player
+                    .events_for(frame)
+                    .into_iter()
+                    .map(|event| (frame, event)),
+            );
+        }
+        assert_eq!(replayed, recorded);
+        assert!(player.is_finished());
+        Ok(())
+    }
+
+    #[test]
+    fn other_versions_are_refused() -> Result<()> {
+        let path =
+            std::env::temp_dir().join("vulkan_starter_replay_version_test.bin");
+
+        // the version is the first field of the file
+        let newer = (
+            REPLAY_VERSION + 1,
+            None::<String>,
+            None::<u64>,
+            Vec::<(u64, u64)>::new(),
+        );
+        bincode::serialize_into(
+            File::create(&path).context("unable to write the replay")?,
+            &newer,
+        )?;
+        let loaded = ReplayPlayer::load(&path);
+        std::fs::remove_file(&path).context("unable to remove the replay")?;
+        assert!(loaded.is_err(), "a replay from another version was loaded");
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use super::live_resources::{ResourceKind, ResourceRegistry};
+use super::render_graph::AliasPlan;
+use super::text::{Label, TextRenderer};
+use crate::display::{Display, MemoryTypeStats};
+use crate::texture::{self, TextureCacheStats};
+use anyhow::Result;
+use vulkano::command_buffer::AutoCommandBuffer;
+
+/// The top left corner of the first line, in normalized device coordinates.
+const TOP_LEFT: [f32; 2] = [-0.95, -0.85];
+
+/// The height of a line of text and the distance between lines, in
+/// normalized device coordinates.
+const LINE_HEIGHT: f32 = 0.04;
+const LINE_SPACING: f32 = 0.05;
+
+/// The most resources listed, the smallest are left off.
+const MAX_ROWS: usize = 24;
+
+/// A table of the live pipelines, textures, and buffers in the top left of
+/// the window, largest first, under the count and total size of each kind.
+///
+/// The table is read from the `ResourceRegistry` every frame, so anything
+/// created or dropped shows up on the next frame. Under it is what the
+/// display's allocator holds in each memory type, how fragmented the free
+/// part is, and what sharing offscreen targets saves, then how each texture
+/// cache is doing.
+pub struct ResourceOverlay {
+    text: TextRenderer,
+}
+
+impl ResourceOverlay {
+    pub fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            text: TextRenderer::new(display)?,
+        })
+    }
+
+    /// Build a secondary command buffer which draws the table.
+    pub fn draw(
+        &self,
+        display: &Display,
+        aliasing: &AliasPlan,
+    ) -> Result<AutoCommandBuffer> {
+        let mut lines = table(&ResourceRegistry::snapshot());
+        lines.extend(memory_table(
+            display.allocator.name(),
+            &display.allocator.stats(),
+        ));
+        lines.extend(aliasing_line(aliasing));
+        lines.extend(cache_table(&texture::texture_cache_stats()));
+        let [left, top] = TOP_LEFT;
+        let labels: Vec<Label> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| Label {
+                text: line,
+                position: [left, top + LINE_SPACING * i as f32],
+                height: LINE_HEIGHT,
+            })
+            .collect();
+        self.text.draw(display, &labels)
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.text.rebuild_swapchain_resources(display)
+    }
+}
+
+/// The totals of each kind, then a row for each of the largest resources.
+fn table(registry: &ResourceRegistry) -> Vec<String> {
+    let mut lines = vec!["resources".to_owned()];
+    for &kind in ResourceKind::ALL.iter() {
+        let (count, bytes) = registry.total(kind);
+        lines.push(format!(
+            "{:<10}{:>5}{:>12}",
+            kind_name(kind),
+            count,
+            format_bytes(bytes)
+        ));
+    }
+    lines.push(String::new());
+    let resources = registry.resources();
+    for resource in resources.iter().take(MAX_ROWS) {
+        lines.push(format!(
+            "{:<10}{:>10}  {}  {}",
+            kind_name(resource.kind),
+            format_bytes(resource.bytes),
+            resource.label,
+            resource.detail
+        ));
+    }
+    if resources.len() > MAX_ROWS {
+        lines.push(format!("and {} more", resources.len() - MAX_ROWS));
+    }
+    lines
+}
+
+/// A row for each memory type the allocator holds memory in, with how much
+/// of it is used and how fragmented what's free is.
+fn memory_table(allocator: &str, stats: &[MemoryTypeStats]) -> Vec<String> {
+    let mut lines = vec![String::new(), format!("memory ({})", allocator)];
+    for stats in stats {
+        lines.push(format!(
+            "type {:<5}{:>3} blocks{:>5} allocs{:>12} of {:<12}{:>4.0}% frag",
+            stats.type_index,
+            stats.blocks,
+            stats.allocations,
+            format_bytes(stats.used),
+            format_bytes(stats.allocated),
+            stats.fragmentation() * 100.0
+        ));
+    }
+    lines
+}
+
+/// How many offscreen targets share how many images, and the memory which
+/// saves, when any are planned.
+fn aliasing_line(plan: &AliasPlan) -> Option<String> {
+    let targets: usize =
+        plan.slots().iter().map(|slot| slot.resources.len()).sum();
+    if targets == 0 {
+        return None;
+    }
+    Some(format!(
+        "transient{:>5} targets{:>4} images{:>12} of {:<12} saved",
+        targets,
+        plan.slots().len(),
+        format_bytes(plan.saved_bytes()),
+        format_bytes(plan.unaliased_bytes())
+    ))
+}
+
+/// Two rows for each texture cache: its hits, misses, and evictions, then
+/// what it holds against its budget.
+fn cache_table(caches: &[TextureCacheStats]) -> Vec<String> {
+    let mut lines = vec![];
+    for cache in caches {
+        lines.push(String::new());
+        lines.push(format!(
+            "textures ({}){:>7} hits{:>7} misses{:>7} evicted",
+            cache.label, cache.hits, cache.misses, cache.evictions
+        ));
+        lines.push(format!(
+            "{:>5} resident{:>12} of {:<12}{:>10} retiring{:>4} loading",
+            cache.resident,
+            format_bytes(cache.resident_bytes),
+            format_bytes(cache.budget),
+            format_bytes(cache.retiring_bytes),
+            cache.loading
+        ));
+    }
+    lines
+}
+
+fn kind_name(kind: ResourceKind) -> &'static str {
+    match kind {
+        ResourceKind::Pipeline => "pipeline",
+        ResourceKind::Texture => "texture",
+        ResourceKind::Buffer => "buffer",
+    }
+}
+
+/// A size in the largest unit it has at least one of, or `-` when it isn't
+/// known.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["kb", "mb", "gb"];
+    if bytes == 0 {
+        return "-".to_owned();
+    }
+    if bytes < 1024 {
+        return format!("{} b", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,391 @@
+use super::live_resources::{self, LiveCounts};
+use super::sketch::Sketch;
+use crate::display::Display;
+use anyhow::{bail, Result};
+
+type SceneSetup = Box<dyn Fn(&Display) -> Result<Box<dyn Sketch>>>;
+
+struct Scene {
+    name: String,
+    setup: SceneSetup,
+}
+
+/// The scene which is showing, with what its setup allocated.
+struct ActiveScene {
+    index: usize,
+    allocated: LiveCounts,
+}
+
+/// A scene fading out while the next one fades in.
+pub struct Transition {
+    pub outgoing: Box<dyn Sketch>,
+    name: String,
+    allocated: Option<LiveCounts>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Transition {
+    /// How far the incoming scene has faded in, from 0 to 1.
+    pub fn mix(&self) -> f32 {
+        (self.elapsed / self.duration).clamp(0.0, 1.0)
+    }
+
+    /// Drop the outgoing scene, returning whatever it leaked. Every frame
+    /// which drew it has to have finished first.
+    pub fn tear_down(self) -> LiveCounts {
+        tear_down(&self.name, self.outgoing, self.allocated)
+    }
+}
+
+/// Sketches registered by name, which can be switched between while running.
+///
+/// A switch is only requested when a key is pressed, it's carried out at the
+/// start of the next frame. The outgoing sketch is torn down once every
+/// frame in flight has finished, then the incoming one is set up from its
+/// registered setup function. With a crossfade, the outgoing sketch keeps
+/// drawing while the incoming one fades in and is torn down after.
+///
+/// Whatever a scene's setup allocates is counted with the live resource
+/// counters, and anything still alive after the scene is torn down is
+/// reported as leaked.
+pub struct SceneManager {
+    scenes: Vec<Scene>,
+    active: Option<ActiveScene>,
+    pending: Option<usize>,
+    crossfade: f32,
+    transition: Option<Transition>,
+}
+
+impl SceneManager {
+    pub fn new() -> Self {
+        Self {
+            scenes: vec![],
+            active: None,
+            pending: None,
+            crossfade: 0.0,
+            transition: None,
+        }
+    }
+
+    /// Add a scene, set up by calling `setup` each time it's switched to.
+    /// The first scene registered is shown first.
+    pub fn register<S, F>(&mut self, name: &str, setup: F)
+    where
+        S: Sketch + 'static,
+        F: Fn(&Display) -> Result<S> + 'static,
+    {
+        let setup: SceneSetup = Box::new(move |display| {
+            Ok(Box::new(setup(display)?) as Box<dyn Sketch>)
+        });
+        self.scenes.push(Scene {
+            name: name.to_owned(),
+            setup,
+        });
+        if self.scenes.len() == 1 && self.active.is_none() {
+            self.pending = Some(0);
+        }
+    }
+
+    /// Every scene's name, in the order they were registered.
+    pub fn names(&self) -> Vec<&str> {
+        self.scenes
+            .iter()
+            .map(|scene| scene.name.as_str())
+            .collect()
+    }
+
+    /// The name of the scene which is showing.
+    pub fn current(&self) -> Option<&str> {
+        let active = self.active.as_ref()?;
+        Some(&self.scenes[active.index].name)
+    }
+
+    /// Fade between scenes over `seconds`, or cut between them at 0.
+    pub fn set_crossfade(&mut self, seconds: f32) {
+        self.crossfade = seconds.max(0.0);
+    }
+
+    /// Switch to the scene called `name` at the start of the next frame.
+    pub fn switch_to(&mut self, name: &str) -> Result<()> {
+        match self.scenes.iter().position(|scene| scene.name == name) {
+            Some(index) => self.switch_to_index(index),
+            None => bail!(
+                "there is no scene called {:?}, only {:?}",
+                name,
+                self.names()
+            ),
+        }
+    }
+
+    /// Switch to the scene registered `index`th at the start of the next
+    /// frame.
+    pub fn switch_to_index(&mut self, index: usize) -> Result<()> {
+        if index >= self.scenes.len() {
+            bail!("there are only {} scenes", self.scenes.len());
+        }
+        self.pending = Some(index);
+        Ok(())
+    }
+
+    /// The scene to switch to this frame, if it isn't already showing.
+    pub fn take_pending(&mut self) -> Option<usize> {
+        let index = self.pending.take()?;
+        match &self.active {
+            Some(active) if active.index == index => None,
+            _ => Some(index),
+        }
+    }
+
+    /// The running crossfade.
+    pub fn transition(&self) -> Option<&Transition> {
+        self.transition.as_ref()
+    }
+
+    pub fn transition_mut(&mut self) -> Option<&mut Transition> {
+        self.transition.as_mut()
+    }
+
+    /// Advance the crossfade by `dt` seconds, returning it once it's over so
+    /// the outgoing scene can be torn down.
+    pub fn advance(&mut self, dt: f32) -> Option<Transition> {
+        let transition = self.transition.as_mut()?;
+        transition.elapsed += dt;
+        if transition.mix() < 1.0 {
+            return None;
+        }
+        self.transition.take()
+    }
+
+    /// Let go of the sketch which was showing, ahead of a switch. It's kept
+    /// to fade out when there's a crossfade and torn down right away
+    /// otherwise, along with any scene still fading out. Returns whatever
+    /// the torn down scenes leaked.
+    ///
+    /// Every frame which drew the sketches has to have finished first.
+    pub fn retire(&mut self, outgoing: Option<Box<dyn Sketch>>) -> LiveCounts {
+        let mut leaked = match self.transition.take() {
+            Some(transition) => transition.tear_down(),
+            None => LiveCounts::default(),
+        };
+        let outgoing = match outgoing {
+            Some(outgoing) => outgoing,
+            None => {
+                self.active = None;
+                return leaked;
+            }
+        };
+        // a sketch set without the manager was never measured
+        let (name, allocated) = match self.active.take() {
+            Some(active) => (
+                self.scenes[active.index].name.clone(),
+                Some(active.allocated),
+            ),
+            None => ("sketch".to_owned(), None),
+        };
+        if self.crossfade > 0.0 {
+            self.transition = Some(Transition {
+                outgoing,
+                name,
+                allocated,
+                elapsed: 0.0,
+                duration: self.crossfade,
+            });
+        } else {
+            leaked = leaked + tear_down(&name, outgoing, allocated);
+        }
+        leaked
+    }
+
+    /// Set up the scene registered `index`th, which is showing from now on.
+    pub fn setup(
+        &mut self,
+        index: usize,
+        display: &Display,
+    ) -> Result<Box<dyn Sketch>> {
+        let (sketch, allocated) =
+            live_resources::measure(|| (self.scenes[index].setup)(display));
+        let sketch = sketch?;
+        self.started(index, allocated);
+        Ok(sketch)
+    }
+
+    /// Record that the scene registered `index`th is showing, having
+    /// allocated `allocated` while it was set up.
+    pub fn started(&mut self, index: usize, allocated: LiveCounts) {
+        log::info!(
+            "set up the {} scene with {}",
+            self.scenes[index].name,
+            allocated
+        );
+        self.active = Some(ActiveScene { index, allocated });
+    }
+}
+
+impl Default for SceneManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop a scene's sketch and compare what was freed with what its setup
+/// allocated, returning the difference.
+fn tear_down(
+    name: &str,
+    sketch: Box<dyn Sketch>,
+    allocated: Option<LiveCounts>,
+) -> LiveCounts {
+    let before = LiveCounts::now();
+    drop(sketch);
+    let freed = before.beyond(LiveCounts::now());
+    let leaked = match allocated {
+        Some(allocated) => allocated.beyond(freed),
+        None => LiveCounts::default(),
+    };
+    if leaked.is_empty() {
+        log::info!("tore down the {} scene, freeing {}", name, freed);
+    } else {
+        log::warn!("the {} scene leaked {}", name, leaked);
+    }
+    leaked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::camera::Camera;
+    use crate::application::live_resources::{LiveResource, ResourceKind};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+    use vulkano::command_buffer::AutoCommandBuffer;
+
+    /// The live counts are global, so the tests which compare them take
+    /// turns.
+    static COUNTING: Mutex<()> = Mutex::new(());
+
+    fn counting() -> MutexGuard<'static, ()> {
+        COUNTING.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A sketch holding counted resources like a renderer and a mesh would,
+    /// which can hand some of them to a stash that outlives it.
+    struct Counted {
+        _resources: Vec<LiveResource>,
+    }
+
+    impl Counted {
+        fn new(stash: Option<&Rc<RefCell<Vec<LiveResource>>>>) -> Self {
+            if let Some(stash) = stash {
+                stash
+                    .borrow_mut()
+                    .push(LiveResource::new(ResourceKind::Pipeline));
+            }
+            Self {
+                _resources: vec![
+                    LiveResource::new(ResourceKind::Pipeline),
+                    LiveResource::new(ResourceKind::Buffer),
+                    LiveResource::new(ResourceKind::Buffer),
+                ],
+            }
+        }
+    }
+
+    impl Sketch for Counted {
+        fn draw(
+            &self,
+            _display: &Display,
+            _camera: &Camera,
+        ) -> Result<Vec<AutoCommandBuffer>> {
+            Ok(vec![])
+        }
+    }
+
+    /// Three scenes of counted sketches, built directly rather than by
+    /// their setup functions so no gpu is needed.
+    fn scenes() -> SceneManager {
+        let mut scenes = SceneManager::new();
+        for name in ["triangle", "ring", "clock"].iter() {
+            scenes.register(name, |_: &Display| Ok(Counted::new(None)));
+        }
+        scenes
+    }
+
+    /// Set up a scene as if it had been switched to.
+    fn start(
+        scenes: &mut SceneManager,
+        index: usize,
+        setup: impl FnOnce() -> Counted,
+    ) -> Counted {
+        let (sketch, allocated) = live_resources::measure(setup);
+        scenes.started(index, allocated);
+        sketch
+    }
+
+    #[test]
+    fn switches_only_happen_when_asked_for() -> Result<()> {
+        let mut scenes = scenes();
+        assert_eq!(scenes.take_pending(), Some(0));
+        assert_eq!(scenes.take_pending(), None);
+        assert!(scenes.switch_to("missing").is_err());
+        assert!(scenes.switch_to_index(3).is_err());
+        scenes.switch_to("clock")?;
+        assert_eq!(scenes.take_pending(), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn torn_down_scenes_free_what_they_set_up() -> Result<()> {
+        let _counting = counting();
+        let mut scenes = scenes();
+        scenes.take_pending();
+        let baseline = LiveCounts::now();
+        let triangle = start(&mut scenes, 0, || Counted::new(None));
+        scenes.switch_to_index(0)?;
+        assert_eq!(scenes.take_pending(), None, "switched to itself");
+        assert!(scenes.retire(Some(Box::new(triangle))).is_empty());
+        assert_eq!(LiveCounts::now(), baseline);
+
+        // a scene which stashes a pipeline somewhere is reported
+        let stash = Rc::new(RefCell::new(vec![]));
+        let ring = start(&mut scenes, 1, || Counted::new(Some(&stash)));
+        let leaked = scenes.retire(Some(Box::new(ring)));
+        let expected = LiveCounts {
+            pipelines: 1,
+            textures: 0,
+            buffers: 0,
+        };
+        assert_eq!(leaked, expected);
+        stash.borrow_mut().clear();
+        assert_eq!(LiveCounts::now(), baseline);
+        Ok(())
+    }
+
+    #[test]
+    fn crossfades_keep_the_outgoing_scene_until_theyre_over() {
+        let _counting = counting();
+        let mut scenes = scenes();
+        let baseline = LiveCounts::now();
+        scenes.set_crossfade(0.5);
+        let triangle = start(&mut scenes, 0, || Counted::new(None));
+        scenes.retire(Some(Box::new(triangle)));
+        let ring = start(&mut scenes, 1, || Counted::new(None));
+        assert!(scenes.advance(0.2).is_none(), "the crossfade ended early");
+        let mix = scenes.transition().map(Transition::mix).unwrap();
+        assert!((mix - 0.4).abs() < 1e-6, "the crossfade was at {}", mix);
+        let finished = scenes.advance(0.4).expect("the crossfade never ended");
+        assert!(finished.tear_down().is_empty());
+
+        // switching again mid fade tears the fading scene down right away
+        scenes.retire(Some(Box::new(ring)));
+        let clock = start(&mut scenes, 2, || Counted::new(None));
+        let before = LiveCounts::now();
+        assert!(scenes.retire(Some(Box::new(clock))).is_empty());
+        assert!(!before.beyond(LiveCounts::now()).is_empty());
+
+        let triangle = start(&mut scenes, 0, || Counted::new(None));
+        scenes.set_crossfade(0.0);
+        assert!(scenes.retire(Some(Box::new(triangle))).is_empty());
+        assert_eq!(LiveCounts::now(), baseline);
+    }
+}
@@ -0,0 +1,493 @@
+use super::camera::Camera;
+use super::meshes::{self, DrawList, Material, Mesh, MeshRenderer, MeshVertex};
+use super::transform::{Transform, TransformHierarchy, TransformId};
+use crate::display::Display;
+use anyhow::{anyhow, bail, Context, Result};
+use notify::{
+    watcher, DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use std::cell::{Ref, RefCell};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::VirtualKeyCode;
+
+#[cfg(feature = "lua")]
+mod lua_runtime;
+#[cfg(feature = "scripting")]
+mod rhai_runtime;
+
+#[cfg(feature = "lua")]
+pub use lua_runtime::LuaRuntime;
+#[cfg(feature = "scripting")]
+pub use rhai_runtime::RhaiRuntime;
+
+/// How long a script has to be left alone before it is reloaded, editors
+/// often save a file in more than one step.
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+/// A mesh loaded by a script.
+pub struct ScriptMesh {
+    /// The OBJ file the mesh was loaded from.
+    pub path: PathBuf,
+    pub vertices: Vec<MeshVertex>,
+    pub color: [f32; 4],
+}
+
+/// Everything the running script has asked for.
+#[derive(Default)]
+pub struct ScriptState {
+    pub camera_position: Option<[f32; 3]>,
+    pub camera_target: Option<[f32; 3]>,
+    pub triangle_position: [f32; 2],
+    pub meshes: Vec<ScriptMesh>,
+
+    /// Counts the times a script has been loaded. The state is cleared on
+    /// every load, so meshes from an older generation are gone.
+    pub generation: u64,
+}
+
+/// The state shared by a runtime and the functions it gives to scripts.
+type SharedState = Rc<RefCell<ScriptState>>;
+
+/// A language scripts can be written in.
+///
+/// Each runtime keeps the last script which ran successfully. Once a hook
+/// fails the runtime stops calling hooks until the next load, so a broken
+/// script doesn't flood the log every frame.
+trait ScriptRuntime {
+    /// Compile and run a script's top level with a fresh state. A script
+    /// which doesn't compile leaves the last working one running.
+    fn load(&mut self, path: &Path) -> Result<()>;
+
+    /// Called every frame with the seconds since the application started
+    /// and since the previous frame.
+    fn update(&mut self, time: f32, dt: f32) -> Result<()>;
+
+    /// Called when a key is pressed or released, with the key's name like
+    /// `"Space"` or `"A"`.
+    fn key(&mut self, key: &str, pressed: bool) -> Result<()>;
+
+    /// Called when the mouse moves, with the raw motion since the last call.
+    fn mouse(&mut self, dx: f32, dy: f32) -> Result<()>;
+}
+
+/// Clear everything a previous script asked for.
+fn reset(state: &SharedState) {
+    let mut state = state.borrow_mut();
+    *state = ScriptState {
+        generation: state.generation + 1,
+        ..ScriptState::default()
+    };
+}
+
+/// Load an OBJ file for a script, returning the new mesh's id.
+fn load_mesh(state: &SharedState, path: &str) -> Result<usize> {
+    let vertices = meshes::load_obj(Path::new(path))?;
+    let mut state = state.borrow_mut();
+    state.meshes.push(ScriptMesh {
+        path: PathBuf::from(path),
+        vertices,
+        color: [1.0, 1.0, 1.0, 1.0],
+    });
+    Ok(state.meshes.len() - 1)
+}
+
+/// Set the color of a mesh the script loaded.
+fn set_albedo_color(
+    state: &SharedState,
+    mesh: i64,
+    [r, g, b]: [f32; 3],
+) -> Result<()> {
+    let mut state = state.borrow_mut();
+    let loaded = usize::try_from(mesh)
+        .ok()
+        .and_then(|mesh| state.meshes.get_mut(mesh))
+        .with_context(|| format!("there is no mesh {}", mesh))?;
+    loaded.color = [r, g, b, 1.0];
+    Ok(())
+}
+
+/// A script file, the runtime it runs in, and the watcher which notices
+/// when it changes.
+struct LoadedScript {
+    path: PathBuf,
+    runtime: Box<dyn ScriptRuntime>,
+    _watcher: RecommendedWatcher,
+    changes: Receiver<DebouncedEvent>,
+}
+
+impl LoadedScript {
+    fn is_change(&self, event: &DebouncedEvent) -> bool {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Rename(_, path) => {
+                path.file_name() == self.path.file_name()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Runs a script which controls the scene, in Rhai for `.rhai` files or
+/// Lua for `.lua` files, depending on which features were enabled.
+///
+/// The script's top level runs whenever the file is loaded or saved. See
+/// [`RhaiRuntime`] and [`LuaRuntime`] for what each language can call.
+/// Errors are logged rather than returned once the first load succeeds, so
+/// a typo doesn't close the window and fixing it reloads the script.
+pub struct ScriptEngine {
+    state: SharedState,
+    script: Option<LoadedScript>,
+    last_time: Option<f32>,
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        Self {
+            state: Rc::new(RefCell::new(ScriptState::default())),
+            script: None,
+            last_time: None,
+        }
+    }
+
+    /// Run a script file, then run it again every time it changes.
+    pub fn load_file(&mut self, path: &Path) -> Result<()> {
+        let extension =
+            path.extension().and_then(|extension| extension.to_str());
+        let runtime: Box<dyn ScriptRuntime> = match extension {
+            #[cfg(feature = "scripting")]
+            Some("rhai") => Box::new(RhaiRuntime::new(self.state.clone())),
+            #[cfg(feature = "lua")]
+            Some("lua") => Box::new(LuaRuntime::new(self.state.clone())),
+            _ => bail!(
+                "{:?} isn't a script this build can run, check the features",
+                path
+            ),
+        };
+
+        let (sender, changes) = channel();
+        // notify's errors only describe themselves through Debug
+        let mut watcher = watcher(sender, RELOAD_DELAY).map_err(|error| {
+            anyhow!("unable to create the script watcher: {:?}", error)
+        })?;
+        let directory = path
+            .parent()
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|error| {
+                anyhow!("unable to watch {:?}: {:?}", directory, error)
+            })?;
+        self.script = Some(LoadedScript {
+            path: path.to_owned(),
+            runtime,
+            _watcher: watcher,
+            changes,
+        });
+        self.reload()
+    }
+
+    /// Reload the script if it changed, then call its update hook.
+    pub fn update(&mut self, time: f32) {
+        let changed = match &self.script {
+            Some(script) => {
+                script
+                    .changes
+                    .try_iter()
+                    .filter(|event| script.is_change(event))
+                    .count()
+                    > 0
+            }
+            None => false,
+        };
+        if changed {
+            match self.reload() {
+                Ok(()) => log::info!("reloaded the script"),
+                Err(error) => log::error!("{:?}", error),
+            }
+        }
+
+        let dt = time - self.last_time.unwrap_or(time);
+        self.last_time = Some(time);
+        self.call_hook("update", |runtime| runtime.update(time, dt));
+    }
+
+    /// Tell the script a key was pressed or released.
+    pub fn key(&mut self, key: VirtualKeyCode, pressed: bool) {
+        let name = format!("{:?}", key);
+        self.call_hook("on_key", |runtime| runtime.key(&name, pressed));
+    }
+
+    /// Tell the script the mouse moved.
+    pub fn mouse(&mut self, dx: f32, dy: f32) {
+        self.call_hook("on_mouse", |runtime| runtime.mouse(dx, dy));
+    }
+
+    /// Read an OBJ file again for every mesh the script loaded from it,
+    /// returning the ids of the meshes which changed. The script itself
+    /// isn't run again.
+    pub fn reload_mesh(&mut self, path: &Path) -> Result<Vec<usize>> {
+        // scripts load meshes by relative paths, file watchers report
+        // absolute ones
+        let file = path
+            .canonicalize()
+            .with_context(|| format!("unable to find {:?}", path))?;
+        let mut state = self.state.borrow_mut();
+        let changed: Vec<usize> = state
+            .meshes
+            .iter()
+            .enumerate()
+            .filter(|(_, loaded)| {
+                loaded.path.canonicalize().is_ok_and(|other| other == file)
+            })
+            .map(|(id, _)| id)
+            .collect();
+        if !changed.is_empty() {
+            let vertices = meshes::load_obj(path)?;
+            for &id in &changed {
+                state.meshes[id].vertices = vertices.clone();
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Everything the script has asked for so far.
+    pub fn state(&self) -> Ref<'_, ScriptState> {
+        self.state.borrow()
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        let script =
+            self.script.as_mut().context("no script has been loaded")?;
+        script.runtime.load(&script.path)
+    }
+
+    fn call_hook(
+        &mut self,
+        hook: &str,
+        call: impl FnOnce(&mut dyn ScriptRuntime) -> Result<()>,
+    ) {
+        if let Some(script) = &mut self.script {
+            if let Err(error) = call(script.runtime.as_mut()) {
+                log::error!(
+                    "{:?} failed in {}, waiting for it to change: {:?}",
+                    script.path,
+                    hook,
+                    error
+                );
+            }
+        }
+    }
+}
+
+impl Default for ScriptEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws the meshes loaded by a script at the origin.
+pub struct ScriptedMeshes {
+    renderer: MeshRenderer,
+    transforms: TransformHierarchy,
+    origin: TransformId,
+    meshes: Vec<Mesh>,
+    generation: u64,
+}
+
+impl ScriptedMeshes {
+    pub fn new(display: &Display) -> Result<Self> {
+        let mut transforms = TransformHierarchy::default();
+        let origin = transforms.add(Transform::default(), None)?;
+        transforms.resolve()?;
+        Ok(Self {
+            renderer: MeshRenderer::new(display)?,
+            transforms,
+            origin,
+            meshes: vec![],
+            generation: 0,
+        })
+    }
+
+    /// Upload any meshes the script loaded since the last call, dropping
+    /// the old ones when the script was reloaded.
+    pub fn sync(
+        &mut self,
+        display: &Display,
+        state: &ScriptState,
+    ) -> Result<()> {
+        if state.generation != self.generation {
+            self.meshes.clear();
+            self.generation = state.generation;
+        }
+        for loaded in &state.meshes[self.meshes.len()..] {
+            self.meshes.push(Mesh::from_vertices(
+                &display.device,
+                loaded.vertices.clone(),
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Upload the meshes with these ids again, after their vertices were
+    /// reloaded. The renderer's pipeline doesn't change, only the buffers
+    /// it draws.
+    pub fn replace(
+        &mut self,
+        display: &Display,
+        state: &ScriptState,
+        ids: &[usize],
+    ) -> Result<()> {
+        for &id in ids {
+            if let (Some(mesh), Some(loaded)) =
+                (self.meshes.get_mut(id), state.meshes.get(id))
+            {
+                *mesh = Mesh::from_vertices(
+                    &display.device,
+                    loaded.vertices.clone(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Build a secondary command buffer which draws every mesh in its
+    /// scripted color.
+    pub fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+        state: &ScriptState,
+    ) -> Result<AutoCommandBuffer> {
+        let mut draw_list = DrawList::default();
+        for (mesh, loaded) in self.meshes.iter().zip(&state.meshes) {
+            let material = Material {
+                color: loaded.color,
+            };
+            draw_list.draw_mesh(mesh, material, self.origin);
+        }
+        self.renderer
+            .draw(display, camera, &draw_list, &self.transforms)
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.renderer.rebuild_swapchain_resources(display)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// How long to wait for a saved script to be reloaded.
+    const RELOAD_TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn rhai_demo_and_hooks() -> Result<()> {
+        check_demo("triangle.rhai")?;
+        check_hooks(
+            "rhai",
+            r#"
+                fn update(t) { set_triangle_position(t - 0.25, 0.0); }
+                fn on_key(key, pressed) {
+                    if key == "Space" && pressed {
+                        set_triangle_position(3.0, 0.0);
+                    }
+                }
+                fn on_mouse(dx, dy) { set_triangle_position(dx, dy); }
+            "#,
+            "set_triangle_position(0.0, 1.0);",
+        )
+    }
+
+    #[cfg(feature = "lua")]
+    #[test]
+    fn lua_demo_and_hooks() -> Result<()> {
+        check_demo("triangle.lua")?;
+        check_hooks(
+            "lua",
+            r#"
+                function on_update(dt) triangle.set_position(dt, 0.0) end
+                function on_key(key, pressed)
+                    if key == "Space" and pressed then
+                        triangle.set_position(3.0, 0.0)
+                    end
+                end
+                function on_mouse(dx, dy) triangle.set_position(dx, dy) end
+            "#,
+            "triangle.set_position(0.0, 1.0)",
+        )
+    }
+
+    /// The demo scripts load the diamond, color it, and move the triangle
+    /// and camera on their first update. Nothing is uploaded, the meshes a
+    /// script loads only go to the gpu when there is a display to draw them.
+    fn check_demo(name: &str) -> Result<()> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("assets/scripts")
+            .join(name);
+        let mut engine = ScriptEngine::new();
+        engine.load_file(&path)?;
+        engine.update(0.0);
+        let state = engine.state();
+        assert_eq!(state.meshes.len(), 1, "{} loads one diamond", name);
+        assert_eq!(state.meshes[0].vertices.len(), 6, "of 2 triangles");
+        assert_eq!(state.meshes[0].color, [0.9, 0.6, 0.2, 1.0]);
+        assert_eq!(state.triangle_position, [0.25, 0.0]);
+        assert_eq!(state.camera_target, Some([0.0, 0.0, -1.0]));
+        Ok(())
+    }
+
+    /// Call each hook in `first`, then save `second` over it and wait for
+    /// the reload.
+    fn check_hooks(extension: &str, first: &str, second: &str) -> Result<()> {
+        let directory = std::env::temp_dir()
+            .join(format!("vulkan_starter_script_test_{}", extension));
+        std::fs::create_dir_all(&directory)
+            .context("unable to create the script directory")?;
+        let path = directory.join(format!("script.{}", extension));
+        std::fs::write(&path, first).context("unable to write the script")?;
+
+        let mut engine = ScriptEngine::new();
+        engine.load_file(&path)?;
+        engine.update(0.25);
+        engine.update(0.75);
+        assert_eq!(engine.state().triangle_position, [0.5, 0.0], "update");
+        engine.key(VirtualKeyCode::Space, true);
+        assert_eq!(engine.state().triangle_position, [3.0, 0.0], "on_key");
+        engine.mouse(1.0, 2.0);
+        assert_eq!(engine.state().triangle_position, [1.0, 2.0], "on_mouse");
+
+        let first_generation = engine.state().generation;
+        std::fs::write(&path, second)
+            .context("unable to rewrite the script")?;
+        let start = Instant::now();
+        while engine.state().generation == first_generation {
+            assert!(
+                start.elapsed() < RELOAD_TIMEOUT,
+                "the saved script was never reloaded"
+            );
+            std::thread::sleep(Duration::from_millis(50));
+            engine.update(1.0);
+        }
+        assert_eq!(
+            engine.state().triangle_position,
+            [0.0, 1.0],
+            "the reloaded script didn't run"
+        );
+
+        std::fs::remove_dir_all(&directory)
+            .context("unable to remove the script directory")
+    }
+}
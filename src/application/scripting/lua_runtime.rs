@@ -0,0 +1,156 @@
+use super::{load_mesh, reset, set_albedo_color, ScriptRuntime, SharedState};
+use anyhow::{Context, Result};
+use mlua::{Function, IntoLuaMulti, Lua};
+use std::path::Path;
+
+/// Runs Lua scripts.
+///
+/// A script can define `on_update(dt)`, which is called every frame with
+/// the seconds since the last one, `on_key(key, pressed)`, and
+/// `on_mouse(dx, dy)`. Scripts can call:
+///
+/// - `camera.set_position(x, y, z)` and `camera.look_at(x, y, z)`
+/// - `mesh.load_obj(path)`, which returns a mesh id
+/// - `material.set_albedo_color(mesh, r, g, b)`
+/// - `triangle.set_position(x, y)`
+///
+/// Every load starts from a new Lua state, so globals from the previous
+/// version of a script are gone.
+pub struct LuaRuntime {
+    state: SharedState,
+
+    // missing once the script fails, until the next load
+    lua: Option<Lua>,
+}
+
+impl LuaRuntime {
+    pub fn new(state: SharedState) -> Self {
+        Self { state, lua: None }
+    }
+
+    /// Call a global function the script defines, doing nothing when it
+    /// isn't defined.
+    pub fn call_hook<A>(&mut self, name: &str, args: A) -> Result<()>
+    where
+        A: for<'lua> IntoLuaMulti<'lua>,
+    {
+        let lua = match &self.lua {
+            Some(lua) => lua,
+            None => return Ok(()),
+        };
+        let result =
+            lua.globals()
+                .get::<_, Option<Function>>(name)
+                .and_then(|hook| match hook {
+                    Some(hook) => hook.call::<_, ()>(args),
+                    None => Ok(()),
+                });
+        if let Err(error) = result {
+            self.lua = None;
+            return Err(error).with_context(|| format!("{} failed", name));
+        }
+        Ok(())
+    }
+
+    /// Create a Lua state with the `camera`, `mesh`, `material`, and
+    /// `triangle` tables.
+    fn create_lua(&self) -> mlua::Result<Lua> {
+        let lua = Lua::new();
+        let globals = lua.globals();
+
+        let camera = lua.create_table()?;
+        let shared = self.state.clone();
+        camera.set(
+            "set_position",
+            lua.create_function(move |_, (x, y, z): (f32, f32, f32)| {
+                shared.borrow_mut().camera_position = Some([x, y, z]);
+                Ok(())
+            })?,
+        )?;
+        let shared = self.state.clone();
+        camera.set(
+            "look_at",
+            lua.create_function(move |_, (x, y, z): (f32, f32, f32)| {
+                shared.borrow_mut().camera_target = Some([x, y, z]);
+                Ok(())
+            })?,
+        )?;
+        globals.set("camera", camera)?;
+
+        let mesh = lua.create_table()?;
+        let shared = self.state.clone();
+        mesh.set(
+            "load_obj",
+            lua.create_function(move |_, path: String| {
+                load_mesh(&shared, &path).map_err(|error| {
+                    mlua::Error::RuntimeError(format!("{:#}", error))
+                })
+            })?,
+        )?;
+        globals.set("mesh", mesh)?;
+
+        let material = lua.create_table()?;
+        let shared = self.state.clone();
+        material.set(
+            "set_albedo_color",
+            lua.create_function(
+                move |_, (mesh, r, g, b): (i64, f32, f32, f32)| {
+                    set_albedo_color(&shared, mesh, [r, g, b]).map_err(
+                        |error| {
+                            mlua::Error::RuntimeError(format!("{:#}", error))
+                        },
+                    )
+                },
+            )?,
+        )?;
+        globals.set("material", material)?;
+
+        let triangle = lua.create_table()?;
+        let shared = self.state.clone();
+        triangle.set(
+            "set_position",
+            lua.create_function(move |_, (x, y): (f32, f32)| {
+                shared.borrow_mut().triangle_position = [x, y];
+                Ok(())
+            })?,
+        )?;
+        globals.set("triangle", triangle)?;
+
+        drop(globals);
+        Ok(lua)
+    }
+}
+
+impl ScriptRuntime for LuaRuntime {
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        let lua = self.create_lua().context("unable to create the lua api")?;
+        {
+            let chunk = lua
+                .load(&source)
+                .set_name(path.to_string_lossy())
+                .into_function()
+                .with_context(|| format!("unable to compile {:?}", path))?;
+            reset(&self.state);
+            self.lua = None;
+            chunk
+                .call::<_, ()>(())
+                .with_context(|| format!("{:?} failed", path))?;
+        }
+        self.lua = Some(lua);
+        Ok(())
+    }
+
+    fn update(&mut self, _time: f32, dt: f32) -> Result<()> {
+        self.call_hook("on_update", dt)
+    }
+
+    fn key(&mut self, key: &str, pressed: bool) -> Result<()> {
+        self.call_hook("on_key", (key.to_owned(), pressed))
+    }
+
+    fn mouse(&mut self, dx: f32, dy: f32) -> Result<()> {
+        self.call_hook("on_mouse", (dx, dy))
+    }
+}
@@ -0,0 +1,145 @@
+use super::{load_mesh, reset, set_albedo_color, ScriptRuntime, SharedState};
+use anyhow::{anyhow, Result};
+use rhai::{
+    CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST, FLOAT, INT,
+};
+use std::path::Path;
+
+/// Runs Rhai scripts.
+///
+/// A script can define `fn update(t)`, which is called every frame with
+/// the time in seconds, `fn on_key(key, pressed)`, and `fn on_mouse(dx, dy)`.
+/// Scripts can call:
+///
+/// - `set_camera_position(x, y, z)` and `set_camera_look_at(x, y, z)`
+/// - `load_obj(path)`, which returns a mesh id
+/// - `set_albedo_color(mesh, r, g, b)`
+/// - `set_triangle_position(x, y)`
+///
+/// Numbers passed to these must be floats, like `1.0` rather than `1`.
+pub struct RhaiRuntime {
+    engine: Engine,
+    state: SharedState,
+
+    // missing once the script fails, until the next load
+    ast: Option<AST>,
+}
+
+impl RhaiRuntime {
+    pub fn new(state: SharedState) -> Self {
+        let mut engine = Engine::new();
+
+        let shared = state.clone();
+        engine.register_fn(
+            "set_camera_position",
+            move |x: FLOAT, y: FLOAT, z: FLOAT| {
+                shared.borrow_mut().camera_position =
+                    Some([x as f32, y as f32, z as f32]);
+            },
+        );
+        let shared = state.clone();
+        engine.register_fn(
+            "set_camera_look_at",
+            move |x: FLOAT, y: FLOAT, z: FLOAT| {
+                shared.borrow_mut().camera_target =
+                    Some([x as f32, y as f32, z as f32]);
+            },
+        );
+        let shared = state.clone();
+        engine.register_fn(
+            "set_triangle_position",
+            move |x: FLOAT, y: FLOAT| {
+                shared.borrow_mut().triangle_position = [x as f32, y as f32];
+            },
+        );
+        let shared = state.clone();
+        engine.register_fn(
+            "load_obj",
+            move |path: &str| -> Result<INT, Box<EvalAltResult>> {
+                let mesh = load_mesh(&shared, path)
+                    .map_err(|error| format!("{:#}", error))?;
+                Ok(mesh as INT)
+            },
+        );
+        let shared = state.clone();
+        engine.register_fn(
+            "set_albedo_color",
+            move |mesh: INT,
+                  r: FLOAT,
+                  g: FLOAT,
+                  b: FLOAT|
+                  -> Result<(), Box<EvalAltResult>> {
+                set_albedo_color(&shared, mesh, [r as f32, g as f32, b as f32])
+                    .map_err(|error| format!("{:#}", error).into())
+            },
+        );
+
+        Self {
+            engine,
+            state,
+            ast: None,
+        }
+    }
+
+    /// Call a function the script defines with as many parameters as there
+    /// are arguments, doing nothing when it isn't defined.
+    fn call_hook(&mut self, name: &str, args: Vec<Dynamic>) -> Result<()> {
+        let ast = match &self.ast {
+            Some(ast) => ast,
+            None => return Ok(()),
+        };
+        let defined = ast.iter_functions().any(|function| {
+            function.name == name && function.params.len() == args.len()
+        });
+        if !defined {
+            return Ok(());
+        }
+        // only the function runs, the top level already ran when loading
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false),
+            &mut Scope::new(),
+            ast,
+            name,
+            args,
+        );
+        if let Err(error) = result {
+            self.ast = None;
+            return Err(anyhow!("{}", error));
+        }
+        Ok(())
+    }
+}
+
+impl ScriptRuntime for RhaiRuntime {
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let ast =
+            self.engine.compile_file(path.to_owned()).map_err(|error| {
+                anyhow!("unable to compile {:?}: {}", path, error)
+            })?;
+        reset(&self.state);
+        self.ast = None;
+        self.engine
+            .run_ast(&ast)
+            .map_err(|error| anyhow!("{:?} failed: {}", path, error))?;
+        self.ast = Some(ast);
+        Ok(())
+    }
+
+    fn update(&mut self, time: f32, _dt: f32) -> Result<()> {
+        self.call_hook("update", vec![Dynamic::from_float(time as FLOAT)])
+    }
+
+    fn key(&mut self, key: &str, pressed: bool) -> Result<()> {
+        self.call_hook("on_key", vec![key.into(), pressed.into()])
+    }
+
+    fn mouse(&mut self, dx: f32, dy: f32) -> Result<()> {
+        self.call_hook(
+            "on_mouse",
+            vec![
+                Dynamic::from_float(dx as FLOAT),
+                Dynamic::from_float(dy as FLOAT),
+            ],
+        )
+    }
+}
@@ -0,0 +1,861 @@
+use super::camera::{self, Camera, CoordinateSpace};
+use super::clock::FrameTiming;
+use super::fullscreen::{self, FullscreenVertex};
+use super::live_resources::{LiveResource, ResourceKind};
+use super::sketch::Sketch;
+use super::text::{Label, TextRenderer};
+use crate::application::debug::BarrierValidator;
+use crate::display::Display;
+use crate::shader_compiler::ShaderCompiler;
+use crate::texture::{self, Texture, TextureBuilder, TextureKind};
+use anyhow::{bail, Context, Result};
+use shaderc::ShaderKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState, SubpassContents,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::{
+    Framebuffer, FramebufferAbstract, RenderPassAbstract, Subpass,
+};
+use vulkano::image::{Dimensions, ImageUsage, ImageViewAccess, StorageImage};
+use vulkano::pipeline::GraphicsPipelineAbstract;
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+use winit::event::VirtualKeyCode;
+
+mod keyboard;
+mod pipeline;
+mod project;
+mod seed;
+
+pub use project::{
+    Channel, PassConfig, ResizeMode, ShadertoyProject, BUFFER_NAMES,
+};
+pub use seed::{decode_srgb, SeedFit, SeedImage};
+
+use keyboard::{KeyboardState, KEYBOARD_EXTENT};
+use pipeline::{ShadertoyInputs, BUFFER_FORMAT};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynPipeline = dyn GraphicsPipelineAbstract + Send + Sync;
+type DynDescriptorSet = dyn DescriptorSet + Send + Sync;
+type DynFramebuffer = dyn FramebufferAbstract + Send + Sync;
+type DynImage = Arc<dyn ImageViewAccess + Send + Sync>;
+
+/// Shadertoy shaders can sample up to four input textures.
+pub const CHANNELS: usize = 4;
+
+/// How often the shaders' files are checked for changes, in seconds.
+const POLL_INTERVAL: f32 = 0.25;
+
+/// The height of a line of the compile error, in normalized device
+/// coordinates, and how many lines fit on screen.
+const ERROR_LINE_HEIGHT: f32 = 0.05;
+const ERROR_LINES: usize = 38;
+
+/// Runs Shadertoy style fragment shaders over the whole scene.
+///
+/// Each shader defines `mainImage(out vec4 fragColor, in vec2 fragCoord)`
+/// and can read `iResolution`, `iTime`, `iTimeDelta`, `iFrame`, `iMouse`,
+/// `iChannelResolution`, and `iChannel0` to `iChannel3` just like on
+/// Shadertoy. A `ShadertoyProject` can add buffer passes, A to D, which run
+/// before the image pass and draw into floating point images the size of
+/// the scene. Each buffer has two images which it draws into on alternate
+/// frames, so it can read what it drew the frame before. Buffers start
+/// black, or from a seed image given to `seed_buffer`.
+///
+/// The files are recompiled whenever they change. When one doesn't compile,
+/// the error is drawn over the last shader which did.
+pub struct ShadertoyMode {
+    resources: Resources,
+    compiler: ShaderCompiler,
+    since_poll: f32,
+
+    // the buffer passes in order, then the image pass
+    passes: Vec<Pass>,
+    resize: ResizeMode,
+
+    // the buffers draw into images[current] and read their own previous
+    // frame from the other image
+    current: usize,
+    cleared: bool,
+    resized_from: Option<[Option<BufferTargets>; 4]>,
+    seeds: [Option<SeedImage>; 4],
+
+    keyboard: KeyboardState,
+    text: TextRenderer,
+    inputs: ShadertoyInputs,
+    clicked: bool,
+    _live: [LiveResource; 2],
+}
+
+/// Everything passes share, kept apart from the passes so a pass can be
+/// rebuilt while the rest is borrowed.
+struct Resources {
+    device: Arc<Device>,
+    render_pass: Arc<DynRenderPass>,
+    buffer_render_pass: Arc<DynRenderPass>,
+    extent: [u32; 2],
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+    repeat_sampler: Arc<Sampler>,
+    clamp_sampler: Arc<Sampler>,
+    empty: Texture,
+    keyboard: Arc<StorageImage<Format>>,
+    buffers: [Option<BufferTargets>; 4],
+}
+
+/// The two images a buffer pass alternates between.
+struct BufferTargets {
+    images: [Arc<StorageImage<Format>>; 2],
+    framebuffers: [Arc<DynFramebuffer>; 2],
+}
+
+/// One of the shaders and what it reads.
+struct Pass {
+    name: &'static str,
+    config: PassConfig,
+
+    // the buffer the pass draws into, none for the image pass
+    buffer: Option<usize>,
+    images: Vec<Option<Texture>>,
+    modified: Option<SystemTime>,
+
+    // the spir-v of the last shader which compiled, kept so the pipeline
+    // can be rebuilt when the scene is resized
+    words: Option<Vec<u32>>,
+    pipeline: Option<Arc<DynPipeline>>,
+
+    // one set for each value of `current`
+    descriptor_sets: Vec<Arc<DynDescriptorSet>>,
+    channel_resolution: [[f32; 4]; CHANNELS],
+    error: Option<String>,
+}
+
+impl ShadertoyMode {
+    /// Load the shader at `path` with up to four png images as its input
+    /// channels, in order. Channels without an image are black.
+    pub fn new(
+        display: &Display,
+        path: &Path,
+        channel_images: &[PathBuf],
+    ) -> Result<Self> {
+        Self::with_project(
+            display,
+            ShadertoyProject::single(path, channel_images)?,
+        )
+    }
+
+    /// Load every pass of a project.
+    pub fn with_project(
+        display: &Display,
+        project: ShadertoyProject,
+    ) -> Result<Self> {
+        let buffer_render_pass: Arc<DynRenderPass> = Arc::new(
+            vulkano::single_pass_renderpass!(
+                display.device.clone(),
+                attachments: {
+                    color: {
+                        load: DontCare,
+                        store: Store,
+                        format: BUFFER_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
+                }
+            )
+            .context("unable to create the shadertoy buffer render pass")?,
+        );
+        let extent = display.scene_extent();
+        let mut buffers: [Option<BufferTargets>; 4] = Default::default();
+        for (buffer, config) in project.buffers.iter().enumerate() {
+            if config.is_some() {
+                buffers[buffer] = Some(BufferTargets::new(
+                    display,
+                    &buffer_render_pass,
+                    extent,
+                )?);
+            }
+        }
+        let keyboard = StorageImage::with_usage(
+            display.device.clone(),
+            Dimensions::Dim2d {
+                width: KEYBOARD_EXTENT[0],
+                height: KEYBOARD_EXTENT[1],
+            },
+            Format::R8G8B8A8Unorm,
+            ImageUsage {
+                sampled: true,
+                transfer_destination: true,
+                ..ImageUsage::none()
+            },
+            Some(display.graphics_queue.family()),
+        )
+        .context("unable to create the keyboard image")?;
+        let resources = Resources {
+            device: display.device.clone(),
+            render_pass: display.render_pass.clone(),
+            buffer_render_pass,
+            extent,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+            repeat_sampler: create_sampler(
+                &display.device,
+                SamplerAddressMode::Repeat,
+            )?,
+            clamp_sampler: create_sampler(
+                &display.device,
+                SamplerAddressMode::ClampToEdge,
+            )?,
+            empty: TextureBuilder::from_rgba(
+                "empty channel",
+                1,
+                1,
+                vec![0; 4],
+            )?
+            .kind(TextureKind::Data)
+            .build(&display.device, &display.graphics_queue)?,
+            keyboard,
+            buffers,
+        };
+
+        let ShadertoyProject {
+            buffers,
+            image,
+            resize,
+        } = project;
+        let mut passes = vec![];
+        for (buffer, config) in buffers.iter().enumerate() {
+            if let Some(config) = config {
+                passes.push(Pass::new(
+                    display,
+                    BUFFER_NAMES[buffer],
+                    config.clone(),
+                    Some(buffer),
+                )?);
+            }
+        }
+        passes.push(Pass::new(display, "image", image, None)?);
+
+        let include_directories = passes
+            .iter()
+            .filter_map(|pass| pass.config.shader.parent())
+            .map(Path::to_path_buf)
+            .collect();
+        let mut mode = Self {
+            resources,
+            compiler: ShaderCompiler::new(include_directories)?,
+            since_poll: 0.0,
+            passes,
+            resize,
+            current: 0,
+            cleared: false,
+            resized_from: None,
+            seeds: Default::default(),
+            keyboard: KeyboardState::new(),
+            text: TextRenderer::new(display)?,
+            inputs: ShadertoyInputs::default(),
+            clicked: false,
+            _live: [
+                LiveResource::described(
+                    ResourceKind::Pipeline,
+                    "shadertoy",
+                    "",
+                    0,
+                ),
+                LiveResource::described(
+                    ResourceKind::Buffer,
+                    "shadertoy quad",
+                    "host visible",
+                    0,
+                ),
+            ],
+        };
+        for pass in &mut mode.passes {
+            pass.reload(&mut mode.compiler, &mode.resources);
+        }
+        Ok(mode)
+    }
+
+    /// Start buffer `buffer`, 0 for buffer A, from an image instead of
+    /// black. The seed is drawn in before the buffer passes next run, and
+    /// again whenever the buffer starts over, like after a resize with
+    /// `resize = clear`. Every other buffer starts over with it.
+    pub fn seed_buffer(
+        &mut self,
+        buffer: usize,
+        seed: SeedImage,
+    ) -> Result<()> {
+        match self.resources.buffers.get(buffer) {
+            Some(Some(_)) => (),
+            _ => bail!(
+                "{} isn't declared, so it can't be seeded",
+                BUFFER_NAMES.get(buffer).unwrap_or(&"the buffer")
+            ),
+        }
+        self.seeds[buffer] = Some(seed);
+        self.cleared = false;
+        Ok(())
+    }
+
+    /// The first compile error keeping a pass from running, if there is
+    /// one.
+    pub fn error(&self) -> Option<&str> {
+        self.passes.iter().find_map(|pass| pass.error.as_deref())
+    }
+
+    fn draw_image(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the shader")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        let image = self.passes.last().context("there is no image pass")?;
+        if let Some(pipeline) = &image.pipeline {
+            builder
+                .draw(
+                    pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![self.resources.vertices.clone()],
+                    image.descriptor_sets[self.current].clone(),
+                    image.inputs(self.inputs),
+                )
+                .context("unable to draw the shader")?;
+        }
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    fn draw_error(
+        &self,
+        display: &Display,
+        error: &str,
+    ) -> Result<AutoCommandBuffer> {
+        let lines: Vec<String> = error
+            .lines()
+            .flat_map(|line| textwrap::wrap(line, 60))
+            .take(ERROR_LINES)
+            .map(|line| line.into_owned())
+            .collect();
+        let labels: Vec<Label> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| Label {
+                text: line,
+                position: [-0.95, -0.95 + ERROR_LINE_HEIGHT * i as f32],
+                height: ERROR_LINE_HEIGHT * 0.8,
+            })
+            .collect();
+        self.text.draw(display, &labels)
+    }
+
+    /// Clear or seed the buffers, or stretch what they held before the
+    /// scene was resized over them.
+    fn reset_buffers(&mut self, builder: &mut BarrierValidator) -> Result<()> {
+        let resized_from = self.resized_from.take();
+        for (buffer, targets) in self.resources.buffers.iter().enumerate() {
+            let targets = match targets {
+                Some(targets) => targets,
+                None => continue,
+            };
+            let previous = resized_from
+                .as_ref()
+                .and_then(|resized_from| resized_from[buffer].as_ref());
+            // both images start from the seed, one is read the first frame
+            let seed = match (previous, &self.seeds[buffer]) {
+                (None, Some(seed)) => Some(
+                    CpuAccessibleBuffer::from_iter(
+                        self.resources.device.clone(),
+                        BufferUsage::transfer_source(),
+                        false,
+                        seed.half_texels(self.resources.extent).into_iter(),
+                    )
+                    .context("unable to upload a seed image")?,
+                ),
+                _ => None,
+            };
+            for (i, image) in targets.images.iter().enumerate() {
+                match (previous, &seed) {
+                    (Some(previous), _) => {
+                        let source = &previous.images[i];
+                        let [width, height] = extent_of(source);
+                        let [new_width, new_height] = extent_of(image);
+                        builder
+                            .blit_image(
+                                source.clone(),
+                                [0, 0, 0],
+                                [width as i32, height as i32, 1],
+                                0,
+                                0,
+                                image.clone(),
+                                [0, 0, 0],
+                                [new_width as i32, new_height as i32, 1],
+                                0,
+                                0,
+                                1,
+                                Filter::Linear,
+                            )
+                            .context("unable to preserve a buffer")?;
+                    }
+                    (None, Some(seed)) => {
+                        builder
+                            .copy_buffer_to_image(seed.clone(), image.clone())
+                            .context("unable to seed a buffer")?;
+                    }
+                    (None, None) => {
+                        builder
+                            .clear_color_image(
+                                image.clone(),
+                                ClearValue::Float([0.0, 0.0, 0.0, 0.0]),
+                            )
+                            .context("unable to clear a buffer")?;
+                    }
+                }
+            }
+        }
+        self.cleared = true;
+        Ok(())
+    }
+}
+
+impl Sketch for ShadertoyMode {
+    fn update(&mut self, time: f32, dt: f32) -> Result<()> {
+        self.since_poll += dt;
+        if self.since_poll >= POLL_INTERVAL {
+            self.since_poll = 0.0;
+            for pass in &mut self.passes {
+                if modified_time(&pass.config.shader) != pass.modified {
+                    pass.reload(&mut self.compiler, &self.resources);
+                }
+            }
+        }
+
+        let [width, height] = self.resources.extent;
+        self.inputs.resolution = [width as f32, height as f32, 1.0];
+        self.inputs.aspect_fix =
+            camera::aspect_fix(self.resources.extent, CoordinateSpace::Unit);
+        self.inputs.time = time;
+        self.inputs.time_delta = dt;
+        Ok(())
+    }
+
+    fn timing(&mut self, timing: FrameTiming) -> Result<()> {
+        self.inputs.frame = timing.shader_frame();
+        Ok(())
+    }
+
+    fn render_offscreen(
+        &mut self,
+        display: &Display,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+            )
+            .context("unable to create the shadertoy command buffer builder")?,
+            &display.graphics_queue,
+        );
+
+        let pixels = CpuAccessibleBuffer::from_iter(
+            display.device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            self.keyboard.take_pixels().into_iter(),
+        )
+        .context("unable to upload the keyboard state")?;
+        builder
+            .copy_buffer_to_image(pixels, self.resources.keyboard.clone())
+            .context("unable to copy the keyboard state")?;
+        if !self.cleared {
+            self.reset_buffers(&mut builder)?;
+        }
+
+        self.current = 1 - self.current;
+        for pass in &self.passes {
+            let (buffer, pipeline) = match (pass.buffer, &pass.pipeline) {
+                (Some(buffer), Some(pipeline)) => (buffer, pipeline),
+                _ => continue,
+            };
+            let targets = self.resources.buffers[buffer]
+                .as_ref()
+                .context("a buffer pass has no images")?;
+            builder
+                .begin_render_pass(
+                    targets.framebuffers[self.current].clone(),
+                    SubpassContents::Inline,
+                    vec![ClearValue::None],
+                )
+                .context("unable to begin a buffer pass")?;
+            builder
+                .draw(
+                    pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![self.resources.vertices.clone()],
+                    pass.descriptor_sets[self.current].clone(),
+                    pass.inputs(self.inputs),
+                )
+                .with_context(|| format!("unable to draw {}", pass.name))?;
+            builder
+                .end_render_pass()
+                .context("unable to end a buffer pass")?;
+        }
+
+        Ok(vec![builder
+            .build()
+            .context("unable to build the shadertoy command buffer")?])
+    }
+
+    fn draw(
+        &self,
+        display: &Display,
+        _camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        let mut commands = vec![self.draw_image(display)?];
+        if let Some(error) = self.error() {
+            commands.push(self.draw_error(display, error)?);
+        }
+        Ok(commands)
+    }
+
+    fn rebuild_swapchain_resources(&mut self, display: &Display) -> Result<()> {
+        self.resources.render_pass = display.render_pass.clone();
+        let extent = display.scene_extent();
+        if extent != self.resources.extent {
+            self.resources.extent = extent;
+            let mut buffers: [Option<BufferTargets>; 4] = Default::default();
+            for (buffer, targets) in buffers.iter_mut().enumerate() {
+                if self.resources.buffers[buffer].is_some() {
+                    *targets = Some(BufferTargets::new(
+                        display,
+                        &self.resources.buffer_render_pass,
+                        extent,
+                    )?);
+                }
+            }
+            let previous =
+                std::mem::replace(&mut self.resources.buffers, buffers);
+            if self.resize == ResizeMode::Preserve && self.cleared {
+                self.resized_from = Some(previous);
+            }
+            self.cleared = false;
+        }
+        for pass in &mut self.passes {
+            pass.rebuild(&self.resources)?;
+        }
+        self.text.rebuild_swapchain_resources(display)
+    }
+
+    fn handle_key(&mut self, key: VirtualKeyCode) -> Result<()> {
+        self.keyboard.press(key);
+        Ok(())
+    }
+
+    fn handle_key_release(&mut self, key: VirtualKeyCode) -> Result<()> {
+        self.keyboard.release(key);
+        Ok(())
+    }
+
+    /// Shadertoy's `iMouse`: the cursor's position while the button is held
+    /// in xy, and where it was pressed in zw, negated once released.
+    fn cursor(
+        &mut self,
+        cursor: Option<[f32; 2]>,
+        left_pressed: bool,
+    ) -> Result<()> {
+        let height = self.resources.extent[1] as f32;
+        match (cursor, left_pressed) {
+            (Some([x, y]), true) => {
+                let [x, y] = [x, height - y];
+                if !self.clicked {
+                    self.inputs.mouse[2] = x;
+                    self.inputs.mouse[3] = y;
+                }
+                self.inputs.mouse[0] = x;
+                self.inputs.mouse[1] = y;
+            }
+            _ => {
+                self.inputs.mouse[2] = -self.inputs.mouse[2].abs();
+                self.inputs.mouse[3] = -self.inputs.mouse[3].abs();
+            }
+        }
+        self.clicked = left_pressed && cursor.is_some();
+        Ok(())
+    }
+}
+
+impl Pass {
+    fn new(
+        display: &Display,
+        name: &'static str,
+        config: PassConfig,
+        buffer: Option<usize>,
+    ) -> Result<Self> {
+        let images = config
+            .channels
+            .iter()
+            .map(|channel| match channel {
+                Channel::Image(path) => {
+                    load_channel_image(display, path).map(Some)
+                }
+                _ => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("unable to load the {} channels", name))?;
+        Ok(Self {
+            name,
+            config,
+            buffer,
+            images,
+            modified: None,
+            words: None,
+            pipeline: None,
+            descriptor_sets: vec![],
+            channel_resolution: Default::default(),
+            error: None,
+        })
+    }
+
+    /// The inputs with this pass's channel resolutions.
+    fn inputs(&self, inputs: ShadertoyInputs) -> ShadertoyInputs {
+        ShadertoyInputs {
+            channel_resolution: self.channel_resolution,
+            ..inputs
+        }
+    }
+
+    /// Recompile the shader, keeping the previous pipeline and recording
+    /// the error if anything goes wrong.
+    fn reload(&mut self, compiler: &mut ShaderCompiler, resources: &Resources) {
+        let path = self.config.shader.clone();
+        self.modified = modified_time(&path);
+        let result = std::fs::read_to_string(&path)
+            .with_context(|| format!("unable to read {:?}", path))
+            .and_then(|source| {
+                let wrapped =
+                    pipeline::wrap_source(&source, self.buffer.is_some());
+                compiler.compile_source(&wrapped, ShaderKind::Fragment, &path)
+            })
+            .and_then(|words| {
+                let previous = self.words.replace(words);
+                self.rebuild(resources).map_err(|error| {
+                    self.words = previous;
+                    error
+                })
+            });
+        match result {
+            Ok(()) => {
+                if self.error.take().is_some() {
+                    log::info!("{:?} compiles again", path);
+                }
+            }
+            Err(error) => {
+                log::warn!("{:?} didn't compile: {:?}", path, error);
+                self.error = Some(format!("{}: {:?}", self.name, error));
+            }
+        }
+    }
+
+    /// Rebuild the pipeline and descriptor sets for the current extent and
+    /// buffer images.
+    fn rebuild(&mut self, resources: &Resources) -> Result<()> {
+        let words = match &self.words {
+            Some(words) => words,
+            None => return Ok(()),
+        };
+        let render_pass = match self.buffer {
+            Some(_) => &resources.buffer_render_pass,
+            None => &resources.render_pass,
+        };
+        let pipeline = pipeline::create_graphics_pipeline(
+            &resources.device,
+            words,
+            resources.extent,
+            render_pass,
+        )?;
+        let layout = pipeline
+            .descriptor_set_layout(0)
+            .context("shadertoy pipeline has no descriptor set layout")?;
+
+        let mut descriptor_sets = vec![];
+        for current in 0..2 {
+            let mut sources = vec![];
+            for (channel, config) in self.config.channels.iter().enumerate() {
+                sources.push(
+                    self.channel_source(resources, channel, config, current)?,
+                );
+            }
+            for (channel, (image, _)) in sources.iter().enumerate() {
+                let [width, height] = extent_of(image);
+                self.channel_resolution[channel] =
+                    [width as f32, height as f32, 1.0, 0.0];
+            }
+            let set: Arc<DynDescriptorSet> = Arc::new(
+                PersistentDescriptorSet::start(layout.clone())
+                    .add_sampled_image(
+                        sources[0].0.clone(),
+                        sources[0].1.clone(),
+                    )?
+                    .add_sampled_image(
+                        sources[1].0.clone(),
+                        sources[1].1.clone(),
+                    )?
+                    .add_sampled_image(
+                        sources[2].0.clone(),
+                        sources[2].1.clone(),
+                    )?
+                    .add_sampled_image(
+                        sources[3].0.clone(),
+                        sources[3].1.clone(),
+                    )?
+                    .build()
+                    .context("unable to create the channel descriptors")?,
+            );
+            descriptor_sets.push(set);
+        }
+        self.pipeline = Some(pipeline);
+        self.descriptor_sets = descriptor_sets;
+        Ok(())
+    }
+
+    /// The image a channel samples, and how, while the buffers draw into
+    /// `images[current]`.
+    fn channel_source(
+        &self,
+        resources: &Resources,
+        channel: usize,
+        config: &Channel,
+        current: usize,
+    ) -> Result<(DynImage, Arc<Sampler>)> {
+        let source: (DynImage, Arc<Sampler>) = match config {
+            Channel::Empty => (
+                resources.empty.image.clone(),
+                resources.repeat_sampler.clone(),
+            ),
+            Channel::Image(_) => (
+                self.images[channel]
+                    .as_ref()
+                    .context("a channel's image wasn't loaded")?
+                    .image
+                    .clone(),
+                resources.repeat_sampler.clone(),
+            ),
+            Channel::Keyboard => {
+                (resources.keyboard.clone(), resources.clamp_sampler.clone())
+            }
+            Channel::Buffer(buffer) => {
+                let targets = resources.buffers[*buffer]
+                    .as_ref()
+                    .context("a channel reads a buffer without images")?;
+                // buffers run in order, the image pass runs after them all
+                let drawn_this_frame =
+                    self.buffer.map_or(true, |own| *buffer < own);
+                let image = if drawn_this_frame {
+                    targets.images[current].clone()
+                } else {
+                    targets.images[1 - current].clone()
+                };
+                (image, resources.clamp_sampler.clone())
+            }
+        };
+        Ok(source)
+    }
+}
+
+impl BufferTargets {
+    fn new(
+        display: &Display,
+        render_pass: &Arc<DynRenderPass>,
+        extent: [u32; 2],
+    ) -> Result<Self> {
+        let [width, height] = extent;
+        let create_target = || -> Result<_> {
+            let image = StorageImage::with_usage(
+                display.device.clone(),
+                Dimensions::Dim2d { width, height },
+                BUFFER_FORMAT,
+                ImageUsage {
+                    color_attachment: true,
+                    sampled: true,
+                    transfer_source: true,
+                    transfer_destination: true,
+                    ..ImageUsage::none()
+                },
+                Some(display.graphics_queue.family()),
+            )
+            .context("unable to create a shadertoy buffer")?;
+            let framebuffer: Arc<DynFramebuffer> = Arc::new(
+                Framebuffer::start(render_pass.clone())
+                    .add(image.clone())?
+                    .build()
+                    .context("unable to create a shadertoy framebuffer")?,
+            );
+            Ok((image, framebuffer))
+        };
+        let (first, first_framebuffer) = create_target()?;
+        let (second, second_framebuffer) = create_target()?;
+        Ok(Self {
+            images: [first, second],
+            framebuffers: [first_framebuffer, second_framebuffer],
+        })
+    }
+}
+
+/// Load a png with its rows flipped, so the bottom of the image is at the
+/// bottom of the texture coordinates like it is on Shadertoy.
+fn load_channel_image(display: &Display, path: &Path) -> Result<Texture> {
+    let (width, height, pixels) = texture::decode_png(path)
+        .with_context(|| format!("unable to decode {:?}", path))?;
+    let flipped = pixels
+        .chunks_exact(width as usize * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect();
+    TextureBuilder::from_rgba(&path.to_string_lossy(), width, height, flipped)?
+        .kind(TextureKind::Data)
+        .build(&display.device, &display.graphics_queue)
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+fn extent_of(image: &Arc<impl ImageViewAccess + ?Sized>) -> [u32; 2] {
+    let dimensions = image.dimensions();
+    [dimensions.width(), dimensions.height()]
+}
+
+/// Linear filtering, like Shadertoy's default channel sampler.
+fn create_sampler(
+    device: &Arc<Device>,
+    address_mode: SamplerAddressMode,
+) -> Result<Arc<Sampler>> {
+    Sampler::new(
+        device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        address_mode,
+        address_mode,
+        address_mode,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the channel sampler")
+}
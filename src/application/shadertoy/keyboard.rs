@@ -0,0 +1,83 @@
+use winit::event::VirtualKeyCode;
+
+/// The keyboard texture is 256 keys wide, one row for each state.
+pub const KEYBOARD_EXTENT: [u32; 2] = [256, 3];
+
+/// Which keys are held, were pressed this frame, and are toggled on, laid
+/// out like Shadertoy's keyboard texture.
+pub struct KeyboardState {
+    held: [bool; 256],
+    pressed: [bool; 256],
+    toggled: [bool; 256],
+}
+
+impl KeyboardState {
+    pub fn new() -> Self {
+        Self {
+            held: [false; 256],
+            pressed: [false; 256],
+            toggled: [false; 256],
+        }
+    }
+
+    pub fn press(&mut self, key: VirtualKeyCode) {
+        if let Some(code) = key_code(key) {
+            // key repeats arrive as more presses while the key is held
+            if !self.held[code] {
+                self.pressed[code] = true;
+                self.toggled[code] = !self.toggled[code];
+            }
+            self.held[code] = true;
+        }
+    }
+
+    pub fn release(&mut self, key: VirtualKeyCode) {
+        if let Some(code) = key_code(key) {
+            self.held[code] = false;
+        }
+    }
+
+    /// Rgba pixels for the keyboard texture, then forget this frame's
+    /// presses.
+    pub fn take_pixels(&mut self) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(256 * 3 * 4);
+        for row in [&self.held, &self.pressed, &self.toggled].iter() {
+            for &set in row.iter() {
+                let value = if set { 255 } else { 0 };
+                pixels.extend_from_slice(&[value, value, value, 255]);
+            }
+        }
+        self.pressed = [false; 256];
+        pixels
+    }
+}
+
+/// The javascript key code Shadertoy indexes the texture with.
+fn key_code(key: VirtualKeyCode) -> Option<usize> {
+    use VirtualKeyCode::*;
+    let code = match key {
+        Back => 8,
+        Tab => 9,
+        Return => 13,
+        LShift | RShift => 16,
+        LControl | RControl => 17,
+        LAlt | RAlt => 18,
+        Escape => 27,
+        Space => 32,
+        Left => 37,
+        Up => 38,
+        Right => 39,
+        Down => 40,
+        Key0 => 48,
+        Key1 | Key2 | Key3 | Key4 | Key5 | Key6 | Key7 | Key8 | Key9 => {
+            49 + key as usize - Key1 as usize
+        }
+        A | B | C | D | E | F | G | H | I | J | K | L | M | N | O | P | Q
+        | R | S | T | U | V | W | X | Y | Z => 65 + key as usize - A as usize,
+        F1 | F2 | F3 | F4 | F5 | F6 | F7 | F8 | F9 | F10 | F11 | F12 => {
+            112 + key as usize - F1 as usize
+        }
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,252 @@
+use super::CHANNELS;
+#[cfg(all(feature = "shader-reflect", debug_assertions))]
+use crate::application::debug;
+use crate::application::fullscreen::{self, FullscreenVertex};
+use crate::shader_compiler;
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::ffi::CStr;
+use std::sync::Arc;
+use vulkano::descriptor::descriptor::{
+    DescriptorDesc, DescriptorDescTy, DescriptorImageDesc,
+    DescriptorImageDescArray, DescriptorImageDescDimensions, ShaderStages,
+};
+use vulkano::descriptor::pipeline_layout::{
+    PipelineLayoutDesc, PipelineLayoutDescPcRange,
+};
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::shader::{
+    GraphicsShaderType, ShaderInterfaceDef, ShaderInterfaceDefEntry,
+};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+type DynPipeline = dyn GraphicsPipelineAbstract + Send + Sync;
+
+/// Declares the inputs a Shadertoy shader expects before the shader's own
+/// source. `#line 1` keeps the compiler's line numbers matching the file.
+const HEADER: &str = r#"#version 450
+
+layout(push_constant) uniform ShadertoyInputs {
+    vec3 iResolution;
+    float iTime;
+    vec4 iMouse;
+    int iFrame;
+    float iTimeDelta;
+    vec2 iAspectFix;
+    vec3 iChannelResolution[4];
+};
+
+layout(set = 0, binding = 0) uniform sampler2D iChannel0;
+layout(set = 0, binding = 1) uniform sampler2D iChannel1;
+layout(set = 0, binding = 2) uniform sampler2D iChannel2;
+layout(set = 0, binding = 3) uniform sampler2D iChannel3;
+
+layout(location = 0) in vec2 vertUv;
+
+layout(location = 0) out vec4 outColor;
+
+#line 1
+"#;
+
+/// Calls the shader's `mainImage` with Shadertoy's bottom left origin. The
+/// scene is drawn to an sRGB target while Shadertoy writes its colors out
+/// as-is, so they're decoded first to look the same.
+const IMAGE_FOOTER: &str = r#"
+void main() {
+    vec2 fragCoord = vec2(gl_FragCoord.x, iResolution.y - gl_FragCoord.y);
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    mainImage(color, fragCoord);
+    outColor = vec4(pow(max(color.rgb, vec3(0.0)), vec3(2.2)), 1.0);
+}
+"#;
+
+/// Buffers are linear floating point images which keep whatever the shader
+/// writes, alpha included. Their rows are stored bottom up, like on
+/// Shadertoy, so `fragCoord / iResolution.xy` samples the pixel which was
+/// drawn at `fragCoord`.
+const BUFFER_FOOTER: &str = r#"
+void main() {
+    vec2 fragCoord = gl_FragCoord.xy;
+    vec4 color = vec4(0.0);
+    mainImage(color, fragCoord);
+    outColor = color;
+}
+"#;
+
+/// The format of the buffer passes' images. Shadertoy's buffers are 32 bit
+/// floats, 16 bits are enough for most feedback effects and every device
+/// can render to and blend them.
+pub const BUFFER_FORMAT: Format = Format::R16G16B16A16Sfloat;
+
+/// The push constants, laid out like the `ShadertoyInputs` block.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ShadertoyInputs {
+    pub resolution: [f32; 3],
+    pub time: f32,
+    pub mouse: [f32; 4],
+    pub frame: i32,
+    pub time_delta: f32,
+
+    /// Not part of Shadertoy. Dividing a position which spans -1 to 1
+    /// across the window by this makes a unit the same size across and up,
+    /// with the shorter side spanning -1 to 1.
+    pub aspect_fix: [f32; 2],
+    pub channel_resolution: [[f32; 4]; CHANNELS],
+}
+
+/// Wrap a shader's source so it compiles as a complete fragment shader.
+pub fn wrap_source(source: &str, is_buffer: bool) -> String {
+    let footer = if is_buffer {
+        BUFFER_FOOTER
+    } else {
+        IMAGE_FOOTER
+    };
+    [HEADER, source, footer].concat()
+}
+
+/// Build the pipeline for a compiled shader. The shader is only known at
+/// runtime, so its interface and layout are described by hand rather than
+/// by `vulkano_shaders`.
+pub fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    words: &[u32],
+    extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<DynPipeline>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let module = shader_compiler::create_module(device, words)?;
+    let main = CStr::from_bytes_with_nul(b"main\0").unwrap();
+    let frag = unsafe {
+        // unsafe because vulkano cannot check the description against the
+        // spir-v, HEADER declares exactly these inputs and outputs
+        module.graphics_entry_point::<(), _, _, _>(
+            main,
+            FragmentInput,
+            FragmentOutput,
+            ShadertoyLayout,
+            GraphicsShaderType::Fragment,
+        )
+    };
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag, ())
+        .viewports(vec![fullscreen::viewport(extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the shadertoy pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the shadertoy graphics pipeline")?;
+    let pipeline: Arc<DynPipeline> = Arc::new(pipeline);
+
+    // every pass declares all four channels, but most read fewer
+    #[cfg(all(feature = "shader-reflect", debug_assertions))]
+    match spirv_reflect::ShaderModule::load_u32_data(words) {
+        Ok(reflected) => debug::check_unused_bindings(&reflected, &pipeline),
+        Err(error) => log::warn!("unable to reflect the shader: {}", error),
+    }
+    Ok(pipeline)
+}
+
+/// The `vertUv` the fullscreen vertex shader passes along.
+#[derive(Debug, Copy, Clone)]
+struct FragmentInput;
+
+unsafe impl ShaderInterfaceDef for FragmentInput {
+    type Iter = std::iter::Once<ShaderInterfaceDefEntry>;
+
+    fn elements(&self) -> Self::Iter {
+        std::iter::once(ShaderInterfaceDefEntry {
+            location: 0..1,
+            format: Format::R32G32Sfloat,
+            name: Some(Cow::Borrowed("vertUv")),
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct FragmentOutput;
+
+unsafe impl ShaderInterfaceDef for FragmentOutput {
+    type Iter = std::iter::Once<ShaderInterfaceDefEntry>;
+
+    fn elements(&self) -> Self::Iter {
+        std::iter::once(ShaderInterfaceDefEntry {
+            location: 0..1,
+            format: Format::R32G32B32A32Sfloat,
+            name: Some(Cow::Borrowed("outColor")),
+        })
+    }
+}
+
+/// Four sampled channels in set 0 and the `ShadertoyInputs` push constants.
+#[derive(Debug, Copy, Clone)]
+struct ShadertoyLayout;
+
+unsafe impl PipelineLayoutDesc for ShadertoyLayout {
+    fn num_sets(&self) -> usize {
+        1
+    }
+
+    fn num_bindings_in_set(&self, set: usize) -> Option<usize> {
+        match set {
+            0 => Some(CHANNELS),
+            _ => None,
+        }
+    }
+
+    fn descriptor(&self, set: usize, binding: usize) -> Option<DescriptorDesc> {
+        if set != 0 || binding >= CHANNELS {
+            return None;
+        }
+        Some(DescriptorDesc {
+            ty: DescriptorDescTy::CombinedImageSampler(DescriptorImageDesc {
+                sampled: true,
+                dimensions: DescriptorImageDescDimensions::TwoDimensional,
+                format: None,
+                multisampled: false,
+                array_layers: DescriptorImageDescArray::NonArrayed,
+            }),
+            array_count: 1,
+            stages: ShaderStages {
+                fragment: true,
+                ..ShaderStages::none()
+            },
+            readonly: true,
+        })
+    }
+
+    fn num_push_constants_ranges(&self) -> usize {
+        1
+    }
+
+    fn push_constants_range(
+        &self,
+        num: usize,
+    ) -> Option<PipelineLayoutDescPcRange> {
+        match num {
+            0 => Some(PipelineLayoutDescPcRange {
+                offset: 0,
+                size: std::mem::size_of::<ShadertoyInputs>(),
+                stages: ShaderStages {
+                    fragment: true,
+                    ..ShaderStages::none()
+                },
+            }),
+            _ => None,
+        }
+    }
+}
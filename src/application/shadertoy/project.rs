@@ -0,0 +1,222 @@
+use super::CHANNELS;
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Shadertoy's buffer passes, which always run in this order before the
+/// image pass.
+pub const BUFFER_NAMES: [&str; 4] =
+    ["buffer_a", "buffer_b", "buffer_c", "buffer_d"];
+
+/// What a pass reads through one of its `iChannel` samplers.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Channel {
+    /// A black texture.
+    #[default]
+    Empty,
+
+    /// The output of buffer A to D, by index. Buffers which run before the
+    /// reading pass are read as they were drawn this frame, the pass's own
+    /// buffer and later ones as they were drawn the frame before.
+    Buffer(usize),
+
+    /// Shadertoy's 256x3 keyboard texture. The red channel of row 0 is set
+    /// while a key is held, row 1 on the frame it was pressed, and row 2
+    /// toggles with every press. Keys are indexed by their javascript key
+    /// code.
+    Keyboard,
+
+    /// A png file.
+    Image(PathBuf),
+}
+
+/// A fragment shader with `mainImage` and what its channels read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassConfig {
+    pub shader: PathBuf,
+    pub channels: [Channel; CHANNELS],
+}
+
+/// What happens to the buffers' contents when the window is resized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Start again from black.
+    Clear,
+
+    /// Stretch the previous contents over the new size.
+    Preserve,
+}
+
+/// The passes of a Shadertoy shader.
+///
+/// Projects are written as a small ini style file, with paths relative to
+/// the file:
+///
+/// ```text
+/// resize = preserve
+///
+/// [buffer_a]
+/// shader = trails.frag
+/// channel0 = buffer_a
+/// channel1 = keyboard
+///
+/// [image]
+/// shader = image.frag
+/// channel0 = buffer_a
+/// channel1 = noise.png
+/// ```
+///
+/// Any of `buffer_a` to `buffer_d` can be left out, `image` can't. A
+/// channel is `buffer_a` to `buffer_d`, `keyboard`, or a png path, and
+/// channels which aren't listed are black.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadertoyProject {
+    pub buffers: [Option<PassConfig>; 4],
+    pub image: PassConfig,
+    pub resize: ResizeMode,
+}
+
+impl ShadertoyProject {
+    /// A project with nothing but an image pass, which reads up to four png
+    /// images.
+    pub fn single(shader: &Path, channel_images: &[PathBuf]) -> Result<Self> {
+        if channel_images.len() > CHANNELS {
+            bail!(
+                "shadertoy shaders have {} channels, {} images were given",
+                CHANNELS,
+                channel_images.len()
+            );
+        }
+        let mut image = PassConfig::new(shader.to_path_buf());
+        for (channel, path) in channel_images.iter().enumerate() {
+            image.channels[channel] = Channel::Image(path.clone());
+        }
+        Ok(Self {
+            buffers: Default::default(),
+            image,
+            resize: ResizeMode::Clear,
+        })
+    }
+
+    /// Read a project file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&source, directory)
+            .with_context(|| format!("invalid shadertoy project {:?}", path))
+    }
+
+    /// Parse a project, resolving paths against `directory`.
+    pub fn parse(source: &str, directory: &Path) -> Result<Self> {
+        let mut resize = ResizeMode::Clear;
+        let mut buffers: [Option<PassConfig>; 4] = Default::default();
+        let mut image: Option<PassConfig> = None;
+
+        // the pass being read, by section name
+        let mut section: Option<&str> = None;
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let context = || format!("invalid line {}: {:?}", index + 1, line);
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].trim();
+                if name != "image" && buffer_index(name).is_none() {
+                    bail!("unknown pass {:?} on line {}", name, index + 1);
+                }
+                section = Some(name);
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .with_context(context)?;
+            let name = match section {
+                Some(name) => name,
+                None if key == "resize" => {
+                    resize = match value {
+                        "clear" => ResizeMode::Clear,
+                        "preserve" => ResizeMode::Preserve,
+                        _ => bail!(
+                            "resize must be clear or preserve on line {}",
+                            index + 1
+                        ),
+                    };
+                    continue;
+                }
+                None => bail!("{:?} is outside of a pass", key),
+            };
+            let pass = match buffer_index(name) {
+                Some(buffer) => &mut buffers[buffer],
+                None => &mut image,
+            };
+            let pass =
+                pass.get_or_insert_with(|| PassConfig::new(PathBuf::new()));
+            match key {
+                "shader" => pass.shader = directory.join(value),
+                _ => {
+                    let channel = key
+                        .strip_prefix("channel")
+                        .and_then(|channel| channel.parse::<usize>().ok())
+                        .filter(|&channel| channel < CHANNELS)
+                        .with_context(context)?;
+                    pass.channels[channel] = parse_channel(value, directory);
+                }
+            }
+        }
+
+        for (name, pass) in BUFFER_NAMES
+            .iter()
+            .zip(buffers.iter())
+            .filter_map(|(name, pass)| pass.as_ref().map(|pass| (name, pass)))
+            .chain(image.as_ref().map(|pass| (&"image", pass)))
+        {
+            if pass.shader.as_os_str().is_empty() {
+                bail!("{} doesn't have a shader", name);
+            }
+            for channel in &pass.channels {
+                if let Channel::Buffer(buffer) = channel {
+                    if buffers[*buffer].is_none() {
+                        bail!(
+                            "{} reads {}, which isn't declared",
+                            name,
+                            BUFFER_NAMES[*buffer]
+                        );
+                    }
+                }
+            }
+        }
+        Ok(Self {
+            buffers,
+            image: image.context("the project doesn't have an image pass")?,
+            resize,
+        })
+    }
+}
+
+impl PassConfig {
+    fn new(shader: PathBuf) -> Self {
+        Self {
+            shader,
+            channels: Default::default(),
+        }
+    }
+}
+
+fn buffer_index(name: &str) -> Option<usize> {
+    BUFFER_NAMES.iter().position(|&buffer| buffer == name)
+}
+
+fn parse_channel(value: &str, directory: &Path) -> Channel {
+    match value {
+        "" | "none" => Channel::Empty,
+        "keyboard" => Channel::Keyboard,
+        _ => match buffer_index(value) {
+            Some(buffer) => Channel::Buffer(buffer),
+            None => Channel::Image(directory.join(value)),
+        },
+    }
+}
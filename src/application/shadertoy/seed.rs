@@ -0,0 +1,272 @@
+use crate::texture;
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// How a seed image is fitted into a buffer with another aspect ratio.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SeedFit {
+    /// Show the whole image, filling the bars on either side with the
+    /// letterbox color.
+    Fit,
+
+    /// Cover the whole buffer, cropping the image's longer side.
+    Fill,
+}
+
+/// An image a buffer pass starts from instead of black, for simulations
+/// like reaction-diffusion and feedback zooms which grow out of a picture.
+///
+/// Pngs are sRGB encoded, so the pixels are decoded to linear light as
+/// they're loaded, which is what the buffers' float images hold. The image
+/// is resampled to the buffer's size with bilinear filtering whenever the
+/// buffer starts again, with its rows flipped so its bottom is at the
+/// bottom of the buffer like the channel images.
+#[derive(Debug, Clone)]
+pub struct SeedImage {
+    width: u32,
+    height: u32,
+
+    // linear rgba, bottom row first
+    pixels: Vec<[f32; 4]>,
+    fit: SeedFit,
+    letterbox: [f32; 4],
+}
+
+impl SeedImage {
+    /// Load a png, letterboxed with black when fitted.
+    pub fn load(path: &Path, fit: SeedFit) -> Result<Self> {
+        let (width, height, pixels) = texture::decode_png(path)
+            .with_context(|| format!("unable to decode {:?}", path))?;
+        Self::from_rgba8(width, height, &pixels, fit)
+    }
+
+    /// Seed from sRGB encoded rgba8 pixels, top row first like a png.
+    pub fn from_rgba8(
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        fit: SeedFit,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            bail!("a seed image can't be empty");
+        }
+        if pixels.len() != (width * height * 4) as usize {
+            bail!(
+                "a {}x{} seed image needs {} bytes but has {}",
+                width,
+                height,
+                width * height * 4,
+                pixels.len()
+            );
+        }
+        let pixels = pixels
+            .chunks_exact(width as usize * 4)
+            .rev()
+            .flat_map(|row| row.chunks_exact(4))
+            .map(|pixel| {
+                let unorm = |i: usize| pixel[i] as f32 / 255.0;
+                [
+                    decode_srgb(unorm(0)),
+                    decode_srgb(unorm(1)),
+                    decode_srgb(unorm(2)),
+                    unorm(3),
+                ]
+            })
+            .collect();
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            fit,
+            letterbox: [0.0, 0.0, 0.0, 1.0],
+        })
+    }
+
+    /// The linear color of the bars around a fitted image.
+    pub fn letterbox(mut self, color: [f32; 4]) -> Self {
+        self.letterbox = color;
+        self
+    }
+
+    pub fn fit(&self) -> SeedFit {
+        self.fit
+    }
+
+    /// The image resampled to `extent`, bottom row first.
+    pub fn texels(&self, extent: [u32; 2]) -> Vec<[f32; 4]> {
+        let [target_width, target_height] =
+            [extent[0] as f32, extent[1] as f32];
+        let [width, height] = [self.width as f32, self.height as f32];
+        let scale_x = target_width / width;
+        let scale_y = target_height / height;
+        let scale = match self.fit {
+            SeedFit::Fit => scale_x.min(scale_y),
+            SeedFit::Fill => scale_x.max(scale_y),
+        };
+        // where the image's corner lands, negative when it's cropped
+        let offset_x = (target_width - width * scale) / 2.0;
+        let offset_y = (target_height - height * scale) / 2.0;
+
+        let mut texels = Vec::with_capacity((extent[0] * extent[1]) as usize);
+        for y in 0..extent[1] {
+            for x in 0..extent[0] {
+                let u = (x as f32 + 0.5 - offset_x) / scale;
+                let v = (y as f32 + 0.5 - offset_y) / scale;
+                if u < 0.0 || v < 0.0 || u >= width || v >= height {
+                    texels.push(self.letterbox);
+                } else {
+                    texels.push(self.sample(u - 0.5, v - 0.5));
+                }
+            }
+        }
+        texels
+    }
+
+    /// The image resampled to `extent` as half floats, for the buffers'
+    /// `R16G16B16A16Sfloat` images.
+    pub fn half_texels(&self, extent: [u32; 2]) -> Vec<[u16; 4]> {
+        self.texels(extent)
+            .into_iter()
+            .map(|texel| texel.map(texture::f32_to_f16))
+            .collect()
+    }
+
+    /// Bilinear filtering between pixel centers, clamped at the edges.
+    fn sample(&self, x: f32, y: f32) -> [f32; 4] {
+        let max_x = self.width as f32 - 1.0;
+        let max_y = self.height as f32 - 1.0;
+        let x = x.max(0.0).min(max_x);
+        let y = y.max(0.0).min(max_y);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |x: f32, y: f32| {
+            self.pixels[y as usize * self.width as usize + x as usize]
+        };
+        let [a, b, c, d] =
+            [pixel(x0, y0), pixel(x1, y0), pixel(x0, y1), pixel(x1, y1)];
+        let mut texel = [0.0; 4];
+        for (i, value) in texel.iter_mut().enumerate() {
+            let bottom = a[i] + (b[i] - a[i]) * fx;
+            let top = c[i] + (d[i] - c[i]) * fx;
+            *value = bottom + (top - bottom) * fy;
+        }
+        texel
+    }
+}
+
+/// The inverse of the sRGB transfer function, from encoded to linear light.
+pub fn decode_srgb(encoded: f32) -> f32 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::capture_format::encode_srgb;
+
+    /// The letterbox color the fitted seeds are checked against.
+    const LETTERBOX: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+
+    /// Black on the left and white on the right, twice as wide as tall.
+    const WIDE: [u8; 8] = [0, 0, 0, 255, 255, 255, 255, 255];
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!(
+            (value - expected).abs() < 1e-4,
+            "{} should be {}",
+            value,
+            expected
+        );
+    }
+
+    fn assert_texels(texels: &[[f32; 4]], expected: &[[f32; 4]]) {
+        assert_eq!(texels.len(), expected.len());
+        for (texel, expected) in texels.iter().zip(expected) {
+            for (&value, &expected) in texel.iter().zip(expected) {
+                assert_close(value, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn srgb_decodes_to_its_reference_values() {
+        assert_close(decode_srgb(0.0), 0.0);
+        assert_close(decode_srgb(1.0), 1.0);
+        assert_close(decode_srgb(0.5), 0.214_041);
+        for &linear in &[0.001, 0.01, 0.2, 0.7] {
+            assert_close(decode_srgb(encode_srgb(linear)), linear);
+        }
+    }
+
+    #[test]
+    fn seeds_come_through_flipped_and_linear() -> Result<()> {
+        // red and green on top, blue and white underneath
+        let square = SeedImage::from_rgba8(
+            2,
+            2,
+            &[
+                255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 255, 255, 255, 255, 255, 128,
+            ],
+            SeedFit::Fit,
+        )?;
+        let half_alpha = 128.0 / 255.0;
+        assert_texels(
+            &square.texels([2, 2]),
+            &[
+                [0.0, 0.0, 1.0, 1.0],
+                [1.0, 1.0, 1.0, half_alpha],
+                [1.0, 0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0, 1.0],
+            ],
+        );
+        assert_eq!(square.half_texels([2, 2])[0], [0, 0, 0x3c00, 0x3c00]);
+        Ok(())
+    }
+
+    #[test]
+    fn fitted_wide_seeds_are_letterboxed() -> Result<()> {
+        let fitted = SeedImage::from_rgba8(2, 1, &WIDE, SeedFit::Fit)?
+            .letterbox(LETTERBOX)
+            .texels([4, 4]);
+        let row = [
+            [0.0, 0.0, 0.0, 1.0],
+            [0.25, 0.25, 0.25, 1.0],
+            [0.75, 0.75, 0.75, 1.0],
+            [1.0, 1.0, 1.0, 1.0],
+        ];
+        let mut expected = vec![LETTERBOX; 4];
+        expected.extend_from_slice(&row);
+        expected.extend_from_slice(&row);
+        expected.extend_from_slice(&[LETTERBOX; 4]);
+        assert_texels(&fitted, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn filled_wide_seeds_are_cropped() -> Result<()> {
+        let filled = SeedImage::from_rgba8(2, 1, &WIDE, SeedFit::Fill)?
+            .letterbox(LETTERBOX)
+            .texels([4, 4]);
+        let row = [
+            [0.125, 0.125, 0.125, 1.0],
+            [0.375, 0.375, 0.375, 1.0],
+            [0.625, 0.625, 0.625, 1.0],
+            [0.875, 0.875, 0.875, 1.0],
+        ];
+        let expected: Vec<[f32; 4]> =
+            row.iter().cycle().take(16).copied().collect();
+        assert_texels(&filled, &expected);
+        Ok(())
+    }
+
+    #[test]
+    fn seeds_with_too_few_pixels_are_refused() {
+        assert!(SeedImage::from_rgba8(2, 2, &WIDE, SeedFit::Fit).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use super::camera::Camera;
+use super::clock::FrameTiming;
+use super::gizmos::Gizmos;
+use super::meshes::DrawList;
+use super::raw_frame::RawFrame;
+use super::transform::TransformHierarchy;
+use crate::display::{
+    AppIdentity, Display, WindowConfig, DEFAULT_APPLICATION_NAME,
+};
+use anyhow::Result;
+use vulkano::command_buffer::AutoCommandBuffer;
+use vulkano::instance::Version;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+/// What a sketch says about itself.
+///
+/// The name and version are handed to the driver when the vulkan instance
+/// is created, and the name titles the window and prefixes the sketch's
+/// screenshots and parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SketchInfo {
+    pub name: String,
+    pub version: Version,
+    pub author: Option<String>,
+
+    /// The window's preferred inner size in logical pixels.
+    pub window_size: Option<[u32; 2]>,
+
+    /// The window's preferred width over its height. The width is picked to
+    /// match the height, the preferred one or the default.
+    pub aspect: Option<f32>,
+}
+
+impl Default for SketchInfo {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_APPLICATION_NAME.to_owned(),
+            version: AppIdentity::default().application_version,
+            author: None,
+            window_size: None,
+            aspect: None,
+        }
+    }
+}
+
+impl SketchInfo {
+    /// The window this sketch asks for. Any of it can be changed before the
+    /// window is opened with `Application::with_window_config`.
+    pub fn window_config(&self) -> WindowConfig {
+        let default = WindowConfig::default();
+        let [width, height] = self.window_size.unwrap_or(default.size);
+        let width = match self.aspect {
+            Some(aspect) if aspect > 0.0 => {
+                (height as f32 * aspect).round() as u32
+            }
+            _ => width,
+        };
+        WindowConfig {
+            title: self.name.clone(),
+            size: [width, height],
+            identity: AppIdentity {
+                application_name: self.name.clone(),
+                application_version: self.version,
+                ..default.identity.clone()
+            },
+            ..default
+        }
+    }
+
+    /// The name in lowercase with anything but letters and digits replaced
+    /// by dashes, safe to use in file names and parameter keys.
+    pub fn slug(&self) -> String {
+        let slug = self
+            .name
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        if slug.is_empty() {
+            "sketch".to_owned()
+        } else {
+            slug
+        }
+    }
+}
+
+/// Something to draw, in place of the application's default triangle.
+///
+/// A sketch is usually created from the application's display, so it can
+/// build its renderers, then handed over with `Application::set_sketch`.
+/// Post processing, stereo, and dynamic resolution are applied to whatever
+/// the sketch draws.
+pub trait Sketch {
+    /// The sketch's name, version, and preferred window, read by
+    /// `Application::for_sketch` before the window is opened.
+    fn info() -> SketchInfo
+    where
+        Self: Sized,
+    {
+        SketchInfo::default()
+    }
+
+    /// Move the sketch along.
+    ///
+    /// @param time seconds since the application started
+    /// @param dt seconds since the last update
+    fn update(&mut self, _time: f32, _dt: f32) -> Result<()> {
+        Ok(())
+    }
+
+    /// Hear where the frame about to be updated is in time, for sketches
+    /// which number their frames or hand the frame to shaders. Called
+    /// before every update.
+    fn timing(&mut self, _timing: FrameTiming) -> Result<()> {
+        Ok(())
+    }
+
+    /// Build primary command buffers which run before the scene is drawn,
+    /// like passes which render into images the sketch samples. Called once
+    /// a frame, just before `draw`.
+    fn render_offscreen(
+        &mut self,
+        _display: &Display,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        Ok(vec![])
+    }
+
+    /// Hand command buffers built with raw vulkano to the frame, which runs
+    /// them after the application's offscreen passes. Called once a frame,
+    /// just after `render_offscreen`. See `RawFrame` for what they must not
+    /// do.
+    fn render_raw(&mut self, _frame: &mut RawFrame) -> Result<()> {
+        Ok(())
+    }
+
+    /// Build the secondary command buffers which draw the sketch as seen by
+    /// the camera, in order.
+    fn draw(
+        &self,
+        display: &Display,
+        camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>>;
+
+    /// Rebuild anything which depends on the scene's extent.
+    fn rebuild_swapchain_resources(
+        &mut self,
+        _display: &Display,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Respond to a key which the application doesn't use itself, or any
+    /// key while one of the sketch's text fields is focused.
+    fn handle_key(&mut self, _key: VirtualKeyCode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Respond to a key being released, whether or not the application
+    /// uses it.
+    fn handle_key_release(&mut self, _key: VirtualKeyCode) -> Result<()> {
+        Ok(())
+    }
+
+    /// Where the caret of the sketch's focused text field is, in the scene's
+    /// pixels from the top left, or None while no field is focused. Read
+    /// after every update.
+    ///
+    /// While a field is focused, typed characters come through
+    /// `handle_char`, every key press goes to `handle_key` with the
+    /// application's shortcuts off, and the IME's window is placed at the
+    /// caret. `TextField` does the editing.
+    fn text_caret(&self) -> Option<[f32; 2]> {
+        None
+    }
+
+    /// Respond to a character typed while one of the sketch's text fields
+    /// is focused, already composed from dead keys or committed by an IME.
+    fn handle_char(&mut self, _c: char) -> Result<()> {
+        Ok(())
+    }
+
+    /// Hear where the cursor is, in the scene's pixels from the top left,
+    /// and whether the left button is held. Called before every update.
+    fn cursor(
+        &mut self,
+        _cursor: Option<[f32; 2]>,
+        _left_pressed: bool,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Respond to a mouse button being pressed.
+    fn handle_click(&mut self, _button: MouseButton) -> Result<()> {
+        Ok(())
+    }
+
+    /// Register the handles the sketch's points can be dragged by, with
+    /// `Gizmos::point`. Called before every update.
+    fn gizmos(&mut self, _gizmos: &mut Gizmos) -> Result<()> {
+        Ok(())
+    }
+
+    /// The draws the cursor can pick, and the transforms which place them.
+    /// Nothing is picked unless the sketch returns them.
+    fn pickable(&self) -> Option<(&DrawList, &TransformHierarchy)> {
+        None
+    }
+
+    /// Hear which draw was under the cursor, by its id, as of the previous
+    /// frame. Called before every update while the sketch has pickable
+    /// draws.
+    fn picked(&mut self, _picked_id: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+}
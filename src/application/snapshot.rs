@@ -0,0 +1,454 @@
+use super::compute_pipeline::Simulation;
+use super::post_process::{
+    BokehShape, DepthOfField, LensEffects, MotionBlur, ToneMap,
+    VolumetricClouds,
+};
+use crate::display::Display;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// The directory snapshot slots are saved in.
+pub const SNAPSHOT_DIRECTORY: &str = "snapshots";
+
+/// Bumped whenever the snapshot layout changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// A subsystem whose settings can be saved in a snapshot and restored.
+pub trait Snapshottable {
+    type Settings;
+
+    /// The settings which would recreate what this looks like now.
+    fn snapshot(&self) -> Self::Settings;
+
+    /// Go back to saved settings, rebuilding anything which depends on them.
+    fn restore(
+        &mut self,
+        display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()>;
+}
+
+/// The simulation to create again, and its parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulationSettings {
+    pub name: String,
+
+    /// The state on the gpu isn't saved, the simulation is created again
+    /// from this seed instead.
+    pub seed: Option<u64>,
+
+    pub params: BTreeMap<String, f32>,
+}
+
+/// Where the camera is and what it looks at.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CameraSettings {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudSettings {
+    pub layer_min: f32,
+    pub layer_max: f32,
+    pub coverage: f32,
+    pub density: f32,
+    pub light_steps: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GradingSettings {
+    pub strength: f32,
+    pub dither: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MotionBlurSettings {
+    pub samples: u32,
+    pub max_velocity_px: f32,
+    pub strength: f32,
+}
+
+/// The bokeh shapes which can be picked while running.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Bokeh {
+    Circle,
+    Hexagon,
+    Star,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepthOfFieldSettings {
+    pub focus_distance: f32,
+    pub aperture: f32,
+    pub max_blur_radius: f32,
+    pub bokeh: Bokeh,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LensSettings {
+    pub vignette_strength: f32,
+    pub vignette_radius: f32,
+    pub chromatic_aberration: f32,
+    pub barrel_distortion: f32,
+}
+
+/// Everything needed to put the application back the way it was.
+///
+/// Optional settings are missing when their subsystem was turned off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+
+    /// The animation clock, in seconds.
+    pub time: f32,
+
+    pub simulation: Option<SimulationSettings>,
+    pub camera: CameraSettings,
+
+    /// The grading LUT, the identity LUT when missing.
+    pub lut: Option<PathBuf>,
+
+    pub color_grading: Option<GradingSettings>,
+    pub clouds: Option<CloudSettings>,
+    pub motion_blur: Option<MotionBlurSettings>,
+    pub depth_of_field: Option<DepthOfFieldSettings>,
+    pub lens_effects: Option<LensSettings>,
+    pub stereo: bool,
+    pub slideshow: bool,
+    pub dynamic_resolution: bool,
+}
+
+impl Snapshot {
+    /// Write the snapshot as JSON, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)
+                .with_context(|| format!("unable to create {:?}", directory))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("unable to create {:?}", path))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("unable to write the snapshot {:?}", path))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("unable to open {:?}", path))?;
+        let snapshot: Snapshot = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| {
+                format!("unable to read the snapshot {:?}", path)
+            })?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            bail!(
+                "{:?} is a version {} snapshot, only version {} is supported",
+                path,
+                snapshot.version,
+                SNAPSHOT_VERSION
+            );
+        }
+        Ok(snapshot)
+    }
+}
+
+/// The file a numbered slot is saved in.
+pub fn slot_path(directory: &Path, slot: u32) -> PathBuf {
+    directory.join(format!("slot_{}.json", slot))
+}
+
+/// Turn a subsystem on or off to match the settings, then restore them.
+pub fn restore_enabled<T: Snapshottable>(
+    subsystem: &mut Option<T>,
+    settings: Option<&T::Settings>,
+    display: &Display,
+    create: impl FnOnce(&Display) -> Result<T>,
+) -> Result<()> {
+    match settings {
+        Some(settings) => {
+            if subsystem.is_none() {
+                *subsystem = Some(create(display)?);
+            }
+            if let Some(subsystem) = subsystem {
+                subsystem.restore(display, settings)?;
+            }
+        }
+        None => *subsystem = None,
+    }
+    Ok(())
+}
+
+impl Snapshottable for Box<dyn Simulation> {
+    type Settings = BTreeMap<String, f32>;
+
+    fn snapshot(&self) -> Self::Settings {
+        self.params()
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect()
+    }
+
+    fn restore(
+        &mut self,
+        _display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        for (name, &value) in settings {
+            self.set_param(name, value);
+        }
+        Ok(())
+    }
+}
+
+impl Snapshottable for VolumetricClouds {
+    type Settings = CloudSettings;
+
+    fn snapshot(&self) -> Self::Settings {
+        CloudSettings {
+            layer_min: self.layer_min,
+            layer_max: self.layer_max,
+            coverage: self.coverage,
+            density: self.density,
+            light_steps: self.light_steps,
+        }
+    }
+
+    fn restore(
+        &mut self,
+        _display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        self.layer_min = settings.layer_min;
+        self.layer_max = settings.layer_max;
+        self.coverage = settings.coverage;
+        self.density = settings.density;
+        self.light_steps = settings.light_steps;
+        Ok(())
+    }
+}
+
+impl Snapshottable for ToneMap {
+    type Settings = GradingSettings;
+
+    fn snapshot(&self) -> Self::Settings {
+        GradingSettings {
+            strength: self.grading.strength,
+            dither: self.dither,
+        }
+    }
+
+    fn restore(
+        &mut self,
+        _display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        self.grading.strength = settings.strength;
+        self.dither = settings.dither;
+        Ok(())
+    }
+}
+
+impl Snapshottable for MotionBlur {
+    type Settings = MotionBlurSettings;
+
+    fn snapshot(&self) -> Self::Settings {
+        MotionBlurSettings {
+            samples: self.samples,
+            max_velocity_px: self.max_velocity_px,
+            strength: self.strength,
+        }
+    }
+
+    fn restore(
+        &mut self,
+        _display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        self.samples = settings.samples;
+        self.max_velocity_px = settings.max_velocity_px;
+        self.strength = settings.strength;
+        Ok(())
+    }
+}
+
+impl Snapshottable for DepthOfField {
+    type Settings = DepthOfFieldSettings;
+
+    fn snapshot(&self) -> Self::Settings {
+        let bokeh = match self.bokeh_shape() {
+            BokehShape::Circle => Bokeh::Circle,
+            BokehShape::Hexagon => Bokeh::Hexagon,
+            BokehShape::Custom(_) => Bokeh::Star,
+        };
+        DepthOfFieldSettings {
+            focus_distance: self.focus_distance,
+            aperture: self.aperture,
+            max_blur_radius: self.max_blur_radius,
+            bokeh,
+        }
+    }
+
+    fn restore(
+        &mut self,
+        display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        self.focus_distance = settings.focus_distance;
+        self.aperture = settings.aperture;
+        self.max_blur_radius = settings.max_blur_radius;
+        // only a changed shape rebuilds the descriptor set
+        if self.snapshot().bokeh != settings.bokeh {
+            let shape = match settings.bokeh {
+                Bokeh::Circle => BokehShape::Circle,
+                Bokeh::Hexagon => BokehShape::Hexagon,
+                Bokeh::Star => super::load_bokeh_star(display)?,
+            };
+            self.set_bokeh_shape(display, shape)?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshottable for LensEffects {
+    type Settings = LensSettings;
+
+    fn snapshot(&self) -> Self::Settings {
+        LensSettings {
+            vignette_strength: self.vignette_strength,
+            vignette_radius: self.vignette_radius,
+            chromatic_aberration: self.chromatic_aberration,
+            barrel_distortion: self.barrel_distortion,
+        }
+    }
+
+    fn restore(
+        &mut self,
+        _display: &Display,
+        settings: &Self::Settings,
+    ) -> Result<()> {
+        self.vignette_strength = settings.vignette_strength;
+        self.vignette_radius = settings.vignette_radius;
+        self.chromatic_aberration = settings.chromatic_aberration;
+        self.barrel_distortion = settings.barrel_distortion;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full() -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            time: 12.5,
+            simulation: Some(SimulationSettings {
+                name: "nbody".to_owned(),
+                seed: Some(1234),
+                params: vec![
+                    ("g".to_owned(), 0.8),
+                    ("softening".to_owned(), 0.02),
+                ]
+                .into_iter()
+                .collect(),
+            }),
+            camera: CameraSettings {
+                position: [0.1, 0.2, 0.3],
+                target: [0.0, 0.0, -1.0],
+            },
+            lut: Some(PathBuf::from("assets/luts/warm.cube")),
+            color_grading: Some(GradingSettings {
+                strength: 0.7,
+                dither: false,
+            }),
+            clouds: Some(CloudSettings {
+                layer_min: 1.0,
+                layer_max: 2.5,
+                coverage: 0.4,
+                density: 0.9,
+                light_steps: 6,
+            }),
+            motion_blur: Some(MotionBlurSettings {
+                samples: 12,
+                max_velocity_px: 32.0,
+                strength: 1.25,
+            }),
+            depth_of_field: Some(DepthOfFieldSettings {
+                focus_distance: 1.5,
+                aperture: 2.0,
+                max_blur_radius: 8.0,
+                bokeh: Bokeh::Star,
+            }),
+            lens_effects: Some(LensSettings {
+                vignette_strength: 0.45,
+                vignette_radius: 0.55,
+                chromatic_aberration: 0.004,
+                barrel_distortion: 0.06,
+            }),
+            stereo: true,
+            slideshow: false,
+            dynamic_resolution: true,
+        }
+    }
+
+    fn empty() -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            time: 0.0,
+            simulation: None,
+            camera: CameraSettings {
+                position: [0.0, 0.0, 0.0],
+                target: [0.0, 0.0, -1.0],
+            },
+            lut: None,
+            color_grading: None,
+            clouds: None,
+            motion_blur: None,
+            depth_of_field: None,
+            lens_effects: None,
+            stereo: false,
+            slideshow: false,
+            dynamic_resolution: false,
+        }
+    }
+
+    /// A snapshot with every setting filled in and one with everything
+    /// turned off both come back from their slots unchanged.
+    #[test]
+    fn snapshots_round_trip() -> Result<()> {
+        let directory =
+            std::env::temp_dir().join("vulkan_starter_snapshot_round_trip");
+        for (slot, saved) in [(1, full()), (2, empty())].iter() {
+            let path = slot_path(&directory, *slot);
+            saved.save(&path)?;
+            assert_eq!(&Snapshot::load(&path)?, saved);
+        }
+        std::fs::remove_dir_all(&directory)
+            .context("unable to remove the snapshot directory")
+    }
+
+    #[test]
+    fn other_versions_and_unsaved_slots_are_refused() -> Result<()> {
+        let directory =
+            std::env::temp_dir().join("vulkan_starter_snapshot_refused");
+        let path = slot_path(&directory, 3);
+        Snapshot {
+            version: SNAPSHOT_VERSION + 1,
+            ..empty()
+        }
+        .save(&path)?;
+        assert!(
+            Snapshot::load(&path).is_err(),
+            "a snapshot from another version was loaded"
+        );
+        assert!(
+            Snapshot::load(&slot_path(&directory, 9)).is_err(),
+            "a slot which was never saved was loaded"
+        );
+        std::fs::remove_dir_all(&directory)
+            .context("unable to remove the snapshot directory")
+    }
+}
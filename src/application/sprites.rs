@@ -0,0 +1,276 @@
+use super::fullscreen::{self, FullscreenVertex};
+use super::live_resources::{LiveResource, ResourceKind};
+use crate::display::Display;
+use crate::texture::TextureArray;
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// One textured quad, drawn with a single layer of the sprite sheet.
+#[derive(Debug, Copy, Clone)]
+pub struct Sprite {
+    /// The center of the quad in normalized device coordinates.
+    pub center: [f32; 2],
+
+    /// The width and height of the quad in normalized device coordinates.
+    pub size: [f32; 2],
+
+    /// The layer of the texture array to draw.
+    pub layer: u32,
+}
+
+/// Draws sprites from the layers of a texture array.
+///
+/// Every sprite is its own draw with the layer in a push constant, so a
+/// sprite sheet or slideshow only needs a single descriptor set.
+pub struct SpriteRenderer {
+    pub sheet: TextureArray,
+
+    pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    descriptor_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+    _live: [LiveResource; 2],
+}
+
+impl SpriteRenderer {
+    pub fn new(display: &Display, sheet: TextureArray) -> Result<Self> {
+        let pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        let descriptor_set = create_descriptor_set(display, &pipeline, &sheet)?;
+        Ok(Self {
+            sheet,
+            pipeline,
+            descriptor_set,
+            vertices: create_quad_buffer(&display.device)?,
+            _live: [
+                LiveResource::described(
+                    ResourceKind::Pipeline,
+                    "sprite renderer",
+                    "",
+                    0,
+                ),
+                LiveResource::described(
+                    ResourceKind::Buffer,
+                    "sprite quad",
+                    "host visible",
+                    0,
+                ),
+            ],
+        })
+    }
+
+    /// Build a secondary command buffer which draws each sprite in order.
+    pub fn draw(
+        &self,
+        display: &Display,
+        sprites: &[Sprite],
+    ) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the sprites")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        for sprite in sprites {
+            if sprite.layer >= self.sheet.layers {
+                bail!(
+                    "sprite layer {} is outside of the {} layer sheet",
+                    sprite.layer,
+                    self.sheet.layers
+                );
+            }
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    &DynamicState::none(),
+                    vec![self.vertices.clone()],
+                    self.descriptor_set.clone(),
+                    vertex_shader::ty::PushConstantData {
+                        center: sprite.center,
+                        size: sprite.size,
+                        layer: sprite.layer,
+                    },
+                )
+                .context("unable to draw a sprite")?;
+        }
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    /// Draw from a different sheet, binding it right away.
+    pub fn set_sheet(
+        &mut self,
+        display: &Display,
+        sheet: TextureArray,
+    ) -> Result<()> {
+        self.descriptor_set =
+            create_descriptor_set(display, &self.pipeline, &sheet)?;
+        self.sheet = sheet;
+        Ok(())
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.pipeline = create_graphics_pipeline(
+            &display.device,
+            display.scene_extent(),
+            &display.render_pass,
+        )?;
+        self.descriptor_set =
+            create_descriptor_set(display, &self.pipeline, &self.sheet)?;
+        Ok(())
+    }
+}
+
+/// Two triangles covering the unit square, the positions double as texture
+/// coordinates.
+fn create_quad_buffer(
+    device: &Arc<Device>,
+) -> Result<Arc<CpuAccessibleBuffer<[FullscreenVertex]>>> {
+    let vertices = [
+        [0.0, 0.0],
+        [1.0, 0.0],
+        [1.0, 1.0],
+        [0.0, 0.0],
+        [1.0, 1.0],
+        [0.0, 1.0],
+    ]
+    .iter()
+    .map(|&pos| FullscreenVertex { pos });
+    CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::vertex_buffer(),
+        false,
+        vertices,
+    )
+    .context("unable to create the sprite vertex buffer")
+}
+
+fn create_descriptor_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    sheet: &TextureArray,
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    // clamp so neighboring texels never bleed in from the opposite edge
+    let sampler = Sampler::new(
+        display.device.clone(),
+        Filter::Linear,
+        Filter::Linear,
+        MipmapMode::Nearest,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        SamplerAddressMode::ClampToEdge,
+        0.0,
+        1.0,
+        0.0,
+        0.0,
+    )
+    .context("unable to create the sprite sampler")?;
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("sprite pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(sheet.image.clone(), sampler)?
+            .build()
+            .context("unable to create the sprite descriptors")?,
+    ))
+}
+
+fn create_graphics_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = vertex_shader::Shader::load(device.clone())
+        .context("unable to load the sprite vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the sprite fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_alpha_blending()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the sprite pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the sprite graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod vertex_shader {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 pos;
+
+            layout(location = 0) out vec2 vertUv;
+            layout(location = 1) flat out uint vertLayer;
+
+            layout(push_constant) uniform PushConstantData {
+                vec2 center;
+                vec2 size;
+                uint layer;
+            } pc;
+
+            void main() {
+                vertUv = pos;
+                vertLayer = pc.layer;
+                gl_Position = vec4(pc.center + (pos - 0.5) * pc.size, 0.0, 1.0);
+            }
+            "#
+    }
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 1) flat in uint fragLayer;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2DArray sheet;
+
+            void main() {
+                outColor = texture(sheet, vec3(fragUv, float(fragLayer)));
+            }
+            "#
+    }
+}
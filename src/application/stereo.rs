@@ -0,0 +1,210 @@
+use super::camera::Camera;
+use super::fullscreen::{self, FullscreenVertex};
+use super::offscreen::OffscreenTarget;
+use crate::display::Display;
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::buffer::CpuAccessibleBuffer;
+use vulkano::command_buffer::{
+    AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState,
+};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::{DescriptorSet, PipelineLayoutAbstract};
+use vulkano::device::Device;
+use vulkano::framebuffer::{RenderPassAbstract, Subpass};
+use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
+use vulkano::sampler::Sampler;
+
+type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
+
+/// The distance between the two eyes in world units.
+const EYE_SEPARATION: f32 = 0.12;
+
+/// Renders the scene once per eye and composites both views side by side
+/// onto the swapchain.
+///
+/// Each eye renders into its own image at the scene extent with the display's
+/// render pass, so every pipeline built for the scene can draw into the eyes
+/// without changes. The composite samples the eyes with linear filtering, so
+/// it also upscales them when the scene is drawn below full resolution. vulkano has no multiview render passes, so even
+/// when `VK_KHR_multiview` is supported the eyes are drawn in two passes.
+pub struct StereoPreview {
+    eyes: [OffscreenTarget; 2],
+    composite_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    composite_set: Arc<dyn DescriptorSet + Send + Sync>,
+    vertices: Arc<CpuAccessibleBuffer<[FullscreenVertex]>>,
+}
+
+impl StereoPreview {
+    pub fn new(display: &Display) -> Result<Self> {
+        if display.multiview_supported {
+            log::info!(
+                "multiview is supported, but each eye is rendered in its own \
+                pass"
+            );
+        }
+
+        let eyes = [
+            OffscreenTarget::with_extent(display, display.scene_extent())?,
+            OffscreenTarget::with_extent(display, display.scene_extent())?,
+        ];
+        let composite_pipeline = create_composite_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        let composite_set =
+            create_composite_set(display, &composite_pipeline, &eyes)?;
+
+        Ok(Self {
+            eyes,
+            composite_pipeline,
+            composite_set,
+            vertices: fullscreen::create_vertex_buffer(&display.device)?,
+        })
+    }
+
+    /// The cameras for the left and right eyes.
+    ///
+    /// Each eye is squeezed into half of the swapchain's width when
+    /// composited, so the cameras use the aspect ratio of one half.
+    pub fn eye_cameras(&self, display: &Display) -> [Camera; 2] {
+        let [width, height] = display.swapchain.dimensions();
+        let camera = Camera::for_extent([width / 2, height]);
+        [
+            camera.with_eye_offset(-EYE_SEPARATION / 2.0),
+            camera.with_eye_offset(EYE_SEPARATION / 2.0),
+        ]
+    }
+
+    /// Build a primary command buffer which renders one eye's secondary
+    /// command buffers into that eye's image.
+    pub fn render_eye(
+        &self,
+        display: &Display,
+        eye: usize,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<AutoCommandBuffer> {
+        self.eyes[eye].render(display, graphics_queue_subbuffers)
+    }
+
+    /// Build a secondary command buffer which draws both eyes side by side.
+    pub fn composite(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let mut builder =
+            AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+                display.device.clone(),
+                display.graphics_queue.family(),
+                Subpass::from(display.render_pass.clone(), 0)
+                    .context("unable to select subpass for the composite")?,
+            )
+            .context("unable to create the command buffer builder")?;
+        builder
+            .draw(
+                self.composite_pipeline.clone(),
+                &DynamicState::none(),
+                vec![self.vertices.clone()],
+                self.composite_set.clone(),
+                (),
+            )
+            .context("unable to composite the eyes")?;
+        builder
+            .build()
+            .context("unable to build the command buffer")
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        let eyes = [
+            OffscreenTarget::with_extent(display, display.scene_extent())?,
+            OffscreenTarget::with_extent(display, display.scene_extent())?,
+        ];
+        self.composite_pipeline = create_composite_pipeline(
+            &display.device,
+            display.swapchain.dimensions(),
+            &display.render_pass,
+        )?;
+        self.composite_set =
+            create_composite_set(display, &self.composite_pipeline, &eyes)?;
+        self.eyes = eyes;
+        Ok(())
+    }
+}
+
+fn create_composite_set(
+    display: &Display,
+    pipeline: &Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+    eyes: &[OffscreenTarget; 2],
+) -> Result<Arc<dyn DescriptorSet + Send + Sync>> {
+    let sampler =
+        Sampler::simple_repeat_linear_no_mipmap(display.device.clone());
+    let layout = pipeline
+        .descriptor_set_layout(0)
+        .context("composite pipeline has no descriptor set layout")?;
+    Ok(Arc::new(
+        PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(eyes[0].image.clone(), sampler.clone())?
+            .add_sampled_image(eyes[1].image.clone(), sampler)?
+            .build()
+            .context("unable to create the composite descriptors")?,
+    ))
+}
+
+fn create_composite_pipeline(
+    device: &Arc<Device>,
+    swapchain_extent: [u32; 2],
+    render_pass: &Arc<DynRenderPass>,
+) -> Result<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> {
+    let vert = fullscreen::vertex_shader::Shader::load(device.clone())
+        .context("unable to load the fullscreen vertex shader")?;
+    let frag = fragment_shader::Shader::load(device.clone())
+        .context("unable to load the composite fragment shader")?;
+
+    let pipeline = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<FullscreenVertex>()
+        .vertex_shader(vert.main_entry_point(), ())
+        .fragment_shader(frag.main_entry_point(), ())
+        .viewports(vec![fullscreen::viewport(swapchain_extent)])
+        .depth_clamp(false)
+        .polygon_mode_fill()
+        .line_width(1.0)
+        .depth_write(false)
+        .sample_shading_disabled()
+        .blend_pass_through()
+        .triangle_list()
+        .render_pass(
+            Subpass::from(render_pass.clone(), 0)
+                .context("could not create the composite pipeline subpass")?,
+        )
+        .build(device.clone())
+        .context("could not create the composite graphics pipeline")?;
+
+    Ok(Arc::new(pipeline))
+}
+
+mod fragment_shader {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r#"
+            #version 450
+            #extension GL_ARB_separate_shader_objects : enable
+
+            layout(location = 0) in vec2 fragUv;
+            layout(location = 0) out vec4 outColor;
+
+            layout(set = 0, binding = 0) uniform sampler2D left_eye;
+            layout(set = 0, binding = 1) uniform sampler2D right_eye;
+
+            void main() {
+                if (fragUv.x < 0.5) {
+                    vec2 uv = vec2(fragUv.x * 2.0, fragUv.y);
+                    outColor = texture(left_eye, uv);
+                } else {
+                    vec2 uv = vec2(fragUv.x * 2.0 - 1.0, fragUv.y);
+                    outColor = texture(right_eye, uv);
+                }
+            }
+            "#
+    }
+}
@@ -0,0 +1,213 @@
+use super::sprites::{Sprite, SpriteRenderer};
+use crate::display::Display;
+use crate::texture::{TextureArray, TextureBuilder};
+use anyhow::{Context, Result};
+use vulkano::command_buffer::AutoCommandBuffer;
+
+/// The first character in the font, every glyph after it follows in ASCII
+/// order.
+const FIRST_CHAR: u8 = b' ';
+
+/// Each glyph is 5x7 pixels inside a 6x8 cell, which leaves a pixel between
+/// characters and lines.
+const CELL: [u32; 2] = [6, 8];
+
+/// Font pixels are scaled up by this much before upload so the sprites'
+/// linear filtering keeps the edges sharp.
+const TEXEL_SCALE: u32 = 8;
+
+/// The rows of each glyph from ' ' to '_', top first, with the leftmost
+/// pixel in bit 4.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 7]; 64] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+    [0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A], // #
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D], // &
+    [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E], // 0
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E], // 1
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F], // 2
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E], // 3
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02], // 4
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E], // 5
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E], // 6
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E], // 8
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C], // 9
+    [0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x00], // :
+    [0x00, 0x00, 0x04, 0x00, 0x04, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E], // @
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // A
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E], // B
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E], // C
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C], // D
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F], // E
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10], // F
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F], // G
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11], // H
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F], // L
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // O
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10], // P
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D], // Q
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11], // R
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E], // S
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A], // W
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04], // Y
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F], // Z
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E], // ]
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // _
+];
+
+/// A line of text to draw.
+#[derive(Debug, Copy, Clone)]
+pub struct Label<'a> {
+    pub text: &'a str,
+
+    /// The top left corner of the first character in normalized device
+    /// coordinates.
+    pub position: [f32; 2],
+
+    /// The height of a line in normalized device coordinates.
+    pub height: f32,
+}
+
+/// Draws text with a small built in bitmap font.
+///
+/// The font only has uppercase letters, digits, and punctuation. Lowercase
+/// letters are drawn as uppercase and anything else as '?'.
+pub struct TextRenderer {
+    sprites: SpriteRenderer,
+}
+
+impl TextRenderer {
+    pub fn new(display: &Display) -> Result<Self> {
+        let glyphs = GLYPHS
+            .iter()
+            .enumerate()
+            .map(|(i, rows)| {
+                let [width, height] =
+                    [CELL[0] * TEXEL_SCALE, CELL[1] * TEXEL_SCALE];
+                let name =
+                    format!("glyph {:?}", (FIRST_CHAR + i as u8) as char);
+                TextureBuilder::from_rgba(
+                    &name,
+                    width,
+                    height,
+                    glyph_pixels(rows),
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let font = TextureArray::build(
+            glyphs,
+            &display.device,
+            &display.graphics_queue,
+        )
+        .context("unable to build the font")?;
+        Ok(Self {
+            sprites: SpriteRenderer::new(display, font)?,
+        })
+    }
+
+    /// Build a secondary command buffer which draws every label.
+    pub fn draw(
+        &self,
+        display: &Display,
+        labels: &[Label],
+    ) -> Result<AutoCommandBuffer> {
+        let [width, height] = display.scene_extent();
+        let aspect_ratio = width as f32 / height as f32;
+        let sprites: Vec<Sprite> = labels
+            .iter()
+            .flat_map(|label| layout(label, aspect_ratio))
+            .collect();
+        self.sprites.draw(display, &sprites)
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.sprites.rebuild_swapchain_resources(display)
+    }
+}
+
+/// How far apart the characters of a line `height` tall are, in normalized
+/// device coordinates, on a scene whose width is `aspect_ratio` times its
+/// height.
+pub fn advance(height: f32, aspect_ratio: f32) -> f32 {
+    height * CELL[0] as f32 / CELL[1] as f32 / aspect_ratio
+}
+
+/// A sprite for every character in the label, spaces included.
+fn layout(label: &Label, aspect_ratio: f32) -> Vec<Sprite> {
+    let height = label.height;
+    let width = advance(height, aspect_ratio);
+    let [left, top] = label.position;
+    label
+        .text
+        .chars()
+        .enumerate()
+        .map(|(i, c)| Sprite {
+            center: [left + width * (i as f32 + 0.5), top + height * 0.5],
+            size: [width, height],
+            layer: glyph_layer(c),
+        })
+        .collect()
+}
+
+/// The layer of the font which draws `c`.
+fn glyph_layer(c: char) -> u32 {
+    let c = c.to_ascii_uppercase();
+    let last = FIRST_CHAR as usize + GLYPHS.len() - 1;
+    match c as usize {
+        code if (FIRST_CHAR as usize..=last).contains(&code) => {
+            (code - FIRST_CHAR as usize) as u32
+        }
+        _ => (b'?' - FIRST_CHAR) as u32,
+    }
+}
+
+/// White rgba pixels for a glyph, transparent everywhere the glyph isn't.
+fn glyph_pixels(rows: &[u8; 7]) -> Vec<u8> {
+    let [width, height] = [CELL[0] * TEXEL_SCALE, CELL[1] * TEXEL_SCALE];
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let [col, row] = [x / TEXEL_SCALE, y / TEXEL_SCALE];
+            let lit = (row as usize) < rows.len()
+                && col < 5
+                && rows[row as usize] & (0x10 >> col) != 0;
+            let alpha = if lit { 255 } else { 0 };
+            pixels.extend_from_slice(&[255, 255, 255, alpha]);
+        }
+    }
+    pixels
+}
@@ -0,0 +1,102 @@
+use super::text::{self, Label, TextRenderer};
+use super::text_input::{TextEdit, TextField};
+use crate::display::Display;
+use anyhow::Result;
+use vulkano::command_buffer::AutoCommandBuffer;
+use winit::event::VirtualKeyCode;
+
+/// The top left corner of the hint, in normalized device coordinates. The
+/// field is the line under it.
+const TOP_LEFT: [f32; 2] = [-0.95, 0.75];
+
+/// The height of a line of text and the distance between lines, in
+/// normalized device coordinates.
+const LINE_HEIGHT: f32 = 0.06;
+const LINE_SPACING: f32 = 0.08;
+
+const HINT: &str = "type, enter submits, escape closes";
+const PROMPT: &str = "> ";
+
+/// A one line text field near the bottom of the window, drawn with the
+/// built in font, for trying text input and IMEs out.
+///
+/// The font only has ASCII, so anything else is drawn as '?', one for each
+/// character. What was typed is logged whole when it's submitted.
+pub struct TextBox {
+    text: TextRenderer,
+    field: TextField,
+}
+
+impl TextBox {
+    pub fn new(display: &Display) -> Result<Self> {
+        Ok(Self {
+            text: TextRenderer::new(display)?,
+            field: TextField::default(),
+        })
+    }
+
+    pub fn handle_char(&mut self, c: char) -> TextEdit {
+        self.field.handle_char(c)
+    }
+
+    pub fn handle_key(&mut self, key: VirtualKeyCode) -> TextEdit {
+        self.field.handle_key(key)
+    }
+
+    /// Where the caret's bottom left corner is, in the window's pixels from
+    /// the top left, so the IME's window can be placed under it.
+    pub fn caret_position(
+        &self,
+        display: &Display,
+        window_size: [u32; 2],
+    ) -> [f32; 2] {
+        let [x, top] = self.caret(display);
+        let y = top + LINE_HEIGHT;
+        [
+            (x + 1.0) * 0.5 * window_size[0] as f32,
+            (y + 1.0) * 0.5 * window_size[1] as f32,
+        ]
+    }
+
+    /// Build a secondary command buffer which draws the hint, the field,
+    /// and an underscore for the caret.
+    pub fn draw(&self, display: &Display) -> Result<AutoCommandBuffer> {
+        let line = format!("{}{}", PROMPT, self.field.text());
+        let [left, top] = TOP_LEFT;
+        let labels = [
+            Label {
+                text: HINT,
+                position: [left, top],
+                height: LINE_HEIGHT,
+            },
+            Label {
+                text: &line,
+                position: [left, top + LINE_SPACING],
+                height: LINE_HEIGHT,
+            },
+            Label {
+                text: "_",
+                position: self.caret(display),
+                height: LINE_HEIGHT,
+            },
+        ];
+        self.text.draw(display, &labels)
+    }
+
+    /// The caret's top left corner in normalized device coordinates, one
+    /// character cell after the prompt and the text before it.
+    fn caret(&self, display: &Display) -> [f32; 2] {
+        let [width, height] = display.scene_extent();
+        let advance = text::advance(LINE_HEIGHT, width as f32 / height as f32);
+        let columns = PROMPT.chars().count() + self.field.caret_column();
+        let [left, top] = TOP_LEFT;
+        [left + advance * columns as f32, top + LINE_SPACING]
+    }
+
+    pub fn rebuild_swapchain_resources(
+        &mut self,
+        display: &Display,
+    ) -> Result<()> {
+        self.text.rebuild_swapchain_resources(display)
+    }
+}
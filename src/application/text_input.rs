@@ -0,0 +1,193 @@
+use winit::event::VirtualKeyCode;
+
+/// What a character or key did to a text field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEdit {
+    /// Nothing changed, like a control character or a key fields don't use.
+    Unchanged,
+
+    /// The text or the caret changed.
+    Changed,
+
+    /// Enter was pressed. Holds the text, which the field is emptied of.
+    Submitted(String),
+
+    /// Escape was pressed.
+    Cancelled,
+}
+
+/// A line of text being typed, and a caret between its characters.
+///
+/// Fields are fed the characters winit receives, which are already what the
+/// user meant to type: the OS composes dead keys with the key after them,
+/// and an IME commits its text as one character after another. Characters
+/// are kept whole however many bytes they take, so the caret only ever
+/// moves, and backspace only ever removes, a whole character.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextField {
+    text: String,
+
+    // a byte offset into the text, always on a character boundary
+    caret: usize,
+}
+
+impl TextField {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// How many characters are before the caret.
+    pub fn caret_column(&self) -> usize {
+        self.text[..self.caret].chars().count()
+    }
+
+    /// Respond to a character from `WindowEvent::ReceivedCharacter`.
+    ///
+    /// Enter submits, backspace and delete remove the character before or
+    /// after the caret, and other control characters are ignored. Escape
+    /// and the arrow keys come through `handle_key` instead, since not every
+    /// platform sends a character for them.
+    pub fn handle_char(&mut self, c: char) -> TextEdit {
+        match c {
+            '\r' | '\n' => {
+                self.caret = 0;
+                TextEdit::Submitted(std::mem::take(&mut self.text))
+            }
+            '\u{8}' => match self.previous_boundary() {
+                Some(previous) => {
+                    self.text.replace_range(previous..self.caret, "");
+                    self.caret = previous;
+                    TextEdit::Changed
+                }
+                None => TextEdit::Unchanged,
+            },
+            '\u{7f}' => match self.next_boundary() {
+                Some(next) => {
+                    self.text.replace_range(self.caret..next, "");
+                    TextEdit::Changed
+                }
+                None => TextEdit::Unchanged,
+            },
+
+            // macOS sends the arrow and function keys as characters in the
+            // private use area
+            '\u{f700}'..='\u{f8ff}' => TextEdit::Unchanged,
+            c if c.is_control() => TextEdit::Unchanged,
+            c => {
+                self.text.insert(self.caret, c);
+                self.caret += c.len_utf8();
+                TextEdit::Changed
+            }
+        }
+    }
+
+    /// Respond to a key which moves the caret or leaves the field: the
+    /// left and right arrows, Home, End, and Escape.
+    pub fn handle_key(&mut self, key: VirtualKeyCode) -> TextEdit {
+        let caret = match key {
+            VirtualKeyCode::Left => self.previous_boundary(),
+            VirtualKeyCode::Right => self.next_boundary(),
+            VirtualKeyCode::Home => Some(0),
+            VirtualKeyCode::End => Some(self.text.len()),
+            VirtualKeyCode::Escape => return TextEdit::Cancelled,
+            _ => None,
+        };
+        match caret {
+            Some(caret) if caret != self.caret => {
+                self.caret = caret;
+                TextEdit::Changed
+            }
+            _ => TextEdit::Unchanged,
+        }
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.text[..self.caret]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.caret..]
+            .chars()
+            .next()
+            .map(|c| self.caret + c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_chars(field: &mut TextField, text: &str) {
+        for c in text.chars() {
+            assert_eq!(field.handle_char(c), TextEdit::Changed, "{:?}", c);
+        }
+    }
+
+    fn assert_field(field: &TextField, text: &str, caret_column: usize) {
+        assert_eq!(field.text(), text);
+        assert_eq!(field.caret_column(), caret_column);
+    }
+
+    /// A dead ´ then e arrives as é, a dead ´ then x as the two characters.
+    #[test]
+    fn dead_keys_arrive_composed() {
+        let mut field = TextField::default();
+        type_chars(&mut field, "caf\u{e9} \u{b4}x");
+        assert_field(&field, "café ´x", 7);
+    }
+
+    #[test]
+    fn multi_byte_characters_are_kept_whole() {
+        // an IME commits its text a character at a time, each 3 bytes
+        let mut field = TextField::default();
+        type_chars(&mut field, "日本語");
+        assert_field(&field, "日本語", 3);
+        field.handle_char('\u{8}');
+        assert_field(&field, "日本", 2);
+
+        // the caret steps over whole characters, and text goes in at it
+        field.handle_key(VirtualKeyCode::Left);
+        type_chars(&mut field, "한");
+        assert_field(&field, "日한本", 2);
+        field.handle_char('\u{7f}');
+        assert_field(&field, "日한", 2);
+        field.handle_key(VirtualKeyCode::Home);
+        assert_eq!(field.handle_char('\u{8}'), TextEdit::Unchanged);
+        assert_field(&field, "日한", 0);
+        field.handle_key(VirtualKeyCode::End);
+        assert_field(&field, "日한", 2);
+
+        // an emoji outside the basic plane is still one character
+        type_chars(&mut field, "🎨");
+        assert_field(&field, "日한🎨", 3);
+    }
+
+    #[test]
+    fn control_characters_and_other_keys_are_ignored() {
+        let mut field = TextField::default();
+        type_chars(&mut field, "text");
+        for &ignored in &['\u{1b}', '\t', '\u{f702}', '\u{0}'] {
+            assert_eq!(field.handle_char(ignored), TextEdit::Unchanged);
+        }
+        assert_eq!(field.handle_key(VirtualKeyCode::A), TextEdit::Unchanged);
+        assert_eq!(
+            field.handle_key(VirtualKeyCode::Escape),
+            TextEdit::Cancelled
+        );
+        assert_field(&field, "text", 4);
+    }
+
+    #[test]
+    fn enter_hands_back_the_text_and_empties_the_field() {
+        let mut field = TextField::default();
+        type_chars(&mut field, "café 한本🎨");
+        assert_eq!(
+            field.handle_char('\r'),
+            TextEdit::Submitted("café 한本🎨".to_owned())
+        );
+        assert_field(&field, "", 0);
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+
+/// The live values which can appear in a window title.
+pub struct TitleValues<'a> {
+    pub app: &'a str,
+    pub fps: f32,
+
+    /// The average frame time in milliseconds.
+    pub frame_time: f32,
+
+    /// The average estimated input to photon latency in milliseconds.
+    pub latency: Option<f32>,
+
+    pub gpu: &'a str,
+    pub extent: [u32; 2],
+
+    /// The fraction of the window's resolution the scene is drawn at, and
+    /// the resulting size.
+    pub render_scale: f32,
+    pub scene_extent: [u32; 2],
+
+    /// Seconds since the application started.
+    pub time: f32,
+
+    pub seed: Option<u64>,
+}
+
+/// A window title with `{name}` placeholders for live values.
+///
+/// Numeric placeholders accept a precision like `{fps:.0}`. `{{` and `}}`
+/// are literal braces. Unknown placeholders are rendered exactly as they were
+/// written so a typo is visible in the title, and each one is warned about
+/// once.
+pub struct TitleTemplate {
+    segments: Vec<Segment>,
+    warned: HashSet<String>,
+}
+
+enum Segment {
+    Literal(String),
+    Placeholder {
+        name: String,
+        precision: Option<usize>,
+
+        // the placeholder as written, used when it can't be rendered
+        source: String,
+    },
+}
+
+impl TitleTemplate {
+    pub fn parse(template: &str) -> Self {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    let mut closed = false;
+                    for c in &mut chars {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        body.push(c);
+                    }
+                    if !closed {
+                        // an unterminated placeholder is just text
+                        literal.push('{');
+                        literal.push_str(&body);
+                        continue;
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(literal.split_off(0)));
+                    }
+                    segments.push(placeholder(&body));
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Self {
+            segments,
+            warned: HashSet::new(),
+        }
+    }
+
+    /// Build the title from the current values.
+    pub fn render(&mut self, values: &TitleValues) -> String {
+        let mut title = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => title.push_str(text),
+                Segment::Placeholder {
+                    name,
+                    precision,
+                    source,
+                } => match format_value(name, *precision, values) {
+                    Some(value) => title.push_str(&value),
+                    None => {
+                        if self.warned.insert(source.clone()) {
+                            log::warn!(
+                                "unknown title placeholder {:?}",
+                                source
+                            );
+                        }
+                        title.push_str(source);
+                    }
+                },
+            }
+        }
+        title
+    }
+}
+
+/// Split a placeholder body like `fps:.1` into its name and precision.
+fn placeholder(body: &str) -> Segment {
+    let source = format!("{{{}}}", body);
+    let (name, spec) = match body.find(':') {
+        Some(split) => (&body[..split], Some(&body[split + 1..])),
+        None => (body, None),
+    };
+    let precision = match spec {
+        None => None,
+        Some(spec) => match spec
+            .strip_prefix('.')
+            .and_then(|digits| digits.parse().ok())
+        {
+            Some(precision) => Some(precision),
+            None => {
+                // a malformed spec can never render, keep the text
+                return Segment::Placeholder {
+                    name: String::new(),
+                    precision: None,
+                    source,
+                };
+            }
+        },
+    };
+    Segment::Placeholder {
+        name: name.trim().to_owned(),
+        precision,
+        source,
+    }
+}
+
+/// Format a single named value, or `None` when the name is unknown.
+fn format_value(
+    name: &str,
+    precision: Option<usize>,
+    values: &TitleValues,
+) -> Option<String> {
+    let number = |value: f32, default: usize| {
+        format!("{:.*}", precision.unwrap_or(default), value)
+    };
+    let value = match name {
+        "app" => values.app.to_owned(),
+        "fps" => number(values.fps, 1),
+        "frame_time" => number(values.frame_time, 2),
+        "latency" => match values.latency {
+            Some(latency) => number(latency, 1),
+            None => "-".to_owned(),
+        },
+        "gpu" => values.gpu.to_owned(),
+        "w" => values.extent[0].to_string(),
+        "h" => values.extent[1].to_string(),
+        "scale" => number(values.render_scale, 2),
+        "rw" => values.scene_extent[0].to_string(),
+        "rh" => values.scene_extent[1].to_string(),
+        "time" => number(values.time, 1),
+        "seed" => match values.seed {
+            Some(seed) => seed.to_string(),
+            None => "-".to_owned(),
+        },
+        _ => return None,
+    };
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> TitleValues<'static> {
+        TitleValues {
+            app: "Vulkan Starter",
+            fps: 59.94,
+            frame_time: 16.683,
+            latency: None,
+            gpu: "llvmpipe",
+            extent: [1366, 768],
+            render_scale: 0.5,
+            scene_extent: [683, 384],
+            time: 12.25,
+            seed: Some(42),
+        }
+    }
+
+    fn render(template: &str) -> String {
+        TitleTemplate::parse(template).render(&values())
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_live_values() {
+        assert_eq!(
+            render("{app} — {fps} fps — {gpu} — {w}x{h} — scene {rw}x{rh}"),
+            "Vulkan Starter — 59.9 fps — llvmpipe — 1366x768 — scene 683x384"
+        );
+        assert_eq!(
+            render("{frame_time}ms {latency} {scale} {time}s seed {seed}"),
+            "16.68ms - 0.50 12.2s seed 42"
+        );
+    }
+
+    #[test]
+    fn numbers_take_a_precision() {
+        assert_eq!(
+            render("{fps:.0} {frame_time:.3} {time:.0}"),
+            "60 16.683 12"
+        );
+        assert_eq!(render("{ fps :.1}"), "59.9");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{fps}} {{{fps:.0}}}"), "{fps} {60}");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept_as_written() {
+        let mut template = TitleTemplate::parse("{fsp} {fps:x} {fps:.} {}");
+        assert_eq!(template.render(&values()), "{fsp} {fps:x} {fps:.} {}");
+
+        // each is only warned about once
+        template.render(&values());
+        assert_eq!(template.warned.len(), 4);
+    }
+
+    #[test]
+    fn unterminated_placeholders_are_text() {
+        assert_eq!(render("{app} {fps"), "Vulkan Starter {fps");
+        assert_eq!(render("fps}"), "fps}");
+        assert_eq!(render("{"), "{");
+    }
+}
@@ -0,0 +1,331 @@
+use super::cull::Mat4;
+use super::post_process::IDENTITY;
+use anyhow::{bail, Result};
+
+/// A translation, rotation, and scale, applied to a point in the reverse
+/// order: scale first, then rotation, then translation.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct Transform {
+    pub translation: [f32; 3],
+
+    /// A unit quaternion stored as `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: [0.0, 0.0, 0.0],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl Transform {
+    /// A transform which only moves points.
+    pub fn from_translation(translation: [f32; 3]) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+
+    /// A transform which only rotates points counter-clockwise around the z
+    /// axis, in radians.
+    pub fn from_rotation_z(angle: f32) -> Self {
+        let half = angle / 2.0;
+        Self {
+            rotation: [0.0, 0.0, half.sin(), half.cos()],
+            ..Self::default()
+        }
+    }
+
+    /// A transform which only scales points uniformly.
+    pub fn from_scale(scale: f32) -> Self {
+        Self {
+            scale: [scale, scale, scale],
+            ..Self::default()
+        }
+    }
+
+    /// The column-major matrix which applies this transform.
+    pub fn to_matrix(self) -> Mat4 {
+        let [x, y, z, w] = self.rotation;
+        let [sx, sy, sz] = self.scale;
+        let [tx, ty, tz] = self.translation;
+        [
+            [
+                (1.0 - 2.0 * (y * y + z * z)) * sx,
+                2.0 * (x * y + z * w) * sx,
+                2.0 * (x * z - y * w) * sx,
+                0.0,
+            ],
+            [
+                2.0 * (x * y - z * w) * sy,
+                (1.0 - 2.0 * (x * x + z * z)) * sy,
+                2.0 * (y * z + x * w) * sy,
+                0.0,
+            ],
+            [
+                2.0 * (x * z + y * w) * sz,
+                2.0 * (y * z - x * w) * sz,
+                (1.0 - 2.0 * (x * x + y * y)) * sz,
+                0.0,
+            ],
+            [tx, ty, tz, 1.0],
+        ]
+    }
+}
+
+/// Multiply two column-major matrices, the result applies `b` then `a`.
+pub fn multiply(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut result = [[0.0; 4]; 4];
+    for (result_column, b_column) in result.iter_mut().zip(b) {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    result
+}
+
+/// Identifies one transform in a `TransformHierarchy`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "bevy-ecs", derive(bevy_ecs::component::Component))]
+pub struct TransformId(usize);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Resolution {
+    Unresolved,
+    InProgress,
+    Resolved,
+}
+
+struct Node {
+    local: Transform,
+    parent: Option<TransformId>,
+}
+
+/// A set of transforms which may each be relative to a parent.
+///
+/// The world matrices are recomputed by `resolve` once per frame after the
+/// local transforms have been updated. Parents are walked with an explicit
+/// stack, so chains of any depth are fine, and a parent loop is reported as
+/// an error rather than resolved.
+#[derive(Default)]
+pub struct TransformHierarchy {
+    nodes: Vec<Node>,
+    world: Vec<Mat4>,
+}
+
+impl TransformHierarchy {
+    /// Add a transform, relative to the parent if there is one.
+    pub fn add(
+        &mut self,
+        local: Transform,
+        parent: Option<TransformId>,
+    ) -> Result<TransformId> {
+        if let Some(parent) = parent {
+            self.check(parent)?;
+        }
+        self.nodes.push(Node { local, parent });
+        self.world.push(IDENTITY);
+        Ok(TransformId(self.nodes.len() - 1))
+    }
+
+    /// Replace a transform's local transform.
+    pub fn set_local(
+        &mut self,
+        id: TransformId,
+        local: Transform,
+    ) -> Result<()> {
+        self.check(id)?;
+        self.nodes[id.0].local = local;
+        Ok(())
+    }
+
+    /// Move a transform under a new parent, or to the root. Loops are only
+    /// detected when the hierarchy is next resolved.
+    pub fn set_parent(
+        &mut self,
+        id: TransformId,
+        parent: Option<TransformId>,
+    ) -> Result<()> {
+        self.check(id)?;
+        if let Some(parent) = parent {
+            self.check(parent)?;
+        }
+        self.nodes[id.0].parent = parent;
+        Ok(())
+    }
+
+    /// The world matrix computed by the last call to `resolve`.
+    pub fn world(&self, id: TransformId) -> Result<Mat4> {
+        self.check(id)?;
+        Ok(self.world[id.0])
+    }
+
+    /// Compute every transform's world matrix from its local transform and
+    /// its parent's world matrix.
+    pub fn resolve(&mut self) -> Result<()> {
+        let mut state = vec![Resolution::Unresolved; self.nodes.len()];
+        let mut chain = vec![];
+        for start in 0..self.nodes.len() {
+            // walk up until reaching a root or a resolved ancestor
+            let mut current = Some(start);
+            while let Some(index) = current {
+                match state[index] {
+                    Resolution::Resolved => break,
+                    Resolution::InProgress => bail!(
+                        "transform {} is its own ancestor, the hierarchy \
+                        has a cycle",
+                        index
+                    ),
+                    Resolution::Unresolved => {
+                        state[index] = Resolution::InProgress;
+                        chain.push(index);
+                        current = self.nodes[index].parent.map(|id| id.0);
+                    }
+                }
+            }
+
+            // then resolve back down, each parent is ready before its child
+            while let Some(index) = chain.pop() {
+                let node = &self.nodes[index];
+                let local = node.local.to_matrix();
+                self.world[index] = match node.parent {
+                    Some(parent) => multiply(&self.world[parent.0], &local),
+                    None => local,
+                };
+                state[index] = Resolution::Resolved;
+            }
+        }
+        Ok(())
+    }
+
+    fn check(&self, id: TransformId) -> Result<()> {
+        if id.0 >= self.nodes.len() {
+            bail!(
+                "transform {} is not in the hierarchy of {} transforms",
+                id.0,
+                self.nodes.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    /// The number of transforms in the deep chain, far deeper than a
+    /// recursive resolve could handle.
+    const CHAIN_LENGTH: usize = 100_000;
+
+    /// Fail unless every element is within a small tolerance.
+    fn assert_close(actual: Mat4, expected: Mat4) {
+        let close = actual
+            .iter()
+            .flatten()
+            .zip(expected.iter().flatten())
+            .all(|(a, b)| (a - b).abs() < 1e-4);
+        assert!(close, "{:?} should be {:?}", actual, expected);
+    }
+
+    #[test]
+    fn translation() {
+        assert_close(
+            Transform::from_translation([1.0, 2.0, 3.0]).to_matrix(),
+            [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [1.0, 2.0, 3.0, 1.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn quarter_turn() {
+        assert_close(
+            Transform::from_rotation_z(PI / 2.0).to_matrix(),
+            [
+                [0.0, 1.0, 0.0, 0.0],
+                [-1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        );
+    }
+
+    #[test]
+    fn children_are_placed_relative_to_their_parents() -> Result<()> {
+        // the sun is turned a quarter, the planet is 2 along the sun's x
+        // axis, and the half-size moon is 1 along the planet's y axis
+        let mut transforms = TransformHierarchy::default();
+        let sun = transforms.add(Transform::from_rotation_z(PI / 2.0), None)?;
+        let planet = transforms
+            .add(Transform::from_translation([2.0, 0.0, 0.0]), Some(sun))?;
+        let moon = transforms.add(
+            Transform {
+                translation: [0.0, 1.0, 0.0],
+                scale: [0.5, 0.5, 0.5],
+                ..Transform::default()
+            },
+            Some(planet),
+        )?;
+        transforms.resolve()?;
+        assert_close(
+            transforms.world(planet)?,
+            [
+                [0.0, 1.0, 0.0, 0.0],
+                [-1.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 2.0, 0.0, 1.0],
+            ],
+        );
+        assert_close(
+            transforms.world(moon)?,
+            [
+                [0.0, 0.5, 0.0, 0.0],
+                [-0.5, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.5, 0.0],
+                [-1.0, 2.0, 0.0, 1.0],
+            ],
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn deep_chains_resolve_and_loops_are_reported() -> Result<()> {
+        // each link moves one unit along x, the first transform added is
+        // the deepest leaf
+        let mut chain = TransformHierarchy::default();
+        let links = (0..CHAIN_LENGTH)
+            .map(|_| {
+                chain.add(Transform::from_translation([1.0, 0.0, 0.0]), None)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for pair in links.windows(2) {
+            chain.set_parent(pair[0], Some(pair[1]))?;
+        }
+        chain.resolve()?;
+        assert_close(
+            chain.world(links[0])?,
+            Transform::from_translation([CHAIN_LENGTH as f32, 0.0, 0.0])
+                .to_matrix(),
+        );
+
+        chain.set_parent(links[CHAIN_LENGTH - 1], Some(links[0]))?;
+        assert!(
+            chain.resolve().is_err(),
+            "a looping hierarchy resolved without an error"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use super::device::{describe_memory_type, memory_type_for, MemoryUse};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use vulkano::device::Device;
+use vulkano::memory::pool::{
+    AllocFromRequirementsFilter, AllocLayout, MappingRequirement, MemoryPool,
+    MemoryPoolAlloc, PotentialDedicatedAllocation, StdMemoryPoolAlloc,
+};
+use vulkano::memory::{
+    DedicatedAlloc, DeviceMemory, MappedDeviceMemory, MemoryRequirements,
+};
+
+mod pooled;
+
+pub use pooled::{BlockRanges, PooledAllocator};
+
+/// Memory bound to one resource, handed back to its allocator when it's
+/// dropped.
+pub type MemoryAllocation = Box<dyn MemoryPoolAlloc + Send + Sync>;
+
+/// Where the memory for buffers and images comes from.
+///
+/// A resource is created unbound, asks for memory which meets its
+/// requirements, and is bound to the allocation's memory at its offset. The
+/// allocation must outlive the resource.
+pub trait Allocator: Send + Sync {
+    /// The name used on the command line and in the resource overlay.
+    fn name(&self) -> &'static str;
+
+    /// Memory for a resource with `requirements`. Buffers and linear images
+    /// are `AllocLayout::Linear`, optimal images `AllocLayout::Optimal`,
+    /// which keeps them apart by the device's buffer-image granularity.
+    fn allocate(
+        &self,
+        requirements: &MemoryRequirements,
+        layout: AllocLayout,
+        usage: MemoryUse,
+    ) -> Result<MemoryAllocation>;
+
+    /// What's held in each memory type with anything allocated, in order
+    /// of type index.
+    fn stats(&self) -> Vec<MemoryTypeStats>;
+}
+
+/// Which allocator a display hands out memory with.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AllocatorKind {
+    /// vulkano's standard pool, which is what every resource built from
+    /// vulkano's own buffer and image types uses.
+    #[default]
+    PassThrough,
+
+    /// Sub-allocate from large blocks per memory type, see
+    /// `PooledAllocator`.
+    Pooled,
+}
+
+impl AllocatorKind {
+    /// Parse the name used on the command line: `pass-through` or `pooled`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pass-through" => Some(AllocatorKind::PassThrough),
+            "pooled" => Some(AllocatorKind::Pooled),
+            _ => None,
+        }
+    }
+
+    pub fn create(self, device: &Arc<Device>) -> Arc<dyn Allocator> {
+        match self {
+            AllocatorKind::PassThrough => {
+                Arc::new(PassThroughAllocator::new(device))
+            }
+            AllocatorKind::Pooled => Arc::new(PooledAllocator::new(device)),
+        }
+    }
+}
+
+/// What an allocator holds in one memory type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryTypeStats {
+    pub type_index: u32,
+
+    /// Allocations made from the driver.
+    pub blocks: usize,
+
+    /// Allocations handed to resources.
+    pub allocations: usize,
+
+    /// Bytes allocated from the driver.
+    pub allocated: u64,
+
+    /// Bytes handed to resources.
+    pub used: u64,
+
+    /// The largest range free in any one block.
+    pub largest_free: u64,
+}
+
+impl MemoryTypeStats {
+    pub fn free(&self) -> u64 {
+        self.allocated.saturating_sub(self.used)
+    }
+
+    /// How much of the free memory is outside the largest free range, from
+    /// 0 when it's all one range towards 1 when it's scattered in slivers.
+    pub fn fragmentation(&self) -> f32 {
+        match self.free() {
+            0 => 0.0,
+            free => 1.0 - self.largest_free as f32 / free as f32,
+        }
+    }
+}
+
+// the allocations alive in each memory type and their bytes
+type LiveAllocations = Arc<Mutex<HashMap<u32, (usize, u64)>>>;
+
+/// Hands every request to vulkano's standard pool, as the resources built
+/// from vulkano's types are.
+///
+/// vulkano's pool doesn't say how its blocks are used, so the stats only
+/// count what resources hold: nothing is free and there are no blocks.
+pub struct PassThroughAllocator {
+    device: Arc<Device>,
+    live: LiveAllocations,
+}
+
+impl PassThroughAllocator {
+    pub fn new(device: &Arc<Device>) -> Self {
+        Self {
+            device: device.clone(),
+            live: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl Allocator for PassThroughAllocator {
+    fn name(&self) -> &'static str {
+        "pass-through"
+    }
+
+    fn allocate(
+        &self,
+        requirements: &MemoryRequirements,
+        layout: AllocLayout,
+        usage: MemoryUse,
+    ) -> Result<MemoryAllocation> {
+        let type_index = memory_type_for(
+            &self.device.physical_device(),
+            usage,
+            requirements.memory_type_bits,
+        )
+        .with_context(|| {
+            format!(
+                "no memory type is {:?} among the types {:#b}",
+                usage, requirements.memory_type_bits
+            )
+        })?;
+        let map = match usage {
+            MemoryUse::HostVisible => MappingRequirement::Map,
+            MemoryUse::DeviceLocal => MappingRequirement::DoNotMap,
+        };
+        let inner = Device::standard_pool(&self.device)
+            .alloc_from_requirements(
+                requirements,
+                layout,
+                map,
+                DedicatedAlloc::None,
+                |memory_type| {
+                    if memory_type.id() == type_index {
+                        AllocFromRequirementsFilter::Preferred
+                    } else {
+                        AllocFromRequirementsFilter::Forbidden
+                    }
+                },
+            )
+            .with_context(|| {
+                format!(
+                    "unable to allocate {} bytes from memory type {}",
+                    requirements.size, type_index
+                )
+            })?;
+        let size = requirements.size as u64;
+        log::debug!(
+            "{} bytes of {:?} memory allocated from memory type {}",
+            size,
+            usage,
+            describe_memory_type(&self.device.physical_device(), type_index)
+        );
+        {
+            let mut live = self.live.lock().unwrap();
+            let (count, bytes) = live.entry(type_index).or_default();
+            *count += 1;
+            *bytes += size;
+        }
+        Ok(Box::new(PassThroughAllocation {
+            inner,
+            type_index,
+            size,
+            live: self.live.clone(),
+        }))
+    }
+
+    fn stats(&self) -> Vec<MemoryTypeStats> {
+        let live = self.live.lock().unwrap();
+        let mut stats: Vec<MemoryTypeStats> = live
+            .iter()
+            .filter(|(_, (count, _))| *count > 0)
+            .map(|(&type_index, &(allocations, bytes))| MemoryTypeStats {
+                type_index,
+                allocations,
+                allocated: bytes,
+                used: bytes,
+                ..MemoryTypeStats::default()
+            })
+            .collect();
+        stats.sort_by_key(|stats| stats.type_index);
+        stats
+    }
+}
+
+struct PassThroughAllocation {
+    inner: PotentialDedicatedAllocation<StdMemoryPoolAlloc>,
+    type_index: u32,
+    size: u64,
+    live: LiveAllocations,
+}
+
+// safe because every call is forwarded to vulkano's own allocation
+unsafe impl MemoryPoolAlloc for PassThroughAllocation {
+    fn mapped_memory(&self) -> Option<&MappedDeviceMemory> {
+        self.inner.mapped_memory()
+    }
+
+    fn memory(&self) -> &DeviceMemory {
+        self.inner.memory()
+    }
+
+    fn offset(&self) -> usize {
+        self.inner.offset()
+    }
+}
+
+impl Drop for PassThroughAllocation {
+    fn drop(&mut self) {
+        let mut live = self.live.lock().unwrap();
+        if let Some((count, bytes)) = live.get_mut(&self.type_index) {
+            *count -= 1;
+            *bytes -= self.size;
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use super::{Allocator, MemoryAllocation, MemoryTypeStats};
+use crate::display::device::{
+    describe_memory_type, memory_type_for, MemoryUse,
+};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use vulkano::device::Device;
+use vulkano::memory::pool::{AllocLayout, MemoryPoolAlloc};
+use vulkano::memory::{DeviceMemory, MappedDeviceMemory, MemoryRequirements};
+
+/// The size of each block allocated from the driver, unless the heap is
+/// too small for a few of them.
+const BLOCK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A block is at most this fraction of its heap.
+const HEAP_FRACTION: u64 = 8;
+
+/// Resources larger than this fraction of a block get a block of their own,
+/// so one of them can't strand the rest of a block.
+const LARGE_FRACTION: u64 = 2;
+
+/// Sub-allocates resources from large blocks of device memory, one set of
+/// blocks per memory type.
+///
+/// Each resource gets a range of a block at its required alignment. Linear
+/// resources, like buffers, and optimal images which share a page of the
+/// device's buffer-image granularity would alias each other on some
+/// hardware, so neighbours of different layouts are moved onto separate
+/// pages. Host visible blocks are mapped once when they're allocated.
+///
+/// Every allocation holds its block, so a block's memory can't be freed
+/// while anything is bound to it. A block is only dropped from the pool
+/// once it's empty, and one empty block per memory type is kept so a
+/// resource which comes and goes doesn't allocate a block each time.
+pub struct PooledAllocator {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    device: Arc<Device>,
+    granularity: u64,
+
+    // every block with something in it, and the spare empty ones, by
+    // memory type
+    blocks: Mutex<HashMap<u32, Vec<Arc<Block>>>>,
+}
+
+struct Block {
+    memory: BlockMemory,
+    type_index: u32,
+    size: u64,
+
+    // set for a large resource's block, which nothing else shares
+    own: bool,
+    ranges: Mutex<BlockRanges>,
+}
+
+enum BlockMemory {
+    Unmapped(DeviceMemory),
+    Mapped(MappedDeviceMemory),
+}
+
+impl PooledAllocator {
+    pub fn new(device: &Arc<Device>) -> Self {
+        let granularity =
+            device.physical_device().limits().buffer_image_granularity() as u64;
+        Self {
+            shared: Arc::new(Shared {
+                device: device.clone(),
+                granularity: granularity.max(1),
+                blocks: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// The size of the blocks allocated for a memory type.
+    fn block_size(&self, type_index: u32) -> u64 {
+        let physical_device = self.shared.device.physical_device();
+        match physical_device.memory_type_by_id(type_index) {
+            Some(memory_type) => {
+                BLOCK_SIZE.min(memory_type.heap().size() as u64 / HEAP_FRACTION)
+            }
+            None => BLOCK_SIZE,
+        }
+    }
+}
+
+impl Allocator for PooledAllocator {
+    fn name(&self) -> &'static str {
+        "pooled"
+    }
+
+    fn allocate(
+        &self,
+        requirements: &MemoryRequirements,
+        layout: AllocLayout,
+        usage: MemoryUse,
+    ) -> Result<MemoryAllocation> {
+        let shared = &self.shared;
+        let type_index = memory_type_for(
+            &shared.device.physical_device(),
+            usage,
+            requirements.memory_type_bits,
+        )
+        .with_context(|| {
+            format!(
+                "no memory type is {:?} among the types {:#b}",
+                usage, requirements.memory_type_bits
+            )
+        })?;
+        let size = requirements.size as u64;
+        let alignment = requirements.alignment as u64;
+        log::debug!(
+            "allocating {} bytes of {:?} memory from memory type {}",
+            size,
+            usage,
+            describe_memory_type(&shared.device.physical_device(), type_index)
+        );
+        let block_size = self.block_size(type_index);
+        let own =
+            requirements.prefer_dedicated || size > block_size / LARGE_FRACTION;
+
+        let mut blocks = shared.blocks.lock().unwrap();
+        let blocks = blocks.entry(type_index).or_default();
+        if !own {
+            for block in blocks.iter().filter(|block| !block.own) {
+                let offset = block.ranges.lock().unwrap().allocate(
+                    size,
+                    alignment,
+                    layout,
+                    shared.granularity,
+                );
+                if let Some(offset) = offset {
+                    return Ok(shared.allocation(block, offset));
+                }
+            }
+        }
+
+        let block = Arc::new(shared.allocate_block(
+            type_index,
+            if own { size } else { block_size },
+            own,
+        )?);
+        let offset = block
+            .ranges
+            .lock()
+            .unwrap()
+            .allocate(size, alignment, layout, shared.granularity)
+            .with_context(|| {
+                format!("{} bytes don't fit in a new block", size)
+            })?;
+        blocks.push(block.clone());
+        Ok(shared.allocation(&block, offset))
+    }
+
+    fn stats(&self) -> Vec<MemoryTypeStats> {
+        let blocks = self.shared.blocks.lock().unwrap();
+        let mut stats: Vec<MemoryTypeStats> = blocks
+            .iter()
+            .filter(|(_, blocks)| !blocks.is_empty())
+            .map(|(&type_index, blocks)| {
+                let mut stats = MemoryTypeStats {
+                    type_index,
+                    blocks: blocks.len(),
+                    ..MemoryTypeStats::default()
+                };
+                for block in blocks {
+                    let ranges = block.ranges.lock().unwrap();
+                    stats.allocations += ranges.len();
+                    stats.allocated += block.size;
+                    stats.used += ranges.used();
+                    stats.largest_free =
+                        stats.largest_free.max(ranges.largest_free());
+                }
+                stats
+            })
+            .collect();
+        stats.sort_by_key(|stats| stats.type_index);
+        stats
+    }
+}
+
+impl Shared {
+    fn allocate_block(
+        &self,
+        type_index: u32,
+        size: u64,
+        own: bool,
+    ) -> Result<Block> {
+        let physical_device = self.device.physical_device();
+        let memory_type = physical_device
+            .memory_type_by_id(type_index)
+            .with_context(|| format!("no memory type {}", type_index))?;
+        let memory = if memory_type.is_host_visible() {
+            BlockMemory::Mapped(
+                DeviceMemory::alloc_and_map(
+                    self.device.clone(),
+                    memory_type,
+                    size as usize,
+                )
+                .with_context(|| {
+                    format!(
+                        "unable to allocate a {} byte mapped block from \
+                        memory type {}",
+                        size, type_index
+                    )
+                })?,
+            )
+        } else {
+            BlockMemory::Unmapped(
+                DeviceMemory::alloc(
+                    self.device.clone(),
+                    memory_type,
+                    size as usize,
+                )
+                .with_context(|| {
+                    format!(
+                        "unable to allocate a {} byte block from memory \
+                        type {}",
+                        size, type_index
+                    )
+                })?,
+            )
+        };
+        log::debug!(
+            "allocated a {} byte block from memory type {}",
+            size,
+            type_index
+        );
+        Ok(Block {
+            memory,
+            type_index,
+            size,
+            own,
+            ranges: Mutex::new(BlockRanges::new(size)),
+        })
+    }
+
+    fn allocation(
+        self: &Arc<Self>,
+        block: &Arc<Block>,
+        offset: u64,
+    ) -> MemoryAllocation {
+        Box::new(PooledAllocation {
+            shared: self.clone(),
+            block: block.clone(),
+            offset,
+        })
+    }
+
+    /// Give a range back to its block, dropping the block from the pool
+    /// once it's empty unless it's the only spare for its memory type.
+    fn free(&self, block: &Arc<Block>, offset: u64) {
+        let mut blocks = self.blocks.lock().unwrap();
+        let empty = {
+            let mut ranges = block.ranges.lock().unwrap();
+            ranges.free(offset);
+            ranges.is_empty()
+        };
+        if !empty {
+            return;
+        }
+        let blocks = match blocks.get_mut(&block.type_index) {
+            Some(blocks) => blocks,
+            None => return,
+        };
+        let spare = blocks.iter().any(|other| {
+            !Arc::ptr_eq(other, block)
+                && !other.own
+                && other.ranges.lock().unwrap().is_empty()
+        });
+        if block.own || spare {
+            blocks.retain(|other| !Arc::ptr_eq(other, block));
+        }
+    }
+}
+
+struct PooledAllocation {
+    shared: Arc<Shared>,
+    block: Arc<Block>,
+    offset: u64,
+}
+
+// safe because the range at the offset is only handed out once and the
+// block's memory lives as long as the allocation
+unsafe impl MemoryPoolAlloc for PooledAllocation {
+    fn mapped_memory(&self) -> Option<&MappedDeviceMemory> {
+        match &self.block.memory {
+            BlockMemory::Mapped(memory) => Some(memory),
+            BlockMemory::Unmapped(_) => None,
+        }
+    }
+
+    fn memory(&self) -> &DeviceMemory {
+        match &self.block.memory {
+            BlockMemory::Mapped(memory) => memory.as_ref(),
+            BlockMemory::Unmapped(memory) => memory,
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.offset as usize
+    }
+}
+
+impl Drop for PooledAllocation {
+    fn drop(&mut self) {
+        self.shared.free(&self.block, self.offset);
+    }
+}
+
+/// The ranges in use in one block, in order of offset.
+///
+/// This is only bookkeeping, so the placement rules can be checked without
+/// a device.
+#[derive(Debug, Clone)]
+pub struct BlockRanges {
+    size: u64,
+    used: Vec<UsedRange>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct UsedRange {
+    offset: u64,
+    size: u64,
+    layout: AllocLayout,
+}
+
+impl UsedRange {
+    fn end(&self) -> u64 {
+        self.offset + self.size
+    }
+}
+
+impl BlockRanges {
+    pub fn new(size: u64) -> Self {
+        Self { size, used: vec![] }
+    }
+
+    /// Place `size` bytes in the first gap which fits them at `alignment`,
+    /// on a different page of `granularity` bytes than any neighbour of the
+    /// other layout. Returns the offset, or nothing when no gap fits.
+    pub fn allocate(
+        &mut self,
+        size: u64,
+        alignment: u64,
+        layout: AllocLayout,
+        granularity: u64,
+    ) -> Option<u64> {
+        let alignment = alignment.max(1);
+        let granularity = granularity.max(1);
+        for i in 0..=self.used.len() {
+            let previous = i.checked_sub(1).map(|i| self.used[i]);
+            let next = self.used.get(i).copied();
+            let gap_end = next.map_or(self.size, |next| next.offset);
+
+            let mut offset = align_up(
+                previous.map_or(0, |previous| previous.end()),
+                alignment,
+            );
+            if let Some(previous) = previous {
+                if previous.layout != layout
+                    && same_page(previous.end() - 1, offset, granularity)
+                {
+                    offset = align_up(offset, granularity.max(alignment));
+                }
+            }
+            let end = offset + size;
+            if end > gap_end {
+                continue;
+            }
+            if let Some(next) = next {
+                if next.layout != layout
+                    && same_page(end - 1, next.offset, granularity)
+                {
+                    continue;
+                }
+            }
+            self.used.insert(
+                i,
+                UsedRange {
+                    offset,
+                    size,
+                    layout,
+                },
+            );
+            return Some(offset);
+        }
+        None
+    }
+
+    /// Free the range starting at `offset`, false when none does.
+    pub fn free(&mut self, offset: u64) -> bool {
+        match self
+            .used
+            .binary_search_by_key(&offset, |range| range.offset)
+        {
+            Ok(i) => {
+                self.used.remove(i);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// How many ranges are in use.
+    pub fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    /// The bytes in use, not counting the gaps alignment leaves.
+    pub fn used(&self) -> u64 {
+        self.used.iter().map(|range| range.size).sum()
+    }
+
+    /// The largest gap between ranges, or at either end.
+    pub fn largest_free(&self) -> u64 {
+        let mut largest = 0;
+        let mut start = 0;
+        for range in &self.used {
+            largest = largest.max(range.offset - start);
+            start = range.end();
+        }
+        largest.max(self.size - start)
+    }
+}
+
+fn align_up(offset: u64, alignment: u64) -> u64 {
+    offset.div_ceil(alignment) * alignment
+}
+
+fn same_page(a: u64, b: u64, granularity: u64) -> bool {
+    a / granularity == b / granularity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINEAR: AllocLayout = AllocLayout::Linear;
+    const OPTIMAL: AllocLayout = AllocLayout::Optimal;
+
+    #[test]
+    fn ranges_go_in_the_first_gap_which_fits() {
+        let mut ranges = BlockRanges::new(4096);
+        assert_eq!(ranges.allocate(100, 16, LINEAR, 1), Some(0));
+        assert_eq!(ranges.allocate(100, 256, LINEAR, 1), Some(256));
+        assert_eq!(ranges.allocate(50, 4, LINEAR, 1), Some(100));
+
+        // an image after a buffer starts on the next page
+        assert_eq!(ranges.allocate(64, 16, OPTIMAL, 1024), Some(1024));
+        assert_eq!(ranges.allocate(4096, 1, LINEAR, 1), None);
+        assert_eq!(ranges.len(), 4);
+        assert_eq!(ranges.used(), 314);
+        assert_eq!(ranges.largest_free(), 4096 - 1088);
+    }
+
+    #[test]
+    fn linear_and_optimal_ranges_never_share_a_page() {
+        // a buffer can't go on the image's page, before or after it
+        let mut ranges = BlockRanges::new(4096);
+        assert_eq!(ranges.allocate(64, 16, OPTIMAL, 1024), Some(0));
+        assert_eq!(ranges.allocate(64, 16, LINEAR, 1024), Some(1024));
+        assert!(ranges.free(0));
+        assert!(!ranges.free(0), "a range was freed twice");
+        assert_eq!(ranges.allocate(64, 16, LINEAR, 1024), Some(0));
+        assert_eq!(ranges.allocate(64, 16, OPTIMAL, 1024), Some(2048));
+
+        ranges.free(0);
+        ranges.free(1024);
+        ranges.free(2048);
+        assert!(ranges.is_empty());
+        assert_eq!(ranges.largest_free(), 4096);
+    }
+}
@@ -0,0 +1,155 @@
+use vulkano::swapchain::FullscreenExclusive;
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window};
+
+/// How the window behaves with screen capture, screen sharing, and
+/// streaming software.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CapturePolicy {
+    /// Whatever the platform does by default. Fullscreen switches the
+    /// monitor's video mode.
+    #[default]
+    Default,
+
+    /// Keep the window's contents out of screenshots, recordings, and
+    /// screen sharing, for prototypes which shouldn't leave the machine.
+    /// Windows 10 2004 and later leave the window out of captures, older
+    /// versions show it as black, and macOS stops sharing it with capture
+    /// apis which respect the window's sharing type. Other platforms keep
+    /// capturing it.
+    Protected,
+
+    /// Never take the display away from capture software like OBS: the
+    /// swapchain doesn't allow exclusive fullscreen and fullscreen is a
+    /// borderless window over the monitor.
+    StreamingFriendly,
+}
+
+impl CapturePolicy {
+    /// Parse the name used on the command line: `default`, `protected`, or
+    /// `streaming`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(CapturePolicy::Default),
+            "protected" => Some(CapturePolicy::Protected),
+            "streaming" => Some(CapturePolicy::StreamingFriendly),
+            _ => None,
+        }
+    }
+
+    /// Whether the swapchain may take exclusive control of the display.
+    pub fn fullscreen_exclusive(&self) -> FullscreenExclusive {
+        match self {
+            CapturePolicy::StreamingFriendly => FullscreenExclusive::Disallowed,
+            _ => FullscreenExclusive::AppControlled,
+        }
+    }
+
+    /// How the window covers `monitor` when it goes fullscreen.
+    ///
+    /// Borderless when streaming, otherwise the fastest video mode at the
+    /// monitor's current resolution, or borderless when there is none.
+    pub fn fullscreen(&self, monitor: Option<MonitorHandle>) -> Fullscreen {
+        if *self == CapturePolicy::StreamingFriendly {
+            return Fullscreen::Borderless(monitor);
+        }
+        let video_mode = monitor.as_ref().and_then(|monitor| {
+            let size = monitor.size();
+            monitor
+                .video_modes()
+                .filter(|mode| mode.size() == size)
+                .max_by_key(|mode| mode.refresh_rate())
+        });
+        match video_mode {
+            Some(video_mode) => Fullscreen::Exclusive(video_mode),
+            None => Fullscreen::Borderless(monitor),
+        }
+    }
+
+    /// Apply the policy to a newly created window and log what was done.
+    pub fn apply(&self, window: &Window) {
+        match self {
+            CapturePolicy::Default => (),
+            CapturePolicy::Protected => match exclude_from_capture(window) {
+                Some(applied) => log::info!(
+                    "the window is excluded from screen capture with {}",
+                    applied
+                ),
+                None => log::warn!(
+                    "excluding the window from screen capture isn't \
+                    supported on this platform, it can still be captured"
+                ),
+            },
+            CapturePolicy::StreamingFriendly => log::info!(
+                "streaming friendly: exclusive fullscreen is disallowed and \
+                fullscreen is borderless"
+            ),
+        }
+    }
+}
+
+/// Ask the window system to leave the window out of captures, returning
+/// what was applied.
+#[cfg(target_os = "windows")]
+fn exclude_from_capture(window: &Window) -> Option<&'static str> {
+    use std::os::raw::c_void;
+    use winit::platform::windows::WindowExtWindows;
+
+    const WDA_MONITOR: u32 = 0x01;
+    const WDA_EXCLUDEFROMCAPTURE: u32 = 0x11;
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SetWindowDisplayAffinity(hwnd: *mut c_void, affinity: u32) -> i32;
+    }
+
+    let hwnd = window.hwnd();
+    // WDA_EXCLUDEFROMCAPTURE fails before windows 10 2004
+    unsafe {
+        if SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE) != 0 {
+            Some("WDA_EXCLUDEFROMCAPTURE")
+        } else if SetWindowDisplayAffinity(hwnd, WDA_MONITOR) != 0 {
+            Some("WDA_MONITOR")
+        } else {
+            None
+        }
+    }
+}
+
+/// Ask the window system to leave the window out of captures, returning
+/// what was applied.
+#[cfg(target_os = "macos")]
+fn exclude_from_capture(window: &Window) -> Option<&'static str> {
+    use std::os::raw::{c_char, c_void};
+    use winit::platform::macos::WindowExtMacOS;
+
+    const NS_WINDOW_SHARING_NONE: usize = 0;
+
+    #[link(name = "objc")]
+    extern "C" {
+        fn sel_registerName(name: *const c_char) -> *mut c_void;
+        fn objc_msgSend();
+    }
+
+    let ns_window = window.ns_window();
+    if ns_window.is_null() {
+        return None;
+    }
+    // objc_msgSend must be called through the method's own signature
+    unsafe {
+        let set_sharing_type: unsafe extern "C" fn(
+            *mut c_void,
+            *mut c_void,
+            usize,
+        ) = std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let selector =
+            sel_registerName(b"setSharingType:\0".as_ptr() as *const c_char);
+        set_sharing_type(ns_window, selector, NS_WINDOW_SHARING_NONE);
+    }
+    Some("NSWindowSharingNone")
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+fn exclude_from_capture(_window: &Window) -> Option<&'static str> {
+    None
+}
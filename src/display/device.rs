@@ -1,19 +1,79 @@
+use super::instance::required_extensions_for_platform;
 use anyhow::{Context, Result};
+use std::ffi::CString;
 use std::sync::Arc;
-use vulkano::device::{Device, DeviceExtensions, Features, Queue};
+use vulkano::device::{
+    Device, DeviceExtensions, Features, Queue, RawDeviceExtensions,
+};
 use vulkano::instance::{Instance, PhysicalDevice};
-use vulkano::swapchain::Surface;
+use vulkano::swapchain::{
+    AcquireError, CapabilitiesError, Surface, SwapchainCreationError,
+};
+use vulkano::sync::{FenceWaitError, FlushError};
 use winit::window::Window;
 
+mod memory_budget;
+mod memory_type;
 mod queue_family_indices;
+#[cfg(feature = "device-report")]
+mod report;
+#[cfg(feature = "swiftshader-fallback")]
+mod swiftshader;
 
-use queue_family_indices::QueueFamilyIndices;
+pub use memory_budget::{memory_budget, HeapBudget};
+pub use memory_type::{describe_memory_type, memory_type_for, MemoryUse};
+pub use queue_family_indices::QueueFamilyIndices;
+#[cfg(feature = "device-report")]
+pub use report::{
+    check_baseline, check_required_limits, generate_device_report,
+    REQUIRED_LIMITS,
+};
+#[cfg(feature = "swiftshader-fallback")]
+pub use swiftshader::use_swiftshader;
 
-/// Create a logical device and command queues
+/// Whether the physical device is a gpu or a cpu implementation of vulkan.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DeviceBackend {
+    Hardware,
+
+    /// SwiftShader, which is picked when no hardware device is suitable so
+    /// rendering still works on machines without a gpu, like CI runners.
+    SwiftShader,
+}
+
+impl DeviceBackend {
+    /// Tell the backends apart by the device's name, swiftshader's devices
+    /// are all named "SwiftShader Device (...)".
+    pub fn of(physical_device: &PhysicalDevice) -> Self {
+        if physical_device.name().starts_with("SwiftShader") {
+            DeviceBackend::SwiftShader
+        } else {
+            DeviceBackend::Hardware
+        }
+    }
+}
+
+/// The device with its graphics, present, and compute queues, then every
+/// queue it was created with.
+type DeviceQueues = (
+    Arc<Device>,
+    Arc<Queue>,
+    Arc<Queue>,
+    Arc<Queue>,
+    Vec<Arc<Queue>>,
+);
+
+/// Create a logical device and command queues.
+///
+/// Without a surface the swapchain extension is not enabled and the present
+/// queue is the graphics queue. The compute queue is the graphics queue
+/// unless the device has a separate compute family. A queue is created in
+/// every family which can present, so presentation can move to another
+/// family later.
 pub fn create_logical_device(
-    surface: &Arc<Surface<Window>>,
+    surface: Option<&Arc<Surface<Arc<Window>>>>,
     physical_device: &PhysicalDevice,
-) -> Result<(Arc<Device>, Arc<Queue>, Arc<Queue>)> {
+) -> Result<DeviceQueues> {
     let indices = QueueFamilyIndices::find(surface, &physical_device)?;
     let unique_indices = indices.unique_indices();
 
@@ -24,20 +84,57 @@ pub fn create_logical_device(
 
     let (device, queues) = Device::new(
         *physical_device,
-        &required_device_features(),
-        &required_device_extensions(),
+        &enabled_device_features(physical_device),
+        enabled_device_extensions(physical_device, surface.is_some()),
         families,
     )
     .context("unable to build logical device")?;
 
-    let (graphics_queue, present_queue) = indices.take_queues(queues)?;
+    let queues: Vec<Arc<Queue>> = queues.collect();
+    let (graphics_queue, present_queue, compute_queue) =
+        indices.take_queues(&queues)?;
+    let compute_queue = compute_queue.unwrap_or_else(|| graphics_queue.clone());
+
+    Ok((device, graphics_queue, present_queue, compute_queue, queues))
+}
+
+/// True when the error, or anything which caused it, says the device was
+/// lost. Nothing built on a lost device can be used again, it has to be
+/// recreated from scratch.
+pub fn is_device_lost(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(cause.downcast_ref(), Some(FlushError::DeviceLost))
+            || matches!(cause.downcast_ref(), Some(AcquireError::DeviceLost))
+            || matches!(
+                cause.downcast_ref(),
+                Some(FenceWaitError::DeviceLostError)
+            )
+    })
+}
 
-    Ok((device, graphics_queue, present_queue))
+/// True when the error, or anything which caused it, says the window's
+/// surface can't be presented to again. Windows drivers report a lost
+/// surface, or a window still in use by a swapchain they've forgotten,
+/// after they're updated or reset underneath a running application. The
+/// device may still work, but the window needs a new surface.
+pub fn is_surface_lost(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref(),
+            Some(SwapchainCreationError::SurfaceLost)
+                | Some(SwapchainCreationError::NativeWindowInUse)
+        ) || matches!(
+            cause.downcast_ref(),
+            Some(CapabilitiesError::SurfaceLost)
+        ) || matches!(cause.downcast_ref(), Some(AcquireError::SurfaceLost))
+            || matches!(cause.downcast_ref(), Some(FlushError::SurfaceLost))
+    })
 }
 
-/// Take the first suitable physical device
+/// Take the first suitable physical device. Without a surface, devices are
+/// not required to support presentation.
 pub fn pick_physical_device<'a>(
-    surface: &Arc<Surface<Window>>,
+    surface: Option<&Arc<Surface<Arc<Window>>>>,
     instance: &'a Arc<Instance>,
 ) -> Result<PhysicalDevice<'a>> {
     let devices: Vec<PhysicalDevice> =
@@ -51,14 +148,25 @@ pub fn pick_physical_device<'a>(
 
     devices
         .iter()
-        .find(|device| is_device_suitable(&surface, &device))
+        .find(|device| is_device_suitable(surface, &device))
         .cloned()
         .context("unable to pick a suitable physical device")
 }
 
+/// True when the instance has a physical device which suits the
+/// application's needs.
+#[cfg(feature = "swiftshader-fallback")]
+pub fn has_suitable_device(
+    surface: Option<&Arc<Surface<Arc<Window>>>>,
+    instance: &Arc<Instance>,
+) -> bool {
+    PhysicalDevice::enumerate(&instance)
+        .any(|device| is_device_suitable(surface, &device))
+}
+
 /// Find a device which suits the application's needs
 fn is_device_suitable(
-    surface: &Arc<Surface<Window>>,
+    surface: Option<&Arc<Surface<Arc<Window>>>>,
     device: &PhysicalDevice,
 ) -> bool {
     let queue_supported = QueueFamilyIndices::find(surface, device)
@@ -73,15 +181,18 @@ fn is_device_suitable(
             },
             |_indices| true,
         );
-    let extensions_supported = check_device_extension_support(&device);
-    let swap_chain_adequate = if extensions_supported {
-        let capabilities = surface
-            .capabilities(*device)
-            .expect("unable to get surface capabilities");
-        !capabilities.supported_formats.is_empty()
-            && capabilities.present_modes.iter().next().is_some()
-    } else {
-        false
+    let extensions_supported =
+        check_device_extension_support(&device, surface.is_some());
+    let swap_chain_adequate = match surface {
+        Some(surface) if extensions_supported => {
+            let capabilities = surface
+                .capabilities(*device)
+                .expect("unable to get surface capabilities");
+            !capabilities.supported_formats.is_empty()
+                && capabilities.present_modes.iter().next().is_some()
+        }
+        Some(_) => false,
+        None => true,
     };
     let features_supported = check_device_feature_support(&device);
 
@@ -92,21 +203,54 @@ fn is_device_suitable(
 }
 
 /// Check that the device supports all of the required extensions
-fn check_device_extension_support(device: &PhysicalDevice) -> bool {
-    let extensions = DeviceExtensions::supported_by_device(*device);
-    extensions
-        .intersection(&required_device_extensions())
-        .khr_swapchain
+fn check_device_extension_support(
+    device: &PhysicalDevice,
+    windowed: bool,
+) -> bool {
+    let required = required_device_extensions(windowed);
+    DeviceExtensions::supported_by_device(*device).intersection(&required)
+        == required
+}
+
+/// Check whether the device can render multiple views in a single pass
+pub fn supports_multiview(device: &PhysicalDevice) -> bool {
+    DeviceExtensions::supported_by_device(*device).khr_multiview
+}
+
+/// True when the device supports the extension, looked up by name for the
+/// extensions vulkano doesn't know about yet.
+fn supports_extension(device: &PhysicalDevice, name: &str) -> bool {
+    RawDeviceExtensions::supported_by_device(*device)
+        .iter()
+        .any(|supported| supported.to_bytes() == name.as_bytes())
 }
 
-/// Yield the set of required device extensions
-fn required_device_extensions() -> DeviceExtensions {
+/// Yield the set of required device extensions, presenting to a window
+/// requires the swapchain extension.
+fn required_device_extensions(windowed: bool) -> DeviceExtensions {
     DeviceExtensions {
-        khr_swapchain: true,
+        khr_swapchain: windowed,
         ..DeviceExtensions::none()
     }
 }
 
+/// Yield the required extensions plus the ones this platform needs enabled
+/// whenever they're supported, like `VK_KHR_portability_subset` on
+/// MoltenVK's devices.
+fn enabled_device_extensions(
+    device: &PhysicalDevice,
+    windowed: bool,
+) -> RawDeviceExtensions {
+    let mut extensions =
+        RawDeviceExtensions::from(&required_device_extensions(windowed));
+    for &name in required_extensions_for_platform() {
+        if supports_extension(device, name) {
+            extensions.insert(CString::new(name).unwrap());
+        }
+    }
+    extensions
+}
+
 /// Check that the device supports all of the required features
 fn check_device_feature_support(device: &PhysicalDevice) -> bool {
     device
@@ -122,3 +266,20 @@ fn required_device_features() -> Features {
         ..Features::none()
     }
 }
+
+/// Yield the required features plus the optional features which the device
+/// supports.
+///
+/// Compressed texture formats can only be used when their feature is
+/// enabled, textures fall back to decompressing on the cpu otherwise. The
+/// wireframe debug view needs `fill_mode_non_solid` and is skipped without
+/// it.
+fn enabled_device_features(physical_device: &PhysicalDevice) -> Features {
+    let supported = physical_device.supported_features();
+    Features {
+        texture_compression_bc: supported.texture_compression_bc,
+        texture_compression_astc_ldr: supported.texture_compression_astc_ldr,
+        fill_mode_non_solid: supported.fill_mode_non_solid,
+        ..required_device_features()
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use std::ffi::{c_void, CStr};
+use vulkano::device::RawDeviceExtensions;
+use vulkano::instance::{loader, PhysicalDevice};
+use vulkano::VulkanObject;
+
+const MEMORY_BUDGET_EXTENSION: &[u8] = b"VK_EXT_memory_budget";
+
+// structure types from the vulkan registry, vk-sys predates the extension
+const STRUCTURE_TYPE_MEMORY_PROPERTIES_2: u32 = 1_000_059_006;
+const STRUCTURE_TYPE_MEMORY_BUDGET_PROPERTIES: u32 = 1_000_237_000;
+
+/// VK_MAX_MEMORY_HEAPS
+const MAX_MEMORY_HEAPS: usize = 16;
+
+#[repr(C)]
+struct MemoryProperties2 {
+    s_type: u32,
+    p_next: *mut c_void,
+    memory_properties: vk_sys::PhysicalDeviceMemoryProperties,
+}
+
+#[repr(C)]
+struct MemoryBudgetProperties {
+    s_type: u32,
+    p_next: *mut c_void,
+    heap_budget: [u64; MAX_MEMORY_HEAPS],
+    heap_usage: [u64; MAX_MEMORY_HEAPS],
+}
+
+type GetMemoryProperties2 = extern "system" fn(
+    physical_device: vk_sys::PhysicalDevice,
+    properties: *mut MemoryProperties2,
+);
+
+/// How much of a memory heap this process uses, and how much it can use
+/// before allocations start to fail or slow down, in bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HeapBudget {
+    pub usage: u64,
+    pub budget: u64,
+}
+
+/// Ask the driver how much of each memory heap is in use, in the order
+/// vulkano lists the heaps.
+///
+/// Unlike counting allocations, this includes memory the driver allocates
+/// on the application's behalf. Nothing is returned when the device doesn't
+/// support `VK_EXT_memory_budget` or the instance wasn't created with
+/// `khr_get_physical_device_properties2`.
+pub fn memory_budget(
+    physical_device: &PhysicalDevice,
+) -> Result<Option<Vec<HeapBudget>>> {
+    let instance = physical_device.instance();
+    let supported = RawDeviceExtensions::supported_by_device(*physical_device)
+        .iter()
+        .any(|name| name.to_bytes() == MEMORY_BUDGET_EXTENSION);
+    if !supported
+        || !instance
+            .loaded_extensions()
+            .khr_get_physical_device_properties2
+    {
+        return Ok(None);
+    }
+
+    let name =
+        CStr::from_bytes_with_nul(b"vkGetPhysicalDeviceMemoryProperties2KHR\0")
+            .unwrap();
+    let address = loader::auto_loader()
+        .context("unable to load vulkan")?
+        .get_instance_proc_addr(instance.internal_object(), name.as_ptr())
+        as *const c_void;
+    if address.is_null() {
+        return Ok(None);
+    }
+    // safe because the extension which provides the function is enabled
+    // and the signature matches the registry
+    let get_memory_properties: GetMemoryProperties2 =
+        unsafe { std::mem::transmute(address) };
+
+    let mut budget = MemoryBudgetProperties {
+        s_type: STRUCTURE_TYPE_MEMORY_BUDGET_PROPERTIES,
+        p_next: std::ptr::null_mut(),
+        heap_budget: [0; MAX_MEMORY_HEAPS],
+        heap_usage: [0; MAX_MEMORY_HEAPS],
+    };
+    let mut properties = MemoryProperties2 {
+        s_type: STRUCTURE_TYPE_MEMORY_PROPERTIES_2,
+        p_next: &mut budget as *mut MemoryBudgetProperties as *mut c_void,
+        memory_properties: unsafe { std::mem::zeroed() },
+    };
+    get_memory_properties(physical_device.internal_object(), &mut properties);
+
+    let heaps = physical_device.memory_heaps().count();
+    Ok(Some(
+        (0..heaps.min(MAX_MEMORY_HEAPS))
+            .map(|heap| HeapBudget {
+                usage: budget.heap_usage[heap],
+                budget: budget.heap_budget[heap],
+            })
+            .collect(),
+    ))
+}
@@ -0,0 +1,78 @@
+use vulkano::instance::{MemoryType, PhysicalDevice};
+
+/// How a resource's memory is used, which decides the memory type vulkano
+/// allocates it from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MemoryUse {
+    /// Written by the cpu, like a `CpuAccessibleBuffer`. The memory must be
+    /// host visible, and host cached memory is preferred.
+    HostVisible,
+
+    /// Only touched by the gpu, like images and immutable buffers. Device
+    /// local memory is preferred.
+    DeviceLocal,
+}
+
+/// The memory type vulkano's standard pool picks for a resource used this
+/// way, among the types whose bits are set in `type_bits`, a resource's
+/// `memory_type_bits`: the first preferred type, or the first allowed one
+/// when no type is preferred.
+pub fn memory_type_for(
+    physical_device: &PhysicalDevice,
+    usage: MemoryUse,
+    type_bits: u32,
+) -> Option<u32> {
+    let is_allowed = |memory_type: &MemoryType| {
+        let in_bits = type_bits & (1 << memory_type.id()) != 0;
+        in_bits
+            && match usage {
+                MemoryUse::HostVisible => memory_type.is_host_visible(),
+                MemoryUse::DeviceLocal => !memory_type.is_lazily_allocated(),
+            }
+    };
+    let is_preferred = |memory_type: &MemoryType| match usage {
+        MemoryUse::HostVisible => memory_type.is_host_cached(),
+        MemoryUse::DeviceLocal => memory_type.is_device_local(),
+    };
+    let allowed: Vec<MemoryType> =
+        physical_device.memory_types().filter(is_allowed).collect();
+    allowed
+        .iter()
+        .find(|memory_type| is_preferred(*memory_type))
+        .or_else(|| allowed.first())
+        .map(|memory_type| memory_type.id())
+}
+
+/// A memory type's index, heap, and property flags, like
+/// `1 on heap 0 (DEVICE_LOCAL)`.
+pub fn describe_memory_type(
+    physical_device: &PhysicalDevice,
+    type_index: u32,
+) -> String {
+    let memory_type = match physical_device.memory_type_by_id(type_index) {
+        Some(memory_type) => memory_type,
+        None => return format!("{} (no such memory type)", type_index),
+    };
+    let flags: Vec<&str> = [
+        (memory_type.is_device_local(), "DEVICE_LOCAL"),
+        (memory_type.is_host_visible(), "HOST_VISIBLE"),
+        (memory_type.is_host_coherent(), "HOST_COHERENT"),
+        (memory_type.is_host_cached(), "HOST_CACHED"),
+        (memory_type.is_lazily_allocated(), "LAZILY_ALLOCATED"),
+    ]
+    .iter()
+    .filter(|(set, _)| *set)
+    .map(|(_, name)| *name)
+    .collect();
+    let flags = if flags.is_empty() {
+        "no flags".to_owned()
+    } else {
+        flags.join(" | ")
+    };
+    format!(
+        "{} on heap {} ({})",
+        type_index,
+        memory_type.heap().id(),
+        flags
+    )
+}
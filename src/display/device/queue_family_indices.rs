@@ -1,77 +1,119 @@
 use anyhow::{Context, Result};
 use std::sync::Arc;
-use vulkano::device::{Queue, QueuesIter};
+use vulkano::device::Queue;
 use vulkano::instance::PhysicalDevice;
 use vulkano::swapchain::Surface;
 use winit::window::Window;
 
+/// The graphics, present, and optional compute queues.
+type Queues = (Arc<Queue>, Arc<Queue>, Option<Arc<Queue>>);
+
 pub struct QueueFamilyIndices {
     graphics_family: usize,
     present_family: usize,
+
+    // every family which can present, so presentation can move to another
+    // family without recreating the device
+    present_families: Vec<usize>,
+
+    // a family which supports compute but not graphics, if there is one
+    compute_family: Option<usize>,
 }
 
 impl QueueFamilyIndices {
-    /// Find the queue family indices for the given device
+    /// Find the queue family indices for the given device.
+    ///
+    /// Without a surface there is nothing to present to, so the graphics
+    /// family doubles as the present family. A compute family is only
+    /// picked when it is separate from graphics, otherwise compute work
+    /// shares the graphics queue.
     pub fn find(
-        surface: &Arc<Surface<Window>>,
+        surface: Option<&Arc<Surface<Arc<Window>>>>,
         device: &PhysicalDevice,
     ) -> Result<Self> {
         let mut graphics = None;
         let mut present = None;
+        let mut present_families = vec![];
 
         for (i, family) in device.queue_families().enumerate() {
-            if family.supports_graphics() {
+            // keep the first graphics and present families found together,
+            // but look at every family for others which can present
+            let found = graphics.is_some() && present.is_some();
+            if family.supports_graphics() && !found {
                 graphics = Some(i);
             }
 
-            if surface.is_supported(family)? {
-                present = Some(i);
-            }
-            if graphics.is_some() && present.is_some() {
-                break;
+            match surface {
+                Some(surface) => {
+                    if surface.is_supported(family)? {
+                        present_families.push(i);
+                        if !found {
+                            present = Some(i);
+                        }
+                    }
+                }
+                None if !found => present = graphics,
+                None => (),
             }
         }
 
+        let compute_family = device.queue_families().position(|family| {
+            family.supports_compute() && !family.supports_graphics()
+        });
+
         graphics
             .zip(present)
             .map(|(graphics_family, present_family)| Self {
                 graphics_family,
                 present_family,
+                present_families,
+                compute_family,
             })
             .context("unable to find all required queue families for this physical device")
     }
 
+    /// The family picked to present with.
+    pub fn present_family(&self) -> usize {
+        self.present_family
+    }
+
     /// Return the set of unique queue family indices
     pub fn unique_indices(&self) -> Vec<usize> {
-        if self.is_same_queue() {
-            vec![self.graphics_family]
-        } else {
-            vec![self.graphics_family, self.present_family]
+        let mut indices = vec![self.graphics_family];
+        let others = std::iter::once(self.present_family)
+            .chain(self.present_families.iter().copied())
+            .chain(self.compute_family);
+        for index in others {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
         }
+        indices
     }
 
-    /// get the graphics and present queues based on the index order returned
-    /// by unique_indices
-    pub fn take_queues(
-        &self,
-        mut queues: QueuesIter,
-    ) -> Result<(Arc<Queue>, Arc<Queue>)> {
-        let graphics_queue = queues
-            .next()
-            .context("could not find a graphics queue for this device")?;
-
-        let present_queue = if self.is_same_queue() {
-            graphics_queue.clone()
-        } else {
-            queues.next().context(
-                "could not find a presentation queue for this device",
-            )?
+    /// Get the graphics, present, and compute queues from the queues created
+    /// for `unique_indices`. There is one queue per family, so queues are
+    /// shared when families are.
+    pub fn take_queues(&self, queues: &[Arc<Queue>]) -> Result<Queues> {
+        let find = |index: usize| {
+            queues
+                .iter()
+                .find(|queue| queue.family().id() as usize == index)
+                .cloned()
         };
 
-        Ok((graphics_queue, present_queue))
-    }
+        let graphics_queue = find(self.graphics_family)
+            .context("could not find a graphics queue for this device")?;
+        let present_queue = find(self.present_family)
+            .context("could not find a presentation queue for this device")?;
+        let compute_queue =
+            match self.compute_family {
+                Some(index) => Some(find(index).context(
+                    "could not find a compute queue for this device",
+                )?),
+                None => None,
+            };
 
-    fn is_same_queue(&self) -> bool {
-        self.graphics_family == self.present_family
+        Ok((graphics_queue, present_queue, compute_queue))
     }
 }
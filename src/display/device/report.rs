@@ -0,0 +1,386 @@
+use anyhow::{bail, Result};
+use assert_json_diff::{assert_json_matches_no_panic, CompareMode, Config};
+use serde_json::{json, Map, Value};
+use vulkano::instance::PhysicalDevice;
+
+/// The limits this crate relies on and the smallest value of each it can
+/// run with.
+pub const REQUIRED_LIMITS: [(&str, u64); 4] = [
+    ("max_push_constants_size", 128),
+    ("max_bound_descriptor_sets", 2),
+    ("max_compute_work_group_invocations", 64),
+    ("max_image_dimension_2d", 4096),
+];
+
+/// Build a json object from the named fields of a value.
+macro_rules! json_fields {
+    ($source:expr, [$($name:ident),* $(,)?]) => {{
+        let mut object = Map::new();
+        $(object.insert(stringify!($name).to_owned(), json!($source.$name));)*
+        Value::Object(object)
+    }};
+}
+
+/// Build a json object from the results of the named methods of a value.
+macro_rules! json_methods {
+    ($source:expr, [$($name:ident),* $(,)?]) => {{
+        let mut object = Map::new();
+        $(object.insert(stringify!($name).to_owned(), json!($source.$name()));)*
+        Value::Object(object)
+    }};
+}
+
+/// Describe everything vulkano knows about a physical device as json.
+///
+/// The properties, every feature, every limit, the memory heaps and types,
+/// and the queue families are each an object or list, named the way vulkano
+/// names them.
+pub fn generate_device_report(physical_device: &PhysicalDevice) -> Value {
+    let api_version = physical_device.api_version();
+    let uuid: String = physical_device
+        .uuid()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let properties = json!({
+        "device_name": physical_device.name(),
+        "device_type": format!("{:?}", physical_device.ty()),
+        "api_version": format!(
+            "{}.{}.{}",
+            api_version.major, api_version.minor, api_version.patch
+        ),
+        "driver_version": physical_device.driver_version(),
+        "vendor_id": physical_device.pci_vendor_id(),
+        "device_id": physical_device.pci_device_id(),
+        "pipeline_cache_uuid": uuid,
+        "subgroup_size":
+            physical_device.extended_properties().subgroup_size(),
+    });
+    let memory_heaps: Vec<Value> = physical_device
+        .memory_heaps()
+        .map(|heap| {
+            json!({
+                "id": heap.id(),
+                "size": heap.size(),
+                "device_local": heap.is_device_local(),
+            })
+        })
+        .collect();
+    let memory_types: Vec<Value> = physical_device
+        .memory_types()
+        .map(|memory_type| {
+            json!({
+                "id": memory_type.id(),
+                "heap": memory_type.heap().id(),
+                "device_local": memory_type.is_device_local(),
+                "host_visible": memory_type.is_host_visible(),
+                "host_coherent": memory_type.is_host_coherent(),
+                "host_cached": memory_type.is_host_cached(),
+                "lazily_allocated": memory_type.is_lazily_allocated(),
+            })
+        })
+        .collect();
+    let queue_families: Vec<Value> = physical_device
+        .queue_families()
+        .map(|family| {
+            json!({
+                "id": family.id(),
+                "queue_count": family.queues_count(),
+                "graphics": family.supports_graphics(),
+                "compute": family.supports_compute(),
+                "transfer": family.explicitly_supports_transfers(),
+                "sparse_binding": family.supports_sparse_binding(),
+                "timestamp_valid_bits": family.timestamp_valid_bits(),
+                "min_image_transfer_granularity":
+                    family.min_image_transfer_granularity(),
+            })
+        })
+        .collect();
+    json!({
+        "properties": properties,
+        "features": features(physical_device),
+        "limits": limits(physical_device),
+        "memory_heaps": memory_heaps,
+        "memory_types": memory_types,
+        "queue_families": queue_families,
+    })
+}
+
+/// Check a report has at least every required limit.
+pub fn check_required_limits(report: &Value) -> Result<()> {
+    let mut missing = vec![];
+    for &(name, required) in REQUIRED_LIMITS.iter() {
+        match report["limits"][name].as_u64() {
+            Some(limit) if limit >= required => (),
+            limit => missing.push(format!(
+                "{} is {:?}, at least {} is required",
+                name, limit, required
+            )),
+        }
+    }
+    if !missing.is_empty() {
+        bail!("the device is missing limits:\n{}", missing.join("\n"));
+    }
+    Ok(())
+}
+
+/// Check a report has every value in a baseline. Anything the baseline
+/// leaves out can be different.
+pub fn check_baseline(report: &Value, baseline: &Value) -> Result<()> {
+    let config = Config::new(CompareMode::Inclusive);
+    if let Err(differences) =
+        assert_json_matches_no_panic(report, baseline, config)
+    {
+        bail!("the device differs from the baseline:\n{}", differences);
+    }
+    Ok(())
+}
+
+fn features(physical_device: &PhysicalDevice) -> Value {
+    let features = physical_device.supported_features();
+    json_fields!(
+        features,
+        [
+            robust_buffer_access,
+            full_draw_index_uint32,
+            image_cube_array,
+            independent_blend,
+            geometry_shader,
+            tessellation_shader,
+            sample_rate_shading,
+            dual_src_blend,
+            logic_op,
+            multi_draw_indirect,
+            draw_indirect_first_instance,
+            depth_clamp,
+            depth_bias_clamp,
+            fill_mode_non_solid,
+            depth_bounds,
+            wide_lines,
+            large_points,
+            alpha_to_one,
+            multi_viewport,
+            sampler_anisotropy,
+            texture_compression_etc2,
+            texture_compression_astc_ldr,
+            texture_compression_bc,
+            occlusion_query_precise,
+            pipeline_statistics_query,
+            vertex_pipeline_stores_and_atomics,
+            fragment_stores_and_atomics,
+            shader_tessellation_and_geometry_point_size,
+            shader_image_gather_extended,
+            shader_storage_image_extended_formats,
+            shader_storage_image_multisample,
+            shader_storage_image_read_without_format,
+            shader_storage_image_write_without_format,
+            shader_uniform_buffer_array_dynamic_indexing,
+            shader_sampled_image_array_dynamic_indexing,
+            shader_storage_buffer_array_dynamic_indexing,
+            shader_storage_image_array_dynamic_indexing,
+            shader_clip_distance,
+            shader_cull_distance,
+            shader_float64,
+            shader_int64,
+            shader_int16,
+            shader_resource_residency,
+            shader_resource_min_lod,
+            sparse_binding,
+            sparse_residency_buffer,
+            sparse_residency_image2d,
+            sparse_residency_image3d,
+            sparse_residency2_samples,
+            sparse_residency4_samples,
+            sparse_residency8_samples,
+            sparse_residency16_samples,
+            sparse_residency_aliased,
+            variable_multisample_rate,
+            inherited_queries,
+            buffer_device_address,
+            buffer_device_address_capture_replay,
+            buffer_device_address_multi_device,
+            variable_pointers_storage_buffer,
+            variable_pointers,
+            shader_buffer_int64_atomics,
+            shader_shared_int64_atomics,
+            storage_buffer_8bit,
+            storage_uniform_8bit,
+            storage_push_constant_8bit,
+            storage_buffer_16bit,
+            storage_uniform_16bit,
+            storage_push_constant_16bit,
+            storage_input_output_16bit,
+            shader_float16,
+            shader_int8,
+        ]
+    )
+}
+
+fn limits(physical_device: &PhysicalDevice) -> Value {
+    let limits = physical_device.limits();
+    json_methods!(
+        limits,
+        [
+            max_image_dimension_1d,
+            max_image_dimension_2d,
+            max_image_dimension_3d,
+            max_image_dimension_cube,
+            max_image_array_layers,
+            max_texel_buffer_elements,
+            max_uniform_buffer_range,
+            max_storage_buffer_range,
+            max_push_constants_size,
+            max_memory_allocation_count,
+            max_sampler_allocation_count,
+            buffer_image_granularity,
+            sparse_address_space_size,
+            max_bound_descriptor_sets,
+            max_per_stage_descriptor_samplers,
+            max_per_stage_descriptor_uniform_buffers,
+            max_per_stage_descriptor_storage_buffers,
+            max_per_stage_descriptor_sampled_images,
+            max_per_stage_descriptor_storage_images,
+            max_per_stage_descriptor_input_attachments,
+            max_per_stage_resources,
+            max_descriptor_set_samplers,
+            max_descriptor_set_uniform_buffers,
+            max_descriptor_set_uniform_buffers_dynamic,
+            max_descriptor_set_storage_buffers,
+            max_descriptor_set_storage_buffers_dynamic,
+            max_descriptor_set_sampled_images,
+            max_descriptor_set_storage_images,
+            max_descriptor_set_input_attachments,
+            max_vertex_input_attributes,
+            max_vertex_input_bindings,
+            max_vertex_input_attribute_offset,
+            max_vertex_input_binding_stride,
+            max_vertex_output_components,
+            max_tessellation_generation_level,
+            max_tessellation_patch_size,
+            max_tessellation_control_per_vertex_input_components,
+            max_tessellation_control_per_vertex_output_components,
+            max_tessellation_control_per_patch_output_components,
+            max_tessellation_control_total_output_components,
+            max_tessellation_evaluation_input_components,
+            max_tessellation_evaluation_output_components,
+            max_geometry_shader_invocations,
+            max_geometry_input_components,
+            max_geometry_output_components,
+            max_geometry_output_vertices,
+            max_geometry_total_output_components,
+            max_fragment_input_components,
+            max_fragment_output_attachments,
+            max_fragment_dual_src_attachments,
+            max_fragment_combined_output_resources,
+            max_compute_shared_memory_size,
+            max_compute_work_group_count,
+            max_compute_work_group_invocations,
+            max_compute_work_group_size,
+            sub_pixel_precision_bits,
+            sub_texel_precision_bits,
+            mipmap_precision_bits,
+            max_draw_indexed_index_value,
+            max_draw_indirect_count,
+            max_sampler_lod_bias,
+            max_sampler_anisotropy,
+            max_viewports,
+            max_viewport_dimensions,
+            viewport_bounds_range,
+            viewport_sub_pixel_bits,
+            min_memory_map_alignment,
+            min_texel_buffer_offset_alignment,
+            min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment,
+            min_texel_offset,
+            max_texel_offset,
+            min_texel_gather_offset,
+            max_texel_gather_offset,
+            min_interpolation_offset,
+            max_interpolation_offset,
+            sub_pixel_interpolation_offset_bits,
+            max_framebuffer_width,
+            max_framebuffer_height,
+            max_framebuffer_layers,
+            framebuffer_color_sample_counts,
+            framebuffer_depth_sample_counts,
+            framebuffer_stencil_sample_counts,
+            framebuffer_no_attachments_sample_counts,
+            max_color_attachments,
+            sampled_image_color_sample_counts,
+            sampled_image_integer_sample_counts,
+            sampled_image_depth_sample_counts,
+            sampled_image_stencil_sample_counts,
+            storage_image_sample_counts,
+            max_sample_mask_words,
+            timestamp_compute_and_graphics,
+            timestamp_period,
+            max_clip_distances,
+            max_cull_distances,
+            max_combined_clip_and_cull_distances,
+            discrete_queue_priorities,
+            point_size_range,
+            line_width_range,
+            point_size_granularity,
+            line_width_granularity,
+            strict_lines,
+            standard_sample_locations,
+            optimal_buffer_copy_offset_alignment,
+            optimal_buffer_copy_row_pitch_alignment,
+            non_coherent_atom_size,
+        ]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A saved report with the required limits and the baseline's values.
+    fn report() -> Value {
+        json!({
+            "properties": { "device_name": "saved" },
+            "features": { "large_points": true, "wide_lines": false },
+            "limits": {
+                "max_push_constants_size": 256,
+                "max_bound_descriptor_sets": 8,
+                "max_compute_work_group_invocations": 1024,
+                "max_image_dimension_2d": 16384,
+            },
+        })
+    }
+
+    fn baseline() -> Value {
+        let path =
+            concat!(env!("CARGO_MANIFEST_DIR"), "/ci/device_baseline.json");
+        let json = std::fs::read_to_string(path).expect("no baseline");
+        serde_json::from_str(&json).expect("the baseline isn't json")
+    }
+
+    #[test]
+    fn saved_reports_are_checked_without_a_device() -> Result<()> {
+        let report = report();
+        check_required_limits(&report)?;
+        check_baseline(&report, &baseline())
+    }
+
+    #[test]
+    fn small_and_missing_limits_are_reported() {
+        let mut report = report();
+        report["limits"]["max_push_constants_size"] = json!(64);
+        report["limits"]
+            .as_object_mut()
+            .unwrap()
+            .remove("max_image_dimension_2d");
+        let error = check_required_limits(&report).unwrap_err().to_string();
+        assert!(error.contains("max_push_constants_size is Some(64)"));
+        assert!(error.contains("max_image_dimension_2d is None"));
+        assert!(!error.contains("max_bound_descriptor_sets"));
+    }
+
+    #[test]
+    fn values_other_than_the_baselines_are_reported() {
+        let mut report = report();
+        report["features"]["large_points"] = json!(false);
+        assert!(check_baseline(&report, &baseline()).is_err());
+    }
+}
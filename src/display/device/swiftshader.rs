@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+#[cfg(windows)]
+const LIBRARY: &str = "vk_swiftshader.dll";
+#[cfg(not(windows))]
+const LIBRARY: &str = "libvk_swiftshader.so";
+
+/// The manifest swiftshader's build puts next to the library.
+const MANIFEST: &str = "vk_swiftshader_icd.json";
+
+/// Point the vulkan loader at swiftshader's cpu implementation.
+///
+/// The loader only reads `VK_ICD_FILENAMES` when an instance is created, so
+/// instances created before this still list the hardware devices. The
+/// library is looked for in `SWIFTSHADER_PATH`, next to the executable, and
+/// in the system library directories. When it has no manifest next to it one
+/// is written to the temp directory.
+pub fn use_swiftshader() -> Result<PathBuf> {
+    let library = search_directories()
+        .into_iter()
+        .map(|directory| directory.join(LIBRARY))
+        .find(|library| library.is_file())
+        .with_context(|| {
+            format!(
+                "no hardware device is suitable and {} wasn't found, set \
+                SWIFTSHADER_PATH to the directory which contains it",
+                LIBRARY
+            )
+        })?;
+    let manifest = library.with_file_name(MANIFEST);
+    let manifest = if manifest.is_file() {
+        manifest
+    } else {
+        write_manifest(&library)?
+    };
+    std::env::set_var("VK_ICD_FILENAMES", &manifest);
+    log::warn!("falling back to swiftshader with {:?}", manifest);
+    Ok(manifest)
+}
+
+fn search_directories() -> Vec<PathBuf> {
+    let mut directories = vec![];
+    if let Some(path) = std::env::var_os("SWIFTSHADER_PATH") {
+        directories.push(PathBuf::from(path));
+    }
+    if let Some(directory) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned))
+    {
+        directories.push(directory);
+    }
+    if cfg!(not(windows)) {
+        directories.extend(
+            [
+                "/usr/local/lib",
+                "/usr/lib",
+                "/usr/lib/x86_64-linux-gnu",
+                "/usr/lib/aarch64-linux-gnu",
+            ]
+            .iter()
+            .map(PathBuf::from),
+        );
+    }
+    directories
+}
+
+/// Write an icd manifest which loads `library`.
+fn write_manifest(library: &Path) -> Result<PathBuf> {
+    let library = library
+        .canonicalize()
+        .with_context(|| format!("unable to resolve {:?}", library))?;
+    let library_path = library.to_string_lossy().replace('\\', "\\\\");
+    let manifest = std::env::temp_dir().join(MANIFEST);
+    std::fs::write(
+        &manifest,
+        format!(
+            concat!(
+                r#"{{"file_format_version": "1.0.0", "ICD": "#,
+                r#"{{"library_path": "{}", "api_version": "1.0.5"}}}}"#,
+            ),
+            library_path
+        ),
+    )
+    .with_context(|| format!("unable to write {:?}", manifest))?;
+    Ok(manifest)
+}
@@ -0,0 +1,158 @@
+use super::instance;
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The least time between two failure reports, so a storm of errors
+/// doesn't write one every frame.
+pub const CAPTURE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The frame which failed to submit and what's known about it.
+struct Armed {
+    frame: u64,
+    error: String,
+    operations: Vec<String>,
+}
+
+/// Captures the frame after a failed submission in detail, since
+/// intermittent flush errors are nearly impossible to debug after the fact.
+///
+/// Arming it after an error traces every step the next frame's submission
+/// takes, with its arguments, and collects every debug callback message of
+/// any severity, whatever the log level or message filter. Once that frame
+/// is submitted, or fails too, the error, the operations, and the messages
+/// are written to `frame-failure-<frame>.txt`.
+///
+/// vulkano can't record debug labels in its command buffers, so the trace
+/// names each pass in their place.
+#[derive(Default)]
+pub struct FailureCapture {
+    armed: Option<Armed>,
+    last_report: Option<Instant>,
+}
+
+impl FailureCapture {
+    /// Capture the next frame after `error` failed to submit frame `frame`.
+    /// Returns false without arming when the last report was written less
+    /// than `CAPTURE_INTERVAL` ago, or a capture is already armed.
+    pub fn arm(&mut self, error: &anyhow::Error, frame: u64) -> bool {
+        let recent = self
+            .last_report
+            .map_or(false, |last| last.elapsed() < CAPTURE_INTERVAL);
+        if recent || self.armed.is_some() {
+            return false;
+        }
+        instance::capture_debug_messages();
+        self.armed = Some(Armed {
+            frame,
+            error: format!("{:?}", error),
+            operations: vec![],
+        });
+        true
+    }
+
+    /// True while the frame after a failure is being captured.
+    pub fn capturing(&self) -> bool {
+        self.armed.is_some()
+    }
+
+    /// Note a step of the captured frame's submission. The description is
+    /// only built while capturing.
+    pub fn trace(&mut self, operation: impl FnOnce() -> String) {
+        if let Some(armed) = &mut self.armed {
+            armed.operations.push(operation());
+        }
+    }
+
+    /// Write the report for the captured frame, with the error it failed
+    /// with if it failed too, and stop capturing. Returns where the report
+    /// was written, or nothing when no capture was armed.
+    pub fn finish(
+        &mut self,
+        error: Option<&anyhow::Error>,
+    ) -> Result<Option<PathBuf>> {
+        let armed = match self.armed.take() {
+            Some(armed) => armed,
+            None => return Ok(None),
+        };
+        let messages = instance::take_captured_debug_messages();
+        self.last_report = Some(Instant::now());
+
+        let mut report = format!(
+            "frame {} failed to submit\n{}\n\n",
+            armed.frame, armed.error
+        );
+        match error {
+            Some(error) => report
+                .push_str(&format!("the next frame failed too\n{:?}\n", error)),
+            None => report.push_str("the next frame submitted without error\n"),
+        }
+        report.push_str(&format!(
+            "\n{} operations in the next frame\n",
+            armed.operations.len()
+        ));
+        for operation in &armed.operations {
+            report.push_str(&format!("  {}\n", operation));
+        }
+        report.push_str(&format!("\n{} debug messages\n", messages.len()));
+        for message in &messages {
+            report.push_str(&format!("  {}\n", message));
+        }
+
+        let path = PathBuf::from(format!("frame-failure-{}.txt", armed.frame));
+        std::fs::write(&path, report)
+            .with_context(|| format!("unable to write {:?}", path))?;
+        Ok(Some(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    /// The frame the made up failure happens on, high enough that no real
+    /// report in the working directory is overwritten.
+    const FRAME: u64 = 9_999_999;
+
+    #[test]
+    fn nothing_is_written_without_a_capture_armed() -> Result<()> {
+        let mut capture = FailureCapture::default();
+        capture.trace(|| panic!("traced without a capture armed"));
+        assert_eq!(capture.finish(None)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn the_frame_after_a_failure_is_reported_once() -> Result<()> {
+        let mut capture = FailureCapture::default();
+        let error = anyhow!("OutOfDeviceMemory").context("unable to flush");
+        assert!(capture.arm(&error, FRAME));
+        assert!(capture.capturing());
+        capture.trace(|| "execute the scene pass (Scene)".to_owned());
+        capture.trace(|| "signal the fence and flush".to_owned());
+        let path = capture
+            .finish(None)?
+            .context("finishing the capture wrote no report")?;
+        let report = std::fs::read_to_string(&path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        std::fs::remove_file(&path)
+            .with_context(|| format!("unable to remove {:?}", path))?;
+        for expected in &[
+            "frame 9999999 failed to submit",
+            "OutOfDeviceMemory",
+            "the next frame submitted without error",
+            "2 operations in the next frame",
+            "  execute the scene pass (Scene)",
+            "  signal the fence and flush",
+            "0 debug messages",
+        ] {
+            assert!(report.contains(expected), "{:?} in\n{}", expected, report);
+        }
+
+        // a second failure within the minute isn't captured
+        assert!(!capture.capturing());
+        assert!(!capture.arm(&error, FRAME + 1));
+        Ok(())
+    }
+}
@@ -0,0 +1,444 @@
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use vulkano::sync::{FenceSignalFuture, FlushError, GpuFuture};
+
+/// How many frames can be queued on the gpu by default.
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+
+/// The most frames which can be queued on the gpu.
+pub const MAX_FRAMES_IN_FLIGHT: usize = 3;
+
+/// How long to wait for a frame before logging what's in flight. The wait
+/// carries on after that, a slow frame isn't an error.
+pub const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Waits shorter than this don't count as the cpu having to wait, they're
+/// the cost of checking a fence which already signaled.
+const WAIT_THRESHOLD: Duration = Duration::from_micros(100);
+
+/// A future shared between the frame's own fence and the next frame, which
+/// is chained after it.
+pub type FrameFuture = Arc<FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>>;
+
+/// Something which signals when a frame's work is done, a fence for the
+/// frames the display submits.
+pub trait FrameFence {
+    /// Block until the frame's work is done, or until the timeout passes.
+    fn wait(&self, timeout: Option<Duration>) -> Result<(), FlushError>;
+}
+
+impl FrameFence for FrameFuture {
+    fn wait(&self, timeout: Option<Duration>) -> Result<(), FlushError> {
+        FenceSignalFuture::wait(self, timeout)
+    }
+}
+
+/// The place in the rotation a frame was given when it began.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameSlot {
+    /// Counts from 0 up to the depth, then starts again.
+    pub index: usize,
+
+    /// How long the cpu blocked for the slot, counting a wait for it before
+    /// the frame began.
+    pub waited: Duration,
+}
+
+/// How often the cpu had to wait for the gpu to finish an old frame before
+/// it could begin a new one, a direct measure of how gpu bound the frames
+/// are.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct WaitStats {
+    pub frames: u64,
+
+    /// The frames which blocked for longer than checking a fence takes.
+    pub waited: u64,
+
+    pub total_wait: Duration,
+}
+
+impl WaitStats {
+    /// The fraction of frames which had to wait, from 0 to 1.
+    pub fn fraction_waited(&self) -> f32 {
+        if self.frames == 0 {
+            0.0
+        } else {
+            self.waited as f32 / self.frames as f32
+        }
+    }
+
+    /// The average wait of the frames which had to.
+    pub fn average_wait(&self) -> Duration {
+        if self.waited == 0 {
+            Duration::default()
+        } else {
+            self.total_wait / self.waited as u32
+        }
+    }
+
+    fn record(&mut self, waited: Duration) {
+        self.frames += 1;
+        if waited >= WAIT_THRESHOLD {
+            self.waited += 1;
+            self.total_wait += waited;
+        }
+    }
+}
+
+/// How long one frame took to reach the screen, as measured on the cpu.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FrameLatency {
+    /// The age of the newest input the frame was recorded from when its
+    /// swapchain image was acquired, nothing when no new input arrived.
+    pub input_age: Option<Duration>,
+
+    /// From acquiring the swapchain image until the frame's fence was seen
+    /// signaled, which includes the present.
+    pub acquire_to_present: Duration,
+}
+
+/// When a frame was seen to finish.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Presented {
+    pub at: Instant,
+
+    /// How long acquiring the frame's swapchain image blocked, which is time
+    /// spent waiting on the display rather than drawing.
+    pub acquire_wait: Duration,
+}
+
+impl FrameLatency {
+    /// The estimated time from input to photons.
+    pub fn total(&self) -> Duration {
+        self.input_age.unwrap_or_default() + self.acquire_to_present
+    }
+}
+
+/// A submitted frame which hasn't been seen to finish.
+struct Frame<F> {
+    future: F,
+    slot: usize,
+    ended: Instant,
+    acquire_started: Instant,
+    acquire_wait: Duration,
+    input: Option<Instant>,
+}
+
+/// The frames submitted to the gpu but not yet known to have finished,
+/// oldest first.
+///
+/// Every frame is chained after the one before it, so vulkano sees the
+/// resources they share are used in order. A new frame waits for the oldest
+/// once there are `depth` of them. A depth of one finishes each frame before
+/// the next starts, trading throughput for latency.
+///
+/// Each frame begins with `begin_frame`, which hands out the next slot in
+/// the rotation once one is free, and ends with `end_frame` and the frame's
+/// fence. Anything which waits on a fence can stand in for the display's
+/// futures, which is how the rotation is checked without a gpu.
+///
+/// Each frame has a fence of its own. vulkano 0.20 has no timeline
+/// semaphores and can't enable the `timelineSemaphore` feature, so frames
+/// can't wait on a single semaphore signaled with the frame count instead.
+pub struct FramesInFlight<F: FrameFence = FrameFuture> {
+    frames: VecDeque<Frame<F>>,
+    depth: usize,
+    latest: Option<FrameLatency>,
+    presented: Option<Presented>,
+    next_slot: usize,
+    stats: WaitStats,
+
+    // waited for a slot before the frame began, counted with its begin
+    early_wait: Duration,
+
+    // frames handed to end_frame, and the ones of those seen to finish
+    submitted: u64,
+    finished: u64,
+}
+
+impl<F: FrameFence> FramesInFlight<F> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            frames: VecDeque::with_capacity(MAX_FRAMES_IN_FLIGHT),
+            depth: depth.clamp(1, MAX_FRAMES_IN_FLIGHT),
+            latest: None,
+            presented: None,
+            next_slot: 0,
+            stats: WaitStats::default(),
+            early_wait: Duration::default(),
+            submitted: 0,
+            finished: 0,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Queue at most `depth` frames, clamped between 1 and
+    /// `MAX_FRAMES_IN_FLIGHT`. A lower depth takes effect when the next
+    /// frame waits for a slot.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth.clamp(1, MAX_FRAMES_IN_FLIGHT);
+    }
+
+    /// Block until there's room for another frame, before it begins. The
+    /// wait counts towards the frame's when it does.
+    pub fn wait_for_slot(&mut self) -> Result<()> {
+        let start = Instant::now();
+        while self.frames.len() >= self.depth {
+            self.finish_oldest()?;
+        }
+        self.early_wait += start.elapsed();
+        Ok(())
+    }
+
+    /// Block until there's room for another frame, then give it the next
+    /// slot in the rotation.
+    pub fn begin_frame(&mut self) -> Result<FrameSlot> {
+        self.wait_for_slot()?;
+        let waited = std::mem::take(&mut self.early_wait);
+        self.stats.record(waited);
+        let slot = FrameSlot {
+            index: self.next_slot % self.depth,
+            waited,
+        };
+        self.next_slot = slot.index + 1;
+        Ok(slot)
+    }
+
+    /// Block until every submitted frame has finished.
+    pub fn wait_for_all(&mut self) -> Result<()> {
+        while !self.frames.is_empty() {
+            self.finish_oldest()?;
+        }
+        Ok(())
+    }
+
+    /// Track a submitted frame until its fence signals.
+    ///
+    /// @param slot what `begin_frame` gave the frame
+    /// @param acquire_started when acquiring the frame's swapchain image
+    /// started
+    /// @param acquired when the swapchain image was acquired
+    /// @param input when the newest input the frame was recorded from arrived
+    pub fn end_frame(
+        &mut self,
+        slot: FrameSlot,
+        future: F,
+        acquire_started: Instant,
+        acquired: Instant,
+        input: Option<Instant>,
+    ) {
+        self.submitted += 1;
+        self.frames.push_back(Frame {
+            future,
+            slot: slot.index,
+            ended: Instant::now(),
+            acquire_started,
+            acquire_wait: acquired.saturating_duration_since(acquire_started),
+            input,
+        });
+    }
+
+    /// The latency of the last frame seen to finish since the last call.
+    pub fn take_latency(&mut self) -> Option<FrameLatency> {
+        self.latest.take()
+    }
+
+    /// When the last frame was seen to finish.
+    pub fn last_presented(&self) -> Option<Presented> {
+        self.presented
+    }
+
+    /// How often beginning a frame had to wait, since the start.
+    pub fn wait_stats(&self) -> WaitStats {
+        self.stats
+    }
+
+    /// How many frames are submitted and not yet seen to finish.
+    pub fn in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// How many frames were submitted since the start, which is also the
+    /// number of the frame being recorded, counting from 0.
+    pub fn submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    /// How many frames were seen to finish since the start. Frames finish
+    /// in order, so everything frame `n` used is free once this is more
+    /// than `n`.
+    pub fn finished(&self) -> u64 {
+        self.finished
+    }
+
+    /// Leak every frame in flight and start the rotation again, for when
+    /// they can't be waited on, like after the device is lost. vulkano
+    /// waits on a frame's fence when it's dropped and panics when that
+    /// fails.
+    pub fn forget(&mut self) {
+        for frame in self.frames.drain(..) {
+            std::mem::forget(frame);
+        }
+        // what the leaked frames hold is never freed, so nothing waits on
+        // them
+        self.finished = self.submitted;
+        self.next_slot = 0;
+        self.early_wait = Duration::default();
+    }
+
+    fn finish_oldest(&mut self) -> Result<()> {
+        let frame = match self.frames.pop_front() {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        let mut result = frame.future.wait(Some(FRAME_TIMEOUT));
+        if let Err(FlushError::Timeout) = result {
+            log::warn!(
+                "the frame in slot {} is still running {:?} after it was \
+                submitted, {} more in flight, waiting without a timeout",
+                frame.slot,
+                frame.ended.elapsed(),
+                self.frames.len()
+            );
+            result = frame.future.wait(None);
+        }
+        if let Err(error) = result {
+            // vulkano waits on the fence again when the future is dropped,
+            // and panics when that fails too, so the frame is leaked
+            std::mem::forget(frame);
+            return Err(error).context("unable to complete a frame in flight");
+        }
+        self.finished += 1;
+        // a frame which finished before it was waited on is seen late, so
+        // this overestimates rather than under
+        let now = Instant::now();
+        self.presented = Some(Presented {
+            at: now,
+            acquire_wait: frame.acquire_wait,
+        });
+        let acquire_started = frame.acquire_started;
+        self.latest = Some(FrameLatency {
+            input_age: frame
+                .input
+                .map(|input| acquire_started.saturating_duration_since(input)),
+            acquire_to_present: now - acquire_started,
+        });
+        Ok(())
+    }
+}
+
+impl FramesInFlight<FrameFuture> {
+    /// The newest frame, for the next frame to chain after.
+    pub fn newest(&self) -> Option<Box<dyn GpuFuture + Send + Sync>> {
+        self.frames.back().map(|frame| {
+            Box::new(frame.future.clone()) as Box<dyn GpuFuture + Send + Sync>
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// How long a mock fence which isn't done yet blocks, well over what
+    /// counts as the cpu having to wait.
+    const GPU_TIME: Duration = Duration::from_millis(2);
+
+    /// Stands in for a frame's fence, noting the frame in a shared log when
+    /// it's waited on and blocking for a while unless the frame is done.
+    struct MockFence {
+        frame: usize,
+        done: bool,
+        waited: Rc<RefCell<Vec<usize>>>,
+    }
+
+    impl FrameFence for MockFence {
+        fn wait(&self, _timeout: Option<Duration>) -> Result<(), FlushError> {
+            self.waited.borrow_mut().push(self.frame);
+            if !self.done {
+                std::thread::sleep(GPU_TIME);
+            }
+            Ok(())
+        }
+    }
+
+    /// Begin and end `frames` frames, with fences which are done already
+    /// when `done` is set, returning the slots each frame was given.
+    fn run_frames(
+        frames_in_flight: &mut FramesInFlight<MockFence>,
+        waited: &Rc<RefCell<Vec<usize>>>,
+        first: usize,
+        frames: usize,
+        done: bool,
+    ) -> Result<Vec<usize>> {
+        let mut slots = vec![];
+        for frame in first..first + frames {
+            let slot = frames_in_flight.begin_frame()?;
+            slots.push(slot.index);
+            let now = Instant::now();
+            frames_in_flight.end_frame(
+                slot,
+                MockFence {
+                    frame,
+                    done,
+                    waited: waited.clone(),
+                },
+                now,
+                now,
+                None,
+            );
+        }
+        Ok(slots)
+    }
+
+    #[test]
+    fn the_oldest_frame_is_waited_on_once_the_slots_are_used() -> Result<()> {
+        let waited = Rc::new(RefCell::new(vec![]));
+        let mut frames_in_flight = FramesInFlight::new(3);
+        let slots = run_frames(&mut frames_in_flight, &waited, 0, 7, false)?;
+        assert_eq!(slots, vec![0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(*waited.borrow(), vec![0, 1, 2, 3]);
+        let stats = frames_in_flight.wait_stats();
+        assert_eq!((stats.frames, stats.waited), (7, 4));
+        assert!(stats.average_wait() >= GPU_TIME);
+
+        frames_in_flight.wait_for_all()?;
+        assert_eq!(frames_in_flight.in_flight(), 0);
+        assert_eq!(*waited.borrow(), vec![0, 1, 2, 3, 4, 5, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn frames_which_are_done_dont_count_as_waits() -> Result<()> {
+        let waited = Rc::new(RefCell::new(vec![]));
+        let mut frames_in_flight = FramesInFlight::new(3);
+        run_frames(&mut frames_in_flight, &waited, 0, 7, false)?;
+        frames_in_flight.wait_for_all()?;
+
+        // a lower depth carries the rotation on from where it was
+        frames_in_flight.set_depth(2);
+        let slots = run_frames(&mut frames_in_flight, &waited, 7, 4, true)?;
+        assert_eq!(slots, vec![1, 0, 1, 0]);
+        assert_eq!(frames_in_flight.wait_stats().waited, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn forgetting_starts_the_rotation_again() -> Result<()> {
+        let waited = Rc::new(RefCell::new(vec![]));
+        let mut frames_in_flight = FramesInFlight::new(3);
+        run_frames(&mut frames_in_flight, &waited, 0, 2, false)?;
+        frames_in_flight.forget();
+        assert_eq!(frames_in_flight.in_flight(), 0);
+        assert_eq!(frames_in_flight.begin_frame()?.index, 0);
+        assert!(waited.borrow().is_empty());
+        Ok(())
+    }
+}
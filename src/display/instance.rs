@@ -1,37 +1,287 @@
-use anyhow::{Context, Result};
+use super::message_filter::{
+    message_id, MessageFilter, Verdict, DEFAULT_SPAM_THRESHOLD,
+};
+use anyhow::{bail, Context, Result};
 use log;
-use std::sync::Arc;
-use vulkano::instance::debug::{DebugCallback, MessageSeverity, MessageType};
+#[cfg(all(feature = "api-dump", debug_assertions))]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use vulkano::instance::debug::{
+    DebugCallback, Message, MessageSeverity, MessageType,
+};
 use vulkano::instance::{
     layers_list, ApplicationInfo, Instance, InstanceExtensions, Version,
 };
 
+mod moltenvk;
+
+pub use moltenvk::{
+    check_moltenvk_available, moltenvk_version,
+    required_extensions_for_platform,
+};
+
 const VALIDATION_LAYERS: &[&str] = &["VK_LAYER_KHRONOS_validation"];
 const ENABLE_VALIDATION_LAYERS: bool = cfg!(debug_assertions);
 
-pub fn create_instance() -> Result<Arc<Instance>> {
-    if ENABLE_VALIDATION_LAYERS && !check_debug_layers()? {
+/// Traces every vulkan call, see `setup_api_dump_layer`.
+#[cfg(all(feature = "api-dump", debug_assertions))]
+const API_DUMP_LAYER: &str = "VK_LAYER_LUNARG_api_dump";
+
+// layers enabled on instances created from now on, on top of the validation
+// layers which are only checked for
+static ENABLED_LAYERS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+// set by `force_validation` to validate release builds too
+static FORCE_VALIDATION: AtomicBool = AtomicBool::new(false);
+
+// every error reported by the debug callback of any instance
+static VALIDATION_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+// decides which debug messages are logged, created with the default
+// threshold on first use
+static MESSAGE_FILTER: Mutex<Option<MessageFilter>> = Mutex::new(None);
+
+// every debug message while a failed frame is being captured, whatever
+// its severity, see `FailureCapture`
+static CAPTURED_MESSAGES: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// The validation layer's settings file, written when messages are
+/// suppressed so the layer drops them before they reach the callback.
+const LAYER_SETTINGS_FILE: &str = "vk_layer_settings.txt";
+
+fn with_message_filter<T>(f: impl FnOnce(&mut MessageFilter) -> T) -> T {
+    let mut filter = MESSAGE_FILTER.lock().unwrap();
+    f(filter.get_or_insert_with(|| {
+        MessageFilter::new(Some(DEFAULT_SPAM_THRESHOLD))
+    }))
+}
+
+/// Drop validation messages with these ids, like
+/// `UNASSIGNED-BestPractices-vkCreateSwapchainKHR-suboptimal-swapchain-image-count`,
+/// for the rest of the run.
+///
+/// The debug callback drops them for instances which already exist. For
+/// instances created after this, a settings file listing them is written to
+/// the temp directory and pointed to with `VK_LAYER_SETTINGS_PATH`, so the
+/// layer doesn't even produce them. A settings path which is already set
+/// is left alone.
+pub fn suppress_validation_messages(
+    ids: impl IntoIterator<Item = impl Into<String>>,
+) -> Result<()> {
+    let mut suppressed: Vec<String> = with_message_filter(|filter| {
+        for id in ids {
+            filter.suppress(id);
+        }
+        filter.suppressed().map(str::to_owned).collect()
+    });
+    let directory = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
+    let path = directory.join(LAYER_SETTINGS_FILE);
+    // the file is rewritten when more ids are suppressed, but one set by
+    // the user is theirs
+    match std::env::var_os("VK_LAYER_SETTINGS_PATH") {
+        Some(current) if current != path.as_os_str() => return Ok(()),
+        _ if suppressed.is_empty() => return Ok(()),
+        _ => (),
+    }
+    std::fs::create_dir_all(&directory)
+        .with_context(|| format!("unable to create {:?}", directory))?;
+    suppressed.sort();
+    std::fs::write(
+        &path,
+        format!(
+            "khronos_validation.message_id_filter = {}\n",
+            suppressed.join(",")
+        ),
+    )
+    .with_context(|| format!("unable to write {:?}", path))?;
+    std::env::set_var("VK_LAYER_SETTINGS_PATH", &path);
+    log::info!("wrote the validation layer settings to {:?}", path);
+    Ok(())
+}
+
+/// Drop any validation message which has fired more than `threshold` times
+/// for the rest of the run, or never with `None`. The default is
+/// `DEFAULT_SPAM_THRESHOLD`.
+pub fn set_validation_spam_threshold(threshold: Option<u32>) {
+    with_message_filter(|filter| filter.set_threshold(threshold));
+}
+
+/// Start collecting every debug message, including the info and verbose
+/// ones the log level hides and the ones the filter drops.
+pub(super) fn capture_debug_messages() {
+    *CAPTURED_MESSAGES.lock().unwrap() = Some(vec![]);
+}
+
+/// Stop collecting debug messages and take the ones collected.
+pub(super) fn take_captured_debug_messages() -> Vec<String> {
+    CAPTURED_MESSAGES.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Log how many validation messages of each id were dropped, once at the
+/// end of the run.
+pub fn log_dropped_validation_messages() {
+    let dropped = with_message_filter(|filter| filter.dropped());
+    for (id, count) in dropped {
+        log::info!("dropped {} validation messages {}", count, id);
+    }
+}
+
+/// Enable the validation layers and the debug callback on instances created
+/// after this, even in release builds.
+///
+/// Fails when the layers aren't installed, since a check relying on them
+/// would otherwise pass without validating anything.
+pub fn force_validation() -> Result<()> {
+    if !check_debug_layers()? {
+        bail!(
+            "the validation layers aren't installed, they come with the \
+            Vulkan SDK or a distribution's vulkan-validationlayers package"
+        );
+    }
+    FORCE_VALIDATION.store(true, Ordering::Relaxed);
+    let mut layers = ENABLED_LAYERS.lock().unwrap();
+    for layer in VALIDATION_LAYERS {
+        if !layers.contains(layer) {
+            layers.push(*layer);
+        }
+    }
+    Ok(())
+}
+
+/// How many errors the debug callbacks have reported since the program
+/// started.
+pub fn validation_error_count() -> u64 {
+    VALIDATION_ERRORS.load(Ordering::Relaxed)
+}
+
+fn validation_enabled() -> bool {
+    ENABLE_VALIDATION_LAYERS || FORCE_VALIDATION.load(Ordering::Relaxed)
+}
+
+/// Trace every vulkan call into `output_path` with the
+/// `VK_LAYER_LUNARG_api_dump` layer, for instances created after this.
+///
+/// The layer isn't part of the driver, it's installed with the Vulkan SDK or
+/// a distribution's vulkan tools package. The dump is flushed after every
+/// call so it's complete up to a crash, which makes everything very slow, so
+/// this only exists in debug builds with the api-dump feature.
+#[cfg(all(feature = "api-dump", debug_assertions))]
+pub fn setup_api_dump_layer(enabled: bool, output_path: &Path) -> Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    let available = layers_list()
+        .context("unable to list the instance layers")?
+        .any(|layer| layer.name() == API_DUMP_LAYER);
+    if !available {
+        bail!(
+            "{} isn't installed, it comes with the Vulkan SDK",
+            API_DUMP_LAYER
+        );
+    }
+    std::env::set_var("VK_APIDUMP_OUTPUT_FILE", output_path);
+    std::env::set_var("VK_APIDUMP_FLUSH_AFTER_WRITE", "TRUE");
+    let mut layers = ENABLED_LAYERS.lock().unwrap();
+    if !layers.contains(&API_DUMP_LAYER) {
+        layers.push(API_DUMP_LAYER);
+    }
+    log::info!("dumping vulkan calls to {:?}", output_path);
+    Ok(())
+}
+
+/// The name used for the application and the window until a sketch names
+/// itself.
+pub const DEFAULT_APPLICATION_NAME: &str = "vulkan starter";
+
+/// What the instance tells the driver about the application and the engine
+/// it's built on. Some drivers key application specific optimizations and
+/// workarounds off these, so sketches can name themselves.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppIdentity {
+    pub application_name: String,
+    pub application_version: Version,
+    pub engine_name: String,
+    pub engine_version: Version,
+}
+
+impl Default for AppIdentity {
+    fn default() -> Self {
+        Self {
+            application_name: DEFAULT_APPLICATION_NAME.to_owned(),
+            application_version: Version {
+                major: 1,
+                minor: 0,
+                patch: 0,
+            },
+            engine_name: env!("CARGO_PKG_NAME").to_owned(),
+            engine_version: Version {
+                major: env!("CARGO_PKG_VERSION_MAJOR").parse().unwrap_or(0),
+                minor: env!("CARGO_PKG_VERSION_MINOR").parse().unwrap_or(0),
+                patch: env!("CARGO_PKG_VERSION_PATCH").parse().unwrap_or(0),
+            },
+        }
+    }
+}
+
+impl AppIdentity {
+    /// The application info every instance is created with, headless or
+    /// windowed.
+    fn application_info(&self) -> ApplicationInfo<'_> {
+        ApplicationInfo {
+            application_name: Some(self.application_name.as_str().into()),
+            application_version: Some(self.application_version),
+            engine_name: Some(self.engine_name.as_str().into()),
+            engine_version: Some(self.engine_version),
+        }
+    }
+}
+
+/// Create the vulkan instance.
+///
+/// Windowed instances enable the extensions required to create a surface,
+/// headless instances only enable the debug extensions. On macOS the loader
+/// is pointed at MoltenVK first, when it's installed.
+pub fn create_instance(
+    windowed: bool,
+    identity: &AppIdentity,
+) -> Result<Arc<Instance>> {
+    if validation_enabled() && !check_debug_layers()? {
         log::warn!("requested validation layers are unavailable")
     }
 
     let supported_extensions = InstanceExtensions::supported_by_core()
         .context("unable to get supported instance extensions")?;
-    let required_extensions = required_extensions();
+    let required_extensions =
+        required_extensions(windowed, &supported_extensions);
     log::info!("supported extensions: {:?}", supported_extensions);
     log::info!("required extensions: {:?}", required_extensions);
+    log::info!(
+        "{} {:?} on {} {:?}",
+        identity.application_name,
+        identity.application_version,
+        identity.engine_name,
+        identity.engine_version
+    );
 
-    let app_info = ApplicationInfo {
-        application_name: Some("Vulkan Experiments".into()),
-        application_version: Some(Version {
-            major: 1,
-            minor: 0,
-            patch: 0,
-        }),
-        engine_name: Some("no engine".into()),
-        engine_version: None,
-    };
+    let app_info = identity.application_info();
 
-    Ok(Instance::new(Some(&app_info), &required_extensions, None)?)
+    moltenvk::use_moltenvk().context("unable to set up moltenvk")?;
+    let layers = ENABLED_LAYERS.lock().unwrap().clone();
+    let instance =
+        Instance::new(Some(&app_info), &required_extensions, layers)?;
+    if cfg!(target_os = "macos") {
+        match moltenvk_version(&instance) {
+            Some(version) => log::info!("running on moltenvk {}", version),
+            None if check_moltenvk_available() => {
+                log::info!("moltenvk is installed but not running")
+            }
+            None => log::warn!(
+                "moltenvk isn't installed, it comes with the Vulkan SDK"
+            ),
+        }
+    }
+    Ok(instance)
 }
 
 fn check_debug_layers() -> Result<bool> {
@@ -47,16 +297,27 @@ fn check_debug_layers() -> Result<bool> {
     Ok(all_available)
 }
 
-fn required_extensions() -> InstanceExtensions {
-    let mut required_extensions = vulkano_win::required_extensions();
-    if ENABLE_VALIDATION_LAYERS {
+/// The extensions to enable. Querying the memory budget needs
+/// `khr_get_physical_device_properties2`, which is enabled when supported.
+fn required_extensions(
+    windowed: bool,
+    supported: &InstanceExtensions,
+) -> InstanceExtensions {
+    let mut required_extensions = if windowed {
+        vulkano_win::required_extensions()
+    } else {
+        InstanceExtensions::none()
+    };
+    if validation_enabled() {
         required_extensions.ext_debug_utils = true;
     }
+    required_extensions.khr_get_physical_device_properties2 =
+        supported.khr_get_physical_device_properties2;
     required_extensions
 }
 
 pub fn setup_debug_callback(instance: &Arc<Instance>) -> Option<DebugCallback> {
-    if !ENABLE_VALIDATION_LAYERS {
+    if !validation_enabled() {
         return None;
     }
 
@@ -73,7 +334,48 @@ pub fn setup_debug_callback(instance: &Arc<Instance>) -> Option<DebugCallback> {
         validation: true,
     };
 
-    DebugCallback::new(instance, severity, msgtype, |msg| match msg.severity {
+    DebugCallback::new(instance, severity, msgtype, |msg| {
+        if let Some(captured) = CAPTURED_MESSAGES.lock().unwrap().as_mut() {
+            captured.push(format!(
+                "{}: {}",
+                severity_name(msg.severity),
+                msg.description
+            ));
+        }
+        // suppressed errors are expected, but errors dropped as spam are
+        // still errors
+        let verdict =
+            with_message_filter(|filter| filter.check(msg.description));
+        if msg.severity.error && verdict != Verdict::Suppressed {
+            VALIDATION_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+        match verdict {
+            Verdict::Show => log_message(&msg),
+            Verdict::Suppressed | Verdict::Spam { first: false } => (),
+            Verdict::Spam { first: true } => log::warn!(
+                "{} fired more than {} times, dropping the rest of them",
+                message_id(msg.description).unwrap_or_default(),
+                with_message_filter(|filter| filter.threshold())
+                    .unwrap_or_default()
+            ),
+        }
+    })
+    .ok()
+}
+
+fn severity_name(severity: MessageSeverity) -> &'static str {
+    match severity {
+        MessageSeverity { error: true, .. } => "error",
+        MessageSeverity { warning: true, .. } => "warning",
+        MessageSeverity {
+            information: true, ..
+        } => "info",
+        _ => "verbose",
+    }
+}
+
+fn log_message(msg: &Message) {
+    match msg.severity {
         MessageSeverity { error: true, .. } => {
             log::error!("Vulkan Debug Callback\n{:?}", msg.description)
         }
@@ -91,6 +393,5 @@ pub fn setup_debug_callback(instance: &Arc<Instance>) -> Option<DebugCallback> {
         _ => {
             log::debug!("Vulkan Debug Callback\n{:?}", msg.description)
         }
-    })
-    .ok()
+    }
 }
@@ -0,0 +1,175 @@
+use anyhow::{Context, Result};
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use vulkano::instance::{loader, Instance};
+use vulkano::VulkanObject;
+
+const LIBRARY: &str = "libMoltenVK.dylib";
+
+/// The manifest the Vulkan SDK and homebrew install for MoltenVK.
+const MANIFEST: &str = "MoltenVK_icd.json";
+
+/// Set by some MoltenVK packages to the version they installed.
+const VERSION_VARIABLE: &str = "MVK_MOLTENVK_VERSION";
+
+/// Devices which only implement part of vulkan, like MoltenVK's, must have
+/// this enabled whenever they support it.
+const PORTABILITY_SUBSET: &str = "VK_KHR_portability_subset";
+
+/// MoltenVK's version strings are at most this long, with the nul.
+const VERSION_LENGTH: usize = 64;
+
+type GetVersionStrings = extern "system" fn(*mut c_char, u32, *mut c_char, u32);
+
+/// True when MoltenVK, the vulkan driver layered over Metal on macOS, looks
+/// installed: `MVK_MOLTENVK_VERSION` is set or its manifest or library is
+/// in one of the places the loader and the Vulkan SDK put them.
+pub fn check_moltenvk_available() -> bool {
+    std::env::var_os(VERSION_VARIABLE).is_some()
+        || find_in(&manifest_directories(), MANIFEST).is_some()
+        || find_in(&library_directories(), LIBRARY).is_some()
+}
+
+/// The device extensions this platform needs enabled when a device
+/// supports them, `VK_KHR_portability_subset` on macOS and none elsewhere.
+pub fn required_extensions_for_platform() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        &[PORTABILITY_SUBSET]
+    } else {
+        &[]
+    }
+}
+
+/// Point the vulkan loader at MoltenVK on macOS, unless it was already
+/// pointed somewhere.
+///
+/// The loader only reads `VK_ICD_FILENAMES` when an instance is created.
+/// The installed manifest is used when there is one, otherwise one is
+/// written to the temp directory for the library.
+pub fn use_moltenvk() -> Result<Option<PathBuf>> {
+    if !cfg!(target_os = "macos")
+        || std::env::var_os("VK_ICD_FILENAMES").is_some()
+        || std::env::var_os("VK_DRIVER_FILES").is_some()
+    {
+        return Ok(None);
+    }
+    let manifest = match find_in(&manifest_directories(), MANIFEST) {
+        Some(manifest) => manifest,
+        None => match find_in(&library_directories(), LIBRARY) {
+            Some(library) => write_manifest(&library)?,
+            None => return Ok(None),
+        },
+    };
+    std::env::set_var("VK_ICD_FILENAMES", &manifest);
+    log::info!("using moltenvk with {:?}", manifest);
+    Ok(Some(manifest))
+}
+
+/// MoltenVK's version string, like "1.2.7", from `vkGetVersionStringsMVK`
+/// when the instance runs on it, or `MVK_MOLTENVK_VERSION` otherwise.
+///
+/// `vkGetMoltenVKConfigurationMVK` only returns the configuration, whose
+/// layout changes between releases, so the version comes from the function
+/// MoltenVK has for it.
+pub fn moltenvk_version(instance: &Instance) -> Option<String> {
+    query_version(instance)
+        .or_else(|| std::env::var(VERSION_VARIABLE).ok())
+        .filter(|version| !version.is_empty())
+}
+
+fn query_version(instance: &Instance) -> Option<String> {
+    let name = CStr::from_bytes_with_nul(b"vkGetVersionStringsMVK\0").unwrap();
+    let address = loader::auto_loader()
+        .ok()?
+        .get_instance_proc_addr(instance.internal_object(), name.as_ptr())
+        as *const c_void;
+    if address.is_null() {
+        return None;
+    }
+    // safe because only MoltenVK provides the function and the signature
+    // matches its header
+    let get_version_strings: GetVersionStrings =
+        unsafe { std::mem::transmute(address) };
+    let mut moltenvk = [0 as c_char; VERSION_LENGTH];
+    let mut vulkan = [0 as c_char; VERSION_LENGTH];
+    get_version_strings(
+        moltenvk.as_mut_ptr(),
+        VERSION_LENGTH as u32,
+        vulkan.as_mut_ptr(),
+        VERSION_LENGTH as u32,
+    );
+    // the function truncates but doesn't promise a nul
+    moltenvk[VERSION_LENGTH - 1] = 0;
+    let version = unsafe { CStr::from_ptr(moltenvk.as_ptr()) };
+    Some(version.to_string_lossy().into_owned())
+}
+
+fn find_in(directories: &[PathBuf], file: &str) -> Option<PathBuf> {
+    directories
+        .iter()
+        .map(|directory| directory.join(file))
+        .find(|path| path.is_file())
+}
+
+/// Where the loader looks for driver manifests on macOS, after the Vulkan
+/// SDK's own.
+fn manifest_directories() -> Vec<PathBuf> {
+    let mut directories = vec![];
+    if let Some(sdk) = std::env::var_os("VULKAN_SDK") {
+        directories.push(PathBuf::from(sdk).join("share/vulkan/icd.d"));
+    }
+    directories.extend(
+        [
+            "/usr/local/share/vulkan/icd.d",
+            "/opt/homebrew/share/vulkan/icd.d",
+            "/usr/local/etc/vulkan/icd.d",
+            "/etc/vulkan/icd.d",
+        ]
+        .iter()
+        .map(PathBuf::from),
+    );
+    directories
+}
+
+/// Where MoltenVK's library is installed, next to the executable for
+/// bundled apps.
+fn library_directories() -> Vec<PathBuf> {
+    let mut directories = vec![];
+    if let Some(sdk) = std::env::var_os("VULKAN_SDK") {
+        directories.push(PathBuf::from(sdk).join("lib"));
+    }
+    if let Some(directory) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned))
+    {
+        directories.push(directory);
+    }
+    directories.extend(
+        ["/usr/local/lib", "/opt/homebrew/lib"]
+            .iter()
+            .map(PathBuf::from),
+    );
+    directories
+}
+
+/// Write a driver manifest which loads `library`.
+fn write_manifest(library: &Path) -> Result<PathBuf> {
+    let library = library
+        .canonicalize()
+        .with_context(|| format!("unable to resolve {:?}", library))?;
+    let manifest = std::env::temp_dir().join(MANIFEST);
+    std::fs::write(
+        &manifest,
+        format!(
+            concat!(
+                r#"{{"file_format_version": "1.0.0", "ICD": "#,
+                r#"{{"library_path": "{}", "api_version": "1.2.0", "#,
+                r#""is_portability_driver": true}}}}"#,
+            ),
+            library.to_string_lossy()
+        ),
+    )
+    .with_context(|| format!("unable to write {:?}", manifest))?;
+    Ok(manifest)
+}
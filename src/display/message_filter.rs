@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+
+/// How many times a message can fire before the rest are dropped.
+pub const DEFAULT_SPAM_THRESHOLD: u32 = 50;
+
+/// What to do with a debug message.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Show,
+
+    /// Drop the message, its id is on the suppression list.
+    Suppressed,
+
+    /// Drop the message, it fired more than the threshold. `first` is set
+    /// for the first one dropped, which is when to say so.
+    Spam {
+        first: bool,
+    },
+}
+
+/// Decides which validation messages are logged, by the message id the
+/// layers put in square brackets at the start of each description.
+///
+/// Some messages are known to be noisy on particular drivers, like the best
+/// practices warnings about swapchain image counts, and drown out the real
+/// problems. Ids on the suppression list are always dropped. Any other id
+/// which fires more than the threshold is dropped from then on. Messages
+/// without an id are always shown.
+///
+/// Checking only looks the id up, so it's cheap enough to run before a
+/// message is formatted.
+pub struct MessageFilter {
+    suppressed: HashSet<String>,
+    threshold: Option<u32>,
+
+    // how often each id fired, shown or not
+    fired: HashMap<String, u64>,
+}
+
+impl MessageFilter {
+    /// A filter with nothing on the suppression list which drops messages
+    /// after `threshold` of them, or never without one.
+    pub fn new(threshold: Option<u32>) -> Self {
+        Self {
+            suppressed: HashSet::new(),
+            threshold,
+            fired: HashMap::new(),
+        }
+    }
+
+    /// Always drop messages with the id `id`, like
+    /// `UNASSIGNED-BestPractices-vkCreateSwapchainKHR-suboptimal-swapchain-image-count`.
+    pub fn suppress(&mut self, id: impl Into<String>) {
+        self.suppressed.insert(id.into());
+    }
+
+    /// The ids on the suppression list, in no particular order.
+    pub fn suppressed(&self) -> impl Iterator<Item = &str> {
+        self.suppressed.iter().map(String::as_str)
+    }
+
+    pub fn threshold(&self) -> Option<u32> {
+        self.threshold
+    }
+
+    pub fn set_threshold(&mut self, threshold: Option<u32>) {
+        self.threshold = threshold;
+    }
+
+    /// Count a message and decide whether to show it.
+    pub fn check(&mut self, description: &str) -> Verdict {
+        let id = match message_id(description) {
+            Some(id) => id,
+            None => return Verdict::Show,
+        };
+        // only a new id allocates
+        let fired = match self.fired.get_mut(id) {
+            Some(fired) => {
+                *fired += 1;
+                *fired
+            }
+            None => {
+                self.fired.insert(id.to_owned(), 1);
+                1
+            }
+        };
+        if self.suppressed.contains(id) {
+            return Verdict::Suppressed;
+        }
+        match self.threshold {
+            Some(threshold) if fired > threshold as u64 => Verdict::Spam {
+                first: fired == threshold as u64 + 1,
+            },
+            _ => Verdict::Show,
+        }
+    }
+
+    /// How many messages of each id were dropped, most first.
+    pub fn dropped(&self) -> Vec<(String, u64)> {
+        let mut dropped: Vec<(String, u64)> = self
+            .fired
+            .iter()
+            .filter_map(|(id, &fired)| {
+                let shown = match self.threshold {
+                    _ if self.suppressed.contains(id) => 0,
+                    Some(threshold) => fired.min(threshold as u64),
+                    None => fired,
+                };
+                match fired - shown {
+                    0 => None,
+                    count => Some((id.clone(), count)),
+                }
+            })
+            .collect();
+        dropped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        dropped
+    }
+}
+
+/// The id the validation layers put at the start of a message, like
+/// `VUID-vkCmdDraw-None-02699` in
+/// `Validation Error: [ VUID-vkCmdDraw-None-02699 ] Object 0: ...`.
+pub fn message_id(description: &str) -> Option<&str> {
+    let start = description.find("[ ")? + 2;
+    let length = description[start..].find(" ]")?;
+    let id = description[start..start + length].trim();
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        None
+    } else {
+        Some(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A best practices warning which is noisy on some drivers.
+    const NOISY: &str = "UNASSIGNED-BestPractices-vkCreateSwapchainKHR-\
+        suboptimal-swapchain-image-count";
+
+    /// A real error, which should be shown until it becomes spam.
+    const REAL: &str = "VUID-vkCmdDraw-None-02699";
+
+    const THRESHOLD: u32 = 3;
+
+    /// A message the way the validation layers format them.
+    fn message(id: &str) -> String {
+        format!(
+            "Validation Error: [ {} ] Object 0: handle = 0x5581, \
+            type = VK_OBJECT_TYPE_COMMAND_BUFFER; | MessageID = 0x1608dec0",
+            id
+        )
+    }
+
+    #[test]
+    fn ids_are_found_between_brackets() {
+        assert_eq!(message_id(&message(REAL)), Some(REAL));
+        for description in &["", "no id here", "[ ]", "[ two words ]", "[ open"]
+        {
+            assert_eq!(message_id(description), None, "{:?}", description);
+        }
+    }
+
+    #[test]
+    fn suppressed_and_spam_messages_are_dropped() {
+        let mut filter = MessageFilter::new(Some(THRESHOLD));
+        filter.suppress(NOISY);
+        for _ in 0..10 {
+            assert_eq!(filter.check(&message(NOISY)), Verdict::Suppressed);
+        }
+        for _ in 0..THRESHOLD {
+            assert_eq!(filter.check(&message(REAL)), Verdict::Show);
+        }
+        assert_eq!(filter.check(&message(REAL)), Verdict::Spam { first: true });
+        assert_eq!(
+            filter.check(&message(REAL)),
+            Verdict::Spam { first: false }
+        );
+        for _ in 0..10 {
+            assert_eq!(filter.check("no id here"), Verdict::Show);
+        }
+        assert_eq!(
+            filter.dropped(),
+            vec![(NOISY.to_owned(), 10), (REAL.to_owned(), 2)]
+        );
+    }
+
+    #[test]
+    fn nothing_is_spam_without_a_threshold() {
+        let mut filter = MessageFilter::new(Some(THRESHOLD));
+        filter.suppress(NOISY);
+        filter.check(&message(NOISY));
+        filter.set_threshold(None);
+        for _ in 0..THRESHOLD * 2 {
+            assert_eq!(filter.check(&message(REAL)), Verdict::Show);
+        }
+        assert_eq!(filter.dropped(), vec![(NOISY.to_owned(), 1)]);
+    }
+}
@@ -1,23 +1,67 @@
-use anyhow::{Context, Result};
+use crate::application::debug::BarrierValidator;
+use crate::application::events::AppEvent;
+use anyhow::{bail, Context, Result};
 use std::sync::Arc;
+use std::time::Duration;
 use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
 use vulkano::device::{Device, Queue};
 use vulkano::format::ClearValue;
 use vulkano::framebuffer::{FramebufferAbstract, RenderPassAbstract};
 use vulkano::image::swapchain::SwapchainImage;
 use vulkano::instance::debug::DebugCallback;
-use vulkano::instance::Instance;
-use vulkano::swapchain::acquire_next_image;
+use vulkano::instance::{Instance, QueueFamily};
 use vulkano::swapchain::{Surface, Swapchain};
-use vulkano::sync::GpuFuture;
-use vulkano_win::VkSurfaceBuild;
 use winit::dpi::LogicalSize;
-use winit::event_loop::EventLoop;
+use winit::event_loop::{EventLoop, EventLoopProxy};
 use winit::window::{Window, WindowBuilder};
 
+mod allocator;
+mod capture;
 mod device;
+mod failure_capture;
+mod frames;
 mod instance;
+mod message_filter;
+mod submission_batch;
+mod submitter;
 mod swapchain;
+mod window_size;
+
+pub use allocator::{
+    Allocator, AllocatorKind, BlockRanges, MemoryAllocation, MemoryTypeStats,
+    PassThroughAllocator, PooledAllocator,
+};
+pub use capture::CapturePolicy;
+#[cfg(feature = "device-report")]
+pub use device::{
+    check_baseline, check_required_limits, generate_device_report,
+    REQUIRED_LIMITS,
+};
+pub use device::{
+    describe_memory_type, is_device_lost, is_surface_lost, memory_budget,
+    memory_type_for, DeviceBackend, HeapBudget, MemoryUse,
+};
+pub use failure_capture::{FailureCapture, CAPTURE_INTERVAL};
+pub use frames::{
+    FrameFence, FrameFuture, FrameLatency, FrameSlot, FramesInFlight,
+    Presented, WaitStats, DEFAULT_FRAMES_IN_FLIGHT, FRAME_TIMEOUT,
+    MAX_FRAMES_IN_FLIGHT,
+};
+#[cfg(all(feature = "api-dump", debug_assertions))]
+pub use instance::setup_api_dump_layer;
+pub use instance::{
+    check_moltenvk_available, force_validation,
+    log_dropped_validation_messages, set_validation_spam_threshold,
+    suppress_validation_messages, validation_error_count, AppIdentity,
+    DEFAULT_APPLICATION_NAME,
+};
+pub use message_filter::{
+    message_id, MessageFilter, Verdict, DEFAULT_SPAM_THRESHOLD,
+};
+pub use submission_batch::{InFlightBatch, Submission, SubmissionBatch};
+pub use submitter::{Stage, Submitter};
+pub use swapchain::SurfaceFormatInfo;
+pub use window_size::{logical_to_physical, physical_to_logical, WindowSizes};
 
 pub enum SwapchainState {
     Optimal,
@@ -30,50 +74,345 @@ pub struct Display {
     pub debug_callback: Option<DebugCallback>,
 
     // window/surface resources
-    pub surface: Arc<Surface<Window>>,
-    pub event_loop: Option<EventLoop<()>>,
+    pub surface: Arc<Surface<Arc<Window>>>,
+    pub event_loop: Option<EventLoop<AppEvent>>,
+
+    /// Sends events to the event loop from other threads, waking it.
+    pub event_proxy: EventLoopProxy<AppEvent>,
     pub render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
-    pub swapchain: Arc<Swapchain<Window>>,
-    pub swapchain_images: Vec<Arc<SwapchainImage<Window>>>,
+    pub swapchain: Arc<Swapchain<Arc<Window>>>,
+    pub swapchain_images: Vec<Arc<SwapchainImage<Arc<Window>>>>,
     pub framebuffer_images: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
 
+    // the format and color space the swapchain was created with
+    surface_format: SurfaceFormatInfo,
+
     // devices and queues
     pub device: Arc<Device>,
     pub graphics_queue: Arc<Queue>,
     pub present_queue: Arc<Queue>,
+
+    /// A queue from a compute-only family which runs alongside the graphics
+    /// queue, or the graphics queue when the device has no such family.
+    pub compute_queue: Arc<Queue>,
+
+    // every queue the device was created with, the present queue can move
+    // to any of them which can present
+    queues: Vec<Arc<Queue>>,
+
+    // set when a frame failed to present and is being retried, so it's
+    // only retried once
+    present_retried: bool,
+
+    // optional device capabilities
+    pub multiview_supported: bool,
+
+    pub backend: DeviceBackend,
+
+    /// Where memory for resources bound by hand comes from.
+    pub allocator: Arc<dyn Allocator>,
+    allocator_kind: AllocatorKind,
+
+    // the fraction of the swapchain's resolution the scene is drawn at
+    render_scale: f32,
+
+    // the async compute submitted last frame, waited on before the next
+    compute_in_flight: Option<InFlightBatch>,
+
+    latency_mode: LatencyMode,
+    capture_policy: CapturePolicy,
+
+    // traces the frame after a failed submission, kept across device
+    // recreation
+    failure_capture: FailureCapture,
+    frames_in_flight: FramesInFlight,
+
+    // the index of the swapchain image presented last
+    presented_image: Option<usize>,
+}
+
+/// The smallest fraction of the swapchain's resolution the scene can be
+/// drawn at.
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+
+/// How many times the swapchain is created while the surface reports it's
+/// lost, before the surface itself has to be replaced.
+const SWAPCHAIN_ATTEMPTS: u32 = 3;
+
+/// The wait between swapchain attempts.
+const SWAPCHAIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// How frames are paced, trading throughput for the time between an input
+/// and the frame which shows it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Queue up to `DEFAULT_FRAMES_IN_FLIGHT` frames, presenting with
+    /// mailbox when it's supported and fifo otherwise.
+    Throughput,
+
+    /// Keep one frame in flight and present with mailbox or immediate when
+    /// either is supported, so input is sampled as late as possible. With
+    /// `late_latch` the application also waits until just before the next
+    /// frame is predicted to be needed before sampling input.
+    Reduced { late_latch: bool },
+}
+
+impl LatencyMode {
+    /// The frames which can be queued on the gpu in this mode.
+    pub fn frames_in_flight(&self) -> usize {
+        match self {
+            LatencyMode::Throughput => DEFAULT_FRAMES_IN_FLIGHT,
+            LatencyMode::Reduced { .. } => 1,
+        }
+    }
+}
+
+/// Configuration for the window created by `Display::create_windowed`.
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+
+    /// The title shown while the application runs, refreshed once per
+    /// second. Placeholders like `{fps:.0}` are replaced with live values,
+    /// see `TitleTemplate` for the full list.
+    pub title_template: String,
+
+    /// The window's inner size in logical pixels.
+    pub size: [u32; 2],
+
+    pub resizable: bool,
+
+    pub latency_mode: LatencyMode,
+
+    /// Whether the window is kept out of screen capture or kept friendly to
+    /// streaming software.
+    pub capture_policy: CapturePolicy,
+
+    /// The names and versions the vulkan instance is created with.
+    pub identity: AppIdentity,
+
+    /// Where memory for resources bound by hand comes from.
+    pub allocator: AllocatorKind,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_APPLICATION_NAME.to_owned(),
+            title_template:
+                "{app} — {fps:.0} fps — {gpu} — {w}x{h} — scene {rw}x{rh}"
+                    .to_owned(),
+            size: [1366, 768],
+            resizable: true,
+            latency_mode: LatencyMode::Throughput,
+            capture_policy: CapturePolicy::Default,
+            identity: AppIdentity::default(),
+            allocator: AllocatorKind::default(),
+        }
+    }
+}
+
+/// The parts of a display which outlive its device, dropped in the order
+/// they're declared.
+struct WindowResources {
+    surface: Arc<Surface<Arc<Window>>>,
+    event_loop: Option<EventLoop<AppEvent>>,
+    event_proxy: EventLoopProxy<AppEvent>,
+    debug_callback: Option<DebugCallback>,
+    instance: Arc<Instance>,
+}
+
+/// The vulkan instance, device, and queues without any windowing resources.
+///
+/// This is enough to build compute pipelines and compile shaders in tools
+/// which never open a window.
+pub struct HeadlessDisplay {
+    // vulkan library resources
+    pub instance: Arc<Instance>,
+    pub debug_callback: Option<DebugCallback>,
+
+    // devices and queues
+    pub device: Arc<Device>,
+    pub graphics_queue: Arc<Queue>,
+
+    // optional device capabilities
+    pub multiview_supported: bool,
+
+    pub backend: DeviceBackend,
+}
+
+impl WindowResources {
+    /// Swap the surface for a new one on the same window and instance. The
+    /// old surface is destroyed first.
+    fn with_new_surface(self) -> Result<Self> {
+        let Self {
+            surface,
+            event_loop,
+            event_proxy,
+            debug_callback,
+            instance,
+        } = self;
+        let window = surface.window().clone();
+        drop(surface);
+        let surface = create_surface(window, instance.clone())?;
+        log::info!("created a new surface for the window");
+        Ok(Self {
+            surface,
+            event_loop,
+            event_proxy,
+            debug_callback,
+            instance,
+        })
+    }
 }
 
 impl Display {
-    pub fn create() -> Result<Self> {
-        let instance = instance::create_instance()
+    /// Bring up a vulkan device without creating an event loop, window, or
+    /// surface.
+    ///
+    /// With the swiftshader-fallback feature, swiftshader is used when no
+    /// hardware device is suitable.
+    pub fn create_headless() -> Result<HeadlessDisplay> {
+        Self::create_headless_as(&AppIdentity::default())
+    }
+
+    /// Bring up a headless device for an instance which names the
+    /// application and engine as `identity` does.
+    pub fn create_headless_as(
+        identity: &AppIdentity,
+    ) -> Result<HeadlessDisplay> {
+        #[allow(unused_mut)]
+        let mut instance = instance::create_instance(false, identity)
             .context("unable to create the vulkan instance")?;
+        #[cfg(feature = "swiftshader-fallback")]
+        if !device::has_suitable_device(None, &instance) {
+            device::use_swiftshader()
+                .context("unable to switch to swiftshader")?;
+            instance = instance::create_instance(false, identity)
+                .context("unable to create the swiftshader instance")?;
+        }
         let debug_callback = instance::setup_debug_callback(&instance);
 
-        let event_loop: EventLoop<()> = EventLoop::new();
-        let surface = WindowBuilder::new()
-            .with_title("vulkan starter")
-            .with_resizable(true)
+        let physical_device = device::pick_physical_device(None, &instance)
+            .context("unable to pick a physical device")?;
+        let multiview_supported = device::supports_multiview(&physical_device);
+        let backend = DeviceBackend::of(&physical_device);
+        log::info!(
+            "headless device {:?} ({:?}), multiview supported: {}",
+            physical_device.name(),
+            backend,
+            multiview_supported
+        );
+
+        let (device, graphics_queue, _, _, _) =
+            device::create_logical_device(None, &physical_device)
+                .context("unable to create the logical device")?;
+
+        Ok(HeadlessDisplay {
+            instance,
+            debug_callback,
+            device,
+            graphics_queue,
+            multiview_supported,
+            backend,
+        })
+    }
+
+    /// Create a window and bring up a vulkan device which can present to it.
+    ///
+    /// With the swiftshader-fallback feature, swiftshader is used when no
+    /// hardware device can present to the window. The window gets a new
+    /// surface from swiftshader's instance since a surface belongs to its
+    /// instance.
+    pub fn create_windowed(window_config: WindowConfig) -> Result<Self> {
+        #[allow(unused_mut)]
+        let mut instance =
+            instance::create_instance(true, &window_config.identity)
+                .context("unable to create the vulkan instance")?;
+
+        let latency_mode = window_config.latency_mode;
+        let capture_policy = window_config.capture_policy;
+        let allocator_kind = window_config.allocator;
+        let [width, height] = window_config.size;
+        let event_loop = create_event_loop();
+        let event_proxy = event_loop.create_proxy();
+        let window = WindowBuilder::new()
+            .with_title(window_config.title.clone())
+            .with_resizable(window_config.resizable)
             .with_decorations(true)
             .with_visible(false)
-            .with_inner_size(LogicalSize::new(1366, 768))
-            .build_vk_surface(&event_loop, instance.clone())
-            .context("unable to build the main vulkan window")?;
+            .with_inner_size(LogicalSize::new(width, height))
+            .build(&event_loop)
+            .context("unable to build the main window")?;
+        let window = Arc::new(window);
+        #[allow(unused_mut)]
+        let mut surface = create_surface(window.clone(), instance.clone())?;
+        #[cfg(feature = "swiftshader-fallback")]
+        if !device::has_suitable_device(Some(&surface), &instance) {
+            device::use_swiftshader()
+                .context("unable to switch to swiftshader")?;
+            instance = instance::create_instance(true, &window_config.identity)
+                .context("unable to create the swiftshader instance")?;
+            surface = create_surface(window, instance.clone())?;
+        }
+        let debug_callback = instance::setup_debug_callback(&instance);
+        capture_policy.apply(surface.window());
+
+        let window = WindowResources {
+            surface,
+            event_loop: Some(event_loop),
+            event_proxy,
+            debug_callback,
+            instance,
+        };
+        Self::with_window(window, latency_mode, capture_policy, allocator_kind)
+    }
 
+    /// Bring up a vulkan device which presents to an existing window.
+    fn with_window(
+        window: WindowResources,
+        latency_mode: LatencyMode,
+        capture_policy: CapturePolicy,
+        allocator_kind: AllocatorKind,
+    ) -> Result<Self> {
+        let WindowResources {
+            surface,
+            event_loop,
+            event_proxy,
+            debug_callback,
+            instance,
+        } = window;
         let physical_device =
-            device::pick_physical_device(&surface, &instance)?;
+            device::pick_physical_device(Some(&surface), &instance)
+                .context("unable to pick a physical device")?;
+        let multiview_supported = device::supports_multiview(&physical_device);
+        let backend = DeviceBackend::of(&physical_device);
+        log::info!("device backend: {:?}", backend);
+        log::info!("multiview supported: {}", multiview_supported);
 
-        let (device, graphics_queue, present_queue) =
-            device::create_logical_device(&surface, &physical_device)?;
-        let (swapchain, swapchain_images) = swapchain::create_swap_chain(
-            &surface,
-            &physical_device,
-            &device,
-            &graphics_queue,
-            &present_queue,
-        )?;
+        let (device, graphics_queue, present_queue, compute_queue, queues) =
+            device::create_logical_device(Some(&surface), &physical_device)
+                .context("unable to create the logical device")?;
+        log::info!(
+            "async compute supported: {}",
+            compute_queue.family().id() != graphics_queue.family().id()
+        );
+        let (swapchain, swapchain_images, surface_format) =
+            swapchain::create_swap_chain(
+                &surface,
+                &physical_device,
+                &device,
+                &graphics_queue,
+                &present_queue,
+                latency_mode,
+                capture_policy,
+                None,
+            )
+            .context("unable to create the swapchain")?;
 
         let render_pass =
-            swapchain::create_render_pass(&device, swapchain.format())?;
+            swapchain::create_render_pass(&device, swapchain.format())
+                .context("unable to create the render pass")?;
 
         let framebuffer_images = swapchain::create_framebuffers(
             &device,
@@ -81,6 +420,8 @@ impl Display {
             &swapchain_images,
             &render_pass,
         );
+        let allocator = allocator_kind.create(&device);
+        log::info!("allocator: {}", allocator.name());
 
         Ok(Display {
             // library resources
@@ -89,27 +430,490 @@ impl Display {
 
             // window/surface resources
             surface,
-            event_loop: Option::Some(event_loop),
+            event_loop,
+            event_proxy,
             render_pass,
             swapchain,
             swapchain_images,
             framebuffer_images,
+            surface_format,
 
             // devices and queues
             device,
             graphics_queue,
             present_queue,
+            compute_queue,
+            queues,
+            present_retried: false,
+
+            // optional device capabilities
+            multiview_supported,
+
+            backend,
+
+            allocator,
+            allocator_kind,
+
+            render_scale: 1.0,
+            compute_in_flight: None,
+            latency_mode,
+            capture_policy,
+            failure_capture: FailureCapture::default(),
+            frames_in_flight: FramesInFlight::new(
+                latency_mode.frames_in_flight(),
+            ),
+            presented_image: None,
         })
     }
 
+    /// True when the compute queue is separate from the graphics queue.
+    pub fn has_async_compute(&self) -> bool {
+        self.compute_queue.family().id() != self.graphics_queue.family().id()
+    }
+
+    /// The queue families which use buffers shared between compute and
+    /// graphics. Buffers created for more than one family are shared
+    /// concurrently, so no ownership transfers are needed.
+    pub fn compute_families(&self) -> Vec<QueueFamily<'_>> {
+        let mut families = vec![self.graphics_queue.family()];
+        if self.has_async_compute() {
+            families.push(self.compute_queue.family());
+        }
+        families
+    }
+
+    /// The refresh rate of the monitor the window is on, in hertz.
+    ///
+    /// winit lists a monitor's video modes but not which one is in use, so
+    /// this is the fastest mode at the monitor's current resolution. Nothing
+    /// is returned when the monitor or its modes can't be found.
+    pub fn refresh_rate(&self) -> Option<f32> {
+        let monitor = self.surface.window().current_monitor()?;
+        let size = monitor.size();
+        monitor
+            .video_modes()
+            .filter(|mode| mode.size() == size)
+            .map(|mode| mode.refresh_rate())
+            .max()
+            .filter(|&refresh_rate| refresh_rate > 0)
+            .map(f32::from)
+    }
+
+    /// The window's logical, physical, and surface sizes as they are now.
+    pub fn window_sizes(&self) -> Result<WindowSizes> {
+        WindowSizes::of(&self.surface, &self.device)
+    }
+
+    /// The capture of the frame after a failed submission.
+    pub fn failure_capture(&mut self) -> &mut FailureCapture {
+        &mut self.failure_capture
+    }
+
+    pub fn latency_mode(&self) -> LatencyMode {
+        self.latency_mode
+    }
+
+    pub fn capture_policy(&self) -> CapturePolicy {
+        self.capture_policy
+    }
+
+    /// Switch the window in or out of fullscreen on its current monitor, as
+    /// the capture policy allows. The swapchain is rebuilt when the window
+    /// reports its new size.
+    pub fn toggle_fullscreen(&self) {
+        let window = self.surface.window();
+        if window.fullscreen().is_some() {
+            window.set_fullscreen(None);
+        } else {
+            let fullscreen =
+                self.capture_policy.fullscreen(window.current_monitor());
+            log::info!("fullscreen: {:?}", fullscreen);
+            window.set_fullscreen(Some(fullscreen));
+        }
+    }
+
+    /// The most frames which can be queued on the gpu at once.
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames_in_flight.depth()
+    }
+
+    /// Queue at most `depth` frames, between 1 and `MAX_FRAMES_IN_FLIGHT`.
+    pub fn set_frames_in_flight(&mut self, depth: usize) {
+        self.frames_in_flight.set_depth(depth);
+    }
+
+    /// Hand out memory with another allocator from now on. Anything bound
+    /// to the previous allocator's memory keeps it until it's dropped.
+    pub fn set_allocator(&mut self, kind: AllocatorKind) {
+        self.allocator = kind.create(&self.device);
+        self.allocator_kind = kind;
+    }
+
+    /// The number of the frame being recorded, counting every frame this
+    /// display submitted. See `FramesInFlight::submitted`.
+    pub fn frames_submitted(&self) -> u64 {
+        self.frames_in_flight.submitted()
+    }
+
+    /// How many of this display's frames are known to have finished.
+    /// Anything only the frame numbered `n` used can be dropped once this
+    /// is more than `n`.
+    pub fn frames_finished(&self) -> u64 {
+        self.frames_in_flight.finished()
+    }
+
+    /// How often the cpu had to wait for the gpu before beginning a frame.
+    pub fn frame_wait_stats(&self) -> WaitStats {
+        self.frames_in_flight.wait_stats()
+    }
+
+    /// Block until another frame can be submitted. Submitting waits anyway,
+    /// waiting first means the frame is recorded from the latest input.
+    pub fn wait_for_frame_slot(&mut self) -> Result<()> {
+        self.frames_in_flight.wait_for_slot()
+    }
+
+    /// Block until every submitted frame has finished, after which nothing
+    /// recorded so far is still in use by the gpu.
+    pub fn wait_for_all_frames(&mut self) -> Result<()> {
+        self.frames_in_flight.wait_for_all()
+    }
+
+    /// The latency of the last frame seen to finish since the last call.
+    pub fn take_frame_latency(&mut self) -> Option<FrameLatency> {
+        self.frames_in_flight.take_latency()
+    }
+
+    /// When the last frame was seen to finish presenting.
+    pub fn last_presented(&self) -> Option<Presented> {
+        self.frames_in_flight.last_presented()
+    }
+
+    /// The swapchain image presented last, while it can still be copied.
+    ///
+    /// Swapchain images can only be copied from with the `rebuild-fade`
+    /// feature on a surface which allows it, otherwise this is always
+    /// `None`.
+    pub fn presented_image(&self) -> Option<Arc<SwapchainImage<Arc<Window>>>> {
+        let copyable = self
+            .surface
+            .capabilities(self.device.physical_device())
+            .map_or(false, |capabilities| {
+                capabilities.supported_usage_flags.transfer_source
+            });
+        if !cfg!(feature = "rebuild-fade") || !copyable {
+            return None;
+        }
+        self.presented_image
+            .map(|index| self.swapchain_images[index].clone())
+    }
+
+    /// The format and color space of the swapchain's images.
+    pub fn surface_format(&self) -> SurfaceFormatInfo {
+        self.surface_format
+    }
+
+    /// The fraction of the swapchain's resolution the scene is drawn at.
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Change the resolution the scene is drawn at, clamped between
+    /// `MIN_RENDER_SCALE` and 1. Anything built for the scene extent must be
+    /// rebuilt afterwards.
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(MIN_RENDER_SCALE, 1.0);
+    }
+
+    /// The extent the scene is drawn at.
+    ///
+    /// Pipelines which draw the scene use this extent, while passes which
+    /// draw straight to the swapchain, like the post-processing, use the
+    /// swapchain's dimensions.
+    pub fn scene_extent(&self) -> [u32; 2] {
+        let [width, height] = self.swapchain.dimensions();
+        let scale = |size: u32| {
+            ((size as f32 * self.render_scale).round() as u32).max(1)
+        };
+        [scale(width), scale(height)]
+    }
+
+    /// Wait for the device to finish everything submitted, then drop the
+    /// display's resources children first: the frames in flight, the
+    /// framebuffers and render pass, the swapchain, the device and its
+    /// queues, the surface, and finally the instance.
+    ///
+    /// Anything else built on the device should be dropped before this is
+    /// called. Dropping the display without calling this drops the instance
+    /// first, leaving the order to whatever still holds a reference.
+    pub fn destroy(self) {
+        if let Err(error) = self.destroy_checked() {
+            log::warn!("{:?}", error);
+        }
+    }
+
+    /// Destroy the display like `destroy`, failing when any of its
+    /// resources still had another owner when it was dropped.
+    ///
+    /// A resource cloned into something which outlived the application is
+    /// only freed once that's dropped too, if ever, which is after the
+    /// device or instance it needs is already gone.
+    pub fn destroy_checked(mut self) -> Result<()> {
+        if let Err(error) = self.frames_in_flight.wait_for_all() {
+            log::warn!("unable to wait for the frames in flight {:?}", error);
+            self.forget_frames();
+        }
+        if let Some(mut compute) = self.compute_in_flight.take() {
+            if let Err(error) = compute.wait(None) {
+                log::warn!("unable to wait for the async compute {:?}", error);
+                std::mem::forget(compute);
+            }
+        }
+
+        // unsafe because nothing else submits to the device's queues while
+        // it waits, the display owns the only references left
+        if let Err(error) = unsafe { self.device.wait() } {
+            log::warn!("unable to wait for the device to idle {:?}", error);
+        }
+
+        let (window, mut retained) = self.release_device();
+        let WindowResources {
+            surface,
+            event_loop,
+            event_proxy,
+            debug_callback,
+            instance,
+        } = window;
+        release("surface", surface, &mut retained);
+        drop(event_loop);
+        drop(event_proxy);
+        drop(debug_callback);
+        release("instance", instance, &mut retained);
+        log::debug!("display destroyed");
+        log_dropped_validation_messages();
+
+        if !retained.is_empty() {
+            bail!(
+                "still referenced when the display was destroyed: {}",
+                retained.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The strong count of each of the display's vulkan resources, which
+    /// are also logged at the debug level.
+    ///
+    /// Resources reference their parents, so the framebuffers hold the
+    /// render pass and the swapchain images, which hold the swapchain, and
+    /// everything holds the device. Only the counts left once the children
+    /// are dropped say whether anything else kept a clone, which is what
+    /// `destroy_checked` looks at.
+    pub fn debug_resource_counts(&self) -> Vec<(String, usize)> {
+        let mut counts = vec![
+            ("instance".to_owned(), Arc::strong_count(&self.instance)),
+            ("surface".to_owned(), Arc::strong_count(&self.surface)),
+            (
+                "render pass".to_owned(),
+                Arc::strong_count(&self.render_pass),
+            ),
+            ("swapchain".to_owned(), Arc::strong_count(&self.swapchain)),
+            ("device".to_owned(), Arc::strong_count(&self.device)),
+            (
+                "graphics queue".to_owned(),
+                Arc::strong_count(&self.graphics_queue),
+            ),
+            (
+                "present queue".to_owned(),
+                Arc::strong_count(&self.present_queue),
+            ),
+            (
+                "compute queue".to_owned(),
+                Arc::strong_count(&self.compute_queue),
+            ),
+        ];
+        for (i, image) in self.swapchain_images.iter().enumerate() {
+            counts.push((
+                format!("swapchain image {}", i),
+                Arc::strong_count(image),
+            ));
+        }
+        for (i, framebuffer) in self.framebuffer_images.iter().enumerate() {
+            counts.push((
+                format!("framebuffer {}", i),
+                Arc::strong_count(framebuffer),
+            ));
+        }
+        for (name, count) in &counts {
+            log::debug!("{}: {} references", name, count);
+        }
+        counts
+    }
+
+    /// Replace a lost device with a new one which presents to the same
+    /// window.
+    ///
+    /// Everything else built on the lost device must be dropped first,
+    /// including any references to the swapchain, since a window only has
+    /// one swapchain at a time.
+    pub fn recreate_device(self) -> Result<Self> {
+        self.recreate(false)
+            .context("unable to recreate the device")
+    }
+
+    /// Give the window a new surface after the old one was lost, and bring
+    /// up a device which can present to it.
+    ///
+    /// The device is recreated along with the surface: the old swapchain
+    /// has to be destroyed before the window will take a new one, and a
+    /// driver update can change which physical devices can present to the
+    /// window at all. Like `recreate_device`, everything else built on the
+    /// device must be dropped first.
+    pub fn recreate_surface(self) -> Result<Self> {
+        self.recreate(true)
+            .context("unable to recreate the surface")
+    }
+
+    fn recreate(mut self, new_surface: bool) -> Result<Self> {
+        let depth = self.frames_in_flight.depth();
+        let render_scale = self.render_scale;
+        // a lost surface leaves the device working, so its frames can
+        // finish, but a lost device's can't be waited on
+        let waited =
+            new_surface && self.frames_in_flight.wait_for_all().is_ok();
+        if !waited {
+            self.forget_frames();
+        }
+
+        let latency_mode = self.latency_mode;
+        let capture_policy = self.capture_policy;
+        let allocator_kind = self.allocator_kind;
+        let failure_capture = std::mem::take(&mut self.failure_capture);
+        let (mut window, retained) = self.release_device();
+        // forgotten frames still hold their resources, so the counts of
+        // what's retained only say something after a wait
+        if waited && !retained.is_empty() {
+            log::warn!(
+                "still held when the surface was replaced: {}",
+                retained.join(", ")
+            );
+        }
+        if new_surface {
+            window = window.with_new_surface()?;
+        }
+        let mut display = Self::with_window(
+            window,
+            latency_mode,
+            capture_policy,
+            allocator_kind,
+        )?;
+        display.set_frames_in_flight(depth);
+        display.render_scale = render_scale;
+        display.failure_capture = failure_capture;
+        Ok(display)
+    }
+
+    /// Leak the frames in flight. vulkano waits on a frame's fence when it's
+    /// dropped and panics when the device is lost, so frames which can't be
+    /// waited on are never dropped.
+    fn forget_frames(&mut self) {
+        self.frames_in_flight.forget();
+        std::mem::forget(self.compute_in_flight.take());
+    }
+
+    /// Drop everything built on the device, children first, leaving only
+    /// the window and instance. Returns the names of the resources which
+    /// had another owner when they were dropped.
+    fn release_device(self) -> (WindowResources, Vec<String>) {
+        let Self {
+            instance,
+            debug_callback,
+            surface,
+            event_loop,
+            event_proxy,
+            render_pass,
+            swapchain,
+            swapchain_images,
+            framebuffer_images,
+            device,
+            graphics_queue,
+            present_queue,
+            compute_queue,
+            queues,
+            allocator,
+            frames_in_flight,
+            compute_in_flight,
+            ..
+        } = self;
+        let mut retained = vec![];
+        drop(frames_in_flight);
+        drop(compute_in_flight);
+        for (i, framebuffer) in framebuffer_images.into_iter().enumerate() {
+            release(format!("framebuffer {}", i), framebuffer, &mut retained);
+        }
+        release("render pass", render_pass, &mut retained);
+        for (i, image) in swapchain_images.into_iter().enumerate() {
+            release(format!("swapchain image {}", i), image, &mut retained);
+        }
+        release("swapchain", swapchain, &mut retained);
+        // anything still bound to the allocator's memory holds it
+        release("allocator", allocator, &mut retained);
+
+        // the graphics, present, and compute queues are clones of the
+        // device's queues
+        drop(graphics_queue);
+        drop(present_queue);
+        drop(compute_queue);
+        for queue in queues {
+            let name = format!("queue in family {}", queue.family().id());
+            release(name, queue, &mut retained);
+        }
+        release("device", device, &mut retained);
+        let window = WindowResources {
+            surface,
+            event_loop,
+            event_proxy,
+            debug_callback,
+            instance,
+        };
+        (window, retained)
+    }
+
     /// Rebuild the swapchain and dependent resources based on the the
     /// window's current size.
     pub fn rebuild_swapchain(&mut self) -> Result<()> {
-        let size = self.surface.window().inner_size();
-        let (swapchain, swapchain_images) = self
-            .swapchain
-            .recreate_with_dimensions([size.width, size.height])
-            .context("unable to recreate the swapchain")?;
+        self.frames_in_flight.wait_for_all()?;
+        let mut attempt = 1;
+        let (swapchain, swapchain_images, surface_format) = loop {
+            let created = swapchain::create_swap_chain(
+                &self.surface,
+                &self.device.physical_device(),
+                &self.device,
+                &self.graphics_queue,
+                &self.present_queue,
+                self.latency_mode,
+                self.capture_policy,
+                Some(&self.swapchain),
+            )
+            .context("unable to recreate the swapchain");
+            match created {
+                // a driver being updated can hold the surface for a moment
+                Err(error)
+                    if attempt < SWAPCHAIN_ATTEMPTS
+                        && device::is_surface_lost(&error) =>
+                {
+                    log::warn!(
+                        "swapchain attempt {} of {} failed {:?}",
+                        attempt,
+                        SWAPCHAIN_ATTEMPTS,
+                        error
+                    );
+                    attempt += 1;
+                    std::thread::sleep(SWAPCHAIN_RETRY_DELAY);
+                }
+                created => break created?,
+            }
+        };
         let render_pass =
             swapchain::create_render_pass(&self.device, swapchain.format())
                 .context("unable to recreate the render pass")?;
@@ -122,48 +926,61 @@ impl Display {
 
         self.swapchain = swapchain;
         self.swapchain_images = swapchain_images;
+        self.surface_format = surface_format;
+        self.presented_image = None;
         self.render_pass = render_pass;
         self.framebuffer_images = framebuffer_images;
 
         Ok(())
     }
 
-    /// Render the frame.
+    /// Look for the present family again after a frame failed to present,
+    /// returning true when the frame should be retried.
     ///
-    /// @param graphics_queue_subbuffers a vector of secondary command buffers
-    /// to be executed on the graphics queue
-    pub fn render(
-        &mut self,
-        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
-    ) -> Result<SwapchainState> {
-        let (image_index, suboptimal, acquire_swapchain_future) =
-            acquire_next_image(self.swapchain.clone(), None).with_context(
-                || "unable to acquire next frame for rendering",
-            )?;
-
-        let render_buffer = self.build_render_pass_command_buffer(
-            graphics_queue_subbuffers,
-            image_index,
-        )?;
-
-        acquire_swapchain_future
-            .then_execute(self.graphics_queue.clone(), render_buffer)
-            .with_context(|| "unable to execute the display command buffer")?
-            .then_swapchain_present(
-                self.present_queue.clone(),
-                self.swapchain.clone(),
-                image_index,
-            )
-            .then_signal_fence_and_flush()
-            .with_context(|| "unable to present, signal, and flush")?
-            .wait(None)
-            .with_context(|| "unable to complete the frame")?;
+    /// Some drivers reject presenting from the family picked when the
+    /// device was created, while another family works. When the surface
+    /// now prefers another family the present queue moves there. Either way
+    /// the swapchain needs to be rebuilt before the retry, which picks a
+    /// sharing mode to match the present queue, and the frame is only
+    /// retried once before the failure is reported.
+    fn reselect_present_queue(&mut self) -> Result<bool> {
+        if self.present_retried {
+            self.present_retried = false;
+            return Ok(false);
+        }
+        self.present_retried = true;
 
-        if suboptimal {
-            Ok(SwapchainState::NeedsRebuild)
-        } else {
-            Ok(SwapchainState::Optimal)
+        let current = self.present_queue.family().id() as usize;
+        let indices = device::QueueFamilyIndices::find(
+            Some(&self.surface),
+            &self.device.physical_device(),
+        )
+        .context("unable to find a queue family which can present")?;
+        let family = indices.present_family();
+        if family == current {
+            log::warn!(
+                "presenting from queue family {} failed, retrying",
+                current
+            );
+            return Ok(true);
         }
+
+        let queue = self
+            .queues
+            .iter()
+            .find(|queue| queue.family().id() as usize == family)
+            .cloned()
+            .with_context(|| {
+                format!("the device has no queue in family {}", family)
+            })?;
+        log::warn!(
+            "presenting from queue family {} failed, switching the present \
+             queue to family {}",
+            current,
+            family
+        );
+        self.present_queue = queue;
+        Ok(true)
     }
 
     /// Build a command buffer which renders the full render pass.
@@ -177,11 +994,14 @@ impl Display {
         graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
         framebuffer_index: usize,
     ) -> Result<AutoCommandBuffer> {
-        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
-            self.device.clone(),
-            self.graphics_queue.family(),
-        )
-        .with_context(|| "unable to create the command buffer builder")?;
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                self.device.clone(),
+                self.graphics_queue.family(),
+            )
+            .with_context(|| "unable to create the command buffer builder")?,
+            &self.graphics_queue,
+        );
 
         builder
             .begin_render_pass(
@@ -213,3 +1033,61 @@ impl Display {
             .with_context(|| "unable to build the command buffer")
     }
 }
+
+/// Drop a resource which nothing else should own by now, adding its name to
+/// `retained` when something still does.
+fn release<T: ?Sized>(
+    name: impl Into<String>,
+    resource: Arc<T>,
+    retained: &mut Vec<String>,
+) {
+    let count = Arc::strong_count(&resource);
+    if count > 1 {
+        retained.push(format!("{} ({} references)", name.into(), count));
+    }
+}
+
+/// A vulkan surface which presents to `window`. The surface shares the
+/// window, so the window can be given a new surface when this one is lost.
+fn create_surface(
+    window: Arc<Window>,
+    instance: Arc<Instance>,
+) -> Result<Arc<Surface<Arc<Window>>>> {
+    vulkano_win::create_vk_surface(window, instance)
+        .context("unable to create a surface for the window")
+}
+
+/// The window's event loop. Where the platform allows it, the loop can be
+/// created off the main thread, which the integration tests need since
+/// cargo runs each test on a thread of its own.
+fn create_event_loop() -> EventLoop<AppEvent> {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    {
+        use winit::platform::unix::EventLoopExtUnix;
+        EventLoop::new_any_thread()
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use winit::platform::windows::EventLoopExtWindows;
+        EventLoop::new_any_thread()
+    }
+
+    // macOS can only have an event loop on the main thread
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "windows"
+    )))]
+    {
+        EventLoop::with_user_event()
+    }
+}
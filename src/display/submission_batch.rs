@@ -0,0 +1,234 @@
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use vulkano::command_buffer::{AutoCommandBuffer, CommandBuffer};
+use vulkano::device::{Device, Queue};
+use vulkano::sync::{now, Fence, Semaphore};
+use vulkano::{SynchronizedVulkanObject, VulkanObject};
+
+/// One `VkSubmitInfo`: command buffers which start once the wait semaphores
+/// are signaled, and signal the signal semaphores when they finish.
+#[derive(Default)]
+pub struct Submission {
+    pub wait_semaphores: Vec<Arc<Semaphore>>,
+    pub signal_semaphores: Vec<Arc<Semaphore>>,
+    pub command_buffers: Vec<Arc<AutoCommandBuffer>>,
+}
+
+/// Accumulates submissions for one queue and flushes them all with a single
+/// `vkQueueSubmit`, so a frame's worth of work costs one trip into the
+/// driver.
+///
+/// vulkano merges chained command buffers into one submission without a
+/// barrier between them, and splits them with a second submission when a
+/// semaphore is signaled. A batch keeps each submission separate inside the
+/// one call, so `chain` can order a command buffer after everything before
+/// it with a semaphore.
+///
+/// This talks to vk-sys directly, so vulkano's futures know nothing about
+/// it. The command buffers are locked like vulkano locks them, and unlocked
+/// once the batch's fence is signaled. Anything they touch must not be in
+/// use by a future, which rules out the swapchain images: the acquire's
+/// semaphore and the images' layouts only exist inside vulkano's futures.
+pub struct SubmissionBatch {
+    device: Arc<Device>,
+    submissions: Vec<Submission>,
+}
+
+impl SubmissionBatch {
+    pub fn new(device: Arc<Device>) -> Self {
+        Self {
+            device,
+            submissions: vec![],
+        }
+    }
+
+    /// Add a submission which runs alongside the ones already added, other
+    /// than what its semaphores order.
+    pub fn add(&mut self, submission: Submission) {
+        self.submissions.push(submission);
+    }
+
+    /// Add a command buffer which waits for every write of the submission
+    /// added before it.
+    pub fn chain(
+        &mut self,
+        command_buffer: Arc<AutoCommandBuffer>,
+    ) -> Result<()> {
+        let wait_semaphores = match self.submissions.last_mut() {
+            Some(previous) => {
+                let semaphore = Arc::new(
+                    Semaphore::alloc(self.device.clone())
+                        .context("unable to create a batch semaphore")?,
+                );
+                previous.signal_semaphores.push(semaphore.clone());
+                vec![semaphore]
+            }
+            None => vec![],
+        };
+        self.add(Submission {
+            wait_semaphores,
+            signal_semaphores: vec![],
+            command_buffers: vec![command_buffer],
+        });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.submissions.is_empty()
+    }
+
+    /// Submit every submission to `queue` in one call, returning what's in
+    /// flight. Nothing is submitted when a command buffer can't be locked.
+    pub fn flush(self, queue: &Arc<Queue>) -> Result<InFlightBatch> {
+        let command_buffers: Vec<&Arc<AutoCommandBuffer>> = self
+            .submissions
+            .iter()
+            .flat_map(|submission| &submission.command_buffers)
+            .collect();
+        let fence = Fence::alloc(self.device.clone())
+            .context("unable to create the batch's fence")?;
+        let check = now(self.device.clone());
+        for (locked, command_buffer) in command_buffers.iter().enumerate() {
+            if let Err(error) = command_buffer.lock_submit(&check, queue) {
+                for command_buffer in &command_buffers[..locked] {
+                    // safe because none of these were submitted
+                    unsafe { command_buffer.unlock() };
+                }
+                return Err(error)
+                    .context("unable to lock a batched command buffer");
+            }
+        }
+
+        // the handles are collected first so the pointers in the submit
+        // infos stay put
+        let handles: Vec<RawSubmission> =
+            self.submissions.iter().map(RawSubmission::of).collect();
+        let infos: Vec<vk_sys::SubmitInfo> =
+            handles.iter().map(RawSubmission::info).collect();
+
+        let result = {
+            let vk = self.device.pointers();
+            let queue = queue.internal_object_guard();
+            // safe because every handle outlives the call, the command
+            // buffers are locked, and the queue is held for the submit
+            unsafe {
+                vk.QueueSubmit(
+                    *queue,
+                    infos.len() as u32,
+                    infos.as_ptr(),
+                    fence.internal_object(),
+                )
+            }
+        };
+        if result != vk_sys::SUCCESS {
+            for command_buffer in &command_buffers {
+                // safe because the submit failed, so nothing is in flight
+                unsafe { command_buffer.unlock() };
+            }
+            bail!(
+                "unable to submit the batch, vkQueueSubmit returned {}",
+                result
+            );
+        }
+        tracing::trace!(
+            "submitted {} command buffers in {} submissions with one call",
+            command_buffers.len(),
+            infos.len()
+        );
+        Ok(InFlightBatch {
+            fence,
+            submissions: self.submissions,
+            finished: false,
+        })
+    }
+}
+
+/// A flushed batch, holding everything it uses until its fence is signaled.
+///
+/// Dropping it waits for the fence, since the command buffers and
+/// semaphores can't be freed while the gpu uses them.
+pub struct InFlightBatch {
+    fence: Fence,
+    submissions: Vec<Submission>,
+    finished: bool,
+}
+
+impl InFlightBatch {
+    /// Block until every submission in the batch has finished, then unlock
+    /// its command buffers for the next submit.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.fence
+            .wait(timeout)
+            .context("unable to wait for the batch's fence")?;
+        for submission in &self.submissions {
+            for command_buffer in &submission.command_buffers {
+                // safe because the fence says the gpu is done with it
+                unsafe { command_buffer.unlock() };
+            }
+        }
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Drop for InFlightBatch {
+    fn drop(&mut self) {
+        if let Err(error) = self.wait(None) {
+            log::error!("dropped a batch still in flight {:?}", error);
+        }
+    }
+}
+
+/// The raw handles of one submission.
+struct RawSubmission {
+    wait_semaphores: Vec<vk_sys::Semaphore>,
+    wait_stages: Vec<vk_sys::PipelineStageFlags>,
+    command_buffers: Vec<vk_sys::CommandBuffer>,
+    signal_semaphores: Vec<vk_sys::Semaphore>,
+}
+
+impl RawSubmission {
+    fn of(submission: &Submission) -> Self {
+        Self {
+            wait_semaphores: submission
+                .wait_semaphores
+                .iter()
+                .map(|semaphore| semaphore.internal_object())
+                .collect(),
+            // the semaphores order whole submissions, everything waits
+            wait_stages: submission
+                .wait_semaphores
+                .iter()
+                .map(|_| vk_sys::PIPELINE_STAGE_ALL_COMMANDS_BIT)
+                .collect(),
+            command_buffers: submission
+                .command_buffers
+                .iter()
+                .map(|command_buffer| command_buffer.inner().internal_object())
+                .collect(),
+            signal_semaphores: submission
+                .signal_semaphores
+                .iter()
+                .map(|semaphore| semaphore.internal_object())
+                .collect(),
+        }
+    }
+
+    fn info(&self) -> vk_sys::SubmitInfo {
+        vk_sys::SubmitInfo {
+            sType: vk_sys::STRUCTURE_TYPE_SUBMIT_INFO,
+            pNext: std::ptr::null(),
+            waitSemaphoreCount: self.wait_semaphores.len() as u32,
+            pWaitSemaphores: self.wait_semaphores.as_ptr(),
+            pWaitDstStageMask: self.wait_stages.as_ptr(),
+            commandBufferCount: self.command_buffers.len() as u32,
+            pCommandBuffers: self.command_buffers.as_ptr(),
+            signalSemaphoreCount: self.signal_semaphores.len() as u32,
+            pSignalSemaphores: self.signal_semaphores.as_ptr(),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use super::submission_batch::{InFlightBatch, SubmissionBatch};
+use super::{Display, SwapchainState};
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::Instant;
+use vulkano::command_buffer::AutoCommandBuffer;
+use vulkano::swapchain::acquire_next_image;
+use vulkano::sync::{FlushError, GpuFuture};
+
+/// Where a pass belongs in the frame. Passes run stage by stage, and in the
+/// order they were added within a stage.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    /// Compute work which runs on the compute queue, alongside the rest of
+    /// the frame. Anything drawn from its results must use what the previous
+    /// frame computed. Without a separate compute queue these passes run
+    /// first on the graphics queue.
+    AsyncCompute,
+
+    /// Work which the scene reads, like stepping a simulation.
+    Simulation,
+
+    /// Offscreen passes which draw the scene, like the stereo eyes.
+    Scene,
+
+    /// Passes which read the scene drawn by the passes before them.
+    PostProcess,
+
+    /// Command buffers built by sketches with raw vulkano, after everything
+    /// the application draws offscreen and before the swapchain's render
+    /// pass draws the frame and its overlays.
+    Raw,
+}
+
+/// A primary command buffer and where it runs.
+struct Pass {
+    stage: Stage,
+    name: &'static str,
+    commands: Arc<AutoCommandBuffer>,
+}
+
+/// Collects every primary command buffer in the frame and submits them as
+/// one batch.
+///
+/// Passes are chained on the graphics queue behind the swapchain acquire,
+/// which vulkano submits together, followed by a semaphore, the render pass
+/// that draws into the swapchain image, the present, and the frame's fence.
+/// The names only appear in logs and errors so it's clear which pass failed
+/// and what ran before it.
+///
+/// With a separate compute queue the async compute passes are submitted
+/// there as their own `SubmissionBatch`, one call for every pass with a
+/// semaphore between each, which the graphics batch doesn't wait for. The
+/// next frame's submission waits on its fence before reusing anything it
+/// touched.
+///
+/// The graphics batch is chained after the previous frame's and tracked with
+/// the frames in flight, so submitting only blocks once the display's limit
+/// of queued frames is reached.
+#[derive(Default)]
+pub struct Submitter {
+    passes: Vec<Pass>,
+    input: Option<Instant>,
+}
+
+impl Submitter {
+    /// Add a pass which runs after every pass already added to the same or
+    /// an earlier stage.
+    pub fn add(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        commands: AutoCommandBuffer,
+    ) {
+        self.add_shared(stage, name, Arc::new(commands));
+    }
+
+    /// Add a pass like `add`, from a command buffer which someone else
+    /// holds on to as well.
+    pub fn add_shared(
+        &mut self,
+        stage: Stage,
+        name: &'static str,
+        commands: Arc<AutoCommandBuffer>,
+    ) {
+        self.passes.push(Pass {
+            stage,
+            name,
+            commands,
+        });
+    }
+
+    /// Note when the newest input the frame was recorded from arrived, so
+    /// its age counts towards the frame's latency.
+    pub fn set_input_time(&mut self, input: Instant) {
+        self.input = Some(input);
+    }
+
+    /// Submit every pass, then draw and present the frame.
+    ///
+    /// @param graphics_queue_subbuffers a vector of secondary command buffers
+    /// to be executed in the swapchain's render pass, after every pass.
+    /// Anything written by the passes is visible to them.
+    pub fn submit(
+        mut self,
+        display: &mut Display,
+        graphics_queue_subbuffers: Vec<AutoCommandBuffer>,
+    ) -> Result<SwapchainState> {
+        // a stable sort keeps the order within each stage
+        self.passes.sort_by_key(|pass| pass.stage);
+        log::trace!(
+            "submitting {:?}",
+            self.passes.iter().map(|pass| pass.name).collect::<Vec<_>>()
+        );
+
+        if let Some(mut previous) = display.compute_in_flight.take() {
+            display
+                .failure_capture
+                .trace(|| "wait for the previous async compute".to_owned());
+            previous
+                .wait(None)
+                .context("unable to complete the previous async compute")?;
+        }
+        if display.has_async_compute() {
+            let compute_passes = self
+                .passes
+                .iter()
+                .take_while(|pass| pass.stage == Stage::AsyncCompute)
+                .count();
+            let remaining = self.passes.split_off(compute_passes);
+            let compute_passes = std::mem::replace(&mut self.passes, remaining);
+            let family = display.compute_queue.family().id();
+            display.failure_capture.trace(|| {
+                format!(
+                    "submit the async compute passes {:?} to queue family {}",
+                    compute_passes
+                        .iter()
+                        .map(|pass| pass.name)
+                        .collect::<Vec<_>>(),
+                    family
+                )
+            });
+            display.compute_in_flight =
+                submit_async_compute(display, compute_passes)?;
+        }
+
+        let depth = display.frames_in_flight.depth();
+        display
+            .failure_capture
+            .trace(|| format!("wait for one of {} frame slots", depth));
+        let slot = display.frames_in_flight.begin_frame()?;
+        display.failure_capture.trace(|| {
+            format!("began slot {} after {:?}", slot.index, slot.waited)
+        });
+        let acquire_started = Instant::now();
+        let (image_index, suboptimal, acquire_swapchain_future) =
+            acquire_next_image(display.swapchain.clone(), None).with_context(
+                || "unable to acquire next frame for rendering",
+            )?;
+        let acquired = Instant::now();
+        display.failure_capture.trace(|| {
+            format!(
+                "acquired swapchain image {}, suboptimal {}, after {:?}",
+                image_index,
+                suboptimal,
+                acquired - acquire_started
+            )
+        });
+
+        let subbuffers = graphics_queue_subbuffers.len();
+        let render_buffer = display.build_render_pass_command_buffer(
+            graphics_queue_subbuffers,
+            image_index,
+        )?;
+        let graphics_family = display.graphics_queue.family().id();
+
+        let mut passes_future: Box<dyn GpuFuture + Send + Sync> = match display
+            .frames_in_flight
+            .newest()
+        {
+            Some(previous) => Box::new(previous.join(acquire_swapchain_future)),
+            None => Box::new(acquire_swapchain_future),
+        };
+        for pass in self.passes {
+            let name = pass.name;
+            let stage = pass.stage;
+            // a check can submit without the application starting tracy
+            #[cfg(feature = "tracy")]
+            let _span = tracy_client::Client::running().map(|client| {
+                client.span_alloc(Some(name), "submit", file!(), line!(), 0)
+            });
+            display.failure_capture.trace(|| {
+                format!(
+                    "execute the {} pass ({:?}) on queue family {}",
+                    name, stage, graphics_family
+                )
+            });
+            passes_future = Box::new(
+                passes_future
+                    .then_execute(display.graphics_queue.clone(), pass.commands)
+                    .with_context(|| {
+                        format!("unable to execute the {} pass", name)
+                    })?,
+            );
+        }
+
+        let present_family = display.present_queue.family().id();
+        display.failure_capture.trace(|| {
+            format!(
+                "signal a semaphore, execute the render pass with {} \
+                secondary command buffers into image {}, present on queue \
+                family {}, then signal the fence and flush",
+                subbuffers, image_index, present_family
+            )
+        });
+
+        // the semaphore makes every pass's writes available to the render
+        // pass which follows
+        let frame: Box<dyn GpuFuture + Send + Sync> = Box::new(
+            passes_future
+                .then_signal_semaphore()
+                .then_execute(display.graphics_queue.clone(), render_buffer)
+                .with_context(|| {
+                    "unable to execute the display command buffer"
+                })?
+                .then_swapchain_present(
+                    display.present_queue.clone(),
+                    display.swapchain.clone(),
+                    image_index,
+                ),
+        );
+        let frame = match frame.then_signal_fence_and_flush() {
+            Ok(frame) => frame,
+            Err(error) => {
+                display
+                    .failure_capture
+                    .trace(|| format!("flush failed: {:?}", error));
+                // the failed frame is dropped and the next one is the retry,
+                // on a rebuilt swapchain since the image is never released
+                if matches!(error, FlushError::SurfaceLost)
+                    && display.reselect_present_queue()?
+                {
+                    return Ok(SwapchainState::NeedsRebuild);
+                }
+                return Err(error)
+                    .context("unable to present, signal, and flush");
+            }
+        };
+        display.present_retried = false;
+        display.frames_in_flight.end_frame(
+            slot,
+            Arc::new(frame),
+            acquire_started,
+            acquired,
+            self.input,
+        );
+        display.presented_image = Some(image_index);
+
+        if suboptimal {
+            Ok(SwapchainState::NeedsRebuild)
+        } else {
+            Ok(SwapchainState::Optimal)
+        }
+    }
+}
+
+/// Submit the passes to the compute queue in one call, each waiting for the
+/// one before it, returning the batch in flight.
+fn submit_async_compute(
+    display: &Display,
+    passes: Vec<Pass>,
+) -> Result<Option<InFlightBatch>> {
+    if passes.is_empty() {
+        return Ok(None);
+    }
+    let names: Vec<&str> = passes.iter().map(|pass| pass.name).collect();
+    let mut batch = SubmissionBatch::new(display.device.clone());
+    for pass in passes {
+        batch.chain(pass.commands)?;
+    }
+    let in_flight = batch.flush(&display.compute_queue).with_context(|| {
+        format!("unable to submit the async compute passes {:?}", names)
+    })?;
+    Ok(Some(in_flight))
+}
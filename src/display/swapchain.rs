@@ -1,3 +1,4 @@
+use super::{CapturePolicy, LatencyMode};
 use anyhow::{Context, Result};
 use log;
 use std::cmp::{max, min};
@@ -12,14 +13,28 @@ use vulkano::image::{swapchain::SwapchainImage, ImageUsage};
 use vulkano::instance::PhysicalDevice;
 use vulkano::single_pass_renderpass;
 use vulkano::swapchain::{
-    Capabilities, ColorSpace, CompositeAlpha, FullscreenExclusive, PresentMode,
-    Surface, Swapchain,
+    Capabilities, ColorSpace, CompositeAlpha, PresentMode, Surface, Swapchain,
 };
 use vulkano::sync::SharingMode;
 use winit::window::Window;
 
 type DynRenderPass = dyn RenderPassAbstract + Send + Sync;
 
+/// The swapchain, its images, and the format they were created with.
+type SwapchainParts = (
+    Arc<Swapchain<Arc<Window>>>,
+    Vec<Arc<SwapchainImage<Arc<Window>>>>,
+    SurfaceFormatInfo,
+);
+
+/// The format and color space of the swapchain's images, which say how the
+/// values in a presented image are shown and so how to read them back.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SurfaceFormatInfo {
+    pub format: Format,
+    pub color_space: ColorSpace,
+}
+
 /// Build a render pass which uses the maximum multisampling level supported
 /// by this device.
 pub fn create_render_pass(
@@ -77,7 +92,7 @@ fn pick_sample_count(physical_device: &PhysicalDevice) -> u32 {
 pub fn create_framebuffers(
     device: &Arc<Device>,
     color_format: Format,
-    swapchain_images: &[Arc<SwapchainImage<Window>>],
+    swapchain_images: &[Arc<SwapchainImage<Arc<Window>>>],
     render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
 ) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
     swapchain_images
@@ -104,60 +119,92 @@ pub fn create_framebuffers(
         .collect::<Vec<_>>()
 }
 
-/// Construct a swapchain and it's owned images
+/// Construct a swapchain and it's owned images.
+///
+/// Everything is chosen again from the surface's current capabilities, so
+/// a swapchain which replaces `old_swapchain` also picks up a change of
+/// present queue.
+#[allow(clippy::too_many_arguments)]
 pub fn create_swap_chain(
-    surface: &Arc<Surface<Window>>,
+    surface: &Arc<Surface<Arc<Window>>>,
     physical_device: &PhysicalDevice,
     logical_device: &Arc<Device>,
     graphics_queue: &Arc<Queue>,
     present_queue: &Arc<Queue>,
-) -> Result<(Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>)> {
+    latency_mode: LatencyMode,
+    capture_policy: CapturePolicy,
+    old_swapchain: Option<&Arc<Swapchain<Arc<Window>>>>,
+) -> Result<SwapchainParts> {
     let capabilities = surface.capabilities(*physical_device)?;
     let swap_format = choose_swap_surface_format(&capabilities);
-    let swap_present_mode = choose_swap_present_mode(&capabilities);
+    let swap_present_mode =
+        choose_swap_present_mode(&capabilities, latency_mode);
     let swap_extent = choose_swap_extent(surface, &capabilities);
     let swap_image_count = choose_image_count(&capabilities);
     let sharing_mode = choose_sharing_mode(graphics_queue, present_queue);
 
+    // the last frame is copied before the swapchain is rebuilt, so it can
+    // be shown while everything else is rebuilt
     let image_usage = ImageUsage {
         color_attachment: true,
+        transfer_source: cfg!(feature = "rebuild-fade")
+            && capabilities.supported_usage_flags.transfer_source,
         ..ImageUsage::none()
     };
 
-    let (swapchain, images) = Swapchain::new(
-        logical_device.clone(),
-        surface.clone(),
-        swap_image_count,
-        swap_format.0,
-        swap_extent,
-        1,
-        image_usage,
-        sharing_mode,
-        capabilities.current_transform,
-        CompositeAlpha::Opaque,
-        swap_present_mode,
-        FullscreenExclusive::AppControlled,
-        false,
-        swap_format.1,
-    )
+    let (swapchain, images) = match old_swapchain {
+        None => Swapchain::new(
+            logical_device.clone(),
+            surface.clone(),
+            swap_image_count,
+            swap_format.format,
+            swap_extent,
+            1,
+            image_usage,
+            sharing_mode,
+            capabilities.current_transform,
+            CompositeAlpha::Opaque,
+            swap_present_mode,
+            capture_policy.fullscreen_exclusive(),
+            false,
+            swap_format.color_space,
+        ),
+        Some(old_swapchain) => Swapchain::with_old_swapchain(
+            logical_device.clone(),
+            surface.clone(),
+            swap_image_count,
+            swap_format.format,
+            swap_extent,
+            1,
+            image_usage,
+            sharing_mode,
+            capabilities.current_transform,
+            CompositeAlpha::Opaque,
+            swap_present_mode,
+            capture_policy.fullscreen_exclusive(),
+            false,
+            swap_format.color_space,
+            old_swapchain.clone(),
+        ),
+    }
     .context("unable to build swapchain")?;
 
-    Ok((swapchain, images))
+    Ok((swapchain, images, swap_format))
 }
 
+/// Share the swapchain images between the graphics and present families
+/// when they differ. This is chosen whenever the swapchain is built since
+/// the present queue can move to another family.
 fn choose_sharing_mode(
     graphics_queue: &Arc<Queue>,
     present_queue: &Arc<Queue>,
 ) -> SharingMode {
-    let same_queue =
-        graphics_queue.id_within_family() == present_queue.id_within_family();
-    if same_queue {
+    let graphics_family = graphics_queue.family().id();
+    let present_family = present_queue.family().id();
+    if graphics_family == present_family {
         SharingMode::Exclusive
     } else {
-        SharingMode::Concurrent(vec![
-            graphics_queue.id_within_family(),
-            present_queue.id_within_family(),
-        ])
+        SharingMode::Concurrent(vec![graphics_family, present_family])
     }
 }
 
@@ -173,7 +220,7 @@ fn choose_image_count(capabilities: &Capabilities) -> u32 {
 /// Select a format and color space from the available formats
 fn choose_swap_surface_format(
     capabilities: &Capabilities,
-) -> (Format, ColorSpace) {
+) -> SurfaceFormatInfo {
     log::info!("display formats {:?}", capabilities.supported_formats);
 
     let (format, color_space) = *capabilities
@@ -187,13 +234,24 @@ fn choose_swap_surface_format(
 
     log::info!("chosen format: {:?}", (format, color_space));
 
-    (format, color_space)
+    SurfaceFormatInfo {
+        format,
+        color_space,
+    }
 }
 
-/// Select the presentation mode
-fn choose_swap_present_mode(capabilities: &Capabilities) -> PresentMode {
-    let mode = if capabilities.present_modes.mailbox {
+/// Select the presentation mode. Reduced latency falls back to immediate,
+/// which can tear, rather than fifo, which queues frames.
+fn choose_swap_present_mode(
+    capabilities: &Capabilities,
+    latency_mode: LatencyMode,
+) -> PresentMode {
+    let present_modes = &capabilities.present_modes;
+    let reduced = latency_mode != LatencyMode::Throughput;
+    let mode = if present_modes.mailbox {
         PresentMode::Mailbox
+    } else if reduced && present_modes.immediate {
+        PresentMode::Immediate
     } else {
         PresentMode::Fifo
     };
@@ -203,9 +261,11 @@ fn choose_swap_present_mode(capabilities: &Capabilities) -> PresentMode {
 
 /// Select the swapchain presentation extent.
 /// Some window managers will automatically fill the current_extent property.
-/// Otherwise, an extent will need to be decided by hand.
+/// Otherwise, an extent will need to be decided by hand, always in physical
+/// pixels: the logical size would be upscaled, and blurry, under fractional
+/// scaling.
 fn choose_swap_extent(
-    surface: &Arc<Surface<Window>>,
+    surface: &Arc<Surface<Arc<Window>>>,
     capabilities: &Capabilities,
 ) -> [u32; 2] {
     // if an extent already exists, just use it
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::swapchain::Surface;
+use winit::window::Window;
+
+/// The sizes a window has at once, which only agree at a scale factor of 1.
+///
+/// With fractional scaling, like 125% on Wayland, a window asked for in
+/// logical pixels covers a fractional number of physical ones, and the
+/// surface may or may not report an extent of its own. The swapchain,
+/// viewports, cursor, and picking all work in physical pixels, only the
+/// window's requested size is logical.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WindowSizes {
+    /// The window's inner size in logical pixels.
+    pub logical: [f64; 2],
+
+    /// The window's inner size in physical pixels, what the swapchain is
+    /// built for when the surface leaves it up to the application.
+    pub physical: [u32; 2],
+
+    /// The surface's current extent, or none when the compositor leaves it
+    /// to the swapchain, as Wayland does.
+    pub surface: Option<[u32; 2]>,
+
+    /// Physical pixels per logical pixel.
+    pub scale_factor: f64,
+}
+
+impl WindowSizes {
+    /// Read the sizes of the surface's window as they are now.
+    pub fn of(
+        surface: &Arc<Surface<Arc<Window>>>,
+        device: &Device,
+    ) -> Result<Self> {
+        let window = surface.window();
+        let scale_factor = window.scale_factor();
+        let physical = window.inner_size();
+        let logical = physical.to_logical::<f64>(scale_factor);
+        let capabilities = surface
+            .capabilities(device.physical_device())
+            .context("unable to get the surface capabilities")?;
+        Ok(Self {
+            logical: [logical.width, logical.height],
+            physical: [physical.width, physical.height],
+            surface: capabilities.current_extent,
+            scale_factor,
+        })
+    }
+
+    /// One line for each size, for logs and overlays.
+    pub fn describe(&self) -> Vec<String> {
+        let [lw, lh] = self.logical;
+        let [pw, ph] = self.physical;
+        let surface = match self.surface {
+            Some([sw, sh]) => format!("{}x{}", sw, sh),
+            None => "set by the swapchain".to_owned(),
+        };
+        vec![
+            format!("logical  {:.1}x{:.1} @{}x", lw, lh, self.scale_factor),
+            format!("physical {}x{}", pw, ph),
+            format!("surface  {}", surface),
+        ]
+    }
+}
+
+/// A position in logical pixels in physical pixels, like a point from a ui
+/// laid out in logical pixels to compare with the cursor.
+pub fn logical_to_physical(position: [f32; 2], scale_factor: f64) -> [f32; 2] {
+    let scale = scale_factor as f32;
+    [position[0] * scale, position[1] * scale]
+}
+
+/// A position in physical pixels, like the cursor, in logical pixels.
+pub fn physical_to_logical(position: [f32; 2], scale_factor: f64) -> [f32; 2] {
+    let scale = scale_factor as f32;
+    [position[0] / scale, position[1] / scale]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scale factors compositors use, 125% and 150% being the fractional
+    /// ones.
+    const SCALE_FACTORS: [f64; 4] = [1.0, 1.25, 1.5, 2.0];
+
+    #[test]
+    fn positions_round_trip_at_common_scale_factors() {
+        let cursor = [1023.5, 767.25];
+        for &scale_factor in SCALE_FACTORS.iter() {
+            let logical = physical_to_logical(cursor, scale_factor);
+            let [x, y] = logical_to_physical(logical, scale_factor);
+            assert!(
+                (x - cursor[0]).abs() < 1e-3 && (y - cursor[1]).abs() < 1e-3,
+                "{:?} came back as {:?} at {}x",
+                cursor,
+                [x, y],
+                scale_factor
+            );
+        }
+    }
+
+    #[test]
+    fn logical_positions_are_smaller_at_fractional_scales() {
+        assert_eq!(physical_to_logical([150.0, 75.0], 1.5), [100.0, 50.0]);
+        assert_eq!(logical_to_physical([100.0, 50.0], 1.25), [125.0, 62.5]);
+    }
+}
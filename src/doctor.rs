@@ -0,0 +1,342 @@
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use vulkan_starter::application::debug::BarrierValidator;
+use vulkan_starter::display::{Display, HeadlessDisplay};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, CommandBuffer, SubpassContents,
+};
+use vulkano::format::{ClearValue, Format};
+use vulkano::framebuffer::Framebuffer;
+use vulkano::image::{AttachmentImage, ImageUsage};
+use vulkano::instance::{
+    layers_list, loader, Instance, InstanceExtensions, PhysicalDevice,
+};
+use vulkano::sync::GpuFuture;
+use vulkano_win::VkSurfaceBuild;
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+/// The color the offscreen frame is cleared to, and read back as.
+const CLEAR_COLOR: [u8; 4] = [64, 128, 192, 255];
+
+/// How a check turned out.
+enum Outcome {
+    Pass(String),
+
+    /// The check failed, with a suggestion for how to fix it.
+    Fail(String, &'static str),
+
+    /// The check couldn't run, usually because an earlier check failed.
+    Skip(String),
+}
+
+/// Run through everything the starter needs from the vulkan setup, printing
+/// whether each step works and how to fix the ones which don't.
+///
+/// The output is meant to be pasted into a bug report, so it's printed
+/// rather than logged. Returns an error when any check fails.
+pub fn run() -> Result<()> {
+    let mut failures = 0;
+    let mut report = |name: &str, outcome: Outcome| match outcome {
+        Outcome::Pass(detail) => println!("[pass] {}: {}", name, detail),
+        Outcome::Fail(detail, fix) => {
+            failures += 1;
+            println!("[FAIL] {}: {}\n       fix: {}", name, detail, fix);
+        }
+        Outcome::Skip(reason) => println!("[skip] {}: {}", name, reason),
+    };
+
+    let loader_present = loader::auto_loader().is_ok();
+    report("vulkan loader", check_loader());
+    if !loader_present {
+        bail!("the vulkan loader is missing, nothing else can be checked");
+    }
+
+    report("instance", check_instance(false));
+    report("validation layers", check_layers());
+    report("instance with validation", check_instance(true));
+
+    match check_devices() {
+        Ok(outcome) => report("devices", outcome),
+        Err(error) => report(
+            "devices",
+            Outcome::Fail(
+                format!("{:#}", error),
+                "update your gpu driver, or install mesa's vulkan drivers \
+                (lavapipe renders on the cpu)",
+            ),
+        ),
+    }
+
+    report("window surface", check_surface());
+
+    match Display::create_headless() {
+        Ok(headless) => {
+            report(
+                "headless device",
+                Outcome::Pass(format!(
+                    "{} ({:?})",
+                    headless.device.physical_device().name(),
+                    headless.backend
+                )),
+            );
+            let outcome = match render_offscreen_frame(&headless) {
+                Ok(()) => Outcome::Pass("cleared and read back".to_owned()),
+                Err(error) => Outcome::Fail(
+                    format!("{:#}", error),
+                    "update your gpu driver, the driver accepted the device \
+                    but couldn't render with it",
+                ),
+            };
+            report("offscreen frame", outcome);
+        }
+        Err(error) => {
+            report(
+                "headless device",
+                Outcome::Fail(
+                    format!("{:#}", error),
+                    "no device supports what the starter needs, large \
+                    points and a graphics queue, update your gpu driver",
+                ),
+            );
+            report(
+                "offscreen frame",
+                Outcome::Skip("there's no device".to_owned()),
+            );
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} checks failed", failures);
+    }
+    println!("everything looks healthy");
+    Ok(())
+}
+
+fn check_loader() -> Outcome {
+    match loader::auto_loader() {
+        Ok(_) => Outcome::Pass("found".to_owned()),
+        Err(error) => Outcome::Fail(
+            format!("{}", error),
+            "install the vulkan loader, libvulkan1 on debian and ubuntu, \
+            vulkan-loader on fedora and arch, or the Vulkan SDK",
+        ),
+    }
+}
+
+fn check_layers() -> Outcome {
+    let layers: Vec<String> = match layers_list() {
+        Ok(layers) => layers.map(|layer| layer.name().to_owned()).collect(),
+        Err(error) => {
+            return Outcome::Fail(
+                format!("{}", error),
+                "reinstall the vulkan loader",
+            )
+        }
+    };
+    if layers.iter().any(|layer| layer == VALIDATION_LAYER) {
+        Outcome::Pass(format!(
+            "{} of {} layers",
+            VALIDATION_LAYER,
+            layers.len()
+        ))
+    } else {
+        Outcome::Fail(
+            format!("{} is missing, found {:?}", VALIDATION_LAYER, layers),
+            "install vulkan-validationlayers, or the Vulkan SDK, debug \
+            builds run without validation until then",
+        )
+    }
+}
+
+/// Create a bare instance, or one with the validation layer and the debug
+/// extension which debug builds use.
+fn check_instance(validation: bool) -> Outcome {
+    let (extensions, layers) = if validation {
+        let extensions = InstanceExtensions {
+            ext_debug_utils: true,
+            ..InstanceExtensions::none()
+        };
+        (extensions, vec![VALIDATION_LAYER])
+    } else {
+        (InstanceExtensions::none(), vec![])
+    };
+    match Instance::new(None, &extensions, layers) {
+        Ok(_) => Outcome::Pass("created".to_owned()),
+        Err(error) if validation => Outcome::Fail(
+            format!("{}", error),
+            "install vulkan-validationlayers, or the Vulkan SDK",
+        ),
+        Err(error) => Outcome::Fail(
+            format!("{}", error),
+            "install a vulkan driver for your gpu, mesa-vulkan-drivers on \
+            linux, or the latest driver from your gpu's vendor",
+        ),
+    }
+}
+
+/// List the physical devices, failing when none of them supports vulkan 1.1.
+fn check_devices() -> Result<Outcome> {
+    let instance = Instance::new(None, &InstanceExtensions::none(), None)
+        .context("unable to create an instance")?;
+    let devices: Vec<String> = PhysicalDevice::enumerate(&instance)
+        .map(|device| {
+            let version = device.api_version();
+            format!(
+                "{} (vulkan {}.{}, {:?})",
+                device.name(),
+                version.major,
+                version.minor,
+                device.ty()
+            )
+        })
+        .collect();
+    if devices.is_empty() {
+        bail!("no physical devices");
+    }
+    let any_current = PhysicalDevice::enumerate(&instance).any(|device| {
+        let version = device.api_version();
+        (version.major, version.minor) >= (1, 1)
+    });
+    Ok(if any_current {
+        Outcome::Pass(devices.join(", "))
+    } else {
+        Outcome::Fail(
+            devices.join(", "),
+            "your gpu driver predates vulkan 1.1, update it",
+        )
+    })
+}
+
+/// Create a hidden window and check that some device can present to it in
+/// at least one format.
+fn check_surface() -> Outcome {
+    if cfg!(all(unix, not(target_os = "macos")))
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        return Outcome::Skip("there's no display".to_owned());
+    }
+    let result = (|| -> Result<String> {
+        let instance =
+            Instance::new(None, &vulkano_win::required_extensions(), None)
+                .context("unable to create a windowed instance")?;
+        let event_loop: EventLoop<()> = EventLoop::new();
+        let surface = WindowBuilder::new()
+            .with_visible(false)
+            .build_vk_surface(&event_loop, instance.clone())
+            .context("unable to create the window's surface")?;
+        for device in PhysicalDevice::enumerate(&instance) {
+            let capabilities = match surface.capabilities(device) {
+                Ok(capabilities) => capabilities,
+                Err(_) => continue,
+            };
+            if let Some((format, _)) = capabilities.supported_formats.first() {
+                return Ok(format!(
+                    "{} presents {} formats, like {:?}",
+                    device.name(),
+                    capabilities.supported_formats.len(),
+                    format
+                ));
+            }
+        }
+        bail!("no device has a swapchain format for the window")
+    })();
+    match result {
+        Ok(detail) => Outcome::Pass(detail),
+        Err(error) => Outcome::Fail(
+            format!("{:#}", error),
+            "check that the desktop session uses the gpu's driver, remote \
+            and virtual sessions often can't present with vulkan",
+        ),
+    }
+}
+
+/// Clear a small image in a render pass and read it back.
+fn render_offscreen_frame(headless: &HeadlessDisplay) -> Result<()> {
+    let device = &headless.device;
+    let render_pass = Arc::new(
+        vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::R8G8B8A8Unorm,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )
+        .context("unable to create the render pass")?,
+    );
+    let image = AttachmentImage::with_usage(
+        device.clone(),
+        [4, 4],
+        Format::R8G8B8A8Unorm,
+        ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            ..ImageUsage::none()
+        },
+    )
+    .context("unable to create the image")?;
+    let framebuffer = Arc::new(
+        Framebuffer::start(render_pass)
+            .add(image.clone())?
+            .build()
+            .context("unable to create the framebuffer")?,
+    );
+    let readback = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::transfer_destination(),
+        true,
+        (0..4 * 4 * 4).map(|_| 0u8),
+    )
+    .context("unable to create the readback buffer")?;
+
+    let clear = CLEAR_COLOR.map(|channel| channel as f32 / 255.0);
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            headless.graphics_queue.family(),
+        )?,
+        &headless.graphics_queue,
+    );
+    builder
+        .begin_render_pass(
+            framebuffer,
+            SubpassContents::Inline,
+            vec![ClearValue::Float(clear)],
+        )
+        .context("unable to begin the render pass")?;
+    builder
+        .end_render_pass()
+        .context("unable to end the render pass")?;
+    builder
+        .copy_image_to_buffer(image, readback.clone())
+        .context("unable to copy the image")?;
+    builder
+        .build()?
+        .execute(headless.graphics_queue.clone())?
+        .then_signal_fence_and_flush()?
+        .wait(None)
+        .context("the frame never finished")?;
+
+    let texels = readback.read()?;
+    let first = &texels[..4];
+    let close = first
+        .iter()
+        .zip(CLEAR_COLOR.iter())
+        .all(|(&read, &expected)| (read as i32 - expected as i32).abs() <= 1);
+    if !close {
+        bail!("read back {:?}, expected {:?}", first, CLEAR_COLOR);
+    }
+    Ok(())
+}
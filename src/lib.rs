@@ -0,0 +1,14 @@
+//! A quick-and-dirty starting point for Vulkan sketches.
+//!
+//! An [`Application`] opens a window and draws a [`Sketch`] every frame,
+//! along with whichever post processing effects are turned on. The helper
+//! modules under `application` are the building blocks sketches draw with.
+
+pub mod application;
+pub mod display;
+pub mod logging;
+pub mod shader_compiler;
+pub mod texture;
+
+pub use application::{Application, Sketch};
+pub use display::Display;
@@ -0,0 +1,337 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use std::cell::RefCell;
+use std::fmt::{self, Write as FmtWrite};
+use textwrap::{termwidth, Options};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::fmt::format::{self, FormatEvent, FormatFields};
+use tracing_subscriber::fmt::FmtContext;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+#[cfg(feature = "telemetry")]
+mod telemetry;
+
+#[cfg(feature = "telemetry")]
+pub use telemetry::flush;
+
+/// The most capacity the formatting buffer keeps between records, so one
+/// huge message doesn't hold on to its memory for the rest of the run.
+const RETAINED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    // each record is formatted into this before it's wrapped, reused so
+    // logging doesn't allocate it for every record
+    static BUFFER: RefCell<String> = RefCell::new(String::new());
+}
+
+/// How log lines are written, picked with `LOG_FORMAT`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Lines wrapped on the terminal width, see `multiline_format`.
+    Pretty,
+
+    /// One json object per event, for tools which collect logs.
+    Json,
+}
+
+impl LogFormat {
+    /// Json when `LOG_FORMAT` is `json`, pretty printed otherwise.
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => {
+                LogFormat::Json
+            }
+            _ => LogFormat::Pretty,
+        }
+    }
+}
+
+/// Log at the level in `RUST_LOG`, or info, in the format `LOG_FORMAT`
+/// picks. Records from the `log` macros become tracing events.
+///
+/// With the telemetry feature every span is exported over OTLP as well,
+/// see `telemetry::layer`.
+pub fn start() -> Result<()> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+    let output = match LogFormat::from_env() {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .event_format(MultilineFormat)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .json()
+            .boxed(),
+    };
+    let subscriber = tracing_subscriber::registry().with(filter).with(output);
+    #[cfg(feature = "telemetry")]
+    let subscriber = subscriber.with(telemetry::layer()?);
+    subscriber.try_init().context("unable to start logging")?;
+    Ok(())
+}
+
+/// What one log line says, whether it came from a `log` record or a
+/// tracing event.
+pub struct LogLine<'a> {
+    pub level: Level,
+    pub file: Option<&'a str>,
+    pub line: Option<u32>,
+    pub message: &'a dyn fmt::Display,
+}
+
+/// Formats tracing events with `multiline_format`.
+pub struct MultilineFormat;
+
+impl<S, N> FormatEvent<S, N> for MultilineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _context: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        // events from the log macros carry the record's file and line
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        multiline_format(
+            &mut writer,
+            &Local::now(),
+            &LogLine {
+                level: *metadata.level(),
+                file: metadata.file(),
+                line: metadata.line(),
+                message: &EventFields(event),
+            },
+        )
+    }
+}
+
+/// A formatting function for lines which automaticaly wrap on the terminal
+/// width.
+///
+/// Validation can log hundreds of messages a second, so the record is
+/// formatted into a buffer kept for the thread, and the wrapped lines are
+/// written straight to `w` rather than joined into another string first.
+pub fn multiline_format(
+    w: &mut dyn fmt::Write,
+    now: &DateTime<Local>,
+    line: &LogLine,
+) -> fmt::Result {
+    BUFFER.with(|buffer| match buffer.try_borrow_mut() {
+        Ok(mut buffer) => {
+            let result = format_wrapped(w, now, line, &mut buffer);
+            buffer.clear();
+            buffer.shrink_to(RETAINED_CAPACITY);
+            result
+        }
+
+        // a record logged while formatting another, like from a Display
+        // impl which logs, gets a buffer of its own
+        Err(_) => format_wrapped(w, now, line, &mut String::new()),
+    })
+}
+
+fn format_wrapped(
+    w: &mut dyn fmt::Write,
+    now: &DateTime<Local>,
+    line: &LogLine,
+    full_line: &mut String,
+) -> fmt::Result {
+    let size = termwidth().min(74);
+    let wrap_options = Options::new(size)
+        .initial_indent("┏ ")
+        .subsequent_indent("┃ ");
+
+    writeln!(
+        full_line,
+        "{} [{}] [{}:{}]",
+        line.level,
+        now.format("%H:%M:%S%.6f"),
+        line.file.unwrap_or("<unnamed>"),
+        line.line.unwrap_or(0),
+    )
+    .expect("unable to format first log line");
+
+    write!(full_line, "{}", line.message).expect("unable to format log!");
+
+    // the same text textwrap::fill would join, and a newline
+    for (i, wrapped) in textwrap::wrap(full_line.as_str(), wrap_options)
+        .iter()
+        .enumerate()
+    {
+        if i > 0 {
+            w.write_char('\n')?;
+        }
+        w.write_str(wrapped)?;
+    }
+    w.write_char('\n')
+}
+
+/// An event's message followed by its other fields as `name=value`.
+struct EventFields<'a>(&'a Event<'a>);
+
+impl fmt::Display for EventFields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut writer = FieldWriter { f, result: Ok(()) };
+        self.0.record(&mut writer);
+        writer.result
+    }
+}
+
+struct FieldWriter<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    result: fmt::Result,
+}
+
+impl Visit for FieldWriter<'_, '_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // a log record's target, file, and line are fields too, but the
+        // first line already says where it came from
+        if self.result.is_err() || field.name().starts_with("log.") {
+            return;
+        }
+        self.result = if field.name() == "message" {
+            write!(self.f, "{:?}", value)
+        } else {
+            write!(self.f, " {}={:?}", field.name(), value)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// The formatter before it reused its buffer, which the output must
+    /// match byte for byte.
+    fn allocating_format(
+        w: &mut dyn fmt::Write,
+        now: &DateTime<Local>,
+        line: &LogLine,
+    ) -> fmt::Result {
+        let size = termwidth().min(74);
+        let wrap_options = Options::new(size)
+            .initial_indent("┏ ")
+            .subsequent_indent("┃ ");
+
+        let mut full_line = String::new();
+        writeln!(
+            full_line,
+            "{} [{}] [{}:{}]",
+            line.level,
+            now.format("%H:%M:%S%.6f"),
+            line.file.unwrap_or("<unnamed>"),
+            line.line.unwrap_or(0),
+        )
+        .expect("unable to format first log line");
+
+        write!(&mut full_line, "{}", line.message)
+            .expect("unable to format log!");
+
+        writeln!(w, "{}", textwrap::fill(&full_line, wrap_options))
+    }
+
+    type Format =
+        fn(&mut dyn fmt::Write, &DateTime<Local>, &LogLine) -> fmt::Result;
+
+    /// Format `message` as a validation error would be logged.
+    fn format(
+        formatter: Format,
+        now: &DateTime<Local>,
+        message: &str,
+        out: &mut String,
+    ) -> fmt::Result {
+        out.clear();
+        formatter(
+            out,
+            now,
+            &LogLine {
+                level: Level::ERROR,
+                file: Some("src/display/instance.rs"),
+                line: Some(300),
+                message: &format_args!("Vulkan Debug Callback\n{:?}", message),
+            },
+        )
+    }
+
+    /// A validation message as long as the layers' longest, several
+    /// kilobytes of object handles and spec text.
+    fn long_message(kilobytes: usize) -> String {
+        let mut message = String::from(
+            "Validation Error: [ VUID-vkCmdDraw-None-02699 ] Object 0: \
+            handle = 0x5581e0c7a2d0, type = VK_OBJECT_TYPE_DESCRIPTOR_SET;",
+        );
+        while message.len() < kilobytes * 1024 {
+            message.push_str(
+                " Descriptor set 0x5581e0c7a2d0 encountered the following \
+                validation error at vkCmdDraw time: Image layout specified \
+                at vkCmdBindDescriptorSets doesn't match actual image \
+                layout.\n",
+            );
+        }
+        message
+    }
+
+    #[test]
+    fn reusing_the_buffer_writes_the_same_lines() -> fmt::Result {
+        let cases = vec![
+            String::new(),
+            "a short message".to_owned(),
+            "several\nlines\n\nwith a blank one\n".to_owned(),
+            "a line long enough to wrap on the terminal width, or on seventy \
+            four columns when the terminal is wider than that"
+                .repeat(3),
+            "unicode ┏━┓ wraps by width, not bytes ┃ äöü ".repeat(8),
+            long_message(4),
+        ];
+        let now = Local::now();
+        let (mut expected, mut actual) = (String::new(), String::new());
+        for case in &cases {
+            format(allocating_format, &now, case, &mut expected)?;
+            format(multiline_format, &now, case, &mut actual)?;
+            assert_eq!(actual, expected);
+        }
+        Ok(())
+    }
+
+    /// Time both formatters on 1, 4, and 16 KiB messages. Run it with
+    /// `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn reusing_the_buffer_keeps_up_with_allocating() -> fmt::Result {
+        const ITERATIONS: u32 = 2000;
+        let time = |formatter: Format, message: &str| {
+            let now = Local::now();
+            let mut out = String::new();
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                format(formatter, &now, message, &mut out)?;
+            }
+            Ok::<Duration, fmt::Error>(start.elapsed())
+        };
+        for &kilobytes in &[1, 4, 16] {
+            let message = long_message(kilobytes);
+            let allocating = time(allocating_format, &message)?;
+            let reusing = time(multiline_format, &message)?;
+            println!(
+                "{} KiB messages x{}: allocating {:?}, reusing {:?} ({:.2}x)",
+                kilobytes,
+                ITERATIONS,
+                allocating,
+                reusing,
+                allocating.as_secs_f64() / reusing.as_secs_f64()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Where spans are sent when `OTEL_EXPORTER_OTLP_ENDPOINT` isn't set, a
+/// collector's usual grpc port on this machine.
+const DEFAULT_ENDPOINT: &str = "http://localhost:4317";
+
+/// The service the spans are reported under.
+const SERVICE_NAME: &str = "vulkan_starter";
+
+/// The exporter, kept so `flush` can reach it. The event loop exits the
+/// process without returning to `main`, so nothing would drop it.
+static TELEMETRY: Mutex<Option<Telemetry>> = Mutex::new(None);
+
+struct Telemetry {
+    provider: TracerProvider,
+
+    // the exporter's batches are sent from this runtime, so it's dropped
+    // after the provider
+    _runtime: Runtime,
+}
+
+/// A layer which exports every span over OTLP to the collector at
+/// `OTEL_EXPORTER_OTLP_ENDPOINT`, or on this machine.
+///
+/// Spans are batched and sent on a small tokio runtime of the exporter's
+/// own, since nothing else in the application runs one, so a slow or
+/// missing collector never stalls a frame.
+pub fn layer<S>() -> Result<OpenTelemetryLayer<S, Tracer>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .unwrap_or_else(|_| DEFAULT_ENDPOINT.to_owned());
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("telemetry")
+        .enable_all()
+        .build()
+        .context("unable to start the telemetry runtime")?;
+    let provider = {
+        // the exporter's channel and the batches are spawned on the
+        // runtime it's entered in
+        let _entered = runtime.enter();
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_tonic()
+            .with_endpoint(endpoint.clone())
+            .build()
+            .with_context(|| {
+                format!("unable to create the OTLP exporter for {}", endpoint)
+            })?;
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(Resource::new([KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )]))
+            .build()
+    };
+    let tracer = provider.tracer(SERVICE_NAME);
+    *TELEMETRY.lock().unwrap() = Some(Telemetry {
+        provider,
+        _runtime: runtime,
+    });
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// Send the spans still waiting in a batch, before the process exits.
+pub fn flush() {
+    let telemetry = TELEMETRY.lock().unwrap();
+    if let Some(telemetry) = telemetry.as_ref() {
+        for result in telemetry.provider.force_flush() {
+            if let Err(error) = result {
+                log::warn!("unable to export spans {}", error);
+            }
+        }
+    }
+}
@@ -1,13 +1,26 @@
-mod application;
-mod display;
+mod doctor;
+mod precompile;
 
-use anyhow::Result;
-use application::Application;
-use flexi_logger::DeferredNow;
-use flexi_logger::Logger;
-use flexi_logger::Record;
-use std::fmt::Write as FmtWrite;
-use textwrap::{termwidth, Options};
+use anyhow::{bail, Context, Result};
+use scopeguard::ScopeGuard;
+use std::path::{Path, PathBuf};
+use vulkan_starter::application::camera::CoordinateSpace;
+use vulkan_starter::application::capture_format::CaptureEncoding;
+use vulkan_starter::application::clock::TimeSource;
+#[cfg(feature = "replay")]
+use vulkan_starter::application::replay::ReplayPlayer;
+use vulkan_starter::application::shadertoy::{
+    SeedFit, SeedImage, ShadertoyMode, ShadertoyProject,
+};
+use vulkan_starter::display;
+use vulkan_starter::display::{AllocatorKind, CapturePolicy, LatencyMode};
+use vulkan_starter::{logging, Application, Display};
+
+/// mimalloc in place of the system allocator, see the `draw_call_bench`
+/// example.
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static ALLOC: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 fn main() -> Result<()> {
     let result = run();
@@ -18,43 +31,268 @@ fn main() -> Result<()> {
             error.root_cause()
         );
     }
+    #[cfg(feature = "telemetry")]
+    logging::flush();
     result
 }
 
 fn run() -> Result<()> {
-    Logger::with_env_or_str("info")
-        .format(multiline_format)
-        .start()?;
+    logging::start()?;
+
+    let simulation = std::env::args().nth(1);
+    match simulation.as_deref() {
+        Some("probe") => return probe(),
+        Some("doctor") | Some("--doctor") => return doctor::run(),
+        Some("precompile") => {
+            let directory = std::env::args().nth(2).context(
+                "usage: precompile <shader directory> [--write-spv]",
+            )?;
+            let write_spirv =
+                std::env::args().skip(3).any(|arg| arg == "--write-spv");
+            return precompile::run(Path::new(&directory), write_spirv);
+        }
+        Some("shadertoy") => {
+            let shader = std::env::args().nth(2).context(
+                "usage: shadertoy <shader.frag | project.shadertoy> \
+                [channel images...] [--seed-image <path.png>] \
+                [--seed-fit <fit | fill>]",
+            )?;
+            let mut channels = vec![];
+            let mut seed_image = None;
+            let mut seed_fit = SeedFit::Fit;
+            let mut args = std::env::args().skip(3);
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--seed-image" => {
+                        let path = args
+                            .next()
+                            .context("--seed-image needs a png path")?;
+                        seed_image = Some(PathBuf::from(path));
+                    }
+                    "--seed-fit" => {
+                        seed_fit = match args.next().as_deref() {
+                            Some("fit") => SeedFit::Fit,
+                            Some("fill") => SeedFit::Fill,
+                            _ => bail!("--seed-fit must be fit or fill"),
+                        };
+                    }
+                    _ => channels.push(PathBuf::from(arg)),
+                }
+            }
+            let seed = seed_image
+                .map(|path| SeedImage::load(&path, seed_fit))
+                .transpose()?;
+            return shadertoy(Path::new(&shader), &channels, seed);
+        }
+        _ => (),
+    }
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let lut = args
+        .windows(2)
+        .find(|pair| pair[0] == "--lut")
+        .map(|pair| PathBuf::from(&pair[1]));
+    let render_scale = args
+        .windows(2)
+        .find(|pair| pair[0] == "--render-scale")
+        .map(|pair| {
+            pair[1]
+                .parse::<f32>()
+                .with_context(|| format!("invalid render scale {:?}", pair[1]))
+        })
+        .transpose()?;
+    let seed = args
+        .windows(2)
+        .find(|pair| pair[0] == "--seed")
+        .map(|pair| {
+            pair[1]
+                .parse::<u64>()
+                .with_context(|| format!("invalid seed {:?}", pair[1]))
+        })
+        .transpose()?;
+    let simulation = simulation.filter(|arg| !arg.starts_with("--"));
+    let late_latch = args.iter().any(|arg| arg == "--late-latch");
+    let latency_mode =
+        if late_latch || args.iter().any(|arg| arg == "--low-latency") {
+            LatencyMode::Reduced { late_latch }
+        } else {
+            LatencyMode::Throughput
+        };
+    let capture_policy = args
+        .windows(2)
+        .find(|pair| pair[0] == "--capture")
+        .map(|pair| {
+            CapturePolicy::from_name(&pair[1]).with_context(|| {
+                format!(
+                    "invalid capture policy {:?}, expected default, \
+                    protected, or streaming",
+                    pair[1]
+                )
+            })
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    // a replay brings its own simulation and seed
+    #[cfg(feature = "replay")]
+    let replay = args
+        .windows(2)
+        .find(|pair| pair[0] == "--replay")
+        .map(|pair| ReplayPlayer::load(Path::new(&pair[1])))
+        .transpose()?;
+    #[cfg(feature = "replay")]
+    let (simulation, seed) = match &replay {
+        Some(player) => (player.simulation().map(str::to_owned), player.seed()),
+        None => (simulation, seed),
+    };
+
+    // the layers read their settings when the instance is created
+    if let Some(pair) = args
+        .windows(2)
+        .find(|pair| pair[0] == "--suppress-validation")
+    {
+        display::suppress_validation_messages(pair[1].split(','))?;
+    }
+    if let Some(pair) =
+        args.windows(2).find(|pair| pair[0] == "--validation-spam")
+    {
+        let threshold = pair[1].parse::<u32>().with_context(|| {
+            format!("invalid validation spam threshold {:?}", pair[1])
+        })?;
+        display::set_validation_spam_threshold(match threshold {
+            0 => None,
+            threshold => Some(threshold),
+        });
+    }
+
+    // the layer is enabled when the instance is created
+    #[cfg(all(feature = "api-dump", debug_assertions))]
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--api-dump") {
+        display::setup_api_dump_layer(true, Path::new(&pair[1]))?;
+    }
+
+    // the guard destroys the application in order if setting it up fails
+    // before the main loop takes it over
+    #[allow(unused_mut)]
+    let mut app = scopeguard::guard(
+        Application::initialize(
+            simulation.as_deref(),
+            lut,
+            render_scale,
+            seed,
+            latency_mode,
+            capture_policy,
+        )?,
+        Application::destroy,
+    );
+    #[cfg(feature = "replay")]
+    match (replay, args.windows(2).find(|pair| pair[0] == "--record")) {
+        (Some(_), Some(_)) => bail!("--record and --replay can't be combined"),
+        (Some(player), None) => app.play(player),
+        (None, Some(pair)) => {
+            app.record(PathBuf::from(&pair[1]), simulation.as_deref())
+        }
+        (None, None) => (),
+    }
+    #[cfg(feature = "network")]
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--connect") {
+        let addr = pair[1]
+            .parse()
+            .with_context(|| format!("invalid server address {:?}", pair[1]))?;
+        app.connect(addr)?;
+    }
+    if let Some(pair) =
+        args.windows(2).find(|pair| pair[0] == "--frames-in-flight")
+    {
+        let depth = pair[1].parse::<usize>().with_context(|| {
+            format!("invalid number of frames in flight {:?}", pair[1])
+        })?;
+        app.set_frames_in_flight(depth);
+    }
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--allocator") {
+        let kind = AllocatorKind::from_name(&pair[1]).with_context(|| {
+            format!(
+                "invalid allocator {:?}, expected pass-through or pooled",
+                pair[1]
+            )
+        })?;
+        app.set_allocator(kind);
+    }
+    #[cfg(feature = "device-report")]
+    if let Some(pair) =
+        args.windows(2).find(|pair| pair[0] == "--device-report")
+    {
+        app.save_device_report(Path::new(&pair[1]))?;
+    }
+    if let Some(pair) =
+        args.windows(2).find(|pair| pair[0] == "--capture-encoding")
+    {
+        let encoding =
+            CaptureEncoding::from_name(&pair[1]).with_context(|| {
+                format!(
+                    "invalid capture encoding {:?}, expected png8 or png16",
+                    pair[1]
+                )
+            })?;
+        app.set_capture_encoding(encoding);
+    }
+    if args.iter().any(|arg| arg == "--display-locked") {
+        let catch_up = args.iter().any(|arg| arg == "--catch-up");
+        app.set_time_source(TimeSource::DisplayLocked, catch_up);
+    }
+    if args.iter().any(|arg| arg == "--raw-ndc") {
+        app.set_coordinate_space(CoordinateSpace::Ndc);
+    }
+    if args.iter().any(|arg| arg == "--ecs") {
+        app.create_world()?;
+    }
+    #[cfg(any(feature = "scripting", feature = "lua"))]
+    if let Some(pair) = args.windows(2).find(|pair| pair[0] == "--script") {
+        app.load_script(Path::new(&pair[1]))?;
+    }
+    #[cfg(feature = "hot-reload")]
+    if args.iter().any(|arg| arg == "--watch-assets") {
+        app.watch_assets()?;
+    }
+    #[cfg(feature = "profiling")]
+    if args.iter().any(|arg| arg == "--puffin") {
+        app.start_puffin_server()?;
+    }
+    ScopeGuard::into_inner(app).main_loop()
+}
 
-    let app = Application::initialize()?;
-    app.main_loop()
+/// Open a window which runs a Shadertoy style fragment shader, or every
+/// pass of a `.shadertoy` project, with buffer A starting from `seed`.
+fn shadertoy(
+    shader: &Path,
+    channels: &[PathBuf],
+    seed: Option<SeedImage>,
+) -> Result<()> {
+    let project = match shader.extension() {
+        Some(extension) if extension == "shadertoy" => {
+            ShadertoyProject::load(shader)?
+        }
+        _ => ShadertoyProject::single(shader, channels)?,
+    };
+    let mut app = scopeguard::guard(Application::new()?, Application::destroy);
+    let mut mode = ShadertoyMode::with_project(app.display(), project)?;
+    if let Some(seed) = seed {
+        mode.seed_buffer(0, seed)?;
+    }
+    app.set_sketch(mode);
+    ScopeGuard::into_inner(app).main_loop()
 }
 
-/// A formatting function for lines which automaticaly wrap on the terminal
-/// width.
-fn multiline_format(
-    w: &mut dyn std::io::Write,
-    now: &mut DeferredNow,
-    record: &Record,
-) -> Result<(), std::io::Error> {
-    let size = termwidth().min(74);
-    let wrap_options = Options::new(size)
-        .initial_indent("┏ ")
-        .subsequent_indent("┃ ");
-
-    let mut full_line = String::new();
-    writeln!(
-        full_line,
-        "{} [{}] [{}:{}]",
-        record.level(),
-        now.now().format("%H:%M:%S%.6f"),
-        record.file().unwrap_or("<unnamed>"),
-        record.line().unwrap_or(0),
-    )
-    .expect("unable to format first log line");
-
-    write!(&mut full_line, "{}", &record.args())
-        .expect("unable to format log!");
-
-    writeln!(w, "{}", textwrap::fill(&full_line, wrap_options))
+/// Bring up a headless device and report what was found, without opening a
+/// window.
+fn probe() -> Result<()> {
+    let headless = Display::create_headless()?;
+    log::info!(
+        "created a headless device on {:?} ({:?}), graphics queue family \
+        {}, multiview supported: {}",
+        headless.device.physical_device().name(),
+        headless.backend,
+        headless.graphics_queue.family().id(),
+        headless.multiview_supported
+    );
+    Ok(())
 }
@@ -0,0 +1,56 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use vulkan_starter::display::Display;
+use vulkan_starter::shader_compiler::{self, ShaderCompiler};
+
+/// Compile and load every glsl file beneath a directory without opening a
+/// window.
+///
+/// Every failure is logged before returning, so one run reports all of the
+/// broken shaders. When `write_spirv` is set each compiled shader is written
+/// next to its source with a `.spv` suffix.
+///
+/// Returns an error when any shader fails to compile or load.
+pub fn run(directory: &Path, write_spirv: bool) -> Result<()> {
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let shaders = shader_compiler::find_shaders(directory)?;
+    let mut compiler = ShaderCompiler::new(vec![directory.to_path_buf()])?;
+
+    let mut failures = 0;
+    for shader in &shaders {
+        let result = compiler.compile_file(shader).and_then(|words| {
+            shader_compiler::create_module(&headless.device, &words)
+                .with_context(|| format!("unable to load {:?}", shader))?;
+            if write_spirv {
+                write_spirv_file(shader, &words)?;
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => log::info!("compiled {:?}", shader),
+            Err(error) => {
+                failures += 1;
+                log::error!("{:?}", error);
+            }
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} of {} shaders failed", failures, shaders.len());
+    }
+    log::info!("compiled all {} shaders in {:?}", shaders.len(), directory);
+    Ok(())
+}
+
+fn write_spirv_file(shader: &Path, words: &[u32]) -> Result<()> {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .collect();
+
+    let mut output = PathBuf::from(shader).into_os_string();
+    output.push(".spv");
+    std::fs::write(&output, bytes)
+        .with_context(|| format!("unable to write {:?}", output))
+}
@@ -0,0 +1,368 @@
+use anyhow::{anyhow, bail, Context, Result};
+use shaderc::{
+    CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind,
+    TargetEnv,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::pipeline::shader::ShaderModule;
+
+/// The vulkan version targeted by compiled shaders. This is the same target
+/// `vulkano_shaders::shader!` uses, so a shader which compiles here also
+/// compiles when it's embedded in the application.
+const TARGET_VULKAN_VERSION: u32 = (1 << 22) | (1 << 12);
+
+/// The file extensions recognized as glsl sources and the shader stage each
+/// one holds.
+const SHADER_EXTENSIONS: &[(&str, ShaderKind)] = &[
+    ("vert", ShaderKind::Vertex),
+    ("frag", ShaderKind::Fragment),
+    ("comp", ShaderKind::Compute),
+    ("geom", ShaderKind::Geometry),
+    ("tesc", ShaderKind::TessControl),
+    ("tese", ShaderKind::TessEvaluation),
+];
+
+/// The source lines shown before and after each line with an error.
+const CONTEXT_LINES: usize = 3;
+
+/// Compiles glsl files into spir-v at runtime.
+///
+/// `#include "file"` is resolved relative to the including file and
+/// `#include <file>` is resolved against the compiler's include directories.
+pub struct ShaderCompiler {
+    compiler: Compiler,
+    include_directories: Vec<PathBuf>,
+}
+
+impl ShaderCompiler {
+    pub fn new(include_directories: Vec<PathBuf>) -> Result<Self> {
+        Ok(Self {
+            compiler: Compiler::new()
+                .context("unable to create the glsl compiler")?,
+            include_directories,
+        })
+    }
+
+    /// The shader stage for a file, based on its extension.
+    pub fn shader_kind(path: &Path) -> Option<ShaderKind> {
+        let extension = path.extension()?.to_str()?;
+        SHADER_EXTENSIONS
+            .iter()
+            .find(|(known, _)| *known == extension)
+            .map(|&(_, kind)| kind)
+    }
+
+    /// Compile a glsl file into spir-v words.
+    ///
+    /// Compiler errors already name the file and line of each problem, so
+    /// they're returned as-is.
+    pub fn compile_file(&mut self, path: &Path) -> Result<Vec<u32>> {
+        let kind = Self::shader_kind(path).with_context(|| {
+            format!("{:?} does not have a known shader extension", path)
+        })?;
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        self.compile_source(&source, kind, path)
+    }
+
+    /// Compile glsl source which was read from, or stands in for, `path`.
+    /// Errors and relative includes use the path just as if the source had
+    /// been read from it.
+    pub fn compile_source(
+        &mut self,
+        source: &str,
+        kind: ShaderKind,
+        path: &Path,
+    ) -> Result<Vec<u32>> {
+        let include_directories = &self.include_directories;
+        let mut options = CompileOptions::new()
+            .context("unable to create the glsl compiler options")?;
+        options.set_target_env(TargetEnv::Vulkan, TARGET_VULKAN_VERSION);
+        options.set_include_callback(
+            |requested, include_type, requested_by, _depth| {
+                resolve_include(
+                    requested,
+                    include_type,
+                    requested_by,
+                    include_directories,
+                )
+            },
+        );
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(
+                source,
+                kind,
+                &path.to_string_lossy(),
+                "main",
+                Some(&options),
+            )
+            .map_err(|error| {
+                let message = error.to_string();
+                log::error!("{}", format_shader_error(source, &message));
+                anyhow!("{}", message)
+            })?;
+        Ok(artifact.as_binary().to_vec())
+    }
+}
+
+/// Load compiled spir-v onto the device.
+///
+/// Debug builds with the spirv-validate feature run the spir-v through
+/// `validate_spirv` first.
+pub fn create_module(
+    device: &Arc<Device>,
+    words: &[u32],
+) -> Result<Arc<ShaderModule>> {
+    #[cfg(all(feature = "spirv-validate", debug_assertions))]
+    validate_spirv(words).context("the compiled spir-v is invalid")?;
+    unsafe {
+        // unsafe because vulkano cannot check that the spir-v is valid, the
+        // compiler only produces valid modules
+        ShaderModule::from_words(device.clone(), words)
+            .context("unable to create the shader module")
+    }
+}
+
+/// Show each error in a compiler message against the source it came from,
+/// like rustc does.
+///
+/// Messages like `shader.frag:12: error: 'foo' : undeclared identifier` are
+/// followed by the lines around line 12, with the quoted token underlined
+/// where it can be found. Line numbers follow `#line` directives, so
+/// errors still point at the right line when the source was wrapped in a
+/// header. Messages about included files and anything else the compiler
+/// said are kept as they are.
+pub fn format_shader_error(source: &str, error: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let includes: Vec<&str> = lines
+        .iter()
+        .filter_map(|line| line.trim().strip_prefix("#include"))
+        .map(|name| name.trim().trim_matches(['"', '<', '>']))
+        .collect();
+
+    // the line the compiler reports for each line of the source, and the
+    // lines which start numbering again
+    let mut reported = Vec::with_capacity(lines.len());
+    let mut directives = vec![];
+    let mut next = 1;
+    for (index, line) in lines.iter().enumerate() {
+        reported.push(next);
+        next = match line.trim().strip_prefix("#line") {
+            Some(directive) => {
+                directives.push(index);
+                directive
+                    .split_whitespace()
+                    .next()
+                    .and_then(|number| number.parse().ok())
+                    .unwrap_or(next + 1)
+            }
+            None => next + 1,
+        };
+    }
+
+    let mut formatted = String::new();
+    let mut previous = None;
+    for message in error.lines() {
+        let location = parse_location(message).filter(|(name, ..)| {
+            !includes.iter().any(|include| name.ends_with(include))
+        });
+        // a header can reuse line numbers, the source comes after it
+        let found = location.and_then(|(name, line, text)| {
+            let index = reported.iter().rposition(|&number| number == line)?;
+            Some((name, line, text, index))
+        });
+        // the source is only shown once for several errors on a line
+        let (name, line, text, index) = match found {
+            Some(found) if previous != Some(found.3) => found,
+            _ => {
+                formatted.push_str(message);
+                formatted.push('\n');
+                continue;
+            }
+        };
+        previous = Some(index);
+
+        // the context stops at `#line` directives
+        let first = directives
+            .iter()
+            .rev()
+            .find(|&&directive| directive < index)
+            .map_or(0, |directive| directive + 1)
+            .max(index.saturating_sub(CONTEXT_LINES));
+        let last = directives
+            .iter()
+            .find(|&&directive| directive > index)
+            .map_or(lines.len() - 1, |directive| directive - 1)
+            .min(index + CONTEXT_LINES);
+        let width = reported[first..=last]
+            .iter()
+            .map(|number| number.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(width);
+        formatted.push_str(&format!("{}\n", text));
+        formatted.push_str(&format!("{}--> {}:{}\n", gutter, name, line));
+        formatted.push_str(&format!("{} |\n", gutter));
+        for context in first..=last {
+            formatted.push_str(&format!(
+                "{:>width$} | {}\n",
+                reported[context],
+                lines[context],
+                width = width
+            ));
+            if context != index {
+                continue;
+            }
+            let token = quoted_token(text)
+                .and_then(|token| Some((lines[index].find(token)?, token)));
+            if let Some((column, token)) = token {
+                formatted.push_str(&format!(
+                    "{} | {}{}\n",
+                    gutter,
+                    " ".repeat(column),
+                    "^".repeat(token.chars().count())
+                ));
+            }
+        }
+        formatted.push_str(&format!("{} |\n", gutter));
+    }
+    formatted
+}
+
+/// The file, line, and text of a message like
+/// `shader.frag:12: error: 'foo' : undeclared identifier`.
+fn parse_location(message: &str) -> Option<(&str, usize, &str)> {
+    // file names can contain colons on windows, so search from the message
+    ["error:", "warning:"].iter().find_map(|kind| {
+        let start = message.find(&format!(": {}", kind))?;
+        let (location, text) = message.split_at(start);
+        let (name, line) = location.rsplit_once(':')?;
+        Some((name, line.trim().parse().ok()?, text[2..].trim()))
+    })
+}
+
+/// The first token in single quotes, which glslang puts the offending token
+/// in.
+fn quoted_token(text: &str) -> Option<&str> {
+    let start = text.find('\'')? + 1;
+    let length = text[start..].find('\'')?;
+    Some(&text[start..start + length]).filter(|token| !token.is_empty())
+}
+
+/// Why spir-v failed validation.
+#[cfg(feature = "spirv-validate")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpirvValidationError {
+    /// The validator's diagnostic, or its result code when it didn't give
+    /// one.
+    pub message: String,
+}
+
+#[cfg(feature = "spirv-validate")]
+impl std::fmt::Display for SpirvValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid spir-v: {}", self.message)
+    }
+}
+
+#[cfg(feature = "spirv-validate")]
+impl std::error::Error for SpirvValidationError {}
+
+/// Check spir-v with the spirv-tools validator, `spvValidate`, for the same
+/// vulkan version shaders are compiled for.
+#[cfg(feature = "spirv-validate")]
+pub fn validate_spirv(words: &[u32]) -> Result<(), SpirvValidationError> {
+    use spirv_tools_sys::{diagnostics, shared, val};
+    use std::ffi::CStr;
+
+    let binary = shared::Binary {
+        code: words.as_ptr(),
+        size: words.len(),
+    };
+    unsafe {
+        // unsafe because these are raw calls into spirv-tools, the context
+        // and diagnostic are destroyed exactly once and the binary outlives
+        // them
+        let context = shared::context_create(shared::TargetEnv::Vulkan_1_1);
+        let mut diagnostic = std::ptr::null_mut();
+        let result = val::validate(context, &binary, &mut diagnostic);
+        let message = if diagnostic.is_null() {
+            None
+        } else {
+            let message = CStr::from_ptr((*diagnostic).error)
+                .to_string_lossy()
+                .into_owned();
+            diagnostics::diagnostic_destroy(diagnostic);
+            Some(message)
+        };
+        shared::context_destroy(context);
+        match result {
+            shared::SpirvResult::Success => Ok(()),
+            result => Err(SpirvValidationError {
+                message: message.unwrap_or_else(|| format!("{:?}", result)),
+            }),
+        }
+    }
+}
+
+fn resolve_include(
+    requested: &str,
+    include_type: IncludeType,
+    requested_by: &str,
+    include_directories: &[PathBuf],
+) -> Result<ResolvedInclude, String> {
+    let candidates: Vec<PathBuf> = match include_type {
+        IncludeType::Relative => Path::new(requested_by)
+            .parent()
+            .map(|directory| vec![directory.join(requested)])
+            .unwrap_or_default(),
+        IncludeType::Standard => include_directories
+            .iter()
+            .map(|directory| directory.join(requested))
+            .collect(),
+    };
+
+    let path = candidates
+        .iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            format!(
+                "unable to find {:?} included by {}",
+                requested, requested_by
+            )
+        })?;
+    let content = std::fs::read_to_string(path)
+        .map_err(|error| format!("unable to read {:?} - {}", path, error))?;
+    Ok(ResolvedInclude {
+        resolved_name: path.to_string_lossy().into_owned(),
+        content,
+    })
+}
+
+/// Find every glsl source beneath a directory, in a stable order.
+pub fn find_shaders(directory: &Path) -> Result<Vec<PathBuf>> {
+    if !directory.is_dir() {
+        bail!("{:?} is not a directory", directory);
+    }
+
+    let mut shaders = vec![];
+    let mut pending = vec![directory.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        let entries = std::fs::read_dir(&directory)
+            .with_context(|| format!("unable to list {:?}", directory))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if ShaderCompiler::shader_kind(&path).is_some() {
+                shaders.push(path);
+            }
+        }
+    }
+    shaders.sort();
+    Ok(shaders)
+}
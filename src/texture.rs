@@ -0,0 +1,659 @@
+use crate::application::debug::BarrierValidator;
+use crate::application::live_resources::{LiveResource, ResourceKind};
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::device::{Device, Queue};
+use vulkano::format::Format;
+use vulkano::image::{
+    Dimensions, ImageLayout, ImageUsage, ImmutableImage, MipmapsCount,
+};
+use vulkano::instance::PhysicalDevice;
+use vulkano::sync::GpuFuture;
+
+mod block_decode;
+pub mod ktx2;
+mod streaming;
+
+use ktx2::{BlockCompression, Ktx2};
+
+pub use streaming::{
+    default_budget, png_directory, texture_cache_stats, TextureCache,
+    TextureCacheStats, TextureSource,
+};
+
+/// How the texels in a texture should be interpreted.
+///
+/// Color images are stored gamma encoded and must be sampled through an sRGB
+/// format so the shader sees linear values. Data textures like normal maps
+/// and lookup tables are already linear and must not be decoded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TextureKind {
+    Color,
+    Data,
+}
+
+impl TextureKind {
+    /// The formats which can hold this kind of texture, in order of
+    /// preference.
+    fn candidate_formats(self) -> [Format; 2] {
+        match self {
+            TextureKind::Color => [Format::R8G8B8A8Srgb, Format::B8G8R8A8Srgb],
+            TextureKind::Data => [Format::R8G8B8A8Unorm, Format::B8G8R8A8Unorm],
+        }
+    }
+}
+
+/// A sampled rgba texture on the gpu.
+pub struct Texture {
+    pub image: Arc<ImmutableImage<Format>>,
+    pub format: Format,
+    pub kind: TextureKind,
+    _live: LiveResource,
+}
+
+/// Builds a texture from rgba pixels.
+pub struct TextureBuilder {
+    name: String,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+    kind: TextureKind,
+
+    // the kind implied by the source's embedded color space, if it had one
+    source_hint: Option<TextureKind>,
+}
+
+impl TextureBuilder {
+    /// Start a texture from tightly packed rgba pixels.
+    pub fn from_rgba(
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    ) -> Result<Self> {
+        if pixels.len() != (width * height * 4) as usize {
+            bail!(
+                "{} has {} bytes but a {}x{} rgba image needs {}",
+                name,
+                pixels.len(),
+                width,
+                height,
+                width * height * 4
+            );
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            width,
+            height,
+            pixels,
+            kind: TextureKind::Color,
+            source_hint: None,
+        })
+    }
+
+    /// Start a texture from a png file.
+    ///
+    /// Any `sRGB`, `iCCP`, or `gAMA` chunk in the file is remembered so a
+    /// mismatched kind can be reported when the texture is built.
+    pub fn from_png(path: &Path) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("unable to read {:?}", path))?;
+        let (width, height, pixels) = decode_png(path)
+            .with_context(|| format!("unable to decode {:?}", path))?;
+        let mut builder =
+            Self::from_rgba(&path.to_string_lossy(), width, height, pixels)?;
+        builder.source_hint = png_color_hint(&bytes);
+        Ok(builder)
+    }
+
+    /// Set how the texels should be interpreted. Textures are `Color` by
+    /// default.
+    pub fn kind(mut self, kind: TextureKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Pick a format, upload the pixels, and wait for the upload to finish.
+    pub fn build(
+        self,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+    ) -> Result<Texture> {
+        self.warn_on_hint_conflict();
+        let Self {
+            name,
+            width,
+            height,
+            pixels,
+            kind,
+            ..
+        } = self;
+
+        let format = select_format(device.physical_device(), kind)?;
+        let pixels = match format {
+            Format::B8G8R8A8Srgb | Format::B8G8R8A8Unorm => {
+                swap_red_blue(pixels)
+            }
+            _ => pixels,
+        };
+        let (image, upload) = ImmutableImage::from_iter(
+            pixels.into_iter(),
+            Dimensions::Dim2d { width, height },
+            MipmapsCount::One,
+            format,
+            queue.clone(),
+        )
+        .with_context(|| format!("unable to create the {} image", name))?;
+        upload
+            .then_signal_fence_and_flush()
+            .with_context(|| format!("unable to upload {}", name))?
+            .wait(None)
+            .with_context(|| format!("unable to upload {}", name))?;
+
+        Ok(Texture {
+            image,
+            format,
+            kind,
+            _live: LiveResource::described(
+                ResourceKind::Texture,
+                name,
+                texture_detail(width, height, format, 1),
+                width as u64 * height as u64 * 4,
+            ),
+        })
+    }
+
+    fn warn_on_hint_conflict(&self) {
+        if let Some(hint) = self.source_hint {
+            if hint != self.kind {
+                log::warn!(
+                    "{} is loaded as {:?} but its embedded color space \
+                    suggests {:?}",
+                    self.name,
+                    self.kind,
+                    hint
+                );
+            }
+        }
+    }
+}
+
+/// A sampled 2D rgba texture array, one layer per source image.
+///
+/// Shaders sample it as a `sampler2DArray` with the layer in the third
+/// texture coordinate.
+pub struct TextureArray {
+    pub image: Arc<ImmutableImage<Format>>,
+    pub format: Format,
+    pub kind: TextureKind,
+    pub layers: u32,
+    _live: LiveResource,
+}
+
+impl TextureArray {
+    /// Stack equally sized images into layers, in order, and wait for the
+    /// upload to finish.
+    ///
+    /// Every layer must have the same dimensions and kind as the first.
+    pub fn build(
+        layers: Vec<TextureBuilder>,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+    ) -> Result<TextureArray> {
+        let first = layers.first().context("a texture array needs layers")?;
+        let (width, height, kind) = (first.width, first.height, first.kind);
+        for layer in &layers[1..] {
+            if [layer.width, layer.height] != [width, height] {
+                bail!(
+                    "{} is {}x{} but {} and the rest of the array are {}x{}",
+                    layer.name,
+                    layer.width,
+                    layer.height,
+                    first.name,
+                    width,
+                    height
+                );
+            }
+            if layer.kind != kind {
+                bail!(
+                    "{} is a {:?} texture but {} and the rest of the array \
+                    are {:?}",
+                    layer.name,
+                    layer.kind,
+                    first.name,
+                    kind
+                );
+            }
+        }
+
+        let max_layers =
+            device.physical_device().limits().max_image_array_layers();
+        if layers.len() as u32 > max_layers {
+            bail!(
+                "the array has {} layers but the device supports {}",
+                layers.len(),
+                max_layers
+            );
+        }
+
+        let label = match layers.len() {
+            1 => first.name.clone(),
+            count => format!("{} and {} more", first.name, count - 1),
+        };
+        let format = select_format(device.physical_device(), kind)?;
+        let swizzle =
+            matches!(format, Format::B8G8R8A8Srgb | Format::B8G8R8A8Unorm);
+        let layer_count = layers.len() as u32;
+        let mut pixels =
+            Vec::with_capacity((width * height * 4 * layer_count) as usize);
+        for layer in layers {
+            layer.warn_on_hint_conflict();
+            if swizzle {
+                pixels.extend(swap_red_blue(layer.pixels));
+            } else {
+                pixels.extend(layer.pixels);
+            }
+        }
+
+        let (image, upload) = ImmutableImage::from_iter(
+            pixels.into_iter(),
+            Dimensions::Dim2dArray {
+                width,
+                height,
+                array_layers: layer_count,
+            },
+            MipmapsCount::One,
+            format,
+            queue.clone(),
+        )
+        .context("unable to create the texture array image")?;
+        upload
+            .then_signal_fence_and_flush()
+            .context("unable to upload the texture array")?
+            .wait(None)
+            .context("unable to upload the texture array")?;
+
+        Ok(TextureArray {
+            image,
+            format,
+            kind,
+            layers: layer_count,
+            _live: LiveResource::described(
+                ResourceKind::Texture,
+                label,
+                format!(
+                    "{} layers of {}",
+                    layer_count,
+                    texture_detail(width, height, format, 1)
+                ),
+                width as u64 * height as u64 * 4 * layer_count as u64,
+            ),
+        })
+    }
+
+    /// Load every png in a directory as a layer, in file name order.
+    pub fn from_png_directory(
+        directory: &Path,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+    ) -> Result<TextureArray> {
+        let mut paths = std::fs::read_dir(directory)
+            .with_context(|| format!("unable to read {:?}", directory))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .with_context(|| format!("unable to read {:?}", directory))?;
+        paths.retain(|path| {
+            path.extension().is_some_and(|extension| extension == "png")
+        });
+        paths.sort();
+        let layers = paths
+            .iter()
+            .map(|path| TextureBuilder::from_png(path))
+            .collect::<Result<Vec<_>>>()?;
+        Self::build(layers, device, queue)
+            .with_context(|| format!("unable to build {:?}", directory))
+    }
+}
+
+/// Load a block compressed texture from a KTX2 file.
+///
+/// When the device can sample the payload's format every mip level is
+/// uploaded as-is. Otherwise the full size level is decompressed on the cpu
+/// and uploaded as rgba, which only works for BC1 and BC3.
+pub fn load_ktx2(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    path: &Path,
+) -> Result<Texture> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("unable to read {:?}", path))?;
+    let ktx = Ktx2::parse(data)
+        .with_context(|| format!("unable to parse {:?}", path))?;
+    let kind = if ktx.format.srgb {
+        TextureKind::Color
+    } else {
+        TextureKind::Data
+    };
+
+    if supports_compressed_format(device, &ktx) {
+        log::info!(
+            "uploading {:?} as {:?} with {} levels",
+            path,
+            ktx.format.format,
+            ktx.levels.len()
+        );
+        return upload_compressed(queue, &ktx, kind, &path.to_string_lossy())
+            .with_context(|| format!("unable to upload {:?}", path));
+    }
+
+    log::info!(
+        "the device cannot sample {:?}, decompressing {:?} on the cpu",
+        ktx.format.format,
+        path
+    );
+    let level = &ktx.levels[0];
+    let pixels = ktx
+        .decode_level(level)
+        .with_context(|| format!("unable to decompress {:?}", path))?;
+    TextureBuilder::from_rgba(
+        &path.to_string_lossy(),
+        level.width,
+        level.height,
+        pixels,
+    )?
+    .kind(kind)
+    .build(device, queue)
+}
+
+/// True when the compression feature is enabled and the format can be
+/// sampled with optimal tiling.
+fn supports_compressed_format(device: &Arc<Device>, ktx: &Ktx2) -> bool {
+    let features = device.enabled_features();
+    let feature_enabled = match ktx.format.compression {
+        BlockCompression::Bc1
+        | BlockCompression::Bc3
+        | BlockCompression::Bc7 => features.texture_compression_bc,
+        BlockCompression::Astc => features.texture_compression_astc_ldr,
+    };
+    let format_features = ktx
+        .format
+        .format
+        .properties(device.physical_device())
+        .optimal_tiling_features;
+    feature_enabled && format_features.sampled_image
+}
+
+/// Copy every level's blocks straight into the image.
+fn upload_compressed(
+    queue: &Arc<Queue>,
+    ktx: &Ktx2,
+    kind: TextureKind,
+    name: &str,
+) -> Result<Texture> {
+    let device = queue.device();
+    let (image, initialization) = ImmutableImage::uninitialized(
+        device.clone(),
+        Dimensions::Dim2d {
+            width: ktx.width,
+            height: ktx.height,
+        },
+        ktx.format.format,
+        MipmapsCount::Specific(ktx.levels.len() as u32),
+        ImageUsage {
+            transfer_destination: true,
+            sampled: true,
+            ..ImageUsage::none()
+        },
+        ImageLayout::ShaderReadOnlyOptimal,
+        Some(queue.family()),
+    )
+    .context("unable to create the compressed image")?;
+    let initialization = Arc::new(initialization);
+
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            queue.family(),
+        )
+        .context("unable to create the upload command buffer builder")?,
+        &queue,
+    );
+    for (mip, level) in ktx.levels.iter().enumerate() {
+        let blocks = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            ktx.level_data(level).iter().copied(),
+        )
+        .context("unable to create the level upload buffer")?;
+        builder
+            .copy_buffer_to_image_dimensions(
+                blocks,
+                initialization.clone(),
+                [0, 0, 0],
+                [level.width, level.height, 1],
+                0,
+                1,
+                mip as u32,
+            )
+            .with_context(|| format!("unable to copy level {}", mip))?;
+    }
+    builder
+        .build()
+        .context("unable to build the upload command buffer")?
+        .execute(queue.clone())
+        .context("unable to execute the upload")?
+        .then_signal_fence_and_flush()
+        .context("unable to flush the upload")?
+        .wait(None)
+        .context("unable to wait for the upload")?;
+
+    let bytes = ktx
+        .levels
+        .iter()
+        .map(|level| ktx.level_data(level).len() as u64)
+        .sum();
+    Ok(Texture {
+        image,
+        format: ktx.format.format,
+        kind,
+        _live: LiveResource::described(
+            ResourceKind::Texture,
+            name,
+            texture_detail(
+                ktx.width,
+                ktx.height,
+                ktx.format.format,
+                ktx.levels.len(),
+            ),
+            bytes,
+        ),
+    })
+}
+
+/// A texture's dimensions, format, and mip levels for the resource
+/// registry.
+fn texture_detail(
+    width: u32,
+    height: u32,
+    format: Format,
+    mips: usize,
+) -> String {
+    format!("{}x{} {:?}, {} mips", width, height, format, mips)
+}
+
+/// Upload a `size`³ color lookup table as an `R16G16B16A16Sfloat` 3D image
+/// which can be sampled with trilinear filtering.
+///
+/// The entries are ordered with red changing fastest, then green, then blue.
+pub fn upload_lut(
+    device: &Arc<Device>,
+    queue: &Arc<Queue>,
+    size: u32,
+    entries: &[[f32; 3]],
+) -> Result<Arc<ImmutableImage<Format>>> {
+    let expected = (size * size * size) as usize;
+    if entries.len() != expected {
+        bail!(
+            "a {}³ lut needs {} entries but has {}",
+            size,
+            expected,
+            entries.len()
+        );
+    }
+    let features = Format::R16G16B16A16Sfloat
+        .properties(device.physical_device())
+        .optimal_tiling_features;
+    if !features.sampled_image_filter_linear {
+        bail!("the device cannot filter half float luts");
+    }
+
+    let texels = entries.iter().map(|&[r, g, b]| {
+        [f32_to_f16(r), f32_to_f16(g), f32_to_f16(b), f32_to_f16(1.0)]
+    });
+    let (lut, upload) = ImmutableImage::from_iter(
+        texels,
+        Dimensions::Dim3d {
+            width: size,
+            height: size,
+            depth: size,
+        },
+        MipmapsCount::One,
+        Format::R16G16B16A16Sfloat,
+        queue.clone(),
+    )
+    .context("unable to create the lut image")?;
+    upload
+        .then_signal_fence_and_flush()
+        .context("unable to upload the lut")?
+        .wait(None)
+        .context("unable to upload the lut")?;
+    Ok(lut)
+}
+
+/// Convert to the bits of the nearest IEEE 754 half precision float.
+///
+/// Values too small for a normal half are flushed to zero and values too
+/// large become infinity, neither happens for the colors in a lut or a
+/// seed image.
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x007f_ffff;
+    if ((bits >> 23) & 0xff) == 0xff {
+        // infinity or nan
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    if exponent <= 0 {
+        return sign;
+    }
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // round to nearest, a carry out of the mantissa bumps the exponent which
+    // is still the right answer
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    sign | (half + round).min(0x7c00) as u16
+}
+
+/// The first candidate format for the kind which the device can sample with
+/// optimal tiling.
+pub fn select_format(
+    physical_device: PhysicalDevice,
+    kind: TextureKind,
+) -> Result<Format> {
+    kind.candidate_formats()
+        .iter()
+        .copied()
+        .find(|format| {
+            let features =
+                format.properties(physical_device).optimal_tiling_features;
+            features.sampled_image && features.sampled_image_filter_linear
+        })
+        .with_context(|| {
+            format!("no sampled image format available for {:?}", kind)
+        })
+}
+
+fn swap_red_blue(mut pixels: Vec<u8>) -> Vec<u8> {
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    pixels
+}
+
+/// Decode any 8 or 16 bit png into tightly packed rgba8 pixels.
+pub fn decode_png(path: &Path) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(
+        png::Transformations::EXPAND | png::Transformations::STRIP_16,
+    );
+    let (info, mut reader) = decoder.read_info()?;
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels)?;
+
+    let channels = info.line_size / info.width as usize;
+    let mut rgba = Vec::with_capacity((info.width * info.height * 4) as usize);
+    for row in pixels.chunks_exact(info.line_size) {
+        for pixel in row[..info.width as usize * channels].chunks(channels) {
+            match *pixel {
+                [grey] => rgba.extend_from_slice(&[grey, grey, grey, 255]),
+                [grey, alpha] => {
+                    rgba.extend_from_slice(&[grey, grey, grey, alpha])
+                }
+                [r, g, b] => rgba.extend_from_slice(&[r, g, b, 255]),
+                [r, g, b, a] => rgba.extend_from_slice(&[r, g, b, a]),
+                _ => bail!("unsupported png layout"),
+            }
+        }
+    }
+    Ok((info.width, info.height, rgba))
+}
+
+/// The gAMA chunk stores the encoding gamma times 100000, a value near
+/// 1/2.2 means the pixels are gamma encoded and 1.0 means they are linear.
+const SRGB_GAMMA: u32 = 45455;
+const LINEAR_GAMMA: u32 = 100000;
+const GAMMA_TOLERANCE: u32 = 2000;
+
+/// The kind implied by a png's color space chunks, if it has any.
+///
+/// The png decoder skips these chunks so they're read directly. An `sRGB`
+/// or `iCCP` chunk always means color.
+fn png_color_hint(bytes: &[u8]) -> Option<TextureKind> {
+    let mut offset = 8;
+    while offset + 8 <= bytes.len() {
+        let mut length = [0; 4];
+        length.copy_from_slice(&bytes[offset..offset + 4]);
+        let length = u32::from_be_bytes(length) as usize;
+        let chunk_type = &bytes[offset + 4..offset + 8];
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        match chunk_type {
+            b"sRGB" | b"iCCP" => return Some(TextureKind::Color),
+            b"gAMA" if data.len() == 4 => {
+                let mut gamma = [0; 4];
+                gamma.copy_from_slice(data);
+                let gamma = u32::from_be_bytes(gamma);
+                if gamma.abs_diff(SRGB_GAMMA) < GAMMA_TOLERANCE {
+                    return Some(TextureKind::Color);
+                }
+                if gamma.abs_diff(LINEAR_GAMMA) < GAMMA_TOLERANCE {
+                    return Some(TextureKind::Data);
+                }
+            }
+            // color space chunks must come before the image data
+            b"IDAT" => return None,
+            _ => (),
+        }
+        offset += length + 12;
+    }
+    None
+}
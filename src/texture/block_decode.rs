@@ -0,0 +1,137 @@
+use super::ktx2::{BlockCompression, PayloadFormat};
+use anyhow::{bail, Result};
+
+/// Decompress a level into tightly packed rgba8 pixels.
+///
+/// Only BC1 and BC3 can be decoded on the cpu. BC7 and ASTC can only be
+/// loaded on devices which sample them directly.
+pub fn decode(
+    format: &PayloadFormat,
+    width: u32,
+    height: u32,
+    blocks: &[u8],
+) -> Result<Vec<u8>> {
+    let decode_block: fn(&[u8], &mut [[u8; 4]; 16]) = match format.compression {
+        BlockCompression::Bc1 if has_alpha(format) => |block, texels| {
+            decode_bc1_color(block, Bc1Mode::PunchThrough, texels)
+        },
+        BlockCompression::Bc1 => {
+            |block, texels| decode_bc1_color(block, Bc1Mode::Opaque, texels)
+        }
+        BlockCompression::Bc3 => decode_bc3,
+        compression => bail!("no cpu decoder for {:?}", compression),
+    };
+
+    let blocks_x = width.div_ceil(4) as usize;
+    let mut pixels = vec![0; (width * height * 4) as usize];
+    let mut texels = [[0; 4]; 16];
+    for (index, block) in blocks.chunks_exact(format.block_size).enumerate() {
+        decode_block(block, &mut texels);
+        let (block_x, block_y) = (index % blocks_x * 4, index / blocks_x * 4);
+        for (texel_index, texel) in texels.iter().enumerate() {
+            let x = block_x + texel_index % 4;
+            let y = block_y + texel_index / 4;
+            if x < width as usize && y < height as usize {
+                let pixel = (y * width as usize + x) * 4;
+                pixels[pixel..pixel + 4].copy_from_slice(texel);
+            }
+        }
+    }
+    Ok(pixels)
+}
+
+/// BC1 only has a transparent color in the RGBA variants.
+fn has_alpha(format: &PayloadFormat) -> bool {
+    use vulkano::format::Format;
+    matches!(
+        format.format,
+        Format::BC1_RGBAUnormBlock | Format::BC1_RGBASrgbBlock
+    )
+}
+
+/// How a BC1 color block whose first endpoint is not greater than its second
+/// is decoded.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Bc1Mode {
+    /// Three colors and opaque black.
+    Opaque,
+
+    /// Three colors and transparent black.
+    PunchThrough,
+
+    /// Four colors regardless of the endpoint order, used by BC3.
+    FourColors,
+}
+
+/// Decode an 8 byte BC1 color block.
+fn decode_bc1_color(block: &[u8], mode: Bc1Mode, texels: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let e0 = expand_565(c0);
+    let e1 = expand_565(c1);
+    let mix = |a: u8, b: u8, wa: u32, wb: u32| {
+        ((a as u32 * wa + b as u32 * wb) / (wa + wb)) as u8
+    };
+
+    let mut palette = [[0, 0, 0, 255]; 4];
+    palette[0] = [e0[0], e0[1], e0[2], 255];
+    palette[1] = [e1[0], e1[1], e1[2], 255];
+    let four_colors = c0 > c1 || mode == Bc1Mode::FourColors;
+    for channel in 0..3 {
+        let (a, b) = (e0[channel], e1[channel]);
+        if four_colors {
+            palette[2][channel] = mix(a, b, 2, 1);
+            palette[3][channel] = mix(a, b, 1, 2);
+        } else {
+            palette[2][channel] = mix(a, b, 1, 1);
+        }
+    }
+    if !four_colors && mode == Bc1Mode::PunchThrough {
+        palette[3][3] = 0;
+    }
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (texel_index, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (texel_index * 2)) as usize & 0b11];
+    }
+}
+
+/// Decode a 16 byte BC3 block, interpolated alpha followed by a BC1 color
+/// block which always uses four colors.
+fn decode_bc3(block: &[u8], texels: &mut [[u8; 4]; 16]) {
+    decode_bc1_color(&block[8..16], Bc1Mode::FourColors, texels);
+
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut alphas = [0u8; 8];
+    alphas[0] = a0 as u8;
+    alphas[1] = a1 as u8;
+    if a0 > a1 {
+        for (k, alpha) in (2..).zip(&mut alphas[2..8]) {
+            *alpha = (((8 - k) * a0 + (k - 1) * a1) / 7) as u8;
+        }
+    } else {
+        for (k, alpha) in (2..).zip(&mut alphas[2..6]) {
+            *alpha = (((6 - k) * a0 + (k - 1) * a1) / 5) as u8;
+        }
+        alphas[6] = 0;
+        alphas[7] = 255;
+    }
+
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    for (texel_index, texel) in texels.iter_mut().enumerate() {
+        texel[3] = alphas[(indices >> (texel_index * 3)) as usize & 0b111];
+    }
+}
+
+fn expand_565(color: u16) -> [u8; 3] {
+    let r = ((color >> 11) & 0x1f) as u8;
+    let g = ((color >> 5) & 0x3f) as u8;
+    let b = (color & 0x1f) as u8;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
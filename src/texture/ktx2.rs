@@ -0,0 +1,240 @@
+use anyhow::{bail, Context, Result};
+use vulkano::format::Format;
+
+/// Every KTX2 file starts with these bytes, `«KTX 20»\r\n\x1A\n`.
+const IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+
+/// The size of the identifier, header, and index which precede the level
+/// index.
+const LEVEL_INDEX_OFFSET: usize = 80;
+
+/// Each level index entry holds a byte offset, byte length, and uncompressed
+/// byte length.
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// The block compression schemes which can be loaded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockCompression {
+    Bc1,
+    Bc3,
+    Bc7,
+    Astc,
+}
+
+/// The compressed format of a KTX2 payload.
+#[derive(Debug, Copy, Clone)]
+pub struct PayloadFormat {
+    pub compression: BlockCompression,
+    pub format: Format,
+    pub srgb: bool,
+
+    /// The width and height of a block in texels.
+    pub block_extent: [u32; 2],
+
+    /// The number of bytes in each block.
+    pub block_size: usize,
+}
+
+impl PayloadFormat {
+    /// Look up a `VkFormat` value from the file header.
+    fn from_vk_format(vk_format: u32) -> Option<Self> {
+        use BlockCompression::*;
+        let (compression, format, srgb, block_extent) = match vk_format {
+            131 => (Bc1, Format::BC1_RGBUnormBlock, false, [4, 4]),
+            132 => (Bc1, Format::BC1_RGBSrgbBlock, true, [4, 4]),
+            133 => (Bc1, Format::BC1_RGBAUnormBlock, false, [4, 4]),
+            134 => (Bc1, Format::BC1_RGBASrgbBlock, true, [4, 4]),
+            137 => (Bc3, Format::BC3UnormBlock, false, [4, 4]),
+            138 => (Bc3, Format::BC3SrgbBlock, true, [4, 4]),
+            145 => (Bc7, Format::BC7UnormBlock, false, [4, 4]),
+            146 => (Bc7, Format::BC7SrgbBlock, true, [4, 4]),
+            157 => (Astc, Format::ASTC_4x4UnormBlock, false, [4, 4]),
+            158 => (Astc, Format::ASTC_4x4SrgbBlock, true, [4, 4]),
+            159 => (Astc, Format::ASTC_5x4UnormBlock, false, [5, 4]),
+            160 => (Astc, Format::ASTC_5x4SrgbBlock, true, [5, 4]),
+            161 => (Astc, Format::ASTC_5x5UnormBlock, false, [5, 5]),
+            162 => (Astc, Format::ASTC_5x5SrgbBlock, true, [5, 5]),
+            163 => (Astc, Format::ASTC_6x5UnormBlock, false, [6, 5]),
+            164 => (Astc, Format::ASTC_6x5SrgbBlock, true, [6, 5]),
+            165 => (Astc, Format::ASTC_6x6UnormBlock, false, [6, 6]),
+            166 => (Astc, Format::ASTC_6x6SrgbBlock, true, [6, 6]),
+            167 => (Astc, Format::ASTC_8x5UnormBlock, false, [8, 5]),
+            168 => (Astc, Format::ASTC_8x5SrgbBlock, true, [8, 5]),
+            169 => (Astc, Format::ASTC_8x6UnormBlock, false, [8, 6]),
+            170 => (Astc, Format::ASTC_8x6SrgbBlock, true, [8, 6]),
+            171 => (Astc, Format::ASTC_8x8UnormBlock, false, [8, 8]),
+            172 => (Astc, Format::ASTC_8x8SrgbBlock, true, [8, 8]),
+            173 => (Astc, Format::ASTC_10x5UnormBlock, false, [10, 5]),
+            174 => (Astc, Format::ASTC_10x5SrgbBlock, true, [10, 5]),
+            175 => (Astc, Format::ASTC_10x6UnormBlock, false, [10, 6]),
+            176 => (Astc, Format::ASTC_10x6SrgbBlock, true, [10, 6]),
+            177 => (Astc, Format::ASTC_10x8UnormBlock, false, [10, 8]),
+            178 => (Astc, Format::ASTC_10x8SrgbBlock, true, [10, 8]),
+            179 => (Astc, Format::ASTC_10x10UnormBlock, false, [10, 10]),
+            180 => (Astc, Format::ASTC_10x10SrgbBlock, true, [10, 10]),
+            181 => (Astc, Format::ASTC_12x10UnormBlock, false, [12, 10]),
+            182 => (Astc, Format::ASTC_12x10SrgbBlock, true, [12, 10]),
+            183 => (Astc, Format::ASTC_12x12UnormBlock, false, [12, 12]),
+            184 => (Astc, Format::ASTC_12x12SrgbBlock, true, [12, 12]),
+            _ => return None,
+        };
+        let block_size = if compression == Bc1 { 8 } else { 16 };
+        Some(Self {
+            compression,
+            format,
+            srgb,
+            block_extent,
+            block_size,
+        })
+    }
+
+    /// The number of bytes needed for a level of the given size.
+    pub fn level_size(&self, width: u32, height: u32) -> usize {
+        let [block_width, block_height] = self.block_extent;
+        let blocks_x = width.div_ceil(block_width) as usize;
+        let blocks_y = height.div_ceil(block_height) as usize;
+        blocks_x * blocks_y * self.block_size
+    }
+}
+
+/// One mip level of a KTX2 texture.
+#[derive(Debug, Copy, Clone)]
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+
+    /// The level's position in the file, in bytes.
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// A 2D block compressed texture in a KTX2 container.
+///
+/// Only the subset of KTX2 which can be uploaded directly is supported: a
+/// single 2D image, optionally with mip levels, which is not
+/// supercompressed.
+pub struct Ktx2 {
+    pub format: PayloadFormat,
+    pub width: u32,
+    pub height: u32,
+
+    /// Every mip level, starting with the full size image.
+    pub levels: Vec<Level>,
+
+    data: Vec<u8>,
+}
+
+impl Ktx2 {
+    /// Parse a KTX2 file and check each level's size and position against
+    /// the spec.
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.len() < LEVEL_INDEX_OFFSET || data[..12] != IDENTIFIER {
+            bail!("not a KTX2 file");
+        }
+        let vk_format = read_u32(&data, 12);
+        let width = read_u32(&data, 20);
+        let height = read_u32(&data, 24);
+        let depth = read_u32(&data, 28);
+        let layer_count = read_u32(&data, 32);
+        let face_count = read_u32(&data, 36);
+        let level_count = read_u32(&data, 40).max(1);
+        let supercompression = read_u32(&data, 44);
+
+        let format = PayloadFormat::from_vk_format(vk_format)
+            .with_context(|| format!("unsupported VkFormat {}", vk_format))?;
+        if width == 0 || height == 0 || depth != 0 {
+            bail!("only 2D textures are supported");
+        }
+        if layer_count > 1 || face_count != 1 {
+            bail!("array and cube textures are not supported");
+        }
+        if supercompression != 0 {
+            bail!(
+                "supercompression scheme {} is not supported",
+                supercompression
+            );
+        }
+        if level_count > 32 - width.max(height).leading_zeros() {
+            bail!("{} levels is more than a full mip chain", level_count);
+        }
+
+        let index_end =
+            LEVEL_INDEX_OFFSET + level_count as usize * LEVEL_INDEX_ENTRY_SIZE;
+        if data.len() < index_end {
+            bail!("the level index is truncated");
+        }
+
+        // block compressed levels must be aligned to the least common
+        // multiple of the block size and 4, which is the block size
+        let mut levels = Vec::with_capacity(level_count as usize);
+        for level in 0..level_count {
+            let entry =
+                LEVEL_INDEX_OFFSET + level as usize * LEVEL_INDEX_ENTRY_SIZE;
+            let offset = read_u64(&data, entry) as usize;
+            let length = read_u64(&data, entry + 8) as usize;
+            let level_width = (width >> level).max(1);
+            let level_height = (height >> level).max(1);
+
+            let expected = format.level_size(level_width, level_height);
+            if length != expected {
+                bail!(
+                    "level {} is {} bytes but a {}x{} level needs {}",
+                    level,
+                    length,
+                    level_width,
+                    level_height,
+                    expected
+                );
+            }
+            if !offset.is_multiple_of(format.block_size) {
+                bail!("level {} at byte {} is not aligned", level, offset);
+            }
+            if offset < index_end || offset + length > data.len() {
+                bail!("level {} is outside of the file", level);
+            }
+            levels.push(Level {
+                width: level_width,
+                height: level_height,
+                offset,
+                length,
+            });
+        }
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+            data,
+        })
+    }
+
+    /// The compressed blocks for a level.
+    pub fn level_data(&self, level: &Level) -> &[u8] {
+        &self.data[level.offset..level.offset + level.length]
+    }
+
+    /// Decompress a level into tightly packed rgba8 pixels on the cpu.
+    pub fn decode_level(&self, level: &Level) -> Result<Vec<u8>> {
+        super::block_decode::decode(
+            &self.format,
+            level.width,
+            level.height,
+            self.level_data(level),
+        )
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -0,0 +1,375 @@
+use super::{decode_png, Texture, TextureBuilder, TextureKind};
+use crate::display;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread::JoinHandle;
+use vulkano::device::{Device, Queue};
+use vulkano::instance::PhysicalDevice;
+
+/// The threads each cache decodes images on.
+const LOADER_THREADS: usize = 2;
+
+/// The placeholder is a checkerboard this many texels across, in squares
+/// of `PLACEHOLDER_SQUARE` texels.
+const PLACEHOLDER_SIZE: u32 = 64;
+const PLACEHOLDER_SQUARE: u32 = 8;
+
+/// The placeholder's colors, starting with magenta at the first texel so
+/// it's told apart from an image easily.
+const PLACEHOLDER_COLORS: [[u8; 4]; 2] =
+    [[255, 0, 255, 255], [32, 32, 32, 255]];
+
+/// A cache gets this fraction of the largest device local heap by default.
+const DEFAULT_BUDGET_FRACTION: u64 = 4;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static CACHES: Mutex<BTreeMap<u64, TextureCacheStats>> =
+    Mutex::new(BTreeMap::new());
+
+/// Decodes the image a texture is requested by into tightly packed rgba8
+/// pixels, top row first. Called on the cache's loader threads.
+pub type TextureSource =
+    Arc<dyn Fn(&str) -> Result<(u32, u32, Vec<u8>)> + Send + Sync>;
+
+type Decoded = (String, Result<(u32, u32, Vec<u8>)>);
+
+/// What a texture cache holds and how well it's doing, since it was
+/// created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextureCacheStats {
+    /// What the cache was created for.
+    pub label: String,
+
+    /// Requests answered with a loaded texture.
+    pub hits: u64,
+
+    /// Requests which started a load, including reloads of evicted
+    /// textures.
+    pub misses: u64,
+
+    /// Textures dropped to stay under the budget.
+    pub evictions: u64,
+
+    /// Loads which failed. Their requests get the placeholder from then on.
+    pub failures: u64,
+
+    /// The textures loaded and their bytes.
+    pub resident: usize,
+    pub resident_bytes: u64,
+
+    /// Evicted textures which a frame still in flight used. Their memory is
+    /// freed once those frames finish.
+    pub retiring_bytes: u64,
+
+    /// Textures being decoded.
+    pub loading: usize,
+
+    pub budget: u64,
+}
+
+/// The stats of every texture cache alive right now, in the order they
+/// were created.
+pub fn texture_cache_stats() -> Vec<TextureCacheStats> {
+    caches().values().cloned().collect()
+}
+
+/// The caches' stats, which stay usable after a panic while they were held
+/// since every change is a single insert or remove.
+fn caches() -> MutexGuard<'static, BTreeMap<u64, TextureCacheStats>> {
+    CACHES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A source which decodes the png named by each request in `directory`.
+pub fn png_directory(directory: impl Into<PathBuf>) -> TextureSource {
+    let directory = directory.into();
+    Arc::new(move |name: &str| decode_png(&directory.join(name)))
+}
+
+/// A quarter of the largest device local heap, as the driver budgets it
+/// with `VK_EXT_memory_budget`, or of its size without the extension.
+pub fn default_budget(physical_device: PhysicalDevice) -> u64 {
+    let budgets = display::memory_budget(&physical_device).ok().flatten();
+    let largest = physical_device
+        .memory_heaps()
+        .filter(|heap| heap.is_device_local())
+        .map(|heap| {
+            budgets
+                .as_ref()
+                .and_then(|budgets| budgets.get(heap.id() as usize))
+                .map_or(heap.size() as u64, |budget| budget.budget)
+        })
+        .max()
+        .unwrap_or(0);
+    largest / DEFAULT_BUDGET_FRACTION
+}
+
+/// Loads textures by name when they're first asked for and keeps the most
+/// recently used under a budget, for sketches which cycle through more
+/// images than fit on the gpu at once.
+///
+/// Asking for a texture which isn't loaded starts decoding it on a loader
+/// thread and answers with a checkerboard placeholder until `update`
+/// uploads it. When the loaded textures take more than the budget, the
+/// least recently used are evicted, never one asked for in the frame being
+/// recorded. An evicted texture a frame in flight used is kept until that
+/// frame finishes, so eviction never frees memory the gpu may still read,
+/// and asking for it again loads it again.
+///
+/// Every request names the frame it's for and every update says how many
+/// frames finished, see `Display::frames_submitted` and
+/// `Display::frames_finished`.
+pub struct TextureCache {
+    id: u64,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    kind: TextureKind,
+    placeholder: Texture,
+    stats: TextureCacheStats,
+
+    // the newest frame anything was asked for in
+    frame: u64,
+    resident: HashMap<String, Entry>,
+    retiring: Vec<Entry>,
+    loading: HashSet<String>,
+    failed: HashSet<String>,
+
+    // dropped before the loaders are joined, which stops them
+    requests: Option<Sender<String>>,
+    results: Receiver<Decoded>,
+    loaders: Vec<JoinHandle<()>>,
+}
+
+struct Entry {
+    texture: Texture,
+    bytes: u64,
+
+    // the newest frame the texture was asked for in
+    last_used: u64,
+}
+
+impl TextureCache {
+    /// Start loader threads which decode images from `source`, with
+    /// `budget` bytes for the loaded textures. Textures are `Color` unless
+    /// another kind is set.
+    pub fn new(
+        label: &str,
+        device: &Arc<Device>,
+        queue: &Arc<Queue>,
+        budget: u64,
+        source: TextureSource,
+    ) -> Result<Self> {
+        let placeholder = checkerboard(device, queue)?;
+        let (requests, loader_requests) = mpsc::channel();
+        let (loader_results, results) = mpsc::channel();
+        let loaders = spawn_loaders(source, loader_requests, loader_results)?;
+        let cache = Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            device: device.clone(),
+            queue: queue.clone(),
+            kind: TextureKind::Color,
+            placeholder,
+            stats: TextureCacheStats {
+                label: label.to_owned(),
+                budget,
+                ..TextureCacheStats::default()
+            },
+            frame: 0,
+            resident: HashMap::new(),
+            retiring: vec![],
+            loading: HashSet::new(),
+            failed: HashSet::new(),
+            requests: Some(requests),
+            results,
+            loaders,
+        };
+        cache.publish();
+        Ok(cache)
+    }
+
+    /// Set how the loaded texels are interpreted.
+    pub fn kind(mut self, kind: TextureKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// The texture loaded from `name`, or the placeholder while it loads.
+    ///
+    /// @param frame the number of the frame the texture is drawn in
+    pub fn get(&mut self, name: &str, frame: u64) -> &Texture {
+        self.frame = self.frame.max(frame);
+        if self.resident.contains_key(name) {
+            self.stats.hits += 1;
+            let entry = self.resident.get_mut(name).unwrap();
+            entry.last_used = entry.last_used.max(frame);
+            return &entry.texture;
+        }
+        if !self.loading.contains(name) && !self.failed.contains(name) {
+            self.load(name);
+        }
+        &self.placeholder
+    }
+
+    /// The checkerboard shown in place of textures which aren't loaded.
+    pub fn placeholder(&self) -> &Texture {
+        &self.placeholder
+    }
+
+    /// Upload every texture the loaders finished, drop evicted textures
+    /// once the frames which used them finished, then evict the least
+    /// recently used textures while the cache is over budget.
+    ///
+    /// @param finished how many frames are known to have finished
+    pub fn update(&mut self, finished: u64) -> Result<()> {
+        while let Ok((name, decoded)) = self.results.try_recv() {
+            self.loading.remove(&name);
+            match decoded {
+                Ok((width, height, pixels)) => {
+                    let texture = TextureBuilder::from_rgba(
+                        &name, width, height, pixels,
+                    )?
+                    .kind(self.kind)
+                    .build(&self.device, &self.queue)
+                    .with_context(|| format!("unable to upload {}", name))?;
+                    let entry = Entry {
+                        texture,
+                        bytes: width as u64 * height as u64 * 4,
+                        last_used: self.frame,
+                    };
+                    self.resident.insert(name, entry);
+                }
+                Err(error) => {
+                    log::warn!(
+                        "unable to load the texture {} {:?}",
+                        name,
+                        error
+                    );
+                    self.stats.failures += 1;
+                    self.failed.insert(name);
+                }
+            }
+        }
+        self.retiring.retain(|entry| entry.last_used >= finished);
+        self.evict(finished);
+        self.publish();
+        Ok(())
+    }
+
+    /// What the cache holds and how well it's doing.
+    pub fn stats(&self) -> TextureCacheStats {
+        TextureCacheStats {
+            resident: self.resident.len(),
+            resident_bytes: self.resident.values().map(|e| e.bytes).sum(),
+            retiring_bytes: self.retiring.iter().map(|e| e.bytes).sum(),
+            loading: self.loading.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    fn load(&mut self, name: &str) {
+        let sent = match &self.requests {
+            Some(requests) => requests.send(name.to_owned()).is_ok(),
+            None => false,
+        };
+        if !sent {
+            log::warn!("the texture loaders stopped before loading {}", name);
+            self.failed.insert(name.to_owned());
+            return;
+        }
+        self.stats.misses += 1;
+        self.loading.insert(name.to_owned());
+    }
+
+    fn evict(&mut self, finished: u64) {
+        loop {
+            let stats = self.stats();
+            if stats.resident_bytes + stats.retiring_bytes <= stats.budget {
+                return;
+            }
+            let oldest = self
+                .resident
+                .iter()
+                .filter(|(_, entry)| entry.last_used < self.frame)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(name, _)| name.clone());
+            let entry =
+                match oldest.and_then(|name| self.resident.remove(&name)) {
+                    Some(entry) => entry,
+                    None => return,
+                };
+            self.stats.evictions += 1;
+            // frames finish in order, so a frame numbered below `finished`
+            // is done with it
+            if entry.last_used >= finished {
+                self.retiring.push(entry);
+            }
+        }
+    }
+
+    fn publish(&self) {
+        caches().insert(self.id, self.stats());
+    }
+}
+
+impl Drop for TextureCache {
+    fn drop(&mut self) {
+        caches().remove(&self.id);
+        self.requests = None;
+        for loader in self.loaders.drain(..) {
+            if loader.join().is_err() {
+                log::warn!("a texture loader panicked");
+            }
+        }
+    }
+}
+
+/// Decode each requested image on one of `LOADER_THREADS` threads, until
+/// the requests or the results are dropped.
+fn spawn_loaders(
+    source: TextureSource,
+    requests: Receiver<String>,
+    results: Sender<Decoded>,
+) -> Result<Vec<JoinHandle<()>>> {
+    let requests = Arc::new(Mutex::new(requests));
+    (0..LOADER_THREADS)
+        .map(|i| {
+            let source = source.clone();
+            let requests = requests.clone();
+            let results = results.clone();
+            std::thread::Builder::new()
+                .name(format!("texture loader {}", i))
+                .spawn(move || loop {
+                    let name = match requests.lock().unwrap().recv() {
+                        Ok(name) => name,
+                        Err(_) => return,
+                    };
+                    let decoded = source(&name);
+                    if results.send((name, decoded)).is_err() {
+                        return;
+                    }
+                })
+                .context("unable to start a texture loader")
+        })
+        .collect()
+}
+
+/// The placeholder, with a magenta square in its top left corner.
+fn checkerboard(device: &Arc<Device>, queue: &Arc<Queue>) -> Result<Texture> {
+    let mut pixels = Vec::with_capacity((PLACEHOLDER_SIZE.pow(2) * 4) as usize);
+    for y in 0..PLACEHOLDER_SIZE {
+        for x in 0..PLACEHOLDER_SIZE {
+            let square = (x / PLACEHOLDER_SQUARE + y / PLACEHOLDER_SQUARE) % 2;
+            pixels.extend_from_slice(&PLACEHOLDER_COLORS[square as usize]);
+        }
+    }
+    TextureBuilder::from_rgba(
+        "texture cache placeholder",
+        PLACEHOLDER_SIZE,
+        PLACEHOLDER_SIZE,
+        pixels,
+    )?
+    .build(device, queue)
+}
@@ -0,0 +1,283 @@
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use vulkan_starter::display::{
+    self, Allocator, Display, MemoryAllocation, MemoryUse, PooledAllocator,
+};
+use vulkano::buffer::sys::UnsafeBuffer;
+use vulkano::buffer::BufferUsage;
+use vulkano::device::Device;
+use vulkano::format::Format;
+use vulkano::image::sys::UnsafeImage;
+use vulkano::image::{ImageCreateFlags, ImageDimensions, ImageUsage};
+use vulkano::memory::pool::AllocLayout;
+use vulkano::sync::Sharing;
+use vulkano::VulkanObject;
+
+/// Resources created or dropped in the stress test.
+const OPERATIONS: usize = 5000;
+
+/// The stress test frees a random resource about this often once it holds
+/// any, so the live count wanders around a few hundred.
+const FREE_CHANCE: u64 = 45;
+
+/// Every so often a resource is larger than half a block, which gets a
+/// block of its own.
+const LARGE_BYTES: u64 = 40 * 1024 * 1024;
+
+/// Create and drop thousands of buffers and images bound to the pooled
+/// allocator's memory with the validation layers on. The placement rules
+/// themselves are unit tested on the cpu.
+///
+/// Every live resource is checked against every other: nothing overlaps,
+/// every offset is aligned, and linear and optimal resources never share
+/// a page of the buffer-image granularity. Once everything is dropped at
+/// most one spare block per memory type may be left, and the validation
+/// layers must not have complained about any bind. This creates a headless
+/// device, so it needs a gpu: run it with
+/// `cargo test --test allocator -- --ignored`.
+#[test]
+#[ignore]
+fn pooled_resources_never_overlap() -> Result<()> {
+    display::force_validation()?;
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let granularity = headless
+        .device
+        .physical_device()
+        .limits()
+        .buffer_image_granularity() as u64;
+    let allocator = PooledAllocator::new(&headless.device);
+    stress(&headless.device, &allocator, granularity.max(1))?;
+
+    let stats = allocator.stats();
+    for stats in &stats {
+        if stats.allocations > 0 || stats.blocks > 1 {
+            bail!(
+                "memory type {} still has {} allocations in {} blocks after \
+                everything was dropped",
+                stats.type_index,
+                stats.allocations,
+                stats.blocks
+            );
+        }
+    }
+    drop(allocator);
+    drop(headless);
+    let errors = display::validation_error_count();
+    if errors > 0 {
+        bail!("{} validation errors", errors);
+    }
+    Ok(())
+}
+
+enum Resource {
+    Buffer(UnsafeBuffer),
+    Image(UnsafeImage),
+}
+
+/// A resource and the memory it's bound to, dropped in that order.
+struct Live {
+    _resource: Resource,
+    allocation: MemoryAllocation,
+    size: u64,
+    alignment: u64,
+    layout: AllocLayout,
+}
+
+impl Live {
+    fn memory(&self) -> u64 {
+        self.allocation.memory().internal_object()
+    }
+
+    fn offset(&self) -> u64 {
+        self.allocation.offset() as u64
+    }
+}
+
+fn stress(
+    device: &Arc<Device>,
+    allocator: &PooledAllocator,
+    granularity: u64,
+) -> Result<()> {
+    let mut random = Random(0x5eed);
+    let mut live: Vec<Live> = vec![];
+    let mut most = 0;
+    for operation in 0..OPERATIONS {
+        if !live.is_empty() && random.below(100) < FREE_CHANCE {
+            let i = random.below(live.len() as u64) as usize;
+            live.swap_remove(i);
+            continue;
+        }
+        let created = if random.below(3) == 0 {
+            create_image(device, allocator, &mut random)
+        } else {
+            create_buffer(device, allocator, &mut random)
+        }
+        .with_context(|| format!("operation {} failed", operation))?;
+        check_placement(&created, &live, granularity)
+            .with_context(|| format!("operation {} overlaps", operation))?;
+        live.push(created);
+        most = most.max(live.len());
+        if operation % 1000 == 0 {
+            for stats in allocator.stats() {
+                println!(
+                    "memory type {}: {} allocations in {} blocks, {} of {} \
+                    bytes used, {:.0}% fragmented",
+                    stats.type_index,
+                    stats.allocations,
+                    stats.blocks,
+                    stats.used,
+                    stats.allocated,
+                    stats.fragmentation() * 100.0
+                );
+            }
+        }
+    }
+    println!("at most {} resources were alive at once", most);
+    Ok(())
+}
+
+fn create_buffer(
+    device: &Arc<Device>,
+    allocator: &PooledAllocator,
+    random: &mut Random,
+) -> Result<Live> {
+    let size = if random.below(200) == 0 {
+        LARGE_BYTES
+    } else {
+        256 << random.below(14)
+    };
+    let usage = if random.below(2) == 0 {
+        MemoryUse::HostVisible
+    } else {
+        MemoryUse::DeviceLocal
+    };
+    let buffer_usage = BufferUsage {
+        storage_buffer: true,
+        vertex_buffer: true,
+        transfer_source: true,
+        transfer_destination: true,
+        ..BufferUsage::none()
+    };
+    // safe because the buffer is bound before it's dropped and never used
+    let (buffer, requirements) = unsafe {
+        UnsafeBuffer::new(
+            device.clone(),
+            size as usize,
+            buffer_usage,
+            Sharing::Exclusive::<std::iter::Empty<u32>>,
+            None,
+        )
+    }
+    .context("unable to create a buffer")?;
+    let allocation =
+        allocator.allocate(&requirements, AllocLayout::Linear, usage)?;
+    unsafe { buffer.bind_memory(allocation.memory(), allocation.offset()) }
+        .context("unable to bind a buffer")?;
+    Ok(Live {
+        _resource: Resource::Buffer(buffer),
+        allocation,
+        size: requirements.size as u64,
+        alignment: requirements.alignment as u64,
+        layout: AllocLayout::Linear,
+    })
+}
+
+fn create_image(
+    device: &Arc<Device>,
+    allocator: &PooledAllocator,
+    random: &mut Random,
+) -> Result<Live> {
+    let side = 16 << random.below(6);
+    // safe because the image is bound before it's dropped and never used
+    let (image, requirements) = unsafe {
+        UnsafeImage::new(
+            device.clone(),
+            ImageUsage {
+                sampled: true,
+                transfer_destination: true,
+                ..ImageUsage::none()
+            },
+            Format::R8G8B8A8Unorm,
+            ImageCreateFlags::none(),
+            ImageDimensions::Dim2d {
+                width: side,
+                height: side,
+                array_layers: 1,
+            },
+            1,
+            1,
+            Sharing::Exclusive::<std::iter::Empty<u32>>,
+            false,
+            false,
+        )
+    }
+    .context("unable to create an image")?;
+    let allocation = allocator.allocate(
+        &requirements,
+        AllocLayout::Optimal,
+        MemoryUse::DeviceLocal,
+    )?;
+    unsafe { image.bind_memory(allocation.memory(), allocation.offset()) }
+        .context("unable to bind an image")?;
+    Ok(Live {
+        _resource: Resource::Image(image),
+        allocation,
+        size: requirements.size as u64,
+        alignment: requirements.alignment as u64,
+        layout: AllocLayout::Optimal,
+    })
+}
+
+/// Fail when `created` isn't aligned, overlaps a live resource in the same
+/// memory, or shares a page with one of the other layout.
+fn check_placement(
+    created: &Live,
+    live: &[Live],
+    granularity: u64,
+) -> Result<()> {
+    let start = created.offset();
+    let end = start + created.size;
+    if !start.is_multiple_of(created.alignment.max(1)) {
+        bail!("{} isn't aligned to {} bytes", start, created.alignment);
+    }
+    for other in live
+        .iter()
+        .filter(|other| other.memory() == created.memory())
+    {
+        let other_start = other.offset();
+        let other_end = other_start + other.size;
+        if start < other_end && other_start < end {
+            bail!("{}..{} overlaps {}..{}", start, end, other_start, other_end);
+        }
+        if other.layout != created.layout {
+            let (first_end, second_start) = if start < other_start {
+                (end, other_start)
+            } else {
+                (other_end, start)
+            };
+            if (first_end - 1) / granularity == second_start / granularity {
+                bail!(
+                    "a linear and an optimal resource share the page at {}",
+                    second_start / granularity * granularity
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A small deterministic generator so every run stresses the same way.
+/// (splitmix64)
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, bound: u64) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        z % bound
+    }
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "device-report")]
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::path::Path;
+use vulkan_starter::display::{self, Display, REQUIRED_LIMITS};
+
+/// The values every device this crate runs on must report.
+const BASELINE: &str = "ci/device_baseline.json";
+
+/// Set to the path of a saved report to check it instead of this machine's
+/// device.
+const REPORT: &str = "DEVICE_REPORT";
+
+/// Check a device against the required limits and the checked in baseline.
+///
+/// The report is read from the file `DEVICE_REPORT` names when it's set,
+/// like a report saved with `--device-report` on another machine, so this
+/// runs without a gpu. Otherwise it comes from a headless device, which
+/// needs a gpu: run it with
+/// `cargo test --features device-report --test device_report -- --ignored`.
+#[test]
+#[ignore]
+fn device_meets_the_baseline() -> Result<()> {
+    let report = match std::env::var_os(REPORT) {
+        Some(path) => read_json(Path::new(&path))?,
+        None => {
+            let headless = Display::create_headless()?;
+            display::generate_device_report(&headless.device.physical_device())
+        }
+    };
+    let name = report["properties"]["device_name"].clone();
+    display::check_required_limits(&report)?;
+    println!("{} has the {} required limits", name, REQUIRED_LIMITS.len());
+    display::check_baseline(&report, &read_json(Path::new(BASELINE))?)?;
+    println!("{} matches {}", name, BASELINE);
+    Ok(())
+}
+
+fn read_json(path: &Path) -> Result<Value> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read {:?}", path))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("unable to parse {:?}", path))
+}
@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+use vulkan_starter::application::golden::{compare, Golden, Tolerance};
+use vulkan_starter::display::{CapturePolicy, LatencyMode};
+use vulkan_starter::Application;
+
+/// The seed the particles start from in every golden image.
+const SEED: u64 = 1;
+
+/// A golden test: sets up a feature on the application, captures a frame
+/// with `Golden::check`, and puts the application back the way it was.
+type GoldenTest = fn(&mut Application, &Golden) -> Result<()>;
+
+/// Every golden test, by name.
+const TESTS: &[(&str, GoldenTest)] = &[
+    ("default", default),
+    ("color_grading", color_grading),
+    ("motion_blur", motion_blur),
+    ("depth_of_field", depth_of_field),
+    ("lens_effects", lens_effects),
+    ("aspect", aspect),
+];
+
+/// Set to write the captured frames as the new golden images.
+const REGENERATE: &str = "GOLDEN_REGENERATE";
+
+/// Render a frame for each golden test in a hidden window and compare it
+/// with the golden image in `assets/golden`.
+///
+/// Every frame is drawn at 256x256 from the same seed and animation time.
+/// Failures write the frame and a diff image to `target/golden` and the
+/// remaining tests still run. With `GOLDEN_REGENERATE` set the frames are
+/// written as the new golden images instead, which should only be done on
+/// purpose after a change to the rendering. This opens a hidden window, so
+/// it needs a display.
+#[test]
+fn frames_match_the_golden_images() -> Result<()> {
+    let golden = Golden {
+        size: [256, 256],
+        time: 2.0,
+        regenerate: std::env::var_os(REGENERATE).is_some(),
+    };
+    let mut app = Application::initialize(
+        None,
+        None,
+        None,
+        Some(SEED),
+        LatencyMode::Throughput,
+        CapturePolicy::Default,
+    )?;
+    golden.prepare(&mut app)?;
+
+    let mut failures = 0;
+    for (name, test) in TESTS {
+        if let Err(error) = test(&mut app, &golden) {
+            failures += 1;
+            eprintln!("{} failed: {:?}", name, error);
+        }
+    }
+    if failures > 0 {
+        bail!("{} of {} golden tests failed", failures, TESTS.len());
+    }
+    Ok(())
+}
+
+fn default(app: &mut Application, golden: &Golden) -> Result<()> {
+    golden.check(app, "default", Tolerance::default())
+}
+
+fn color_grading(app: &mut Application, golden: &Golden) -> Result<()> {
+    let toggle = Application::toggle_color_grading;
+    toggled(app, golden, "color_grading", Tolerance::default(), toggle)
+}
+
+fn motion_blur(app: &mut Application, golden: &Golden) -> Result<()> {
+    let toggle = Application::toggle_motion_blur;
+    toggled(app, golden, "motion_blur", Tolerance::default(), toggle)
+}
+
+/// The blur is wide and its edges are soft, so more pixels are allowed
+/// to round differently.
+fn depth_of_field(app: &mut Application, golden: &Golden) -> Result<()> {
+    let tolerance = Tolerance {
+        channel: 4,
+        pixels: 256,
+    };
+    let toggle = Application::toggle_depth_of_field;
+    toggled(app, golden, "depth_of_field", tolerance, toggle)
+}
+
+fn lens_effects(app: &mut Application, golden: &Golden) -> Result<()> {
+    let toggle = Application::toggle_lens_effects;
+    toggled(app, golden, "lens_effects", Tolerance::default(), toggle)
+}
+
+/// The default frame in a window half again as wide, then half again as
+/// tall. Each has its own golden image, and the square in the middle of
+/// each must match the other's, since a unit is the same size across and
+/// up whatever the window's shape. The window is square again afterwards.
+fn aspect(app: &mut Application, golden: &Golden) -> Result<()> {
+    let result = aspect_ratios(app, golden);
+    golden.prepare(app)?;
+    result
+}
+
+fn aspect_ratios(app: &mut Application, golden: &Golden) -> Result<()> {
+    let [width, height] = golden.size;
+    let mut middles = vec![];
+    for &(name, size) in &[
+        ("aspect_wide", [width * 3 / 2, height]),
+        ("aspect_tall", [width, height * 3 / 2]),
+    ] {
+        let resized = Golden {
+            size,
+            time: golden.time,
+            regenerate: golden.regenerate,
+        };
+        resized.prepare(app)?;
+        resized.check(app, name, Tolerance::default())?;
+        app.set_time(golden.time);
+        middles.push(app.capture_frame()?.crop_center(golden.size)?);
+    }
+    let tolerance = Tolerance::default();
+    let comparison = compare(&middles[0], &middles[1], tolerance)?;
+    if comparison.differing > tolerance.pixels {
+        bail!(
+            "the middles of the wide and tall frames differ in {} pixels, \
+            the proportions change with the window",
+            comparison.differing
+        );
+    }
+    Ok(())
+}
+
+/// Check a frame with a feature toggled on, toggling it off again even when
+/// the check fails so the next test starts from the default frame.
+fn toggled(
+    app: &mut Application,
+    golden: &Golden,
+    name: &str,
+    tolerance: Tolerance,
+    toggle: fn(&mut Application) -> Result<()>,
+) -> Result<()> {
+    toggle(app)?;
+    let result = golden.check(app, name, tolerance);
+    toggle(app)?;
+    result
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use vulkan_starter::display::{self, HeapBudget};
+use vulkan_starter::Application;
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, ImmutableBuffer};
+use vulkano::sync::GpuFuture;
+
+/// Frames rendered after the baseline is taken.
+const FRAMES: usize = 1000;
+
+/// Frames rendered before the baseline, long enough for anything which is
+/// created lazily on the first frames to exist.
+const WARM_UP_FRAMES: usize = 10;
+
+/// The size of the buffer staged each frame.
+const STAGING_BYTES: usize = 256 * 1024;
+
+/// How far each heap's usage may end up from the baseline.
+const TOLERANCE: u64 = 1024 * 1024;
+
+/// Render 1000 frames, uploading through a fresh staging buffer on each,
+/// and check that every memory heap's usage returns to where it started.
+///
+/// Usage comes from `VK_EXT_memory_budget`, so it includes memory the driver
+/// allocates for us. Resources kept alive by a future which was dropped
+/// without being waited on pile up here long before anything runs out. This
+/// opens a hidden window, so it needs a display.
+#[test]
+fn heap_usage_returns_to_the_baseline() -> Result<()> {
+    let mut app = Application::new()?;
+    app.render_frames(WARM_UP_FRAMES)?;
+    let baseline = heap_usage(&app)?;
+
+    for frame in 0..FRAMES {
+        stage_buffer(&app)
+            .with_context(|| format!("unable to stage frame {}", frame))?;
+        app.render_frames(1)?;
+    }
+    let after = heap_usage(&app)?;
+
+    let grown: Vec<String> = baseline
+        .iter()
+        .zip(&after)
+        .enumerate()
+        .filter(|(_, (before, after))| {
+            after.usage.saturating_sub(before.usage) > TOLERANCE
+        })
+        .map(|(heap, (before, after))| {
+            format!(
+                "heap {} grew from {} to {} bytes",
+                heap, before.usage, after.usage
+            )
+        })
+        .collect();
+    assert!(
+        grown.is_empty(),
+        "heaps grew over {} frames: {}",
+        FRAMES,
+        grown.join(", ")
+    );
+    Ok(())
+}
+
+fn heap_usage(app: &Application) -> Result<Vec<HeapBudget>> {
+    let physical_device = app.display().device.physical_device();
+    display::memory_budget(&physical_device)?
+        .context("the device doesn't support VK_EXT_memory_budget")
+}
+
+/// Upload a buffer through a staging buffer, waiting for the copy, then
+/// drop both.
+fn stage_buffer(app: &Application) -> Result<()> {
+    let display = app.display();
+    let staging = CpuAccessibleBuffer::from_iter(
+        display.device.clone(),
+        BufferUsage::transfer_source(),
+        false,
+        (0..STAGING_BYTES).map(|byte| byte as u8),
+    )
+    .context("unable to create the staging buffer")?;
+    let (_buffer, upload) = ImmutableBuffer::from_buffer(
+        staging,
+        BufferUsage::vertex_buffer(),
+        display.graphics_queue.clone(),
+    )
+    .context("unable to upload the staging buffer")?;
+    upload
+        .then_signal_fence_and_flush()?
+        .wait(None)
+        .context("the upload never finished")?;
+    Ok(())
+}
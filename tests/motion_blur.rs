@@ -0,0 +1,168 @@
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use vulkan_starter::application::debug::BarrierValidator;
+use vulkan_starter::application::post_process::{
+    VelocityTarget, VelocityVertex, IDENTITY,
+};
+use vulkan_starter::display::{Display, HeadlessDisplay};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::pipeline::ComputePipeline;
+use vulkano::sampler::Sampler;
+use vulkano::sync::GpuFuture;
+
+/// The width and height of the velocity buffer.
+const SIZE: u32 = 128;
+
+/// How far the moving point travels in one frame, in normalized device
+/// coordinates. Across 128 pixels this is 16 pixels.
+const MOTION: f32 = 0.25;
+
+/// Fill a velocity buffer with a static scene and a moving one and check
+/// that only the moving scene would be blurred.
+///
+/// Points which did not move must write exactly zero velocity, so the blur
+/// copies those pixels unchanged. This creates a headless device, so it
+/// needs a gpu: run it with `cargo test --test motion_blur -- --ignored`.
+#[test]
+#[ignore]
+fn only_moving_points_have_a_velocity() -> Result<()> {
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let target = VelocityTarget::new(
+        &headless.device,
+        &headless.graphics_queue,
+        [SIZE, SIZE],
+    )?;
+
+    let point = |previous_x: f32| VelocityVertex {
+        pos: [0.0, 0.0],
+        previous_pos: [previous_x, 0.0],
+    };
+    let static_speed = fastest_pixel(&headless, &target, point(0.0))?;
+    let moving_speed = fastest_pixel(&headless, &target, point(-MOTION))?;
+    let expected = MOTION * 0.5 * SIZE as f32;
+    println!(
+        "static scene {:.3} px, moving scene {:.3} px (expected {:.3} px)",
+        static_speed, moving_speed, expected
+    );
+
+    if static_speed != 0.0 {
+        bail!("a static scene has a velocity of {} px", static_speed);
+    }
+    if (moving_speed - expected).abs() > 0.1 {
+        bail!(
+            "a moving scene has a velocity of {} px, expected {} px",
+            moving_speed,
+            expected
+        );
+    }
+    Ok(())
+}
+
+/// Render a single point into the velocity buffer and find the longest
+/// velocity in it with a compute shader.
+fn fastest_pixel(
+    headless: &HeadlessDisplay,
+    target: &VelocityTarget,
+    vertex: VelocityVertex,
+) -> Result<f32> {
+    let device = &headless.device;
+    let queue = &headless.graphics_queue;
+    target
+        .render(device, queue, vec![vertex], IDENTITY, IDENTITY)?
+        .execute(queue.clone())
+        .context("unable to render the velocities")?
+        .then_signal_fence_and_flush()
+        .context("unable to flush the velocities")?
+        .wait(None)
+        .context("unable to wait for the velocities")?;
+
+    let pipeline = Arc::new(
+        ComputePipeline::new(
+            device.clone(),
+            &max_shader::Shader::load(device.clone())
+                .context("unable to load the max velocity shader")?
+                .main_entry_point(),
+            &(),
+            None,
+        )
+        .context("unable to create the max velocity pipeline")?,
+    );
+    let result = CpuAccessibleBuffer::from_data(
+        device.clone(),
+        BufferUsage::all(),
+        false,
+        0.0f32,
+    )
+    .context("unable to create the max velocity buffer")?;
+    let set = Arc::new(
+        PersistentDescriptorSet::start(
+            pipeline
+                .descriptor_set_layout(0)
+                .context("max velocity pipeline has no descriptor set")?
+                .clone(),
+        )
+        .add_sampled_image(
+            target.image.clone(),
+            Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+        )?
+        .add_buffer(result.clone())?
+        .build()
+        .context("unable to create the max velocity descriptors")?,
+    );
+
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            queue.family(),
+        )
+        .context("unable to create the max velocity command buffer builder")?,
+        &queue,
+    );
+    builder
+        .dispatch([1, 1, 1], pipeline, set, ())
+        .context("unable to find the max velocity")?;
+    builder
+        .build()
+        .context("unable to build the max velocity command buffer")?
+        .execute(queue.clone())
+        .context("unable to execute the max velocity shader")?
+        .then_signal_fence_and_flush()
+        .context("unable to flush the max velocity shader")?
+        .wait(None)
+        .context("unable to wait for the max velocity shader")?;
+
+    let speed = *result.read().context("unable to read the max velocity")?;
+    Ok(speed)
+}
+
+mod max_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D velocity;
+            layout(set = 0, binding = 1) buffer Result {
+                float speed;
+            } result;
+
+            void main() {
+                ivec2 size = textureSize(velocity, 0);
+                float fastest = 0.0;
+                for (int y = 0; y < size.y; y++) {
+                    for (int x = 0; x < size.x; x++) {
+                        vec2 v = texelFetch(velocity, ivec2(x, y), 0).xy;
+                        fastest = max(fastest, length(v));
+                    }
+                }
+                result.speed = fastest;
+            }
+        "#
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::{bail, Context, Result};
+use vulkan_starter::application::noise::{NoiseTextureGenerator, NoiseType};
+use vulkan_starter::display::Display;
+
+const SIZE: u32 = 256;
+const OCTAVES: u32 = 5;
+const LACUNARITY: f32 = 2.0;
+const GAIN: f32 = 0.5;
+
+/// Generate every noise type on a headless device and check that the same
+/// seed always gives the same texels while a different seed does not.
+///
+/// The range and mean of each type are printed so changes to the noise
+/// functions can be compared. This creates a headless device, so it needs a
+/// gpu: run it with `cargo test --test noise -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn noise_is_repeatable_for_a_seed() -> Result<()> {
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let (device, queue) = (&headless.device, &headless.graphics_queue);
+    let generator = NoiseTextureGenerator::new(device, 1)?;
+    let reseeded = NoiseTextureGenerator::new(device, 2)?;
+
+    let mut failures = 0;
+    for &noise_type in &NoiseType::ALL {
+        let generate = |generator: &NoiseTextureGenerator| {
+            generator.generate_values(
+                device, queue, SIZE, SIZE, noise_type, OCTAVES, LACUNARITY,
+                GAIN,
+            )
+        };
+        let first = generate(&generator)?;
+        let second = generate(&generator)?;
+        let other_seed = generate(&reseeded)?;
+        generator.generate(
+            device, queue, SIZE, SIZE, noise_type, OCTAVES, LACUNARITY, GAIN,
+        )?;
+
+        let min = first.iter().copied().fold(f32::INFINITY, f32::min);
+        let max = first.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let mean = first.iter().sum::<f32>() / first.len() as f32;
+        println!(
+            "{:?} noise in [{:.3}, {:.3}] with mean {:.3}",
+            noise_type, min, max, mean
+        );
+
+        if first != second {
+            failures += 1;
+            eprintln!("{:?} noise changed between runs", noise_type);
+        }
+        if first == other_seed {
+            failures += 1;
+            eprintln!("{:?} noise ignores the seed", noise_type);
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} noise checks failed", failures);
+    }
+    Ok(())
+}
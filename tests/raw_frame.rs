@@ -0,0 +1,103 @@
+use anyhow::{bail, Context, Result};
+use std::sync::Arc;
+use vulkan_starter::application::camera::Camera;
+use vulkan_starter::application::raw_frame::RawFrame;
+use vulkan_starter::{Application, Display, Sketch};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::{Dimensions, StorageImage};
+
+/// The side of the square image the raw command buffer clears.
+const SIZE: u32 = 4;
+
+/// The color the image is cleared to, as 8 bit unorm.
+const CLEAR: [u8; 4] = [255, 0, 128, 255];
+
+/// Clears an offscreen image and copies it to the cpu with a command buffer
+/// built from raw vulkano, handed to every frame.
+struct RawClear {
+    image: Arc<StorageImage<Format>>,
+    readback: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+impl Sketch for RawClear {
+    fn render_raw(&mut self, frame: &mut RawFrame) -> Result<()> {
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            frame.device().clone(),
+            frame.graphics_queue().family(),
+        )
+        .context("unable to create the raw command buffer builder")?;
+        let color = CLEAR.map(|c| c as f32 / 255.0);
+        builder
+            .clear_color_image(self.image.clone(), ClearValue::Float(color))
+            .context("unable to clear the raw image")?
+            .copy_image_to_buffer(self.image.clone(), self.readback.clone())
+            .context("unable to read back the raw image")?;
+        let commands = builder
+            .build()
+            .context("unable to build the raw command buffer")?;
+        frame.execute_raw(Arc::new(commands));
+        Ok(())
+    }
+
+    fn draw(
+        &self,
+        _display: &Display,
+        _camera: &Camera,
+    ) -> Result<Vec<AutoCommandBuffer>> {
+        Ok(vec![])
+    }
+}
+
+/// Hand the frame a raw command buffer which clears an offscreen image, then
+/// check every texel read back from it.
+///
+/// The buffer is scheduled with the application's passes, so this checks
+/// that it's chained into the frame and finished by the time the frame is.
+/// This opens a hidden window, so it needs a display.
+#[test]
+fn raw_command_buffers_run_with_the_frame() -> Result<()> {
+    let mut app = Application::new()?;
+    let device = app.display().device.clone();
+    let queue = app.display().graphics_queue.clone();
+    let image = StorageImage::new(
+        device.clone(),
+        Dimensions::Dim2d {
+            width: SIZE,
+            height: SIZE,
+        },
+        Format::R8G8B8A8Unorm,
+        Some(queue.family()),
+    )
+    .context("unable to create the raw image")?;
+    let readback = CpuAccessibleBuffer::from_iter(
+        device,
+        BufferUsage::transfer_destination(),
+        false,
+        (0..SIZE * SIZE * 4).map(|_| 0u8),
+    )
+    .context("unable to create the raw readback buffer")?;
+    app.set_sketch(RawClear {
+        image,
+        readback: readback.clone(),
+    });
+    app.render_frames(2)?;
+
+    let texels = readback
+        .read()
+        .context("the raw readback is still in use")?;
+    let wrong = texels
+        .chunks_exact(4)
+        .filter(|texel| *texel != CLEAR)
+        .count();
+    if wrong > 0 {
+        bail!(
+            "{} of {} texels weren't cleared, the first is {:?}",
+            wrong,
+            SIZE * SIZE,
+            &texels[..4]
+        );
+    }
+    Ok(())
+}
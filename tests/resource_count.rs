@@ -0,0 +1,32 @@
+use anyhow::{Context, Result};
+use vulkan_starter::Application;
+
+/// Frames drawn before the application is destroyed.
+const FRAMES: usize = 50;
+
+/// Render frames with the world and every post processing effect on, print
+/// the reference count of each of the display's resources, then destroy
+/// the application and fail if anything still held one of them.
+///
+/// Every clone of a render pass, swapchain image, queue, or the device
+/// which outlives the application keeps it alive past the point it's meant
+/// to be freed, after the device or instance it belongs to. This opens a
+/// hidden window, so it needs a display.
+#[test]
+fn resources_are_dropped_by_their_last_owner() -> Result<()> {
+    let mut app = Application::new()?;
+    app.create_world()?;
+    app.toggle_color_grading()?;
+    app.toggle_motion_blur()?;
+    app.toggle_depth_of_field()?;
+    app.toggle_lens_effects()?;
+    app.render_frames(FRAMES)?;
+
+    for (name, count) in app.debug_resource_counts() {
+        println!("{}: {} references", name, count);
+    }
+    app.destroy_checked()
+        .context("resources outlived the application")?;
+    println!("every resource was dropped by its last owner");
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::{Context, Result};
+use vulkan_starter::application::compute_pipeline::{Simulation, SphFluid};
+use vulkan_starter::application::cull::Bounds;
+use vulkan_starter::Application;
+use vulkano::command_buffer::CommandBuffer;
+use vulkano::sync::GpuFuture;
+
+/// Particles in the fluid.
+const PARTICLES: usize = 4096;
+
+/// Frames stepped, each of them several steps of the simulation.
+const FRAMES: usize = 200;
+
+/// How fast the fluid's center of mass may end up moving, in units a
+/// second. The fluid starts at rest, so this is the whole drift.
+const MAX_DRIFT: f32 = 1e-3;
+
+/// Step a fluid without gravity or surface tension in a box which wraps
+/// around for 200 frames, and check its total momentum stays near zero.
+///
+/// Pressure and viscosity act between pairs of particles equally and
+/// oppositely, so any momentum the fluid gains comes from the kernels, the
+/// hash grid missing neighbors across the wrapped boundary, or rounding.
+/// This opens a hidden window, so it needs a display.
+#[test]
+fn periodic_fluid_keeps_its_momentum() -> Result<()> {
+    let app = Application::new()?;
+    let display = app.display();
+    let domain = Bounds::Aabb {
+        min: [-0.8, -0.6, -0.3],
+        max: [0.8, 0.6, 0.3],
+    };
+    let mut fluid = SphFluid::new(display, PARTICLES, domain)?;
+    fluid.set_gravity(0.0);
+    fluid.set_surface_tension(0.0);
+    fluid.set_periodic(true);
+
+    for frame in 0..FRAMES {
+        fluid
+            .step(display)?
+            .execute(display.graphics_queue.clone())
+            .with_context(|| format!("unable to step frame {}", frame))?
+            .then_signal_fence_and_flush()?
+            .wait(None)
+            .context("the step never finished")?;
+    }
+
+    let momentum = fluid.momentum()?;
+    let drift =
+        momentum.iter().map(|p| p * p).sum::<f32>().sqrt() / fluid.total_mass();
+    assert!(
+        drift <= MAX_DRIFT,
+        "the fluid's center of mass moves at {} after {} frames, momentum \
+        {:?}",
+        drift,
+        FRAMES,
+        momentum
+    );
+    Ok(())
+}
@@ -0,0 +1,402 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::sync::Arc;
+use vulkan_starter::application::debug::BarrierValidator;
+use vulkan_starter::display::{Display, HeadlessDisplay};
+use vulkan_starter::texture::ktx2::Ktx2;
+use vulkan_starter::texture::{
+    self, Texture, TextureArray, TextureBuilder, TextureKind,
+};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::pipeline::ComputePipeline;
+use vulkano::sampler::Sampler;
+use vulkano::sync::GpuFuture;
+
+/// A 256 texel wide ramp from black to white.
+const GREY_RAMP: &str = "assets/textures/grey_ramp.png";
+
+/// A KTX2 file of BC1 blocks with a full mip chain.
+const BC1_BLOCKS: &str = "assets/textures/bc1_blocks.ktx2";
+
+/// The grey ramp's texels increase by one from 0 at the left edge, so this
+/// column holds the 50% grey value 128.
+const MID_GREY_COLUMN: i32 = 128;
+
+/// How far a sampled value may be from the expected value. Hardware sRGB
+/// decoding is only required to be accurate to about this much.
+const TOLERANCE: f32 = 0.01;
+
+/// Load the grey ramp as each texture kind and check that the 50% grey texel
+/// reads back as the expected linear value in a shader.
+///
+/// A color texture decodes sRGB 128 to about 0.216 while a data texture
+/// reads back the raw 128/255. This creates a headless device, so it needs a
+/// gpu: run it with `cargo test --test texture -- --ignored`.
+#[test]
+#[ignore]
+fn color_and_data_textures_read_back_their_values() -> Result<()> {
+    let ramp = Path::new(GREY_RAMP);
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+
+    let mut failures = 0;
+    for &kind in &[TextureKind::Color, TextureKind::Data] {
+        let texture = TextureBuilder::from_png(ramp)?
+            .kind(kind)
+            .build(&headless.device, &headless.graphics_queue)?;
+        let sampled = read_texel(&headless, &texture, MID_GREY_COLUMN)?;
+        let expected = expected_value(texture.kind, MID_GREY_COLUMN as u8);
+        if (sampled - expected).abs() > TOLERANCE {
+            failures += 1;
+            eprintln!(
+                "{:?} texture in {:?} sampled {:.4}, expected {:.4}",
+                texture.kind, texture.format, sampled, expected
+            );
+        } else {
+            println!(
+                "{:?} texture in {:?} sampled {:.4}",
+                texture.kind, texture.format, sampled
+            );
+        }
+    }
+
+    if failures > 0 {
+        bail!("{} texture kinds read back the wrong value", failures);
+    }
+    Ok(())
+}
+
+/// Parse a KTX2 file, check every level's dimensions and offset against the
+/// spec, then load it and check that a texel in the top row samples the same
+/// value as the cpu decoder produces. This needs a gpu.
+#[test]
+#[ignore]
+fn ktx2_levels_are_laid_out_and_sampled_as_decoded() -> Result<()> {
+    let path = Path::new(BC1_BLOCKS);
+    let data = std::fs::read(path)
+        .with_context(|| format!("unable to read {:?}", path))?;
+    let ktx = Ktx2::parse(data)
+        .with_context(|| format!("unable to parse {:?}", path))?;
+    println!(
+        "{:?} is {}x{} {:?} with {} levels",
+        path,
+        ktx.width,
+        ktx.height,
+        ktx.format.format,
+        ktx.levels.len()
+    );
+
+    // the spec requires levels to be stored from smallest to largest, each
+    // aligned to the block size
+    let mut previous_offset = None;
+    for (index, level) in ktx.levels.iter().enumerate() {
+        println!(
+            "level {}: {}x{} at byte {}, {} bytes",
+            index, level.width, level.height, level.offset, level.length
+        );
+        if level.width != (ktx.width >> index).max(1)
+            || level.height != (ktx.height >> index).max(1)
+        {
+            bail!("level {} has the wrong dimensions", index);
+        }
+        if previous_offset.is_some_and(|offset| level.offset >= offset) {
+            bail!("level {} is not stored before level {}", index, index - 1);
+        }
+        previous_offset = Some(level.offset);
+    }
+
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let texture =
+        texture::load_ktx2(&headless.device, &headless.graphics_queue, path)?;
+    let top_level = &ktx.levels[0];
+    let column = top_level.width as i32 / 2;
+    let pixels = match ktx.decode_level(top_level) {
+        Ok(pixels) => pixels,
+        Err(error) => {
+            eprintln!("skipping the texel comparison: {}", error);
+            return Ok(());
+        }
+    };
+    let sampled = read_texel(&headless, &texture, column)?;
+    let expected = expected_value(texture.kind, pixels[column as usize * 4]);
+    if (sampled - expected).abs() > TOLERANCE {
+        bail!(
+            "{:?} texture in {:?} sampled {:.4}, expected {:.4}",
+            texture.kind,
+            texture.format,
+            sampled,
+            expected
+        );
+    }
+    println!(
+        "{:?} texture in {:?} sampled {:.4}",
+        texture.kind, texture.format, sampled
+    );
+    Ok(())
+}
+
+/// The red value of each layer in the texture array check.
+const LAYER_VALUES: [u8; 3] = [51, 128, 204];
+
+/// Build a texture array with a different value in each layer and check that
+/// a shader reads each layer back when it selects it, then check that a
+/// mismatched layer is rejected by name. This needs a gpu.
+#[test]
+#[ignore]
+fn texture_array_layers_are_read_back_by_index() -> Result<()> {
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let (device, queue) = (&headless.device, &headless.graphics_queue);
+    let solid = |name: &str, size: u32, value: u8| {
+        let pixels = [value, 0, 0, 255].repeat((size * size) as usize);
+        TextureBuilder::from_rgba(name, size, size, pixels)
+            .map(|builder| builder.kind(TextureKind::Data))
+    };
+
+    let layers = LAYER_VALUES
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| solid(&format!("layer {}", index), 4, value))
+        .collect::<Result<Vec<_>>>()?;
+    let array = TextureArray::build(layers, device, queue)?;
+    println!(
+        "{} layer {:?} array in {:?}",
+        array.layers, array.kind, array.format
+    );
+
+    let mut failures = 0;
+    for (layer, &value) in LAYER_VALUES.iter().enumerate() {
+        let sampled = read_layer(&headless, &array, layer as i32)?;
+        let expected = expected_value(array.kind, value);
+        if (sampled - expected).abs() > TOLERANCE {
+            failures += 1;
+            eprintln!(
+                "layer {} sampled {:.4}, expected {:.4}",
+                layer, sampled, expected
+            );
+        }
+    }
+
+    let mismatched = vec![solid("square", 4, 0)?, solid("odd one out", 8, 0)?];
+    match TextureArray::build(mismatched, device, queue) {
+        Ok(_) => {
+            failures += 1;
+            eprintln!("layers with different sizes were accepted");
+        }
+        Err(error) if !error.to_string().contains("odd one out") => {
+            failures += 1;
+            eprintln!("the size error does not name the layer: {}", error);
+        }
+        Err(error) => println!("rejected mismatched layers: {}", error),
+    }
+
+    if failures > 0 {
+        bail!("{} texture array checks failed", failures);
+    }
+    Ok(())
+}
+
+/// The linear value a shader should see for an 8 bit source value.
+fn expected_value(kind: TextureKind, value: u8) -> f32 {
+    let encoded = value as f32 / 255.0;
+    match kind {
+        TextureKind::Data => encoded,
+        TextureKind::Color if encoded <= 0.04045 => encoded / 12.92,
+        TextureKind::Color => ((encoded + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// Fetch the red channel of a texel in the top row with a compute shader.
+fn read_texel(
+    headless: &HeadlessDisplay,
+    texture: &Texture,
+    column: i32,
+) -> Result<f32> {
+    let device = &headless.device;
+    let pipeline = Arc::new(
+        ComputePipeline::new(
+            device.clone(),
+            &fetch_shader::Shader::load(device.clone())
+                .context("unable to load the texel fetch shader")?
+                .main_entry_point(),
+            &(),
+            None,
+        )
+        .context("unable to create the texel fetch pipeline")?,
+    );
+    let result = CpuAccessibleBuffer::from_data(
+        device.clone(),
+        BufferUsage::all(),
+        false,
+        0.0f32,
+    )
+    .context("unable to create the texel readback buffer")?;
+    let set = Arc::new(
+        PersistentDescriptorSet::start(
+            pipeline
+                .descriptor_set_layout(0)
+                .context("texel fetch pipeline has no descriptor set")?
+                .clone(),
+        )
+        .add_sampled_image(
+            texture.image.clone(),
+            Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+        )?
+        .add_buffer(result.clone())?
+        .build()
+        .context("unable to create the texel fetch descriptors")?,
+    );
+
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            headless.graphics_queue.family(),
+        )
+        .context("unable to create the texel fetch command buffer builder")?,
+        &headless.graphics_queue,
+    );
+    builder
+        .dispatch(
+            [1, 1, 1],
+            pipeline,
+            set,
+            fetch_shader::ty::PushConstantData { column },
+        )
+        .context("unable to fetch the texel")?;
+    builder
+        .build()
+        .context("unable to build the texel fetch command buffer")?
+        .execute(headless.graphics_queue.clone())
+        .context("unable to execute the texel fetch")?
+        .then_signal_fence_and_flush()
+        .context("unable to flush the texel fetch")?
+        .wait(None)
+        .context("unable to wait for the texel fetch")?;
+
+    let value = *result.read().context("unable to read the texel")?;
+    Ok(value)
+}
+
+/// Fetch the red channel of the first texel in a layer with a compute
+/// shader.
+fn read_layer(
+    headless: &HeadlessDisplay,
+    array: &TextureArray,
+    layer: i32,
+) -> Result<f32> {
+    let device = &headless.device;
+    let pipeline = Arc::new(
+        ComputePipeline::new(
+            device.clone(),
+            &layer_fetch_shader::Shader::load(device.clone())
+                .context("unable to load the layer fetch shader")?
+                .main_entry_point(),
+            &(),
+            None,
+        )
+        .context("unable to create the layer fetch pipeline")?,
+    );
+    let result = CpuAccessibleBuffer::from_data(
+        device.clone(),
+        BufferUsage::all(),
+        false,
+        0.0f32,
+    )
+    .context("unable to create the layer readback buffer")?;
+    let set = Arc::new(
+        PersistentDescriptorSet::start(
+            pipeline
+                .descriptor_set_layout(0)
+                .context("layer fetch pipeline has no descriptor set")?
+                .clone(),
+        )
+        .add_sampled_image(
+            array.image.clone(),
+            Sampler::simple_repeat_linear_no_mipmap(device.clone()),
+        )?
+        .add_buffer(result.clone())?
+        .build()
+        .context("unable to create the layer fetch descriptors")?,
+    );
+
+    let mut builder = BarrierValidator::wrap(
+        AutoCommandBufferBuilder::primary_one_time_submit(
+            device.clone(),
+            headless.graphics_queue.family(),
+        )
+        .context("unable to create the layer fetch command buffer builder")?,
+        &headless.graphics_queue,
+    );
+    builder
+        .dispatch(
+            [1, 1, 1],
+            pipeline,
+            set,
+            layer_fetch_shader::ty::PushConstantData { layer },
+        )
+        .context("unable to fetch the layer")?;
+    builder
+        .build()
+        .context("unable to build the layer fetch command buffer")?
+        .execute(headless.graphics_queue.clone())
+        .context("unable to execute the layer fetch")?
+        .then_signal_fence_and_flush()
+        .context("unable to flush the layer fetch")?
+        .wait(None)
+        .context("unable to wait for the layer fetch")?;
+
+    let value = *result.read().context("unable to read the layer")?;
+    Ok(value)
+}
+
+mod layer_fetch_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2DArray layers;
+            layout(set = 0, binding = 1) buffer Result {
+                float value;
+            } result;
+
+            layout(push_constant) uniform PushConstantData {
+                int layer;
+            } push;
+
+            void main() {
+                result.value = texelFetch(layers, ivec3(0, 0, push.layer), 0).r;
+            }
+        "#
+    }
+}
+
+mod fetch_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D ramp;
+            layout(set = 0, binding = 1) buffer Result {
+                float value;
+            } result;
+
+            layout(push_constant) uniform PushConstantData {
+                int column;
+            } push;
+
+            void main() {
+                result.value = texelFetch(ramp, ivec2(push.column, 0), 0).r;
+            }
+        "#
+    }
+}
@@ -0,0 +1,232 @@
+use anyhow::{bail, Context, Result};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use vulkan_starter::application::debug::BarrierValidator;
+use vulkan_starter::display::{self, Display};
+use vulkan_starter::texture::{TextureCache, TextureKind, TextureSource};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBuffer};
+use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
+use vulkano::descriptor::PipelineLayoutAbstract;
+use vulkano::pipeline::ComputePipeline;
+use vulkano::sampler::Sampler;
+use vulkano::sync::GpuFuture;
+
+/// The images cycled through.
+const IMAGES: u32 = 500;
+
+/// Every image is this many texels across, 256 KiB each.
+const SIDE: u32 = 256;
+
+/// Room for 16 images, a small fraction of the 125 MiB they take together.
+const BUDGET: u64 = 16 * (SIDE * SIDE * 4) as u64;
+
+/// Each image is the current one for this many frames.
+const FRAMES_PER_IMAGE: u32 = 3;
+
+/// Each frame samples the current image and the ones after it, like a
+/// slideshow which prefetches.
+const LOOKAHEAD: u32 = 2;
+
+/// Frames queued on the gpu before the oldest is waited on.
+const FRAMES_IN_FLIGHT: usize = 2;
+
+/// A readback from a frame, and the image it should have read.
+type Readback = (u32, Arc<CpuAccessibleBuffer<[f32; 4]>>);
+
+/// Cycle through 500 images under a budget of 16 with the validation
+/// layers on, sampling each in a compute shader over several frames in
+/// flight.
+///
+/// Every sample must read the image it asked for or the placeholder while
+/// it loads. The cache must stay under its budget, load every image at
+/// least once, and evict as it goes. Once the last frame finishes nothing
+/// evicted may still be kept, and the validation layers must not have
+/// complained about any image, like one freed while a frame used it. This
+/// creates a headless device, so it needs a gpu: run it with
+/// `cargo test --test texture_cache -- --ignored`.
+#[test]
+#[ignore]
+fn evicted_textures_are_never_sampled() -> Result<()> {
+    display::force_validation()?;
+    let headless = Display::create_headless()
+        .context("unable to create the headless display")?;
+    let device = &headless.device;
+    let queue = &headless.graphics_queue;
+    let pipeline = Arc::new(
+        ComputePipeline::new(
+            device.clone(),
+            &fetch_shader::Shader::load(device.clone())
+                .context("unable to load the texel fetch shader")?
+                .main_entry_point(),
+            &(),
+            None,
+        )
+        .context("unable to create the texel fetch pipeline")?,
+    );
+    let sampler = Sampler::simple_repeat_linear_no_mipmap(device.clone());
+    let mut cache =
+        TextureCache::new("soak", device, queue, BUDGET, soak_source())?
+            .kind(TextureKind::Data);
+
+    let mut in_flight = VecDeque::new();
+    let (mut finished, mut placeholders) = (0, 0);
+    let frames = (IMAGES * FRAMES_PER_IMAGE) as u64;
+    for frame in 0..frames {
+        cache.update(finished)?;
+        let stats = cache.stats();
+        if stats.resident_bytes > BUDGET {
+            bail!(
+                "{} bytes are resident in frame {}, over the budget of {}",
+                stats.resident_bytes,
+                frame,
+                BUDGET
+            );
+        }
+
+        let mut builder = BarrierValidator::wrap(
+            AutoCommandBufferBuilder::primary_one_time_submit(
+                device.clone(),
+                queue.family(),
+            )
+            .context("unable to create the frame's command buffer builder")?,
+            queue,
+        );
+        let mut readbacks: Vec<Readback> = vec![];
+        let current = frame as u32 / FRAMES_PER_IMAGE;
+        for image in current..(current + LOOKAHEAD + 1).min(IMAGES) {
+            let texture = cache.get(&image_name(image), frame);
+            let result = CpuAccessibleBuffer::from_data(
+                device.clone(),
+                BufferUsage::all(),
+                false,
+                [0.0f32; 4],
+            )
+            .context("unable to create a readback buffer")?;
+            let set = Arc::new(
+                PersistentDescriptorSet::start(
+                    pipeline
+                        .descriptor_set_layout(0)
+                        .context("texel fetch pipeline has no descriptor set")?
+                        .clone(),
+                )
+                .add_sampled_image(texture.image.clone(), sampler.clone())?
+                .add_buffer(result.clone())?
+                .build()
+                .context("unable to create the texel fetch descriptors")?,
+            );
+            builder
+                .dispatch([1, 1, 1], pipeline.clone(), set, ())
+                .context("unable to fetch a texel")?;
+            readbacks.push((image, result));
+        }
+        let fence = builder
+            .build()
+            .context("unable to build the frame's command buffer")?
+            .execute(queue.clone())
+            .context("unable to execute the frame")?
+            .then_signal_fence_and_flush()
+            .context("unable to flush the frame")?;
+        in_flight.push_back((fence, readbacks));
+
+        while in_flight.len() > FRAMES_IN_FLIGHT {
+            let (fence, readbacks) = in_flight.pop_front().unwrap();
+            fence.wait(None).context("unable to wait for a frame")?;
+            placeholders += check_readbacks(&readbacks)?;
+            finished += 1;
+        }
+    }
+    for (fence, readbacks) in in_flight.drain(..) {
+        fence.wait(None).context("unable to wait for a frame")?;
+        placeholders += check_readbacks(&readbacks)?;
+        finished += 1;
+    }
+    cache.update(finished)?;
+
+    let stats = cache.stats();
+    println!("{:?}, {} placeholders sampled", stats, placeholders);
+    if stats.retiring_bytes > 0 {
+        bail!(
+            "{} evicted bytes are kept after every frame finished",
+            stats.retiring_bytes
+        );
+    }
+    if stats.misses < IMAGES as u64 || stats.evictions == 0 {
+        bail!(
+            "{} images were loaded and {} evicted, expected every image \
+            loaded and some evicted",
+            stats.misses,
+            stats.evictions
+        );
+    }
+    if stats.failures > 0 {
+        bail!("{} images failed to load", stats.failures);
+    }
+    drop(cache);
+    drop(headless);
+    let errors = display::validation_error_count();
+    if errors > 0 {
+        bail!("{} validation errors", errors);
+    }
+    println!(
+        "{} images cycled under a {} byte budget without validation errors",
+        IMAGES, BUDGET
+    );
+    Ok(())
+}
+
+fn image_name(image: u32) -> String {
+    format!("soak-{}", image)
+}
+
+/// Images filled with their number, the low byte in red and the high byte
+/// in green, so a sample says which image it came from.
+fn soak_source() -> TextureSource {
+    Arc::new(|name: &str| {
+        let image: u32 = name
+            .strip_prefix("soak-")
+            .and_then(|image| image.parse().ok())
+            .with_context(|| format!("{} isn't a soak image", name))?;
+        let texel = [(image % 256) as u8, (image / 256) as u8, 0, 255];
+        Ok((SIDE, SIDE, texel.repeat((SIDE * SIDE) as usize)))
+    })
+}
+
+/// Fail when a frame sampled another image than it asked for, returning
+/// how many samples were of the placeholder, whose first texel is magenta.
+fn check_readbacks(readbacks: &[Readback]) -> Result<usize> {
+    let mut placeholders = 0;
+    for (image, result) in readbacks {
+        let texel = *result.read().context("unable to read a texel")?;
+        if texel[2] > 0.5 {
+            placeholders += 1;
+            continue;
+        }
+        let sampled = (texel[0] * 255.0).round() as u32
+            + (texel[1] * 255.0).round() as u32 * 256;
+        if sampled != *image {
+            bail!("sampled image {} when asking for {}", sampled, image);
+        }
+    }
+    Ok(placeholders)
+}
+
+mod fetch_shader {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        src: r#"
+            #version 450
+
+            layout(local_size_x = 1) in;
+
+            layout(set = 0, binding = 0) uniform sampler2D image;
+            layout(set = 0, binding = 1) buffer Result {
+                vec4 texel;
+            } result;
+
+            void main() {
+                result.texel = texelFetch(image, ivec2(0, 0), 0);
+            }
+        "#
+    }
+}
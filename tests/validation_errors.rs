@@ -0,0 +1,40 @@
+use anyhow::Result;
+use vulkan_starter::display;
+use vulkan_starter::Application;
+
+/// Frames drawn with only the default scene, then again with the world and
+/// the post processing effects turned on.
+const FRAMES: usize = 50;
+
+/// Render 100 frames with the validation layers on, in release builds too,
+/// destroy the application, and fail if the debug callback saw any errors.
+///
+/// Missing barriers, images in the wrong layout, and resources freed while
+/// the gpu still uses them all show up as validation errors long before they
+/// show up on screen. This opens a hidden window, so it needs a display.
+#[test]
+fn rendering_every_effect_has_no_validation_errors() -> Result<()> {
+    display::force_validation()?;
+    let mut app = Application::new()?;
+    app.render_frames(FRAMES)?;
+    let plain_errors = display::validation_error_count();
+
+    app.create_world()?;
+    app.toggle_color_grading()?;
+    app.toggle_motion_blur()?;
+    app.toggle_depth_of_field()?;
+    app.toggle_lens_effects()?;
+    app.render_frames(FRAMES)?;
+
+    // the callback is dropped with the instance, last, so anything freed
+    // out of order while tearing down is still counted
+    app.destroy();
+    let errors = display::validation_error_count();
+
+    assert_eq!(
+        errors, 0,
+        "{} validation errors, {} before the effects were turned on",
+        errors, plain_errors
+    );
+    Ok(())
+}
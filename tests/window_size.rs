@@ -0,0 +1,47 @@
+use anyhow::{bail, Result};
+use vulkan_starter::Application;
+
+/// The physical sizes the window is resized through, odd ones included so
+/// a fractional scale factor can't divide them evenly.
+const SIZES: [[u32; 2]; 4] = [[640, 480], [1707, 960], [333, 221], [1366, 768]];
+
+/// Resize the window through several physical sizes and check that the
+/// swapchain is always built for the physical size, never the logical one,
+/// and that the logical size is the physical size over the scale factor.
+///
+/// winit can't change the scale factor itself, so the scale factor change
+/// events are covered by running this on a display with fractional scaling,
+/// where the logical and physical sizes differ. This opens a hidden window,
+/// so it needs a display.
+#[test]
+fn the_swapchain_follows_the_physical_size() -> Result<()> {
+    let mut app = Application::new()?;
+    for &size in SIZES.iter() {
+        app.set_window_size(size)?;
+        app.render_frames(2)?;
+        let sizes = app.display().window_sizes()?;
+        let extent = app.display().swapchain.dimensions();
+        println!("{:?}: {}", size, sizes.describe().join(", "));
+
+        if extent != sizes.physical && Some(extent) != sizes.surface {
+            bail!(
+                "the swapchain is {:?}, the window is {:?} physical pixels",
+                extent,
+                sizes.physical
+            );
+        }
+        for axis in 0..2 {
+            let scaled = sizes.logical[axis] * sizes.scale_factor;
+            if (scaled - sizes.physical[axis] as f64).abs() > 1.0 {
+                bail!(
+                    "{:?} logical pixels at {}x aren't {:?} physical pixels",
+                    sizes.logical,
+                    sizes.scale_factor,
+                    sizes.physical
+                );
+            }
+        }
+    }
+    app.destroy();
+    Ok(())
+}